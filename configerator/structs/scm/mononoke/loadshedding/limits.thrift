@@ -17,6 +17,8 @@ struct MononokeThrottleLimit {
   5: double getfiles_files;
   6: double getpack_files;
   7: double commits;
+  // CPU time spent serving the sessions, in milliseconds. Not limited if unset.
+  8: optional double cpu_time_ms;
 }
 
 enum RateLimitStatus {
@@ -97,4 +99,14 @@ struct MononokeThrottleLimits {
   // Consistent percentage of load shedding tiers (specified as identity subsets).
   // Percentage is the the percentage of hosts retained from the tier.
   6: optional StaticSlicedLimits static_sliced_limits;
+
+  // Limits per repo name. When a repo has an entry here, its load is tracked
+  // separately from other repos on the same host, and these limits are used
+  // instead of the hostprefix ones. This stops a single large repo from
+  // consuming the budget of every other repo served by a host.
+  7: optional map<string, MononokeThrottleLimit> repos;
+
+  // Multipliers per repo name, applied on top of the hostprefix (or repo)
+  // limits. Repos that are not listed here use a multiplier of 1.
+  8: optional map<string, double> repo_multipliers;
 }
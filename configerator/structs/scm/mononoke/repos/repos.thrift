@@ -133,6 +133,26 @@ struct RawRepoConfig {
     44: optional RawBackupRepoConfig backup_config
     // Define parameters for repo scrub/walker jobs
     45: optional RawWalkerConfig walker_config
+    // Mirror a sample of the EdenAPI reads to another storage config
+    46: optional RawEdenApiReadMirrorConfig edenapi_read_mirror
+    // Storage for blobs that are only kept for a while, e.g. snapshots
+    47: optional RawEphemeralBlobstoreConfig ephemeral_blobstore
+}
+
+struct RawEphemeralBlobstoreConfig {
+    // Name of the storage config: its blobstore keeps the blobs, and its
+    // metadata database keeps track of them. The blobstore must drop blobs
+    // on its own once they are older than the longest TTL that is used.
+    1: string storage_config,
+    // How long blobs are kept for if nothing else is asked for
+    2: i64 default_ttl_secs,
+}
+
+struct RawEdenApiReadMirrorConfig {
+    // Name of the storage config that the reads are mirrored to
+    1: string storage_config,
+    // Percentage of the read requests that are mirrored
+    2: i32 sample_percentage,
 }
 
 struct RawWalkerConfig {
@@ -153,6 +173,15 @@ struct RawBackupRepoConfig {
 
 struct RawRepoClientKnobs {
   1: bool allow_short_getpack_history,
+  // Wireproto commands that are going away, by name
+  2: optional map<string, RawCommandDeprecation> deprecated_commands,
+}
+
+struct RawCommandDeprecation {
+  // When the command will stop working, as shown to the users
+  1: string sunset_date,
+  // What to use instead
+  2: optional string replacement,
 }
 
 struct RawDerivedDataConfig {
@@ -217,6 +246,14 @@ struct RawBlobstoreS3 {
     5: optional i32 num_concurrent_operations,
 }
 
+struct RawBlobstoreEncrypted {
+    1: RawBlobstoreConfig blobstore (rust.box),
+    // JSON file mapping key ids to hex-encoded AES-256 keys
+    2: string keyring_path,
+    // Key used to encrypt new blobs
+    3: string active_key_id,
+}
+
 // Configuration for a single blobstore. These are intended to be defined in a
 // separate blobstore.toml config file, and then referenced by name from a
 // per-server config. Names are only necessary for blobstores which are going
@@ -235,6 +272,7 @@ union RawBlobstoreConfig {
     9: RawBlobstoreLogging logging,
     10: RawBlobstorePack pack,
     11: RawBlobstoreS3 s3,
+    12: RawBlobstoreEncrypted encrypted,
 }
 
 // A write-mostly blobstore is one that is not read from in normal operation.
@@ -323,6 +361,12 @@ struct RawPushrebaseParams {
     // Normally pushes of a commit like this are not allowed unless
     // this option is set to false.
     11: optional bool allow_change_xrepo_mapping_extra,
+    // If specified, the authors of pushrebased commits must match this
+    // pattern (regex).
+    12: optional string author_format,
+    // Trailers (e.g. "Release-Branch: 1.2") added to the messages of
+    // pushrebased commits that don't already have them.
+    13: optional list<string> added_trailers,
 }
 
 struct RawBookmarkConfig {
@@ -368,6 +412,11 @@ struct RawBookmarkConfig {
     // ancestors of master, which should mean they have already passed the
     // hook.
     7: optional list<string> hooks_skip_ancestors_of,
+
+    // Override the repo's pushrebase author_format and added_trailers for
+    // pushes onto this bookmark.
+    9: optional string pushrebase_author_format,
+    10: optional list<string> pushrebase_added_trailers,
 }
 
 struct RawWhitelistEntry {
@@ -388,6 +437,23 @@ struct RawCommonConfig {
     // Whether to enable the control API over HTTP. At this time, this is
     // only meant to be used in tests.
     5: bool enable_http_control_api,
+
+    // Options for the sockets of the connections that the server accepts
+    6: optional RawTcpSocketOptions tcp_socket_options,
+}
+
+struct RawTcpSocketOptions {
+    // How long a connection can be idle before keepalive probes are sent.
+    // Keepalives are disabled if this isn't set.
+    1: optional i64 keepalive_idle_secs,
+    // Time between two keepalive probes
+    2: optional i64 keepalive_interval_secs,
+    // Number of unanswered keepalive probes after which the connection is
+    // closed
+    3: optional i64 keepalive_probes,
+    // How long sent data can remain unacknowledged before the connection is
+    // closed
+    4: optional i64 user_timeout_millis,
 }
 
 struct RawCacheWarmupConfig {
@@ -452,6 +518,7 @@ struct RawInfinitepushParams {
 struct RawFilestoreParams {
     1: i64 chunk_size,
     2: i32 concurrency,
+    3: optional i64 inline_threshold,
 }
 
 struct RawCommitSyncSmallRepoConfig {
@@ -11,7 +11,6 @@ mod bookmark;
 mod changeset;
 mod concurrency;
 
-use std::cmp;
 use std::collections::HashMap;
 use std::error::Error as StdError;
 use std::ops::Deref;
@@ -34,6 +33,7 @@ use blobrepo::BlobRepo;
 use blobrepo_hg::BlobRepoHg;
 use bonsai_git_mapping::BonsaiGitMapping;
 use bonsai_globalrev_mapping::{bulk_import_globalrevs, BonsaiGlobalrevMapping};
+use cmdlib_progress::{Progress, ProgressOptions};
 use context::CoreContext;
 use derived_data_utils::derive_data_for_csids;
 use mercurial_revlog::{revlog::RevIdx, RevlogRepo};
@@ -112,10 +112,13 @@ impl<'a> Blobimport<'a> {
             .with_context(|| format!("While opening revlog repo at {:?}", revlogrepo_path))?;
         let stale_bookmarks_fut = bookmark::read_bookmarks(&revlogrepo).compat();
 
-        let log_step = match commits_limit {
-            Some(commits_limit) => cmp::max(1, commits_limit / 10),
-            None => 5000,
-        };
+        let progress: Progress = Progress::new(
+            "inserted commits",
+            commits_limit.map(|limit| limit as u64),
+            ProgressOptions::default(),
+        )
+        .with_logger(ctx.logger().clone());
+        let progress = &progress;
 
         let chunk_size = 100;
 
@@ -145,9 +148,8 @@ impl<'a> Blobimport<'a> {
                     cs_count,
                     cs.1.get_changeset_id()
                 );
-                if cs_count % log_step == 0 {
-                    info!(ctx.logger(), "inserted commits # {}", cs_count);
-                }
+                progress.record(1);
+                progress.report_throttled();
                 (revidx, cs.0.clone())
             }
         })
@@ -254,6 +256,7 @@ impl<'a> Blobimport<'a> {
             )
             .await?;
         }
+        progress.report();
 
         info!(
             ctx.logger(),
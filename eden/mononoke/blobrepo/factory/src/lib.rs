@@ -9,7 +9,7 @@ use anyhow::{Context, Error, Result};
 use blame::BlameRoot;
 use blobrepo::BlobRepo;
 use blobrepo_errors::*;
-use blobstore::Blobstore;
+use blobstore::{Blobstore, CountedBlobstore, PutBehaviour};
 use blobstore_factory::{make_blobstore, make_metadata_sql_factory, MetadataSqlFactory};
 use bonsai_git_mapping::{BonsaiGitMapping, SqlBonsaiGitMappingConnection};
 use bonsai_globalrev_mapping::{
@@ -37,7 +37,7 @@ use derived_data_filenodes::FilenodesOnlyPublic;
 use fastlog::RootFastlog;
 use fbinit::FacebookInit;
 use filenodes::Filenodes;
-use filestore::FilestoreConfig;
+use filestore::{FilestoreConfig, MetadataCacheBlobstore};
 use fsnodes::RootFsnodeId;
 use futures::{future, try_join};
 use futures_watchdog::WatchdogExt;
@@ -65,6 +65,7 @@ use slog::Logger;
 use sql::{rusqlite::Connection as SqliteConnection, Connection};
 use sql_construct::SqlConstruct;
 use sql_ext::{facebook::MysqlOptions, SqlConnections};
+use stats_ext::repo_key;
 use std::num::NonZeroUsize;
 use std::{collections::HashMap, sync::Arc, time::Duration};
 use type_map::TypeMap;
@@ -84,6 +85,8 @@ pub enum Caching {
 
 const BLOBSTORE_BLOBS_CACHE_POOL: &str = "blobstore-blobs";
 const BLOBSTORE_PRESENCE_CACHE_POOL: &str = "blobstore-presence";
+/// The cachelib pool of the content metadata cache, which is only used if it is set up.
+pub const CONTENT_METADATA_CACHE_POOL: &str = "content-metadata";
 
 pub struct BlobrepoBuilder<'a> {
     fb: FacebookInit,
@@ -173,6 +176,7 @@ impl<'a> BlobrepoBuilder<'a> {
             self.readonly_storage,
             self.reponame,
             self.blobstore_options.cachelib_options,
+            self.blobstore_options.put_behaviour,
             self.logger,
         )
         .watched(self.logger)
@@ -192,6 +196,7 @@ pub async fn open_blobrepo_given_datasources<'a>(
     readonly_storage: ReadOnlyStorage,
     reponame: String,
     cachelib_options: CachelibBlobstoreOptions,
+    put_behaviour: PutBehaviour,
     logger: &'a Logger,
 ) -> Result<BlobRepo, Error> {
     let redacted_blobs = match redaction {
@@ -212,9 +217,14 @@ pub async fn open_blobrepo_given_datasources<'a>(
         .map(|p| FilestoreConfig {
             chunk_size: Some(p.chunk_size),
             concurrency: p.concurrency,
+            inline_threshold: p.inline_threshold,
         })
         .unwrap_or_default();
 
+    // The storage can be shared between repos, so its counters can't tell them apart.
+    let blobstore =
+        Arc::new(CountedBlobstore::new(repo_key(&reponame), blobstore)) as Arc<dyn Blobstore>;
+
     let repo = match caching {
         Caching::Disabled | Caching::CachelibOnlyBlobstore(_) => {
             let blobstore = if let Caching::CachelibOnlyBlobstore(cache_shards) = caching {
@@ -256,6 +266,7 @@ pub async fn open_blobrepo_given_datasources<'a>(
                 repo_config.bookmarks_cache_ttl,
                 filestore_config,
                 readonly_storage,
+                put_behaviour,
                 repo_config.derived_data_config.clone(),
                 repo_config.segmented_changelog_config.clone(),
                 reponame,
@@ -684,6 +695,7 @@ async fn new_production<'a>(
     bookmarks_cache_ttl: Option<Duration>,
     filestore_config: FilestoreConfig,
     readonly_storage: ReadOnlyStorage,
+    put_behaviour: PutBehaviour,
     derived_data_config: DerivedDataConfig,
     segmented_changelog_config: SegmentedChangelogConfig,
     reponame: String,
@@ -721,6 +733,18 @@ async fn new_production<'a>(
     };
     let phases_factory = sql_factory.open::<SqlPhasesFactory>();
 
+    // The metadata of file contents is fetched for every file that is served, so it gets a cache
+    // pool of its own when --content-metadata-cache-size sets one up.
+    let blobstore = match cachelib::get_volatile_pool(CONTENT_METADATA_CACHE_POOL)? {
+        Some(pool) => Arc::new(MetadataCacheBlobstore::new(
+            fb,
+            blobstore,
+            pool,
+            put_behaviour,
+        )?) as Arc<dyn Blobstore>,
+        None => blobstore,
+    };
+
     // Wrap again to avoid any writes to memcache
     let blobstore = if readonly_storage.0 {
         Arc::new(ReadOnlyBlobstore::new(blobstore)) as Arc<dyn Blobstore>
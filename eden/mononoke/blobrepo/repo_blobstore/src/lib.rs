@@ -94,7 +94,8 @@ impl RepoBlobstoreArgs {
         repoid: RepositoryId,
         scuba_builder: MononokeScubaSampleBuilder,
     ) -> Self {
-        let redacted_blobstore_config = RedactedBlobstoreConfig::new(redacted_blobs, scuba_builder);
+        let redacted_blobstore_config =
+            RedactedBlobstoreConfig::new(redacted_blobs, scuba_builder, repoid);
         Self::build(blobstore, repoid, redacted_blobstore_config)
     }
 
@@ -0,0 +1,420 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! A circuit breaker for a blobstore backend. Once the backend has failed a number of operations
+//! in a row, the breaker opens, and operations fail straight away instead of waiting on a
+//! backend that is browning out. While it is open, one operation per probe interval is still let
+//! through: if it succeeds, the breaker closes again.
+//!
+//! Breakers are per backend, not per blobstore: all the blobstores that are created with the same
+//! name (e.g. those of the repos that share a storage) share one breaker.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::num::NonZeroU32;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
+use context::CoreContext;
+use lazy_static::lazy_static;
+use mononoke_types::BlobstoreBytes;
+use serde::Serialize;
+use stats::prelude::*;
+
+mod errors;
+pub use crate::errors::ErrorKind;
+
+define_stats! {
+    prefix = "mononoke.blobstore.circuit_breaker";
+    opened: dynamic_timeseries("{}.opened", (name: String); Rate, Sum),
+    closed: dynamic_timeseries("{}.closed", (name: String); Rate, Sum),
+    rejected: dynamic_timeseries("{}.rejected", (name: String); Rate, Sum),
+    probes: dynamic_timeseries("{}.probes", (name: String); Rate, Sum),
+}
+
+lazy_static! {
+    static ref BREAKERS: Mutex<HashMap<String, Weak<Breaker>>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CircuitBreakerOptions {
+    /// Number of operations in a row that must fail for the breaker to open
+    pub failure_threshold: NonZeroU32,
+    /// How often an operation is let through to probe the backend while the breaker is open
+    pub probe_interval: Duration,
+}
+
+impl Default for CircuitBreakerOptions {
+    fn default() -> Self {
+        Self {
+            failure_threshold: NonZeroU32::new(10).unwrap(),
+            probe_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// The state of a circuit breaker, as reported by `circuit_breaker_states`.
+#[derive(Clone, Debug, Serialize)]
+pub struct CircuitBreakerState {
+    pub name: String,
+    pub open: bool,
+    pub consecutive_failures: u32,
+    /// How long the breaker has been open for, in seconds.
+    pub open_for_secs: Option<u64>,
+}
+
+/// The state of all the circuit breakers of this process.
+pub fn circuit_breaker_states() -> Vec<CircuitBreakerState> {
+    let mut breakers = BREAKERS.lock().expect("lock poisoned");
+    breakers.retain(|_, breaker| breaker.strong_count() > 0);
+    let mut states: Vec<_> = breakers
+        .values()
+        .filter_map(Weak::upgrade)
+        .map(|breaker| breaker.state())
+        .collect();
+    states.sort_by(|a, b| a.name.cmp(&b.name));
+    states
+}
+
+/// The breaker of the backend called `name`, which is created with `options` if there is none.
+fn breaker(name: String, options: CircuitBreakerOptions) -> Arc<Breaker> {
+    let mut breakers = BREAKERS.lock().expect("lock poisoned");
+    if let Some(breaker) = breakers.get(&name).and_then(Weak::upgrade) {
+        return breaker;
+    }
+
+    let breaker = Arc::new(Breaker {
+        name: name.clone(),
+        options,
+        state: Mutex::new(BreakerState::default()),
+    });
+    breakers.insert(name, Arc::downgrade(&breaker));
+    breaker
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Admission {
+    Allowed,
+    Probe,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    // When the breaker opened, and when the last probe was let through. Unset while closed.
+    opened: Option<(Instant, Instant)>,
+}
+
+#[derive(Debug)]
+struct Breaker {
+    name: String,
+    options: CircuitBreakerOptions,
+    state: Mutex<BreakerState>,
+}
+
+impl Breaker {
+    fn admit(&self) -> Result<Admission, ErrorKind> {
+        let mut state = self.state.lock().expect("lock poisoned");
+        let consecutive_failures = state.consecutive_failures;
+        match &mut state.opened {
+            None => Ok(Admission::Allowed),
+            Some((_, last_probe)) if last_probe.elapsed() >= self.options.probe_interval => {
+                // Probes go by time rather than by whether the last one is done, so that a
+                // probe that never finishes doesn't keep the breaker open forever.
+                *last_probe = Instant::now();
+                STATS::probes.add_value(1, (self.name.clone(),));
+                Ok(Admission::Probe)
+            }
+            Some(_) => {
+                STATS::rejected.add_value(1, (self.name.clone(),));
+                Err(ErrorKind::CircuitOpen(
+                    self.name.clone(),
+                    consecutive_failures,
+                ))
+            }
+        }
+    }
+
+    fn record(&self, admission: Admission, success: bool) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        if success {
+            state.consecutive_failures = 0;
+            if state.opened.take().is_some() {
+                STATS::closed.add_value(1, (self.name.clone(),));
+            }
+            return;
+        }
+
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        if admission == Admission::Allowed
+            && state.opened.is_none()
+            && state.consecutive_failures >= self.options.failure_threshold.get()
+        {
+            let now = Instant::now();
+            state.opened = Some((now, now));
+            STATS::opened.add_value(1, (self.name.clone(),));
+        }
+    }
+
+    fn state(&self) -> CircuitBreakerState {
+        let state = self.state.lock().expect("lock poisoned");
+        CircuitBreakerState {
+            name: self.name.clone(),
+            open: state.opened.is_some(),
+            consecutive_failures: state.consecutive_failures,
+            open_for_secs: state.opened.map(|(since, _)| since.elapsed().as_secs()),
+        }
+    }
+}
+
+/// A layer over an existing blobstore that fails operations fast while its backend is failing.
+#[derive(Debug)]
+pub struct CircuitBreakerBlobstore<T> {
+    blobstore: T,
+    breaker: Arc<Breaker>,
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for CircuitBreakerBlobstore<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CircuitBreakerBlobstore<{}>", &self.blobstore)
+    }
+}
+
+impl<T> CircuitBreakerBlobstore<T> {
+    /// `name` identifies the backend in the stats and in `circuit_breaker_states`. If there
+    /// already is a breaker for it, it is shared, along with its options.
+    pub fn new(blobstore: T, name: String, options: CircuitBreakerOptions) -> Self {
+        Self {
+            blobstore,
+            breaker: breaker(name, options),
+        }
+    }
+
+    async fn call<V>(&self, fut: impl Future<Output = Result<V>>) -> Result<V> {
+        let admission = self.breaker.admit()?;
+        let res = fut.await;
+        self.breaker.record(admission, res.is_ok());
+        res
+    }
+}
+
+#[async_trait]
+impl<T: BlobstorePutOps> Blobstore for CircuitBreakerBlobstore<T> {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        self.call(self.blobstore.get(ctx, key)).await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        self.put_with_status(ctx, key, value).await?;
+        Ok(())
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        self.call(self.blobstore.is_present(ctx, key)).await
+    }
+}
+
+#[async_trait]
+impl<T: BlobstorePutOps> BlobstorePutOps for CircuitBreakerBlobstore<T> {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        self.call(self.blobstore.put_explicit(ctx, key, value, put_behaviour))
+            .await
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        self.call(self.blobstore.put_with_status(ctx, key, value))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::format_err;
+    use borrowed::borrowed;
+    use fbinit::FacebookInit;
+    use memblob::Memblob;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Fails every get while `failing` is set, then defers to a Memblob
+    #[derive(Debug, Default)]
+    struct FailingBlobstore {
+        inner: Memblob,
+        failing: AtomicBool,
+    }
+
+    impl std::fmt::Display for FailingBlobstore {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "FailingBlobstore")
+        }
+    }
+
+    #[async_trait]
+    impl Blobstore for FailingBlobstore {
+        async fn get<'a>(
+            &'a self,
+            ctx: &'a CoreContext,
+            key: &'a str,
+        ) -> Result<Option<BlobstoreGetData>> {
+            if self.failing.load(Ordering::Relaxed) {
+                return Err(format_err!("backend is browning out"));
+            }
+            self.inner.get(ctx, key).await
+        }
+
+        async fn put<'a>(
+            &'a self,
+            ctx: &'a CoreContext,
+            key: String,
+            value: BlobstoreBytes,
+        ) -> Result<()> {
+            self.inner.put(ctx, key, value).await
+        }
+    }
+
+    #[async_trait]
+    impl BlobstorePutOps for FailingBlobstore {
+        async fn put_explicit<'a>(
+            &'a self,
+            ctx: &'a CoreContext,
+            key: String,
+            value: BlobstoreBytes,
+            put_behaviour: PutBehaviour,
+        ) -> Result<OverwriteStatus> {
+            self.inner
+                .put_explicit(ctx, key, value, put_behaviour)
+                .await
+        }
+
+        async fn put_with_status<'a>(
+            &'a self,
+            ctx: &'a CoreContext,
+            key: String,
+            value: BlobstoreBytes,
+        ) -> Result<OverwriteStatus> {
+            self.inner.put_with_status(ctx, key, value).await
+        }
+    }
+
+    fn is_circuit_open(res: &Result<Option<BlobstoreGetData>>) -> bool {
+        match res {
+            Err(e) => e.is::<ErrorKind>(),
+            Ok(_) => false,
+        }
+    }
+
+    #[fbinit::test]
+    async fn test_trips_and_recovers(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let store = CircuitBreakerBlobstore::new(
+            FailingBlobstore::default(),
+            "test_trips_and_recovers".to_string(),
+            CircuitBreakerOptions {
+                failure_threshold: NonZeroU32::new(3).unwrap(),
+                probe_interval: Duration::from_millis(50),
+            },
+        );
+        let state = || {
+            circuit_breaker_states()
+                .into_iter()
+                .find(|state| state.name == "test_trips_and_recovers")
+                .unwrap()
+        };
+
+        store
+            .put(ctx, "key".to_string(), BlobstoreBytes::from_bytes("value"))
+            .await?;
+        store.blobstore.failing.store(true, Ordering::Relaxed);
+
+        // Failures reach the backend until the threshold, then fail fast.
+        for _ in 0..3 {
+            let res = store.get(ctx, "key").await;
+            assert!(res.is_err() && !is_circuit_open(&res));
+        }
+        assert!(state().open);
+        assert!(is_circuit_open(&store.get(ctx, "key").await));
+
+        // A failed probe keeps it open.
+        tokio::time::delay_for(Duration::from_millis(60)).await;
+        let res = store.get(ctx, "key").await;
+        assert!(res.is_err() && !is_circuit_open(&res));
+        assert!(is_circuit_open(&store.get(ctx, "key").await));
+
+        // A successful probe closes it.
+        store.blobstore.failing.store(false, Ordering::Relaxed);
+        tokio::time::delay_for(Duration::from_millis(60)).await;
+        assert!(store.get(ctx, "key").await?.is_some());
+        assert!(store.get(ctx, "key").await?.is_some());
+        let state = state();
+        assert!(!state.open);
+        assert_eq!(state.consecutive_failures, 0);
+
+        Ok(())
+    }
+    #[fbinit::test]
+    async fn test_shared_by_backend(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let options = CircuitBreakerOptions {
+            failure_threshold: NonZeroU32::new(1).unwrap(),
+            probe_interval: Duration::from_secs(60),
+        };
+        let new_store = |name: &str| {
+            let store = FailingBlobstore::default();
+            store.failing.store(true, Ordering::Relaxed);
+            CircuitBreakerBlobstore::new(store, name.to_string(), options)
+        };
+        let first = new_store("test_shared_by_backend");
+        let second = new_store("test_shared_by_backend");
+        let other = new_store("test_shared_by_backend_other");
+
+        // Failures of one blobstore open the breaker of all those of the same backend.
+        let res = first.get(ctx, "key").await;
+        assert!(res.is_err() && !is_circuit_open(&res));
+        assert!(is_circuit_open(&second.get(ctx, "key").await));
+        let res = other.get(ctx, "key").await;
+        assert!(res.is_err() && !is_circuit_open(&res));
+
+        let names = circuit_breaker_states()
+            .into_iter()
+            .map(|state| state.name)
+            .filter(|name| name.starts_with("test_shared_by_backend"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["test_shared_by_backend", "test_shared_by_backend_other"]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Encrypted blobs are stored as:
+//!
+//! | magic (4) | version (1) | key id length (1) | key id | nonce (12) | tag (16) | ciphertext |
+//!
+//! The blob is sealed with AES-256-GCM, and everything up to and including the key id is
+//! authenticated along with it, as is the key of the blob in the store. That way a blob can't be
+//! swapped for another one by whoever can write to the store underneath, as it only decrypts
+//! under its own key.
+
+use std::convert::TryInto;
+use std::str;
+
+use anyhow::{bail, format_err, Context, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+use crate::keyring::Keyring;
+
+const MAGIC: &[u8; 4] = b"MENC";
+const VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+struct Header<'a> {
+    key_id: &'a str,
+    // The header bytes, which are authenticated with the ciphertext.
+    raw: &'a [u8],
+    body: &'a [u8],
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Header<'_>> {
+    let fixed_len = MAGIC.len() + 2;
+    if bytes.len() < fixed_len || &bytes[..MAGIC.len()] != MAGIC {
+        bail!("Blob is not encrypted");
+    }
+    let version = bytes[MAGIC.len()];
+    if version != VERSION {
+        bail!("Unsupported encryption envelope version {}", version);
+    }
+    let key_id_len = bytes[MAGIC.len() + 1] as usize;
+    let header_len = fixed_len + key_id_len;
+    if bytes.len() < header_len + NONCE_LEN + TAG_LEN {
+        bail!("Encrypted blob is truncated");
+    }
+    let key_id = str::from_utf8(&bytes[fixed_len..header_len])
+        .context("Encrypted blob has invalid key id")?;
+
+    Ok(Header {
+        key_id,
+        raw: &bytes[..header_len],
+        body: &bytes[header_len..],
+    })
+}
+
+/// The id of the key that `bytes` was encrypted with.
+pub(crate) fn key_id(bytes: &[u8]) -> Result<&str> {
+    Ok(parse_header(bytes)?.key_id)
+}
+
+// What is authenticated along with the ciphertext.
+fn aad(header: &[u8], blob_key: &str) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(header.len() + blob_key.len());
+    aad.extend_from_slice(header);
+    aad.extend_from_slice(blob_key.as_bytes());
+    aad
+}
+
+pub(crate) fn encrypt(keyring: &Keyring, blob_key: &str, plaintext: &[u8]) -> Result<Bytes> {
+    let (key_id, key) = keyring.active_key();
+
+    let mut nonce = [0; NONCE_LEN];
+    rand_bytes(&mut nonce)?;
+
+    let mut buf = BytesMut::with_capacity(
+        MAGIC.len() + 2 + key_id.len() + NONCE_LEN + TAG_LEN + plaintext.len(),
+    );
+    buf.put_slice(MAGIC);
+    buf.put_u8(VERSION);
+    // The keyring checks that key ids fit in a byte.
+    buf.put_u8(key_id.len().try_into()?);
+    buf.put_slice(key_id.as_bytes());
+
+    let mut tag = [0; TAG_LEN];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(&nonce),
+        &aad(&buf, blob_key),
+        plaintext,
+        &mut tag,
+    )?;
+
+    buf.put_slice(&nonce);
+    buf.put_slice(&tag);
+    buf.put_slice(&ciphertext);
+    Ok(buf.freeze())
+}
+
+pub(crate) fn decrypt(keyring: &Keyring, blob_key: &str, bytes: &[u8]) -> Result<Bytes> {
+    let header = parse_header(bytes)?;
+    let key = keyring
+        .get(header.key_id)
+        .ok_or_else(|| format_err!("Key {} is not in the keyring", header.key_id))?;
+
+    let (nonce, rest) = header.body.split_at(NONCE_LEN);
+    let (tag, ciphertext) = rest.split_at(TAG_LEN);
+    let plaintext = decrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(nonce),
+        &aad(header.raw, blob_key),
+        ciphertext,
+        tag,
+    )
+    .map_err(|_| format_err!("Failed to decrypt blob with key {}", header.key_id))?;
+
+    Ok(Bytes::from(plaintext))
+}
@@ -0,0 +1,93 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, format_err, Context, Result};
+
+/// Length of the AES-256 keys in a keyring.
+pub const KEY_LEN: usize = 32;
+
+/// The keys an EncryptedBlob can use. New blobs are encrypted with the active key, and blobs are
+/// decrypted with whichever key their envelope names, so a keyring can keep old keys around for
+/// as long as blobs encrypted with them remain.
+#[derive(Clone)]
+pub struct Keyring {
+    keys: HashMap<String, [u8; KEY_LEN]>,
+    active_key_id: String,
+}
+
+// Never print the key material.
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut key_ids: Vec<_> = self.keys.keys().collect();
+        key_ids.sort();
+        f.debug_struct("Keyring")
+            .field("key_ids", &key_ids)
+            .field("active_key_id", &self.active_key_id)
+            .finish()
+    }
+}
+
+impl Keyring {
+    pub fn new(keys: HashMap<String, [u8; KEY_LEN]>, active_key_id: String) -> Result<Self> {
+        if !keys.contains_key(&active_key_id) {
+            bail!("Active key {} is not in the keyring", active_key_id);
+        }
+        for key_id in keys.keys() {
+            if key_id.is_empty() || key_id.len() > u8::MAX as usize {
+                bail!("Key id {:?} must be between 1 and 255 bytes long", key_id);
+            }
+        }
+        Ok(Self {
+            keys,
+            active_key_id,
+        })
+    }
+
+    /// Load a keyring from a JSON file mapping key ids to hex-encoded keys, e.g.
+    /// `{"2021-01": "<64 hex digits>", "2021-06": "<64 hex digits>"}`.
+    pub fn load(path: &Path, active_key_id: String) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read keyring {}", path.display()))?;
+        let raw: HashMap<String, String> = serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid keyring {}", path.display()))?;
+
+        let keys = raw
+            .into_iter()
+            .map(|(key_id, hex_key)| {
+                let mut key = [0; KEY_LEN];
+                hex::decode_to_slice(hex_key.trim(), &mut key).map_err(|_| {
+                    format_err!(
+                        "Key {} in {} is not {} hex-encoded bytes",
+                        key_id,
+                        path.display(),
+                        KEY_LEN
+                    )
+                })?;
+                Ok((key_id, key))
+            })
+            .collect::<Result<_>>()?;
+
+        Self::new(keys, active_key_id)
+    }
+
+    pub fn active_key_id(&self) -> &str {
+        &self.active_key_id
+    }
+
+    pub(crate) fn active_key(&self) -> (&str, &[u8; KEY_LEN]) {
+        (&self.active_key_id, &self.keys[&self.active_key_id])
+    }
+
+    pub(crate) fn get(&self, key_id: &str) -> Option<&[u8; KEY_LEN]> {
+        self.keys.get(key_id)
+    }
+}
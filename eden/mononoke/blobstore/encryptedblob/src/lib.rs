@@ -0,0 +1,15 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+mod envelope;
+mod keyring;
+mod store;
+
+pub use keyring::{Keyring, KEY_LEN};
+pub use store::{EncryptedBlob, ReencryptOutcome};
@@ -0,0 +1,324 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
+use context::CoreContext;
+use mononoke_types::BlobstoreBytes;
+
+use crate::envelope;
+use crate::keyring::Keyring;
+
+/// A layer over an existing blobstore that encrypts blobs at rest, for backends that don't
+/// encrypt them themselves. Each blob records the id of the key it was encrypted with, so that
+/// keys can be rotated without rewriting the whole store at once.
+#[derive(Debug)]
+pub struct EncryptedBlob<T> {
+    inner: T,
+    keyring: Arc<Keyring>,
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for EncryptedBlob<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EncryptedBlob<{}>", &self.inner)
+    }
+}
+
+impl<T> EncryptedBlob<T> {
+    pub fn new(inner: T, keyring: Arc<Keyring>) -> Self {
+        Self { inner, keyring }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReencryptOutcome {
+    /// There is no blob with this key.
+    Missing,
+    /// The blob is already encrypted with the active key.
+    AlreadyActive,
+    /// The blob was encrypted with `from_key_id`, and has been rewritten using the active key.
+    Reencrypted { from_key_id: String },
+}
+
+#[async_trait]
+impl<T: Blobstore + BlobstorePutOps> Blobstore for EncryptedBlob<T> {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let inner_get_data = match self.inner.get(ctx, key).await? {
+            Some(inner_get_data) => inner_get_data,
+            None => return Ok(None),
+        };
+
+        let meta = inner_get_data.as_meta().clone();
+        let plaintext = envelope::decrypt(&self.keyring, key, inner_get_data.as_raw_bytes())
+            .with_context(|| format!("While decrypting {:?}", key))?;
+
+        Ok(Some(BlobstoreGetData::new(
+            meta,
+            BlobstoreBytes::from_bytes(plaintext),
+        )))
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        self.inner.is_present(ctx, key).await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        BlobstorePutOps::put_with_status(self, ctx, key, value).await?;
+        Ok(())
+    }
+}
+
+impl<T: BlobstorePutOps> EncryptedBlob<T> {
+    async fn put_impl<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: Option<PutBehaviour>,
+    ) -> Result<OverwriteStatus> {
+        let value = envelope::encrypt(&self.keyring, &key, value.as_bytes())
+            .with_context(|| format!("While encrypting {:?}", key))?;
+        let value = BlobstoreBytes::from_bytes(value);
+
+        if let Some(put_behaviour) = put_behaviour {
+            self.inner
+                .put_explicit(ctx, key, value, put_behaviour)
+                .await
+        } else {
+            self.inner.put_with_status(ctx, key, value).await
+        }
+    }
+}
+
+#[async_trait]
+impl<T: BlobstorePutOps> BlobstorePutOps for EncryptedBlob<T> {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        self.put_impl(ctx, key, value, Some(put_behaviour)).await
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        self.put_impl(ctx, key, value, None).await
+    }
+}
+
+impl<T: Blobstore + BlobstorePutOps> EncryptedBlob<T> {
+    /// Rewrite the blob at `key` with the active key if it was encrypted with another one. Once
+    /// every blob has been rewritten, the old keys can be removed from the keyring.
+    pub async fn reencrypt<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<ReencryptOutcome> {
+        let inner_get_data = match self.inner.get(ctx, key).await? {
+            Some(inner_get_data) => inner_get_data,
+            None => return Ok(ReencryptOutcome::Missing),
+        };
+        let from_key_id = envelope::key_id(inner_get_data.as_raw_bytes())
+            .with_context(|| format!("While reading envelope of {:?}", key))?
+            .to_string();
+        if from_key_id == self.keyring.active_key_id() {
+            return Ok(ReencryptOutcome::AlreadyActive);
+        }
+
+        let plaintext = envelope::decrypt(&self.keyring, key, inner_get_data.as_raw_bytes())
+            .with_context(|| format!("While decrypting {:?}", key))?;
+        self.put_impl(
+            ctx,
+            key.to_string(),
+            BlobstoreBytes::from_bytes(plaintext),
+            Some(PutBehaviour::Overwrite),
+        )
+        .await?;
+
+        Ok(ReencryptOutcome::Reencrypted { from_key_id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyring::KEY_LEN;
+    use borrowed::borrowed;
+    use bytes::Bytes;
+    use fbinit::FacebookInit;
+    use memblob::Memblob;
+    use std::collections::HashMap;
+
+    fn keyring(key_ids: &[&str], active_key_id: &str) -> Arc<Keyring> {
+        // Derive the key from its id, so that the same id always has the same key.
+        let keys: HashMap<_, _> = key_ids
+            .iter()
+            .map(|key_id| (key_id.to_string(), [key_id.as_bytes()[1]; KEY_LEN]))
+            .collect();
+        Arc::new(Keyring::new(keys, active_key_id.to_string()).unwrap())
+    }
+
+    #[fbinit::test]
+    async fn roundtrip_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let inner = Arc::new(Memblob::default());
+        let blobstore = EncryptedBlob::new(inner.clone(), keyring(&["k1"], "k1"));
+
+        let value = Bytes::from_static(b"appleveldata");
+        blobstore
+            .put(
+                ctx,
+                "key".to_string(),
+                BlobstoreBytes::from_bytes(value.clone()),
+            )
+            .await?;
+
+        let get = blobstore.get(ctx, "key").await?.expect("blob is missing");
+        assert_eq!(get.into_raw_bytes(), value);
+
+        // The inner store only sees the ciphertext.
+        let inner_bytes = inner.get(ctx, "key").await?.unwrap().into_raw_bytes();
+        assert_eq!(envelope::key_id(&inner_bytes)?, "k1");
+        assert!(!inner_bytes
+            .windows(value.len())
+            .any(|w| w == value.as_ref()));
+
+        assert!(blobstore.get(ctx, "missing").await?.is_none());
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn tampered_blob_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let inner = Arc::new(Memblob::new(PutBehaviour::Overwrite));
+        let blobstore = EncryptedBlob::new(inner.clone(), keyring(&["k1"], "k1"));
+
+        blobstore
+            .put(
+                ctx,
+                "key".to_string(),
+                BlobstoreBytes::from_bytes(Bytes::from_static(b"appleveldata")),
+            )
+            .await?;
+
+        let mut inner_bytes = inner
+            .get(ctx, "key")
+            .await?
+            .unwrap()
+            .into_raw_bytes()
+            .to_vec();
+        let last = inner_bytes.len() - 1;
+        inner_bytes[last] ^= 1;
+        inner
+            .put(
+                ctx,
+                "key".to_string(),
+                BlobstoreBytes::from_bytes(inner_bytes),
+            )
+            .await?;
+
+        assert!(blobstore.get(ctx, "key").await.is_err());
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn moved_blob_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let inner = Arc::new(Memblob::default());
+        let blobstore = EncryptedBlob::new(inner.clone(), keyring(&["k1"], "k1"));
+
+        blobstore
+            .put(
+                ctx,
+                "key".to_string(),
+                BlobstoreBytes::from_bytes(Bytes::from_static(b"appleveldata")),
+            )
+            .await?;
+
+        // A blob only decrypts under the key it was written to.
+        let inner_bytes = inner.get(ctx, "key").await?.unwrap().into_raw_bytes();
+        inner
+            .put(
+                ctx,
+                "other".to_string(),
+                BlobstoreBytes::from_bytes(inner_bytes),
+            )
+            .await?;
+
+        assert!(blobstore.get(ctx, "other").await.is_err());
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn key_rotation_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let inner = Arc::new(Memblob::default());
+        let value = Bytes::from_static(b"appleveldata");
+
+        let old = EncryptedBlob::new(inner.clone(), keyring(&["k1"], "k1"));
+        old.put(
+            ctx,
+            "key".to_string(),
+            BlobstoreBytes::from_bytes(value.clone()),
+        )
+        .await?;
+
+        // After rotating, blobs written with the old key can still be read.
+        let rotated = EncryptedBlob::new(inner.clone(), keyring(&["k1", "k2"], "k2"));
+        let get = rotated.get(ctx, "key").await?.expect("blob is missing");
+        assert_eq!(get.into_raw_bytes(), value);
+
+        assert_eq!(
+            rotated.reencrypt(ctx, "key").await?,
+            ReencryptOutcome::Reencrypted {
+                from_key_id: "k1".to_string()
+            }
+        );
+        assert_eq!(
+            rotated.reencrypt(ctx, "key").await?,
+            ReencryptOutcome::AlreadyActive
+        );
+        assert_eq!(
+            rotated.reencrypt(ctx, "missing").await?,
+            ReencryptOutcome::Missing
+        );
+
+        // Once re-encrypted, the old key is no longer needed.
+        let new_only = EncryptedBlob::new(inner.clone(), keyring(&["k2"], "k2"));
+        let inner_bytes = inner.get(ctx, "key").await?.unwrap().into_raw_bytes();
+        assert_eq!(envelope::key_id(&inner_bytes)?, "k2");
+        let get = new_only.get(ctx, "key").await?.expect("blob is missing");
+        assert_eq!(get.into_raw_bytes(), value);
+
+        // But reading a blob with a key that was dropped from the keyring fails.
+        let dropped = EncryptedBlob::new(inner.clone(), keyring(&["k3"], "k3"));
+        assert!(dropped.get(ctx, "key").await.is_err());
+        Ok(())
+    }
+}
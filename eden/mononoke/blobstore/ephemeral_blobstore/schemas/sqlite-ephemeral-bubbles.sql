@@ -0,0 +1,20 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+CREATE TABLE IF NOT EXISTS ephemeral_bubbles (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_at BIGINT NOT NULL,
+  expires_at BIGINT NOT NULL,
+  -- 0: open, 1: being promoted, 2: promoted
+  status TINYINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS ephemeral_bubble_keys (
+  bubble_id BIGINT NOT NULL,
+  blob_key VARCHAR(255) NOT NULL,
+  PRIMARY KEY (bubble_id, blob_key)
+);
@@ -0,0 +1,20 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use thiserror::Error;
+
+use crate::BubbleId;
+
+#[derive(Debug, Error)]
+pub enum ErrorKind {
+    #[error("Bubble {0} does not exist")]
+    NoSuchBubble(BubbleId),
+    #[error("Bubble {0} has expired")]
+    BubbleExpired(BubbleId),
+    #[error("Bubble {0} is being promoted, it can't be written to")]
+    BubblePromoting(BubbleId),
+}
@@ -0,0 +1,456 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Ephemeral "bubbles" for blobs that may never be needed for long, e.g. those of draft commits
+//! that are only pushed for CI. Each bubble has its own TTL, and its blobs are kept apart from
+//! the persistent blobstore until the bubble is promoted, e.g. when its commit becomes public.
+
+mod errors;
+mod store;
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use context::CoreContext;
+use futures::stream::{self, StreamExt, TryStreamExt};
+
+use blobstore::{Blobstore, BlobstoreGetData};
+use mononoke_types::{BlobstoreBytes, DateTime};
+
+pub use crate::errors::ErrorKind;
+use crate::store::BubbleStatus;
+pub use crate::store::SqlBubbleStore;
+
+// How many blobs to copy to the persistent blobstore at once when promoting a bubble.
+const PROMOTE_CONCURRENCY: usize = 100;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct BubbleId(u64);
+
+impl BubbleId {
+    pub fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for BubbleId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn now() -> i64 {
+    DateTime::now().timestamp_secs()
+}
+
+struct Inner {
+    ephemeral: Arc<dyn Blobstore>,
+    persistent: Arc<dyn Blobstore>,
+    bubbles: SqlBubbleStore,
+    default_ttl: Duration,
+}
+
+/// Creates and keeps track of the bubbles. The bubbles, and the keys of their blobs, are kept in
+/// `bubbles`, so that they survive restarts and are shared by all the servers. The blobs of the
+/// bubbles go to `ephemeral`, which is expected to drop data on its own after a while (e.g.
+/// because of its retention policy): once a bubble expired it is forgotten, and nothing refers to
+/// its blobs anymore.
+#[derive(Clone)]
+pub struct EphemeralBlobstore {
+    inner: Arc<Inner>,
+}
+
+impl fmt::Debug for EphemeralBlobstore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EphemeralBlobstore")
+            .field("ephemeral", &self.inner.ephemeral)
+            .field("persistent", &self.inner.persistent)
+            .field("default_ttl", &self.inner.default_ttl)
+            .finish()
+    }
+}
+
+impl EphemeralBlobstore {
+    pub fn new(
+        ephemeral: Arc<dyn Blobstore>,
+        persistent: Arc<dyn Blobstore>,
+        bubbles: SqlBubbleStore,
+        default_ttl: Duration,
+    ) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                ephemeral,
+                persistent,
+                bubbles,
+                default_ttl,
+            }),
+        }
+    }
+
+    /// Create a new bubble, which expires after `ttl`, or the default TTL if `ttl` is `None`.
+    pub async fn create_bubble(&self, ttl: Option<Duration>) -> Result<Bubble> {
+        let created_at = now();
+        let ttl = ttl.unwrap_or(self.inner.default_ttl);
+        let expires_at = created_at + ttl.as_secs() as i64;
+        let bubble_id = self
+            .inner
+            .bubbles
+            .create_bubble(created_at, expires_at)
+            .await?;
+        Ok(Bubble {
+            bubble_id,
+            expires_at,
+            store: self.clone(),
+        })
+    }
+
+    pub async fn open_bubble(&self, bubble_id: BubbleId) -> Result<Bubble> {
+        let (expires_at, status) = self
+            .inner
+            .bubbles
+            .get_bubble(bubble_id)
+            .await?
+            .ok_or(ErrorKind::NoSuchBubble(bubble_id))?;
+        if status == BubbleStatus::Open && expires_at <= now() {
+            return Err(ErrorKind::BubbleExpired(bubble_id).into());
+        }
+        Ok(Bubble {
+            bubble_id,
+            expires_at,
+            store: self.clone(),
+        })
+    }
+
+    /// Forget the bubbles that expired, and return their ids.
+    pub async fn cleanup_expired(&self) -> Result<Vec<BubbleId>> {
+        self.inner.bubbles.delete_expired(now()).await
+    }
+}
+
+/// A blobstore for the blobs of one bubble. Reads see the blobs of the bubble and those of the
+/// persistent blobstore, writes only go to the bubble until it is promoted.
+#[derive(Clone)]
+pub struct Bubble {
+    bubble_id: BubbleId,
+    expires_at: i64,
+    store: EphemeralBlobstore,
+}
+
+impl fmt::Display for Bubble {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Bubble<{}, {}, {}>",
+            self.bubble_id, self.store.inner.ephemeral, self.store.inner.persistent
+        )
+    }
+}
+
+impl fmt::Debug for Bubble {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bubble")
+            .field("bubble_id", &self.bubble_id)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl Bubble {
+    pub fn bubble_id(&self) -> BubbleId {
+        self.bubble_id
+    }
+
+    /// Unix timestamp at which the bubble expires, unless it is promoted by then.
+    pub fn expires_at(&self) -> i64 {
+        self.expires_at
+    }
+
+    fn ephemeral_key(&self, key: &str) -> String {
+        format!("eph{}.{}", self.bubble_id, key)
+    }
+
+    async fn status(&self) -> Result<BubbleStatus> {
+        let (_, status) = self
+            .store
+            .inner
+            .bubbles
+            .get_bubble(self.bubble_id)
+            .await?
+            .ok_or(ErrorKind::NoSuchBubble(self.bubble_id))?;
+        if status == BubbleStatus::Open && self.expires_at <= now() {
+            return Err(ErrorKind::BubbleExpired(self.bubble_id).into());
+        }
+        Ok(status)
+    }
+
+    /// Whether reads still need to look at the blobs of the bubble. Only a bubble that looks
+    /// expired needs its status to be looked up: it might have been promoted.
+    async fn reads_ephemeral(&self) -> Result<bool> {
+        if self.expires_at > now() {
+            return Ok(true);
+        }
+        Ok(self.status().await? != BubbleStatus::Promoted)
+    }
+
+    /// Copy all the blobs of the bubble to the persistent blobstore. The bubble can't be written
+    /// to while this runs. If copying fails the bubble stays as it was and promoting it can be
+    /// retried: the blobs that were copied already aren't visible to anyone until whatever
+    /// refers to them (e.g. the commit) is published, which must only happen once this succeeds.
+    ///
+    /// Returns the number of blobs that were copied.
+    pub async fn promote(&self, ctx: &CoreContext) -> Result<usize> {
+        let bubbles = &self.store.inner.bubbles;
+        match self.status().await? {
+            BubbleStatus::Promoted => return Ok(0),
+            BubbleStatus::Promoting => {
+                return Err(ErrorKind::BubblePromoting(self.bubble_id).into());
+            }
+            BubbleStatus::Open => {}
+        }
+        if !bubbles
+            .update_status(self.bubble_id, BubbleStatus::Open, BubbleStatus::Promoting)
+            .await?
+        {
+            // Someone else got to it first.
+            return Err(ErrorKind::BubblePromoting(self.bubble_id).into());
+        }
+
+        let res = async {
+            let keys = bubbles.get_keys(self.bubble_id).await?;
+            stream::iter(keys.iter())
+                .map(|key| self.copy_to_persistent(ctx, key))
+                .buffer_unordered(PROMOTE_CONCURRENCY)
+                .try_collect::<Vec<()>>()
+                .await
+        }
+        .await;
+
+        let status = if res.is_ok() {
+            BubbleStatus::Promoted
+        } else {
+            BubbleStatus::Open
+        };
+        bubbles
+            .update_status(self.bubble_id, BubbleStatus::Promoting, status)
+            .await?;
+        let copied = res?;
+
+        // Handles that are still open read from and write to the persistent blobstore from now
+        // on, there is nothing left to keep track of.
+        bubbles.delete_keys(self.bubble_id).await?;
+        Ok(copied.len())
+    }
+
+    async fn copy_to_persistent(&self, ctx: &CoreContext, key: &str) -> Result<()> {
+        let value = self
+            .store
+            .inner
+            .ephemeral
+            .get(ctx, &self.ephemeral_key(key))
+            .await?
+            .ok_or_else(|| {
+                anyhow::anyhow!("Blob {} of bubble {} is missing", key, self.bubble_id)
+            })?;
+        self.store
+            .inner
+            .persistent
+            .put(ctx, key.to_string(), value.into_bytes())
+            .await
+    }
+}
+
+#[async_trait]
+impl Blobstore for Bubble {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        if self.reads_ephemeral().await? {
+            let ephemeral_key = self.ephemeral_key(key);
+            if let Some(value) = self.store.inner.ephemeral.get(ctx, &ephemeral_key).await? {
+                return Ok(Some(value));
+            }
+        }
+        self.store.inner.persistent.get(ctx, key).await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        match self.status().await? {
+            BubbleStatus::Open => {}
+            BubbleStatus::Promoting => {
+                return Err(ErrorKind::BubblePromoting(self.bubble_id).into());
+            }
+            // Everything in the bubble is persistent now, so new blobs should be too.
+            BubbleStatus::Promoted => {
+                return self.store.inner.persistent.put(ctx, key, value).await;
+            }
+        }
+
+        self.store
+            .inner
+            .ephemeral
+            .put(ctx, self.ephemeral_key(&key), value)
+            .await?;
+        self.store
+            .inner
+            .bubbles
+            .add_key(self.bubble_id, key)
+            .await?;
+        // Promotion might have started while the blob was written, and it might not know about
+        // it. If it started after the key was added, it does.
+        if self.status().await? != BubbleStatus::Open {
+            return Err(ErrorKind::BubblePromoting(self.bubble_id).into());
+        }
+        Ok(())
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        if self.reads_ephemeral().await? {
+            let ephemeral_key = self.ephemeral_key(key);
+            if self
+                .store
+                .inner
+                .ephemeral
+                .is_present(ctx, &ephemeral_key)
+                .await?
+            {
+                return Ok(true);
+            }
+        }
+        self.store.inner.persistent.is_present(ctx, key).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use borrowed::borrowed;
+    use fbinit::FacebookInit;
+    use memblob::Memblob;
+    use sql_construct::SqlConstruct;
+
+    fn make_store(bubbles: SqlBubbleStore) -> (EphemeralBlobstore, Memblob, Memblob) {
+        let ephemeral = Memblob::default();
+        let persistent = Memblob::default();
+        let store = EphemeralBlobstore::new(
+            Arc::new(ephemeral.clone()),
+            Arc::new(persistent.clone()),
+            bubbles,
+            Duration::from_secs(3600),
+        );
+        (store, ephemeral, persistent)
+    }
+
+    #[fbinit::test]
+    async fn test_promote(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let (store, _ephemeral, persistent) = make_store(SqlBubbleStore::with_sqlite_in_memory()?);
+        persistent
+            .put(ctx, "public".to_string(), BlobstoreBytes::from_bytes("1"))
+            .await?;
+
+        let bubble = store.create_bubble(None).await?;
+        bubble
+            .put(ctx, "draft".to_string(), BlobstoreBytes::from_bytes("2"))
+            .await?;
+
+        // The bubble sees both, the persistent blobstore only its own blobs.
+        assert!(bubble.is_present(ctx, "public").await?);
+        assert!(bubble.is_present(ctx, "draft").await?);
+        assert!(!persistent.is_present(ctx, "draft").await?);
+
+        let reopened = store.open_bubble(bubble.bubble_id()).await?;
+        assert!(reopened.is_present(ctx, "draft").await?);
+
+        assert_eq!(bubble.promote(ctx).await?, 1);
+        assert_eq!(
+            persistent
+                .get(ctx, "draft")
+                .await?
+                .map(|v| v.into_raw_bytes()),
+            Some("2".into())
+        );
+        // Promoting again is a no-op.
+        assert_eq!(reopened.promote(ctx).await?, 0);
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_expiry(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let (store, ephemeral, _persistent) = make_store(SqlBubbleStore::with_sqlite_in_memory()?);
+
+        let short = store.create_bubble(Some(Duration::from_secs(0))).await?;
+        let long = store.create_bubble(None).await?;
+        long.put(ctx, "draft".to_string(), BlobstoreBytes::from_bytes("1"))
+            .await?;
+        assert!(ephemeral.is_present(ctx, "eph2.draft").await?);
+
+        match short.is_present(ctx, "draft").await {
+            Err(e) => match e.downcast_ref::<ErrorKind>() {
+                Some(ErrorKind::BubbleExpired(_)) => {}
+                _ => panic!("Unexpected error: {:?}", e),
+            },
+            Ok(_) => panic!("Expired bubble should not be readable"),
+        }
+        assert!(short.promote(ctx).await.is_err());
+
+        assert_eq!(store.cleanup_expired().await?, vec![short.bubble_id()]);
+        assert!(store.open_bubble(short.bubble_id()).await.is_err());
+        assert!(store.open_bubble(long.bubble_id()).await.is_ok());
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_restart(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let bubbles = SqlBubbleStore::with_sqlite_in_memory()?;
+        let (store, ephemeral, persistent) = make_store(bubbles.clone());
+
+        let before = store.create_bubble(None).await?;
+        before
+            .put(ctx, "draft".to_string(), BlobstoreBytes::from_bytes("1"))
+            .await?;
+
+        // A store made afresh over the same bubbles, as after a restart, knows about the bubbles
+        // that were there, and doesn't reuse their ids.
+        let restarted = EphemeralBlobstore::new(
+            Arc::new(ephemeral),
+            Arc::new(persistent.clone()),
+            bubbles,
+            Duration::from_secs(3600),
+        );
+        let after = restarted.create_bubble(None).await?;
+        assert_ne!(after.bubble_id(), before.bubble_id());
+        assert!(!after.is_present(ctx, "draft").await?);
+
+        let reopened = restarted.open_bubble(before.bubble_id()).await?;
+        assert!(reopened.is_present(ctx, "draft").await?);
+        assert_eq!(reopened.promote(ctx).await?, 1);
+        assert!(persistent.is_present(ctx, "draft").await?);
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{format_err, Result};
+use sql::{queries, Connection};
+use sql_construct::{SqlConstruct, SqlConstructFromMetadataDatabaseConfig};
+use sql_ext::SqlConnections;
+
+use crate::BubbleId;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum BubbleStatus {
+    Open,
+    Promoting,
+    Promoted,
+}
+
+impl BubbleStatus {
+    fn to_sql(self) -> u8 {
+        match self {
+            Self::Open => 0,
+            Self::Promoting => 1,
+            Self::Promoted => 2,
+        }
+    }
+
+    fn from_sql(status: u8) -> Result<Self> {
+        match status {
+            0 => Ok(Self::Open),
+            1 => Ok(Self::Promoting),
+            2 => Ok(Self::Promoted),
+            _ => Err(format_err!("Invalid bubble status {}", status)),
+        }
+    }
+}
+
+queries! {
+    write CreateBubble(created_at: i64, expires_at: i64) {
+        none,
+        "INSERT INTO ephemeral_bubbles (created_at, expires_at, status)
+         VALUES ({created_at}, {expires_at}, 0)"
+    }
+
+    read SelectBubble(id: u64) -> (i64, u8) {
+        "SELECT expires_at, status FROM ephemeral_bubbles WHERE id = {id}"
+    }
+
+    write UpdateBubbleStatus(id: u64, from: u8, to: u8) {
+        none,
+        "UPDATE ephemeral_bubbles SET status = {to} WHERE id = {id} AND status = {from}"
+    }
+
+    write AddBubbleKeys(values: (bubble_id: u64, blob_key: String)) {
+        insert_or_ignore,
+        "{insert_or_ignore} INTO ephemeral_bubble_keys (bubble_id, blob_key) VALUES {values}"
+    }
+
+    read SelectBubbleKeys(bubble_id: u64) -> (String) {
+        "SELECT blob_key FROM ephemeral_bubble_keys WHERE bubble_id = {bubble_id}"
+    }
+
+    read SelectExpiredBubbles(now: i64) -> (u64) {
+        "SELECT id FROM ephemeral_bubbles WHERE status = 0 AND expires_at <= {now}"
+    }
+
+    write DeleteBubbles(>list ids: u64) {
+        none,
+        "DELETE FROM ephemeral_bubbles WHERE id IN {ids}"
+    }
+
+    write DeleteBubbleKeys(>list bubble_ids: u64) {
+        none,
+        "DELETE FROM ephemeral_bubble_keys WHERE bubble_id IN {bubble_ids}"
+    }
+}
+
+/// The bubbles and the keys of their blobs, for them to outlive the server that created them.
+/// Everything that decides what a bubble can do is read from the master, so that servers agree
+/// on it.
+#[derive(Clone)]
+pub struct SqlBubbleStore {
+    write_connection: Connection,
+    read_master_connection: Connection,
+}
+
+impl SqlConstruct for SqlBubbleStore {
+    const LABEL: &'static str = "ephemeral_bubbles";
+
+    const CREATION_QUERY: &'static str = include_str!("../schemas/sqlite-ephemeral-bubbles.sql");
+
+    fn from_sql_connections(connections: SqlConnections) -> Self {
+        Self {
+            write_connection: connections.write_connection,
+            read_master_connection: connections.read_master_connection,
+        }
+    }
+}
+
+impl SqlConstructFromMetadataDatabaseConfig for SqlBubbleStore {}
+
+impl SqlBubbleStore {
+    pub(crate) async fn create_bubble(&self, created_at: i64, expires_at: i64) -> Result<BubbleId> {
+        let res = CreateBubble::query(&self.write_connection, &created_at, &expires_at).await?;
+        let id = res
+            .last_insert_id()
+            .ok_or_else(|| format_err!("No id for the new bubble"))?;
+        Ok(BubbleId::new(id))
+    }
+
+    /// When the bubble expires, and its status, if it exists.
+    pub(crate) async fn get_bubble(
+        &self,
+        bubble_id: BubbleId,
+    ) -> Result<Option<(i64, BubbleStatus)>> {
+        let rows = SelectBubble::query(&self.read_master_connection, &bubble_id.id()).await?;
+        rows.into_iter()
+            .next()
+            .map(|(expires_at, status)| Ok((expires_at, BubbleStatus::from_sql(status)?)))
+            .transpose()
+    }
+
+    /// Move the bubble from one status to another. Returns false if it wasn't in `from`.
+    pub(crate) async fn update_status(
+        &self,
+        bubble_id: BubbleId,
+        from: BubbleStatus,
+        to: BubbleStatus,
+    ) -> Result<bool> {
+        let res = UpdateBubbleStatus::query(
+            &self.write_connection,
+            &bubble_id.id(),
+            &from.to_sql(),
+            &to.to_sql(),
+        )
+        .await?;
+        Ok(res.affected_rows() > 0)
+    }
+
+    pub(crate) async fn add_key(&self, bubble_id: BubbleId, key: String) -> Result<()> {
+        AddBubbleKeys::query(&self.write_connection, &[(&bubble_id.id(), &key)]).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn get_keys(&self, bubble_id: BubbleId) -> Result<Vec<String>> {
+        let rows = SelectBubbleKeys::query(&self.read_master_connection, &bubble_id.id()).await?;
+        Ok(rows.into_iter().map(|(key,)| key).collect())
+    }
+
+    pub(crate) async fn delete_keys(&self, bubble_id: BubbleId) -> Result<()> {
+        DeleteBubbleKeys::query(&self.write_connection, &[bubble_id.id()]).await?;
+        Ok(())
+    }
+
+    /// Delete the bubbles that are still open and expired by `now`, and return their ids.
+    pub(crate) async fn delete_expired(&self, now: i64) -> Result<Vec<BubbleId>> {
+        let ids: Vec<u64> = SelectExpiredBubbles::query(&self.read_master_connection, &now)
+            .await?
+            .into_iter()
+            .map(|(id,)| id)
+            .collect();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        DeleteBubbleKeys::query(&self.write_connection, &ids[..]).await?;
+        DeleteBubbles::query(&self.write_connection, &ids[..]).await?;
+        Ok(ids.into_iter().map(BubbleId::new).collect())
+    }
+}
@@ -13,8 +13,10 @@ use blobstore_sync_queue::SqlBlobstoreSyncQueue;
 use cacheblob::CachelibBlobstoreOptions;
 use cached_config::ConfigStore;
 use chaosblob::{ChaosBlobstore, ChaosOptions};
+use circuitbreakerblob::{CircuitBreakerBlobstore, CircuitBreakerOptions};
+use encryptedblob::{EncryptedBlob, Keyring};
 use fbinit::FacebookInit;
-use fileblob::Fileblob;
+use fileblob::{Fileblob, FileblobOptions};
 use futures::{
     compat::Future01CompatExt,
     future::{self, BoxFuture, FutureExt},
@@ -28,6 +30,7 @@ use metaconfig_types::{
 use multiplexedblob::{MultiplexedBlobstore, ScrubAction, ScrubBlobstore, ScrubOptions};
 use packblob::{PackBlob, PackOptions};
 use readonlyblob::ReadOnlyBlobstore;
+use retryblob::{BackendType, RetryBlobstore, RetryOptions};
 use scuba_ext::MononokeScubaSampleBuilder;
 use slog::Logger;
 use sql_construct::SqlConstructFromDatabaseConfig;
@@ -50,6 +53,9 @@ pub struct BlobstoreOptions {
     pub cachelib_options: CachelibBlobstoreOptions,
     pub put_behaviour: PutBehaviour,
     pub scrub_options: Option<ScrubOptions>,
+    pub retry_options: Option<RetryOptions>,
+    pub circuit_breaker_options: Option<CircuitBreakerOptions>,
+    pub fileblob_options: FileblobOptions,
 }
 
 impl BlobstoreOptions {
@@ -73,6 +79,9 @@ impl BlobstoreOptions {
             put_behaviour: put_behaviour.unwrap_or(DEFAULT_PUT_BEHAVIOUR),
             // These are added via the builder methods
             scrub_options: None,
+            retry_options: None,
+            circuit_breaker_options: None,
+            fileblob_options: FileblobOptions::default(),
         }
     }
 
@@ -89,6 +98,30 @@ impl BlobstoreOptions {
         }
     }
 
+    pub fn with_retry_options(self, retry_options: Option<RetryOptions>) -> Self {
+        Self {
+            retry_options,
+            ..self
+        }
+    }
+
+    pub fn with_circuit_breaker_options(
+        self,
+        circuit_breaker_options: Option<CircuitBreakerOptions>,
+    ) -> Self {
+        Self {
+            circuit_breaker_options,
+            ..self
+        }
+    }
+
+    pub fn with_fileblob_options(self, fileblob_options: FileblobOptions) -> Self {
+        Self {
+            fileblob_options,
+            ..self
+        }
+    }
+
     pub fn with_scrub_grace(self, scrub_grace: Option<u64>) -> Self {
         if let Some(mut scrub_options) = self.scrub_options {
             scrub_options.scrub_grace = scrub_grace.map(Duration::from_secs);
@@ -262,8 +295,31 @@ pub async fn make_sql_blobstore<'a>(
     }
 }
 
+/// Where the data of a blobstore that talks to a backend directly is, to tell its circuit breaker
+/// apart from the others.
+fn location(blobconfig: &BlobConfig) -> String {
+    use BlobConfig::*;
+
+    match blobconfig {
+        Files { path } | Sqlite { path } => path.display().to_string(),
+        Manifold { bucket, prefix } | ManifoldWithTtl { bucket, prefix, .. } => {
+            format!("{}/{}", bucket, prefix)
+        }
+        Mysql {
+            remote: ShardableRemoteDatabaseConfig::Unsharded(remote),
+        } => remote.db_address.clone(),
+        Mysql {
+            remote: ShardableRemoteDatabaseConfig::Sharded(remote),
+        } => remote.shard_map.clone(),
+        S3 { bucket, .. } => bucket.clone(),
+        Disabled | Multiplexed { .. } | Logging { .. } | Pack { .. } | Encrypted { .. } => {
+            String::new()
+        }
+    }
+}
+
 // Constructs the BlobstorePutOps store implementations for low level blobstore access
-fn make_blobstore_put_ops<'a>(
+pub fn make_blobstore_put_ops<'a>(
     fb: FacebookInit,
     blobconfig: BlobConfig,
     mysql_options: &'a MysqlOptions,
@@ -276,6 +332,18 @@ fn make_blobstore_put_ops<'a>(
     async move {
         use BlobConfig::*;
 
+        // Only the stores that talk to a backend directly get retries, so that a failure isn't
+        // retried again by every layer above it.
+        let backend = match &blobconfig {
+            Sqlite { .. } | Mysql { .. } => Some(BackendType::Sql),
+            Files { .. } => Some(BackendType::Files),
+            Manifold { .. } | ManifoldWithTtl { .. } => Some(BackendType::Manifold),
+            S3 { .. } => Some(BackendType::S3),
+            Disabled | Multiplexed { .. } | Logging { .. } | Pack { .. } | Encrypted { .. } => None,
+        };
+        let backend_name =
+            backend.map(|backend| format!("{:?}:{}", backend, location(&blobconfig)));
+
         let mut has_components = false;
         let store = match blobconfig {
             Sqlite { .. } | Mysql { .. } => make_sql_blobstore(
@@ -320,9 +388,18 @@ fn make_blobstore_put_ops<'a>(
                 Arc::new(DisabledBlob::new("Disabled by configuration")) as Arc<dyn BlobstorePutOps>
             }
 
-            Files { path } => Fileblob::create(path.join("blobs"), blobstore_options.put_behaviour)
-                .context(ErrorKind::StateOpen)
-                .map(|store| Arc::new(store) as Arc<dyn BlobstorePutOps>)?,
+            Files { path } => {
+                let store = Fileblob::create_with_options(
+                    path.join("blobs"),
+                    blobstore_options.put_behaviour,
+                    blobstore_options.fileblob_options,
+                )
+                .context(ErrorKind::StateOpen)?;
+                if let Some(interval) = blobstore_options.fileblob_options.gc_interval {
+                    store.spawn_gc(interval, logger.clone());
+                }
+                Arc::new(store) as Arc<dyn BlobstorePutOps>
+            }
 
             Logging {
                 blobconfig,
@@ -412,6 +489,26 @@ fn make_blobstore_put_ops<'a>(
                 Arc::new(PackBlob::new(store, blobstore_options.pack_options.clone()))
                     as Arc<dyn BlobstorePutOps>
             }
+            Encrypted {
+                blobconfig,
+                keyring_path,
+                active_key_id,
+            } => {
+                let keyring = Keyring::load(&keyring_path, active_key_id)?;
+                let store = make_blobstore_put_ops(
+                    fb,
+                    *blobconfig,
+                    mysql_options,
+                    readonly_storage,
+                    &blobstore_options,
+                    logger,
+                    config_store,
+                )
+                .watched(logger)
+                .await?;
+
+                Arc::new(EncryptedBlob::new(store, Arc::new(keyring))) as Arc<dyn BlobstorePutOps>
+            }
             S3 {
                 bucket,
                 keychain_group,
@@ -450,6 +547,24 @@ fn make_blobstore_put_ops<'a>(
             }
         };
 
+        let store = match (backend, blobstore_options.retry_options) {
+            (Some(backend), Some(retry_options)) => {
+                Arc::new(RetryBlobstore::new(store, backend, retry_options))
+                    as Arc<dyn BlobstorePutOps>
+            }
+            _ => store,
+        };
+
+        // Outside of the retries, so that an open breaker isn't retried, and an operation that
+        // is only failing after all its retries counts as one failure.
+        let store = match (backend_name, blobstore_options.circuit_breaker_options) {
+            (Some(name), Some(options)) => {
+                Arc::new(CircuitBreakerBlobstore::new(store, name, options))
+                    as Arc<dyn BlobstorePutOps>
+            }
+            _ => store,
+        };
+
         let store = if readonly_storage.0 {
             Arc::new(ReadOnlyBlobstore::new(store)) as Arc<dyn BlobstorePutOps>
         } else {
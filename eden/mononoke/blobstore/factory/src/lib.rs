@@ -15,11 +15,16 @@ mod sql;
 pub use ::blobstore::{PutBehaviour, DEFAULT_PUT_BEHAVIOUR};
 pub use cacheblob::CachelibBlobstoreOptions;
 pub use chaosblob::ChaosOptions;
+pub use circuitbreakerblob::CircuitBreakerOptions;
+pub use fileblob::FileblobOptions;
 pub use multiplexedblob::{scrub::ScrubOptions, ScrubAction};
 pub use packblob::PackOptions;
+pub use retryblob::RetryOptions;
 pub use throttledblob::ThrottleOptions;
 
-pub use crate::blobstore::{make_blobstore, make_sql_blobstore, BlobstoreOptions};
+pub use crate::blobstore::{
+    make_blobstore, make_blobstore_put_ops, make_sql_blobstore, BlobstoreOptions,
+};
 pub use crate::sql::{make_metadata_sql_factory, MetadataSqlFactory, SqlTierInfo};
 
 #[derive(Copy, Clone, PartialEq)]
@@ -9,24 +9,27 @@
 
 use std::collections::HashSet;
 use std::convert::TryFrom;
-use std::fs::create_dir_all;
+use std::fs::{self as std_fs, create_dir_all};
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use anyhow::{bail, format_err, Result};
 use async_trait::async_trait;
-use percent_encoding::{percent_encode, AsciiSet, CONTROLS};
+use percent_encoding::{percent_decode_str, percent_encode, AsciiSet, CONTROLS};
 
 use blobstore::{
     Blobstore, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreKeyParam, BlobstoreKeySource,
-    BlobstoreMetadata, BlobstorePutOps, BlobstoreWithLink, OverwriteStatus, PutBehaviour,
+    BlobstoreMetadata, BlobstorePutOps, BlobstoreWithFileHandle, BlobstoreWithLink,
+    OverwriteStatus, PutBehaviour,
 };
 use context::CoreContext;
 use mononoke_types::BlobstoreBytes;
+use slog::{warn, Logger};
 use tempfile::{NamedTempFile, PersistError};
 use tokio::{
-    fs::{hard_link, File},
+    fs::{self as tokio_fs, hard_link, File},
     io::{self, AsyncReadExt, AsyncWriteExt},
+    task::{spawn_blocking, JoinHandle},
 };
 
 use walkdir::WalkDir;
@@ -36,39 +39,279 @@ const PREFIX: &str = "blob";
 const FRAGMENT: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b'<').add(b'>').add(b'`');
 // https://url.spec.whatwg.org/#path-percent-encode-set
 const PATH: &AsciiSet = &FRAGMENT.add(b'#').add(b'?').add(b'{').add(b'}');
+// Prefix of the temporary files that puts write to before renaming them into place.
+const TEMPFILE_PREFIX: &str = ".tmp";
+// Each level of sharding uses 2 hex digits of a 64 bit hash of the key.
+const MAX_SHARD_LEVELS: usize = 8;
+
+#[derive(Clone, Copy, Debug)]
+pub struct FileblobOptions {
+    /// How many levels of directories to spread the blobs over, each level having up to 256
+    /// directories. With 0, all the blobs are in the base directory.
+    pub shard_levels: usize,
+    /// Whether to fsync blobs (and the directories they are in) before a put returns.
+    pub fsync: bool,
+    /// How often to run `gc` in the background, if at all.
+    pub gc_interval: Option<Duration>,
+}
+
+impl Default for FileblobOptions {
+    fn default() -> Self {
+        Self {
+            shard_levels: 0,
+            fsync: true,
+            gc_interval: None,
+        }
+    }
+}
+
+/// What a `gc` pass did.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FileblobGcStats {
+    /// Blobs moved from the base directory to their shard, after sharding was enabled.
+    pub moved_blobs: u64,
+    /// Temporary files left behind by puts that didn't finish.
+    pub removed_tempfiles: u64,
+    pub removed_empty_dirs: u64,
+}
 
 #[derive(Debug, Clone)]
 pub struct Fileblob {
     base: PathBuf,
     put_behaviour: PutBehaviour,
+    options: FileblobOptions,
 }
 
 impl Fileblob {
     pub fn open<P: AsRef<Path>>(base: P, put_behaviour: PutBehaviour) -> Result<Self> {
+        Self::open_with_options(base, put_behaviour, FileblobOptions::default())
+    }
+
+    pub fn create<P: AsRef<Path>>(base: P, put_behaviour: PutBehaviour) -> Result<Self> {
+        Self::create_with_options(base, put_behaviour, FileblobOptions::default())
+    }
+
+    pub fn open_with_options<P: AsRef<Path>>(
+        base: P,
+        put_behaviour: PutBehaviour,
+        options: FileblobOptions,
+    ) -> Result<Self> {
         let base = base.as_ref();
 
         if !base.is_dir() {
             bail!("Base {:?} doesn't exist or is not directory", base);
         }
+        if options.shard_levels > MAX_SHARD_LEVELS {
+            bail!(
+                "Fileblob supports up to {} shard levels, {} requested",
+                MAX_SHARD_LEVELS,
+                options.shard_levels
+            );
+        }
 
         Ok(Self {
             base: base.to_owned(),
             put_behaviour,
+            options,
         })
     }
 
-    pub fn create<P: AsRef<Path>>(base: P, put_behaviour: PutBehaviour) -> Result<Self> {
+    pub fn create_with_options<P: AsRef<Path>>(
+        base: P,
+        put_behaviour: PutBehaviour,
+        options: FileblobOptions,
+    ) -> Result<Self> {
         let base = base.as_ref();
         create_dir_all(base)?;
-        Self::open(base, put_behaviour)
+        Self::open_with_options(base, put_behaviour, options)
     }
 
-    fn path(&self, key: &str) -> PathBuf {
+    fn file_name(key: &str) -> String {
         let key = percent_encode(key.as_bytes(), PATH);
-        self.base.join(format!("{}-{}", PREFIX, key))
+        format!("{}-{}", PREFIX, key)
+    }
+
+    /// The key of the blob in the file `name`, if it holds one.
+    fn key_from_file_name(name: &str) -> Option<String> {
+        let encoded_key = name.strip_prefix(PREFIX)?.strip_prefix('-')?;
+        percent_decode_str(encoded_key)
+            .decode_utf8()
+            .ok()
+            .map(|key| key.into_owned())
+    }
+
+    fn shard_dir(&self, key: &str) -> PathBuf {
+        let hash = format!("{:016x}", fnv1a(key.as_bytes()));
+        let mut dir = self.base.clone();
+        for level in 0..self.options.shard_levels {
+            dir.push(&hash[level * 2..level * 2 + 2]);
+        }
+        dir
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.shard_dir(key).join(Self::file_name(key))
+    }
+
+    // Where the blob was before sharding was enabled, if it is enabled. `gc` moves blobs from
+    // there to their shard, until then they are read from there.
+    fn unsharded_path(&self, key: &str) -> Option<PathBuf> {
+        if self.options.shard_levels > 0 {
+            Some(self.base.join(Self::file_name(key)))
+        } else {
+            None
+        }
+    }
+
+    async fn open_existing(&self, key: &str) -> Result<Option<File>> {
+        let paths = Some(self.path(key))
+            .into_iter()
+            .chain(self.unsharded_path(key));
+        for p in paths {
+            match File::open(&p).await {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+                Ok(f) => return Ok(Some(f)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Tidy up the blobstore: move blobs that aren't in their shard yet there, and remove
+    /// temporary files older than `min_tempfile_age` as well as empty shard directories.
+    pub async fn gc(&self, min_tempfile_age: Duration) -> Result<FileblobGcStats> {
+        let this = self.clone();
+        spawn_blocking(move || this.gc_blocking(min_tempfile_age)).await?
+    }
+
+    /// Run `gc` every `interval`, for as long as the runtime is up.
+    pub fn spawn_gc(&self, interval: Duration, logger: Logger) -> JoinHandle<()> {
+        let this = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::delay_for(interval).await;
+                // A failed pass is retried with the next one.
+                if let Err(e) = this.gc(interval).await {
+                    warn!(logger, "Fileblob gc of {:?} failed: {:?}", this.base, e);
+                }
+            }
+        })
+    }
+
+    fn gc_blocking(&self, min_tempfile_age: Duration) -> Result<FileblobGcStats> {
+        let mut stats = FileblobGcStats::default();
+        let now = SystemTime::now();
+
+        for entry in std_fs::read_dir(&self.base)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = match name.to_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+
+            if name.starts_with(TEMPFILE_PREFIX) {
+                if is_older_than(&entry.metadata()?, now, min_tempfile_age)
+                    && ignore_not_found(std_fs::remove_file(entry.path()))?
+                {
+                    stats.removed_tempfiles += 1;
+                }
+            } else if self.options.shard_levels > 0 && name.starts_with(PREFIX) {
+                let key = match Self::key_from_file_name(name) {
+                    Some(key) => key,
+                    None => continue,
+                };
+                let dir = self.shard_dir(&key);
+                create_dir_all(&dir)?;
+                // A link doesn't replace the sharded blob if there is one: it was written after
+                // sharding was enabled, possibly while this runs, so it's more recent than this
+                // one.
+                match std_fs::hard_link(entry.path(), dir.join(name)) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                    // Moved since it was listed, e.g. by another gc.
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                }
+                ignore_not_found(std_fs::remove_file(entry.path()))?;
+                stats.moved_blobs += 1;
+            }
+        }
+
+        if self.options.shard_levels > 0 {
+            // Deepest first, so that directories that only contain empty directories go too.
+            // Recent directories are left alone, a put might be about to write to them.
+            for entry in WalkDir::new(&self.base)
+                .min_depth(1)
+                .max_depth(self.options.shard_levels)
+                .contents_first(true)
+            {
+                // Directories can be removed since they were listed, e.g. by another gc.
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) if is_walk_not_found(&e) => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                if !entry.file_type().is_dir() {
+                    continue;
+                }
+                let meta = match entry.metadata() {
+                    Ok(meta) => meta,
+                    Err(e) if is_walk_not_found(&e) => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                if !is_older_than(&meta, now, min_tempfile_age) {
+                    continue;
+                }
+                match std_fs::remove_dir(entry.path()) {
+                    Ok(()) => stats.removed_empty_dirs += 1,
+                    Err(e)
+                        if e.kind() == io::ErrorKind::NotFound
+                            || e.raw_os_error() == Some(libc::ENOTEMPTY) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+
+        Ok(stats)
+    }
+}
+
+// A stable hash for the shard of a key. It doesn't need to be cryptographic, only to spread the
+// keys, which often share long prefixes, evenly.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn is_older_than(meta: &std_fs::Metadata, now: SystemTime, age: Duration) -> bool {
+    match meta.modified() {
+        Ok(modified) => now.duration_since(modified).unwrap_or_default() >= age,
+        Err(_) => false,
     }
 }
 
+// Whether the file was there to be removed. It not being there is fine, as gc only tidies up.
+fn ignore_not_found<T>(res: io::Result<T>) -> io::Result<bool> {
+    match res {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn is_walk_not_found(e: &walkdir::Error) -> bool {
+    e.io_error().map(|e| e.kind()) == Some(io::ErrorKind::NotFound)
+}
+
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    std_fs::File::open(dir)?.sync_all()
+}
+
 impl std::fmt::Display for Fileblob {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Fileblob")
@@ -92,14 +335,21 @@ impl BlobstorePutOps for Fileblob {
         put_behaviour: PutBehaviour,
     ) -> Result<OverwriteStatus> {
         let p = self.path(&key);
+        if self.options.shard_levels > 0 {
+            tokio_fs::create_dir_all(self.shard_dir(&key)).await?;
+        }
         // block_in_place on tempfile would be ideal here, but it interacts
         // badly with tokio_compat
-        let tempfile = NamedTempFile::new_in(&self.base)?;
+        let tempfile = tempfile::Builder::new()
+            .prefix(TEMPFILE_PREFIX)
+            .tempfile_in(&self.base)?;
         let new_file = tempfile.as_file().try_clone()?;
         let mut tokio_file = File::from_std(new_file);
         tokio_file.write_all(value.as_bytes().as_ref()).await?;
         tokio_file.flush().await?;
-        tokio_file.sync_all().await?;
+        if self.options.fsync {
+            tokio_file.sync_all().await?;
+        }
         let status = match put_behaviour {
             PutBehaviour::Overwrite => {
                 tempfile.persist(&p)?;
@@ -126,6 +376,13 @@ impl BlobstorePutOps for Fileblob {
             }
         };
 
+        // The rename has to be durable too.
+        if self.options.fsync && status != OverwriteStatus::Prevented {
+            if let Some(dir) = p.parent() {
+                sync_dir(dir)?;
+            }
+        }
+
         Ok(status)
     }
 
@@ -146,12 +403,9 @@ impl Blobstore for Fileblob {
         _ctx: &'a CoreContext,
         key: &'a str,
     ) -> Result<Option<BlobstoreGetData>> {
-        let p = self.path(key);
-
-        let ret = match File::open(&p).await {
-            Err(ref r) if r.kind() == io::ErrorKind::NotFound => None,
-            Err(e) => return Err(e.into()),
-            Ok(mut f) => {
+        let ret = match self.open_existing(key).await? {
+            None => None,
+            Some(mut f) => {
                 let mut v = Vec::new();
                 f.read_to_end(&mut v).await?;
 
@@ -165,14 +419,7 @@ impl Blobstore for Fileblob {
     }
 
     async fn is_present<'a>(&'a self, _ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
-        let p = self.path(key);
-
-        let ret = match File::open(&p).await {
-            Err(ref e) if e.kind() == io::ErrorKind::NotFound => false,
-            Err(e) => return Err(e.into()),
-            Ok(_) => true,
-        };
-        Ok(ret)
+        Ok(self.open_existing(key).await?.is_some())
     }
 
     async fn put<'a>(
@@ -197,12 +444,33 @@ impl BlobstoreWithLink for Fileblob {
         link_key: String,
     ) -> Result<()> {
         // from std::fs::hard_link: The dst path will be a link pointing to the src path
-        let src_path = self.path(existing_key);
+        let src_path = match self.unsharded_path(existing_key) {
+            Some(unsharded) if !self.path(existing_key).exists() => unsharded,
+            _ => self.path(existing_key),
+        };
         let dst_path = self.path(&link_key);
+        if self.options.shard_levels > 0 {
+            tokio_fs::create_dir_all(self.shard_dir(&link_key)).await?;
+        }
         Ok(hard_link(src_path, dst_path).await?)
     }
 }
 
+#[async_trait]
+impl BlobstoreWithFileHandle for Fileblob {
+    async fn open_file<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<std::fs::File>> {
+        let ret = match self.open_existing(key).await? {
+            None => None,
+            Some(f) => Some(f.into_std().await),
+        };
+        Ok(ret)
+    }
+}
+
 #[async_trait]
 impl BlobstoreKeySource for Fileblob {
     async fn enumerate<'a>(
@@ -216,17 +484,19 @@ impl BlobstoreKeySource for Fileblob {
                     keys: HashSet::new(),
                     next_token: None,
                 };
+                // The range is of keys, which are in the names of the files rather than their
+                // paths, as blobs can be sharded over directories. An empty end is unbounded.
                 WalkDir::new(&self.base)
                     .into_iter()
                     .filter_map(|v| v.ok())
-                    .for_each(|entry| {
-                        let entry = entry.path().to_str();
-                        if let Some(data) = entry {
-                            let key = data.to_string();
-                            if key < range.end_key && key > range.begin_key {
-                                enum_data.keys.insert(key);
-                            }
-                        }
+                    .filter(|entry| entry.file_type().is_file())
+                    .filter_map(|entry| Self::key_from_file_name(entry.file_name().to_str()?))
+                    .filter(|key| {
+                        key >= &range.begin_key
+                            && (range.end_key.is_empty() || key < &range.end_key)
+                    })
+                    .for_each(|key| {
+                        enum_data.keys.insert(key);
                     });
                 Ok(enum_data)
             }
@@ -248,6 +518,7 @@ mod test {
         let blob = Fileblob {
             base: PathBuf::from("/mononoke/fileblob/test/path/should/not/exist"),
             put_behaviour: PutBehaviour::IfAbsent,
+            options: FileblobOptions::default(),
         };
 
         let ret = blob
@@ -258,4 +529,116 @@ mod test {
 
         Ok(())
     }
+
+    #[fbinit::test]
+    async fn test_open_file(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let dir = tempfile::tempdir()?;
+        let blob = Fileblob::create(dir.path(), PutBehaviour::IfAbsent)?;
+
+        assert!(blob.open_file(&ctx, "key").await?.is_none());
+
+        blob.put(&ctx, "key".into(), BlobstoreBytes::from_bytes("value"))
+            .await?;
+
+        let mut contents = String::new();
+        std::io::Read::read_to_string(
+            &mut blob.open_file(&ctx, "key").await?.expect("file is missing"),
+            &mut contents,
+        )?;
+        assert_eq!(contents, "value");
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_enumerate(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let dir = tempfile::tempdir()?;
+        let options = FileblobOptions {
+            shard_levels: 1,
+            ..Default::default()
+        };
+        let blob = Fileblob::open_with_options(dir.path(), PutBehaviour::IfAbsent, options)?;
+        for key in &["repo0001.a", "repo0001.b c", "repo0002.a"] {
+            blob.put(&ctx, key.to_string(), BlobstoreBytes::from_bytes("value"))
+                .await?;
+        }
+        std::fs::write(dir.path().join(".tmpleftover"), "")?;
+
+        let range = BlobstoreKeyParam::from("repo0001.".to_string().."repo0001.\x7f".to_string());
+        let keys = blob.enumerate(&ctx, &range).await?.keys;
+        assert_eq!(
+            keys,
+            vec!["repo0001.a".to_string(), "repo0001.b c".to_string()]
+                .into_iter()
+                .collect()
+        );
+
+        let keys = blob
+            .enumerate(&ctx, &BlobstoreKeyParam::from(..))
+            .await?
+            .keys;
+        assert_eq!(keys.len(), 3);
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_sharding_and_gc(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let dir = tempfile::tempdir()?;
+
+        let unsharded = Fileblob::create(dir.path(), PutBehaviour::IfAbsent)?;
+        unsharded
+            .put(&ctx, "old".into(), BlobstoreBytes::from_bytes("1"))
+            .await?;
+
+        let options = FileblobOptions {
+            shard_levels: 2,
+            fsync: false,
+            gc_interval: None,
+        };
+        let sharded = Fileblob::open_with_options(dir.path(), PutBehaviour::IfAbsent, options)?;
+        sharded
+            .put(&ctx, "new".into(), BlobstoreBytes::from_bytes("2"))
+            .await?;
+        assert!(!dir.path().join("blob-new").exists());
+        assert!(sharded.path("new").exists());
+
+        // Blobs from before sharding are still readable, and gc moves them to their shard.
+        assert!(sharded.is_present(&ctx, "old").await?);
+        let stats = sharded.gc(Duration::from_secs(3600)).await?;
+        assert_eq!(stats.moved_blobs, 1);
+        assert!(!dir.path().join("blob-old").exists());
+        assert_eq!(
+            sharded
+                .get(&ctx, "old")
+                .await?
+                .map(|blob| blob.into_raw_bytes()),
+            Some("1".into())
+        );
+
+        // A blob from before sharding doesn't replace the one in its shard, that is more recent.
+        std::fs::write(dir.path().join("blob-new"), "stale")?;
+        let stats = sharded.gc(Duration::from_secs(3600)).await?;
+        assert_eq!(stats.moved_blobs, 1);
+        assert!(!dir.path().join("blob-new").exists());
+        assert_eq!(
+            sharded
+                .get(&ctx, "new")
+                .await?
+                .map(|blob| blob.into_raw_bytes()),
+            Some("2".into())
+        );
+
+        // Only old enough temp files are removed.
+        std::fs::write(dir.path().join(".tmpleftover"), "")?;
+        let stats = sharded.gc(Duration::from_secs(3600)).await?;
+        assert_eq!(stats.removed_tempfiles, 0);
+        let stats = sharded.gc(Duration::from_secs(0)).await?;
+        assert_eq!(stats.removed_tempfiles, 1);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,106 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::envelope::PackEnvelope;
+use crate::pack;
+
+use anyhow::{format_err, Result};
+use blobstore::BlobstoreMetadata;
+use mononoke_types::{BlobstoreBytes, REPO_PREFIX_REGEX};
+use packblob_thrift::{PackedValue, SingleValue, StorageFormat};
+use std::convert::TryInto;
+
+fn describe_single(value: &SingleValue) -> String {
+    match value {
+        SingleValue::Raw(v) => format!("uncompressed, {} bytes", v.len()),
+        SingleValue::Zstd(v) => format!("zstd compressed, {} bytes", v.len()),
+        SingleValue::UnknownField(e) => format!("unknown encoding {:?}", e),
+    }
+}
+
+/// Decode the envelope that PackBlob stored for `key`, for debugging. Returns a description of
+/// how the value was stored, outermost layer first, along with the value itself.
+pub fn decode_envelope(
+    key: &str,
+    envelope: BlobstoreBytes,
+) -> Result<(Vec<String>, BlobstoreBytes)> {
+    let meta = BlobstoreMetadata::new(None);
+    let envelope: PackEnvelope = envelope.try_into()?;
+
+    let mut layers = vec![];
+    let value = match envelope.0.storage {
+        StorageFormat::Single(single) => {
+            layers.push(format!("single value, {}", describe_single(&single)));
+            pack::decode_independent(meta, single)?
+        }
+        StorageFormat::Packed(packed) => {
+            layers.push(format!(
+                "pack {}, {} entries",
+                packed.key,
+                packed.entries.len()
+            ));
+            let entry_key = match REPO_PREFIX_REGEX.find(key) {
+                Some(m) => &key[m.end()..],
+                None => key,
+            };
+            if let Some(entry) = packed.entries.iter().find(|entry| entry.key == entry_key) {
+                layers.push(match &entry.data {
+                    PackedValue::Single(single) => {
+                        format!("packed entry, {}", describe_single(single))
+                    }
+                    PackedValue::ZstdFromDict(v) => format!(
+                        "packed entry, zstd delta from {}, {} bytes",
+                        v.dict_key,
+                        v.zstd.len()
+                    ),
+                    PackedValue::UnknownField(e) => {
+                        format!("packed entry, unknown encoding {:?}", e)
+                    }
+                });
+            }
+            pack::decode_pack(meta, packed, key)?
+        }
+        StorageFormat::UnknownField(e) => {
+            return Err(format_err!("StorageFormat::UnknownField {:?}", e));
+        }
+    };
+
+    Ok((layers, value.into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{store::ENVELOPE_SUFFIX, PackBlob, PackOptions};
+    use blobstore::Blobstore;
+    use bytes::Bytes;
+    use context::CoreContext;
+    use fbinit::FacebookInit;
+    use memblob::Memblob;
+    use std::sync::Arc;
+
+    #[fbinit::test]
+    async fn decode_envelope_test(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let innerblob = Arc::new(Memblob::default());
+        let packblob = PackBlob::new(innerblob.clone(), PackOptions::new(Some(0)));
+
+        let key = "repo0000.compressible";
+        let value = BlobstoreBytes::from_bytes(Bytes::from(vec![7u8; 65535]));
+        packblob.put(&ctx, key.to_string(), value.clone()).await?;
+
+        let envelope = innerblob
+            .get(&ctx, &[key, ENVELOPE_SUFFIX].concat())
+            .await?
+            .ok_or_else(|| format_err!("envelope not stored"))?;
+        let (layers, decoded) = decode_envelope(key, envelope.into_bytes())?;
+        assert_eq!(layers.len(), 1);
+        assert!(layers[0].starts_with("single value, zstd compressed"));
+        assert_eq!(decoded, value);
+        Ok(())
+    }
+}
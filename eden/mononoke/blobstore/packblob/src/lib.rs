@@ -8,7 +8,9 @@
 #![deny(warnings)]
 
 mod envelope;
+mod inspect;
 mod pack;
 mod store;
 
-pub use store::{PackBlob, PackOptions};
+pub use inspect::decode_envelope;
+pub use store::{PackBlob, PackOptions, ENVELOPE_SUFFIX};
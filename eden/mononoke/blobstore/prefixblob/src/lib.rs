@@ -13,7 +13,10 @@ use inlinable_string::InlinableString;
 
 use context::CoreContext;
 
-use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
+use blobstore::{
+    Blobstore, BlobstoreGetData, BlobstorePutOps, BlobstoreWithFileHandle, OverwriteStatus,
+    PutBehaviour,
+};
 use mononoke_types::BlobstoreBytes;
 
 /// A layer over an existing blobstore that prepends a fixed string to each get and put.
@@ -105,6 +108,18 @@ impl<T: BlobstorePutOps> BlobstorePutOps for PrefixBlobstore<T> {
     }
 }
 
+#[async_trait]
+impl<T: BlobstoreWithFileHandle> BlobstoreWithFileHandle for PrefixBlobstore<T> {
+    #[inline]
+    async fn open_file<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<std::fs::File>> {
+        self.blobstore.open_file(ctx, &self.prepend(key)).await
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,97 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Every access to a redacted key is recorded here, unsampled, so that the recent ones can be
+//! queried on a running server (e.g. to find out who is still trying to fetch something that
+//! was just redacted). Scuba only gets a sample of them.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use context::CoreContext;
+use lazy_static::lazy_static;
+use mononoke_types::RepositoryId;
+use serde::Serialize;
+
+// Hits are only kept for the server to answer queries about recent ones, so a few of them are
+// enough. Older ones are in scuba.
+const MAX_RECENT_HITS: usize = 1000;
+
+lazy_static! {
+    static ref RECENT_HITS: Mutex<VecDeque<RedactionAuditEvent>> = Mutex::new(VecDeque::new());
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RedactionAuditEvent {
+    /// Seconds since the epoch.
+    pub timestamp: u64,
+    pub repo_id: RepositoryId,
+    pub key: String,
+    pub task: String,
+    pub operation: &'static str,
+    /// The wireproto command or API method that accessed the key, if known.
+    pub command: Option<&'static str>,
+    pub session_id: String,
+    pub unix_name: Option<String>,
+    pub identities: Vec<String>,
+    /// Whether the access was allowed, because the key is only redacted in log-only mode.
+    pub log_only: bool,
+}
+
+impl RedactionAuditEvent {
+    pub(crate) fn new(
+        ctx: &CoreContext,
+        repo_id: RepositoryId,
+        key: &str,
+        task: &str,
+        operation: &'static str,
+        log_only: bool,
+    ) -> Self {
+        let metadata = ctx.metadata();
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            repo_id,
+            key: key.to_string(),
+            task: task.to_string(),
+            operation,
+            command: ctx.caller_tag(),
+            session_id: metadata.session_id().to_string(),
+            unix_name: metadata.unix_name().map(String::from),
+            identities: metadata
+                .identities()
+                .iter()
+                .map(|id| id.to_string())
+                .collect(),
+            log_only,
+        }
+    }
+
+    pub(crate) fn record(self) {
+        let mut hits = RECENT_HITS.lock().expect("lock poisoned");
+        if hits.len() >= MAX_RECENT_HITS {
+            hits.pop_front();
+        }
+        hits.push_back(self);
+    }
+}
+
+/// The most recent accesses to redacted keys, newest first, optionally only those of one repo.
+pub fn recent_redaction_hits(
+    repo_id: Option<RepositoryId>,
+    limit: usize,
+) -> Vec<RedactionAuditEvent> {
+    let hits = RECENT_HITS.lock().expect("lock poisoned");
+    hits.iter()
+        .rev()
+        .filter(|hit| repo_id.map_or(true, |repo_id| hit.repo_id == repo_id))
+        .take(limit)
+        .cloned()
+        .collect()
+}
@@ -9,6 +9,13 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum ErrorKind {
-    #[error("The blob {0} is censored. \n Task/Sev: {1}")]
-    Censored(String, String),
+    #[error("The blob {0} is censored. \n Task/Sev: {1}{}", reference(.2))]
+    Censored(String, String, Option<String>),
+}
+
+fn reference(reference_url: &Option<String>) -> String {
+    match reference_url {
+        Some(url) => format!(" \n See: {}", url),
+        None => String::new(),
+    }
 }
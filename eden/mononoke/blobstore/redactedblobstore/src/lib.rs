@@ -7,6 +7,7 @@
 
 #![deny(warnings)]
 
+mod audit;
 mod errors;
 mod store;
 
@@ -14,7 +15,8 @@ use anyhow::{Error, Result};
 use async_trait::async_trait;
 use blobstore::{Blobstore, BlobstoreGetData};
 use context::CoreContext;
-use mononoke_types::BlobstoreBytes;
+use mononoke_types::{BlobstoreBytes, ContentAlias, MononokeId, RepositoryId};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use scuba_ext::MononokeScubaSampleBuilder;
 use slog::debug;
 use std::collections::HashMap;
@@ -23,6 +25,7 @@ use std::num::NonZeroU64;
 use std::{ops::Deref, sync::Arc};
 use tunables::tunables;
 
+pub use crate::audit::{recent_redaction_hits, RedactionAuditEvent};
 pub use crate::errors::ErrorKind;
 pub use crate::store::{RedactedMetadata, SqlRedactedContentStore};
 
@@ -31,10 +34,13 @@ pub mod config {
     pub const PUT_OPERATION: &str = "PUT";
 }
 
+const ALIAS_PREFIX: &str = "alias.";
+
 #[derive(Debug, Clone)]
 pub struct RedactedBlobstoreConfigInner {
     redacted: Option<HashMap<String, RedactedMetadata>>,
     scuba_builder: MononokeScubaSampleBuilder,
+    repo_id: RepositoryId,
 }
 
 #[derive(Debug, Clone)]
@@ -54,11 +60,13 @@ impl RedactedBlobstoreConfig {
     pub fn new(
         redacted: Option<HashMap<String, RedactedMetadata>>,
         scuba_builder: MononokeScubaSampleBuilder,
+        repo_id: RepositoryId,
     ) -> Self {
         Self {
             inner: Arc::new(RedactedBlobstoreConfigInner {
                 redacted,
                 scuba_builder,
+                repo_id,
             }),
         }
     }
@@ -134,22 +142,63 @@ impl<T: Blobstore> RedactedBlobstoreInner<T> {
     ) -> Result<&T> {
         match &self.config.redacted {
             Some(redacted) => redacted.get(key).map_or(Ok(&self.blobstore), |metadata| {
+                let event = RedactionAuditEvent::new(
+                    ctx,
+                    self.config.repo_id,
+                    key,
+                    &metadata.task,
+                    operation,
+                    metadata.log_only,
+                );
                 debug!(
                     ctx.logger(),
-                    "{} operation with redacted blobstore with key {:?}", operation, key
+                    "{} operation with redacted blobstore with key {:?}", operation, key;
+                    "repo_id" => event.repo_id.id(),
+                    "task" => &event.task,
+                    "command" => event.command,
+                    "session_id" => &event.session_id,
+                    "unix_name" => &event.unix_name,
+                    "log_only" => event.log_only
                 );
                 self.to_scuba_redacted_blob_accessed(&ctx, &key, operation);
+                event.record();
 
                 if metadata.log_only {
                     Ok(&self.blobstore)
                 } else {
-                    Err(ErrorKind::Censored(key.to_string(), metadata.task.to_string()).into())
+                    Err(ErrorKind::Censored(
+                        key.to_string(),
+                        metadata.task.to_string(),
+                        reference_url(&metadata.task),
+                    )
+                    .into())
                 }
             }),
             None => Ok(&self.blobstore),
         }
     }
 
+    // Aliases of small files can have the contents inline, which are then served without the
+    // content being fetched by its own key, so access to them is checked against that key.
+    fn access_inline_alias(
+        &self,
+        ctx: &CoreContext,
+        key: &str,
+        data: &BlobstoreGetData,
+    ) -> Result<()> {
+        if self.config.redacted.is_none() || !key.starts_with(ALIAS_PREFIX) {
+            return Ok(());
+        }
+        // Anything that isn't an alias is left for its readers to fail on.
+        if let Ok(alias) = ContentAlias::from_bytes(data.as_raw_bytes().clone()) {
+            if alias.inline_contents().is_some() {
+                let content_key = alias.content_id().blobstore_key();
+                self.access_blobstore(ctx, &content_key, config::GET_OPERATION)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn to_scuba_redacted_blob_accessed(&self, ctx: &CoreContext, key: &str, operation: &str) {
         let sampling_rate = tunables()
             .get_redacted_logging_sampling_rate()
@@ -170,6 +219,10 @@ impl<T: Blobstore> RedactedBlobstoreInner<T> {
             .add("key", key.to_string())
             .add("session_uuid", ctx.metadata().session_id().to_string());
 
+        if let Some(command) = ctx.caller_tag() {
+            scuba_builder.add("command", command);
+        }
+
         if let Some(unix_username) = ctx.metadata().unix_name() {
             scuba_builder.add("unix_username", unix_username);
         }
@@ -186,7 +239,11 @@ impl<T: Blobstore> Blobstore for RedactedBlobstoreInner<T> {
         key: &'a str,
     ) -> Result<Option<BlobstoreGetData>> {
         let blobstore = self.access_blobstore(ctx, key, config::GET_OPERATION)?;
-        blobstore.get(ctx, key).await
+        let data = blobstore.get(ctx, key).await?;
+        if let Some(data) = &data {
+            self.access_inline_alias(ctx, key, data)?;
+        }
+        Ok(data)
     }
 
     async fn put<'a>(
@@ -226,9 +283,20 @@ impl<B: Blobstore> Blobstore for RedactedBlobstore<B> {
     }
 }
 
+// Where users are pointed to from the errors for redacted content, if that is configured.
+fn reference_url(task: &str) -> Option<String> {
+    let url = tunables().get_redaction_reference_url();
+    if url.is_empty() {
+        None
+    } else {
+        let task = utf8_percent_encode(task, NON_ALPHANUMERIC).to_string();
+        Some(url.replace("{task}", &task))
+    }
+}
+
 pub fn has_redaction_root_cause(e: &Error) -> bool {
     match e.root_cause().downcast_ref::<ErrorKind>() {
-        Some(ErrorKind::Censored(_, _)) => true,
+        Some(ErrorKind::Censored(_, _, _)) => true,
         None => false,
     }
 }
@@ -240,9 +308,13 @@ mod test {
     use borrowed::borrowed;
     use context::CoreContext;
     use fbinit::FacebookInit;
+    use futures::FutureExt;
     use maplit::hashmap;
     use memblob::Memblob;
+    use mononoke_types::FileContents;
+    use mononoke_types_mocks::repo::{REPO_ONE, REPO_TWO};
     use prefixblob::PrefixBlobstore;
+    use tunables::{with_tunables_async, MononokeTunables};
 
     #[fbinit::test]
     async fn test_redacted_key(fb: FacebookInit) {
@@ -266,6 +338,7 @@ mod test {
             RedactedBlobstoreConfig::new(
                 Some(redacted_pairs),
                 MononokeScubaSampleBuilder::with_discard(),
+                RepositoryId::new(0),
             ),
         );
 
@@ -280,7 +353,7 @@ mod test {
 
         assert_matches!(
             res.expect_err("the key should be redacted").downcast::<ErrorKind>(),
-            Ok(ErrorKind::Censored(_, ref task)) if task == &redacted_task
+            Ok(ErrorKind::Censored(_, ref task, _)) if task == &redacted_task
         );
 
         //Test key added to the blob
@@ -298,7 +371,7 @@ mod test {
 
         assert_matches!(
             res.expect_err("the key should be redacted").downcast::<ErrorKind>(),
-            Ok(ErrorKind::Censored(_, ref task)) if task == &redacted_task
+            Ok(ErrorKind::Censored(_, ref task, _)) if task == &redacted_task
         );
 
         // Test accessing a key which exists and is accesible
@@ -327,6 +400,7 @@ mod test {
             RedactedBlobstoreConfig::new(
                 Some(redacted_pairs),
                 MononokeScubaSampleBuilder::with_discard(),
+                RepositoryId::new(0),
             ),
         );
 
@@ -340,4 +414,119 @@ mod test {
 
         Ok(())
     }
+
+    #[fbinit::test]
+    async fn test_redacted_inline_alias(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+
+        let redacted = FileContents::new_bytes("redacted").content_id();
+        let unredacted = FileContents::new_bytes("unredacted").content_id();
+        let redacted_pairs = hashmap! {
+            redacted.blobstore_key() => RedactedMetadata {
+                task: "bar task".to_owned(),
+                log_only: false,
+            },
+        };
+
+        let blob = RedactedBlobstore::new(
+            Memblob::default(),
+            RedactedBlobstoreConfig::new(
+                Some(redacted_pairs),
+                MononokeScubaSampleBuilder::with_discard(),
+                RepositoryId::new(0),
+            ),
+        );
+
+        let aliases = vec![
+            (
+                "alias.sha256.inline_redacted",
+                ContentAlias::from_content_id_with_contents(redacted, "redacted".into()),
+                false,
+            ),
+            (
+                "alias.sha256.redacted",
+                ContentAlias::from_content_id(redacted),
+                true,
+            ),
+            (
+                "alias.sha256.inline_unredacted",
+                ContentAlias::from_content_id_with_contents(unredacted, "unredacted".into()),
+                true,
+            ),
+        ];
+        for (key, alias, accessible) in aliases {
+            blob.put(ctx, key.to_owned(), alias.into_blob()).await?;
+            let res = blob.get(ctx, key).await;
+            if accessible {
+                assert!(res?.is_some(), "{} should be accessible", key);
+            } else {
+                assert_matches!(
+                    res.expect_err("the contents should be redacted").downcast::<ErrorKind>(),
+                    Ok(ErrorKind::Censored(ref key, _, _)) if key == &redacted.blobstore_key()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_redaction_audit(fb: FacebookInit) -> Result<()> {
+        // The hits are recorded for the whole process, so this test has a repo to itself.
+        let repo_id = REPO_ONE;
+        let redacted_key = "bar";
+        let redacted_log_only_key = "baz";
+
+        let ctx = CoreContext::test_mock(fb).with_caller_tag("getpack");
+        borrowed!(ctx);
+
+        let redacted_pairs = hashmap! {
+            redacted_key.to_owned() => RedactedMetadata {
+                task: "bar task".to_owned(),
+                log_only: false,
+            },
+            redacted_log_only_key.to_owned() => RedactedMetadata {
+                task: "baz task".to_owned(),
+                log_only: true,
+            },
+        };
+
+        let blob = RedactedBlobstore::new(
+            Memblob::default(),
+            RedactedBlobstoreConfig::new(
+                Some(redacted_pairs),
+                MononokeScubaSampleBuilder::with_discard(),
+                repo_id,
+            ),
+        );
+
+        let tunables = MononokeTunables::default();
+        tunables.update_strings(&hashmap! {
+            "redaction_reference_url".to_string() => "https://example.com/{task}".to_string(),
+        });
+        let res = with_tunables_async(tunables, blob.get(ctx, redacted_key).boxed()).await;
+        assert_matches!(
+            res.expect_err("the key should be redacted").downcast::<ErrorKind>(),
+            Ok(ErrorKind::Censored(_, _, Some(ref url))) if url == "https://example.com/bar%20task"
+        );
+
+        blob.get(ctx, redacted_log_only_key).await?;
+        blob.get(ctx, "unredacted").await?;
+
+        let hits = recent_redaction_hits(Some(repo_id), 10);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].key, redacted_log_only_key);
+        assert!(hits[0].log_only);
+        assert_eq!(hits[1].key, redacted_key);
+        assert_eq!(hits[1].task, "bar task");
+        assert_eq!(hits[1].operation, config::GET_OPERATION);
+        assert_eq!(hits[1].command, Some("getpack"));
+        assert!(!hits[1].log_only);
+
+        assert_eq!(recent_redaction_hits(Some(repo_id), 1).len(), 1);
+        assert!(recent_redaction_hits(Some(REPO_TWO), 10).is_empty());
+
+        Ok(())
+    }
 }
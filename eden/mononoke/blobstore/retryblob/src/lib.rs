@@ -0,0 +1,458 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use std::future::Future;
+use std::io;
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
+use context::{CoreContext, PerfCounterType};
+use mononoke_types::BlobstoreBytes;
+use rand::{thread_rng, Rng};
+use sql::mysql_async::error::{Error as MysqlError, ServerError};
+use sql::rusqlite::{Error as SqliteError, ErrorCode as SqliteErrorCode};
+use stats::prelude::*;
+
+define_stats! {
+    prefix = "mononoke.blobstore.retry";
+    retries: dynamic_timeseries("{}.{}.retries", (backend: &'static str, op: &'static str); Rate, Sum),
+    budget_exhausted: dynamic_timeseries("{}.budget_exhausted", (backend: &'static str); Rate, Sum),
+}
+
+/// The kind of storage behind a blobstore. Each kind fails in its own way, so this decides which
+/// errors are worth retrying.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BackendType {
+    Files,
+    Sql,
+    Manifold,
+    S3,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorClass {
+    /// The operation may succeed if retried
+    Transient,
+    /// Retrying won't help
+    Permanent,
+}
+
+impl BackendType {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Files => "files",
+            Self::Sql => "sql",
+            Self::Manifold => "manifold",
+            Self::S3 => "s3",
+        }
+    }
+
+    pub fn classify(self, error: &Error) -> ErrorClass {
+        let transient = error.chain().any(|cause| {
+            if let Some(io_err) = cause.downcast_ref::<io::Error>() {
+                return is_transient_io_error(io_err);
+            }
+            if cause.is::<tokio::time::Elapsed>() {
+                return true;
+            }
+            match self {
+                Self::Sql => is_transient_sql_error(cause),
+                // Their clients aren't part of this build, so only the errors that all backends
+                // share are known to be transient.
+                Self::Files | Self::Manifold | Self::S3 => false,
+            }
+        });
+        if transient {
+            ErrorClass::Transient
+        } else {
+            ErrorClass::Permanent
+        }
+    }
+}
+
+// MySQL server errors that go away on their own: lock wait timeout, deadlock and too many
+// connections.
+const TRANSIENT_MYSQL_ERROR_CODES: &[u16] = &[1205, 1213, 1040];
+
+fn is_transient_sql_error(cause: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(SqliteError::SqliteFailure(err, _)) = cause.downcast_ref::<SqliteError>() {
+        return err.code == SqliteErrorCode::DatabaseBusy
+            || err.code == SqliteErrorCode::DatabaseLocked;
+    }
+    match cause.downcast_ref::<MysqlError>() {
+        Some(MysqlError::Server(ServerError { code, .. })) => {
+            TRANSIENT_MYSQL_ERROR_CODES.contains(code)
+        }
+        Some(MysqlError::Io(_)) => true,
+        _ => false,
+    }
+}
+
+fn is_transient_io_error(err: &io::Error) -> bool {
+    use io::ErrorKind::*;
+
+    match err.kind() {
+        Interrupted | TimedOut | WouldBlock | ConnectionReset | ConnectionAborted | BrokenPipe => {
+            true
+        }
+        _ => false,
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RetryOptions {
+    /// Total number of attempts for an operation, including the first one
+    pub max_attempts: NonZeroU32,
+    /// Upper bound of the delay before the first retry. It doubles for each following retry.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Number of retries a single CoreContext may make over its lifetime. Once it is used up,
+    /// errors are returned straight away, so that a struggling backend isn't hammered further.
+    pub budget_per_context: i64,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            max_attempts: NonZeroU32::new(3).unwrap(),
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+            budget_per_context: 100,
+        }
+    }
+}
+
+impl RetryOptions {
+    // Full jitter: pick anywhere between zero and the exponential backoff for this attempt, so
+    // that clients that failed together don't retry together.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .checked_mul(1 << attempt.saturating_sub(1).min(16))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        exp.mul_f64(thread_rng().gen::<f64>())
+    }
+}
+
+/// A layer over an existing blobstore that retries operations that failed with transient errors.
+#[derive(Debug)]
+pub struct RetryBlobstore<T> {
+    blobstore: T,
+    backend: BackendType,
+    options: RetryOptions,
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for RetryBlobstore<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RetryBlobstore<{}>", &self.blobstore)
+    }
+}
+
+impl<T> RetryBlobstore<T> {
+    pub fn new(blobstore: T, backend: BackendType, options: RetryOptions) -> Self {
+        Self {
+            blobstore,
+            backend,
+            options,
+        }
+    }
+}
+
+/// Run `f` until it succeeds, fails with an error that `backend` doesn't consider transient, or
+/// runs out of attempts or of the retry budget of `ctx`. This is what `RetryBlobstore` does for
+/// each operation, for the callers that talk to a backend other than through a blobstore.
+pub async fn retry<V, F, Fut>(
+    ctx: &CoreContext,
+    backend: BackendType,
+    options: &RetryOptions,
+    op: &'static str,
+    mut f: F,
+) -> Result<V>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<V>>,
+{
+    let mut attempt = 1;
+    loop {
+        let err = match f().await {
+            Ok(v) => return Ok(v),
+            Err(err) => err,
+        };
+
+        if attempt >= options.max_attempts.get() || backend.classify(&err) == ErrorClass::Permanent
+        {
+            return Err(err);
+        }
+
+        let perf_counters = ctx.perf_counters();
+        if perf_counters.get_counter(PerfCounterType::BlobRetries) >= options.budget_per_context {
+            perf_counters.increment_counter(PerfCounterType::BlobRetriesBudgetExhausted);
+            STATS::budget_exhausted.add_value(1, (backend.name(),));
+            return Err(err);
+        }
+        perf_counters.increment_counter(PerfCounterType::BlobRetries);
+        STATS::retries.add_value(1, (backend.name(), op));
+
+        tokio::time::delay_for(options.backoff(attempt)).await;
+        attempt += 1;
+    }
+}
+
+#[async_trait]
+impl<T: BlobstorePutOps> Blobstore for RetryBlobstore<T> {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        retry(ctx, self.backend, &self.options, "get", || {
+            self.blobstore.get(ctx, key)
+        })
+        .await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        self.put_with_status(ctx, key, value).await?;
+        Ok(())
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        retry(ctx, self.backend, &self.options, "is_present", || {
+            self.blobstore.is_present(ctx, key)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl<T: BlobstorePutOps> BlobstorePutOps for RetryBlobstore<T> {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        retry(ctx, self.backend, &self.options, "put", || {
+            self.blobstore
+                .put_explicit(ctx, key.clone(), value.clone(), put_behaviour)
+        })
+        .await
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        retry(ctx, self.backend, &self.options, "put", || {
+            self.blobstore
+                .put_with_status(ctx, key.clone(), value.clone())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::format_err;
+    use borrowed::borrowed;
+    use fbinit::FacebookInit;
+    use memblob::Memblob;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Fails the first `failures` gets with the given error, then defers to a Memblob
+    #[derive(Debug)]
+    struct FlakyBlobstore {
+        inner: Memblob,
+        failures: AtomicU32,
+        make_error: fn() -> Error,
+    }
+
+    impl std::fmt::Display for FlakyBlobstore {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "FlakyBlobstore")
+        }
+    }
+
+    #[async_trait]
+    impl Blobstore for FlakyBlobstore {
+        async fn get<'a>(
+            &'a self,
+            ctx: &'a CoreContext,
+            key: &'a str,
+        ) -> Result<Option<BlobstoreGetData>> {
+            let failures = self.failures.load(Ordering::Relaxed);
+            if failures > 0 {
+                self.failures.store(failures - 1, Ordering::Relaxed);
+                return Err((self.make_error)());
+            }
+            self.inner.get(ctx, key).await
+        }
+
+        async fn put<'a>(
+            &'a self,
+            ctx: &'a CoreContext,
+            key: String,
+            value: BlobstoreBytes,
+        ) -> Result<()> {
+            self.inner.put(ctx, key, value).await
+        }
+    }
+
+    #[async_trait]
+    impl BlobstorePutOps for FlakyBlobstore {
+        async fn put_explicit<'a>(
+            &'a self,
+            ctx: &'a CoreContext,
+            key: String,
+            value: BlobstoreBytes,
+            put_behaviour: PutBehaviour,
+        ) -> Result<OverwriteStatus> {
+            self.inner
+                .put_explicit(ctx, key, value, put_behaviour)
+                .await
+        }
+
+        async fn put_with_status<'a>(
+            &'a self,
+            ctx: &'a CoreContext,
+            key: String,
+            value: BlobstoreBytes,
+        ) -> Result<OverwriteStatus> {
+            self.inner.put_with_status(ctx, key, value).await
+        }
+    }
+
+    fn flaky(
+        failures: u32,
+        make_error: fn() -> Error,
+        options: RetryOptions,
+    ) -> RetryBlobstore<FlakyBlobstore> {
+        RetryBlobstore::new(
+            FlakyBlobstore {
+                inner: Memblob::default(),
+                failures: AtomicU32::new(failures),
+                make_error,
+            },
+            BackendType::Sql,
+            RetryOptions {
+                base_delay: Duration::from_millis(1),
+                ..options
+            },
+        )
+    }
+
+    fn transient() -> Error {
+        // SQLITE_BUSY
+        SqliteError::SqliteFailure(sql::rusqlite::ffi::Error::new(5), None).into()
+    }
+
+    fn permanent() -> Error {
+        format_err!("Table doesn't exist")
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            BackendType::Sql.classify(&transient()),
+            ErrorClass::Transient
+        );
+        assert_eq!(
+            BackendType::Sql.classify(&permanent()),
+            ErrorClass::Permanent
+        );
+        assert_eq!(
+            BackendType::Files.classify(&transient()),
+            ErrorClass::Permanent
+        );
+        // Only the error types count, not what their message says.
+        assert_eq!(
+            BackendType::Sql.classify(&format_err!("Deadlock found when trying to get lock")),
+            ErrorClass::Permanent
+        );
+
+        let io_err: Error = io::Error::from(io::ErrorKind::TimedOut).into();
+        assert_eq!(
+            BackendType::Files.classify(&io_err.context("reading blob")),
+            ErrorClass::Transient
+        );
+        let io_err: Error = io::Error::from(io::ErrorKind::NotFound).into();
+        assert_eq!(BackendType::Files.classify(&io_err), ErrorClass::Permanent);
+    }
+
+    #[fbinit::test]
+    async fn test_retries_transient(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let store = flaky(2, transient, RetryOptions::default());
+
+        store
+            .put(ctx, "key".to_string(), BlobstoreBytes::from_bytes("value"))
+            .await?;
+        assert!(store.get(ctx, "key").await?.is_some());
+        assert_eq!(
+            ctx.perf_counters()
+                .get_counter(PerfCounterType::BlobRetries),
+            2
+        );
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_does_not_retry_permanent(fb: FacebookInit) {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+        let store = flaky(1, permanent, RetryOptions::default());
+
+        assert!(store.get(ctx, "key").await.is_err());
+        assert_eq!(
+            ctx.perf_counters()
+                .get_counter(PerfCounterType::BlobRetries),
+            0
+        );
+    }
+
+    #[fbinit::test]
+    async fn test_gives_up(fb: FacebookInit) {
+        let ctx = CoreContext::test_mock(fb);
+        borrowed!(ctx);
+
+        // Runs out of attempts
+        let store = flaky(3, transient, RetryOptions::default());
+        assert!(store.get(ctx, "key").await.is_err());
+
+        // Runs out of budget
+        let store = flaky(
+            1,
+            transient,
+            RetryOptions {
+                budget_per_context: 2,
+                ..Default::default()
+            },
+        );
+        assert!(store.get(ctx, "key").await.is_err());
+        assert_eq!(
+            ctx.perf_counters()
+                .get_counter(PerfCounterType::BlobRetriesBudgetExhausted),
+            1
+        );
+    }
+}
@@ -0,0 +1,114 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::{
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+use stats::prelude::*;
+use thiserror::Error;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tunables::tunables;
+
+// Roughly matches the size of the per-shard connection pools, so that queries queue here rather
+// than failing in the pool when it runs dry.
+const DEFAULT_MAX_QUERIES_PER_SHARD: usize = 100;
+// How long a query may wait for a slot before we give up and report an error.
+const DEFAULT_QUEUE_DEADLINE: Duration = Duration::from_secs(5);
+
+define_stats! {
+    prefix = "mononoke.sqlblob.admission";
+    utilization_pct: dynamic_timeseries("{}.shard_{}.utilization_pct", (entity: String, shard: usize); Average, Max),
+    queued: dynamic_timeseries("{}.queued", (entity: String); Rate, Sum),
+    queue_wait_ms: dynamic_timeseries("{}.queue_wait_ms", (entity: String); Rate, Sum),
+    deadline_exceeded: dynamic_timeseries("{}.deadline_exceeded", (entity: String); Rate, Sum),
+}
+
+fn max_queries_per_shard() -> usize {
+    match tunables().get_sqlblob_max_queries_per_shard() {
+        max if max > 0 => max as usize,
+        _ => DEFAULT_MAX_QUERIES_PER_SHARD,
+    }
+}
+
+fn queue_deadline() -> Duration {
+    match tunables().get_sqlblob_queue_deadline_ms() {
+        ms if ms > 0 => Duration::from_millis(ms as u64),
+        _ => DEFAULT_QUEUE_DEADLINE,
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Timed out after {waited:?} waiting for a connection to shard {shard_id} of {entity}")]
+pub struct ShardSaturated {
+    pub entity: String,
+    pub shard_id: usize,
+    pub waited: Duration,
+}
+
+/// Limits the number of queries in flight to each shard. When a shard is saturated, queries wait
+/// for a slot for up to a deadline, so that short bursts are absorbed instead of failing. Both are
+/// set by tunables.
+#[derive(Clone)]
+pub struct ShardAdmission {
+    shards: Arc<Vec<Semaphore>>,
+    capacity: usize,
+    entity: String,
+}
+
+impl ShardAdmission {
+    pub fn new(shard_count: NonZeroUsize, entity: String) -> Self {
+        let capacity = max_queries_per_shard();
+        let shards = (0..shard_count.get())
+            .map(|_| Semaphore::new(capacity))
+            .collect();
+        Self {
+            shards: Arc::new(shards),
+            capacity,
+            entity,
+        }
+    }
+
+    /// Wait for a slot on the given shard. The slot is released when the permit is dropped.
+    pub async fn admit(&self, shard_id: usize) -> Result<SemaphorePermit<'_>, Error> {
+        let semaphore = &self.shards[shard_id];
+
+        let permit = match semaphore.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                STATS::queued.add_value(1, (self.entity.clone(),));
+                let start = Instant::now();
+                let permit = tokio::time::timeout(queue_deadline(), semaphore.acquire()).await;
+                let waited = start.elapsed();
+                STATS::queue_wait_ms.add_value(waited.as_millis() as i64, (self.entity.clone(),));
+                match permit {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        STATS::deadline_exceeded.add_value(1, (self.entity.clone(),));
+                        return Err(ShardSaturated {
+                            entity: self.entity.clone(),
+                            shard_id,
+                            waited,
+                        }
+                        .into());
+                    }
+                }
+            }
+        };
+
+        let in_use = self.capacity - semaphore.available_permits();
+        STATS::utilization_pct.add_value(
+            (in_use * 100 / self.capacity.max(1)) as i64,
+            (self.entity.clone(), shard_id),
+        );
+
+        Ok(permit)
+    }
+}
@@ -0,0 +1,106 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Hedged reads: when a replica is browning out, a read that is taking longer than usual is
+//! sent to the master as well, and whichever of the two succeeds first is used. This cuts the
+//! tail latency of fetches at the cost of some extra load on the master, which is capped by a
+//! budget of hedges per second.
+
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+use futures::future::{self, Either};
+use once_cell::sync::Lazy;
+use stats::prelude::*;
+use tunables::tunables;
+
+define_stats! {
+    prefix = "mononoke.sqlblob.hedge";
+    hedged: dynamic_timeseries("{}.hedged", (entity: &'static str); Rate, Sum),
+    hedge_won: dynamic_timeseries("{}.hedge_won", (entity: &'static str); Rate, Sum),
+    over_budget: dynamic_timeseries("{}.over_budget", (entity: &'static str); Rate, Sum),
+}
+
+static BUDGET: Lazy<Budget> = Lazy::new(Budget::new);
+
+/// How many hedges were sent in the current second. Hedges are extra load for the master, which
+/// is what is left when the replicas are struggling, so once the budget for the second is spent
+/// reads are left to finish on the replica instead.
+pub(crate) struct Budget {
+    // The start of the current second, and how many hedges were sent since.
+    window: Mutex<(Instant, i64)>,
+}
+
+impl Budget {
+    pub(crate) fn new() -> Self {
+        Self {
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Take one hedge from the budget of `per_sec` hedges, if there is any left.
+    pub(crate) fn take(&self, per_sec: i64, now: Instant) -> bool {
+        let mut window = self.window.lock().expect("lock poisoned");
+        if now.saturating_duration_since(window.0) >= Duration::from_secs(1) {
+            *window = (now, 0);
+        }
+        if window.1 >= per_sec {
+            return false;
+        }
+        window.1 += 1;
+        true
+    }
+}
+
+fn threshold() -> Option<Duration> {
+    let ms = tunables().get_sqlblob_hedge_threshold_ms();
+    if ms > 0 {
+        Some(Duration::from_millis(ms as u64))
+    } else {
+        None
+    }
+}
+
+/// Run `read`, and if it hasn't completed after the hedging threshold, run `hedge` alongside it.
+/// The first of the two to succeed is returned, and if one of them fails, the other one's result
+/// is. `entity` is what the stats are reported under.
+pub(crate) async fn hedged<T, R, H, HF>(entity: &'static str, read: R, hedge: H) -> Result<T, Error>
+where
+    R: Future<Output = Result<T, Error>>,
+    H: FnOnce() -> HF,
+    HF: Future<Output = Result<T, Error>>,
+{
+    let threshold = match threshold() {
+        Some(threshold) => threshold,
+        None => return read.await,
+    };
+
+    futures::pin_mut!(read);
+    if let Ok(res) = tokio::time::timeout(threshold, read.as_mut()).await {
+        return res;
+    }
+
+    if !BUDGET.take(tunables().get_sqlblob_hedges_per_sec(), Instant::now()) {
+        STATS::over_budget.add_value(1, (entity,));
+        return read.await;
+    }
+    STATS::hedged.add_value(1, (entity,));
+
+    let hedge = hedge();
+    futures::pin_mut!(hedge);
+    match future::select(read, hedge).await {
+        Either::Left((Ok(res), _)) => Ok(res),
+        Either::Left((Err(_), hedge)) => hedge.await,
+        Either::Right((Ok(res), _)) => {
+            STATS::hedge_won.add_value(1, (entity,));
+            Ok(res)
+        }
+        Either::Right((Err(_), read)) => read.await,
+    }
+}
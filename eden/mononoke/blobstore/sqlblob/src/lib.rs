@@ -7,15 +7,18 @@
 
 #![deny(warnings)]
 
+mod admission;
 mod delay;
 #[cfg(fbcode_build)]
 mod facebook;
+mod hedge;
 #[cfg(not(fbcode_build))]
 mod myadmin_delay_dummy;
 mod store;
 #[cfg(test)]
 mod tests;
 
+use crate::admission::ShardAdmission;
 use crate::delay::BlobDelay;
 #[cfg(fbcode_build)]
 use crate::facebook::myadmin_delay;
@@ -202,6 +205,7 @@ impl Sqlblob {
                 let write_connections = Arc::new(write_connections);
                 let read_connections = Arc::new(read_connections);
                 let read_master_connections = Arc::new(read_master_connections);
+                let admission = ShardAdmission::new(shard_num, shardmap.clone());
                 Self::counted(
                     Self {
                         data_store: Arc::new(DataSqlStore::new(
@@ -210,6 +214,7 @@ impl Sqlblob {
                             read_connections.clone(),
                             read_master_connections.clone(),
                             delay.clone(),
+                            admission.clone(),
                         )),
                         chunk_store: Arc::new(ChunkSqlStore::new(
                             shard_num,
@@ -217,6 +222,7 @@ impl Sqlblob {
                             read_connections,
                             read_master_connections,
                             delay,
+                            admission,
                             config_handle,
                         )),
                         put_behaviour,
@@ -354,6 +360,7 @@ impl Sqlblob {
                 let write_connections = Arc::new(write_connections);
                 let read_connections = Arc::new(read_connections);
                 let read_master_connections = Arc::new(read_master_connections);
+                let admission = ShardAdmission::new(shard_num, label.clone());
 
                 Self::counted(
                     Self {
@@ -363,6 +370,7 @@ impl Sqlblob {
                             read_connections.clone(),
                             read_master_connections.clone(),
                             delay.clone(),
+                            admission.clone(),
                         )),
                         chunk_store: Arc::new(ChunkSqlStore::new(
                             shard_num,
@@ -370,6 +378,7 @@ impl Sqlblob {
                             read_connections,
                             read_master_connections,
                             delay,
+                            admission,
                             config_handle,
                         )),
                         put_behaviour,
@@ -439,6 +448,8 @@ impl Sqlblob {
         let config_handle = get_gc_config_handle(config_store)
             .or_else(|_| get_gc_config_handle(&(get_test_config_store().1)))?;
 
+        let admission = ShardAdmission::new(SQLITE_SHARD_NUM, "sqlite".to_string());
+
         Ok(Self::counted(
             Self {
                 data_store: Arc::new(DataSqlStore::new(
@@ -447,6 +458,7 @@ impl Sqlblob {
                     cons.clone(),
                     cons.clone(),
                     BlobDelay::dummy(SQLITE_SHARD_NUM),
+                    admission.clone(),
                 )),
                 chunk_store: Arc::new(ChunkSqlStore::new(
                     SQLITE_SHARD_NUM,
@@ -454,6 +466,7 @@ impl Sqlblob {
                     cons.clone(),
                     cons,
                     BlobDelay::dummy(SQLITE_SHARD_NUM),
+                    admission,
                     config_handle,
                 )),
                 put_behaviour,
@@ -473,6 +486,11 @@ impl Sqlblob {
         &self.data_store
     }
 
+    /// The shard that the data of `key` is stored on. Its chunks may be spread over others.
+    pub fn shard_for_key(&self, key: &str) -> usize {
+        self.data_store.shard(key)
+    }
+
     pub fn get_keys_from_shard(&self, shard_num: usize) -> impl Stream<Item = Result<String>> {
         self.data_store.get_keys_from_shard(shard_num)
     }
@@ -18,7 +18,9 @@ use sql::{queries, Connection};
 use twox_hash::XxHash32;
 use xdb_gc_structs::XdbGc;
 
+use crate::admission::ShardAdmission;
 use crate::delay::BlobDelay;
+use crate::hedge::hedged;
 
 mod types {
     use sql::mysql;
@@ -168,6 +170,7 @@ pub(crate) struct DataSqlStore {
     read_connection: Arc<Vec<Connection>>,
     read_master_connection: Arc<Vec<Connection>>,
     delay: BlobDelay,
+    admission: ShardAdmission,
 }
 
 impl DataSqlStore {
@@ -177,6 +180,7 @@ impl DataSqlStore {
         read_connection: Arc<Vec<Connection>>,
         read_master_connection: Arc<Vec<Connection>>,
         delay: BlobDelay,
+        admission: ShardAdmission,
     ) -> Self {
         Self {
             shard_count,
@@ -184,20 +188,26 @@ impl DataSqlStore {
             read_connection,
             read_master_connection,
             delay,
+            admission,
         }
     }
 
     pub(crate) async fn get(&self, key: &str) -> Result<Option<Chunked>, Error> {
         let shard_id = self.shard(key);
+        let _permit = self.admission.admit(shard_id).await?;
 
-        let rows = {
+        let read = async {
             let rows = SelectData::query(&self.read_connection[shard_id], &key).await?;
             if rows.is_empty() {
-                SelectData::query(&self.read_master_connection[shard_id], &key).await?
+                Ok(SelectData::query(&self.read_master_connection[shard_id], &key).await?)
             } else {
-                rows
+                Ok(rows)
             }
         };
+        let hedge = || async move {
+            Ok(SelectData::query(&self.read_master_connection[shard_id], &key).await?)
+        };
+        let rows = hedged("data", read, hedge).await?;
 
         Ok(rows
             .into_iter()
@@ -221,6 +231,7 @@ impl DataSqlStore {
         let shard_id = self.shard(key);
 
         self.delay.delay(shard_id).await;
+        let _permit = self.admission.admit(shard_id).await?;
 
         let res = InsertData::query(
             &self.write_connection[shard_id],
@@ -243,6 +254,7 @@ impl DataSqlStore {
 
     pub(crate) async fn is_present(&self, key: &str) -> Result<bool, Error> {
         let shard_id = self.shard(key);
+        let _permit = self.admission.admit(shard_id).await?;
 
         let rows = {
             let rows = SelectIsDataPresent::query(&self.read_connection[shard_id], &key).await?;
@@ -270,7 +282,7 @@ impl DataSqlStore {
         .try_flatten_stream()
     }
 
-    fn shard(&self, key: &str) -> usize {
+    pub(crate) fn shard(&self, key: &str) -> usize {
         let mut hasher = XxHash32::with_seed(0);
         hasher.write(key.as_bytes());
         (hasher.finish() % self.shard_count.get() as u64) as usize
@@ -284,6 +296,7 @@ pub(crate) struct ChunkSqlStore {
     read_connection: Arc<Vec<Connection>>,
     read_master_connection: Arc<Vec<Connection>>,
     delay: BlobDelay,
+    admission: ShardAdmission,
     gc_generations: ConfigHandle<XdbGc>,
 }
 
@@ -294,6 +307,7 @@ impl ChunkSqlStore {
         read_connection: Arc<Vec<Connection>>,
         read_master_connection: Arc<Vec<Connection>>,
         delay: BlobDelay,
+        admission: ShardAdmission,
         gc_generations: ConfigHandle<XdbGc>,
     ) -> Self {
         Self {
@@ -302,6 +316,7 @@ impl ChunkSqlStore {
             read_connection,
             read_master_connection,
             delay,
+            admission,
             gc_generations,
         }
     }
@@ -313,15 +328,23 @@ impl ChunkSqlStore {
         chunking_method: ChunkingMethod,
     ) -> Result<BytesMut, Error> {
         let shard_id = self.shard(id, chunk_num, chunking_method);
+        let _permit = self.admission.admit(shard_id).await?;
 
-        let rows = {
+        let read = async {
             let rows = SelectChunk::query(&self.read_connection[shard_id], &id, &chunk_num).await?;
             if rows.is_empty() {
-                SelectChunk::query(&self.read_master_connection[shard_id], &id, &chunk_num).await?
+                Ok(
+                    SelectChunk::query(&self.read_master_connection[shard_id], &id, &chunk_num)
+                        .await?,
+                )
             } else {
-                rows
+                Ok(rows)
             }
         };
+        let hedge = || async move {
+            Ok(SelectChunk::query(&self.read_master_connection[shard_id], &id, &chunk_num).await?)
+        };
+        let rows = hedged("chunk", read, hedge).await?;
         rows.into_iter()
             .next()
             .map(|(value,)| (&*value).into())
@@ -338,6 +361,7 @@ impl ChunkSqlStore {
         let shard_id = self.shard(key, chunk_num, chunking_method);
 
         self.delay.delay(shard_id).await;
+        let _permit = self.admission.admit(shard_id).await?;
         UpdateGeneration::query(
             &self.write_connection[shard_id],
             &key,
@@ -361,6 +385,7 @@ impl ChunkSqlStore {
         let shard_id = self.shard(key, chunk_num, chunking_method);
 
         self.delay.delay(shard_id).await;
+        let _permit = self.admission.admit(shard_id).await?;
         UpdateGeneration::query(
             &self.write_connection[shard_id],
             &key,
@@ -377,6 +402,7 @@ impl ChunkSqlStore {
         chunking_method: ChunkingMethod,
     ) -> Result<Option<u64>, Error> {
         let shard_id = self.shard(key, chunk_num, chunking_method);
+        let _permit = self.admission.admit(shard_id).await?;
         let rows = {
             let rows = GetChunkGeneration::query(&self.read_connection[shard_id], &key).await?;
             if rows.is_empty() {
@@ -397,6 +423,7 @@ impl ChunkSqlStore {
         let put_generation = self.gc_generations.get().put_generation as u64;
         let mark_generation = self.gc_generations.get().mark_generation as u64;
         let shard_id = self.shard(key, chunk_num, chunking_method);
+        let _permit = self.admission.admit(shard_id).await?;
 
         // Short-circuit if we have a generation in replica, and that generation is >=
         // mark_generation
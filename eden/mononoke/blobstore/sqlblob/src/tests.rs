@@ -10,8 +10,11 @@ use blobstore::DEFAULT_PUT_BEHAVIOUR;
 use borrowed::borrowed;
 use bytes::Bytes;
 use fbinit::FacebookInit;
+use futures::future::FutureExt;
+use maplit::hashmap;
 use rand::{distributions::Alphanumeric, thread_rng, Rng, RngCore};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tunables::{with_tunables_async, MononokeTunables};
 
 const UPDATE_WAIT_TIME: Duration = Duration::from_millis(3);
 
@@ -293,3 +296,134 @@ async fn generations(fb: FacebookInit) -> Result<()> {
     assert_eq!(generations, vec![Some(10)], "key2 generation not updated");
     Ok(())
 }
+
+#[tokio::test]
+async fn admission_waits_then_times_out() -> Result<(), Error> {
+    let tunables = MononokeTunables::default();
+    tunables.update_ints(&hashmap! {
+        "sqlblob_max_queries_per_shard".to_string() => 1,
+        "sqlblob_queue_deadline_ms".to_string() => 50,
+    });
+
+    with_tunables_async(
+        tunables,
+        async {
+            let admission = ShardAdmission::new(SINGLE_SHARD_NUM, "test".to_string());
+
+            // A free slot is handed out immediately.
+            let permit = admission.admit(0).await?;
+
+            // A saturated shard makes the next query wait, and fail once the deadline passes.
+            let err = admission
+                .admit(0)
+                .await
+                .err()
+                .expect("admit should time out");
+            assert!(err.is::<admission::ShardSaturated>());
+
+            // A slot released while waiting is picked up by the waiter.
+            let waiter = admission.admit(0);
+            futures::pin_mut!(waiter);
+            assert!(futures::poll!(waiter.as_mut()).is_pending());
+            drop(permit);
+            waiter.await?;
+
+            Ok::<_, Error>(())
+        }
+        .boxed(),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn hedged_read_takes_the_first_success() -> Result<(), Error> {
+    let read = |res: Result<&'static str, Error>| async move {
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+        res
+    };
+
+    // Hedging is off by default.
+    let res = hedge::hedged("test", read(Ok("replica")), || async { Ok("master") }).await?;
+    assert_eq!(res, "replica");
+
+    // A slow read is overtaken by the hedge.
+    let res = with_tunables_async(
+        hedging_tunables(),
+        hedge::hedged("test", read(Ok("replica")), || async { Ok("master") }).boxed(),
+    )
+    .await?;
+    assert_eq!(res, "master");
+
+    // If either of them fails, the other one's result is used.
+    let res = with_tunables_async(
+        hedging_tunables(),
+        hedge::hedged("test", read(Ok("replica")), || async {
+            Err(format_err!("master is down"))
+        })
+        .boxed(),
+    )
+    .await?;
+    assert_eq!(res, "replica");
+
+    let res = with_tunables_async(
+        hedging_tunables(),
+        hedge::hedged(
+            "test",
+            read(Err(format_err!("replica is down"))),
+            || async {
+                tokio::time::delay_for(Duration::from_millis(200)).await;
+                Ok("master")
+            },
+        )
+        .boxed(),
+    )
+    .await?;
+    assert_eq!(res, "master");
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn shard_for_key(fb: FacebookInit) {
+    let (_, config_store) = get_test_config_store();
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx);
+    let suffix: String = thread_rng().sample_iter(&Alphanumeric).take(10).collect();
+    let key = format!("manifoldblob_test_{}", suffix);
+
+    let bs = Sqlblob::with_sqlite_in_memory(DEFAULT_PUT_BEHAVIOUR, &config_store).unwrap();
+
+    let blobstore_bytes = BlobstoreBytes::from_bytes(Bytes::from_static(b"shard"));
+    bs.put(ctx, key.clone(), blobstore_bytes).await.unwrap();
+
+    let keys: Vec<_> = bs
+        .get_keys_from_shard(bs.shard_for_key(&key))
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(keys, vec![key]);
+}
+
+#[test]
+fn hedge_budget_is_per_second() {
+    let budget = hedge::Budget::new();
+    let start = Instant::now();
+
+    assert!(budget.take(2, start));
+    assert!(budget.take(2, start + Duration::from_millis(500)));
+    assert!(!budget.take(2, start + Duration::from_millis(900)));
+
+    // The budget is back a second later, and follows the tunable.
+    assert!(budget.take(1, start + Duration::from_millis(1000)));
+    assert!(!budget.take(1, start + Duration::from_millis(1500)));
+    assert!(!budget.take(0, start + Duration::from_millis(2500)));
+}
+
+fn hedging_tunables() -> MononokeTunables {
+    let tunables = MononokeTunables::default();
+    tunables.update_ints(&hashmap! {
+        "sqlblob_hedge_threshold_ms".to_string() => 10,
+        "sqlblob_hedges_per_sec".to_string() => 100,
+    });
+    tunables
+}
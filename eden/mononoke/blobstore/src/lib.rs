@@ -401,6 +401,20 @@ pub trait BlobstoreWithLink: Blobstore {
     ) -> Result<()>;
 }
 
+/// Mixin trait for blobstores that keep every value in its own local file. This lets callers
+/// serve values straight from disk (e.g. with sendfile) rather than copying them through `get`.
+#[async_trait]
+#[auto_impl(Arc, Box)]
+pub trait BlobstoreWithFileHandle: Blobstore {
+    /// Open the file holding the value associated with `key`, or None if no value is present.
+    /// The file contains exactly the bytes that `get` would return for this key.
+    async fn open_file<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<std::fs::File>>;
+}
+
 /// BlobstoreKeySource Interface
 /// Abstract for use with populate_healer
 #[async_trait]
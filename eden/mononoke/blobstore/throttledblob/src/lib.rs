@@ -10,22 +10,35 @@
 use anyhow::Result;
 use async_trait::async_trait;
 use governor::{
-    clock::DefaultClock,
+    clock::{Clock, DefaultClock},
     state::{direct::NotKeyed, InMemoryState},
-    Jitter, Quota, RateLimiter,
+    Jitter, NegativeMultiDecision, Quota, RateLimiter,
 };
+use lazy_static::lazy_static;
 use nonzero_ext::nonzero;
+use stats::prelude::*;
 use std::{
     convert::TryInto,
     fmt,
     num::{NonZeroU32, NonZeroUsize},
-    time::Duration,
+    sync::Mutex,
+    time::{Duration, Instant},
 };
 
 use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
 use context::CoreContext;
 use mononoke_types::BlobstoreBytes;
 
+mod errors;
+pub use crate::errors::ErrorKind;
+
+define_stats! {
+    prefix = "mononoke.blobstore.throttled";
+    waiters: singleton_counter(),
+    wait_ms: histogram(10, 0, 10_000, Average, Sum, Count; P 50; P 95; P 99),
+    shed: timeseries(Rate, Sum),
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct ThrottleOptions {
     pub read_qps: Option<NonZeroU32>,
@@ -35,6 +48,9 @@ pub struct ThrottleOptions {
     pub read_burst_bytes: Option<NonZeroUsize>,
     pub write_burst_bytes: Option<NonZeroUsize>,
     pub bytes_min_count: Option<NonZeroUsize>,
+    /// Fail operations with `ErrorKind::Throttled` rather than have them wait for quota for
+    /// longer than this. Operations wait for as long as it takes if not set.
+    pub max_wait: Option<Duration>,
 }
 
 impl ThrottleOptions {
@@ -59,15 +75,48 @@ pub const DEFAULT_BYTES_MIN_COUNT: usize = 1_000;
 // Default is set high as we'd rather throttle than error unless specified
 pub const DEFAULT_BURST_BYTES_S: usize = 100_000_000;
 
+type Limiter = RateLimiter<NotKeyed, InMemoryState, DefaultClock>;
+
+// Operations waiting for quota, across all the throttled blobstores of this process. The gauge
+// is set under the lock, so that concurrent waiters can't publish their counts out of order.
+lazy_static! {
+    static ref WAITERS: Mutex<i64> = Mutex::new(0);
+}
+
+fn update_waiters(ctx: &CoreContext, delta: i64) {
+    let mut waiters = WAITERS.lock().expect("lock poisoned");
+    *waiters += delta;
+    STATS::waiters.set_value(ctx.fb, *waiters);
+}
+
+struct Waiter<'a> {
+    ctx: &'a CoreContext,
+}
+
+impl<'a> Waiter<'a> {
+    fn start(ctx: &'a CoreContext) -> Self {
+        update_waiters(ctx, 1);
+        Self { ctx }
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        update_waiters(self.ctx, -1);
+    }
+}
+
 /// A Blobstore that rate limits the number of read and write operations.
 pub struct ThrottledBlob<T: fmt::Debug> {
     blobstore: T,
-    read_qps_limiter: Option<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-    write_qps_limiter: Option<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-    read_bytes_limiter: Option<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
-    write_bytes_limiter: Option<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>,
+    clock: DefaultClock,
+    read_qps_limiter: Option<Limiter>,
+    write_qps_limiter: Option<Limiter>,
+    read_bytes_limiter: Option<Limiter>,
+    write_bytes_limiter: Option<Limiter>,
     bytes_min_count: usize,
-    /// The options fields are used for Debug. They are not consulted at runtime.
+    /// Apart from `max_wait`, the options fields are only used for Debug. The limiters are built
+    /// from them.
     options: ThrottleOptions,
 }
 
@@ -85,8 +134,12 @@ fn jitter() -> Jitter {
 
 impl<T: fmt::Debug + Send + Sync> ThrottledBlob<T> {
     pub async fn new(blobstore: T, options: ThrottleOptions) -> Self {
-        let qps_limiter =
-            |qps: Option<NonZeroU32>| qps.map(|qps| RateLimiter::direct(Quota::per_second(qps)));
+        // The limiters share the clock, so that how long they would make operations wait is
+        // measured against it.
+        let clock = DefaultClock::default();
+        let qps_limiter = |qps: Option<NonZeroU32>| {
+            qps.map(|qps| RateLimiter::direct_with_clock(Quota::per_second(qps), &clock))
+        };
         let read_qps_limiter = qps_limiter(options.read_qps);
         let write_qps_limiter = qps_limiter(options.write_qps);
 
@@ -96,12 +149,13 @@ impl<T: fmt::Debug + Send + Sync> ThrottledBlob<T> {
         let bytes_limiter = |bytes_s: Option<NonZeroUsize>, burst_bytes_s: Option<NonZeroUsize>| {
             bytes_s.map(|bytes_s| {
                 let count_s = bytes_to_count(bytes_min_count, bytes_s.get());
-                RateLimiter::direct(Quota::per_second(count_s).allow_burst(
-                    burst_bytes_s.map_or_else(
+                RateLimiter::direct_with_clock(
+                    Quota::per_second(count_s).allow_burst(burst_bytes_s.map_or_else(
                         || bytes_to_count(bytes_min_count, DEFAULT_BURST_BYTES_S),
                         |burst_bytes_s| bytes_to_count(bytes_min_count, burst_bytes_s.get()),
-                    ),
-                ))
+                    )),
+                    &clock,
+                )
             })
         };
         let read_bytes_limiter = bytes_limiter(options.read_bytes, options.read_burst_bytes);
@@ -109,6 +163,7 @@ impl<T: fmt::Debug + Send + Sync> ThrottledBlob<T> {
 
         Self {
             blobstore,
+            clock,
             read_qps_limiter,
             write_qps_limiter,
             read_bytes_limiter,
@@ -122,6 +177,72 @@ impl<T: fmt::Debug + Send + Sync> ThrottledBlob<T> {
     fn count_n(&self, num_bytes: usize) -> NonZeroU32 {
         bytes_to_count(self.bytes_min_count, num_bytes)
     }
+
+    /// Take `n` counts of quota from `limiter`, waiting for them if it is over its quota. If
+    /// `max_wait` is set and `may_shed` is true, fail instead if they can't be had within
+    /// `max_wait`: straight away if the limiter already says they won't be, or else once
+    /// `max_wait` is up, as other operations waiting on the same limiter may get them first.
+    async fn acquire(
+        &self,
+        ctx: &CoreContext,
+        limiter: &Limiter,
+        n: NonZeroU32,
+        may_shed: bool,
+    ) -> Result<()> {
+        let wait = match limiter.check_n(n) {
+            Ok(()) => return Ok(()),
+            Err(NegativeMultiDecision::BatchNonConforming(_, not_until)) => {
+                not_until.wait_time_from(self.clock.now())
+            }
+            // This can never be had: waiting below fails straight away, as it always has.
+            Err(NegativeMultiDecision::InsufficientCapacity(_)) => Duration::from_secs(0),
+        };
+
+        let start = Instant::now();
+        // Sheds are recorded in the wait times too, for them to show how long the operations
+        // that were shed waited, if at all, rather than only the ones that got quota.
+        let shed = |max_wait| -> Result<()> {
+            STATS::shed.add_value(1);
+            STATS::wait_ms.add_value(start.elapsed().as_millis() as i64);
+            Err(ErrorKind::Throttled(max_wait).into())
+        };
+
+        let max_wait = self.options.max_wait.filter(|_| may_shed);
+        if let Some(max_wait) = max_wait {
+            if wait > max_wait {
+                return shed(max_wait);
+            }
+        }
+
+        let _waiter = Waiter::start(ctx);
+        let ready = limiter.until_n_ready_with_jitter(n, jitter());
+        match max_wait {
+            Some(max_wait) => match tokio::time::timeout(max_wait, ready).await {
+                Ok(res) => res?,
+                Err(_) => return shed(max_wait),
+            },
+            None => ready.await?,
+        }
+        STATS::wait_ms.add_value(start.elapsed().as_millis() as i64);
+        Ok(())
+    }
+
+    async fn acquire_one(&self, ctx: &CoreContext, limiter: &Option<Limiter>) -> Result<()> {
+        match limiter {
+            Some(limiter) => self.acquire(ctx, limiter, nonzero!(1u32), true).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn acquire_write_bytes(&self, ctx: &CoreContext, num_bytes: usize) -> Result<()> {
+        match self.write_bytes_limiter.as_ref() {
+            Some(limiter) => {
+                self.acquire(ctx, limiter, self.count_n(num_bytes), true)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
 }
 
 #[async_trait]
@@ -132,13 +253,9 @@ impl<T: Blobstore> Blobstore for ThrottledBlob<T> {
         ctx: &'a CoreContext,
         key: &'a str,
     ) -> Result<Option<BlobstoreGetData>> {
-        if let Some(limiter) = self.read_qps_limiter.as_ref() {
-            limiter.until_ready_with_jitter(jitter()).await;
-        }
-        if let Some(limiter) = self.read_bytes_limiter.as_ref() {
-            // Only know we'll use some bytes. Access one count so we throttle if already over the limit
-            limiter.until_ready_with_jitter(jitter()).await;
-        }
+        self.acquire_one(ctx, &self.read_qps_limiter).await?;
+        // Only know we'll use some bytes. Access one count so we throttle if already over the limit
+        self.acquire_one(ctx, &self.read_bytes_limiter).await?;
 
         let get_data = self.blobstore.get(ctx, key).await?;
 
@@ -148,9 +265,9 @@ impl<T: Blobstore> Blobstore for ThrottledBlob<T> {
                 let count_n = self.count_n(data.as_bytes().len());
                 let adjusted_n = NonZeroU32::new(count_n.get().saturating_sub(1));
                 if let Some(adjusted_n) = adjusted_n {
-                    limiter
-                        .until_n_ready_with_jitter(adjusted_n, jitter())
-                        .await?;
+                    // The bytes are already read, so they must be accounted for even if it
+                    // takes longer than max_wait.
+                    self.acquire(ctx, limiter, adjusted_n, false).await?;
                 }
             }
         }
@@ -163,26 +280,16 @@ impl<T: Blobstore> Blobstore for ThrottledBlob<T> {
         key: String,
         value: BlobstoreBytes,
     ) -> Result<()> {
-        if let Some(limiter) = self.write_qps_limiter.as_ref() {
-            limiter.until_ready_with_jitter(jitter()).await;
-        }
-        if let Some(limiter) = self.write_bytes_limiter.as_ref() {
-            limiter
-                .until_n_ready_with_jitter(self.count_n(value.len()), jitter())
-                .await?;
-        }
+        self.acquire_one(ctx, &self.write_qps_limiter).await?;
+        self.acquire_write_bytes(ctx, value.len()).await?;
         self.blobstore.put(ctx, key, value).await
     }
 
     async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
-        if let Some(limiter) = self.read_qps_limiter.as_ref() {
-            limiter.until_ready_with_jitter(jitter()).await;
-        }
+        self.acquire_one(ctx, &self.read_qps_limiter).await?;
         // TODO(ahornby) would need to enhance Blobstore::is_present() to know how many bytes it transferred.
         // Some stores fetch just a flag, some fetch all the data then throw it away.
-        if let Some(limiter) = self.read_bytes_limiter.as_ref() {
-            limiter.until_ready_with_jitter(jitter()).await;
-        }
+        self.acquire_one(ctx, &self.read_bytes_limiter).await?;
         self.blobstore.is_present(ctx, key).await
     }
 }
@@ -196,14 +303,8 @@ impl<T: BlobstorePutOps> BlobstorePutOps for ThrottledBlob<T> {
         value: BlobstoreBytes,
         put_behaviour: PutBehaviour,
     ) -> Result<OverwriteStatus> {
-        if let Some(limiter) = self.write_qps_limiter.as_ref() {
-            limiter.until_ready_with_jitter(jitter()).await;
-        }
-        if let Some(limiter) = self.write_bytes_limiter.as_ref() {
-            limiter
-                .until_n_ready_with_jitter(self.count_n(value.len()), jitter())
-                .await?;
-        }
+        self.acquire_one(ctx, &self.write_qps_limiter).await?;
+        self.acquire_write_bytes(ctx, value.len()).await?;
         self.blobstore
             .put_explicit(ctx, key, value, put_behaviour)
             .await
@@ -215,14 +316,8 @@ impl<T: BlobstorePutOps> BlobstorePutOps for ThrottledBlob<T> {
         key: String,
         value: BlobstoreBytes,
     ) -> Result<OverwriteStatus> {
-        if let Some(limiter) = self.write_qps_limiter.as_ref() {
-            limiter.until_ready_with_jitter(jitter()).await;
-        }
-        if let Some(limiter) = self.write_bytes_limiter.as_ref() {
-            limiter
-                .until_n_ready_with_jitter(self.count_n(value.len()), jitter())
-                .await?;
-        }
+        self.acquire_one(ctx, &self.write_qps_limiter).await?;
+        self.acquire_write_bytes(ctx, value.len()).await?;
         self.blobstore.put_with_status(ctx, key, value).await
     }
 }
@@ -235,3 +330,70 @@ impl<T: fmt::Debug> fmt::Debug for ThrottledBlob<T> {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fbinit::FacebookInit;
+    use futures::future;
+    use memblob::Memblob;
+
+    async fn throttled(max_wait: Duration) -> ThrottledBlob<Memblob> {
+        ThrottledBlob::new(
+            Memblob::default(),
+            ThrottleOptions {
+                write_qps: Some(nonzero!(1u32)),
+                max_wait: Some(max_wait),
+                ..ThrottleOptions::default()
+            },
+        )
+        .await
+    }
+
+    fn assert_throttled(res: Result<()>, max_wait: Duration) {
+        match res
+            .expect_err("the put should be shed")
+            .downcast_ref::<ErrorKind>()
+        {
+            Some(ErrorKind::Throttled(wait)) => assert_eq!(*wait, max_wait),
+            None => panic!("the put should fail with ErrorKind::Throttled"),
+        }
+    }
+
+    #[fbinit::test]
+    async fn test_shed_immediately(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let max_wait = Duration::from_millis(10);
+        let blob = throttled(max_wait).await;
+
+        blob.put(&ctx, "a".to_string(), BlobstoreBytes::from_bytes("a"))
+            .await?;
+        // The next put would have to wait for about a second, which is more than max_wait.
+        let res = blob
+            .put(&ctx, "b".to_string(), BlobstoreBytes::from_bytes("b"))
+            .await;
+        assert_throttled(res, max_wait);
+        assert!(!blob.blobstore.is_present(&ctx, "b").await?);
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_shed_on_timeout(fb: FacebookInit) -> Result<()> {
+        tokio::time::pause();
+        let ctx = CoreContext::test_mock(fb);
+        let max_wait = Duration::from_secs(2);
+        let blob = throttled(max_wait).await;
+
+        blob.put(&ctx, "a".to_string(), BlobstoreBytes::from_bytes("a"))
+            .await?;
+        // The next put waits for quota, as it is due within max_wait, but max_wait is up before
+        // the quota is.
+        let put = blob.put(&ctx, "b".to_string(), BlobstoreBytes::from_bytes("b"));
+        let (res, ()) = future::join(put, tokio::time::advance(Duration::from_secs(3))).await;
+        assert_throttled(res, max_wait);
+        assert!(!blob.blobstore.is_present(&ctx, "b").await?);
+
+        Ok(())
+    }
+}
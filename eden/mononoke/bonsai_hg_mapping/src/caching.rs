@@ -5,7 +5,7 @@
  * GNU General Public License version 2.
  */
 
-use super::{BonsaiHgMapping, BonsaiHgMappingEntry, BonsaiOrHgChangesetIds};
+use super::{prefetch, BonsaiHgMapping, BonsaiHgMappingEntry, BonsaiOrHgChangesetIds};
 use anyhow::Error;
 use async_trait::async_trait;
 use bonsai_hg_mapping_entry_thrift as thrift;
@@ -117,9 +117,14 @@ impl BonsaiHgMapping for CachingBonsaiHgMapping {
         repo_id: RepositoryId,
         cs: BonsaiOrHgChangesetIds,
     ) -> Result<Vec<BonsaiHgMappingEntry>, Error> {
+        let (mut res, cs) = prefetch::take_prefetched(ctx, repo_id, cs);
+        if cs.is_empty() {
+            return Ok(res);
+        }
+
         let ctx = (ctx, repo_id, self);
 
-        let res = match cs {
+        let mut fetched = match cs {
             BonsaiOrHgChangesetIds::Bonsai(cs_ids) => {
                 get_or_fill(ctx, cs_ids.into_iter().collect())
                     .await?
@@ -133,6 +138,7 @@ impl BonsaiHgMapping for CachingBonsaiHgMapping {
                 .map(|(_, val)| val)
                 .collect(),
         };
+        res.append(&mut fetched);
 
         Ok(res)
     }
@@ -28,6 +28,7 @@ use stats::prelude::*;
 
 mod caching;
 mod errors;
+mod prefetch;
 
 pub use crate::caching::CachingBonsaiHgMapping;
 pub use crate::errors::ErrorKind;
@@ -146,6 +147,19 @@ pub trait BonsaiHgMapping: Send + Sync {
         Ok(bcs_id)
     }
 
+    /// Load the mappings for all of `cs_ids`, e.g. the range of commits a request is about to
+    /// serve, in a few large queries, and keep them for the rest of the request `ctx` belongs to.
+    /// Lookups of these commits made with `ctx`, or contexts derived from it, are then answered
+    /// without going to the caches or the database.
+    async fn prefetch(
+        &self,
+        ctx: &CoreContext,
+        repo_id: RepositoryId,
+        cs_ids: BonsaiOrHgChangesetIds,
+    ) -> Result<(), Error> {
+        prefetch::prefetch(self, ctx, repo_id, cs_ids).await
+    }
+
     async fn get_many_hg_by_prefix(
         &self,
         ctx: &CoreContext,
@@ -279,12 +293,18 @@ impl BonsaiHgMapping for SqlBonsaiHgMapping {
         repo_id: RepositoryId,
         ids: BonsaiOrHgChangesetIds,
     ) -> Result<Vec<BonsaiHgMappingEntry>, Error> {
+        let (mut mappings, ids) = prefetch::take_prefetched(ctx, repo_id, ids);
+        if ids.is_empty() {
+            return Ok(mappings);
+        }
+
         STATS::gets.add_value(1);
         ctx.perf_counters()
             .increment_counter(PerfCounterType::SqlReadsReplica);
-        let mut mappings = select_mapping(&self.read_connection, repo_id, &ids).await?;
+        let mut replica_mappings = select_mapping(&self.read_connection, repo_id, &ids).await?;
 
-        let left_to_fetch = filter_fetched_ids(ids, &mappings[..]);
+        let left_to_fetch = filter_fetched_ids(ids, &replica_mappings[..]);
+        mappings.append(&mut replica_mappings);
         if left_to_fetch.is_empty() {
             return Ok(mappings);
         }
@@ -0,0 +1,124 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::Error;
+use context::CoreContext;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use mercurial_types::HgChangesetId;
+use mononoke_types::{ChangesetId, RepositoryId};
+
+use crate::{BonsaiHgMapping, BonsaiHgMappingEntry, BonsaiOrHgChangesetIds};
+
+// Large enough that a range of commits only needs a few queries, small enough to keep the
+// queries themselves reasonable.
+const PREFETCH_CHUNK_SIZE: usize = 1000;
+const PREFETCH_CONCURRENCY: usize = 4;
+
+/// Mappings loaded by `prefetch`, kept in the request cache of the CoreContext so that lookups
+/// later in the same request don't have to go to the caches or the database one by one.
+#[derive(Default)]
+struct PrefetchedMappings {
+    inner: Mutex<PrefetchedMappingsInner>,
+}
+
+#[derive(Default)]
+struct PrefetchedMappingsInner {
+    by_bonsai: HashMap<(RepositoryId, ChangesetId), HgChangesetId>,
+    by_hg: HashMap<(RepositoryId, HgChangesetId), ChangesetId>,
+}
+
+impl PrefetchedMappings {
+    fn insert(&self, entries: Vec<BonsaiHgMappingEntry>) {
+        let mut inner = self.inner.lock().expect("poisoned lock");
+        for entry in entries {
+            inner
+                .by_bonsai
+                .insert((entry.repo_id, entry.bcs_id), entry.hg_cs_id);
+            inner
+                .by_hg
+                .insert((entry.repo_id, entry.hg_cs_id), entry.bcs_id);
+        }
+    }
+}
+
+/// Split `ids` into the entries that were prefetched for this request, and the ids that still
+/// have to be fetched.
+pub(crate) fn take_prefetched(
+    ctx: &CoreContext,
+    repo_id: RepositoryId,
+    ids: BonsaiOrHgChangesetIds,
+) -> (Vec<BonsaiHgMappingEntry>, BonsaiOrHgChangesetIds) {
+    let prefetched = match ctx.request_cache().get::<PrefetchedMappings>() {
+        Some(prefetched) => prefetched,
+        None => return (vec![], ids),
+    };
+    let inner = prefetched.inner.lock().expect("poisoned lock");
+
+    let mut found = vec![];
+    let left = match ids {
+        BonsaiOrHgChangesetIds::Bonsai(bcs_ids) => BonsaiOrHgChangesetIds::Bonsai(
+            bcs_ids
+                .into_iter()
+                .filter(|bcs_id| match inner.by_bonsai.get(&(repo_id, *bcs_id)) {
+                    Some(hg_cs_id) => {
+                        found.push(BonsaiHgMappingEntry::new(repo_id, *hg_cs_id, *bcs_id));
+                        false
+                    }
+                    None => true,
+                })
+                .collect(),
+        ),
+        BonsaiOrHgChangesetIds::Hg(hg_cs_ids) => BonsaiOrHgChangesetIds::Hg(
+            hg_cs_ids
+                .into_iter()
+                .filter(|hg_cs_id| match inner.by_hg.get(&(repo_id, *hg_cs_id)) {
+                    Some(bcs_id) => {
+                        found.push(BonsaiHgMappingEntry::new(repo_id, *hg_cs_id, *bcs_id));
+                        false
+                    }
+                    None => true,
+                })
+                .collect(),
+        ),
+    };
+    (found, left)
+}
+
+fn split_into_chunks(ids: BonsaiOrHgChangesetIds) -> Vec<BonsaiOrHgChangesetIds> {
+    match ids {
+        BonsaiOrHgChangesetIds::Bonsai(bcs_ids) => bcs_ids
+            .chunks(PREFETCH_CHUNK_SIZE)
+            .map(|chunk| BonsaiOrHgChangesetIds::Bonsai(chunk.to_vec()))
+            .collect(),
+        BonsaiOrHgChangesetIds::Hg(hg_cs_ids) => hg_cs_ids
+            .chunks(PREFETCH_CHUNK_SIZE)
+            .map(|chunk| BonsaiOrHgChangesetIds::Hg(chunk.to_vec()))
+            .collect(),
+    }
+}
+
+pub(crate) async fn prefetch<M: BonsaiHgMapping + ?Sized>(
+    mapping: &M,
+    ctx: &CoreContext,
+    repo_id: RepositoryId,
+    ids: BonsaiOrHgChangesetIds,
+) -> Result<(), Error> {
+    let (_, left_to_fetch) = take_prefetched(ctx, repo_id, ids);
+    let prefetched = ctx.request_cache().get_or_default::<PrefetchedMappings>();
+
+    stream::iter(split_into_chunks(left_to_fetch))
+        .map(|chunk| mapping.get(ctx, repo_id, chunk))
+        .buffer_unordered(PREFETCH_CONCURRENCY)
+        .try_for_each(|entries| {
+            prefetched.insert(entries);
+            async { Ok(()) }
+        })
+        .await
+}
@@ -17,7 +17,7 @@ use bonsai_hg_mapping::{
     BonsaiHgMapping, BonsaiHgMappingEntry, BonsaiOrHgChangesetIds, CachingBonsaiHgMapping,
     ErrorKind, SqlBonsaiHgMapping,
 };
-use context::CoreContext;
+use context::{CoreContext, PerfCounterType};
 use fbinit::FacebookInit;
 use mercurial_types::{HgChangesetIdPrefix, HgChangesetIdsResolvedFromPrefix};
 use mercurial_types_mocks::nodehash as hg;
@@ -357,6 +357,66 @@ async fn caching<M: BonsaiHgMapping + 'static>(fb: FacebookInit, mapping: M) {
     assert_eq!(gets.load(Ordering::Relaxed), 2);
 }
 
+async fn prefetch<M: BonsaiHgMapping>(fb: FacebookInit, mapping: M) {
+    let ctx = CoreContext::test_mock(fb);
+    let entries = vec![
+        BonsaiHgMappingEntry::new(REPO_ZERO, hg::ONES_CSID, bonsai::ONES_CSID),
+        BonsaiHgMappingEntry::new(REPO_ZERO, hg::TWOS_CSID, bonsai::TWOS_CSID),
+    ];
+    for entry in entries {
+        mapping
+            .add(&ctx, entry)
+            .await
+            .expect("Adding new entry failed");
+    }
+
+    let ctx = CoreContext::test_mock(fb);
+    let sql_reads = |ctx: &CoreContext| {
+        ctx.perf_counters()
+            .get_counter(PerfCounterType::SqlReadsReplica)
+    };
+    mapping
+        .prefetch(
+            &ctx,
+            REPO_ZERO,
+            vec![bonsai::ONES_CSID, bonsai::TWOS_CSID, bonsai::THREES_CSID].into(),
+        )
+        .await
+        .expect("Prefetch failed");
+    assert_eq!(sql_reads(&ctx), 1);
+
+    // Prefetched lookups are answered from the request cache, in both directions, including for
+    // contexts derived from the one that prefetched.
+    let result = mapping
+        .get_hg_from_bonsai(&ctx, REPO_ZERO, bonsai::TWOS_CSID)
+        .await
+        .expect("Failed to get hg changeset by its bonsai counterpart");
+    assert_eq!(result, Some(hg::TWOS_CSID));
+    let tagged_ctx = ctx.with_caller_tag("test");
+    let result = mapping
+        .get_bonsai_from_hg(&tagged_ctx, REPO_ZERO, hg::ONES_CSID)
+        .await
+        .expect("Failed to get bonsai changeset by its hg counterpart");
+    assert_eq!(result, Some(bonsai::ONES_CSID));
+    assert_eq!(sql_reads(&ctx), 1);
+
+    // Commits that had no mapping when prefetching are still looked up.
+    let result = mapping
+        .get_hg_from_bonsai(&ctx, REPO_ZERO, bonsai::THREES_CSID)
+        .await
+        .expect("Failed to get hg changeset by its bonsai counterpart");
+    assert_eq!(result, None);
+    assert_eq!(sql_reads(&ctx), 2);
+
+    // Other requests don't see what was prefetched.
+    let other_ctx = CoreContext::test_mock(fb);
+    mapping
+        .get_hg_from_bonsai(&other_ctx, REPO_ZERO, bonsai::ONES_CSID)
+        .await
+        .expect("Failed to get hg changeset by its bonsai counterpart");
+    assert_eq!(sql_reads(&other_ctx), 1);
+}
+
 #[fbinit::test]
 async fn test_add_and_get(fb: FacebookInit) {
     add_and_get(fb, SqlBonsaiHgMapping::with_sqlite_in_memory().unwrap()).await;
@@ -372,6 +432,11 @@ async fn test_caching(fb: FacebookInit) {
     caching(fb, SqlBonsaiHgMapping::with_sqlite_in_memory().unwrap()).await;
 }
 
+#[fbinit::test]
+async fn test_prefetch(fb: FacebookInit) {
+    prefetch(fb, SqlBonsaiHgMapping::with_sqlite_in_memory().unwrap()).await;
+}
+
 #[fbinit::test]
 async fn test_get_many_hg_by_prefix(fb: FacebookInit) {
     get_many_hg_by_prefix(fb, SqlBonsaiHgMapping::with_sqlite_in_memory().unwrap()).await;
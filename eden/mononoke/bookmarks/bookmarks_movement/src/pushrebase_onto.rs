@@ -160,11 +160,8 @@ impl<'op> PushrebaseOntoBookmarkOp<'op> {
             pushrebase_hooks.push(hook);
         }
 
-        let mut flags = pushrebase_params.flags.clone();
-        if let Some(rewritedates) = bookmark_attrs.should_rewrite_dates(self.bookmark) {
-            // Bookmark config overrides repo flags.rewritedates config
-            flags.rewritedates = rewritedates;
-        }
+        // Bookmark config overrides repo flags
+        let flags = bookmark_attrs.pushrebase_flags(&pushrebase_params.flags, self.bookmark);
 
         ctx.scuba().clone().log_with_msg("Pushrebase started", None);
         let (stats, result) = pushrebase::do_pushrebase_bonsai(
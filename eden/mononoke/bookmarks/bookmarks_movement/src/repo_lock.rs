@@ -8,17 +8,16 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::Result;
 use async_trait::async_trait;
 use bookmarks::BookmarkTransactionError;
 use bytes::Bytes;
 use context::CoreContext;
-use metaconfig_types::RepoReadOnly;
 use mononoke_types::{BonsaiChangesetMut, ChangesetId};
 use pushrebase::{
     PushrebaseCommitHook, PushrebaseHook, PushrebaseTransactionHook, RebasedChangesets,
 };
-use repo_read_write_status::RepoReadWriteFetcher;
+use repo_read_write_status::{RepoReadWriteFetcher, RepoWriteError};
 use sql::Transaction;
 
 use crate::restrictions::BookmarkKind;
@@ -46,12 +45,12 @@ pub(crate) async fn check_repo_lock(
     pushvars: Option<&HashMap<String, Bytes>>,
 ) -> Result<(), BookmarkMovementError> {
     if should_check_repo_lock(kind, pushvars) {
-        let state = repo_read_write_fetcher
-            .readonly()
-            .await
-            .context("Failed to fetch repo lock state")?;
-        if let RepoReadOnly::ReadOnly(reason) = state {
-            return Err(BookmarkMovementError::RepoLocked(reason));
+        match repo_read_write_fetcher.check_writable().await {
+            Ok(()) => {}
+            Err(RepoWriteError::Locked(reason)) => {
+                return Err(BookmarkMovementError::RepoLocked(reason));
+            }
+            Err(e) => return Err(BookmarkMovementError::Error(e.into())),
         }
     }
 
@@ -119,18 +118,10 @@ impl PushrebaseTransactionHook for RepoLockCommitTransactionHook {
         _ctx: &CoreContext,
         txn: Transaction,
     ) -> Result<Transaction, BookmarkTransactionError> {
-        let state = self
-            .repo_read_write_fetcher
-            .readonly()
+        self.repo_read_write_fetcher
+            .check_writable()
             .await
-            .context("Failed to fetch repo lock state")?;
-        if let RepoReadOnly::ReadOnly(reason) = state {
-            return Err(BookmarkTransactionError::Other(anyhow!(
-                "Repo is locked: {}",
-                reason
-            )));
-        }
-
+            .map_err(|e| BookmarkTransactionError::Other(e.into()))?;
         Ok(txn)
     }
 }
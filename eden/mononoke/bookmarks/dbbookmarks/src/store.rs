@@ -218,6 +218,22 @@ queries! {
          LIMIT {max_records}"
     }
 
+    read SelectBookmarkLogEntryAtTimestamp(
+        repo_id: RepositoryId,
+        name: BookmarkName,
+        timestamp: Timestamp
+    ) -> (
+        u64, Option<ChangesetId>, BookmarkUpdateReason, Timestamp
+    ) {
+        "SELECT id, to_changeset_id, reason, timestamp
+         FROM bookmarks_update_log
+         WHERE repo_id = {repo_id}
+           AND name = {name}
+           AND timestamp <= {timestamp}
+         ORDER BY id DESC
+         LIMIT 1"
+    }
+
     read SelectBookmarkLogsWithOffset(repo_id: RepositoryId, name: BookmarkName, max_records: u32, offset: u32) -> (
         u64, Option<ChangesetId>, BookmarkUpdateReason, Timestamp
     ) {
@@ -433,6 +449,35 @@ impl BookmarkUpdateLog for SqlBookmarks {
         .boxed()
     }
 
+    fn get_bookmark_log_entry_at_timestamp(
+        &self,
+        ctx: CoreContext,
+        name: BookmarkName,
+        timestamp: Timestamp,
+        freshness: Freshness,
+    ) -> BoxFuture<
+        'static,
+        Result<Option<(u64, Option<ChangesetId>, BookmarkUpdateReason, Timestamp)>>,
+    > {
+        let conn = if freshness == Freshness::MostRecent {
+            ctx.perf_counters()
+                .increment_counter(PerfCounterType::SqlReadsMaster);
+            self.connections.read_master_connection.clone()
+        } else {
+            ctx.perf_counters()
+                .increment_counter(PerfCounterType::SqlReadsReplica);
+            self.connections.read_connection.clone()
+        };
+        let repo_id = self.repo_id;
+
+        async move {
+            let rows = SelectBookmarkLogEntryAtTimestamp::query(&conn, &repo_id, &name, &timestamp)
+                .await?;
+            Ok(rows.into_iter().next())
+        }
+        .boxed()
+    }
+
     fn count_further_bookmark_log_entries(
         &self,
         ctx: CoreContext,
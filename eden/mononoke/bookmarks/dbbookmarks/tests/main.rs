@@ -17,7 +17,7 @@ use bookmarks::{
 use context::CoreContext;
 use dbbookmarks::SqlBookmarksBuilder;
 use fbinit::FacebookInit;
-use futures::stream::TryStreamExt;
+use futures::{future::TryFutureExt, stream::TryStreamExt};
 use maplit::hashmap;
 use mononoke_types::Timestamp;
 use mononoke_types_mocks::changesetid::{
@@ -1161,6 +1161,58 @@ fn test_list_bookmark_log_entries(fb: FacebookInit) {
     })
 }
 
+#[fbinit::test]
+fn test_get_bookmark_log_entry_at_timestamp(fb: FacebookInit) {
+    async_unit::tokio_unit_test(async move {
+        let ctx = CoreContext::test_mock(fb);
+        let bookmarks = SqlBookmarksBuilder::with_sqlite_in_memory()
+            .unwrap()
+            .with_repo_id(REPO_ZERO);
+        let name_1 = create_bookmark_name("book");
+
+        let before_creation = Timestamp::now();
+
+        let mut txn = bookmarks.create_transaction(ctx.clone());
+        txn.force_set(&name_1, ONES_CSID, BookmarkUpdateReason::TestMove, None)
+            .unwrap();
+        txn.commit().await.unwrap();
+        let at_ones = Timestamp::now();
+
+        let mut txn = bookmarks.create_transaction(ctx.clone());
+        txn.update(
+            &name_1,
+            TWOS_CSID,
+            ONES_CSID,
+            BookmarkUpdateReason::TestMove,
+            None,
+        )
+        .unwrap();
+        txn.commit().await.unwrap();
+        let at_twos = Timestamp::now();
+
+        let mut txn = bookmarks.create_transaction(ctx.clone());
+        txn.force_delete(&name_1, BookmarkUpdateReason::TestMove, None)
+            .unwrap();
+        txn.commit().await.unwrap();
+
+        let value_at = |timestamp| {
+            bookmarks
+                .get_bookmark_log_entry_at_timestamp(
+                    ctx.clone(),
+                    name_1.clone(),
+                    timestamp,
+                    Freshness::MostRecent,
+                )
+                .map_ok(|entry| entry.map(|(_id, cs, _rs, _ts)| cs))
+        };
+
+        assert_eq!(value_at(before_creation).await.unwrap(), None);
+        assert_eq!(value_at(at_ones).await.unwrap(), Some(Some(ONES_CSID)));
+        assert_eq!(value_at(at_twos).await.unwrap(), Some(Some(TWOS_CSID)));
+        assert_eq!(value_at(Timestamp::now()).await.unwrap(), Some(None));
+    })
+}
+
 #[fbinit::test]
 fn test_get_largest_log_id(fb: FacebookInit) {
     async_unit::tokio_unit_test(async move {
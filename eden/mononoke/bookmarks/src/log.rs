@@ -81,6 +81,20 @@ pub trait BookmarkUpdateLog: Send + Sync + 'static {
         max_ts: Timestamp,
     ) -> BoxStream<'static, Result<(u64, Option<ChangesetId>, BookmarkUpdateReason, Timestamp)>>;
 
+    /// Read the last log entry for specific bookmark at or before the given timestamp, i.e. the
+    /// one that tells where the bookmark pointed to at that time. Its changeset id is None if the
+    /// bookmark was deleted then. Returns None if the log has no entries that old.
+    fn get_bookmark_log_entry_at_timestamp(
+        &self,
+        _ctx: CoreContext,
+        name: BookmarkName,
+        timestamp: Timestamp,
+        freshness: Freshness,
+    ) -> BoxFuture<
+        'static,
+        Result<Option<(u64, Option<ChangesetId>, BookmarkUpdateReason, Timestamp)>>,
+    >;
+
     /// Count the number of BookmarkUpdateLog entries with id greater than the given value,
     /// possibly excluding a given reason.
     fn count_further_bookmark_log_entries(
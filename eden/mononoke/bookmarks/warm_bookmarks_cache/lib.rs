@@ -9,6 +9,7 @@
 
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::ops::RangeBounds;
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
@@ -21,7 +22,7 @@ use changeset_info::ChangesetInfo;
 use cloned::cloned;
 use context::CoreContext;
 use deleted_files_manifest::RootDeletedManifestId;
-use derived_data::BonsaiDerivable;
+use derived_data::{BonsaiDerivable, BonsaiDerived};
 use fsnodes::RootFsnodeId;
 use futures::{
     channel::oneshot,
@@ -43,7 +44,9 @@ use tunables::tunables;
 use unodes::RootUnodeManifestId;
 
 mod warmers;
-pub use warmers::{blobimport_changeset_warmer, create_derived_data_warmer};
+pub use warmers::{
+    blobimport_changeset_warmer, create_derived_data_waiter, create_derived_data_warmer,
+};
 
 define_stats! {
     prefix = "mononoke.warm_bookmarks_cache";
@@ -54,6 +57,7 @@ define_stats! {
 
 pub struct WarmBookmarksCache {
     bookmarks: Arc<RwLock<HashMap<BookmarkName, (ChangesetId, BookmarkKind)>>>,
+    max_staleness_secs: Arc<AtomicI64>,
     terminate: Option<oneshot::Sender<()>>,
 }
 
@@ -85,6 +89,21 @@ pub enum InitMode {
     Warm,
 }
 
+#[derive(Clone, Copy)]
+enum Derivation {
+    Derive,
+    Wait,
+}
+
+impl Derivation {
+    fn warmer<D: BonsaiDerived>(self, ctx: &CoreContext) -> Warmer {
+        match self {
+            Self::Derive => create_derived_data_warmer::<D>(ctx),
+            Self::Wait => create_derived_data_waiter::<D>(ctx),
+        }
+    }
+}
+
 pub struct WarmBookmarksCacheBuilder<'a> {
     ctx: &'a CoreContext,
     repo: &'a BlobRepo,
@@ -110,6 +129,29 @@ impl<'a> WarmBookmarksCacheBuilder<'a> {
         &mut self,
         types: impl IntoIterator<Item = &'name Name>,
     ) -> Result<(), Error>
+    where
+        Name: 'name + AsRef<str> + ?Sized,
+    {
+        self.add_derived_data(types, Derivation::Derive)
+    }
+
+    /// Only move bookmarks once these types are derived, but leave deriving them to someone else,
+    /// e.g. the bookmark_warmer.
+    pub fn add_derived_data_waiters<'name, Name>(
+        &mut self,
+        types: impl IntoIterator<Item = &'name Name>,
+    ) -> Result<(), Error>
+    where
+        Name: 'name + AsRef<str> + ?Sized,
+    {
+        self.add_derived_data(types, Derivation::Wait)
+    }
+
+    fn add_derived_data<'name, Name>(
+        &mut self,
+        types: impl IntoIterator<Item = &'name Name>,
+        derivation: Derivation,
+    ) -> Result<(), Error>
     where
         Name: 'name + AsRef<str> + ?Sized,
     {
@@ -122,38 +164,32 @@ impl<'a> WarmBookmarksCacheBuilder<'a> {
             }
         }
 
+        let ctx = self.ctx;
         if types.contains(MappedHgChangesetId::NAME) {
             self.warmers
-                .push(create_derived_data_warmer::<MappedHgChangesetId>(&self.ctx));
+                .push(derivation.warmer::<MappedHgChangesetId>(ctx));
         }
 
         if types.contains(RootUnodeManifestId::NAME) {
             self.warmers
-                .push(create_derived_data_warmer::<RootUnodeManifestId>(&self.ctx));
+                .push(derivation.warmer::<RootUnodeManifestId>(ctx));
         }
         if types.contains(RootFsnodeId::NAME) {
-            self.warmers
-                .push(create_derived_data_warmer::<RootFsnodeId>(&self.ctx));
+            self.warmers.push(derivation.warmer::<RootFsnodeId>(ctx));
         }
         if types.contains(RootSkeletonManifestId::NAME) {
             self.warmers
-                .push(create_derived_data_warmer::<RootSkeletonManifestId>(
-                    &self.ctx,
-                ));
+                .push(derivation.warmer::<RootSkeletonManifestId>(ctx));
         }
         if types.contains(BlameRoot::NAME) {
-            self.warmers
-                .push(create_derived_data_warmer::<BlameRoot>(&self.ctx));
+            self.warmers.push(derivation.warmer::<BlameRoot>(ctx));
         }
         if types.contains(ChangesetInfo::NAME) {
-            self.warmers
-                .push(create_derived_data_warmer::<ChangesetInfo>(&self.ctx));
+            self.warmers.push(derivation.warmer::<ChangesetInfo>(ctx));
         }
         if types.contains(RootDeletedManifestId::NAME) {
             self.warmers
-                .push(create_derived_data_warmer::<RootDeletedManifestId>(
-                    &self.ctx,
-                ));
+                .push(derivation.warmer::<RootDeletedManifestId>(ctx));
         }
 
         Ok(())
@@ -201,10 +237,12 @@ impl WarmBookmarksCache {
         info!(ctx.logger(), "Starting warm bookmark cache updater");
         let bookmarks = init_bookmarks(&ctx, &repo, &warmers, init_mode).await?;
         let bookmarks = Arc::new(RwLock::new(bookmarks));
+        let max_staleness_secs = Arc::new(AtomicI64::new(0));
 
         let loop_sleep = Duration::from_millis(1000);
         spawn_bookmarks_coordinator(
             bookmarks.clone(),
+            max_staleness_secs.clone(),
             receiver,
             ctx.clone(),
             repo.clone(),
@@ -214,10 +252,17 @@ impl WarmBookmarksCache {
         );
         Ok(Self {
             bookmarks,
+            max_staleness_secs,
             terminate: Some(sender),
         })
     }
 
+    /// How far behind the bookmarks of the repo this cache is: the age in seconds of the oldest
+    /// bookmark move that hasn't been warmed yet, as of the last iteration of the updater.
+    pub fn max_staleness_secs(&self) -> i64 {
+        self.max_staleness_secs.load(Ordering::Relaxed)
+    }
+
     pub fn get(&self, bookmark: &BookmarkName) -> Option<ChangesetId> {
         self.bookmarks
             .read()
@@ -490,6 +535,7 @@ pub async fn find_all_underived_and_latest_derived(
 // Loop that finds bookmarks that were modified and spawns separate bookmark updaters for them
 fn spawn_bookmarks_coordinator(
     bookmarks: Arc<RwLock<HashMap<BookmarkName, (ChangesetId, BookmarkKind)>>>,
+    max_staleness_secs: Arc<AtomicI64>,
     terminate: oneshot::Receiver<()>,
     ctx: CoreContext,
     repo: BlobRepo,
@@ -508,7 +554,9 @@ fn spawn_bookmarks_coordinator(
             ));
             loop {
                 // Report delay and remove finished updaters
-                report_delay_and_remove_finished_updaters(&ctx, &live_updaters, &repo.name());
+                let max_staleness =
+                    report_delay_and_remove_finished_updaters(&ctx, &live_updaters, &repo.name());
+                max_staleness_secs.store(max_staleness, Ordering::Relaxed);
 
                 let cur_bookmarks = bookmarks.with_read(|bookmarks| bookmarks.clone());
 
@@ -626,7 +674,7 @@ fn report_delay_and_remove_finished_updaters(
     ctx: &CoreContext,
     live_updaters: &Arc<RwLock<HashMap<BookmarkName, BookmarkUpdaterState>>>,
     reponame: &str,
-) {
+) -> i64 {
     let mut max_staleness = 0;
     live_updaters.with_write(|live_updaters| {
         let new_updaters = live_updaters
@@ -662,6 +710,7 @@ fn report_delay_and_remove_finished_updaters(
     });
 
     STATS::max_staleness_secs.set_value(ctx.fb, max_staleness as i64, (reponame.to_owned(),));
+    max_staleness
 }
 
 #[derive(Clone)]
@@ -1004,6 +1053,7 @@ mod tests {
         let (cancel, receiver_cancel) = oneshot::channel();
         spawn_bookmarks_coordinator(
             bookmarks.clone(),
+            Arc::new(AtomicI64::new(0)),
             receiver_cancel,
             ctx.clone(),
             repo.clone(),
@@ -1178,6 +1228,7 @@ mod tests {
         let (cancel, receiver_cancel) = oneshot::channel();
         spawn_bookmarks_coordinator(
             bookmarks.clone(),
+            Arc::new(AtomicI64::new(0)),
             receiver_cancel,
             ctx.clone(),
             repo.clone(),
@@ -1209,6 +1260,7 @@ mod tests {
         let (cancel, receiver_cancel) = oneshot::channel();
         spawn_bookmarks_coordinator(
             bookmarks.clone(),
+            Arc::new(AtomicI64::new(0)),
             receiver_cancel,
             ctx,
             repo,
@@ -1294,6 +1346,7 @@ mod tests {
         let (cancel, receiver_cancel) = oneshot::channel();
         spawn_bookmarks_coordinator(
             bookmarks.clone(),
+            Arc::new(AtomicI64::new(0)),
             receiver_cancel,
             ctx.clone(),
             repo.clone(),
@@ -1355,6 +1408,7 @@ mod tests {
         let (cancel, receiver_cancel) = oneshot::channel();
         spawn_bookmarks_coordinator(
             bookmarks.clone(),
+            Arc::new(AtomicI64::new(0)),
             receiver_cancel,
             ctx.clone(),
             repo.clone(),
@@ -1431,6 +1485,7 @@ mod tests {
         let (cancel, receiver_cancel) = oneshot::channel();
         spawn_bookmarks_coordinator(
             bookmarks.clone(),
+            Arc::new(AtomicI64::new(0)),
             receiver_cancel,
             ctx,
             repo,
@@ -1450,6 +1505,31 @@ mod tests {
         Ok(())
     }
 
+    #[fbinit::test]
+    async fn test_derived_data_waiter(fb: FacebookInit) -> Result<(), Error> {
+        let repo = linear::getrepo(fb).await;
+        let ctx = CoreContext::test_mock(fb);
+
+        let waiter = create_derived_data_waiter::<RootUnodeManifestId>(&ctx);
+        let master_cs_id = resolve_cs_id(&ctx, &repo, "master").await?;
+        assert_eq!(false, (waiter.is_warm)(&ctx, &repo, &master_cs_id).await?);
+
+        // The waiter doesn't derive anything itself.
+        let wait = (waiter.warmer)(ctx.clone(), repo.clone(), master_cs_id).compat();
+        assert!(tokio::time::timeout(Duration::from_millis(100), wait)
+            .await
+            .is_err());
+        assert_eq!(false, (waiter.is_warm)(&ctx, &repo, &master_cs_id).await?);
+
+        RootUnodeManifestId::derive(&ctx, &repo, master_cs_id).await?;
+        (waiter.warmer)(ctx.clone(), repo.clone(), master_cs_id)
+            .compat()
+            .await?;
+        assert_eq!(true, (waiter.is_warm)(&ctx, &repo, &master_cs_id).await?);
+
+        Ok(())
+    }
+
     #[fbinit::test]
     async fn test_single_bookmarks_no_history(fb: FacebookInit) -> Result<(), Error> {
         let repo = linear::getrepo(fb).await;
@@ -132,3 +132,43 @@ pub fn create_derived_data_warmer<D: BonsaiDerived>(ctx: &CoreContext) -> Warmer
         });
     Warmer { warmer, is_warm }
 }
+
+/// Like `create_derived_data_warmer`, but waits for the data to be derived by someone else (e.g.
+/// the bookmark_warmer) instead of deriving it.
+pub fn create_derived_data_waiter<D: BonsaiDerived>(ctx: &CoreContext) -> Warmer {
+    info!(ctx.logger(), "Waiting for {} to be derived", D::NAME);
+    let warmer: Box<WarmerFn> = Box::new(|ctx: CoreContext, repo: BlobRepo, cs_id: ChangesetId| {
+        async move {
+            let log_rate = 60;
+            let mut i = 0;
+            let duration = Duration::from_secs(1);
+            while !D::is_derived(&ctx, &repo, &cs_id).await? {
+                i += 1;
+                if i % log_rate == 0 {
+                    info!(
+                        ctx.logger(),
+                        "not moving a bookmark to {} because {} isn't derived yet",
+                        cs_id,
+                        D::NAME
+                    );
+                }
+                tokio::time::delay_for(duration).await;
+            }
+
+            Ok(())
+        }
+        .boxed()
+        .compat()
+        .boxify()
+    });
+
+    let is_warm: Box<IsWarmFn> =
+        Box::new(|ctx: &CoreContext, repo: &BlobRepo, cs_id: &ChangesetId| {
+            let logger = ctx.logger().new(o!("type" => D::NAME));
+            D::is_derived(&ctx, &repo, &cs_id)
+                .watched(logger)
+                .map_err(Error::from)
+                .boxed()
+        });
+    Warmer { warmer, is_warm }
+}
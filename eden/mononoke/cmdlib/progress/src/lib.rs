@@ -0,0 +1,442 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Progress reporting for long-running commands, so that they all report how far along they
+//! are, how fast they are going and when they expect to be done in the same way.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use slog::{info, Logger};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProgressOptions {
+    /// Only consider reporting once every `sample_rate` units of work
+    pub sample_rate: u64,
+    /// Minimum time between two throttled reports
+    pub interval: Duration,
+    /// The current rate (and so the ETA) is computed over this much recent history
+    pub rate_window: Duration,
+    /// Also report once no work has been recorded for this long, so that stalls show up when
+    /// they happen. See `Progress::report_if_stalled`
+    pub stall_timeout: Option<Duration>,
+}
+
+impl Default for ProgressOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: 1,
+            interval: Duration::from_secs(5),
+            rate_window: Duration::from_secs(60),
+            stall_timeout: None,
+        }
+    }
+}
+
+impl ProgressOptions {
+    /// Throttle by sample, then time. Returns the current time if a report is due.
+    pub fn should_report(&self, done: u64, last_report: Instant) -> Option<Instant> {
+        if done % self.sample_rate.max(1) == 0 {
+            let now = Instant::now();
+            if now.duration_since(last_report) >= self.interval {
+                return Some(now);
+            }
+        }
+        None
+    }
+}
+
+/// Counters for commands that only track their main unit of work
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NoCounters {}
+
+impl fmt::Display for NoCounters {
+    fn fmt(&self, _fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {}
+    }
+}
+
+/// A snapshot of progress, as handed to sinks
+pub struct ProgressReport<'a, K> {
+    pub name: &'a str,
+    pub done: u64,
+    pub total: Option<u64>,
+    pub elapsed: Duration,
+    /// Units of work per second over the rate window
+    pub rate: f64,
+    pub eta: Option<Duration>,
+    pub counters: &'a BTreeMap<K, u64>,
+    /// Set for the unconditional report, e.g. at the end of a run
+    pub is_final: bool,
+    /// How long ago work was last recorded, if this reports a stall
+    pub stalled_for: Option<Duration>,
+}
+
+impl<'a, K: fmt::Display> fmt::Display for ProgressReport<'a, K> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{}: {}", self.name, self.done)?;
+        if let Some(total) = self.total {
+            let pct = if total > 0 {
+                self.done as f64 * 100.0 / total as f64
+            } else {
+                100.0
+            };
+            write!(fmt, "/{} ({:.1}%)", total, pct)?;
+        }
+        write!(
+            fmt,
+            ", {:.1}/s, elapsed {}s",
+            self.rate,
+            self.elapsed.as_secs()
+        )?;
+        if let Some(eta) = self.eta {
+            write!(fmt, ", ETA {}s", eta.as_secs())?;
+        }
+        if let Some(stalled_for) = self.stalled_for {
+            write!(fmt, ", stalled for {}s", stalled_for.as_secs())?;
+        }
+        for (idx, (key, value)) in self.counters.iter().enumerate() {
+            let sep = if idx == 0 { ";" } else { "," };
+            write!(fmt, "{} {}: {}", sep, key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Somewhere progress reports go
+pub trait ProgressSink<K>: Send + Sync {
+    fn report(&self, report: &ProgressReport<'_, K>);
+}
+
+/// Logs each report at info level
+pub struct LogSink {
+    logger: Logger,
+}
+
+impl LogSink {
+    pub fn new(logger: Logger) -> Self {
+        Self { logger }
+    }
+}
+
+impl<K: fmt::Display> ProgressSink<K> for LogSink {
+    fn report(&self, report: &ProgressReport<'_, K>) {
+        info!(self.logger, "{}", report);
+    }
+}
+
+struct ProgressState<K> {
+    start: Instant,
+    last_report: Instant,
+    done: u64,
+    counters: BTreeMap<K, u64>,
+    // (time, done) as of each report, oldest first. Rates are computed between the oldest
+    // sample in the window and now, so the window has the resolution of the report interval.
+    samples: VecDeque<(Instant, u64)>,
+    last_record: Instant,
+    // Whether the current stall, if any, was reported, so that a stall is reported once
+    stall_reported: bool,
+}
+
+impl<K> ProgressState<K> {
+    fn rate_at(&mut self, now: Instant, window: Duration) -> f64 {
+        while self.samples.len() > 1 && now.duration_since(self.samples[1].0) >= window {
+            self.samples.pop_front();
+        }
+        let rate = match self.samples.front() {
+            Some((time, done)) => {
+                let elapsed = now.duration_since(*time).as_secs_f64();
+                if elapsed > 0.0 {
+                    (self.done - done) as f64 / elapsed
+                } else {
+                    0.0
+                }
+            }
+            None => 0.0,
+        };
+        self.samples.push_back((now, self.done));
+        rate
+    }
+}
+
+fn eta(done: u64, total: Option<u64>, rate: f64) -> Option<Duration> {
+    let remaining = total?.saturating_sub(done);
+    if remaining == 0 {
+        Some(Duration::from_secs(0))
+    } else if rate > 0.0 {
+        Some(Duration::from_secs_f64(remaining as f64 / rate))
+    } else {
+        None
+    }
+}
+
+/// Tracks the progress of a command: the units of work done so far, out of an optional
+/// expected total, and any number of auxiliary counters of type `K` (errors, bytes, ...).
+/// It can be shared between concurrent tasks.
+pub struct Progress<K = NoCounters> {
+    name: String,
+    total: Option<u64>,
+    options: ProgressOptions,
+    sinks: Vec<Box<dyn ProgressSink<K>>>,
+    state: Mutex<ProgressState<K>>,
+}
+
+impl<K: Copy + Ord> Progress<K> {
+    pub fn new(name: impl Into<String>, total: Option<u64>, options: ProgressOptions) -> Self {
+        let now = Instant::now();
+        Self {
+            name: name.into(),
+            total,
+            options,
+            sinks: Vec::new(),
+            state: Mutex::new(ProgressState {
+                start: now,
+                last_report: now,
+                done: 0,
+                counters: BTreeMap::new(),
+                samples: VecDeque::from(vec![(now, 0)]),
+                last_record: now,
+                stall_reported: false,
+            }),
+        }
+    }
+
+    pub fn with_sink(mut self, sink: impl ProgressSink<K> + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    pub fn with_logger(self, logger: Logger) -> Self
+    where
+        K: fmt::Display + 'static,
+    {
+        self.with_sink(LogSink::new(logger))
+    }
+
+    /// Record `n` more units of work done
+    pub fn record(&self, n: u64) {
+        self.record_at(n, Instant::now())
+    }
+
+    fn record_at(&self, n: u64, now: Instant) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        state.done += n;
+        state.last_record = now;
+        state.stall_reported = false;
+    }
+
+    /// Add `n` to one of the auxiliary counters
+    pub fn increment(&self, key: K, n: u64) {
+        *self
+            .state
+            .lock()
+            .expect("lock poisoned")
+            .counters
+            .entry(key)
+            .or_insert(0) += n;
+    }
+
+    pub fn done(&self) -> u64 {
+        self.state.lock().expect("lock poisoned").done
+    }
+
+    /// Report if the throttling options allow it
+    pub fn report_throttled(&self) {
+        let (done, last_report) = {
+            let state = self.state.lock().expect("lock poisoned");
+            (state.done, state.last_report)
+        };
+        if let Some(now) = self.options.should_report(done, last_report) {
+            self.report_at(now, false)
+        }
+    }
+
+    /// Report unconditionally, e.g. at the end of a run
+    pub fn report(&self) {
+        self.report_at(Instant::now(), true)
+    }
+
+    /// Report if no work was recorded for `stall_timeout`, once per stall. The work can't report
+    /// that it stopped, so this is to be called periodically alongside it, e.g. from a timer.
+    pub fn report_if_stalled(&self) {
+        self.report_if_stalled_at(Instant::now())
+    }
+
+    fn report_if_stalled_at(&self, now: Instant) {
+        let stall_timeout = match self.options.stall_timeout {
+            Some(stall_timeout) => stall_timeout,
+            None => return,
+        };
+        let mut state = self.state.lock().expect("lock poisoned");
+        let stalled_for = now.saturating_duration_since(state.last_record);
+        if state.stall_reported || stalled_for < stall_timeout {
+            return;
+        }
+        state.stall_reported = true;
+        self.report_locked(&mut state, now, false, Some(stalled_for))
+    }
+
+    fn report_at(&self, now: Instant, is_final: bool) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        self.report_locked(&mut state, now, is_final, None)
+    }
+
+    fn report_locked(
+        &self,
+        state: &mut ProgressState<K>,
+        now: Instant,
+        is_final: bool,
+        stalled_for: Option<Duration>,
+    ) {
+        state.last_report = now;
+
+        let rate = state.rate_at(now, self.options.rate_window);
+        let report = ProgressReport {
+            name: &self.name,
+            done: state.done,
+            total: self.total,
+            elapsed: now.duration_since(state.start),
+            rate,
+            eta: eta(state.done, self.total, rate),
+            counters: &state.counters,
+            is_final,
+            stalled_for,
+        };
+        for sink in &self.sinks {
+            sink.report(&report);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    enum Counter {
+        Errors,
+    }
+
+    impl fmt::Display for Counter {
+        fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Counter::Errors => write!(fmt, "errors"),
+            }
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct CollectSink(Arc<Mutex<Vec<String>>>);
+
+    impl<K: fmt::Display> ProgressSink<K> for CollectSink {
+        fn report(&self, report: &ProgressReport<'_, K>) {
+            self.0.lock().unwrap().push(report.to_string());
+        }
+    }
+
+    #[test]
+    fn test_eta() {
+        assert_eq!(eta(50, Some(100), 10.0), Some(Duration::from_secs(5)));
+        assert_eq!(eta(100, Some(100), 0.0), Some(Duration::from_secs(0)));
+        assert_eq!(eta(50, Some(100), 0.0), None);
+        assert_eq!(eta(50, None, 10.0), None);
+    }
+
+    #[test]
+    fn test_rate_window() {
+        let options = ProgressOptions {
+            sample_rate: 1,
+            interval: Duration::from_secs(0),
+            rate_window: Duration::from_secs(10),
+            stall_timeout: None,
+        };
+        let progress: Progress = Progress::new("test", None, options);
+        let start = progress.state.lock().unwrap().start;
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // A fast start...
+        progress.record(100);
+        progress.report_at(at(10), false);
+        assert_eq!(progress.state.lock().unwrap().samples.len(), 2);
+
+        // ...followed by a slow period only counts the slow period once it fills the window
+        progress.record(10);
+        progress.report_at(at(20), false);
+        progress.record(10);
+        let rate = progress
+            .state
+            .lock()
+            .unwrap()
+            .rate_at(at(30), options.rate_window);
+        assert_eq!(rate, 1.0);
+    }
+
+    #[test]
+    fn test_report() {
+        let sink = CollectSink::default();
+        let options = ProgressOptions {
+            sample_rate: 1,
+            interval: Duration::from_secs(60),
+            rate_window: Duration::from_secs(60),
+            stall_timeout: None,
+        };
+        let progress = Progress::new("things", Some(200), options).with_sink(sink.clone());
+        let start = progress.state.lock().unwrap().start;
+
+        progress.record(50);
+        progress.increment(Counter::Errors, 2);
+        // Throttled by the interval
+        progress.report_throttled();
+        assert!(sink.0.lock().unwrap().is_empty());
+
+        progress.report_at(start + Duration::from_secs(10), true);
+        assert_eq!(
+            *sink.0.lock().unwrap(),
+            vec!["things: 50/200 (25.0%), 5.0/s, elapsed 10s, ETA 30s; errors: 2"]
+        );
+    }
+
+    #[test]
+    fn test_report_stalls() {
+        let sink = CollectSink::default();
+        let options = ProgressOptions {
+            sample_rate: 1,
+            interval: Duration::from_secs(60),
+            rate_window: Duration::from_secs(60),
+            stall_timeout: Some(Duration::from_secs(10)),
+        };
+        let progress: Progress = Progress::new("things", None, options).with_sink(sink.clone());
+        let start = progress.state.lock().unwrap().start;
+        let at = |secs| start + Duration::from_secs(secs);
+
+        progress.record_at(100, at(5));
+        progress.report_if_stalled_at(at(14));
+        assert!(sink.0.lock().unwrap().is_empty());
+
+        progress.report_if_stalled_at(at(20));
+        assert_eq!(
+            *sink.0.lock().unwrap(),
+            vec!["things: 100, 5.0/s, elapsed 20s, stalled for 15s"]
+        );
+
+        // A stall is reported once
+        progress.report_if_stalled_at(at(40));
+        assert_eq!(sink.0.lock().unwrap().len(), 1);
+
+        // Until work is recorded again
+        progress.record_at(1, at(41));
+        progress.report_if_stalled_at(at(45));
+        assert_eq!(sink.0.lock().unwrap().len(), 1);
+        progress.report_if_stalled_at(at(51));
+        assert_eq!(sink.0.lock().unwrap().len(), 2);
+    }
+}
@@ -5,7 +5,7 @@
  * GNU General Public License version 2.
  */
 
-use blobrepo_factory::Caching;
+use blobrepo_factory::{Caching, CONTENT_METADATA_CACHE_POOL};
 use clap::{App, Arg, ArgMatches};
 use fbinit::FacebookInit;
 use once_cell::sync::{Lazy, OnceCell};
@@ -28,6 +28,7 @@ const PHASES_CACHE_SIZE: &str = "phases-cache-size";
 const SEGMENTED_CHANGELOG_CACHE_SIZE: &str = "segmented-changelog-cache-size";
 const GLOBALREVS_CACHE_SIZE: &str = "globalrevs-cache-size";
 const SVNREVS_CACHE_SIZE: &str = "svnrevs-cache-size";
+const CONTENT_METADATA_CACHE_SIZE: &str = "content-metadata-cache-size";
 const BUCKETS_POWER: &str = "buckets-power";
 
 const ONE_GIB: usize = 1073741824; // 2^30 aka 1GiB
@@ -63,6 +64,10 @@ const CACHE_ARGS: &[(&str, &str)] = &[
         "override size of the bonsai/svnrev mapping cache",
     ),
     (PHASES_CACHE_SIZE, "override size of the phases cache"),
+    (
+        CONTENT_METADATA_CACHE_SIZE,
+        "size of the file content metadata cache, which is only used if this is set",
+    ),
     (
         BUCKETS_POWER,
         "override the bucket power for cachelib's hashtable",
@@ -257,6 +262,11 @@ pub(crate) fn parse_and_init_cachelib<'a>(
             if let Some(phases_cache_size) = matches.value_of(PHASES_CACHE_SIZE) {
                 settings.phases_cache_size = Some(phases_cache_size.parse().unwrap());
             }
+            if let Some(content_metadata_cache_size) = matches.value_of(CONTENT_METADATA_CACHE_SIZE)
+            {
+                settings.content_metadata_cache_size =
+                    Some(content_metadata_cache_size.parse().unwrap());
+            }
             if let Some(segmented_changelog_cache_size) =
                 matches.value_of(SEGMENTED_CHANGELOG_CACHE_SIZE)
             {
@@ -278,7 +288,14 @@ pub(crate) fn parse_and_init_cachelib<'a>(
             }
             #[cfg(fbcode_build)]
             {
+                let content_metadata_cache_size = settings.content_metadata_cache_size;
                 super::facebook::init_cachelib_from_settings(fb, settings).unwrap();
+                // Not one of the pools that every binary sets up, so it's created here, for the
+                // repos that are opened to find it.
+                if let Some(size) = content_metadata_cache_size {
+                    cachelib::get_or_create_volatile_pool(CONTENT_METADATA_CACHE_POOL, size)
+                        .unwrap();
+                }
             }
         }
         Caching::Disabled => {
@@ -305,6 +322,7 @@ pub struct CachelibSettings {
     pub svnrev_cache_size: Option<usize>,
     pub blob_cache_size: Option<usize>,
     pub phases_cache_size: Option<usize>,
+    pub content_metadata_cache_size: Option<usize>,
     pub segmented_changelog_cache_size: Option<usize>,
     pub expected_item_size_bytes: Option<usize>,
     pub blobstore_cachelib_only: bool,
@@ -329,6 +347,7 @@ impl Default for CachelibSettings {
             svnrev_cache_size: None,
             blob_cache_size: None,
             phases_cache_size: None,
+            content_metadata_cache_size: None,
             segmented_changelog_cache_size: None,
             expected_item_size_bytes: None,
             blobstore_cachelib_only: false,
@@ -40,8 +40,9 @@ use std::panic::{RefUnwindSafe, UnwindSafe};
 use blobrepo::BlobRepo;
 use blobrepo_factory::{BlobrepoBuilder, Caching, ReadOnlyStorage};
 use blobstore_factory::{
-    BlobstoreOptions, CachelibBlobstoreOptions, ChaosOptions, PackOptions, PutBehaviour,
-    ScrubAction, ThrottleOptions, DEFAULT_PUT_BEHAVIOUR,
+    BlobstoreOptions, CachelibBlobstoreOptions, ChaosOptions, CircuitBreakerOptions,
+    FileblobOptions, PackOptions, PutBehaviour, RetryOptions, ScrubAction, ThrottleOptions,
+    DEFAULT_PUT_BEHAVIOUR,
 };
 use metaconfig_parser::{RepoConfigs, StorageConfigs};
 use metaconfig_types::{BlobConfig, CommonConfig, Redaction, RepoConfig};
@@ -53,7 +54,7 @@ use sql_ext::facebook::{MysqlConnectionType, MysqlOptions, PoolConfig, SharedCon
 use strum::VariantNames;
 use tunables::init_tunables_worker;
 
-use crate::helpers::{create_runtime, setup_repo_dir, CreateStorage};
+use crate::helpers::{create_runtime_with_options, setup_repo_dir, CreateStorage, RuntimeOptions};
 use crate::log;
 
 pub use self::cache::parse_caching;
@@ -80,6 +81,8 @@ const MYSQL_POOL_IDLE_TIMEOUT: &str = "mysql-pool-idle-timeout";
 const MYSQL_CONN_OPEN_TIMEOUT: &str = "mysql-conn-open-timeout";
 const MYSQL_MAX_QUERY_TIME: &str = "mysql-query-time-limit";
 const RUNTIME_THREADS: &str = "runtime-threads";
+const MAX_BLOCKING_THREADS: &str = "max-blocking-threads";
+const THREAD_STACK_SIZE: &str = "thread-stack-size";
 const TUNABLES_CONFIG: &str = "tunables-config";
 const DISABLE_TUNABLES: &str = "disable-tunables";
 
@@ -92,6 +95,7 @@ const WRITE_BYTES_ARG: &str = "blobstore-write-bytes-s";
 const READ_BURST_BYTES_ARG: &str = "blobstore-read-burst-bytes-s";
 const WRITE_BURST_BYTES_ARG: &str = "blobstore-write-burst-bytes-s";
 const BLOBSTORE_BYTES_MIN_THROTTLE_ARG: &str = "blobstore-bytes-min-throttle";
+const BLOBSTORE_THROTTLE_MAX_WAIT_ARG: &str = "blobstore-throttle-max-wait-ms";
 const READ_CHAOS_ARG: &str = "blobstore-read-chaos-rate";
 const WRITE_CHAOS_ARG: &str = "blobstore-write-chaos-rate";
 const WRITE_ZSTD_ARG: &str = "blobstore-write-zstd-level";
@@ -99,6 +103,13 @@ const MANIFOLD_API_KEY_ARG: &str = "manifold-api-key";
 const MANIFOLD_USE_CPP_CLIENT_ARG: &str = "manifold-use-cpp-client";
 const CACHELIB_ATTEMPT_ZSTD_ARG: &str = "blobstore-cachelib-attempt-zstd";
 const BLOBSTORE_PUT_BEHAVIOUR_ARG: &str = "blobstore-put-behaviour";
+const BLOBSTORE_RETRY_ATTEMPTS_ARG: &str = "blobstore-retry-attempts";
+const BLOBSTORE_CIRCUIT_BREAKER_THRESHOLD_ARG: &str = "blobstore-circuit-breaker-threshold";
+const BLOBSTORE_CIRCUIT_BREAKER_PROBE_INTERVAL_ARG: &str =
+    "blobstore-circuit-breaker-probe-interval-secs";
+const FILEBLOB_SHARD_LEVELS_ARG: &str = "fileblob-shard-levels";
+const FILEBLOB_FSYNC_ARG: &str = "fileblob-fsync";
+const FILEBLOB_GC_INTERVAL_ARG: &str = "fileblob-gc-interval-secs";
 const BLOBSTORE_SCRUB_ACTION_ARG: &str = "blobstore-scrub-action";
 const BLOBSTORE_SCRUB_GRACE_ARG: &str = "blobstore-scrub-grace";
 
@@ -743,6 +754,13 @@ impl MononokeAppBuilder {
                 .required(false)
                 .help("Minimum number of bytes ThrottledBlob can count"),
         )
+        .arg(
+            Arg::with_name(BLOBSTORE_THROTTLE_MAX_WAIT_ARG)
+                .long(BLOBSTORE_THROTTLE_MAX_WAIT_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("Fail blobstore operations that would wait longer than this many milliseconds for ThrottledBlob quota, rather than have them wait. Operations wait for as long as it takes if not set."),
+        )
         .arg(
             Arg::with_name(READ_CHAOS_ARG)
                 .long(READ_CHAOS_ARG)
@@ -792,6 +810,51 @@ impl MononokeAppBuilder {
         .arg(
           put_arg
         )
+        .arg(
+            Arg::with_name(BLOBSTORE_RETRY_ATTEMPTS_ARG)
+                .long(BLOBSTORE_RETRY_ATTEMPTS_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("Retry blobstore operations that fail with transient errors, making up to this many attempts in total. Retries are off if not set."),
+        )
+        .arg(
+            Arg::with_name(BLOBSTORE_CIRCUIT_BREAKER_THRESHOLD_ARG)
+                .long(BLOBSTORE_CIRCUIT_BREAKER_THRESHOLD_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("Fail blobstore operations straight away once this many in a row have failed on the same backend, until a probe succeeds. Circuit breakers are off if not set."),
+        )
+        .arg(
+            Arg::with_name(BLOBSTORE_CIRCUIT_BREAKER_PROBE_INTERVAL_ARG)
+                .long(BLOBSTORE_CIRCUIT_BREAKER_PROBE_INTERVAL_ARG)
+                .takes_value(true)
+                .required(false)
+                .requires(BLOBSTORE_CIRCUIT_BREAKER_THRESHOLD_ARG)
+                .help("While a circuit breaker is open, let one operation through to its backend every this many seconds, to find out whether it recovered."),
+        )
+        .arg(
+            Arg::with_name(FILEBLOB_SHARD_LEVELS_ARG)
+                .long(FILEBLOB_SHARD_LEVELS_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("For file-backed blobstores, how many levels of directories (of up to 256 each) to spread the blobs over. Default is 0, i.e. all blobs in one directory."),
+        )
+        .arg(
+            Arg::with_name(FILEBLOB_FSYNC_ARG)
+                .long(FILEBLOB_FSYNC_ARG)
+                .takes_value(true)
+                .possible_values(BOOL_VALUES)
+                .required(false)
+                .default_value(bool_as_str(true))
+                .help("For file-backed blobstores, whether puts wait for the blob to be fsynced."),
+        )
+        .arg(
+            Arg::with_name(FILEBLOB_GC_INTERVAL_ARG)
+                .long(FILEBLOB_GC_INTERVAL_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("For file-backed blobstores, run a pass that cleans up leftover temporary files and moves blobs into their shard every this many seconds. Off if not set."),
+        )
         .arg(
             Arg::with_name(READONLY_STORAGE_OLD_ARG)
                 .long(READONLY_STORAGE_OLD_ARG)
@@ -853,6 +916,19 @@ fn add_runtime_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
             .takes_value(true)
             .help("a number of threads to use in the tokio runtime"),
     )
+    .arg(
+        Arg::with_name(MAX_BLOCKING_THREADS)
+            .long(MAX_BLOCKING_THREADS)
+            .takes_value(true)
+            .help("maximum number of threads to use for blocking work in the tokio runtime"),
+    )
+    .arg(
+        Arg::with_name(THREAD_STACK_SIZE)
+            .long(THREAD_STACK_SIZE)
+            .takes_value(true)
+            .value_name("BYTES")
+            .help("stack size of the threads of the tokio runtime"),
+    )
 }
 
 fn add_logger_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
@@ -1679,7 +1755,7 @@ pub fn get_global_mysql_connection_pool<'a>(matches: &MononokeMatches<'a>) -> Sh
     matches.app_data.global_mysql_connection_pool.clone()
 }
 
-fn parse_mysql_pool_options<'a>(matches: &MononokeMatches<'a>) -> PoolConfig {
+pub fn parse_mysql_pool_options<'a>(matches: &MononokeMatches<'a>) -> PoolConfig {
     let size: usize = matches
         .value_of(MYSQL_POOL_LIMIT)
         .map(|v| v.parse().expect("Provided mysql-pool-limit is not usize"))
@@ -1793,6 +1869,12 @@ pub fn parse_blobstore_options(matches: &MononokeMatches) -> Result<BlobstoreOpt
         .value_of(BLOBSTORE_BYTES_MIN_THROTTLE_ARG)
         .map(|v| v.parse().expect("Provided Bytes/s is not usize"));
 
+    let throttle_max_wait: Option<Duration> = matches
+        .value_of(BLOBSTORE_THROTTLE_MAX_WAIT_ARG)
+        .map(|v| v.parse().map(Duration::from_millis))
+        .transpose()
+        .context("Provided blobstore-throttle-max-wait-ms is not u64")?;
+
     let read_chaos: Option<NonZeroU32> = matches
         .value_of(READ_CHAOS_ARG)
         .map(|v| v.parse())
@@ -1835,6 +1917,43 @@ pub fn parse_blobstore_options(matches: &MononokeMatches) -> Result<BlobstoreOpt
         .transpose()
         .context("Provided blobstore-put-behaviour is not PutBehaviour")?;
 
+    let retry_attempts: Option<NonZeroU32> = matches
+        .value_of(BLOBSTORE_RETRY_ATTEMPTS_ARG)
+        .map(|v| v.parse())
+        .transpose()
+        .context("Provided blobstore-retry-attempts is not u32")?;
+
+    let circuit_breaker_threshold: Option<NonZeroU32> = matches
+        .value_of(BLOBSTORE_CIRCUIT_BREAKER_THRESHOLD_ARG)
+        .map(|v| v.parse())
+        .transpose()
+        .context("Provided blobstore-circuit-breaker-threshold is not u32")?;
+
+    let circuit_breaker_probe_interval: Option<Duration> = matches
+        .value_of(BLOBSTORE_CIRCUIT_BREAKER_PROBE_INTERVAL_ARG)
+        .map(|v| v.parse().map(Duration::from_secs))
+        .transpose()
+        .context("Provided blobstore-circuit-breaker-probe-interval-secs is not u64")?;
+
+    let fileblob_shard_levels: Option<usize> = matches
+        .value_of(FILEBLOB_SHARD_LEVELS_ARG)
+        .map(|v| v.parse())
+        .transpose()
+        .context("Provided fileblob-shard-levels is not usize")?;
+
+    let fileblob_fsync: bool = matches
+        .value_of(FILEBLOB_FSYNC_ARG)
+        .map(|v| v.parse())
+        .transpose()
+        .context("Provided fileblob-fsync is not bool")?
+        .ok_or_else(|| format_err!("A default is set, should never be None"))?;
+
+    let fileblob_gc_interval: Option<Duration> = matches
+        .value_of(FILEBLOB_GC_INTERVAL_ARG)
+        .map(|v| v.parse().map(Duration::from_secs))
+        .transpose()
+        .context("Provided fileblob-gc-interval-secs is not u64")?;
+
     let blobstore_options = BlobstoreOptions::new(
         ChaosOptions::new(read_chaos, write_chaos),
         ThrottleOptions {
@@ -1845,13 +1964,30 @@ pub fn parse_blobstore_options(matches: &MononokeMatches) -> Result<BlobstoreOpt
             read_burst_bytes,
             write_burst_bytes,
             bytes_min_count,
+            max_wait: throttle_max_wait,
         },
         manifold_api_key,
         manifold_use_cpp_client,
         PackOptions::new(write_zstd_level),
         CachelibBlobstoreOptions::new_lazy(Some(attempt_zstd)),
         blobstore_put_behaviour,
-    );
+    )
+    .with_retry_options(retry_attempts.map(|max_attempts| RetryOptions {
+        max_attempts,
+        ..Default::default()
+    }))
+    .with_circuit_breaker_options(circuit_breaker_threshold.map(|failure_threshold| {
+        let defaults = CircuitBreakerOptions::default();
+        CircuitBreakerOptions {
+            failure_threshold,
+            probe_interval: circuit_breaker_probe_interval.unwrap_or(defaults.probe_interval),
+        }
+    }))
+    .with_fileblob_options(FileblobOptions {
+        shard_levels: fileblob_shard_levels.unwrap_or_default(),
+        fsync: fileblob_fsync,
+        gc_interval: fileblob_gc_interval,
+    });
 
     let blobstore_options = if matches.arg_types.contains(&ArgType::Scrub) {
         let scrub_action = matches
@@ -2066,10 +2202,18 @@ pub fn init_tunables<'a>(
     init_tunables_worker(logger, config_handle)
 }
 
+/// The sizing of the threads of the tokio runtime, parsed from the CLI
+pub fn parse_runtime_options(matches: &MononokeMatches) -> RuntimeOptions {
+    RuntimeOptions {
+        core_threads: get_usize_opt(matches, RUNTIME_THREADS),
+        max_blocking_threads: get_usize_opt(matches, MAX_BLOCKING_THREADS),
+        thread_stack_size: get_usize_opt(matches, THREAD_STACK_SIZE),
+    }
+}
+
 /// Initialize a new `tokio::runtime::Runtime` with thread number parsed from the CLI
 pub fn init_runtime(matches: &MononokeMatches) -> io::Result<tokio::runtime::Runtime> {
-    let core_threads = get_usize_opt(matches, RUNTIME_THREADS);
-    create_runtime(None, core_threads)
+    create_runtime_with_options(None, parse_runtime_options(matches))
 }
 
 /// NOTE: Don't use this. "configerator:" prefix don't need to exist and is going to be removed.
@@ -2110,6 +2254,11 @@ pub fn init_observability_context<'a>(
     })
 }
 
+/// The ObservabilityContext the process logs with, once logging was initialized.
+pub fn get_observability_context() -> Option<&'static ObservabilityContext> {
+    OBSERVABILITY_CONTEXT.get()
+}
+
 pub fn init_config_store<'a>(
     fb: FacebookInit,
     root_log: impl Into<Option<&'a Logger>>,
@@ -136,22 +136,51 @@ pub fn get_root_manifest_id(
     })
 }
 
+/// How to size the threads of a tokio `Runtime`. Anything left unset gets tokio's default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RuntimeOptions {
+    /// Worker threads, that run the futures. Defaults to the number of CPUs.
+    pub core_threads: Option<usize>,
+    /// Threads for `spawn_blocking`, on top of the worker threads.
+    pub max_blocking_threads: Option<usize>,
+    /// Stack size of all the threads, in bytes.
+    pub thread_stack_size: Option<usize>,
+}
+
 /// Get a tokio `Runtime` with potentially explicitly set number of core threads
 pub fn create_runtime(
     log_thread_name_prefix: Option<&str>,
     core_threads: Option<usize>,
+) -> io::Result<tokio::runtime::Runtime> {
+    create_runtime_with_options(
+        log_thread_name_prefix,
+        RuntimeOptions {
+            core_threads,
+            ..Default::default()
+        },
+    )
+}
+
+/// Get a tokio `Runtime` with its threads sized as per `options`
+pub fn create_runtime_with_options(
+    log_thread_name_prefix: Option<&str>,
+    options: RuntimeOptions,
 ) -> io::Result<tokio::runtime::Runtime> {
     let mut builder = tokio::runtime::Builder::new();
     builder.threaded_scheduler();
     builder.enable_all();
     builder.no_coop();
     builder.thread_name(log_thread_name_prefix.unwrap_or("tk"));
-    if let Some(core_threads) = core_threads {
-        builder.core_threads(core_threads);
-    } else {
-        // TODO(stash) T75113443 remove when
-        // https://github.com/tokio-rs/tokio/issues/2269 is landed
-        builder.core_threads(num_cpus::get());
+    // TODO(stash) T75113443 remove the default when
+    // https://github.com/tokio-rs/tokio/issues/2269 is landed
+    let core_threads = options.core_threads.unwrap_or_else(num_cpus::get);
+    builder.core_threads(core_threads);
+    if let Some(max_blocking_threads) = options.max_blocking_threads {
+        // tokio counts the core threads in its maximum.
+        builder.max_threads(core_threads + max_blocking_threads);
+    }
+    if let Some(thread_stack_size) = options.thread_stack_size {
+        builder.thread_stack_size(thread_stack_size);
     }
     builder.build()
 }
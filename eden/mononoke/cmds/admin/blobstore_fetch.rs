@@ -87,7 +87,10 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
         )
 }
 
-fn get_blobconfig(blob_config: BlobConfig, inner_blobstore_id: Option<u64>) -> Result<BlobConfig> {
+pub(crate) fn get_blobconfig(
+    blob_config: BlobConfig,
+    inner_blobstore_id: Option<u64>,
+) -> Result<BlobConfig> {
     match inner_blobstore_id {
         None => Ok(blob_config),
         Some(inner_blobstore_id) => match blob_config {
@@ -280,7 +283,7 @@ async fn get_from_sources<T: Blobstore + Clone>(
             };
             let blobstore = RedactedBlobstore::new(
                 blobstore,
-                RedactedBlobstoreConfig::new(redacted_blobs, scuba_redaction_builder),
+                RedactedBlobstoreConfig::new(redacted_blobs, scuba_redaction_builder, repo_id),
             );
             get_cache(&ctx, &blobstore, &key, mode)
                 .await
@@ -293,7 +296,7 @@ async fn get_from_sources<T: Blobstore + Clone>(
             };
             let blobstore = RedactedBlobstore::new(
                 blobstore,
-                RedactedBlobstoreConfig::new(redacted_blobs, scuba_redaction_builder),
+                RedactedBlobstoreConfig::new(redacted_blobs, scuba_redaction_builder, repo_id),
             );
             blobstore.get(&ctx, &key).await
         }
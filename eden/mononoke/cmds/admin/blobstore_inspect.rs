@@ -0,0 +1,239 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Fetches a key from below the layers that encode blobs (as opposed to blobstore-fetch, which
+//! goes through them like the server does), and decodes it one layer at a time, so that it's
+//! possible to tell which of them a bad blob is bad in.
+
+use std::convert::TryInto;
+use std::str::FromStr;
+
+use anyhow::{bail, format_err, Error, Result};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use fbinit::FacebookInit;
+
+use blobstore::{Blobstore, BlobstoreBytes};
+use blobstore_factory::make_blobstore;
+use cmdlib::args::{self, MononokeMatches};
+use context::CoreContext;
+use git_types::Tree as GitTree;
+use mercurial_types::{HgChangesetEnvelope, HgFileEnvelope, HgManifestEnvelope};
+use metaconfig_types::{BlobConfig, Redaction};
+use mononoke_types::{
+    Blob, BlobstoreValue, BonsaiChangeset, ChangesetBlob, ChangesetId, ContentAlias,
+    ContentMetadata, ContentMetadataId, FileContents, REPO_PREFIX_REGEX,
+};
+use packblob::{decode_envelope, ENVELOPE_SUFFIX};
+use redactedblobstore::SqlRedactedContentStore;
+use slog::{info, Logger};
+
+use crate::blobstore_fetch::get_blobconfig;
+use crate::error::SubcommandError;
+
+pub const BLOBSTORE_INSPECT: &str = "blobstore-inspect";
+
+// Raw values are shown up to this many bytes.
+const RAW_PREVIEW_BYTES: usize = 256;
+
+pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(BLOBSTORE_INSPECT)
+        .about("fetches a raw blob, and decodes it through the pack envelope, compression and the struct it encodes")
+        .args_from_usage("<KEY>    'key of the blob to be inspected'")
+        .arg(
+            Arg::with_name("no-prefix")
+                .long("no-prefix")
+                .short("P")
+                .takes_value(false)
+                .required(false)
+                .help("Don't prepend a prefix based on the repo id to the key"),
+        )
+        .arg(
+            Arg::with_name("inner-blobstore-id")
+                .long("inner-blobstore-id")
+                .takes_value(true)
+                .required(false)
+                .help("If main blobstore in the storage config is a multiplexed one, use inner blobstore with this id")
+        )
+}
+
+/// Remove the pack layer from the config, so that its envelopes can be inspected. Returns
+/// whether there was one.
+fn strip_pack(blobconfig: BlobConfig) -> Result<(BlobConfig, bool)> {
+    match blobconfig {
+        BlobConfig::Pack { blobconfig } => Ok((*blobconfig, true)),
+        BlobConfig::Multiplexed { .. } => {
+            bail!("blobstore is multiplexed: pick one of its blobstores with --inner-blobstore-id")
+        }
+        blobconfig => Ok((blobconfig, false)),
+    }
+}
+
+pub async fn subcommand_blobstore_inspect<'a>(
+    fb: FacebookInit,
+    logger: Logger,
+    matches: &'a MononokeMatches<'a>,
+    sub_m: &'a ArgMatches<'a>,
+) -> Result<(), SubcommandError> {
+    let config_store = args::init_config_store(fb, &logger, matches)?;
+    let repo_id = args::get_repo_id(config_store, &matches)?;
+    let (_, config) = args::get_config(config_store, &matches)?;
+    let inner_blobstore_id = args::get_u64_opt(&sub_m, "inner-blobstore-id");
+    let mysql_options = args::parse_mysql_options(&matches);
+    let blobstore_options = args::parse_blobstore_options(&matches)?;
+    let readonly_storage = args::parse_readonly_storage(&matches);
+
+    let key = sub_m.value_of("KEY").unwrap().to_string();
+    let key = if sub_m.is_present("no-prefix") {
+        key
+    } else {
+        format!("{}{}", repo_id.prefix(), key)
+    };
+
+    // This bypasses the redaction layer, so check for redaction here.
+    if config.redaction == Redaction::Enabled {
+        let redacted_blobs =
+            args::open_sql::<SqlRedactedContentStore>(fb, config_store, &matches).await?;
+        let redacted_blobs = redacted_blobs.get_all_redacted_blobs().await?;
+        let unprefixed_key = match REPO_PREFIX_REGEX.find(&key) {
+            Some(m) => &key[m.end()..],
+            None => &key,
+        };
+        if redacted_blobs
+            .iter()
+            .any(|(redacted_key, _)| redacted_key == unprefixed_key)
+        {
+            return Err(format_err!("{} is redacted", key).into());
+        }
+    }
+
+    let blobconfig = get_blobconfig(config.storage_config.blobstore, inner_blobstore_id)?;
+    let (blobconfig, packed) = strip_pack(blobconfig)?;
+    let blobstore = make_blobstore(
+        fb,
+        blobconfig,
+        &mysql_options,
+        readonly_storage,
+        &blobstore_options,
+        &logger,
+        config_store,
+    )
+    .await?;
+    info!(logger, "using blobstore: {:?}", blobstore);
+
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+    let mut layer = 1;
+
+    let value = if packed {
+        let envelope_key = [key.as_str(), ENVELOPE_SUFFIX].concat();
+        match blobstore.get(&ctx, &envelope_key).await? {
+            Some(envelope) => {
+                println!(
+                    "{}. pack envelope {}: {} bytes",
+                    layer,
+                    envelope_key,
+                    envelope.as_bytes().len()
+                );
+                let (descriptions, value) = decode_envelope(&key, envelope.into_bytes())?;
+                for description in descriptions {
+                    layer += 1;
+                    println!("{}. {}", layer, description);
+                }
+                Some(value)
+            }
+            // Written before the pack layer was enabled.
+            None => blobstore.get(&ctx, &key).await?.map(|v| v.into_bytes()),
+        }
+    } else {
+        blobstore.get(&ctx, &key).await?.map(|v| v.into_bytes())
+    };
+
+    let value = match value {
+        Some(value) => value,
+        None => return Err(format_err!("{} not found", key).into()),
+    };
+
+    layer += 1;
+    println!("{}. value: {} bytes", layer, value.len());
+    layer += 1;
+    match decode_value(&key, value.clone()) {
+        Some((name, Ok(decoded))) => println!("{}. {}:\n{}", layer, name, decoded),
+        Some((name, Err(e))) => {
+            println!("{}. failed to decode as {}: {:#}", layer, name, e);
+            print_raw(&value);
+        }
+        None => {
+            println!("{}. unknown key type, raw value:", layer);
+            print_raw(&value);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_raw(value: &BlobstoreBytes) {
+    let bytes = value.as_bytes();
+    let preview = &bytes[..bytes.len().min(RAW_PREVIEW_BYTES)];
+    match std::str::from_utf8(preview) {
+        Ok(text) => println!("{}", text),
+        Err(_) => println!("{:?}", preview),
+    }
+    if bytes.len() > RAW_PREVIEW_BYTES {
+        println!("... ({} more bytes)", bytes.len() - RAW_PREVIEW_BYTES);
+    }
+}
+
+fn parse_id<T: FromStr<Err = Error>>(key: &str, prefix: &str) -> Result<T> {
+    let start = key
+        .find(prefix)
+        .ok_or_else(|| format_err!("{} has no {} prefix", key, prefix))?;
+    T::from_str(&key[start + prefix.len()..])
+}
+
+/// Decode the value of the struct that the key says it is, if known.
+fn decode_value(key: &str, value: BlobstoreBytes) -> Option<(&'static str, Result<String>)> {
+    // The order matters: some prefixes are part of others.
+    let (name, decoded) = if key.contains("hgchangeset.") {
+        (
+            "hg changeset",
+            HgChangesetEnvelope::from_blob(value.into()).map(|v| format!("{}", v)),
+        )
+    } else if key.contains("hgmanifest.") {
+        (
+            "hg manifest",
+            HgManifestEnvelope::from_blob(value.into()).map(|v| format!("{}", v)),
+        )
+    } else if key.contains("hgfilenode.") {
+        (
+            "hg filenode",
+            HgFileEnvelope::from_blob(value.into()).map(|v| format!("{}", v)),
+        )
+    } else if key.contains("git.tree.") {
+        let tree: Result<GitTree> = value.try_into();
+        ("git tree", tree.map(|v| format!("{}", v)))
+    } else if key.contains("content_metadata.") {
+        let metadata = parse_id::<ContentMetadataId>(key, "content_metadata.blake2.")
+            .and_then(|id| ContentMetadata::from_blob(Blob::new(id, value.into_bytes())));
+        ("content metadata", metadata.map(|v| format!("{:#?}", v)))
+    } else if key.contains("changeset.") {
+        let bcs = parse_id::<ChangesetId>(key, "changeset.blake2.")
+            .and_then(|id| BonsaiChangeset::from_blob(ChangesetBlob::new(id, value.into_bytes())));
+        ("bonsai changeset", bcs.map(|v| format!("{:#?}", v)))
+    } else if key.contains("content.") {
+        (
+            "file contents",
+            FileContents::from_encoded_bytes(value.into_bytes()).map(|v| format!("{:#?}", v)),
+        )
+    } else if key.contains("alias.") {
+        (
+            "content alias",
+            ContentAlias::from_bytes(value.into_bytes()).map(|v| format!("{:?}", v)),
+        )
+    } else {
+        return None;
+    };
+    Some((name, decoded))
+}
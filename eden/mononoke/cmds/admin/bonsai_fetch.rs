@@ -9,6 +9,7 @@ use clap::{App, ArgMatches, SubCommand};
 use cmdlib::args::{self, MononokeMatches};
 use context::CoreContext;
 use fbinit::FacebookInit;
+use megarepolib::tool_identity::{tool_provenance, ToolProvenance};
 use mononoke_types::{BonsaiChangeset, ChangesetId, DateTime, FileChange};
 use serde_derive::Serialize;
 use slog::Logger;
@@ -24,7 +25,8 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
         .about("fetches content of the file or manifest from blobrepo")
         .args_from_usage(
             r#"<CHANGESET_ID>    'hg/bonsai id or bookmark to fetch file from'
-                          --json            'if provided json will be returned'"#,
+                          --json            'if provided json will be returned'
+                          --tool-provenance 'print whether the megarepo tooling created the commit'"#,
         )
 }
 
@@ -43,6 +45,20 @@ pub async fn subcommand_bonsai_fetch<'a>(
 
     let blobrepo = args::open_repo(fb, &logger, &matches).await?;
     let bcs = fetch_bonsai_changeset(ctx, &rev, &blobrepo).await?;
+    if sub_m.is_present("tool-provenance") {
+        match tool_provenance(&bcs)? {
+            ToolProvenance::NotGenerated => println!("Not created by the megarepo tooling"),
+            ToolProvenance::Generated(identity) => println!(
+                "Created by {} {} for {:?}",
+                identity.tool, identity.tool_version, identity.operation
+            ),
+            ToolProvenance::Modified(identity) => println!(
+                "Created by {} {} for {:?}, and modified since",
+                identity.tool, identity.tool_version, identity.operation
+            ),
+        }
+        return Ok(());
+    }
     if json_flag {
         match serde_json::to_string(&SerializableBonsaiChangeset::from(bcs)) {
             Ok(json) => println!("{}", json),
@@ -5,16 +5,25 @@
  * GNU General Public License version 2.
  */
 
+use std::sync::Arc;
+
 use anyhow::{format_err, Error};
 use blobrepo_hg::BlobRepoHg;
+use blobstore::Loadable;
 use bookmarks::Freshness;
 use clap::{App, Arg, ArgMatches, SubCommand};
 use cloned::cloned;
 use context::CoreContext;
-use futures::TryStreamExt;
+use futures::{
+    compat::Stream01CompatExt,
+    stream::{self, BoxStream, StreamExt},
+    TryStreamExt,
+};
 use humantime::parse_duration;
-use mononoke_types::Timestamp;
+use mononoke_types::{ChangesetId, DateTime, Timestamp};
+use revset::DifferenceOfUnionsOfAncestorsNodeStream;
 use serde_json::{json, to_string_pretty};
+use skiplist::SkiplistIndex;
 use slog::{info, Logger};
 
 use blobrepo::BlobRepo;
@@ -27,6 +36,7 @@ pub const BOOKMARKS: &str = "bookmarks";
 const SET_CMD: &str = "set";
 const GET_CMD: &str = "get";
 const LOG_CMD: &str = "log";
+const HISTORY_CMD: &str = "history";
 const LIST_CMD: &str = "list";
 const DEL_CMD: &str = "delete";
 
@@ -35,6 +45,7 @@ const ARG_LIMIT: &str = "limit";
 const ARG_START_TIME: &str = "start-time";
 const ARG_END_TIME: &str = "end-time";
 const ARG_KIND: &str = "kind";
+const ARG_AT: &str = "at";
 
 pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
     let parent_subcommand = SubCommand::with_name(BOOKMARKS);
@@ -64,6 +75,16 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
                 .possible_values(&["bonsai", "hg"])
                 .required(false)
                 .help("What changeset type to return, either bonsai or hg. Defaults to hg."),
+        )
+        .arg(
+            Arg::with_name(ARG_AT)
+                .long(ARG_AT)
+                .takes_value(true)
+                .required(false)
+                .help(
+                    "Return the changeset that the bookmark pointed to at this time (in RFC 3339 \
+                    format) instead, as per the bookmark update log.",
+                ),
         );
 
     let log = SubCommand::with_name(LOG_CMD)
@@ -82,38 +103,21 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
                 .possible_values(&["bonsai", "hg"])
                 .required(false)
                 .help("What changeset type to return, either bonsai or hg. Defaults to hg."),
+        );
+    let log = add_log_filter_args(log);
+
+    let history = SubCommand::with_name(HISTORY_CMD)
+        .about(
+            "shows how a bookmark moved over time, with the number of commits added and removed \
+            by each move and who committed the commit it moved to",
         )
-        .arg(
-            Arg::with_name(ARG_LIMIT)
-                .long(ARG_LIMIT)
-                .short("l")
-                .takes_value(true)
-                .required(false)
-                .help("Imposes the limit on number of log records in output."),
-        )
-        .arg(
-            Arg::with_name(ARG_START_TIME)
-                .long(ARG_START_TIME)
-                .short("s")
-                .takes_value(true)
-                .required(false)
-                .help(
-                    "Filter log records by timestamp lower bound. \
-                    Takes time difference in free form e.g. 1h, 10m 30s, etc.",
-                ),
-        )
-        .arg(
-            Arg::with_name(ARG_END_TIME)
-                .long(ARG_END_TIME)
-                .short("e")
-                .takes_value(true)
-                .required(false)
-                .requires(ARG_START_TIME)
-                .help(
-                    "Filter log records by timestamp upper bound. \
-                    Takes time difference in free form e.g. 1h, 10m 30s, etc.",
-                ),
+        .args_from_usage(
+            r#"
+            <BOOKMARK_NAME>        'bookmark to target'
+            --json                 'if provided json will be returned'
+            "#,
         );
+    let history = add_log_filter_args(history);
 
     let list = SubCommand::with_name(LIST_CMD).about("list bookmarks").arg(
         Arg::with_name(ARG_KIND)
@@ -137,10 +141,45 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
         .subcommand(set)
         .subcommand(get)
         .subcommand(log)
+        .subcommand(history)
         .subcommand(list)
         .subcommand(del)
 }
 
+fn add_log_filter_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name(ARG_LIMIT)
+            .long(ARG_LIMIT)
+            .short("l")
+            .takes_value(true)
+            .required(false)
+            .help("Imposes the limit on number of log records in output."),
+    )
+    .arg(
+        Arg::with_name(ARG_START_TIME)
+            .long(ARG_START_TIME)
+            .short("s")
+            .takes_value(true)
+            .required(false)
+            .help(
+                "Filter log records by timestamp lower bound. \
+                    Takes time difference in free form e.g. 1h, 10m 30s, etc.",
+            ),
+    )
+    .arg(
+        Arg::with_name(ARG_END_TIME)
+            .long(ARG_END_TIME)
+            .short("e")
+            .takes_value(true)
+            .required(false)
+            .requires(ARG_START_TIME)
+            .help(
+                "Filter log records by timestamp upper bound. \
+                    Takes time difference in free form e.g. 1h, 10m 30s, etc.",
+            ),
+    )
+}
+
 pub async fn handle_command(
     ctx: CoreContext,
     repo: BlobRepo,
@@ -151,6 +190,7 @@ pub async fn handle_command(
         (GET_CMD, Some(sub_m)) => handle_get(sub_m, ctx, repo).await?,
         (SET_CMD, Some(sub_m)) => handle_set(sub_m, ctx, repo).await?,
         (LOG_CMD, Some(sub_m)) => handle_log(sub_m, ctx, repo).await?,
+        (HISTORY_CMD, Some(sub_m)) => handle_history(sub_m, ctx, repo).await?,
         (LIST_CMD, Some(sub_m)) => handle_list(sub_m, ctx, repo).await?,
         (DEL_CMD, Some(sub_m)) => handle_delete(sub_m, ctx, repo).await?,
         _ => return Err(SubcommandError::InvalidArgs),
@@ -176,6 +216,38 @@ async fn handle_get(args: &ArgMatches<'_>, ctx: CoreContext, repo: BlobRepo) ->
     let changeset_type = args.value_of(ARG_CHANGESET_TYPE).unwrap_or("hg");
     let json_flag = args.is_present("json");
 
+    if let Some(at) = args.value_of(ARG_AT) {
+        let timestamp: Timestamp = DateTime::from_rfc3339(at)?.into();
+        let entry = repo
+            .bookmarks_log()
+            .get_bookmark_log_entry_at_timestamp(
+                ctx.clone(),
+                bookmark.clone(),
+                timestamp,
+                Freshness::MostRecent,
+            )
+            .await?;
+        let cs_id = match entry {
+            Some((_id, Some(cs_id), _reason, _timestamp)) => cs_id,
+            Some((_id, None, _reason, _timestamp)) => {
+                return Err(format_err!("{} was deleted at {}", bookmark, at));
+            }
+            None => {
+                return Err(format_err!("{} has no log entries before {}", bookmark, at));
+            }
+        };
+        let output = match changeset_type {
+            "hg" => {
+                let hg_cs_id = repo.get_hg_from_bonsai_changeset(ctx, cs_id).await?;
+                format_output(json_flag, hg_cs_id.to_string(), "hg")
+            }
+            "bonsai" => format_output(json_flag, cs_id.to_string(), "bonsai"),
+            _ => return Err(format_err!("Unknown changeset-type supplied")),
+        };
+        println!("{}", output);
+        return Ok(());
+    }
+
     match changeset_type {
         "hg" => {
             let cs = repo.get_bookmark(ctx, &bookmark).await?;
@@ -196,23 +268,28 @@ async fn handle_get(args: &ArgMatches<'_>, ctx: CoreContext, repo: BlobRepo) ->
     }
 }
 
-async fn handle_log(args: &ArgMatches<'_>, ctx: CoreContext, repo: BlobRepo) -> Result<(), Error> {
-    let bookmark_name = args.value_of("BOOKMARK_NAME").unwrap().to_string();
-    let bookmark = BookmarkName::new(bookmark_name).unwrap();
-    let changeset_type = args.value_of(ARG_CHANGESET_TYPE).unwrap_or("hg");
-    let json_flag = args.is_present("json");
+fn parse_limit(args: &ArgMatches<'_>) -> Result<u32, Error> {
     let output_limit_as_string = args.value_of(ARG_LIMIT).unwrap_or("25");
-    let max_rec = match output_limit_as_string.parse::<u32>() {
-        Ok(n) => n,
-        Err(e) => {
-            return Err(format_err!(
-                "Bad limit value supplied: \"{}\" - {}",
-                output_limit_as_string,
-                e
-            ));
-        }
-    };
+    match output_limit_as_string.parse::<u32>() {
+        Ok(n) => Ok(n),
+        Err(e) => Err(format_err!(
+            "Bad limit value supplied: \"{}\" - {}",
+            output_limit_as_string,
+            e
+        )),
+    }
+}
 
+fn list_log_entries(
+    args: &ArgMatches<'_>,
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    bookmark: &BookmarkName,
+    max_rec: u32,
+) -> Result<
+    BoxStream<'static, Result<(u64, Option<ChangesetId>, BookmarkUpdateReason, Timestamp), Error>>,
+    Error,
+> {
     let filter_by_ts_range = args.is_present(ARG_START_TIME) || args.is_present(ARG_END_TIME);
     let entries = if filter_by_ts_range {
         let min_ts_diff_ns = parse_duration(args.value_of(ARG_START_TIME).ok_or_else(|| {
@@ -246,6 +323,18 @@ async fn handle_log(args: &ArgMatches<'_>, ctx: CoreContext, repo: BlobRepo) ->
         )
     };
 
+    Ok(entries)
+}
+
+async fn handle_log(args: &ArgMatches<'_>, ctx: CoreContext, repo: BlobRepo) -> Result<(), Error> {
+    let bookmark_name = args.value_of("BOOKMARK_NAME").unwrap().to_string();
+    let bookmark = BookmarkName::new(bookmark_name).unwrap();
+    let changeset_type = args.value_of(ARG_CHANGESET_TYPE).unwrap_or("hg");
+    let json_flag = args.is_present("json");
+    let max_rec = parse_limit(args)?;
+
+    let entries = list_log_entries(args, &ctx, &repo, &bookmark, max_rec)?;
+
     match changeset_type {
         "hg" => {
             entries
@@ -313,6 +402,146 @@ async fn handle_log(args: &ArgMatches<'_>, ctx: CoreContext, repo: BlobRepo) ->
     }
 }
 
+/// A single move of a bookmark, as shown by the history subcommand.
+struct BookmarkMove {
+    entry_id: u64,
+    from: Option<ChangesetId>,
+    to: Option<ChangesetId>,
+    reason: BookmarkUpdateReason,
+    timestamp: Timestamp,
+    added: Option<u64>,
+    removed: Option<u64>,
+    // Of the commit the bookmark was moved to.
+    committer: Option<String>,
+}
+
+impl BookmarkMove {
+    fn format(&self, json_flag: bool) -> String {
+        let to_str = |cs_id: Option<ChangesetId>| cs_id.map(|cs_id| cs_id.to_string());
+        if json_flag {
+            let answer = json!({
+                "entry_id": self.entry_id,
+                "from_changeset_id": to_str(self.from),
+                "to_changeset_id": to_str(self.to),
+                "reason": self.reason.to_string(),
+                "timestamp_sec": self.timestamp.timestamp_seconds(),
+                "commits_added": self.added,
+                "commits_removed": self.removed,
+                "committer": self.committer,
+            });
+            to_string_pretty(&answer).unwrap()
+        } else {
+            let dt: DateTime = self.timestamp.into();
+            let delta = match (self.added, self.removed) {
+                (Some(added), Some(removed)) => format!("+{} -{}", added, removed),
+                _ => "?".to_string(),
+            };
+            format!(
+                "{} {} {} -> {} ({}) {} by {}",
+                self.entry_id,
+                dt.as_chrono().format("%b %e %T %Y"),
+                to_str(self.from).unwrap_or_else(|| "(none)".to_string()),
+                to_str(self.to).unwrap_or_else(|| "(none)".to_string()),
+                delta,
+                self.reason,
+                self.committer.as_deref().unwrap_or("(unknown)"),
+            )
+        }
+    }
+}
+
+async fn count_exclusive_ancestors(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    head: ChangesetId,
+    base: ChangesetId,
+) -> Result<u64, Error> {
+    DifferenceOfUnionsOfAncestorsNodeStream::new_with_excludes(
+        ctx.clone(),
+        &repo.get_changeset_fetcher(),
+        Arc::new(SkiplistIndex::new()),
+        vec![head],
+        vec![base],
+    )
+    .compat()
+    .try_fold(0, |count, _| async move { Ok(count + 1) })
+    .await
+}
+
+async fn describe_move(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    entry_id: u64,
+    from: Option<ChangesetId>,
+    to: Option<ChangesetId>,
+    reason: BookmarkUpdateReason,
+    timestamp: Timestamp,
+) -> Result<BookmarkMove, Error> {
+    let (added, removed) = match (from, to) {
+        (Some(from), Some(to)) => {
+            let (added, removed) = futures::try_join!(
+                count_exclusive_ancestors(ctx, repo, to, from),
+                count_exclusive_ancestors(ctx, repo, from, to),
+            )?;
+            (Some(added), Some(removed))
+        }
+        _ => (None, None),
+    };
+
+    // Commits that were never amended or rebased by someone else have no separate committer.
+    let committer = match to {
+        Some(to) => {
+            let bcs = to.load(ctx, repo.blobstore()).await?;
+            Some(bcs.committer().unwrap_or_else(|| bcs.author()).to_string())
+        }
+        None => None,
+    };
+
+    Ok(BookmarkMove {
+        entry_id,
+        from,
+        to,
+        reason,
+        timestamp,
+        added,
+        removed,
+        committer,
+    })
+}
+
+async fn handle_history(
+    args: &ArgMatches<'_>,
+    ctx: CoreContext,
+    repo: BlobRepo,
+) -> Result<(), Error> {
+    let bookmark_name = args.value_of("BOOKMARK_NAME").unwrap().to_string();
+    let bookmark = BookmarkName::new(bookmark_name).unwrap();
+    let json_flag = args.is_present("json");
+    let max_rec = parse_limit(args)?;
+
+    // Entries come newest first. Fetch one extra entry so that we know where the oldest move in
+    // the range started from.
+    let entries: Vec<_> =
+        list_log_entries(args, &ctx, &repo, &bookmark, max_rec.saturating_add(1))?
+            .try_collect()
+            .await?;
+
+    let moves = entries.iter().enumerate().take(max_rec as usize).map(
+        |(idx, (entry_id, to, reason, timestamp))| {
+            let from = entries.get(idx + 1).and_then(|(_, from, _, _)| *from);
+            describe_move(&ctx, &repo, *entry_id, from, *to, *reason, *timestamp)
+        },
+    );
+
+    stream::iter(moves)
+        .buffered(10)
+        .try_for_each(|bookmark_move| async move {
+            println!("{}", bookmark_move.format(json_flag));
+            Ok(())
+        })
+        .await
+}
+
 async fn handle_list(args: &ArgMatches<'_>, ctx: CoreContext, repo: BlobRepo) -> Result<(), Error> {
     match args.value_of(ARG_KIND) {
         Some("publishing") => {
@@ -413,4 +642,32 @@ mod tests {
     fn plain_output_format() {
         assert_eq!(format_output(false, "123".to_string(), "hg"), "(HG) 123");
     }
+
+    #[test]
+    fn history_json_output_format() {
+        let bookmark_move = BookmarkMove {
+            entry_id: 3,
+            from: None,
+            to: None,
+            reason: BookmarkUpdateReason::Pushrebase,
+            timestamp: Timestamp::from_timestamp_secs(10),
+            added: Some(2),
+            removed: Some(0),
+            committer: Some("alice".to_string()),
+        };
+        let expected_answer = json!({
+            "entry_id": 3,
+            "from_changeset_id": null,
+            "to_changeset_id": null,
+            "reason": "pushrebase",
+            "timestamp_sec": 10,
+            "commits_added": 2,
+            "commits_removed": 0,
+            "committer": "alice",
+        });
+        assert_eq!(
+            bookmark_move.format(true),
+            to_string_pretty(&expected_answer).unwrap()
+        );
+    }
 }
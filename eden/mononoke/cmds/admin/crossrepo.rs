@@ -12,7 +12,7 @@ use blobstore::Loadable;
 use bookmark_renaming::get_small_to_large_renamer;
 use bookmarks::{BookmarkName, BookmarkUpdateLog, BookmarkUpdateReason, Freshness};
 use cached_config::ConfigStore;
-use clap::{App, Arg, ArgMatches, SubCommand};
+use clap::{App, Arg, ArgGroup, ArgMatches, SubCommand};
 use cmdlib::{
     args::{self, MononokeMatches},
     helpers,
@@ -41,7 +41,7 @@ use sorted_vector_map::sorted_vector_map;
 use std::sync::Arc;
 use std::{collections::BTreeMap, convert::TryInto};
 use synced_commit_mapping::{
-    EquivalentWorkingCopyEntry, SqlSyncedCommitMapping, SyncedCommitMapping,
+    EquivalentWorkingCopyEntry, QuarantineResolution, SqlSyncedCommitMapping, SyncedCommitMapping,
     SyncedCommitMappingEntry,
 };
 
@@ -68,6 +68,12 @@ const NOT_SYNC_CANDIDATE_SUBCOMMAND: &str = "not-sync-candidate";
 const SOURCE_HASH_ARG: &str = "source-hash";
 const TARGET_HASH_ARG: &str = "target-hash";
 const VIA_EXTRAS_ARG: &str = "via-extra";
+const QUARANTINE_SUBCOMMAND: &str = "quarantine";
+const RESOLVE_SUBCOMMAND: &str = "resolve";
+const ARG_LIMIT: &str = "limit";
+const ARG_QUARANTINE_ID: &str = "QUARANTINE_ID";
+const ARG_KEEP_EXISTING: &str = "keep-existing";
+const ARG_REPLACE_EXISTING: &str = "replace-existing";
 
 const SUBCOMMAND_CONFIG: &str = "config";
 const SUBCOMMAND_BY_VERSION: &str = "by-version";
@@ -176,6 +182,85 @@ pub async fn subcommand_crossrepo<'a>(
         (INSERT_SUBCOMMAND, Some(sub_sub_m)) => {
             run_insert_subcommand(ctx, matches, sub_sub_m, live_commit_sync_config).await
         }
+        (QUARANTINE_SUBCOMMAND, Some(sub_sub_m)) => {
+            run_quarantine_subcommand(ctx, matches, sub_sub_m, live_commit_sync_config).await
+        }
+        _ => Err(SubcommandError::InvalidArgs),
+    }
+}
+
+async fn run_quarantine_subcommand<'a>(
+    ctx: CoreContext,
+    matches: &'a MononokeMatches<'_>,
+    quarantine_subcommand_matches: &'a ArgMatches<'a>,
+    live_commit_sync_config: CfgrLiveCommitSyncConfig,
+) -> Result<(), SubcommandError> {
+    let (source_repo, target_repo, mapping) =
+        get_source_target_repos_and_mapping(ctx.fb, ctx.logger().clone(), matches).await?;
+
+    match quarantine_subcommand_matches.subcommand() {
+        (SUBCOMMAND_LIST, Some(sub_m)) => {
+            let live_commit_sync_config: Arc<dyn LiveCommitSyncConfig> =
+                Arc::new(live_commit_sync_config);
+            let commit_syncer = get_large_to_small_commit_syncer(
+                &ctx,
+                source_repo,
+                target_repo,
+                live_commit_sync_config,
+                mapping.clone(),
+            )
+            .await?;
+            let limit = args::get_u64(sub_m, ARG_LIMIT, 100);
+
+            let quarantined = mapping
+                .get_quarantined(
+                    ctx.clone(),
+                    commit_syncer.get_large_repo().get_repoid(),
+                    commit_syncer.get_small_repo().get_repoid(),
+                    limit,
+                )
+                .compat()
+                .await?;
+            for quarantined in quarantined {
+                println!(
+                    "{}: large {} -> small {} (version {:?}), conflicts with small {} (version {:?}), quarantined at {}",
+                    quarantined.id,
+                    quarantined.entry.large_bcs_id,
+                    quarantined.entry.small_bcs_id,
+                    quarantined.entry.version_name,
+                    quarantined.existing_small_bcs_id,
+                    quarantined.existing_version_name,
+                    quarantined.quarantined_at.timestamp_seconds(),
+                );
+            }
+            Ok(())
+        }
+        (RESOLVE_SUBCOMMAND, Some(sub_m)) => {
+            let id = sub_m
+                .value_of(ARG_QUARANTINE_ID)
+                .unwrap()
+                .parse::<u64>()
+                .context("Invalid quarantine id")?;
+            let resolution = if sub_m.is_present(ARG_REPLACE_EXISTING) {
+                QuarantineResolution::ReplaceExisting
+            } else {
+                QuarantineResolution::KeepExisting
+            };
+
+            if mapping
+                .resolve_quarantined(ctx.clone(), id, resolution)
+                .compat()
+                .await?
+            {
+                info!(
+                    ctx.logger(),
+                    "resolved quarantined mapping {}: {:?}", id, resolution
+                );
+                Ok(())
+            } else {
+                Err(anyhow!("no quarantined mapping with id {}", id).into())
+            }
+        }
         _ => Err(SubcommandError::InvalidArgs),
     }
 }
@@ -470,7 +555,7 @@ async fn change_mapping_via_extras<'a>(
     .await?;
 
 
-    let pushrebase_flags = repo_config.pushrebase.flags;
+    let pushrebase_flags = repo_config.pushrebase.flags.clone();
     let pushrebase_hooks = bookmarks_movement::get_pushrebase_hooks(
         &ctx,
         &large_repo,
@@ -1375,6 +1460,47 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
         .subcommand(rewritten_subcommand)
         .subcommand(not_sync_candidate_subcommand);
 
+    let quarantine_subcommand = SubCommand::with_name(QUARANTINE_SUBCOMMAND)
+        .about(
+            "inspect and resolve mappings that were quarantined because they conflicted with an existing mapping",
+        )
+        .subcommand(
+            SubCommand::with_name(SUBCOMMAND_LIST)
+                .about("list the most recently quarantined mappings")
+                .arg(
+                    Arg::with_name(ARG_LIMIT)
+                        .long(ARG_LIMIT)
+                        .takes_value(true)
+                        .required(false)
+                        .help("how many mappings to list"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(RESOLVE_SUBCOMMAND)
+                .about("resolve a quarantined mapping, and remove it from the quarantine")
+                .arg(
+                    Arg::with_name(ARG_QUARANTINE_ID)
+                        .required(true)
+                        .takes_value(true)
+                        .help("id of the quarantined mapping, as listed"),
+                )
+                .arg(
+                    Arg::with_name(ARG_KEEP_EXISTING)
+                        .long(ARG_KEEP_EXISTING)
+                        .help("the existing mapping is right: drop the quarantined one"),
+                )
+                .arg(
+                    Arg::with_name(ARG_REPLACE_EXISTING)
+                        .long(ARG_REPLACE_EXISTING)
+                        .help("the quarantined mapping is right: replace the existing one"),
+                )
+                .group(
+                    ArgGroup::with_name("resolution")
+                        .args(&[ARG_KEEP_EXISTING, ARG_REPLACE_EXISTING])
+                        .required(true),
+                ),
+        );
+
     SubCommand::with_name(CROSSREPO)
         .subcommand(map_subcommand)
         .subcommand(verify_wc_subcommand)
@@ -1382,6 +1508,7 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
         .subcommand(commit_sync_config_subcommand)
         .subcommand(pushredirection_subcommand)
         .subcommand(insert_subcommand)
+        .subcommand(quarantine_subcommand)
 }
 
 async fn get_large_to_small_commit_syncer(
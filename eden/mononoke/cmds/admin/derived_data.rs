@@ -16,24 +16,27 @@ use cmdlib::{
 };
 use context::CoreContext;
 use derived_data::{BonsaiDerivable, BonsaiDerived};
+use derived_data_filenodes::FilenodesOnlyPublic;
 use derived_data_utils::{
     derived_data_utils, derived_data_utils_for_backfill, POSSIBLE_DERIVED_TYPES,
 };
 use fbinit::FacebookInit;
 use fsnodes::RootFsnodeId;
 use futures::{
-    compat::Future01CompatExt,
+    compat::{Future01CompatExt, Stream01CompatExt},
     future::{try_join_all, FutureExt as PreviewFutureExt},
-    TryStreamExt,
+    try_join, TryStreamExt,
 };
 use manifest::ManifestOps;
 use mercurial_derived_data::MappedHgChangesetId;
 use mononoke_types::{ChangesetId, ContentId, FileType, MPath};
+use revset::RangeNodeStream;
 use skeleton_manifest::RootSkeletonManifestId;
 use slog::{info, Logger};
 use std::{
     collections::{HashMap, HashSet},
     fmt,
+    io::{self, BufRead, Write},
 };
 use unodes::RootUnodeManifestId;
 
@@ -42,11 +45,16 @@ use crate::error::SubcommandError;
 pub const DERIVED_DATA: &str = "derived-data";
 const SUBCOMMAND_EXISTS: &str = "exists";
 const SUBCOMMAND_VERIFY_MANIFESTS: &str = "verify-manifests";
+const SUBCOMMAND_INVALIDATE: &str = "invalidate";
 
 const ARG_HASH_OR_BOOKMARK: &str = "hash-or-bookmark";
 const ARG_TYPE: &str = "type";
 const ARG_IF_DERIVED: &str = "if-derived";
 const ARG_BACKFILL: &str = "backfill";
+const ARG_START: &str = "start";
+const ARG_END: &str = "end";
+const ARG_DRY_RUN: &str = "dry-run";
+const ARG_YES: &str = "yes";
 
 const MANIFEST_DERIVED_DATA_TYPES: &[&str] = &[
     RootFsnodeId::NAME,
@@ -55,6 +63,11 @@ const MANIFEST_DERIVED_DATA_TYPES: &[&str] = &[
     RootSkeletonManifestId::NAME,
 ];
 
+/// Derived data types whose mappings don't live in the blobstore. Re-deriving them doesn't
+/// overwrite what is already stored, so they can't be invalidated this way.
+const NON_INVALIDATABLE_DERIVED_DATA_TYPES: &[&str] =
+    &[MappedHgChangesetId::NAME, FilenodesOnlyPublic::NAME];
+
 pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name(DERIVED_DATA)
         .about("request information about derived data")
@@ -104,6 +117,45 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
                         .long(ARG_IF_DERIVED),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name(SUBCOMMAND_INVALIDATE)
+                .about(
+                    "invalidate derived data for a range of commits, e.g. after a buggy \
+                     derivation was rolled out. Blobstores can't delete, so the data is \
+                     invalidated by deriving it again and overwriting the mapping",
+                )
+                .arg(
+                    Arg::with_name(ARG_TYPE)
+                        .help("type of derived data")
+                        .takes_value(true)
+                        .possible_values(POSSIBLE_DERIVED_TYPES)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(ARG_START)
+                        .long(ARG_START)
+                        .help("first commit of the range (hash or bookmark)")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(ARG_END)
+                        .long(ARG_END)
+                        .help("last commit of the range (hash or bookmark), a descendant of start")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(ARG_DRY_RUN)
+                        .long(ARG_DRY_RUN)
+                        .help("only list the commits whose derived data would be invalidated"),
+                )
+                .arg(
+                    Arg::with_name(ARG_YES)
+                        .long(ARG_YES)
+                        .help("don't ask for confirmation"),
+                ),
+        )
 }
 
 pub async fn subcommand_derived_data<'a>(
@@ -160,6 +212,24 @@ pub async fn subcommand_derived_data<'a>(
             )
             .await
         }
+        (SUBCOMMAND_INVALIDATE, Some(arg_matches)) => {
+            let derived_data_type = arg_matches.value_of(ARG_TYPE).unwrap().to_string();
+            let start = arg_matches.value_of(ARG_START).unwrap().to_string();
+            let end = arg_matches.value_of(ARG_END).unwrap().to_string();
+            let dry_run = arg_matches.is_present(ARG_DRY_RUN);
+            let skip_confirmation = arg_matches.is_present(ARG_YES);
+
+            invalidate_derived_data(
+                ctx,
+                repo,
+                derived_data_type,
+                start,
+                end,
+                dry_run,
+                skip_confirmation,
+            )
+            .await
+        }
         _ => Err(SubcommandError::InvalidArgs),
     }
 }
@@ -199,6 +269,111 @@ async fn check_derived_data_exists(
     Ok(())
 }
 
+async fn invalidate_derived_data(
+    ctx: CoreContext,
+    repo: BlobRepo,
+    derived_data_type: String,
+    start: String,
+    end: String,
+    dry_run: bool,
+    skip_confirmation: bool,
+) -> Result<(), SubcommandError> {
+    if NON_INVALIDATABLE_DERIVED_DATA_TYPES.contains(&derived_data_type.as_str()) {
+        return Err(anyhow!(
+            "{} are not stored in the blobstore and can't be invalidated",
+            derived_data_type
+        )
+        .into());
+    }
+    let derived_utils = derived_data_utils(&repo, derived_data_type)?;
+
+    let (start, end) = try_join!(
+        csid_resolve(ctx.clone(), repo.clone(), start).compat(),
+        csid_resolve(ctx.clone(), repo.clone(), end).compat(),
+    )?;
+
+    // Sort by generation, so that parents are derived again before their children.
+    let changeset_fetcher = repo.get_changeset_fetcher();
+    let mut cs_ids: Vec<_> =
+        RangeNodeStream::new(ctx.clone(), changeset_fetcher.clone(), start, end)
+            .compat()
+            .map_ok(|cs_id| {
+                let changeset_fetcher = changeset_fetcher.clone();
+                let ctx = ctx.clone();
+                async move {
+                    let generation = changeset_fetcher.get_generation_number(ctx, cs_id).await?;
+                    Result::<_, Error>::Ok((generation, cs_id))
+                }
+            })
+            .try_buffer_unordered(100)
+            .try_collect()
+            .await?;
+    cs_ids.sort();
+    let cs_ids: Vec<_> = cs_ids.into_iter().map(|(_, cs_id)| cs_id).collect();
+
+    // Commits that were never derived have nothing to invalidate.
+    let pending: HashSet<_> = derived_utils
+        .pending(ctx.clone(), repo.clone(), cs_ids.clone())
+        .await?
+        .into_iter()
+        .collect();
+    let derived: Vec<_> = cs_ids
+        .into_iter()
+        .filter(|cs_id| !pending.contains(cs_id))
+        .collect();
+
+    let name = derived_utils.name();
+    if derived.is_empty() {
+        println!("No commits in the range have {} derived", name);
+        return Ok(());
+    }
+
+    if dry_run {
+        println!("Would invalidate {} for {} commits:", name, derived.len());
+        for cs_id in &derived {
+            println!("{}", cs_id);
+        }
+        return Ok(());
+    }
+
+    if !skip_confirmation
+        && !confirm(&format!(
+            "Invalidate {} for {} commits?",
+            name,
+            derived.len()
+        ))?
+    {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    derived_utils.regenerate(&derived);
+    for (idx, cs_id) in derived.iter().enumerate() {
+        derived_utils
+            .derive(ctx.clone(), repo.clone(), *cs_id)
+            .await?;
+        info!(
+            ctx.logger(),
+            "Derived {} for {} ({}/{})",
+            name,
+            cs_id,
+            idx + 1,
+            derived.len()
+        );
+    }
+    println!("Invalidated {} for {} commits", name, derived.len());
+
+    Ok(())
+}
+
+fn confirm(question: &str) -> Result<bool, Error> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
+
 async fn verify_manifests(
     ctx: CoreContext,
     repo: BlobRepo,
@@ -6,26 +6,29 @@
  */
 
 use anyhow::{anyhow, format_err, Error, Result};
-use blobstore::Loadable;
+use blobstore::{Blobstore, Loadable, LoadableError};
 use bytes::BytesMut;
 use clap::{App, Arg, ArgMatches, SubCommand};
 use cmdlib::args::{self, MononokeMatches};
+use cmdlib_progress::{Progress, ProgressOptions};
 use context::CoreContext;
 use fbinit::FacebookInit;
 use filestore::{self, Alias, FetchKey, StoreRequest};
 use futures::{
     future::{self, TryFutureExt},
-    stream::TryStreamExt,
+    stream::{self, StreamExt, TryStreamExt},
 };
 use mononoke_types::{
     hash::{Sha1, Sha256},
-    ContentId, FileContents,
+    ContentId, ContentMetadata, FileContents,
 };
+use serde_derive::Serialize;
 use slog::{info, Logger};
+use std::fmt;
 use std::str::FromStr;
 use tokio::{
     fs::File,
-    io::{AsyncWriteExt, BufReader},
+    io::{AsyncReadExt, AsyncWriteExt, BufReader},
 };
 use tokio_util::codec::{BytesCodec, FramedRead};
 
@@ -37,10 +40,15 @@ const COMMAND_STORE: &str = "store";
 const COMMAND_FETCH: &str = "fetch";
 const COMMAND_VERIFY: &str = "verify";
 const COMMAND_IS_CHUNKED: &str = "is-chunked";
+const COMMAND_REHASH: &str = "rehash";
 
 const ARG_KIND: &str = "kind";
 const ARG_ID: &str = "id";
 const ARG_FILE: &str = "file";
+const ARG_INPUT_FILE: &str = "input-file";
+const ARG_CONCURRENCY: &str = "concurrency";
+
+const DEFAULT_REHASH_CONCURRENCY: usize = 100;
 
 // NOTE: Fetching by GitSha1 is not concurrently supported since that needs a size to instantiate.
 const VALID_KINDS: [&str; 3] = ["id", "sha1", "sha256"];
@@ -87,6 +95,31 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
                 .arg(kind_arg.clone())
                 .arg(id_arg.clone()),
         )
+        .subcommand(
+            SubCommand::with_name(COMMAND_REHASH)
+                .about(
+                    "recompute the hashes and sizes of contents from their bytes, and check them \
+                     against their metadata and aliases. Prints a report as json, one line per \
+                     content",
+                )
+                .arg(
+                    Arg::with_name(ARG_INPUT_FILE)
+                        .long(ARG_INPUT_FILE)
+                        .takes_value(true)
+                        .required(true)
+                        .help(
+                            "file with one content id per line, such as the output of a SQL \
+                             query, or - for stdin",
+                        ),
+                )
+                .arg(
+                    Arg::with_name(ARG_CONCURRENCY)
+                        .long(ARG_CONCURRENCY)
+                        .takes_value(true)
+                        .required(false)
+                        .help("how many contents to rehash at once"),
+                ),
+        )
 }
 
 pub async fn execute_command<'a>(
@@ -212,10 +245,186 @@ pub async fn execute_command<'a>(
             }
             Ok(())
         }
+        (COMMAND_REHASH, Some(matches)) => {
+            let concurrency = args::get_usize(matches, ARG_CONCURRENCY, DEFAULT_REHASH_CONCURRENCY);
+            let content_ids = read_content_ids(matches.value_of(ARG_INPUT_FILE).unwrap()).await?;
+            let blobstore = blobrepo.get_blobstore();
+
+            let progress = Progress::new(
+                "rehashed contents",
+                Some(content_ids.len() as u64),
+                ProgressOptions::default(),
+            )
+            .with_logger(logger.clone());
+
+            let mut reports = stream::iter(content_ids)
+                .map(|content_id| rehash_and_check(&ctx, &blobstore, content_id))
+                .buffered(concurrency);
+            let mut failed = 0;
+            while let Some(report) = reports.next().await {
+                println!("{}", serde_json::to_string(&report).map_err(Error::from)?);
+                if report.status != RehashStatus::Ok {
+                    failed += 1;
+                }
+                progress.record(1);
+                progress.increment(report.status, 1);
+                progress.report_throttled();
+            }
+            progress.report();
+
+            if failed > 0 {
+                return Err(anyhow!(
+                    "{} of {} contents failed to verify",
+                    failed,
+                    progress.done()
+                )
+                .into());
+            }
+            Ok(())
+        }
         _ => Err(SubcommandError::InvalidArgs),
     }
 }
 
+async fn read_content_ids(path: &str) -> Result<Vec<ContentId>> {
+    let mut input = String::new();
+    if path == "-" {
+        tokio::io::stdin().read_to_string(&mut input).await?;
+    } else {
+        File::open(path).await?.read_to_string(&mut input).await?;
+    }
+    input
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(ContentId::from_str)
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum RehashStatus {
+    Ok,
+    Mismatch,
+    Missing,
+    Error,
+}
+
+impl fmt::Display for RehashStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Ok => "ok",
+            Self::Mismatch => "mismatch",
+            Self::Missing => "missing",
+            Self::Error => "error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RehashMismatch {
+    /// What doesn't match, e.g. "sha1" if the metadata has another sha1 than the bytes, or
+    /// "sha1_alias" if the alias of the sha1 of the bytes points to another content
+    field: &'static str,
+    stored: Option<String>,
+    computed: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RehashReport {
+    content_id: String,
+    status: RehashStatus,
+    mismatches: Vec<RehashMismatch>,
+    error: Option<String>,
+}
+
+async fn rehash_and_check(
+    ctx: &CoreContext,
+    blobstore: &impl Blobstore,
+    content_id: ContentId,
+) -> RehashReport {
+    let (status, mismatches, error) = match check_content(ctx, blobstore, content_id).await {
+        Ok(None) => (RehashStatus::Missing, vec![], None),
+        Ok(Some(mismatches)) if mismatches.is_empty() => (RehashStatus::Ok, mismatches, None),
+        Ok(Some(mismatches)) => (RehashStatus::Mismatch, mismatches, None),
+        Err(e) => (RehashStatus::Error, vec![], Some(format!("{:#}", e))),
+    };
+    RehashReport {
+        content_id: content_id.to_string(),
+        status,
+        mismatches,
+        error,
+    }
+}
+
+/// What about a content doesn't match its bytes, or None if there is no such content.
+async fn check_content(
+    ctx: &CoreContext,
+    blobstore: &impl Blobstore,
+    content_id: ContentId,
+) -> Result<Option<Vec<RehashMismatch>>> {
+    let computed = match filestore::rehash(blobstore, ctx, content_id).await? {
+        Some(computed) => computed,
+        None => return Ok(None),
+    };
+    let stored = filestore::get_metadata_readonly(blobstore, ctx, &FetchKey::Canonical(content_id))
+        .await?
+        .flatten();
+
+    let mut mismatches = Vec::new();
+    let mut check = |field, stored: Option<String>, computed: String| {
+        if stored.as_ref() != Some(&computed) {
+            mismatches.push(RehashMismatch {
+                field,
+                stored,
+                computed,
+            });
+        }
+    };
+
+    // The content id is the one the content is stored under, the rest is from its metadata, if
+    // it has any.
+    check(
+        "content_id",
+        Some(content_id.to_string()),
+        computed.content_id.to_string(),
+    );
+    let fields: [(&'static str, fn(&ContentMetadata) -> String); 4] = [
+        ("total_size", |m| m.total_size.to_string()),
+        ("sha1", |m| m.sha1.to_string()),
+        ("sha256", |m| m.sha256.to_string()),
+        ("git_sha1", |m| m.git_sha1.to_string()),
+    ];
+    for (field, value) in fields.iter() {
+        check(*field, stored.as_ref().map(value), value(&computed));
+    }
+
+    // The aliases of the hashes of the bytes must lead back to this content.
+    let aliases = vec![
+        ("sha1_alias", Alias::Sha1(computed.sha1)),
+        ("sha256_alias", Alias::Sha256(computed.sha256)),
+        ("git_sha1_alias", Alias::GitSha1(computed.git_sha1.sha1())),
+    ];
+    for (field, alias) in aliases {
+        let aliased = FetchKey::Aliased(alias)
+            .load(ctx, blobstore)
+            .await
+            .map(Some)
+            .or_else(|err| match err {
+                LoadableError::Error(err) => Err(err),
+                LoadableError::Missing(_) => Ok(None),
+            })?;
+        check(
+            field,
+            aliased.map(|id| id.to_string()),
+            content_id.to_string(),
+        );
+    }
+
+    Ok(Some(mismatches))
+}
+
 // NOTE: This assumes the matches are from a command that has ARG_KIND and ARG_ID.
 fn extract_fetch_key(matches: &ArgMatches<'_>) -> Result<FetchKey> {
     let id = matches.value_of(ARG_ID).unwrap();
@@ -18,6 +18,7 @@ use context::CoreContext;
 use slog::error;
 
 use crate::blobstore_fetch::subcommand_blobstore_fetch;
+use crate::blobstore_inspect::subcommand_blobstore_inspect;
 use crate::blobstore_upload::subcommand_blobstore_upload;
 use crate::bonsai_fetch::subcommand_bonsai_fetch;
 use crate::content_fetch::subcommand_content_fetch;
@@ -33,6 +34,7 @@ use crate::redaction::subcommand_redaction;
 use crate::skiplist_subcommand::subcommand_skiplist;
 
 mod blobstore_fetch;
+mod blobstore_inspect;
 mod blobstore_upload;
 mod bonsai_fetch;
 mod bookmarks_manager;
@@ -57,6 +59,7 @@ mod skiplist_subcommand;
 mod subcommand_blame;
 mod subcommand_deleted_manifest;
 mod subcommand_fsnodes;
+mod subcommand_manifest_consistency;
 mod subcommand_skeleton_manifests;
 mod subcommand_unodes;
 
@@ -69,6 +72,7 @@ fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
         .build()
         .about("Poke at mononoke internals for debugging and investigating data structures.")
         .subcommand(blobstore_fetch::build_subcommand())
+        .subcommand(blobstore_inspect::build_subcommand())
         .subcommand(blobstore_upload::build_subcommand())
         .subcommand(bonsai_fetch::build_subcommand())
         .subcommand(create_bonsai::build_subcommand())
@@ -93,6 +97,7 @@ fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
         .subcommand(rebase::build_subcommand())
         .subcommand(pushrebase::build_subcommand())
         .subcommand(subcommand_skeleton_manifests::build_subcommand())
+        .subcommand(subcommand_manifest_consistency::build_subcommand())
 }
 
 #[fbinit::main]
@@ -112,6 +117,9 @@ fn main(fb: FacebookInit) -> ExitCode {
             (blobstore_fetch::BLOBSTORE_FETCH, Some(sub_m)) => {
                 subcommand_blobstore_fetch(fb, logger, &matches, sub_m).await
             }
+            (blobstore_inspect::BLOBSTORE_INSPECT, Some(sub_m)) => {
+                subcommand_blobstore_inspect(fb, logger, &matches, sub_m).await
+            }
             (blobstore_upload::BLOBSTORE_UPLOAD, Some(sub_m)) => {
                 subcommand_blobstore_upload(fb, logger, &matches, sub_m).await
             }
@@ -193,6 +201,12 @@ fn main(fb: FacebookInit) -> ExitCode {
                 )
                 .await
             }
+            (subcommand_manifest_consistency::MANIFEST_CONSISTENCY, Some(sub_m)) => {
+                subcommand_manifest_consistency::subcommand_manifest_consistency(
+                    fb, logger, &matches, sub_m,
+                )
+                .await
+            }
             _ => Err(SubcommandError::InvalidArgs),
         }
     });
@@ -72,7 +72,7 @@ pub async fn subcommand_pushrebase<'a>(
     let (_, repo_config) = args::get_config(config_store, matches)?;
     let bookmark = BookmarkName::new(bookmark)?;
 
-    let pushrebase_flags = repo_config.pushrebase.flags;
+    let pushrebase_flags = repo_config.pushrebase.flags.clone();
     let pushrebase_hooks =
         bookmarks_movement::get_pushrebase_hooks(&ctx, &repo, &bookmark, &repo_config.pushrebase)
             .map_err(Error::from)?;
@@ -0,0 +1,239 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Cross-check of the files of a commit as per its hg manifest, its unodes and its fsnodes. All
+//! three are derived from the same bonsai changeset, so they must agree on which files there are,
+//! and on their contents and types. A mismatch means one of them was derived wrongly.
+
+use crate::error::SubcommandError;
+
+use anyhow::{anyhow, Error};
+use blobrepo::BlobRepo;
+use blobrepo_hg::BlobRepoHg;
+use blobstore::Loadable;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use cmdlib::{
+    args::{self, MononokeMatches},
+    helpers,
+};
+use context::CoreContext;
+use derived_data::BonsaiDerived;
+use fbinit::FacebookInit;
+use fsnodes::RootFsnodeId;
+use futures::{compat::Future01CompatExt, TryStreamExt};
+use manifest::{Entry, ManifestOps, PathOrPrefix};
+use mononoke_types::{ChangesetId, ContentId, FileType, MPath};
+use serde_derive::Serialize;
+use slog::{info, Logger};
+use std::collections::{BTreeMap, BTreeSet};
+use unodes::RootUnodeManifestId;
+
+pub const MANIFEST_CONSISTENCY: &str = "manifest-consistency";
+const ARG_CSID: &str = "csid";
+const ARG_PATH: &str = "path";
+
+const LOAD_CONCURRENCY: usize = 100;
+
+pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(MANIFEST_CONSISTENCY)
+        .about(
+            "check that the hg filenodes, unodes and fsnodes of a commit agree on the contents \
+             and types of its files, and print the mismatches as json, one per line",
+        )
+        .arg(
+            Arg::with_name(ARG_CSID)
+                .help("{hg|bonsai} changeset id or bookmark name")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(ARG_PATH)
+                .long(ARG_PATH)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("only check the files under this path, can be repeated"),
+        )
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct FileInfo {
+    content_id: ContentId,
+    file_type: FileType,
+}
+
+type Files = BTreeMap<MPath, FileInfo>;
+
+#[derive(Debug, Serialize)]
+struct Mismatch {
+    path: String,
+    /// What doesn't match: "presence", "content_id" or "file_type"
+    field: &'static str,
+    hg: Option<String>,
+    unode: Option<String>,
+    fsnode: Option<String>,
+}
+
+pub async fn subcommand_manifest_consistency<'a>(
+    fb: FacebookInit,
+    logger: Logger,
+    matches: &'a MononokeMatches<'_>,
+    sub_matches: &'a ArgMatches<'_>,
+) -> Result<(), SubcommandError> {
+    args::init_cachelib(fb, &matches);
+
+    let repo = args::open_repo(fb, &logger, &matches).await?;
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+
+    let hash_or_bookmark = String::from(sub_matches.value_of(ARG_CSID).unwrap());
+    let prefixes = match sub_matches.values_of(ARG_PATH) {
+        Some(paths) => paths
+            .map(|path| Ok(PathOrPrefix::Prefix(Some(MPath::new(path)?))))
+            .collect::<Result<Vec<_>, Error>>()?,
+        None => vec![PathOrPrefix::Prefix(None)],
+    };
+
+    let csid = helpers::csid_resolve(ctx.clone(), repo.clone(), hash_or_bookmark)
+        .compat()
+        .await?;
+
+    let (hg, unode, fsnode) = futures::try_join!(
+        hg_files(&ctx, &repo, csid, prefixes.clone()),
+        unode_files(&ctx, &repo, csid, prefixes.clone()),
+        fsnode_files(&ctx, &repo, csid, prefixes),
+    )?;
+
+    let mismatches = find_mismatches(&hg, &unode, &fsnode);
+    for mismatch in &mismatches {
+        println!("{}", serde_json::to_string(mismatch).map_err(Error::from)?);
+    }
+
+    info!(logger, "Found {} mismatches in {}", mismatches.len(), csid);
+    if !mismatches.is_empty() {
+        return Err(anyhow!("manifests of {} are inconsistent", csid).into());
+    }
+    Ok(())
+}
+
+fn leaf<T, L>((path, entry): (Option<MPath>, Entry<T, L>)) -> Option<(MPath, L)> {
+    match (path, entry) {
+        (Some(path), Entry::Leaf(leaf)) => Some((path, leaf)),
+        _ => None,
+    }
+}
+
+async fn hg_files(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    csid: ChangesetId,
+    prefixes: Vec<PathOrPrefix>,
+) -> Result<Files, Error> {
+    let hg_csid = repo.get_hg_from_bonsai_changeset(ctx.clone(), csid).await?;
+    let hg_changeset = hg_csid.load(ctx, repo.blobstore()).await?;
+
+    hg_changeset
+        .manifestid()
+        .find_entries(ctx.clone(), repo.get_blobstore(), prefixes)
+        .try_filter_map(|entry| async move { Ok(leaf(entry)) })
+        .map_ok(|(path, (file_type, filenode_id))| async move {
+            // The content id of the envelope is the one of the file without its copy metadata,
+            // which is what the other manifests refer to.
+            let envelope = filenode_id.load(ctx, repo.blobstore()).await?;
+            let info = FileInfo {
+                content_id: envelope.content_id(),
+                file_type,
+            };
+            Ok::<_, Error>((path, info))
+        })
+        .try_buffer_unordered(LOAD_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
+async fn unode_files(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    csid: ChangesetId,
+    prefixes: Vec<PathOrPrefix>,
+) -> Result<Files, Error> {
+    let root = RootUnodeManifestId::derive(ctx, repo, csid).await?;
+
+    root.manifest_unode_id()
+        .find_entries(ctx.clone(), repo.get_blobstore(), prefixes)
+        .try_filter_map(|entry| async move { Ok(leaf(entry)) })
+        .map_ok(|(path, unode_id)| async move {
+            let unode = unode_id.load(ctx, repo.blobstore()).await?;
+            let info = FileInfo {
+                content_id: *unode.content_id(),
+                file_type: *unode.file_type(),
+            };
+            Ok::<_, Error>((path, info))
+        })
+        .try_buffer_unordered(LOAD_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
+async fn fsnode_files(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    csid: ChangesetId,
+    prefixes: Vec<PathOrPrefix>,
+) -> Result<Files, Error> {
+    let root = RootFsnodeId::derive(ctx, repo, csid).await?;
+
+    root.fsnode_id()
+        .find_entries(ctx.clone(), repo.get_blobstore(), prefixes)
+        .try_filter_map(|entry| async move { Ok(leaf(entry)) })
+        .map_ok(|(path, file)| {
+            let info = FileInfo {
+                content_id: *file.content_id(),
+                file_type: *file.file_type(),
+            };
+            (path, info)
+        })
+        .try_collect()
+        .await
+}
+
+fn find_mismatches(hg: &Files, unode: &Files, fsnode: &Files) -> Vec<Mismatch> {
+    let paths: BTreeSet<_> = hg.keys().chain(unode.keys()).chain(fsnode.keys()).collect();
+
+    let mut mismatches = Vec::new();
+    for path in paths {
+        let infos = [hg.get(path), unode.get(path), fsnode.get(path)];
+        let mismatch = |field: &'static str, value: &dyn Fn(&FileInfo) -> String| {
+            let [in_hg, in_unode, in_fsnode] = infos;
+            Mismatch {
+                path: path.to_string(),
+                field,
+                hg: in_hg.map(value),
+                unode: in_unode.map(value),
+                fsnode: in_fsnode.map(value),
+            }
+        };
+
+        if infos.iter().any(Option::is_none) {
+            mismatches.push(mismatch("presence", &|_| "present".to_string()));
+            continue;
+        }
+        if !all_equal(&infos, |info| info.content_id) {
+            mismatches.push(mismatch("content_id", &|info| info.content_id.to_string()));
+        }
+        if !all_equal(&infos, |info| info.file_type) {
+            mismatches.push(mismatch("file_type", &|info| info.file_type.to_string()));
+        }
+    }
+    mismatches
+}
+
+fn all_equal<T: Eq>(infos: &[Option<&FileInfo>; 3], field: impl Fn(&FileInfo) -> T) -> bool {
+    let mut values = infos.iter().flatten().map(|info| field(info));
+    match values.next() {
+        Some(first) => values.all(|value| value == first),
+        None => true,
+    }
+}
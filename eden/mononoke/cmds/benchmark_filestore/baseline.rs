@@ -0,0 +1,378 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Comparison of the results with those of a previous run, as written by `--output-format json`,
+//! for the benchmark to be a performance gate: `--baseline <file>` fails if the throughput of an
+//! operation regressed by more than `--regression-threshold` percent.
+//!
+//! Operations are compared by their type, size and phase, and by the parameters of their run,
+//! averaging the throughput of those that succeeded. Operations that are only in one of the runs
+//! are reported, but don't fail the comparison, unless none of the operations of the baseline
+//! were run, as nothing would then be compared. Neither do the stats of the runs, which are
+//! compared for the report, as a change in e.g. the retry amplification explains a change in the
+//! throughput rather than being one.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+
+use anyhow::{bail, Context, Error};
+use serde::Deserialize;
+
+use crate::cache_phases::ReadPhase;
+use crate::output::{Operation, RunRecord, StatRecord};
+
+/// A line of the json output.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Line {
+    Operation(RunRecord),
+    Stat(StatRecord),
+}
+
+/// A stat, and the parameters of its run.
+type StatKey<'a> = (&'a str, &'a str, &'a str, u64, usize, &'a str);
+
+fn stat_key(record: &StatRecord) -> StatKey<'_> {
+    (
+        &record.backend,
+        &record.cache,
+        &record.put_behaviour,
+        record.chunk_size,
+        record.concurrency,
+        &record.stat,
+    )
+}
+
+fn stat_values(records: &[StatRecord]) -> BTreeMap<StatKey<'_>, f64> {
+    records
+        .iter()
+        .map(|record| (stat_key(record), record.value))
+        .collect()
+}
+
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct Key {
+    backend: String,
+    cache: String,
+    put_behaviour: String,
+    chunk_size: u64,
+    concurrency: usize,
+    operation: Operation,
+    bytes: u64,
+    phase: Option<ReadPhase>,
+}
+
+impl Key {
+    fn new(record: &RunRecord) -> Self {
+        Self {
+            backend: record.backend.clone(),
+            cache: record.cache.clone(),
+            put_behaviour: record.put_behaviour.clone(),
+            chunk_size: record.chunk_size,
+            concurrency: record.concurrency,
+            operation: record.operation.operation(),
+            bytes: record.operation.bytes(),
+            phase: record.operation.phase(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut description = format!(
+            "{} {} B {}/{}/{} chunk_size={} concurrency={}",
+            self.operation,
+            self.bytes,
+            self.backend,
+            self.cache,
+            self.put_behaviour,
+            self.chunk_size,
+            self.concurrency
+        );
+        if let Some(phase) = self.phase {
+            description.push_str(&format!(" {:?}", phase).to_lowercase());
+        }
+        description
+    }
+}
+
+/// The throughput of the operations of each key, summed up as they are recorded rather than
+/// kept, so that long runs don't grow with every operation.
+#[derive(Default)]
+pub struct Throughputs {
+    /// The sum of the throughputs in MB/s of the operations that succeeded, and how many they
+    /// are.
+    sums: BTreeMap<Key, (f64, usize)>,
+}
+
+impl Throughputs {
+    pub fn add(&mut self, record: &RunRecord) {
+        let entry = self.sums.entry(Key::new(record)).or_default();
+        if let Some(throughput) = record.operation.throughput_mb_per_s() {
+            entry.0 += throughput;
+            entry.1 += 1;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sums.is_empty()
+    }
+
+    /// Mean throughput in MB/s of the operations of each key that succeeded. `None` if they all
+    /// failed.
+    fn means(&self) -> BTreeMap<&Key, Option<f64>> {
+        self.sums
+            .iter()
+            .map(|(key, (sum, count))| {
+                let mean = if *count == 0 {
+                    None
+                } else {
+                    Some(sum / *count as f64)
+                };
+                (key, mean)
+            })
+            .collect()
+    }
+}
+
+pub struct Baseline {
+    path: String,
+    throughputs: Throughputs,
+    stats: Vec<StatRecord>,
+}
+
+impl Baseline {
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+        let mut throughputs = Throughputs::default();
+        let mut stats = Vec::new();
+        for (idx, line) in contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+        {
+            let line: Line = serde_json::from_str(line)
+                .with_context(|| format!("Invalid record on line {} of {}", idx + 1, path))?;
+            match line {
+                Line::Operation(record) => throughputs.add(&record),
+                Line::Stat(record) => stats.push(record),
+            }
+        }
+        if throughputs.is_empty() {
+            bail!("Baseline {} has no records", path);
+        }
+        Ok(Self {
+            path: path.to_string(),
+            throughputs,
+            stats,
+        })
+    }
+
+    /// Print how the throughput of the operations of `current`, and the stats of its runs,
+    /// compare to the baseline to `out`, and fail if any of the operations regressed by more than
+    /// `threshold` percent.
+    pub fn compare(
+        &self,
+        current: &Throughputs,
+        current_stats: &[StatRecord],
+        threshold: f64,
+        out: &mut dyn Write,
+    ) -> Result<(), Error> {
+        let baseline = self.throughputs.means();
+        let current = current.means();
+
+        writeln!(out, "Compared to {}:", self.path)?;
+        writeln!(
+            out,
+            "{:<80} {:>14} {:>14} {:>10}",
+            "operation", "baseline MB/s", "current MB/s", "delta"
+        )?;
+
+        let format_throughput =
+            |value: Option<f64>| value.map_or_else(|| "-".to_string(), |v| format!("{:.2}", v));
+
+        let mut regressions = 0;
+        let mut compared = 0;
+        for (key, before) in &baseline {
+            let after = match current.get(key) {
+                Some(after) => {
+                    compared += 1;
+                    *after
+                }
+                None => {
+                    writeln!(
+                        out,
+                        "{:<80} {:>14} {:>14} {:>10}",
+                        key.describe(),
+                        format_throughput(*before),
+                        "not run",
+                        "-"
+                    )?;
+                    continue;
+                }
+            };
+            let (delta, regressed) = match (before, after) {
+                (Some(before), Some(after)) if *before > 0.0 => {
+                    let delta = (after - before) * 100.0 / before;
+                    (format!("{:+.1}%", delta), delta < -threshold)
+                }
+                // Everything failing is as bad a regression as it gets.
+                (Some(_), None) => ("FAILED".to_string(), true),
+                _ => ("-".to_string(), false),
+            };
+            if regressed {
+                regressions += 1;
+            }
+            writeln!(
+                out,
+                "{:<80} {:>14} {:>14} {:>10}{}",
+                key.describe(),
+                format_throughput(*before),
+                format_throughput(after),
+                delta,
+                if regressed { " REGRESSED" } else { "" }
+            )?;
+        }
+        for (key, after) in &current {
+            if !baseline.contains_key(key) {
+                writeln!(
+                    out,
+                    "{:<80} {:>14} {:>14} {:>10}",
+                    key.describe(),
+                    "not run",
+                    format_throughput(*after),
+                    "-"
+                )?;
+            }
+        }
+
+        self.compare_stats(current_stats, out)?;
+
+        if compared == 0 {
+            bail!(
+                "None of the operations of {} were run, so nothing was compared",
+                self.path
+            );
+        }
+        if regressions > 0 {
+            bail!(
+                "{} operations regressed by more than {}% compared to {}",
+                regressions,
+                threshold,
+                self.path
+            );
+        }
+        Ok(())
+    }
+
+    fn compare_stats(&self, current: &[StatRecord], out: &mut dyn Write) -> Result<(), Error> {
+        let baseline = stat_values(&self.stats);
+        let current = stat_values(current);
+        let mut keys: Vec<_> = baseline.keys().chain(current.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let format_value = |value: Option<&f64>| {
+            value.map_or_else(|| "not run".to_string(), |v| format!("{:.2}", v))
+        };
+
+        writeln!(out, "{:<80} {:>14} {:>14}", "stat", "baseline", "current")?;
+        for key in keys {
+            let (backend, cache, put_behaviour, chunk_size, concurrency, stat) = key;
+            writeln!(
+                out,
+                "{:<80} {:>14} {:>14}",
+                format!(
+                    "{} {}/{}/{} chunk_size={} concurrency={}",
+                    stat, backend, cache, put_behaviour, chunk_size, concurrency
+                ),
+                format_value(baseline.get(key)),
+                format_value(current.get(key)),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::json;
+
+    fn record(backend: &str, throughput_mb_per_s: Option<f64>) -> RunRecord {
+        serde_json::from_value(json!({
+            "backend": backend,
+            "cache": "none",
+            "put_behaviour": "IfAbsent",
+            "chunk_size": 1048576,
+            "concurrency": 1,
+            "operation": "write",
+            "bytes": 10485760,
+            "latency_ms": 10.0,
+            "throughput_mb_per_s": throughput_mb_per_s,
+        }))
+        .expect("the record is valid")
+    }
+
+    fn throughputs(records: &[RunRecord]) -> Throughputs {
+        let mut throughputs = Throughputs::default();
+        for record in records {
+            throughputs.add(record);
+        }
+        throughputs
+    }
+
+    fn baseline(records: &[RunRecord]) -> Baseline {
+        Baseline {
+            path: "baseline.json".to_string(),
+            throughputs: throughputs(records),
+            stats: Vec::new(),
+        }
+    }
+
+    fn compare(baseline: &Baseline, current: &[RunRecord]) -> Result<(), Error> {
+        baseline.compare(&throughputs(current), &[], 10.0, &mut Vec::new())
+    }
+
+    #[test]
+    fn test_within_threshold() -> Result<(), Error> {
+        let baseline = baseline(&[record("memory", Some(100.0)), record("memory", Some(80.0))]);
+        compare(&baseline, &[record("memory", Some(85.0))])?;
+        // Faster is never a regression.
+        compare(&baseline, &[record("memory", Some(200.0))])?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_regressed() {
+        let baseline = baseline(&[record("memory", Some(100.0))]);
+        assert!(compare(&baseline, &[record("memory", Some(50.0))]).is_err());
+    }
+
+    #[test]
+    fn test_failed() {
+        let baseline = baseline(&[record("memory", Some(100.0))]);
+        assert!(compare(&baseline, &[record("memory", None)]).is_err());
+    }
+
+    #[test]
+    fn test_not_matched() -> Result<(), Error> {
+        let baseline = baseline(&[record("memory", Some(100.0))]);
+        // Nothing of the baseline was run.
+        assert!(compare(&baseline, &[record("fileblob", Some(100.0))]).is_err());
+        // Only some of it was, which is compared.
+        compare(
+            &baseline,
+            &[record("memory", Some(100.0)), record("fileblob", Some(1.0))],
+        )?;
+        Ok(())
+    }
+}
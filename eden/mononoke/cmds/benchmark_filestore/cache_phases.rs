@@ -0,0 +1,141 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! With caches in front of the backend, the first read of the contents and the ones after it
+//! measure different things: the first one is cold, i.e. as fast as the caches can find out they
+//! don't have the contents and get them from the backend, and the others are warm, i.e. as fast
+//! as the caches can serve them, if they kept them. They are reported apart, along with how many
+//! of their gets the caches served, which is found out by counting the gets of the backend.
+//!
+//! Writes go through the caches, so they may have the contents before the first read. With
+//! `--drop-caches`, the contents are written straight to the backend instead, so the caches
+//! have never seen them.
+
+use std::sync::{Arc, Mutex};
+
+use blobstore::Blobstore;
+use serde::{Deserialize, Serialize};
+
+use crate::latency::{Latencies, LatencyHistogram};
+use crate::output::OperationRecord;
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+    Deserialize
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadPhase {
+    Cold,
+    Warm,
+}
+
+/// The backend under the caches.
+#[derive(Clone)]
+pub struct Uncached {
+    /// Records the operations that reach the backend.
+    pub blob: Arc<dyn Blobstore>,
+    pub latencies: Arc<Mutex<Latencies>>,
+}
+
+fn gets(latencies: &Mutex<Latencies>) -> u64 {
+    latencies.lock().expect("lock poisoned").get.samples()
+}
+
+#[derive(Default)]
+struct PhaseStats {
+    latencies: LatencyHistogram,
+    throughputs: Vec<f64>,
+    failed: usize,
+    gets: u64,
+    backend_gets: u64,
+}
+
+impl PhaseStats {
+    fn log(&self, phase: &str) {
+        let reads = self.throughputs.len() + self.failed;
+        if reads == 0 {
+            return;
+        }
+        let mean = if self.throughputs.is_empty() {
+            "-".to_string()
+        } else {
+            format!(
+                "{:.2}",
+                self.throughputs.iter().sum::<f64>() / self.throughputs.len() as f64
+            )
+        };
+        let hits = self.gets.saturating_sub(self.backend_gets);
+        let hit_rate = if self.gets == 0 {
+            "-".to_string()
+        } else {
+            format!("{:.1}%", hits as f64 * 100.0 / self.gets as f64)
+        };
+        eprintln!(
+            "{} reads: {} ({} failed), {} MB/s, cache hit rate {} ({}/{} gets), latency {}",
+            phase, reads, self.failed, mean, hit_rate, hits, self.gets, self.latencies
+        );
+    }
+}
+
+/// The stats of the reads of a run, by phase.
+pub struct CachePhases {
+    uncached: Uncached,
+    /// Of the blobstore that the reads are made to, through the caches.
+    latencies: Arc<Mutex<Latencies>>,
+    /// The gets since the start of the current read, through the caches and of the backend.
+    started: (u64, u64),
+    cold: PhaseStats,
+    warm: PhaseStats,
+}
+
+impl CachePhases {
+    pub fn new(uncached: Uncached, latencies: Arc<Mutex<Latencies>>) -> Self {
+        Self {
+            uncached,
+            latencies,
+            started: (0, 0),
+            cold: PhaseStats::default(),
+            warm: PhaseStats::default(),
+        }
+    }
+
+    /// Call before each read.
+    pub fn start_read(&mut self) {
+        self.started = (gets(&self.latencies), gets(&self.uncached.latencies));
+    }
+
+    /// Call after each read, with its record. Returns it, tagged with its phase.
+    pub fn finish_read(&mut self, phase: ReadPhase, record: OperationRecord) -> OperationRecord {
+        let stats = match phase {
+            ReadPhase::Cold => &mut self.cold,
+            ReadPhase::Warm => &mut self.warm,
+        };
+        stats.gets += gets(&self.latencies) - self.started.0;
+        stats.backend_gets += gets(&self.uncached.latencies) - self.started.1;
+        match record.throughput_mb_per_s() {
+            Some(throughput) => {
+                stats.latencies.record(record.latency());
+                stats.throughputs.push(throughput);
+            }
+            None => stats.failed += 1,
+        }
+        record.with_phase(phase)
+    }
+
+    pub fn log(&self) {
+        self.cold.log("Cold");
+        self.warm.log("Warm");
+    }
+}
@@ -0,0 +1,273 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A slow or flaky backend, for how chunked writes and reads hold up when the blobstore isn't
+//! as fast and reliable as the ones at hand: `--inject-latency-ms` delays every operation of the
+//! backend, and `--inject-error-rate` fails that share of them with a transient error.
+//!
+//! With `--retry-attempts`, failed operations are retried, as `RetryBlobstore` does in
+//! production. Each retry is another operation on the backend, so the backend sees more
+//! operations than the filestore makes: that is the retry amplification, which is one of the
+//! stats of the run, along with how many operations failed in spite of the retries.
+
+use std::fmt;
+use std::io;
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
+use context::CoreContext;
+use mononoke_types::BlobstoreBytes;
+use rand::Rng;
+use retryblob::{BackendType, RetryBlobstore, RetryOptions};
+
+use crate::run_stats::{ReportStats, Stat};
+
+#[derive(Clone, Copy, Debug)]
+pub struct FaultOptions {
+    /// Added to every operation of the backend.
+    pub latency: Option<Duration>,
+    /// The odds of an operation of the backend failing, between 0 and 1.
+    pub error_rate: f64,
+    /// How many times an operation is attempted before its error is returned.
+    pub retry_attempts: NonZeroU32,
+}
+
+impl FaultOptions {
+    pub fn new(
+        latency: Option<Duration>,
+        error_rate: f64,
+        retry_attempts: NonZeroU32,
+    ) -> Result<Self> {
+        if !(0.0..=1.0).contains(&error_rate) {
+            bail!("Error rate must be between 0 and 1, not {}", error_rate);
+        }
+        Ok(Self {
+            latency,
+            error_rate,
+            retry_attempts,
+        })
+    }
+
+    pub fn has_faults(&self) -> bool {
+        self.latency.is_some() || self.error_rate > 0.0 || self.retry_attempts.get() > 1
+    }
+}
+
+#[derive(Default)]
+struct FaultStats {
+    /// Operations made by the filestore.
+    requests: AtomicU64,
+    /// Of those, the ones that failed, once retried.
+    failed: AtomicU64,
+    /// Operations made to the backend, including the retries.
+    attempts: AtomicU64,
+    /// Of those, the ones that were failed on purpose.
+    injected: AtomicU64,
+}
+
+impl FaultStats {
+    fn count<T>(&self, res: &Result<T>) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if res.is_err() {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl ReportStats for FaultStats {
+    fn report(&self) -> Vec<Stat> {
+        let requests = self.requests.load(Ordering::Relaxed);
+        if requests == 0 {
+            return Vec::new();
+        }
+        let attempts = self.attempts.load(Ordering::Relaxed);
+        vec![
+            Stat::new("faults.requests", requests as f64),
+            Stat::new("faults.attempts", attempts as f64),
+            Stat::new("faults.amplification", attempts as f64 / requests as f64),
+            Stat::new(
+                "faults.injected",
+                self.injected.load(Ordering::Relaxed) as f64,
+            ),
+            Stat::new("faults.failed", self.failed.load(Ordering::Relaxed) as f64),
+        ]
+    }
+}
+
+/// Where the faults are injected, under the retries.
+struct Injector {
+    blobstore: Arc<dyn Blobstore>,
+    options: FaultOptions,
+    stats: Arc<FaultStats>,
+}
+
+impl fmt::Display for Injector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Injector<{}>", &self.blobstore)
+    }
+}
+
+impl fmt::Debug for Injector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Injector")
+            .field("blobstore", &self.blobstore)
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+impl Injector {
+    async fn inject(&self, op: &str, key: &str) -> Result<()> {
+        self.stats.attempts.fetch_add(1, Ordering::Relaxed);
+        if let Some(latency) = self.options.latency {
+            tokio_shim::time::sleep(latency).await;
+        }
+        if self.options.error_rate > 0.0 && rand::thread_rng().gen_bool(self.options.error_rate) {
+            self.stats.injected.fetch_add(1, Ordering::Relaxed);
+            // Timeouts are transient for every backend, so that these are retried.
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("Injected failure in {} for key {}", op, key),
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Blobstore for Injector {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        self.inject("get", key).await?;
+        self.blobstore.get(ctx, key).await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        self.inject("put", &key).await?;
+        self.blobstore.put(ctx, key, value).await
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        self.inject("is_present", key).await?;
+        self.blobstore.is_present(ctx, key).await
+    }
+}
+
+// The put behaviour is the one of the backend, which it was created with.
+#[async_trait]
+impl BlobstorePutOps for Injector {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        _put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        self.put(ctx, key, value).await?;
+        Ok(OverwriteStatus::NotChecked)
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        self.put(ctx, key, value).await?;
+        Ok(OverwriteStatus::NotChecked)
+    }
+}
+
+/// A backend with faults injected, and the operations on it retried. How many operations were
+/// retried and failed are in its `stats`.
+pub struct FaultyBlob {
+    blobstore: RetryBlobstore<Injector>,
+    stats: Arc<FaultStats>,
+}
+
+impl FaultyBlob {
+    pub fn new(blobstore: Arc<dyn Blobstore>, options: FaultOptions) -> Self {
+        let stats = Arc::new(FaultStats::default());
+        let injector = Injector {
+            blobstore,
+            options,
+            stats: stats.clone(),
+        };
+        let retry_options = RetryOptions {
+            max_attempts: options.retry_attempts,
+            // The retries of the whole run share a context, so its budget would run out.
+            budget_per_context: i64::MAX,
+            ..RetryOptions::default()
+        };
+        Self {
+            blobstore: RetryBlobstore::new(injector, BackendType::Files, retry_options),
+            stats,
+        }
+    }
+
+    pub fn stats(&self) -> Arc<dyn ReportStats> {
+        self.stats.clone()
+    }
+}
+
+impl fmt::Display for FaultyBlob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FaultyBlob<{}>", &self.blobstore)
+    }
+}
+
+impl fmt::Debug for FaultyBlob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaultyBlob")
+            .field("blobstore", &self.blobstore)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Blobstore for FaultyBlob {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let res = self.blobstore.get(ctx, key).await;
+        self.stats.count(&res);
+        res
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        let res = self.blobstore.put(ctx, key, value).await;
+        self.stats.count(&res);
+        res
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        let res = self.blobstore.is_present(ctx, key).await;
+        self.stats.count(&res);
+        res
+    }
+}
@@ -0,0 +1,180 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Ingestion of many files at once, e.g. of all the files in a directory, so that the blobstore
+//! is exercised by many concurrent stores rather than by the chunks of a single one.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Error};
+use blobstore::Blobstore;
+use context::CoreContext;
+use filestore::{self, FilestoreConfig, StoreRequest};
+use futures::stream::{self, TryStreamExt};
+use futures_stats::TimedFutureExt;
+
+use crate::latency::{Latencies, LatencyBlob};
+use crate::output::{Operation, OperationRecord};
+use crate::{open_input, throughput_mb_per_s, BenchmarkOptions};
+
+/// The files to ingest: those listed in `input`, one per line, if it is a manifest, or those
+/// under it if it is a directory. `None` if `input` is a single file to benchmark on its own.
+pub fn list_inputs(input: &str, manifest: bool) -> Result<Option<Vec<PathBuf>>, Error> {
+    let inputs = if manifest {
+        let contents =
+            fs::read_to_string(input).with_context(|| format!("Failed to read {}", input))?;
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(PathBuf::from)
+            .collect()
+    } else if Path::new(input).is_dir() {
+        let mut inputs = Vec::new();
+        list_files(Path::new(input), &mut inputs)?;
+        inputs.sort();
+        inputs
+    } else {
+        return Ok(None);
+    };
+
+    if inputs.is_empty() {
+        bail!("No files to ingest in {}", input);
+    }
+    Ok(Some(inputs))
+}
+
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to list {:?}", dir))? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), files)?;
+        } else {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+pub struct IngestResult {
+    files: usize,
+    failed: usize,
+    bytes: u64,
+    elapsed: Duration,
+    /// Every store, for the json output.
+    pub operations: Vec<OperationRecord>,
+    pub latencies: Latencies,
+}
+
+impl IngestResult {
+    /// Print the aggregate throughput of the ingestion to `out`.
+    pub fn print(&self, out: &mut dyn Write) -> io::Result<()> {
+        let secs = self.elapsed.as_secs_f64();
+        writeln!(
+            out,
+            "Ingested {} files ({} failed), {} B in {:?}: {:.2} MB/s, {:.2} files/s",
+            self.files,
+            self.failed,
+            self.bytes,
+            self.elapsed,
+            self.bytes as f64 / secs / (2_u128.pow(20) as f64),
+            (self.files - self.failed) as f64 / secs,
+        )?;
+        writeln!(out, "Latency of stores: {}", self.latencies.write)
+    }
+}
+
+async fn ingest_one(
+    ctx: &CoreContext,
+    blob: &Arc<dyn Blobstore>,
+    config: FilestoreConfig,
+    options: &BenchmarkOptions,
+    path: &Path,
+) -> Result<OperationRecord, Error> {
+    let (len, data) = open_input(path, options)
+        .await
+        .with_context(|| format!("Failed to open {:?}", path))?;
+
+    let req = StoreRequest::new(len);
+    let (stats, res) = filestore::store(blob, config, ctx, &req, data)
+        .timed()
+        .await;
+    let throughput = match res {
+        Ok(_) => Some(throughput_mb_per_s(&stats, len)),
+        Err(e) => {
+            eprintln!("Store of {:?} failed: {:?}", path, e);
+            None
+        }
+    };
+    Ok(OperationRecord::new(
+        Operation::Write,
+        &stats,
+        len,
+        throughput,
+    ))
+}
+
+/// Store all of `inputs`, up to `concurrency` of them at once.
+pub async fn run_ingestion(
+    ctx: &CoreContext,
+    options: &BenchmarkOptions,
+    inputs: Vec<PathBuf>,
+    concurrency: usize,
+    blob: Arc<dyn Blobstore>,
+) -> Result<IngestResult, Error> {
+    let config = FilestoreConfig {
+        chunk_size: Some(options.chunk_size),
+        concurrency: options.concurrency,
+        inline_threshold: None,
+    };
+
+    eprintln!(
+        "Ingest {} files with {:?}, {} at once, writing into {:?}",
+        inputs.len(),
+        config,
+        concurrency,
+        blob
+    );
+
+    let latencies = Arc::new(Mutex::new(Latencies::default()));
+    let blob: Arc<dyn Blobstore> = Arc::new(LatencyBlob::new(blob, latencies.clone()));
+
+    let start = Instant::now();
+    let operations: Vec<OperationRecord> = stream::iter(inputs.iter().map(Ok))
+        .map_ok(|path| ingest_one(ctx, &blob, config, options, path))
+        .try_buffer_unordered(concurrency)
+        .try_collect()
+        .await?;
+    let elapsed = start.elapsed();
+
+    let mut latencies = latencies.lock().expect("lock poisoned").clone();
+    let mut failed = 0;
+    let mut bytes = 0;
+    for operation in &operations {
+        match operation.throughput_mb_per_s() {
+            Some(_) => {
+                latencies.write.record(operation.latency());
+                bytes += operation.bytes();
+            }
+            None => failed += 1,
+        }
+    }
+    latencies.log();
+
+    Ok(IngestResult {
+        files: operations.len(),
+        failed,
+        bytes,
+        elapsed,
+        operations,
+        latencies,
+    })
+}
@@ -0,0 +1,223 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Latency distributions, kept in HDR histograms so that the tail is as precise as the median.
+//! The latencies of a run are of its requests, i.e. of each store and fetch as a whole, and of
+//! the gets and puts of the blobstore that they make, which are mostly of chunks.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Error, Result};
+use async_trait::async_trait;
+use blobstore::{Blobstore, BlobstoreGetData};
+use context::CoreContext;
+use futures_stats::TimedFutureExt;
+use hdrhistogram::Histogram;
+use mononoke_types::BlobstoreBytes;
+
+pub const PERCENTILES: &[f64] = &[50.0, 90.0, 99.0, 99.9];
+
+/// A distribution of latencies, in microseconds.
+#[derive(Clone)]
+pub struct LatencyHistogram(Histogram<u64>);
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        // The histogram resizes itself to fit the highest latency.
+        Self(Histogram::new(3).expect("3 significant figures are supported"))
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        self.0.saturating_record(latency.as_micros() as u64);
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.0.len()
+    }
+
+    /// Add the latencies recorded by `other`.
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        self.0
+            .add(&other.0)
+            .expect("the histogram resizes itself to fit");
+    }
+
+    /// The latency that `percentile` percent of the operations are within, in ms. `None` if
+    /// nothing was recorded.
+    pub fn percentile_ms(&self, percentile: f64) -> Option<f64> {
+        if self.0.is_empty() {
+            return None;
+        }
+        Some(self.0.value_at_quantile(percentile / 100.0) as f64 / 1000.0)
+    }
+
+    pub fn mean_ms(&self) -> Option<f64> {
+        if self.0.is_empty() {
+            return None;
+        }
+        Some(self.0.mean() / 1000.0)
+    }
+
+    pub fn max_ms(&self) -> Option<f64> {
+        self.percentile_ms(100.0)
+    }
+
+    /// Write every recorded latency with how many times it was recorded, one per line.
+    fn dump(&self, name: &str, out: &mut dyn Write) -> io::Result<()> {
+        for value in self.0.iter_recorded() {
+            writeln!(
+                out,
+                "{} {} {}",
+                name,
+                value.value_iterated_to(),
+                value.count_at_value()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// e.g. `p50=1.23ms p90=2.34ms p99=5.67ms p99.9=9.87ms max=12.34ms (1000 samples)`
+impl fmt::Display for LatencyHistogram {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(fmt, "no samples");
+        }
+        for percentile in PERCENTILES {
+            if let Some(latency) = self.percentile_ms(*percentile) {
+                write!(fmt, "p{}={:.2}ms ", percentile, latency)?;
+            }
+        }
+        if let Some(max) = self.max_ms() {
+            write!(fmt, "max={:.2}ms ", max)?;
+        }
+        write!(fmt, "({} samples)", self.samples())
+    }
+}
+
+impl fmt::Debug for LatencyHistogram {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "LatencyHistogram({})", self)
+    }
+}
+
+/// The latencies of the operations of a run.
+#[derive(Clone, Debug, Default)]
+pub struct Latencies {
+    /// Stores, as a whole.
+    pub write: LatencyHistogram,
+    /// Fetches, as a whole.
+    pub read: LatencyHistogram,
+    /// Fetches of ranges, of any size.
+    pub range_read: LatencyHistogram,
+    /// Blobstore puts.
+    pub put: LatencyHistogram,
+    /// Blobstore gets.
+    pub get: LatencyHistogram,
+}
+
+impl Latencies {
+    fn histograms(&self) -> [(&'static str, &LatencyHistogram); 5] {
+        [
+            ("write", &self.write),
+            ("read", &self.read),
+            ("range_read", &self.range_read),
+            ("put", &self.put),
+            ("get", &self.get),
+        ]
+    }
+
+    pub fn log(&self) {
+        for (name, histogram) in self.histograms().iter() {
+            eprintln!("Latency of {}: {}", name, histogram);
+        }
+    }
+
+    /// Write the raw histograms to `path`, as lines of `<histogram> <latency in us> <count>`.
+    pub fn dump(&self, path: &str) -> Result<(), Error> {
+        let file = File::create(path).with_context(|| format!("Failed to create {}", path))?;
+        let mut out = BufWriter::new(file);
+        for (name, histogram) in self.histograms().iter() {
+            histogram.dump(name, &mut out)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Records how long the gets and puts of a blobstore take.
+pub struct LatencyBlob {
+    blobstore: Arc<dyn Blobstore>,
+    latencies: Arc<Mutex<Latencies>>,
+}
+
+impl LatencyBlob {
+    pub fn new(blobstore: Arc<dyn Blobstore>, latencies: Arc<Mutex<Latencies>>) -> Self {
+        Self {
+            blobstore,
+            latencies,
+        }
+    }
+
+    fn record(
+        &self,
+        histogram: impl FnOnce(&mut Latencies) -> &mut LatencyHistogram,
+        latency: Duration,
+    ) {
+        histogram(&mut self.latencies.lock().expect("lock poisoned")).record(latency);
+    }
+}
+
+impl fmt::Display for LatencyBlob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LatencyBlob<{}>", &self.blobstore)
+    }
+}
+
+impl fmt::Debug for LatencyBlob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LatencyBlob")
+            .field("blobstore", &self.blobstore)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Blobstore for LatencyBlob {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let (stats, res) = self.blobstore.get(ctx, key).timed().await;
+        self.record(|latencies| &mut latencies.get, stats.completion_time);
+        res
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        let (stats, res) = self.blobstore.put(ctx, key, value).timed().await;
+        self.record(|latencies| &mut latencies.put, stats.completion_time);
+        res
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        let (stats, res) = self.blobstore.is_present(ctx, key).timed().await;
+        self.record(|latencies| &mut latencies.get, stats.completion_time);
+        res
+    }
+}
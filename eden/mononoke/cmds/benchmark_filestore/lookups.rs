@@ -0,0 +1,140 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Lookups of the contents by their aliases, and of their metadata. A lookup by alias gets the
+//! alias blob (e.g. `alias.sha1.<hash>`) to find out the content id, and a metadata lookup gets
+//! the metadata blob, so they exercise small blobs and keys that the reads of the contents
+//! don't.
+//!
+//! With `--lookup-qps`, the lookups of each kind are paced to that rate, for their latency to be
+//! that of a steady load rather than of back to back requests.
+
+use std::fmt;
+use std::num::NonZeroU32;
+use std::time::{Duration, Instant};
+
+use anyhow::{format_err, Error};
+use blobstore::{Blobstore, Loadable};
+use context::CoreContext;
+use filestore::{self, Alias, FetchKey};
+use futures_stats::TimedFutureExt;
+use mononoke_types::ContentMetadata;
+
+use crate::latency::LatencyHistogram;
+use crate::output::{Operation, OperationRecord};
+use crate::throughput_mb_per_s;
+
+#[derive(Clone, Copy, Debug)]
+enum Lookup {
+    Sha1,
+    Sha256,
+    GitSha1,
+    Metadata,
+}
+
+const LOOKUPS: &[Lookup] = &[
+    Lookup::Sha1,
+    Lookup::Sha256,
+    Lookup::GitSha1,
+    Lookup::Metadata,
+];
+
+impl Lookup {
+    fn operation(self) -> Operation {
+        match self {
+            Self::Sha1 => Operation::Sha1Lookup,
+            Self::Sha256 => Operation::Sha256Lookup,
+            Self::GitSha1 => Operation::GitSha1Lookup,
+            Self::Metadata => Operation::MetadataLookup,
+        }
+    }
+}
+
+impl fmt::Display for Lookup {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{}", self.operation())
+    }
+}
+
+async fn lookup<B: Blobstore>(
+    blob: &B,
+    ctx: &CoreContext,
+    metadata: &ContentMetadata,
+    lookup: Lookup,
+) -> Result<(), Error> {
+    let alias = match lookup {
+        Lookup::Sha1 => Alias::Sha1(metadata.sha1),
+        Lookup::Sha256 => Alias::Sha256(metadata.sha256),
+        Lookup::GitSha1 => Alias::GitSha1(metadata.git_sha1.sha1()),
+        Lookup::Metadata => {
+            let key = FetchKey::Canonical(metadata.content_id);
+            return match filestore::get_metadata(blob, ctx, &key).await? {
+                Some(_) => Ok(()),
+                None => Err(format_err!("No metadata for {:?}", key)),
+            };
+        }
+    };
+    let content_id = FetchKey::Aliased(alias).load(ctx, blob).await?;
+    if content_id != metadata.content_id {
+        return Err(format_err!(
+            "{:?} resolved to {:?}, not {:?}",
+            alias,
+            content_id,
+            metadata.content_id
+        ));
+    }
+    Ok(())
+}
+
+/// Look up the contents by each of their aliases, and their metadata, `count` times each, at up
+/// to `qps` lookups per second.
+pub async fn run_lookups<B: Blobstore>(
+    blob: &B,
+    ctx: &CoreContext,
+    metadata: &ContentMetadata,
+    count: usize,
+    qps: Option<NonZeroU32>,
+) -> Vec<OperationRecord> {
+    let period = qps.map(|qps| Duration::from_secs(1) / qps.get());
+    let mut operations = Vec::with_capacity(count * LOOKUPS.len());
+
+    for kind in LOOKUPS {
+        eprintln!("Lookups: {} of {}", count, kind);
+        let mut histogram = LatencyHistogram::default();
+        let mut failed = 0;
+        let start = Instant::now();
+        for c in 0..count {
+            if let Some(period) = period {
+                let due = start + period * c as u32;
+                tokio_shim::time::sleep(due.saturating_duration_since(Instant::now())).await;
+            }
+            let (stats, res) = lookup(blob, ctx, metadata, *kind).timed().await;
+            // There is nothing to fetch, so the throughput is only there to tell whether the
+            // lookup succeeded.
+            let throughput = match res {
+                Ok(()) => {
+                    histogram.record(stats.completion_time);
+                    Some(throughput_mb_per_s(&stats, 0))
+                }
+                Err(e) => {
+                    eprintln!("Lookup of {} failed: {:?}", kind, e);
+                    failed += 1;
+                    None
+                }
+            };
+            operations.push(OperationRecord::new(
+                kind.operation(),
+                &stats,
+                0,
+                throughput,
+            ));
+        }
+        eprintln!("Lookups of {}: {} ({} failed)", kind, histogram, failed);
+    }
+
+    operations
+}
@@ -0,0 +1,1879 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use anyhow::{bail, format_err, Error};
+use blobstore::{Blobstore, BlobstorePutOps, PutBehaviour, DEFAULT_PUT_BEHAVIOUR};
+use blobstore_factory::{make_blobstore_put_ops, BlobstoreOptions};
+use blobstore_sync_queue::{SqlBlobstoreSyncQueue, SqlConstruct};
+use bytes::{Bytes, BytesMut};
+use cacheblob::new_memcache_blobstore_no_lease;
+use cached_config::ConfigStore;
+use clap::{Arg, ArgMatches, SubCommand};
+use cmdlib::args::{self, MononokeMatches};
+use context::CoreContext;
+use fbinit::FacebookInit;
+use fileblob::{Fileblob, FileblobOptions};
+use filestore::{self, FetchKey, FilestoreConfig, StoreRequest};
+use futures::{
+    channel::mpsc,
+    compat::Future01CompatExt,
+    future,
+    stream::{self, Stream, StreamExt, TryStreamExt},
+};
+use futures_stats::{FutureStats, TimedFutureExt};
+use metaconfig_types::{BlobstoreId, MultiplexId};
+use mononoke_types::{ContentMetadata, MononokeId};
+use multiplexedblob::MultiplexedBlobstore;
+use rand::Rng;
+use scuba_ext::MononokeScubaSampleBuilder;
+use slog::Logger;
+use sql_ext::facebook::{MysqlConnectionType, ReadConnectionType};
+use sqlblob::Sqlblob;
+use std::fmt::Debug;
+use std::io::Write;
+use std::num::{NonZeroU32, NonZeroU64, NonZeroUsize};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use throttledblob::{ThrottleOptions, ThrottledBlob};
+use tokio::{fs::File, io::BufReader, runtime::Runtime};
+use tokio_util::codec::{BytesCodec, FramedRead};
+
+mod baseline;
+mod cache_phases;
+mod faults;
+mod ingest;
+mod latency;
+mod lookups;
+mod metrics;
+mod output;
+mod pack;
+mod ramp;
+mod run_stats;
+mod scenario;
+mod shards;
+mod verify;
+mod workload;
+
+use baseline::Baseline;
+use cache_phases::{CachePhases, ReadPhase, Uncached};
+use faults::{FaultOptions, FaultyBlob};
+use latency::{Latencies, LatencyBlob, LatencyHistogram, PERCENTILES};
+use metrics::{ExportOptions, MetricsBlob};
+use output::{Operation, OperationRecord, Output, OutputFormat, OUTPUT_FORMATS};
+use pack::PackedBlob;
+use ramp::Ramp;
+use run_stats::RunStats;
+use scenario::{BackendConfig, CacheConfig, Scenario};
+use shards::ShardedBlob;
+use verify::Verifier;
+use workload::{MixedWorkload, WORKLOADS, WORKLOAD_MIXED, WORKLOAD_SINGLE};
+
+const NAME: &str = "benchmark_filestore";
+
+const CMD_MANIFOLD: &str = "manifold";
+const CMD_MEMORY: &str = "memory";
+const CMD_XDB: &str = "xdb";
+const CMD_FILEBLOB: &str = "fileblob";
+const CMD_MULTIPLEXED: &str = "multiplexed";
+const CMD_REPO: &str = "repo";
+const CMD_SCENARIO: &str = "scenario";
+
+const ARG_MANIFOLD_BUCKET: &str = "manifold-bucket";
+const ARG_SHARDMAP: &str = "shardmap";
+const ARG_SHARD_COUNT: &str = "shard-count";
+const ARG_FILEBLOB_PATH: &str = "path";
+const ARG_FSYNC: &str = "fsync";
+const ARG_PACK: &str = "pack";
+const ARG_COMPONENT: &str = "component";
+const ARG_WRITE_MOSTLY: &str = "write-mostly";
+const ARG_MINIMUM_SUCCESSFUL_WRITES: &str = "minimum-successful-writes";
+const ARG_MYROUTER_PORT: &str = "myrouter-port";
+const ARG_USE_MYSQL_CLIENT: &str = "use-mysql-client";
+const ARG_INPUT_CAPACITY: &str = "input-capacity";
+const ARG_CHUNK_SIZE: &str = "chunk-size";
+const ARG_CONCURRENCY: &str = "concurrency";
+const ARG_MEMCACHE: &str = "memcache";
+const ARG_CACHELIB_SIZE: &str = "cachelib-size";
+const ARG_DROP_CACHES: &str = "drop-caches";
+const ARG_VERIFY: &str = "verify";
+const ARG_INPUT: &str = "input";
+const ARG_DELAY: &str = "delay";
+const ARG_RANDOMIZE: &str = "randomize";
+const ARG_READ_QPS: &str = "read-qps";
+const ARG_WRITE_QPS: &str = "write-qps";
+const ARG_RAMP: &str = "ramp";
+const ARG_INJECT_LATENCY_MS: &str = "inject-latency-ms";
+const ARG_INJECT_ERROR_RATE: &str = "inject-error-rate";
+const ARG_RETRY_ATTEMPTS: &str = "retry-attempts";
+const ARG_READ_COUNT: &str = "read-count";
+const ARG_RANGE_READS: &str = "range-reads";
+const ARG_RANGE_SIZES: &str = "range-sizes";
+const ARG_LOOKUPS: &str = "lookups";
+const ARG_LOOKUP_QPS: &str = "lookup-qps";
+const ARG_SCENARIO_FILE: &str = "scenario-file";
+const ARG_COMPARE_PUT_BEHAVIOURS: &str = "compare-put-behaviours";
+const ARG_PUT_BEHAVIOUR_SAMPLES: &str = "put-behaviour-samples";
+const ARG_OUTPUT_FORMAT: &str = "output-format";
+const ARG_OUTPUT_FILE: &str = "output-file";
+const ARG_WORKLOAD: &str = "workload";
+const ARG_READ_RATIO: &str = "read-ratio";
+const ARG_DURATION: &str = "duration";
+const ARG_WARMUP: &str = "warmup";
+const ARG_WORKERS: &str = "workers";
+const ARG_PROGRESS_INTERVAL: &str = "progress-interval";
+const ARG_LATENCY_HISTOGRAM_FILE: &str = "latency-histogram-file";
+const ARG_INGEST_MANIFEST: &str = "ingest-manifest";
+const ARG_INGEST_CONCURRENCY: &str = "ingest-concurrency";
+const ARG_SWEEP: &str = "sweep";
+const ARG_SWEEP_CHUNK_SIZES: &str = "sweep-chunk-sizes";
+const ARG_SWEEP_CONCURRENCY: &str = "sweep-concurrency";
+const ARG_STATSD_HOST: &str = "statsd-host";
+const ARG_PROMETHEUS_PORT: &str = "prometheus-port";
+const ARG_PROMETHEUS_ADDRESS: &str = "prometheus-address";
+const ARG_BASELINE: &str = "baseline";
+const ARG_REGRESSION_THRESHOLD: &str = "regression-threshold";
+const ARG_ALLOW_WRITES_TO_REPO: &str = "allow-writes-to-repo";
+
+// The first one is the baseline that the others are compared against.
+const PUT_BEHAVIOURS: &[PutBehaviour] = &[
+    PutBehaviour::IfAbsent,
+    PutBehaviour::Overwrite,
+    PutBehaviour::OverwriteAndLog,
+];
+
+/// How many operations of the mixed workload can be waiting to be recorded before the workers
+/// wait for them to be.
+const MIXED_WORKLOAD_RECORD_BUFFER: usize = 1000;
+
+/// What to do for a single run of the benchmark, once the blobstore is set up.
+#[derive(Clone, Debug)]
+pub struct BenchmarkOptions {
+    input: String,
+    input_capacity: usize,
+    chunk_size: u64,
+    concurrency: usize,
+    read_count: usize,
+    /// How many reads of random ranges to do of each size, after the reads of the whole contents.
+    range_reads: usize,
+    range_sizes: Vec<u64>,
+    /// How many lookups of each alias and of the metadata to do, after the reads.
+    lookups: usize,
+    lookup_qps: Option<NonZeroU32>,
+    delay: Option<Duration>,
+    randomize: bool,
+    /// Write straight to the backend, under the caches.
+    drop_caches: bool,
+    /// Check the bytes of each read against the hashes of the contents, and fail if any read
+    /// failed.
+    verify: bool,
+}
+
+/// Throughput of each operation of a run, in MB/s. `None` if the operation failed.
+#[derive(Clone, Debug)]
+pub struct BenchmarkResult {
+    write: Option<f64>,
+    write_latency: Option<Duration>,
+    reads: Vec<Option<f64>>,
+    /// Every operation of the run, in order, for the json output.
+    operations: Vec<OperationRecord>,
+    latencies: Latencies,
+}
+
+fn throughput_mb_per_s(stats: &FutureStats, len: u64) -> f64 {
+    let bytes_per_ns = (len as f64) / (stats.completion_time.as_nanos() as f64);
+    bytes_per_ns * (10_u128.pow(9) as f64) / (2_u128.pow(20) as f64)
+}
+
+fn log_perf<I, E: Debug>(stats: &FutureStats, res: &Result<I, E>, len: u64) -> Option<f64> {
+    match res {
+        Ok(_) => {
+            let mbytes_per_s = throughput_mb_per_s(stats, len);
+            let gb_per_s = mbytes_per_s * 8_f64 / 1024_f64;
+            eprintln!(
+                "Success: {:.2} MB/s ({:.2} Gb/s) ({:?})",
+                mbytes_per_s, gb_per_s, stats
+            );
+            Some(mbytes_per_s)
+        }
+        Err(e) => {
+            eprintln!("Failure: {:?}", e);
+            None
+        }
+    }
+}
+
+async fn read<B: Blobstore>(
+    blob: &B,
+    ctx: &CoreContext,
+    content_metadata: &ContentMetadata,
+    latencies: &Mutex<Latencies>,
+    verify: bool,
+) -> Result<OperationRecord, Error> {
+    let key = FetchKey::Canonical(content_metadata.content_id);
+    eprintln!(
+        "Fetch start: {:?} ({:?} B)",
+        key, content_metadata.total_size
+    );
+
+    let stream = filestore::fetch(blob, ctx.clone(), &key)
+        .await?
+        .ok_or(format_err!("Fetch failed: no stream"))?;
+
+    let mut verifier = if verify {
+        Some(Verifier::new(content_metadata))
+    } else {
+        None
+    };
+    let (stats, res) = stream
+        .try_for_each(|bytes| {
+            if let Some(verifier) = &mut verifier {
+                verifier.update(&bytes);
+            }
+            async { Ok(()) }
+        })
+        .timed()
+        .await;
+    let res = res.and_then(|()| match verifier {
+        Some(verifier) => verifier.finish(content_metadata),
+        None => Ok(()),
+    });
+    if res.is_ok() {
+        latencies
+            .lock()
+            .expect("lock poisoned")
+            .read
+            .record(stats.completion_time);
+    }
+
+    // ignore errors - all we do is log them in `log_perf`
+    let throughput = log_perf(&stats, &res, content_metadata.total_size);
+    Ok(OperationRecord::new(
+        Operation::Read,
+        &stats,
+        content_metadata.total_size,
+        throughput,
+    ))
+}
+
+/// Fetch `size` bytes from a random offset of the contents, or all of them if there are fewer.
+async fn range_read<B: Blobstore>(
+    blob: &B,
+    ctx: &CoreContext,
+    content_metadata: &ContentMetadata,
+    size: u64,
+    latencies: &Mutex<Latencies>,
+) -> Result<OperationRecord, Error> {
+    let key = FetchKey::Canonical(content_metadata.content_id);
+    let size = size.min(content_metadata.total_size);
+    let start = rand::thread_rng().gen_range(0, content_metadata.total_size - size + 1);
+
+    let stream = filestore::fetch_range(blob, ctx, &key, start, size)
+        .await?
+        .ok_or(format_err!("Fetch failed: no stream"))?;
+
+    let (stats, res) = stream
+        .try_fold(0, |len, bytes| async move { Ok(len + bytes.len() as u64) })
+        .timed()
+        .await;
+
+    // As for full reads, errors are only reported.
+    let throughput = match res {
+        Ok(len) => {
+            latencies
+                .lock()
+                .expect("lock poisoned")
+                .range_read
+                .record(stats.completion_time);
+            Some(throughput_mb_per_s(&stats, len))
+        }
+        Err(e) => {
+            eprintln!("Range fetch of {} B at {} failed: {:?}", size, start, e);
+            None
+        }
+    };
+    Ok(OperationRecord::new(
+        Operation::RangeRead,
+        &stats,
+        size,
+        throughput,
+    ))
+}
+
+/// The contents to store from `path`, and their size.
+async fn open_input(
+    path: impl AsRef<Path>,
+    options: &BenchmarkOptions,
+) -> Result<(u64, impl Stream<Item = Result<Bytes, Error>>), Error> {
+    let file = File::open(path).await?;
+    let metadata = file.metadata().await?;
+
+    let data = BufReader::with_capacity(options.input_capacity, file);
+    let data = FramedRead::new(data, BytesCodec::new())
+        .map_ok(BytesMut::freeze)
+        .map_err(Error::from);
+    let len = metadata.len();
+
+    if options.randomize {
+        let bytes = rand::thread_rng().gen::<[u8; 32]>();
+        let bytes = Bytes::copy_from_slice(&bytes[..]);
+        Ok((
+            len + (bytes.len() as u64),
+            stream::iter(vec![Ok(bytes)]).chain(data).left_stream(),
+        ))
+    } else {
+        Ok((len, data.right_stream()))
+    }
+}
+
+/// Write the input to `blob`, then read it back. If there are caches in `blob`, the backend under
+/// them is `uncached`, for the reads to be reported by phase.
+async fn run_benchmark_filestore(
+    ctx: &CoreContext,
+    options: &BenchmarkOptions,
+    blob: Arc<dyn Blobstore>,
+    uncached: Option<Uncached>,
+) -> Result<BenchmarkResult, Error> {
+    let config = FilestoreConfig {
+        chunk_size: Some(options.chunk_size),
+        concurrency: options.concurrency,
+        inline_threshold: None,
+    };
+
+    eprintln!("Test with {:?}, writing into {:?}", config, blob);
+
+    let latencies = Arc::new(Mutex::new(Latencies::default()));
+    let blob: Arc<dyn Blobstore> = Arc::new(LatencyBlob::new(blob, latencies.clone()));
+    let write_blob: Arc<dyn Blobstore> = match &uncached {
+        Some(uncached) if options.drop_caches => {
+            Arc::new(LatencyBlob::new(uncached.blob.clone(), latencies.clone()))
+        }
+        _ => blob.clone(),
+    };
+
+    let (len, data) = open_input(&options.input, options).await?;
+
+    eprintln!("Write start: {:?} B", len);
+
+    let req = StoreRequest::new(len);
+
+    let (stats, res) = filestore::store(&write_blob, config, ctx, &req, data)
+        .timed()
+        .await;
+    let write_latency = res.as_ref().ok().map(|_| stats.completion_time);
+    if let Some(write_latency) = write_latency {
+        latencies
+            .lock()
+            .expect("lock poisoned")
+            .write
+            .record(write_latency);
+    }
+    let write = log_perf(&stats, &res, len);
+    let mut operations = vec![OperationRecord::new(Operation::Write, &stats, len, write)];
+
+    let metadata = res?;
+
+    match options.delay {
+        Some(delay) => {
+            tokio_shim::time::sleep(delay).await;
+        }
+        None => {}
+    }
+
+    eprintln!("Write committed: {:?}", metadata.content_id.blobstore_key());
+
+    let mut phases = uncached.map(|uncached| CachePhases::new(uncached, latencies.clone()));
+    let mut reads = Vec::with_capacity(options.read_count);
+    for c in 0..options.read_count {
+        if let Some(phases) = &mut phases {
+            phases.start_read();
+        }
+        let record = read(&blob, ctx, &metadata, &latencies, options.verify).await?;
+        let record = match &mut phases {
+            Some(phases) => {
+                let phase = if c == 0 {
+                    ReadPhase::Cold
+                } else {
+                    ReadPhase::Warm
+                };
+                phases.finish_read(phase, record)
+            }
+            None => record,
+        };
+        reads.push(record.throughput_mb_per_s());
+        operations.push(record);
+    }
+    if let Some(phases) = &phases {
+        phases.log();
+    }
+    let failed = reads.iter().filter(|read| read.is_none()).count();
+    if options.verify && failed > 0 {
+        bail!("{} of {} reads failed", failed, reads.len());
+    }
+
+    let range_sizes = if options.range_reads > 0 {
+        &options.range_sizes[..]
+    } else {
+        &[]
+    };
+    for size in range_sizes {
+        eprintln!("Range fetches: {} of {:?} B", options.range_reads, size);
+        let mut histogram = LatencyHistogram::default();
+        let mut failed = 0;
+        for _c in 0..options.range_reads {
+            let record = range_read(&blob, ctx, &metadata, *size, &latencies).await?;
+            match record.throughput_mb_per_s() {
+                Some(_) => histogram.record(record.latency()),
+                None => failed += 1,
+            }
+            operations.push(record);
+        }
+        eprintln!(
+            "Range fetches of {:?} B: {} ({} failed)",
+            size, histogram, failed
+        );
+    }
+
+    if options.lookups > 0 {
+        operations.extend(
+            lookups::run_lookups(&blob, ctx, &metadata, options.lookups, options.lookup_qps).await,
+        );
+    }
+
+    let latencies = latencies.lock().expect("lock poisoned").clone();
+    latencies.log();
+
+    Ok(BenchmarkResult {
+        write,
+        write_latency,
+        reads,
+        operations,
+        latencies,
+    })
+}
+
+/// One of the backends that stores the blobs itself, rather than wrapping others.
+async fn get_backend<'a>(
+    fb: FacebookInit,
+    matches: &'a MononokeMatches<'a>,
+    config_store: &ConfigStore,
+    put_behaviour: PutBehaviour,
+    backend: &BackendConfig,
+    stats: &mut RunStats,
+) -> Result<Arc<dyn BlobstorePutOps>, Error> {
+    let blob: Arc<dyn BlobstorePutOps> = match backend {
+        BackendConfig::Manifold { bucket } => {
+            #[cfg(fbcode_build)]
+            {
+                use manifoldblob::{ManifoldBlob, ManifoldClientType};
+                use prefixblob::PrefixBlobstore;
+
+                let manifold_client_type =
+                    if args::parse_blobstore_options(matches)?.manifold_use_cpp_client {
+                        ManifoldClientType::CppThriftHybrid
+                    } else {
+                        ManifoldClientType::ThriftOnly
+                    };
+                let manifold =
+                    ManifoldBlob::new(fb, bucket, None, put_behaviour, manifold_client_type)
+                        .map_err(|e| -> Error { e })?;
+                let blobstore = PrefixBlobstore::new(manifold, format!("flat/{}.", NAME));
+                Arc::new(blobstore)
+            }
+            #[cfg(not(fbcode_build))]
+            {
+                let _ = bucket;
+                unimplemented!("Accessing Manifold is not implemented in non fbcode builds");
+            }
+        }
+        BackendConfig::Memory => Arc::new(memblob::Memblob::default()),
+        BackendConfig::Xdb {
+            shardmap,
+            shard_count,
+            myrouter_port,
+            use_mysql_client,
+        } => {
+            let shard_count = *shard_count;
+            let connection_type = match (myrouter_port, use_mysql_client) {
+                (Some(_), true) => bail!(
+                    "Backend {} can't use both MyRouter and the mysql client",
+                    backend
+                ),
+                (Some(port), false) => MysqlConnectionType::Myrouter(*port),
+                (None, true) => MysqlConnectionType::Mysql(
+                    args::get_global_mysql_connection_pool(matches),
+                    args::parse_mysql_pool_options(matches),
+                ),
+                (None, false) => args::parse_mysql_options(&matches).connection_type,
+            };
+            let blobstore = match connection_type {
+                MysqlConnectionType::Myrouter(port) => {
+                    Sqlblob::with_myrouter(
+                        fb,
+                        shardmap.clone(),
+                        port,
+                        ReadConnectionType::Replica,
+                        shard_count,
+                        false,
+                        put_behaviour,
+                        config_store,
+                    )
+                    .compat()
+                    .await?
+                }
+                MysqlConnectionType::Mysql(pool, config) => {
+                    Sqlblob::with_mysql(
+                        fb,
+                        shardmap.clone(),
+                        shard_count,
+                        pool,
+                        config,
+                        ReadConnectionType::Replica,
+                        false,
+                        put_behaviour,
+                        config_store,
+                    )
+                    .compat()
+                    .await?
+                }
+                MysqlConnectionType::RawXDB => {
+                    Sqlblob::with_raw_xdb_shardmap(
+                        fb,
+                        shardmap.clone(),
+                        ReadConnectionType::Replica,
+                        shard_count,
+                        false,
+                        put_behaviour,
+                        config_store,
+                    )
+                    .compat()
+                    .await?
+                }
+            };
+            let blob = ShardedBlob::new(blobstore, shardmap.clone(), shard_count);
+            stats.add(blob.stats());
+            Arc::new(blob)
+        }
+        BackendConfig::Fileblob { path, fsync } => {
+            let options = FileblobOptions {
+                fsync: *fsync,
+                // Nothing is left behind for gc to clean up whilst the benchmark runs.
+                gc_interval: None,
+                ..args::parse_blobstore_options(matches)?.fileblob_options
+            };
+            Arc::new(Fileblob::create_with_options(path, put_behaviour, options)?)
+        }
+        BackendConfig::Pack { .. }
+        | BackendConfig::Multiplexed { .. }
+        | BackendConfig::Repo { .. } => {
+            bail!("{} can't be under another backend", backend)
+        }
+    };
+    Ok(blob)
+}
+
+/// A multiplex of the backends, with a sync queue in memory, for the cost of writing to several
+/// backends (and to the queue) to be measured against the one of writing to one of them.
+async fn get_multiplexed<'a>(
+    fb: FacebookInit,
+    matches: &'a MononokeMatches<'a>,
+    config_store: &ConfigStore,
+    put_behaviour: PutBehaviour,
+    components: &[BackendConfig],
+    write_mostly: &[BackendConfig],
+    minimum_successful_writes: NonZeroUsize,
+    stats: &mut RunStats,
+) -> Result<Arc<dyn BlobstorePutOps>, Error> {
+    if components.is_empty() {
+        bail!("A multiplex needs at least one component");
+    }
+    if minimum_successful_writes.get() > components.len() + write_mostly.len() {
+        bail!(
+            "A multiplex of {} blobstores can't have {} successful writes",
+            components.len() + write_mostly.len(),
+            minimum_successful_writes
+        );
+    }
+
+    let mut ids = (0..).map(BlobstoreId::new);
+    let mut normal = Vec::with_capacity(components.len());
+    for backend in components {
+        let blob = get_backend(fb, matches, config_store, put_behaviour, backend, stats).await?;
+        normal.push((ids.next().expect("ids are unbounded"), blob));
+    }
+    let mut write_mostly_components = Vec::with_capacity(write_mostly.len());
+    for backend in write_mostly {
+        let blob = get_backend(fb, matches, config_store, put_behaviour, backend, stats).await?;
+        write_mostly_components.push((ids.next().expect("ids are unbounded"), blob));
+    }
+
+    Ok(Arc::new(MultiplexedBlobstore::new(
+        MultiplexId::new(1),
+        normal,
+        write_mostly_components,
+        minimum_successful_writes,
+        Arc::new(SqlBlobstoreSyncQueue::with_sqlite_in_memory()?),
+        MononokeScubaSampleBuilder::with_discard(),
+        NonZeroU64::new(1).expect("1 is not 0"),
+    )))
+}
+
+/// The blobstore of the repo called `name`, as the factory builds it from the repo config. Every
+/// run stores its input, so this refuses unless writing to the repo was explicitly allowed, and
+/// the storage of the repo isn't read-only.
+async fn get_repo_blobstore<'a>(
+    fb: FacebookInit,
+    logger: &Logger,
+    matches: &'a MononokeMatches<'a>,
+    config_store: &ConfigStore,
+    put_behaviour: PutBehaviour,
+    name: &str,
+) -> Result<Arc<dyn BlobstorePutOps>, Error> {
+    let readonly_storage = args::parse_readonly_storage(matches);
+    if readonly_storage.0 {
+        bail!(
+            "The storage of repo {} is read-only, and the benchmark writes to it",
+            name
+        );
+    }
+    if !matches.is_present(ARG_ALLOW_WRITES_TO_REPO) {
+        bail!(
+            "The benchmark writes to the blobstore of repo {}, pass --{} if that is fine",
+            name,
+            ARG_ALLOW_WRITES_TO_REPO
+        );
+    }
+
+    let configs = args::load_repo_configs(config_store, matches)?;
+    let config = configs
+        .repos
+        .get(name)
+        .ok_or_else(|| format_err!("Unknown repo {}", name))?;
+    let blobstore_options = BlobstoreOptions {
+        put_behaviour,
+        ..args::parse_blobstore_options(matches)?
+    };
+    make_blobstore_put_ops(
+        fb,
+        config.storage_config.blobstore.clone(),
+        &args::parse_mysql_options(matches),
+        readonly_storage,
+        &blobstore_options,
+        logger,
+        config_store,
+    )
+    .await
+}
+
+async fn get_blob<'a>(
+    fb: FacebookInit,
+    logger: &Logger,
+    matches: &'a MononokeMatches<'a>,
+    config_store: &ConfigStore,
+    put_behaviour: PutBehaviour,
+    backend: &BackendConfig,
+    cache: &CacheConfig,
+) -> Result<(Arc<dyn Blobstore>, Option<Uncached>, RunStats), Error> {
+    let mut stats = RunStats::default();
+    let (backend, pack_level) = match backend {
+        BackendConfig::Pack { level, backend } => (&**backend, Some(*level)),
+        backend => (backend, None),
+    };
+
+    let blob = match backend {
+        BackendConfig::Multiplexed {
+            components,
+            write_mostly,
+            minimum_successful_writes,
+        } => {
+            get_multiplexed(
+                fb,
+                matches,
+                config_store,
+                put_behaviour,
+                components,
+                write_mostly,
+                *minimum_successful_writes,
+                &mut stats,
+            )
+            .await?
+        }
+        BackendConfig::Repo { name } => {
+            get_repo_blobstore(fb, logger, matches, config_store, put_behaviour, name).await?
+        }
+        backend => {
+            get_backend(
+                fb,
+                matches,
+                config_store,
+                put_behaviour,
+                backend,
+                &mut stats,
+            )
+            .await?
+        }
+    };
+
+    let blob: Arc<dyn Blobstore> = match pack_level {
+        Some(level) => {
+            let blob = PackedBlob::new(blob, level);
+            stats.add(blob.stats());
+            Arc::new(blob)
+        }
+        None => Arc::new(blob),
+    };
+
+    let faults = parse_fault_options(matches)?;
+    let blob: Arc<dyn Blobstore> = if faults.has_faults() {
+        let blob = FaultyBlob::new(blob, faults);
+        stats.add(blob.stats());
+        Arc::new(blob)
+    } else {
+        blob
+    };
+
+    let (blob, uncached) = if cache.memcache || cache.cachelib_size.is_some() {
+        let latencies = Arc::new(Mutex::new(Latencies::default()));
+        let blob: Arc<dyn Blobstore> = Arc::new(LatencyBlob::new(blob, latencies.clone()));
+        let uncached = Uncached {
+            blob: blob.clone(),
+            latencies,
+        };
+        (blob, Some(uncached))
+    } else {
+        (blob, None)
+    };
+
+    let blob: Arc<dyn Blobstore> = if cache.memcache {
+        Arc::new(new_memcache_blobstore_no_lease(fb, blob, NAME, "")?)
+    } else {
+        blob
+    };
+
+    let blob: Arc<dyn Blobstore> = match cache.cachelib_size {
+        Some(cache_size_bytes) => {
+            #[cfg(fbcode_build)]
+            {
+                use cacheblob::{new_cachelib_blobstore_no_lease, CachelibBlobstoreOptions};
+
+                cachelib::init_cache_once(fb, cachelib::LruCacheConfig::new(cache_size_bytes))?;
+
+                let presence_pool = cachelib::get_or_create_pool(
+                    "presence",
+                    cachelib::get_available_space()? / 20,
+                )?;
+                let blob_pool =
+                    cachelib::get_or_create_pool("blobs", cachelib::get_available_space()?)?;
+
+                Arc::new(new_cachelib_blobstore_no_lease(
+                    blob,
+                    Arc::new(blob_pool),
+                    Arc::new(presence_pool),
+                    CachelibBlobstoreOptions::default(),
+                ))
+            }
+            #[cfg(not(fbcode_build))]
+            {
+                let _ = cache_size_bytes;
+                unimplemented!("Using cachelib is not implemented for non fbcode build");
+            }
+        }
+        None => blob,
+    };
+
+    let read_qps: Option<NonZeroU32> = matches
+        .value_of(ARG_READ_QPS)
+        .map(|v| v.parse())
+        .transpose()?;
+
+    let write_qps: Option<NonZeroU32> = matches
+        .value_of(ARG_WRITE_QPS)
+        .map(|v| v.parse())
+        .transpose()?;
+
+    let blob = ThrottledBlob::new(
+        blob,
+        ThrottleOptions {
+            read_qps,
+            write_qps,
+            ..ThrottleOptions::default()
+        },
+    )
+    .await;
+
+    let blob: Arc<dyn Blobstore> = if parse_export_options(matches)?.is_enabled() {
+        Arc::new(MetricsBlob::new(blob))
+    } else {
+        Arc::new(blob)
+    };
+
+    Ok((blob, uncached, stats))
+}
+
+fn parse_benchmark_options(matches: &MononokeMatches<'_>) -> Result<BenchmarkOptions, Error> {
+    let input = matches.value_of(ARG_INPUT).unwrap().to_string();
+
+    let input_capacity: usize = matches.value_of(ARG_INPUT_CAPACITY).unwrap().parse()?;
+
+    let chunk_size: u64 = matches.value_of(ARG_CHUNK_SIZE).unwrap().parse()?;
+
+    let concurrency: usize = matches.value_of(ARG_CONCURRENCY).unwrap().parse()?;
+
+    let read_count: usize = matches.value_of(ARG_READ_COUNT).unwrap().parse()?;
+
+    let range_reads: usize = matches.value_of(ARG_RANGE_READS).unwrap().parse()?;
+
+    let range_sizes: Vec<u64> = parse_list(matches, ARG_RANGE_SIZES)?;
+
+    let lookups: usize = matches.value_of(ARG_LOOKUPS).unwrap().parse()?;
+
+    let lookup_qps: Option<NonZeroU32> = matches
+        .value_of(ARG_LOOKUP_QPS)
+        .map(|v| v.parse())
+        .transpose()?;
+
+    let delay: Option<Duration> = matches
+        .value_of(ARG_DELAY)
+        .map(|seconds| -> Result<Duration, Error> {
+            let seconds = seconds.parse().map_err(Error::from)?;
+            Ok(Duration::new(seconds, 0))
+        })
+        .transpose()?;
+
+    let randomize = matches.is_present(ARG_RANDOMIZE);
+
+    let drop_caches = matches.is_present(ARG_DROP_CACHES);
+
+    let verify = matches.is_present(ARG_VERIFY);
+
+    Ok(BenchmarkOptions {
+        input,
+        input_capacity,
+        chunk_size,
+        concurrency,
+        read_count,
+        range_reads,
+        range_sizes,
+        lookups,
+        lookup_qps,
+        delay,
+        randomize,
+        drop_caches,
+        verify,
+    })
+}
+
+/// The mixed workload to run, if that's the workload that was asked for.
+fn parse_mixed_workload(matches: &MononokeMatches<'_>) -> Result<Option<MixedWorkload>, Error> {
+    if matches.value_of(ARG_WORKLOAD) != Some(WORKLOAD_MIXED) {
+        return Ok(None);
+    }
+    let workload = MixedWorkload::new(
+        matches.value_of(ARG_READ_RATIO).unwrap().parse()?,
+        humantime::parse_duration(matches.value_of(ARG_DURATION).unwrap())?,
+        humantime::parse_duration(matches.value_of(ARG_WARMUP).unwrap())?,
+        matches.value_of(ARG_WORKERS).unwrap().parse()?,
+        matches
+            .value_of(ARG_PROGRESS_INTERVAL)
+            .map(humantime::parse_duration)
+            .transpose()?,
+    )?;
+    Ok(Some(workload))
+}
+
+fn parse_fault_options(matches: &MononokeMatches<'_>) -> Result<FaultOptions, Error> {
+    let latency = matches
+        .value_of(ARG_INJECT_LATENCY_MS)
+        .map(|v| v.parse().map(Duration::from_millis))
+        .transpose()?;
+    FaultOptions::new(
+        latency,
+        matches.value_of(ARG_INJECT_ERROR_RATE).unwrap().parse()?,
+        matches.value_of(ARG_RETRY_ATTEMPTS).unwrap().parse()?,
+    )
+}
+
+fn parse_export_options(matches: &MononokeMatches<'_>) -> Result<ExportOptions, Error> {
+    ExportOptions::new(
+        matches.value_of(ARG_STATSD_HOST),
+        matches.value_of(ARG_PROMETHEUS_ADDRESS).unwrap(),
+        matches
+            .value_of(ARG_PROMETHEUS_PORT)
+            .map(|port| port.parse())
+            .transpose()?,
+    )
+}
+
+fn parse_list<T>(matches: &MononokeMatches<'_>, name: &str) -> Result<Vec<T>, Error>
+where
+    T: FromStr,
+    Error: From<T::Err>,
+{
+    matches
+        .value_of(name)
+        .unwrap()
+        .split(',')
+        .map(|value| Ok(value.trim().parse()?))
+        .collect()
+}
+
+fn parse_backends(matches: &ArgMatches<'_>, name: &str) -> Result<Vec<BackendConfig>, Error> {
+    matches
+        .values_of(name)
+        .into_iter()
+        .flatten()
+        .map(|backend| backend.parse())
+        .collect()
+}
+
+fn parse_backend(
+    matches: &MononokeMatches<'_>,
+    config_store: &ConfigStore,
+) -> Result<BackendConfig, Error> {
+    let backend = match matches.subcommand() {
+        (CMD_MANIFOLD, Some(sub)) => BackendConfig::Manifold {
+            bucket: sub.value_of(ARG_MANIFOLD_BUCKET).unwrap().to_string(),
+        },
+        (CMD_MEMORY, Some(_)) => BackendConfig::Memory,
+        (CMD_XDB, Some(sub)) => BackendConfig::Xdb {
+            shardmap: sub.value_of(ARG_SHARDMAP).unwrap().to_string(),
+            shard_count: sub.value_of(ARG_SHARD_COUNT).unwrap().parse()?,
+            myrouter_port: sub
+                .value_of(ARG_MYROUTER_PORT)
+                .map(|port| port.parse())
+                .transpose()?,
+            use_mysql_client: sub.is_present(ARG_USE_MYSQL_CLIENT),
+        },
+        (CMD_FILEBLOB, Some(sub)) => BackendConfig::Fileblob {
+            path: sub.value_of(ARG_FILEBLOB_PATH).unwrap().to_string(),
+            fsync: sub.is_present(ARG_FSYNC),
+        },
+        (CMD_MULTIPLEXED, Some(sub)) => BackendConfig::Multiplexed {
+            components: parse_backends(sub, ARG_COMPONENT)?,
+            write_mostly: parse_backends(sub, ARG_WRITE_MOSTLY)?,
+            minimum_successful_writes: sub
+                .value_of(ARG_MINIMUM_SUCCESSFUL_WRITES)
+                .unwrap()
+                .parse()?,
+        },
+        (CMD_REPO, Some(_)) => BackendConfig::Repo {
+            name: args::get_config(config_store, matches)?.0,
+        },
+        _ => unreachable!(),
+    };
+    Ok(match matches.value_of(ARG_PACK) {
+        Some(level) => BackendConfig::Pack {
+            level: level.parse()?,
+            backend: Box::new(backend),
+        },
+        None => backend,
+    })
+}
+
+fn parse_cache(matches: &MononokeMatches<'_>) -> Result<CacheConfig, Error> {
+    Ok(CacheConfig {
+        memcache: matches.is_present(ARG_MEMCACHE),
+        cachelib_size: matches
+            .value_of(ARG_CACHELIB_SIZE)
+            .map(|size| size.parse())
+            .transpose()?,
+    })
+}
+
+/// Run every combination described by the scenario file, and print a summary comparing them.
+/// Failed runs are reported in the summary rather than aborting the remaining runs.
+async fn run_scenario<'a>(
+    fb: FacebookInit,
+    ctx: &'a CoreContext,
+    matches: &'a MononokeMatches<'a>,
+    config_store: &'a ConfigStore,
+    output: &'a mut Output,
+    scenario: Scenario,
+) -> Result<(), Error> {
+    let runs = scenario.runs(&parse_benchmark_options(matches)?, &parse_cache(matches)?);
+    let total = runs.len();
+
+    let mut results = Vec::with_capacity(total);
+    for (idx, run) in runs.into_iter().enumerate() {
+        eprintln!("Run {}/{}: {}", idx + 1, total, run);
+        let res = async {
+            let (blob, uncached, stats) = get_blob(
+                fb,
+                ctx.logger(),
+                matches,
+                config_store,
+                DEFAULT_PUT_BEHAVIOUR,
+                &run.backend,
+                &run.cache,
+            )
+            .await?;
+            let res = run_benchmark_filestore(ctx, &run.options, blob, uncached).await?;
+            Ok::<_, Error>((res, stats))
+        }
+        .await;
+        let res = match res {
+            Ok((res, stats)) => {
+                output.record(
+                    &run.backend,
+                    &run.cache,
+                    DEFAULT_PUT_BEHAVIOUR,
+                    &run.options,
+                    &res.operations,
+                )?;
+                output.record_stats(
+                    &run.backend,
+                    &run.cache,
+                    DEFAULT_PUT_BEHAVIOUR,
+                    &run.options,
+                    &stats,
+                )?;
+                Ok(res)
+            }
+            Err(e) => {
+                eprintln!("Run failed: {:?}", e);
+                Err(e)
+            }
+        };
+        results.push((run, res));
+    }
+
+    scenario::print_summary(&mut output.tables(), &results)?;
+
+    Ok(())
+}
+
+/// Run the same write against the backend a number of times with each put behaviour, and print
+/// the distribution of the latencies of the writes, and how their median compares to the one with
+/// `IfAbsent`. That is the cost of the existence checks that the backend does (or doesn't do)
+/// before writing. The runs of the put behaviours are interleaved, so that they all see the same
+/// drift in the latency of the backend.
+async fn run_put_behaviour_comparison<'a>(
+    fb: FacebookInit,
+    ctx: &'a CoreContext,
+    matches: &'a MononokeMatches<'a>,
+    config_store: &'a ConfigStore,
+    output: &'a mut Output,
+    options: BenchmarkOptions,
+    backend: &'a BackendConfig,
+    cache: &'a CacheConfig,
+) -> Result<(), Error> {
+    // If the contents were the same for every run, all but the first would be writing keys that
+    // already exist.
+    let options = BenchmarkOptions {
+        randomize: true,
+        ..options
+    };
+    let samples: usize = matches
+        .value_of(ARG_PUT_BEHAVIOUR_SAMPLES)
+        .unwrap()
+        .parse()?;
+
+    let mut blobs = Vec::with_capacity(PUT_BEHAVIOURS.len());
+    for put_behaviour in PUT_BEHAVIOURS {
+        let (blob, uncached, stats) = get_blob(
+            fb,
+            ctx.logger(),
+            matches,
+            config_store,
+            *put_behaviour,
+            backend,
+            cache,
+        )
+        .await?;
+        blobs.push((*put_behaviour, blob, uncached, stats));
+    }
+
+    let mut latencies = vec![(LatencyHistogram::default(), 0); PUT_BEHAVIOURS.len()];
+    for sample in 0..samples {
+        eprintln!("Sample {}/{}", sample + 1, samples);
+        for ((put_behaviour, blob, uncached, _), (histogram, failed)) in
+            blobs.iter().zip(latencies.iter_mut())
+        {
+            let res =
+                run_benchmark_filestore(ctx, &options, blob.clone(), uncached.clone()).await?;
+            output.record(backend, cache, *put_behaviour, &options, &res.operations)?;
+            match res.write_latency {
+                Some(latency) => histogram.record(latency),
+                None => *failed += 1,
+            }
+        }
+    }
+
+    for (put_behaviour, _, _, stats) in &blobs {
+        output.record_stats(backend, cache, *put_behaviour, &options, stats)?;
+    }
+
+    let mut out = output.tables();
+    let baseline = latencies
+        .first()
+        .and_then(|(histogram, _)| histogram.percentile_ms(50.0));
+    let format_latency =
+        |ms: Option<f64>| ms.map_or_else(|| "-".to_string(), |ms| format!("{:.2}", ms));
+
+    write!(
+        out,
+        "{:<18} {:>8} {:>8}",
+        "put_behaviour", "samples", "failed"
+    )?;
+    for percentile in PERCENTILES {
+        write!(out, " {:>10}", format!("p{} ms", percentile))?;
+    }
+    writeln!(out, " {:>10} {:>24}", "max ms", "p50 vs baseline")?;
+    for ((put_behaviour, _, _, _), (histogram, failed)) in blobs.iter().zip(latencies.iter()) {
+        write!(
+            out,
+            "{:<18} {:>8} {:>8}",
+            put_behaviour.to_string(),
+            histogram.samples(),
+            failed
+        )?;
+        for percentile in PERCENTILES {
+            write!(
+                out,
+                " {:>10}",
+                format_latency(histogram.percentile_ms(*percentile))
+            )?;
+        }
+        let difference = match (histogram.percentile_ms(50.0), baseline) {
+            (Some(median), Some(baseline)) => {
+                let difference = median - baseline;
+                format!(
+                    "{:+.2} ms ({:+.1}%)",
+                    difference,
+                    difference * 100.0 / baseline
+                )
+            }
+            _ => "-".to_string(),
+        };
+        writeln!(
+            out,
+            " {:>10} {:>24}",
+            format_latency(histogram.max_ms()),
+            difference
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Run the benchmark in the mode that `matches` ask for, recording the results into `output`.
+fn run(
+    fb: FacebookInit,
+    ctx: &CoreContext,
+    matches: &MononokeMatches<'_>,
+    config_store: &ConfigStore,
+    runtime: &mut Runtime,
+    output: &mut Output,
+) -> Result<(), Error> {
+    if let (CMD_SCENARIO, Some(sub)) = matches.subcommand() {
+        let scenario = Scenario::load(sub.value_of(ARG_SCENARIO_FILE).unwrap())?;
+        return runtime.block_on(run_scenario(
+            fb,
+            ctx,
+            matches,
+            config_store,
+            output,
+            scenario,
+        ));
+    }
+
+    let options = parse_benchmark_options(matches)?;
+    let backend = parse_backend(matches, config_store)?;
+    let cache = parse_cache(matches)?;
+
+    let inputs = ingest::list_inputs(&options.input, matches.is_present(ARG_INGEST_MANIFEST))?;
+    if let Some(inputs) = inputs {
+        if matches.is_present(ARG_SWEEP)
+            || matches.is_present(ARG_COMPARE_PUT_BEHAVIOURS)
+            || matches.is_present(ARG_RAMP)
+            || matches.value_of(ARG_WORKLOAD) == Some(WORKLOAD_MIXED)
+        {
+            bail!(
+                "Several files can only be ingested on their own, not with --sweep, \
+                 --compare-put-behaviours, --ramp or the mixed workload"
+            );
+        }
+        let concurrency: usize = matches.value_of(ARG_INGEST_CONCURRENCY).unwrap().parse()?;
+        let (res, stats) = runtime.block_on(async {
+            let (blob, _, stats) = get_blob(
+                fb,
+                ctx.logger(),
+                matches,
+                config_store,
+                DEFAULT_PUT_BEHAVIOUR,
+                &backend,
+                &cache,
+            )
+            .await?;
+            let res = ingest::run_ingestion(ctx, &options, inputs, concurrency, blob).await?;
+            Ok::<_, Error>((res, stats))
+        })?;
+        output.record(
+            &backend,
+            &cache,
+            DEFAULT_PUT_BEHAVIOUR,
+            &options,
+            &res.operations,
+        )?;
+        output.record_stats(&backend, &cache, DEFAULT_PUT_BEHAVIOUR, &options, &stats)?;
+        res.print(&mut output.tables())?;
+        if let Some(path) = matches.value_of(ARG_LATENCY_HISTOGRAM_FILE) {
+            res.latencies.dump(path)?;
+        }
+        return Ok(());
+    }
+
+    if matches.is_present(ARG_SWEEP) {
+        if matches.is_present(ARG_RAMP) || matches.value_of(ARG_WORKLOAD) == Some(WORKLOAD_MIXED) {
+            bail!("--sweep can't be used with --ramp or the mixed workload");
+        }
+        let scenario = Scenario::sweep(
+            backend,
+            parse_list(matches, ARG_SWEEP_CHUNK_SIZES)?,
+            parse_list(matches, ARG_SWEEP_CONCURRENCY)?,
+        );
+        return runtime.block_on(run_scenario(
+            fb,
+            ctx,
+            matches,
+            config_store,
+            output,
+            scenario,
+        ));
+    }
+
+    if let Some(ramp) = matches.value_of(ARG_RAMP) {
+        if matches.is_present(ARG_COMPARE_PUT_BEHAVIOURS)
+            || matches.value_of(ARG_WORKLOAD) == Some(WORKLOAD_MIXED)
+        {
+            bail!("--ramp can't be used with --compare-put-behaviours or the mixed workload");
+        }
+        let ramp: Ramp = ramp.parse()?;
+        let workers: usize = matches.value_of(ARG_WORKERS).unwrap().parse()?;
+        let (res, stats) = runtime.block_on(async {
+            // There is no --write-qps with --ramp, so it's only the ramp that throttles writes.
+            let (blob, _, stats) = get_blob(
+                fb,
+                ctx.logger(),
+                matches,
+                config_store,
+                DEFAULT_PUT_BEHAVIOUR,
+                &backend,
+                &cache,
+            )
+            .await?;
+            let res = ramp::run_ramp(ctx, &options, &ramp, workers, blob).await?;
+            Ok::<_, Error>((res, stats))
+        })?;
+        output.record(
+            &backend,
+            &cache,
+            DEFAULT_PUT_BEHAVIOUR,
+            &options,
+            &res.operations,
+        )?;
+        output.record_stats(&backend, &cache, DEFAULT_PUT_BEHAVIOUR, &options, &stats)?;
+        res.print(&mut output.tables())?;
+        return Ok(());
+    }
+
+    if let Some(workload) = parse_mixed_workload(matches)? {
+        if matches.is_present(ARG_COMPARE_PUT_BEHAVIOURS) {
+            bail!("--compare-put-behaviours can't be used with the mixed workload");
+        }
+        let (res, stats) = runtime.block_on(async {
+            let (blob, _, stats) = get_blob(
+                fb,
+                ctx.logger(),
+                matches,
+                config_store,
+                DEFAULT_PUT_BEHAVIOUR,
+                &backend,
+                &cache,
+            )
+            .await?;
+            // The operations are recorded as they complete, rather than once the workload is done.
+            let (sender, mut receiver) = mpsc::channel(MIXED_WORKLOAD_RECORD_BUFFER);
+            let run = workload::run_mixed_workload(ctx, &options, &workload, blob, sender);
+            let record = async {
+                while let Some(record) = receiver.next().await {
+                    output.record(
+                        &backend,
+                        &cache,
+                        DEFAULT_PUT_BEHAVIOUR,
+                        &options,
+                        std::slice::from_ref(&record),
+                    )?;
+                }
+                Ok(())
+            };
+            let (res, ()) = future::try_join(run, record).await?;
+            Ok::<_, Error>((res, stats))
+        })?;
+        output.record_stats(&backend, &cache, DEFAULT_PUT_BEHAVIOUR, &options, &stats)?;
+        res.print(&mut output.tables())?;
+        if let Some(path) = matches.value_of(ARG_LATENCY_HISTOGRAM_FILE) {
+            res.latencies.dump(path)?;
+        }
+        return Ok(());
+    }
+
+    if matches.is_present(ARG_COMPARE_PUT_BEHAVIOURS) {
+        let samples = matches
+            .value_of(ARG_PUT_BEHAVIOUR_SAMPLES)
+            .unwrap()
+            .parse()?;
+        return runtime.block_on(run_put_behaviour_comparison(
+            fb,
+            ctx,
+            matches,
+            config_store,
+            output,
+            options,
+            &backend,
+            &cache,
+        ));
+    }
+
+    let (blob, uncached, stats) = runtime.block_on(get_blob(
+        fb,
+        ctx.logger(),
+        matches,
+        config_store,
+        DEFAULT_PUT_BEHAVIOUR,
+        &backend,
+        &cache,
+    ))?;
+
+    let res = runtime.block_on(run_benchmark_filestore(ctx, &options, blob, uncached))?;
+    output.record(
+        &backend,
+        &cache,
+        DEFAULT_PUT_BEHAVIOUR,
+        &options,
+        &res.operations,
+    )?;
+    output.record_stats(&backend, &cache, DEFAULT_PUT_BEHAVIOUR, &options, &stats)?;
+    if let Some(path) = matches.value_of(ARG_LATENCY_HISTOGRAM_FILE) {
+        res.latencies.dump(path)?;
+    }
+
+    Ok(())
+}
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<(), Error> {
+    let manifold_subcommand = SubCommand::with_name(CMD_MANIFOLD).arg(
+        Arg::with_name(ARG_MANIFOLD_BUCKET)
+            .takes_value(true)
+            .required(false),
+    );
+
+    let memory_subcommand = SubCommand::with_name(CMD_MEMORY);
+    let xdb_subcommand = SubCommand::with_name(CMD_XDB)
+        .arg(
+            Arg::with_name(ARG_SHARDMAP)
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(ARG_SHARD_COUNT)
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(ARG_MYROUTER_PORT)
+                .long(ARG_MYROUTER_PORT)
+                .takes_value(true)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(ARG_USE_MYSQL_CLIENT)
+                .long(ARG_USE_MYSQL_CLIENT)
+                .required(false)
+                .conflicts_with(ARG_MYROUTER_PORT),
+        );
+    let fileblob_subcommand = SubCommand::with_name(CMD_FILEBLOB)
+        .about("benchmark a blobstore in a local directory, which is created if need be")
+        .arg(
+            Arg::with_name(ARG_FILEBLOB_PATH)
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(ARG_FSYNC)
+                .long(ARG_FSYNC)
+                .required(false)
+                .help("fsync every blob before its put returns, i.e. measure durable writes"),
+        );
+    let multiplexed_subcommand = SubCommand::with_name(CMD_MULTIPLEXED)
+        .about(
+            "benchmark a multiplex of the components, with its sync queue in memory, for the \
+             cost of multiplexed puts over the ones to a single backend",
+        )
+        .arg(
+            Arg::with_name(ARG_COMPONENT)
+                .long(ARG_COMPONENT)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(true)
+                .help(
+                    "a component, as memory, manifold:BUCKET, xdb:SHARDMAP/SHARD_COUNT or \
+                     fileblob:PATH[+fsync]",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_WRITE_MOSTLY)
+                .long(ARG_WRITE_MOSTLY)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(false)
+                .help("a write mostly component, as for --component"),
+        )
+        .arg(
+            Arg::with_name(ARG_MINIMUM_SUCCESSFUL_WRITES)
+                .long(ARG_MINIMUM_SUCCESSFUL_WRITES)
+                .takes_value(true)
+                .default_value("1")
+                .help("how many of the components a put has to succeed on, i.e. the write quorum"),
+        );
+    let repo_subcommand = SubCommand::with_name(CMD_REPO).about(
+        "benchmark the blobstore of the repo given with --repo-name or --repo-id, as its config \
+         describes it, e.g. a multiplex",
+    );
+    let scenario_subcommand = SubCommand::with_name(CMD_SCENARIO)
+        .about(
+            "run every combination of backends, chunk sizes, concurrency and cache configs \
+             listed in a YAML or JSON scenario file, and print a summary comparing them",
+        )
+        .arg(
+            Arg::with_name(ARG_SCENARIO_FILE)
+                .takes_value(true)
+                .required(true),
+        );
+
+    let app = args::MononokeAppBuilder::new(NAME)
+        .with_all_repos()
+        .build()
+        .arg(
+            Arg::with_name(ARG_INPUT_CAPACITY)
+                .long(ARG_INPUT_CAPACITY)
+                .takes_value(true)
+                .required(false)
+                .default_value("8192"),
+        )
+        .arg(
+            Arg::with_name(ARG_CHUNK_SIZE)
+                .long(ARG_CHUNK_SIZE)
+                .takes_value(true)
+                .required(false)
+                .default_value("1048576"),
+        )
+        .arg(
+            Arg::with_name(ARG_CONCURRENCY)
+                .long(ARG_CONCURRENCY)
+                .takes_value(true)
+                .required(false)
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name(ARG_MEMCACHE)
+                .long(ARG_MEMCACHE)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(ARG_CACHELIB_SIZE)
+                .long(ARG_CACHELIB_SIZE)
+                .takes_value(true)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(ARG_DROP_CACHES)
+                .long(ARG_DROP_CACHES)
+                .required(false)
+                .help(
+                    "with caches, write straight to the backend, so that the first read is of \
+                     contents the caches have never seen",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_VERIFY)
+                .long(ARG_VERIFY)
+                .required(false)
+                .help(
+                    "hash the bytes of each read and check them against the hashes of the \
+                     contents, failing if any read failed or was corrupt (the hashing counts \
+                     towards the latency of the reads)",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_DELAY)
+                .long(ARG_DELAY)
+                .takes_value(true)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(ARG_RANDOMIZE)
+                .long(ARG_RANDOMIZE)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(ARG_READ_QPS)
+                .long(ARG_READ_QPS)
+                .takes_value(true)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(ARG_WRITE_QPS)
+                .long(ARG_WRITE_QPS)
+                .takes_value(true)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name(ARG_RAMP)
+                .long(ARG_RAMP)
+                .takes_value(true)
+                .required(false)
+                .conflicts_with(ARG_WRITE_QPS)
+                .help(
+                    "ramp the write QPS up linearly, as START:END:DURATION (e.g. 10:1000:5m), \
+                     and report the QPS at which the latency of the puts starts degrading",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_PACK)
+                .long(ARG_PACK)
+                .takes_value(true)
+                .required(false)
+                .help(
+                    "put packblob over the backend, compressing with zstd at this level (0 for \
+                     the zstd default), and report how much smaller the puts were stored",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_INJECT_LATENCY_MS)
+                .long(ARG_INJECT_LATENCY_MS)
+                .takes_value(true)
+                .required(false)
+                .help("add this many ms to every get and put of the backend"),
+        )
+        .arg(
+            Arg::with_name(ARG_INJECT_ERROR_RATE)
+                .long(ARG_INJECT_ERROR_RATE)
+                .takes_value(true)
+                .default_value("0")
+                .help(
+                    "the share of the gets and puts of the backend that fail with a transient \
+                     error, between 0 and 1",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_RETRY_ATTEMPTS)
+                .long(ARG_RETRY_ATTEMPTS)
+                .takes_value(true)
+                .default_value("1")
+                .help(
+                    "how many times the gets and puts of the backend are attempted before \
+                     failing, reporting how many more operations the retries made",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_READ_COUNT)
+                .long(ARG_READ_COUNT)
+                .takes_value(true)
+                .default_value("2")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(ARG_RANGE_READS)
+                .long(ARG_RANGE_READS)
+                .takes_value(true)
+                .default_value("0")
+                .help(
+                    "after the reads of the whole contents, read this many random ranges of \
+                     each of the --range-sizes, as on-demand fetching does",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_RANGE_SIZES)
+                .long(ARG_RANGE_SIZES)
+                .takes_value(true)
+                .default_value("4096,65536,1048576")
+                .help("comma-separated sizes in bytes of the ranges for --range-reads"),
+        )
+        .arg(
+            Arg::with_name(ARG_LOOKUPS)
+                .long(ARG_LOOKUPS)
+                .takes_value(true)
+                .default_value("0")
+                .help(
+                    "after the reads, look the contents up this many times by each of their \
+                     aliases (sha1, sha256 and git sha1), and look their metadata up as many \
+                     times",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_LOOKUP_QPS)
+                .long(ARG_LOOKUP_QPS)
+                .takes_value(true)
+                .required(false)
+                .help("how many --lookups to do per second, as fast as they go if not set"),
+        )
+        .arg(
+            Arg::with_name(ARG_COMPARE_PUT_BEHAVIOURS)
+                .long(ARG_COMPARE_PUT_BEHAVIOURS)
+                .required(false)
+                .help(
+                    "run the write a number of times with each put behaviour, with randomized \
+                     contents, and report how their latencies compare (ignored for scenarios)",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_PUT_BEHAVIOUR_SAMPLES)
+                .long(ARG_PUT_BEHAVIOUR_SAMPLES)
+                .takes_value(true)
+                .default_value("10")
+                .help("how many times to run the write with each put behaviour, for --compare-put-behaviours"),
+        )
+        .arg(
+            Arg::with_name(ARG_SWEEP)
+                .long(ARG_SWEEP)
+                .required(false)
+                .conflicts_with(ARG_COMPARE_PUT_BEHAVIOURS)
+                .help(
+                    "run every combination of --sweep-chunk-sizes and --sweep-concurrency \
+                     against the backend, and print a summary comparing them",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_SWEEP_CHUNK_SIZES)
+                .long(ARG_SWEEP_CHUNK_SIZES)
+                .takes_value(true)
+                .default_value("262144,1048576,4194304,16777216")
+                .help("comma-separated chunk sizes for --sweep"),
+        )
+        .arg(
+            Arg::with_name(ARG_SWEEP_CONCURRENCY)
+                .long(ARG_SWEEP_CONCURRENCY)
+                .takes_value(true)
+                .default_value("1,2,4,8,16")
+                .help("comma-separated concurrency levels for --sweep"),
+        )
+        .arg(
+            Arg::with_name(ARG_OUTPUT_FORMAT)
+                .long(ARG_OUTPUT_FORMAT)
+                .takes_value(true)
+                .possible_values(OUTPUT_FORMATS)
+                .default_value("text")
+                .help(
+                    "with json, also write a record of every operation as json, one per line, \
+                     for the results to be ingested by other tools",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_OUTPUT_FILE)
+                .long(ARG_OUTPUT_FILE)
+                .takes_value(true)
+                .required(false)
+                .help("write the json records to this file rather than stdout"),
+        )
+        .arg(
+            Arg::with_name(ARG_WORKLOAD)
+                .long(ARG_WORKLOAD)
+                .takes_value(true)
+                .possible_values(WORKLOADS)
+                .default_value(WORKLOAD_SINGLE)
+                .help(
+                    "single writes the input once then reads it, mixed keeps doing concurrent \
+                     reads and writes for a while (ignored for scenarios)",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_READ_RATIO)
+                .long(ARG_READ_RATIO)
+                .takes_value(true)
+                .default_value("0.8")
+                .help("for the mixed workload, the share of the operations that are reads"),
+        )
+        .arg(
+            Arg::with_name(ARG_DURATION)
+                .long(ARG_DURATION)
+                .takes_value(true)
+                .default_value("60s")
+                .help("for the mixed workload, how long to run it for"),
+        )
+        .arg(
+            Arg::with_name(ARG_WARMUP)
+                .long(ARG_WARMUP)
+                .takes_value(true)
+                .default_value("5s")
+                .help(
+                    "for the mixed workload, for how long at the start operations are not \
+                     counted, for the results to be of the steady state",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_WORKERS)
+                .long(ARG_WORKERS)
+                .takes_value(true)
+                .default_value("10")
+                .help(
+                    "for the mixed workload and --ramp, how many operations are in flight at \
+                     once (each of them with up to --concurrency chunks in flight)",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_PROGRESS_INTERVAL)
+                .long(ARG_PROGRESS_INTERVAL)
+                .takes_value(true)
+                .required(false)
+                .help(
+                    "for the mixed workload, log the throughput over each interval of this \
+                     length, to soak test with a long --duration and see it degrade over time",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_LATENCY_HISTOGRAM_FILE)
+                .long(ARG_LATENCY_HISTOGRAM_FILE)
+                .takes_value(true)
+                .required(false)
+                .help(
+                    "write the raw latency histograms to this file, as lines of \
+                     '<histogram> <latency in us> <count>' (ignored for scenarios and put \
+                     behaviour comparisons)",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_INGEST_MANIFEST)
+                .long(ARG_INGEST_MANIFEST)
+                .required(false)
+                .help("the input is a list of files to ingest, one per line"),
+        )
+        .arg(
+            Arg::with_name(ARG_INGEST_CONCURRENCY)
+                .long(ARG_INGEST_CONCURRENCY)
+                .takes_value(true)
+                .default_value("10")
+                .help("when ingesting several files, how many of them to store at once"),
+        )
+        .arg(
+            Arg::with_name(ARG_STATSD_HOST)
+                .long(ARG_STATSD_HOST)
+                .takes_value(true)
+                .required(false)
+                .help(
+                    "send live metrics of the blobstore operations to StatsD at this host:port, \
+                     every second",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_PROMETHEUS_PORT)
+                .long(ARG_PROMETHEUS_PORT)
+                .takes_value(true)
+                .required(false)
+                .help("serve live metrics of the blobstore operations for Prometheus on this port"),
+        )
+        .arg(
+            Arg::with_name(ARG_PROMETHEUS_ADDRESS)
+                .long(ARG_PROMETHEUS_ADDRESS)
+                .takes_value(true)
+                .required(false)
+                .default_value("127.0.0.1")
+                .help(
+                    "the address that --prometheus-port listens on, e.g. 0.0.0.0 or :: for \
+                     Prometheus to scrape the metrics from another host",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_BASELINE)
+                .long(ARG_BASELINE)
+                .takes_value(true)
+                .required(false)
+                .help(
+                    "json records of a previous run (as written by --output-format json) to \
+                     compare the throughput with, failing if it regressed",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_REGRESSION_THRESHOLD)
+                .long(ARG_REGRESSION_THRESHOLD)
+                .takes_value(true)
+                .default_value("10")
+                .help("with --baseline, by how many percent the throughput may regress"),
+        )
+        .arg(
+            Arg::with_name(ARG_ALLOW_WRITES_TO_REPO)
+                .long(ARG_ALLOW_WRITES_TO_REPO)
+                .required(false)
+                .help(
+                    "let the benchmark write to the blobstore of a repo (with the repo \
+                     subcommand, or a repo backend in a scenario), which it refuses otherwise",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_INPUT)
+                .takes_value(true)
+                .required(true)
+                .help(
+                    "the file to store, or a directory or manifest (with --ingest-manifest) of \
+                     files to ingest",
+                ),
+        )
+        .subcommand(manifold_subcommand)
+        .subcommand(memory_subcommand)
+        .subcommand(xdb_subcommand)
+        .subcommand(fileblob_subcommand)
+        .subcommand(multiplexed_subcommand)
+        .subcommand(repo_subcommand)
+        .subcommand(scenario_subcommand);
+
+    let matches = app.get_matches();
+
+    let logger = args::init_logging(fb, &matches)?;
+    let config_store = args::init_config_store(fb, &logger, &matches)?;
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+
+    let mut runtime = args::init_runtime(&matches)?;
+    metrics::start_exporters(&runtime, &parse_export_options(&matches)?)?;
+
+    let output_format: OutputFormat = matches.value_of(ARG_OUTPUT_FORMAT).unwrap().parse()?;
+    let mut output = Output::new(output_format, matches.value_of(ARG_OUTPUT_FILE))?;
+
+    // Loaded before running, for a bad baseline to fail the benchmark before it runs.
+    let baseline = matches
+        .value_of(ARG_BASELINE)
+        .map(Baseline::load)
+        .transpose()?;
+    let threshold: f64 = matches
+        .value_of(ARG_REGRESSION_THRESHOLD)
+        .unwrap()
+        .parse()?;
+    if threshold < 0.0 {
+        bail!(
+            "The regression threshold must be positive, not {}",
+            threshold
+        );
+    }
+
+    run(fb, &ctx, &matches, config_store, &mut runtime, &mut output)?;
+
+    if let Some(baseline) = baseline {
+        baseline.compare(
+            output.throughputs(),
+            output.stats(),
+            threshold,
+            &mut output.tables(),
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,260 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Live metrics of the operations on the blobstore, for long runs to be watched on dashboards
+//! as they go rather than only once they are done.
+//!
+//! With `--statsd-host`, the counters are sent to StatsD every `STATSD_INTERVAL`, as the change
+//! since the previous flush, so that their rate is the throughput. With `--prometheus-port`,
+//! they are served over HTTP (at any path, `/metrics` included) as totals, for the throughput to
+//! be `rate()` of them. Either way, the operations in flight are a gauge. The metrics are only
+//! served to the local host, unless `--prometheus-address` says otherwise.
+//!
+//! The metrics are of the whole process, so that the runs of a scenario add up.
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::{format_err, Context, Error, Result};
+use async_trait::async_trait;
+use blobstore::{Blobstore, BlobstoreGetData};
+use context::CoreContext;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use mononoke_types::BlobstoreBytes;
+use tokio::runtime::Runtime;
+
+use crate::NAME;
+
+const STATSD_INTERVAL: Duration = Duration::from_secs(1);
+
+struct Metrics {
+    gets: AtomicU64,
+    puts: AtomicU64,
+    errors: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    in_flight: AtomicU64,
+}
+
+static METRICS: Metrics = Metrics {
+    gets: AtomicU64::new(0),
+    puts: AtomicU64::new(0),
+    errors: AtomicU64::new(0),
+    bytes_read: AtomicU64::new(0),
+    bytes_written: AtomicU64::new(0),
+    in_flight: AtomicU64::new(0),
+};
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Counter,
+    Gauge,
+}
+
+impl Metrics {
+    fn snapshot(&self) -> [(&'static str, Kind, u64); 6] {
+        let load = |metric: &AtomicU64| metric.load(Ordering::Relaxed);
+        [
+            ("gets", Kind::Counter, load(&self.gets)),
+            ("puts", Kind::Counter, load(&self.puts)),
+            ("errors", Kind::Counter, load(&self.errors)),
+            ("bytes_read", Kind::Counter, load(&self.bytes_read)),
+            ("bytes_written", Kind::Counter, load(&self.bytes_written)),
+            ("in_flight", Kind::Gauge, load(&self.in_flight)),
+        ]
+    }
+
+    /// In the Prometheus text format.
+    fn prometheus(&self) -> String {
+        let mut text = String::new();
+        for (name, kind, value) in self.snapshot().iter() {
+            let (name, kind) = match kind {
+                Kind::Counter => (format!("{}_{}_total", NAME, name), "counter"),
+                Kind::Gauge => (format!("{}_{}", NAME, name), "gauge"),
+            };
+            text.push_str(&format!("# TYPE {} {}\n{} {}\n", name, kind, name, value));
+        }
+        text
+    }
+}
+
+/// An operation in flight, until dropped.
+struct InFlight;
+
+impl InFlight {
+    fn start() -> Self {
+        METRICS.in_flight.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        METRICS.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn count<T>(operations: &AtomicU64, res: &Result<T>) {
+    operations.fetch_add(1, Ordering::Relaxed);
+    if res.is_err() {
+        METRICS.errors.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ExportOptions {
+    pub statsd: Option<SocketAddr>,
+    /// Where the metrics are served for Prometheus.
+    pub prometheus: Option<SocketAddr>,
+}
+
+impl ExportOptions {
+    pub fn new(
+        statsd_host: Option<&str>,
+        prometheus_address: &str,
+        prometheus_port: Option<u16>,
+    ) -> Result<Self> {
+        let statsd = statsd_host
+            .map(|host| {
+                host.to_socket_addrs()
+                    .with_context(|| format!("Failed to resolve {}", host))?
+                    .next()
+                    .ok_or_else(|| format_err!("No address for {}", host))
+            })
+            .transpose()?;
+        let prometheus = prometheus_port
+            .map(|port| {
+                let ip: IpAddr = prometheus_address
+                    .parse()
+                    .with_context(|| format!("Invalid address {}", prometheus_address))?;
+                Ok::<_, Error>(SocketAddr::new(ip, port))
+            })
+            .transpose()?;
+        Ok(Self { statsd, prometheus })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.statsd.is_some() || self.prometheus.is_some()
+    }
+}
+
+/// Start exporting the metrics in the background, as long as `runtime` runs.
+pub fn start_exporters(runtime: &Runtime, options: &ExportOptions) -> Result<(), Error> {
+    if let Some(addr) = options.statsd {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+        runtime.spawn(async move {
+            let mut last = METRICS.snapshot();
+            loop {
+                tokio_shim::time::sleep(STATSD_INTERVAL).await;
+                let snapshot = METRICS.snapshot();
+                for ((name, kind, value), (_, _, previous)) in snapshot.iter().zip(last.iter()) {
+                    let line = match kind {
+                        Kind::Counter => format!("{}.{}:{}|c", NAME, name, value - previous),
+                        Kind::Gauge => format!("{}.{}:{}|g", NAME, name, value),
+                    };
+                    // StatsD is best effort, as UDP is.
+                    let _ = socket.send(line.as_bytes());
+                }
+                last = snapshot;
+            }
+        });
+        eprintln!("Sending metrics to StatsD at {}", addr);
+    }
+
+    if let Some(addr) = options.prometheus {
+        let builder = runtime
+            .enter(|| Server::try_bind(&addr))
+            .with_context(|| format!("Failed to listen on {}", addr))?;
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, hyper::Error>(service_fn(|_: Request<Body>| async {
+                Ok::<_, hyper::Error>(Response::new(Body::from(METRICS.prometheus())))
+            }))
+        });
+        runtime.spawn(async move {
+            if let Err(e) = builder.serve(make_service).await {
+                eprintln!("Serving metrics failed: {:?}", e);
+            }
+        });
+        eprintln!("Serving metrics for Prometheus at http://{}/metrics", addr);
+    }
+
+    Ok(())
+}
+
+/// Counts the operations on a blobstore into the metrics.
+pub struct MetricsBlob<T> {
+    blobstore: T,
+}
+
+impl<T> MetricsBlob<T> {
+    pub fn new(blobstore: T) -> Self {
+        Self { blobstore }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for MetricsBlob<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MetricsBlob<{}>", &self.blobstore)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MetricsBlob<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricsBlob")
+            .field("blobstore", &self.blobstore)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<T: Blobstore> Blobstore for MetricsBlob<T> {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let in_flight = InFlight::start();
+        let res = self.blobstore.get(ctx, key).await;
+        drop(in_flight);
+        count(&METRICS.gets, &res);
+        if let Ok(Some(data)) = &res {
+            METRICS
+                .bytes_read
+                .fetch_add(data.as_bytes().len() as u64, Ordering::Relaxed);
+        }
+        res
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        let len = value.len() as u64;
+        let in_flight = InFlight::start();
+        let res = self.blobstore.put(ctx, key, value).await;
+        drop(in_flight);
+        count(&METRICS.puts, &res);
+        if res.is_ok() {
+            METRICS.bytes_written.fetch_add(len, Ordering::Relaxed);
+        }
+        res
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        let in_flight = InFlight::start();
+        let res = self.blobstore.is_present(ctx, key).await;
+        drop(in_flight);
+        count(&METRICS.gets, &res);
+        res
+    }
+}
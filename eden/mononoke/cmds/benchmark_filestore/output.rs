@@ -0,0 +1,309 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Machine readable results, for `--output-format json`: one JSON object per line for every
+//! operation of every run, along with the parameters of the run, e.g.:
+//!
+//! ```json
+//! {"backend":"memory","cache":"none","put_behaviour":"IfAbsent","chunk_size":1048576,"concurrency":1,"operation":"write","bytes":10485760,"latency_ms":12.3,"throughput_mb_per_s":813.0}
+//! ```
+//!
+//! The stats of the run that aren't about a single operation (see `run_stats`) follow its
+//! operations, one per line, e.g.:
+//!
+//! ```json
+//! {"backend":"memory","cache":"none","put_behaviour":"IfAbsent","chunk_size":1048576,"concurrency":1,"stat":"faults.amplification","value":1.25}
+//! ```
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Error};
+use blobstore::PutBehaviour;
+use futures_stats::FutureStats;
+use serde::{Deserialize, Serialize};
+
+use crate::baseline::Throughputs;
+use crate::cache_phases::ReadPhase;
+use crate::run_stats::RunStats;
+use crate::scenario::{BackendConfig, CacheConfig};
+use crate::BenchmarkOptions;
+
+pub const OUTPUT_FORMATS: &[&str] = &["text", "json"];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// Only log the operations and print the tables.
+    Text,
+    /// Also write a record for every operation.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => bail!("Invalid output format {}", s),
+        }
+    }
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+    Deserialize
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Write,
+    Read,
+    RangeRead,
+    Sha1Lookup,
+    Sha256Lookup,
+    GitSha1Lookup,
+    MetadataLookup,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Write => write!(fmt, "write"),
+            Self::Read => write!(fmt, "read"),
+            Self::RangeRead => write!(fmt, "range_read"),
+            Self::Sha1Lookup => write!(fmt, "sha1_lookup"),
+            Self::Sha256Lookup => write!(fmt, "sha256_lookup"),
+            Self::GitSha1Lookup => write!(fmt, "git_sha1_lookup"),
+            Self::MetadataLookup => write!(fmt, "metadata_lookup"),
+        }
+    }
+}
+
+/// How a single operation of a run went.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OperationRecord {
+    operation: Operation,
+    bytes: u64,
+    latency_ms: f64,
+    /// In MB/s. `None` if the operation failed.
+    throughput_mb_per_s: Option<f64>,
+    /// For reads through caches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    phase: Option<ReadPhase>,
+    #[serde(skip)]
+    latency: Duration,
+}
+
+impl OperationRecord {
+    pub fn new(
+        operation: Operation,
+        stats: &FutureStats,
+        bytes: u64,
+        throughput_mb_per_s: Option<f64>,
+    ) -> Self {
+        Self {
+            operation,
+            bytes,
+            latency_ms: stats.completion_time.as_secs_f64() * 1000.0,
+            throughput_mb_per_s,
+            phase: None,
+            latency: stats.completion_time,
+        }
+    }
+
+    pub fn with_phase(self, phase: ReadPhase) -> Self {
+        Self {
+            phase: Some(phase),
+            ..self
+        }
+    }
+
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    pub fn phase(&self) -> Option<ReadPhase> {
+        self.phase
+    }
+
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn throughput_mb_per_s(&self) -> Option<f64> {
+        self.throughput_mb_per_s
+    }
+}
+
+/// An operation of a run, along with the parameters of the run.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub backend: String,
+    pub cache: String,
+    pub put_behaviour: String,
+    pub chunk_size: u64,
+    pub concurrency: usize,
+    #[serde(flatten)]
+    pub operation: OperationRecord,
+}
+
+/// A stat of a run, along with the parameters of the run.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StatRecord {
+    pub backend: String,
+    pub cache: String,
+    pub put_behaviour: String,
+    pub chunk_size: u64,
+    pub concurrency: usize,
+    pub stat: String,
+    pub value: f64,
+}
+
+pub struct Output {
+    format: OutputFormat,
+    /// Where the records go. Stdout if `None`.
+    file: Option<File>,
+    /// Of all the records so far, whatever the format, for the comparison with a baseline.
+    throughputs: Throughputs,
+    /// All the stats so far, whatever the format. There are a few per run at most.
+    stats: Vec<StatRecord>,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat, path: Option<&str>) -> Result<Self, Error> {
+        let file = path
+            .map(|path| File::create(path).with_context(|| format!("Failed to create {}", path)))
+            .transpose()?;
+        Ok(Self {
+            format,
+            file,
+            throughputs: Throughputs::default(),
+            stats: Vec::new(),
+        })
+    }
+
+    fn write_json<T: Serialize>(&mut self, records: &[T]) -> Result<(), Error> {
+        let stdout = io::stdout();
+        let mut out: Box<dyn Write + '_> = match &mut self.file {
+            Some(file) => Box::new(file),
+            None => Box::new(stdout.lock()),
+        };
+        for record in records {
+            serde_json::to_writer(&mut out, record)?;
+            writeln!(out)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Record the operations of a run. With the json format, they are written straight away, so
+    /// that the ones of the runs that completed survive a later run crashing. Runs that go on
+    /// for long record their operations as they complete, rather than all at the end.
+    pub fn record(
+        &mut self,
+        backend: &BackendConfig,
+        cache: &CacheConfig,
+        put_behaviour: PutBehaviour,
+        options: &BenchmarkOptions,
+        operations: &[OperationRecord],
+    ) -> Result<(), Error> {
+        let records: Vec<_> = operations
+            .iter()
+            .map(|operation| RunRecord {
+                backend: backend.to_string(),
+                cache: cache.to_string(),
+                put_behaviour: put_behaviour.to_string(),
+                chunk_size: options.chunk_size,
+                concurrency: options.concurrency,
+                operation: operation.clone(),
+            })
+            .collect();
+
+        if self.format == OutputFormat::Json {
+            self.write_json(&records)?;
+        }
+
+        for record in &records {
+            self.throughputs.add(record);
+        }
+        Ok(())
+    }
+
+    /// Record the stats of a run once it is done, and print them with the tables.
+    pub fn record_stats(
+        &mut self,
+        backend: &BackendConfig,
+        cache: &CacheConfig,
+        put_behaviour: PutBehaviour,
+        options: &BenchmarkOptions,
+        stats: &RunStats,
+    ) -> Result<(), Error> {
+        let records: Vec<_> = stats
+            .report()
+            .into_iter()
+            .map(|stat| StatRecord {
+                backend: backend.to_string(),
+                cache: cache.to_string(),
+                put_behaviour: put_behaviour.to_string(),
+                chunk_size: options.chunk_size,
+                concurrency: options.concurrency,
+                stat: stat.name,
+                value: stat.value,
+            })
+            .collect();
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        if self.format == OutputFormat::Json {
+            self.write_json(&records)?;
+        }
+
+        let mut out = self.tables();
+        writeln!(out, "Stats of {}/{}/{}:", backend, cache, put_behaviour)?;
+        for record in &records {
+            writeln!(out, "{:<40} {:>14.2}", record.stat, record.value)?;
+        }
+
+        self.stats.extend(records);
+        Ok(())
+    }
+
+    pub fn throughputs(&self) -> &Throughputs {
+        &self.throughputs
+    }
+
+    pub fn stats(&self) -> &[StatRecord] {
+        &self.stats
+    }
+
+    /// Where the tables comparing runs are printed: stdout, unless the records are written
+    /// there, in which case they go to stderr so as not to get mixed in with the records.
+    pub fn tables(&self) -> Box<dyn Write> {
+        if self.format == OutputFormat::Json && self.file.is_none() {
+            Box::new(io::stderr())
+        } else {
+            Box::new(io::stdout())
+        }
+    }
+}
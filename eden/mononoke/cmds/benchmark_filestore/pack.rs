@@ -0,0 +1,201 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! The backend behind packblob, as `BlobConfig::Pack` sets it up, for the cost of compressing
+//! the chunks with zstd to be measured against what it saves: the time shows in the latency of
+//! the writes and reads, and the bytes that reach the backend are counted against the bytes that
+//! were put, in the stats of the run.
+//!
+//! Scenario files compare levels as they compare backends, e.g.
+//! `{ type = "pack", level = 3, backend = { type = "memory" } }`.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
+use context::CoreContext;
+use mononoke_types::BlobstoreBytes;
+use packblob::{PackBlob, PackOptions};
+
+use crate::run_stats::{ReportStats, Stat};
+
+struct PackStats {
+    level: i32,
+    /// Put to packblob.
+    put: AtomicU64,
+    /// Put by packblob to the backend, once compressed and wrapped in its envelope.
+    stored: AtomicU64,
+}
+
+impl ReportStats for PackStats {
+    fn report(&self) -> Vec<Stat> {
+        let put = self.put.load(Ordering::Relaxed);
+        if put == 0 {
+            return Vec::new();
+        }
+        let stored = self.stored.load(Ordering::Relaxed);
+        vec![
+            Stat::new("pack.level", self.level as f64),
+            Stat::new("pack.put_bytes", put as f64),
+            Stat::new("pack.stored_bytes", stored as f64),
+            Stat::new("pack.stored_pct", stored as f64 * 100.0 / put as f64),
+        ]
+    }
+}
+
+/// Counts the bytes that packblob puts to the backend.
+struct StoredBytes {
+    blobstore: Arc<dyn BlobstorePutOps>,
+    stats: Arc<PackStats>,
+}
+
+impl StoredBytes {
+    fn count(&self, value: &BlobstoreBytes) {
+        self.stats
+            .stored
+            .fetch_add(value.len() as u64, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Display for StoredBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", &self.blobstore)
+    }
+}
+
+impl fmt::Debug for StoredBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", &self.blobstore)
+    }
+}
+
+#[async_trait]
+impl Blobstore for StoredBytes {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        self.blobstore.get(ctx, key).await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        self.count(&value);
+        self.blobstore.put(ctx, key, value).await
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        self.blobstore.is_present(ctx, key).await
+    }
+}
+
+#[async_trait]
+impl BlobstorePutOps for StoredBytes {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        self.count(&value);
+        self.blobstore
+            .put_explicit(ctx, key, value, put_behaviour)
+            .await
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        self.count(&value);
+        self.blobstore.put_with_status(ctx, key, value).await
+    }
+}
+
+/// Packblob over the backend, compressing at `level` (the zstd default if 0). How much the puts
+/// were compressed is in its `stats`.
+pub struct PackedBlob {
+    blobstore: PackBlob<StoredBytes>,
+    level: i32,
+    stats: Arc<PackStats>,
+}
+
+impl PackedBlob {
+    pub fn new(blobstore: Arc<dyn BlobstorePutOps>, level: i32) -> Self {
+        let stats = Arc::new(PackStats {
+            level,
+            put: AtomicU64::new(0),
+            stored: AtomicU64::new(0),
+        });
+        let stored = StoredBytes {
+            blobstore,
+            stats: stats.clone(),
+        };
+        Self {
+            blobstore: PackBlob::new(stored, PackOptions::new(Some(level))),
+            level,
+            stats,
+        }
+    }
+
+    pub fn stats(&self) -> Arc<dyn ReportStats> {
+        self.stats.clone()
+    }
+}
+
+impl fmt::Display for PackedBlob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", &self.blobstore)
+    }
+}
+
+impl fmt::Debug for PackedBlob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PackedBlob")
+            .field("blobstore", &self.blobstore)
+            .field("level", &self.level)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Blobstore for PackedBlob {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        self.blobstore.get(ctx, key).await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        self.stats
+            .put
+            .fetch_add(value.len() as u64, Ordering::Relaxed);
+        self.blobstore.put(ctx, key, value).await
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        self.blobstore.is_present(ctx, key).await
+    }
+}
@@ -0,0 +1,466 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A ramp of the write QPS, for where the backend saturates, e.g. `--ramp 10:1000:5m` goes from
+//! 10 to 1000 puts per second over 5 minutes.
+//!
+//! The ramp is made of steps of about `STEP` each, and every step throttles the puts with a
+//! `ThrottledBlob` of its own, at a QPS between the start and the end of the ramp. Workers store
+//! the input (with a random prefix, as the mixed workload does) as fast as the throttle lets
+//! them. The latency of the puts is measured under the throttle, so that it's the one of the
+//! backend rather than the time waiting for quota.
+//!
+//! The backend is saturated at the first step where the p99 of the puts is `DEGRADATION_FACTOR`
+//! times the one of the first step, or where the puts fall short of the QPS of the step. Puts
+//! can only fall short because of the backend if the workers could have made more of them: with
+//! `--workers` times `--concurrency` puts in flight at most, at the mean latency of the puts,
+//! there can't be more puts per second than the in-flight limit divided by that latency. Steps
+//! that get close to that limit are bound by the client, and are reported as such rather than
+//! as the backend saturating, as a ramp with more workers would get further.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, format_err, Error};
+use blobstore::Blobstore;
+use bytes::Bytes;
+use context::CoreContext;
+use filestore::FilestoreConfig;
+use futures::future;
+use throttledblob::{ThrottleOptions, ThrottledBlob};
+
+use crate::latency::{Latencies, LatencyBlob, LatencyHistogram};
+use crate::output::OperationRecord;
+use crate::workload;
+use crate::BenchmarkOptions;
+
+/// How long each step of the ramp roughly lasts.
+const STEP: Duration = Duration::from_secs(10);
+
+/// How much worse than at the start the p99 of the puts gets for the backend to be saturated.
+const DEGRADATION_FACTOR: f64 = 2.0;
+
+/// The share of the QPS of a step that the puts have to reach for the backend to keep up.
+const MIN_QPS_RATIO: f64 = 0.9;
+
+/// A linear ramp of the write QPS, parsed from `START:END:DURATION`.
+#[derive(Clone, Copy, Debug)]
+pub struct Ramp {
+    pub start: NonZeroU32,
+    pub end: NonZeroU32,
+    pub duration: Duration,
+}
+
+impl FromStr for Ramp {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let parts: Vec<_> = s.split(':').collect();
+        let (start, end, duration) = match parts.as_slice() {
+            [start, end, duration] => (start, end, duration),
+            _ => bail!("Invalid ramp {}, it must be START:END:DURATION", s),
+        };
+        let ramp = Self {
+            start: start.parse()?,
+            end: end.parse()?,
+            duration: humantime::parse_duration(duration)?,
+        };
+        if ramp.start >= ramp.end {
+            bail!(
+                "The ramp must go up, not from {} to {}",
+                ramp.start,
+                ramp.end
+            );
+        }
+        Ok(ramp)
+    }
+}
+
+impl fmt::Display for Ramp {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            fmt,
+            "{} to {} QPS over {:?}",
+            self.start, self.end, self.duration
+        )
+    }
+}
+
+impl Ramp {
+    /// The QPS of each step, and how long they last. There are at least 2 steps, for the start
+    /// and the end.
+    fn steps(&self) -> (Vec<NonZeroU32>, Duration) {
+        let count = (self.duration.as_secs_f64() / STEP.as_secs_f64()).ceil() as u32;
+        let count = count.max(2);
+        let (start, end) = (self.start.get() as u64, self.end.get() as u64);
+        let qps = (0..count as u64)
+            .map(|step| {
+                let qps = start + (end - start) * step / (count as u64 - 1);
+                NonZeroU32::new(qps as u32).expect("the ramp starts above 0")
+            })
+            .collect();
+        (qps, self.duration / count)
+    }
+}
+
+/// How the puts went at one step of the ramp.
+struct RampStep {
+    qps: NonZeroU32,
+    /// How many puts the workers can have in flight at once.
+    max_in_flight: usize,
+    puts_per_s: f64,
+    failed: usize,
+    put: LatencyHistogram,
+    write: LatencyHistogram,
+}
+
+impl RampStep {
+    fn keeps_up(&self) -> bool {
+        self.puts_per_s >= self.qps.get() as f64 * MIN_QPS_RATIO
+    }
+
+    /// The most puts per second that the workers can make at the latency of the puts.
+    fn client_limit(&self) -> Option<f64> {
+        let mean_ms = self.put.mean_ms()?;
+        Some(self.max_in_flight as f64 * 1000.0 / mean_ms)
+    }
+
+    fn is_client_bound(&self) -> bool {
+        self.client_limit()
+            .map_or(false, |limit| self.puts_per_s >= limit * MIN_QPS_RATIO)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum StepState {
+    KeepsUp,
+    /// The p99 of the puts is `DEGRADATION_FACTOR` times the one of the first step.
+    Degraded,
+    /// The puts fell short of the QPS of the step, and not because of the workers.
+    FellShort,
+    /// The puts fell short of the QPS of the step because the workers couldn't make more.
+    ClientBound,
+}
+
+impl StepState {
+    fn is_saturated(self) -> bool {
+        self == Self::Degraded || self == Self::FellShort
+    }
+}
+
+fn format_latency(latency_ms: Option<f64>) -> String {
+    latency_ms.map_or_else(|| "-".to_string(), |latency| format!("{:.2}", latency))
+}
+
+pub struct RampResult {
+    steps: Vec<RampStep>,
+    states: Vec<StepState>,
+    /// The step at which the backend saturated, if it did.
+    saturated: Option<usize>,
+    /// Every write of the ramp, for the json output.
+    pub operations: Vec<OperationRecord>,
+}
+
+impl RampResult {
+    /// Print a table of how the puts went at each step to `out`, and where the backend saturated.
+    pub fn print(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "{:>10} {:>10} {:>8} {:>12} {:>12} {:>14}",
+            "QPS", "puts/s", "failed", "put p50 ms", "put p99 ms", "write p99 ms"
+        )?;
+        for (idx, (step, state)) in self.steps.iter().zip(&self.states).enumerate() {
+            writeln!(
+                out,
+                "{:>10} {:>10.2} {:>8} {:>12} {:>12} {:>14}{}",
+                step.qps,
+                step.puts_per_s,
+                step.failed,
+                format_latency(step.put.percentile_ms(50.0)),
+                format_latency(step.put.percentile_ms(99.0)),
+                format_latency(step.write.percentile_ms(99.0)),
+                if Some(idx) == self.saturated {
+                    "  <- saturated"
+                } else if *state == StepState::ClientBound {
+                    "  <- client-bound"
+                } else {
+                    ""
+                },
+            )?;
+        }
+
+        let client_bound = self
+            .steps
+            .iter()
+            .zip(&self.states)
+            .find(|(_, state)| **state == StepState::ClientBound);
+        if let Some((step, _)) = client_bound {
+            writeln!(
+                out,
+                "From {} QPS, the puts were bound by the {} puts the workers can have in flight \
+                 rather than by the backend: ramp with more workers to go further",
+                step.qps, step.max_in_flight
+            )?;
+        }
+        match self.saturated {
+            Some(idx) => {
+                let step = &self.steps[idx];
+                match self.states[idx] {
+                    StepState::Degraded => {
+                        writeln!(out, "Latency started degrading at {} QPS", step.qps)
+                    }
+                    _ => writeln!(
+                        out,
+                        "The backend fell short of {} QPS, at {:.2} puts/s",
+                        step.qps, step.puts_per_s
+                    ),
+                }
+            }
+            None if client_bound.is_some() => writeln!(
+                out,
+                "No degradation of the backend up to where the client bound the puts"
+            ),
+            None => writeln!(out, "No degradation up to {} QPS", self.end()),
+        }
+    }
+
+    fn end(&self) -> u32 {
+        self.steps.last().map_or(0, |step| step.qps.get())
+    }
+}
+
+/// How each step went, compared to the first one. Empty if the first step made no puts.
+fn step_states(steps: &[RampStep]) -> Vec<StepState> {
+    let baseline = match steps.first().and_then(|step| step.put.percentile_ms(99.0)) {
+        Some(baseline) => baseline,
+        None => return Vec::new(),
+    };
+    steps
+        .iter()
+        .map(|step| {
+            let degraded = step
+                .put
+                .percentile_ms(99.0)
+                .map_or(true, |p99| p99 > baseline * DEGRADATION_FACTOR);
+            if degraded {
+                StepState::Degraded
+            } else if step.keeps_up() {
+                StepState::KeepsUp
+            } else if step.is_client_bound() {
+                StepState::ClientBound
+            } else {
+                StepState::FellShort
+            }
+        })
+        .collect()
+}
+
+/// The first step at which the puts are `DEGRADATION_FACTOR` times slower than at the first
+/// step, or at which they don't keep up for reasons other than the workers.
+fn find_saturation(steps: &[RampStep]) -> Option<usize> {
+    step_states(steps)
+        .into_iter()
+        .position(StepState::is_saturated)
+}
+
+pub async fn run_ramp(
+    ctx: &CoreContext,
+    options: &BenchmarkOptions,
+    ramp: &Ramp,
+    workers: usize,
+    blob: Arc<dyn Blobstore>,
+) -> Result<RampResult, Error> {
+    let config = FilestoreConfig {
+        chunk_size: Some(options.chunk_size),
+        concurrency: options.concurrency,
+        inline_threshold: None,
+    };
+
+    let data = Bytes::from(tokio::fs::read(&options.input).await?);
+
+    let (qps, step_duration) = ramp.steps();
+    eprintln!(
+        "Write ramp with {:?}, {} workers, {} in {} steps of {:?}, writing into {:?}",
+        config,
+        workers,
+        ramp,
+        qps.len(),
+        step_duration,
+        blob
+    );
+
+    let mut steps = Vec::with_capacity(qps.len());
+    let mut operations = Vec::new();
+    for qps in qps {
+        let latencies = Arc::new(Mutex::new(Latencies::default()));
+        let measured: Arc<dyn Blobstore> =
+            Arc::new(LatencyBlob::new(blob.clone(), latencies.clone()));
+        let throttled: Arc<dyn Blobstore> = Arc::new(
+            ThrottledBlob::new(
+                measured,
+                ThrottleOptions {
+                    write_qps: Some(qps),
+                    ..ThrottleOptions::default()
+                },
+            )
+            .await,
+        );
+
+        let start = Instant::now();
+        let deadline = start + step_duration;
+        let worker = |_| {
+            let (throttled, data) = (&throttled, &data);
+            async move {
+                let mut records = Vec::new();
+                while Instant::now() < deadline {
+                    let (record, latency, _) = workload::write(ctx, throttled, config, data).await;
+                    records.push((record, latency));
+                }
+                records
+            }
+        };
+        let records: Vec<_> = future::join_all((0..workers).map(worker))
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+        // The last writes may end after the deadline.
+        let elapsed = start.elapsed();
+
+        let mut write = LatencyHistogram::default();
+        let mut failed = 0;
+        for (record, latency) in &records {
+            match record.throughput_mb_per_s() {
+                Some(_) => write.record(*latency),
+                None => failed += 1,
+            }
+        }
+        let put = latencies.lock().expect("lock poisoned").put.clone();
+        let step = RampStep {
+            qps,
+            max_in_flight: workers * options.concurrency,
+            puts_per_s: put.samples() as f64 / elapsed.as_secs_f64(),
+            failed,
+            put,
+            write,
+        };
+        eprintln!(
+            "Ramp at {} QPS: {:.2} puts/s, {} writes failed, puts {}",
+            step.qps, step.puts_per_s, step.failed, step.put
+        );
+        steps.push(step);
+        operations.extend(records.into_iter().map(|(record, _)| record));
+    }
+
+    if steps.iter().all(|step| step.put.samples() == 0) {
+        return Err(format_err!("No puts were made over the ramp"));
+    }
+    let states = step_states(&steps);
+    let saturated = find_saturation(&steps);
+
+    Ok(RampResult {
+        steps,
+        states,
+        saturated,
+        operations,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn qps(qps: u32) -> NonZeroU32 {
+        NonZeroU32::new(qps).expect("the QPS is not 0")
+    }
+
+    /// A step at `qps` that made `puts_per_s`, with every put taking `put_ms`, and 10 puts in
+    /// flight at most.
+    fn step(step_qps: u32, puts_per_s: f64, put_ms: u64) -> RampStep {
+        let mut put = LatencyHistogram::default();
+        for _ in 0..100 {
+            put.record(Duration::from_millis(put_ms));
+        }
+        RampStep {
+            qps: qps(step_qps),
+            max_in_flight: 10,
+            puts_per_s,
+            failed: 0,
+            put,
+            write: LatencyHistogram::default(),
+        }
+    }
+
+    #[test]
+    fn test_steps() -> Result<(), Error> {
+        let ramp: Ramp = "10:100:40s".parse()?;
+        let (steps, duration) = ramp.steps();
+        assert_eq!(steps, vec![qps(10), qps(40), qps(70), qps(100)]);
+        assert_eq!(duration, Duration::from_secs(10));
+
+        // Shorter than a step, there are still the start and the end.
+        let ramp: Ramp = "10:100:1s".parse()?;
+        let (steps, duration) = ramp.steps();
+        assert_eq!(steps, vec![qps(10), qps(100)]);
+        assert_eq!(duration, Duration::from_millis(500));
+
+        assert!("100:10:40s".parse::<Ramp>().is_err());
+        assert!("10:100".parse::<Ramp>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_saturation() {
+        let steps = vec![step(10, 10.0, 10), step(100, 99.0, 12)];
+        assert_eq!(find_saturation(&steps), None);
+        assert_eq!(find_saturation(&[]), None);
+    }
+
+    #[test]
+    fn test_degraded() {
+        let steps = vec![step(10, 10.0, 10), step(50, 50.0, 15), step(100, 100.0, 30)];
+        assert_eq!(find_saturation(&steps), Some(2));
+        assert_eq!(step_states(&steps)[2], StepState::Degraded);
+    }
+
+    #[test]
+    fn test_fell_short() {
+        // 10 puts in flight of 10ms each could make 1000 puts/s.
+        let steps = vec![step(10, 10.0, 10), step(500, 200.0, 10)];
+        assert_eq!(find_saturation(&steps), Some(1));
+        assert_eq!(step_states(&steps)[1], StepState::FellShort);
+    }
+
+    #[test]
+    fn test_client_bound() {
+        // 10 puts in flight of 19ms each can't make more than about 526 puts/s.
+        let steps = vec![
+            step(10, 10.0, 10),
+            step(500, 500.0, 19),
+            step(1000, 520.0, 19),
+        ];
+        let states = step_states(&steps);
+        assert_eq!(
+            states,
+            vec![
+                StepState::KeepsUp,
+                StepState::KeepsUp,
+                StepState::ClientBound
+            ]
+        );
+        assert_eq!(find_saturation(&steps), None);
+
+        // Past the client bound, the latency degrading is still the backend saturating.
+        let mut steps = steps;
+        steps.push(step(1000, 300.0, 40));
+        assert_eq!(find_saturation(&steps), Some(3));
+    }
+}
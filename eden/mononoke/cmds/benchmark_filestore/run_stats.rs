@@ -0,0 +1,54 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! What the wrappers of the backend count over a run, such as the retry amplification of the
+//! injected faults. The wrappers hand their stats out when the blobstore is set up, and the run
+//! records them with `Output::record_stats` once it is done, for them to be in the json output
+//! and in the comparison with a baseline as the operations are.
+
+use std::sync::Arc;
+
+/// A figure about a whole run, rather than about one of its operations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stat {
+    pub name: String,
+    pub value: f64,
+}
+
+impl Stat {
+    pub fn new(name: impl Into<String>, value: f64) -> Self {
+        Self {
+            name: name.into(),
+            value,
+        }
+    }
+}
+
+/// The stats that a wrapper counts, shared with the run that it was made for.
+pub trait ReportStats: Send + Sync {
+    /// Empty if the run made no operations through the wrapper.
+    fn report(&self) -> Vec<Stat>;
+}
+
+/// The stats of all the wrappers of the blobstore of a run.
+#[derive(Clone, Default)]
+pub struct RunStats {
+    reporters: Vec<Arc<dyn ReportStats>>,
+}
+
+impl RunStats {
+    pub fn add(&mut self, reporter: Arc<dyn ReportStats>) {
+        self.reporters.push(reporter);
+    }
+
+    pub fn report(&self) -> Vec<Stat> {
+        self.reporters
+            .iter()
+            .flat_map(|reporter| reporter.report())
+            .collect()
+    }
+}
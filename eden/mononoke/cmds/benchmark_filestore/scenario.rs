@@ -0,0 +1,568 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Scenario files describe a matrix of benchmark runs, in YAML or JSON, e.g.:
+//!
+//! ```yaml
+//! backends:
+//!   - type: memory
+//!   - type: xdb
+//!     shardmap: xdb.mononoke_test
+//!     shard_count: 10
+//!     myrouter_port: 3307
+//!   - type: fileblob
+//!     path: /tmp/benchmark_filestore
+//!     fsync: true
+//!   - type: pack
+//!     level: 3
+//!     backend: { type: memory }
+//!   - type: multiplexed
+//!     minimum_successful_writes: 1
+//!     components:
+//!       - type: memory
+//!       - type: fileblob
+//!         path: /tmp/benchmark_filestore
+//!   - type: repo
+//!     name: fbsource
+//! chunk_sizes: [1048576, 4194304]
+//! concurrency: [1, 10]
+//! caches: [{}, { memcache: true }]
+//! ```
+//!
+//! The format is picked from the extension of the file: `.yaml`, `.yml` or `.json`.
+//!
+//! Every combination is run. Dimensions that are left out use the values from the command line.
+//!
+//! `--sweep` runs a scenario of chunk sizes and concurrency against the backend of the command
+//! line, without needing a file.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error};
+use serde::Deserialize;
+
+use crate::{BenchmarkOptions, BenchmarkResult};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum BackendConfig {
+    Manifold {
+        bucket: String,
+    },
+    Memory,
+    /// Sqlblob over the shards of `shardmap`. It connects as the mysql flags of the command line
+    /// say, unless it has a MyRouter port or is to use the mysql client itself.
+    Xdb {
+        shardmap: String,
+        shard_count: NonZeroUsize,
+        #[serde(default)]
+        myrouter_port: Option<u16>,
+        #[serde(default)]
+        use_mysql_client: bool,
+    },
+    Fileblob {
+        path: String,
+        #[serde(default)]
+        fsync: bool,
+    },
+    /// Packblob over another backend, compressing at `level` (the zstd default if 0).
+    Pack {
+        #[serde(default)]
+        level: i32,
+        backend: Box<BackendConfig>,
+    },
+    /// A multiplex of the backends, as the multiplexed blobstore of a repo would be, but with
+    /// its sync queue in memory.
+    Multiplexed {
+        components: Vec<BackendConfig>,
+        #[serde(default)]
+        write_mostly: Vec<BackendConfig>,
+        minimum_successful_writes: NonZeroUsize,
+    },
+    /// The blobstore of a repo, as its config describes it, e.g. a multiplex with its sync queue.
+    Repo {
+        name: String,
+    },
+}
+
+fn write_backends(fmt: &mut fmt::Formatter<'_>, backends: &[BackendConfig]) -> fmt::Result {
+    for (idx, backend) in backends.iter().enumerate() {
+        if idx > 0 {
+            write!(fmt, ",")?;
+        }
+        write!(fmt, "{}", backend)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for BackendConfig {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Manifold { bucket } => write!(fmt, "manifold:{}", bucket),
+            Self::Memory => write!(fmt, "memory"),
+            Self::Xdb {
+                shardmap,
+                shard_count,
+                ..
+            } => write!(fmt, "xdb:{}/{}", shardmap, shard_count),
+            Self::Fileblob { path, fsync } => {
+                write!(fmt, "fileblob:{}", path)?;
+                if *fsync {
+                    write!(fmt, "+fsync")?;
+                }
+                Ok(())
+            }
+            Self::Pack { level, backend } => write!(fmt, "pack({}):{}", level, backend),
+            Self::Multiplexed {
+                components,
+                write_mostly,
+                minimum_successful_writes,
+            } => {
+                write!(fmt, "multiplexed({}):[", minimum_successful_writes)?;
+                write_backends(fmt, components)?;
+                if !write_mostly.is_empty() {
+                    write!(fmt, "]+write_mostly:[")?;
+                    write_backends(fmt, write_mostly)?;
+                }
+                write!(fmt, "]")
+            }
+            Self::Repo { name } => write!(fmt, "repo:{}", name),
+        }
+    }
+}
+
+/// The backends that can be the components of a multiplex, as they are displayed, e.g.
+/// `memory`, `manifold:BUCKET`, `xdb:SHARDMAP/SHARD_COUNT` or `fileblob:PATH[+fsync]`.
+impl FromStr for BackendConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let (kind, arg) = match s.find(':') {
+            Some(idx) => (&s[..idx], Some(&s[idx + 1..])),
+            None => (s, None),
+        };
+        let backend = match (kind, arg) {
+            ("memory", None) => Self::Memory,
+            ("manifold", Some(bucket)) => Self::Manifold {
+                bucket: bucket.to_string(),
+            },
+            ("xdb", Some(arg)) => match arg.rfind('/') {
+                Some(idx) => Self::Xdb {
+                    shardmap: arg[..idx].to_string(),
+                    shard_count: arg[idx + 1..].parse()?,
+                    myrouter_port: None,
+                    use_mysql_client: false,
+                },
+                None => bail!("Invalid backend {}, it must be xdb:SHARDMAP/SHARD_COUNT", s),
+            },
+            ("fileblob", Some(path)) => match path.strip_suffix("+fsync") {
+                Some(path) => Self::Fileblob {
+                    path: path.to_string(),
+                    fsync: true,
+                },
+                None => Self::Fileblob {
+                    path: path.to_string(),
+                    fsync: false,
+                },
+            },
+            _ => bail!("Invalid backend {}", s),
+        };
+        Ok(backend)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    #[serde(default)]
+    pub memcache: bool,
+    #[serde(default)]
+    pub cachelib_size: Option<usize>,
+}
+
+impl fmt::Display for CacheConfig {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.memcache, self.cachelib_size) {
+            (false, None) => write!(fmt, "none"),
+            (true, None) => write!(fmt, "memcache"),
+            (false, Some(size)) => write!(fmt, "cachelib({})", size),
+            (true, Some(size)) => write!(fmt, "memcache+cachelib({})", size),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ScenarioFormat {
+    Yaml,
+    Json,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    backends: Vec<BackendConfig>,
+    #[serde(default)]
+    chunk_sizes: Vec<u64>,
+    #[serde(default)]
+    concurrency: Vec<usize>,
+    #[serde(default)]
+    caches: Vec<CacheConfig>,
+}
+
+impl Scenario {
+    /// Every combination of `chunk_sizes` and `concurrency` against `backend`.
+    pub fn sweep(backend: BackendConfig, chunk_sizes: Vec<u64>, concurrency: Vec<usize>) -> Self {
+        Self {
+            backends: vec![backend],
+            chunk_sizes,
+            concurrency,
+            caches: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+        let format = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") | Some("yml") => ScenarioFormat::Yaml,
+            Some("json") => ScenarioFormat::Json,
+            _ => bail!(
+                "Unknown format for scenario {}, it must be a .yaml, .yml or .json file",
+                path
+            ),
+        };
+        Self::parse(&contents, format).with_context(|| format!("Invalid scenario in {}", path))
+    }
+
+    fn parse(contents: &str, format: ScenarioFormat) -> Result<Self, Error> {
+        let scenario: Self = match format {
+            ScenarioFormat::Yaml => serde_yaml::from_str(contents)?,
+            ScenarioFormat::Json => serde_json::from_str(contents)?,
+        };
+
+        if scenario.backends.is_empty() {
+            bail!("Scenario has no backends");
+        }
+        // Cachelib can only be initialized once per process.
+        let cachelib_sizes: BTreeSet<_> = scenario
+            .caches
+            .iter()
+            .filter_map(|cache| cache.cachelib_size)
+            .collect();
+        if cachelib_sizes.len() > 1 {
+            bail!("Scenario uses several cachelib sizes, but only one can be used per run");
+        }
+
+        Ok(scenario)
+    }
+
+    /// All the runs in the matrix, with `options` and `cache` filling in the dimensions that the
+    /// scenario leaves out.
+    pub fn runs(&self, options: &BenchmarkOptions, cache: &CacheConfig) -> Vec<ScenarioRun> {
+        let chunk_sizes = or_default(&self.chunk_sizes, options.chunk_size);
+        let concurrency = or_default(&self.concurrency, options.concurrency);
+        let caches = or_default(&self.caches, cache.clone());
+
+        let mut runs = Vec::new();
+        for backend in &self.backends {
+            for chunk_size in &chunk_sizes {
+                for concurrency in &concurrency {
+                    for cache in &caches {
+                        runs.push(ScenarioRun {
+                            backend: backend.clone(),
+                            cache: cache.clone(),
+                            options: BenchmarkOptions {
+                                chunk_size: *chunk_size,
+                                concurrency: *concurrency,
+                                ..options.clone()
+                            },
+                        });
+                    }
+                }
+            }
+        }
+        runs
+    }
+}
+
+fn or_default<T: Clone>(values: &[T], default: T) -> Vec<T> {
+    if values.is_empty() {
+        vec![default]
+    } else {
+        values.to_vec()
+    }
+}
+
+pub struct ScenarioRun {
+    pub backend: BackendConfig,
+    pub cache: CacheConfig,
+    pub options: BenchmarkOptions,
+}
+
+impl fmt::Display for ScenarioRun {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            fmt,
+            "backend={}, chunk_size={}, concurrency={}, cache={}",
+            self.backend, self.options.chunk_size, self.options.concurrency, self.cache
+        )
+    }
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+}
+
+struct SummaryRow {
+    write: Option<f64>,
+    read: Option<f64>,
+    failed: bool,
+}
+
+impl SummaryRow {
+    fn new(res: &Result<BenchmarkResult, Error>) -> Self {
+        match res {
+            Ok(res) => {
+                let reads: Vec<_> = res.reads.iter().filter_map(|r| *r).collect();
+                Self {
+                    write: res.write,
+                    read: mean(&reads),
+                    failed: res.write.is_none() || reads.len() != res.reads.len(),
+                }
+            }
+            Err(_) => Self {
+                write: None,
+                read: None,
+                failed: true,
+            },
+        }
+    }
+}
+
+fn format_throughput(value: Option<f64>, best: Option<f64>) -> String {
+    match (value, best) {
+        (Some(value), Some(best)) if best > 0.0 => {
+            format!("{:.2} ({:.0}%)", value, value * 100.0 / best)
+        }
+        (Some(value), _) => format!("{:.2}", value),
+        (None, _) => "-".to_string(),
+    }
+}
+
+/// Print a table comparing the runs to `out`. Throughputs are in MB/s, followed by how they
+/// compare to the fastest run. Reads are averaged over all the reads of a run.
+pub fn print_summary(
+    out: &mut dyn Write,
+    results: &[(ScenarioRun, Result<BenchmarkResult, Error>)],
+) -> io::Result<()> {
+    let rows: Vec<_> = results
+        .iter()
+        .map(|(_, res)| SummaryRow::new(res))
+        .collect();
+    let best = |f: fn(&SummaryRow) -> Option<f64>| {
+        rows.iter()
+            .filter_map(f)
+            .fold(None, |best: Option<f64>, v| {
+                Some(best.map_or(v, |b| b.max(v)))
+            })
+    };
+    let best_write = best(|row| row.write);
+    let best_read = best(|row| row.read);
+
+    writeln!(
+        out,
+        "{:<30} {:>12} {:>12} {:<24} {:>18} {:>18} {}",
+        "backend", "chunk_size", "concurrency", "cache", "write MB/s", "read MB/s", "status"
+    )?;
+    for ((run, _), row) in results.iter().zip(rows.iter()) {
+        writeln!(
+            out,
+            "{:<30} {:>12} {:>12} {:<24} {:>18} {:>18} {}",
+            run.backend.to_string(),
+            run.options.chunk_size,
+            run.options.concurrency,
+            run.cache.to_string(),
+            format_throughput(row.write, best_write),
+            format_throughput(row.read, best_read),
+            if row.failed { "FAILED" } else { "ok" },
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn options() -> BenchmarkOptions {
+        BenchmarkOptions {
+            input: "input".to_string(),
+            input_capacity: 8192,
+            chunk_size: 1048576,
+            concurrency: 1,
+            read_count: 2,
+            range_reads: 0,
+            range_sizes: Vec::new(),
+            lookups: 0,
+            lookup_qps: None,
+            delay: None,
+            randomize: false,
+            drop_caches: false,
+            verify: false,
+        }
+    }
+
+    fn describe(runs: &[ScenarioRun]) -> Vec<String> {
+        runs.iter().map(|run| run.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_yaml() -> Result<(), Error> {
+        let scenario = Scenario::parse(
+            r#"
+backends:
+  - type: memory
+  - type: xdb
+    shardmap: xdb.mononoke_test
+    shard_count: 10
+    myrouter_port: 3307
+  - type: pack
+    backend: { type: fileblob, path: /tmp/blobs, fsync: true }
+chunk_sizes: [1024, 2048]
+caches: [{}, { memcache: true }]
+"#,
+            ScenarioFormat::Yaml,
+        )?;
+
+        match &scenario.backends[1] {
+            BackendConfig::Xdb {
+                myrouter_port,
+                use_mysql_client,
+                ..
+            } => {
+                assert_eq!(*myrouter_port, Some(3307));
+                assert!(!use_mysql_client);
+            }
+            backend => panic!("unexpected backend {}", backend),
+        }
+
+        let runs = scenario.runs(&options(), &CacheConfig::default());
+        assert_eq!(runs.len(), 3 * 2 * 2);
+        assert_eq!(
+            describe(&runs[..4]),
+            vec![
+                "backend=memory, chunk_size=1024, concurrency=1, cache=none",
+                "backend=memory, chunk_size=1024, concurrency=1, cache=memcache",
+                "backend=memory, chunk_size=2048, concurrency=1, cache=none",
+                "backend=memory, chunk_size=2048, concurrency=1, cache=memcache",
+            ]
+        );
+        assert_eq!(
+            runs[11].to_string(),
+            "backend=pack(0):fileblob:/tmp/blobs+fsync, chunk_size=2048, concurrency=1, \
+             cache=memcache"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_json() -> Result<(), Error> {
+        let scenario = Scenario::parse(
+            r#"{
+                "backends": [
+                    {
+                        "type": "multiplexed",
+                        "minimum_successful_writes": 1,
+                        "components": [{"type": "memory"}, {"type": "memory"}]
+                    }
+                ],
+                "concurrency": [1, 10]
+            }"#,
+            ScenarioFormat::Json,
+        )?;
+
+        // What the scenario leaves out comes from the command line.
+        let cache = CacheConfig {
+            memcache: true,
+            cachelib_size: None,
+        };
+        let runs = scenario.runs(&options(), &cache);
+        assert_eq!(
+            describe(&runs),
+            vec![
+                "backend=multiplexed(1):[memory,memory], chunk_size=1048576, concurrency=1, \
+                 cache=memcache",
+                "backend=multiplexed(1):[memory,memory], chunk_size=1048576, concurrency=10, \
+                 cache=memcache",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        let invalid = vec![
+            // No backends.
+            "backends: []",
+            "chunk_sizes: [1024]",
+            // Unknown fields and backends.
+            "backends: [{ type: memory }]\nchunk_size: [1024]",
+            "backends: [{ type: fileblob, path: /tmp, sync: true }]",
+            "backends: [{ type: s3 }]",
+            // Cachelib can only be set up once.
+            "backends: [{ type: memory }]\ncaches: [{ cachelib_size: 1 }, { cachelib_size: 2 }]",
+        ];
+        for contents in invalid {
+            assert!(
+                Scenario::parse(contents, ScenarioFormat::Yaml).is_err(),
+                "{} should be rejected",
+                contents
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_format() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+
+        let path = dir.path().join("scenario.json");
+        fs::write(&path, r#"{"backends": [{"type": "memory"}]}"#)?;
+        assert_eq!(Scenario::load(path.to_str().unwrap())?.backends.len(), 1);
+
+        // TOML, or anything else, is not a format that scenarios can be in.
+        let path = dir.path().join("scenario.toml");
+        fs::write(&path, "backends = [{ type = \"memory\" }]")?;
+        assert!(Scenario::load(path.to_str().unwrap()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_backend_from_str() -> Result<(), Error> {
+        for backend in &[
+            "memory",
+            "manifold:bucket",
+            "xdb:xdb.mononoke_test/10",
+            "fileblob:/tmp/blobs",
+            "fileblob:/tmp/blobs+fsync",
+        ] {
+            assert_eq!(backend.parse::<BackendConfig>()?.to_string(), *backend);
+        }
+        assert!("xdb:xdb.mononoke_test".parse::<BackendConfig>().is_err());
+        assert!("s3:bucket".parse::<BackendConfig>().is_err());
+        Ok(())
+    }
+}
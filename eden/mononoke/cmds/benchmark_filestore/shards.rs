@@ -0,0 +1,237 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! The operations on the xdb backend, broken down by shard, for a shard that is slower than the
+//! others or that takes more than its share of the load to stand out of the aggregate numbers.
+//!
+//! An operation is put down to the shard that the data of its key is on, which is where
+//! Sqlblob looks up the key. The chunks of a blob are spread over the shards on their own, so
+//! the latency of an operation also depends on the shards that its chunks are on.
+//!
+//! The breakdown is in the stats of the run, as `shard.<shardmap>.<shard>.<stat>`, along with
+//! the shard that has the slowest gets.
+
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use blobstore::{Blobstore, BlobstoreGetData, BlobstorePutOps, OverwriteStatus, PutBehaviour};
+use context::CoreContext;
+use futures_stats::TimedFutureExt;
+use mononoke_types::BlobstoreBytes;
+use sqlblob::CountedSqlblob;
+
+use crate::latency::LatencyHistogram;
+use crate::run_stats::{ReportStats, Stat};
+
+#[derive(Default)]
+struct ShardStats {
+    /// Gets and presence checks.
+    get: LatencyHistogram,
+    put: LatencyHistogram,
+    failed: u64,
+}
+
+struct Shards {
+    shardmap: String,
+    stats: Vec<Mutex<ShardStats>>,
+}
+
+impl ReportStats for Shards {
+    fn report(&self) -> Vec<Stat> {
+        let shards: Vec<_> = self
+            .stats
+            .iter()
+            .map(|stats| stats.lock().expect("lock poisoned"))
+            .collect();
+        let total: u64 = shards
+            .iter()
+            .map(|stats| stats.get.samples() + stats.put.samples())
+            .sum();
+        if total == 0 {
+            return Vec::new();
+        }
+
+        let name = |shard: usize, stat: &str| format!("shard.{}.{}.{}", self.shardmap, shard, stat);
+        let mut report = Vec::new();
+        for (shard, stats) in shards.iter().enumerate() {
+            let operations = stats.get.samples() + stats.put.samples();
+            report.push(Stat::new(name(shard, "operations"), operations as f64));
+            report.push(Stat::new(
+                name(shard, "share_pct"),
+                operations as f64 * 100.0 / total as f64,
+            ));
+            report.push(Stat::new(name(shard, "failed"), stats.failed as f64));
+            let percentiles = [
+                ("get_p50_ms", stats.get.percentile_ms(50.0)),
+                ("get_p99_ms", stats.get.percentile_ms(99.0)),
+                ("put_p50_ms", stats.put.percentile_ms(50.0)),
+                ("put_p99_ms", stats.put.percentile_ms(99.0)),
+            ];
+            for (stat, value) in percentiles.iter() {
+                if let Some(value) = value {
+                    report.push(Stat::new(name(shard, stat), *value));
+                }
+            }
+        }
+
+        let slowest = shards
+            .iter()
+            .enumerate()
+            .filter_map(|(shard, stats)| {
+                let p99 = stats.get.percentile_ms(99.0)?;
+                Some((shard, p99))
+            })
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).expect("latencies are not NaN"));
+        if let Some((shard, _)) = slowest {
+            report.push(Stat::new(
+                format!("shard.{}.slowest_get", self.shardmap),
+                shard as f64,
+            ));
+        }
+        report
+    }
+}
+
+/// Sqlblob, with the latencies of its operations recorded per shard, in its `stats`.
+pub struct ShardedBlob {
+    blobstore: CountedSqlblob,
+    shards: Arc<Shards>,
+}
+
+impl ShardedBlob {
+    pub fn new(blobstore: CountedSqlblob, shardmap: String, shard_count: NonZeroUsize) -> Self {
+        Self {
+            blobstore,
+            shards: Arc::new(Shards {
+                shardmap,
+                stats: (0..shard_count.get())
+                    .map(|_| Mutex::new(ShardStats::default()))
+                    .collect(),
+            }),
+        }
+    }
+
+    pub fn stats(&self) -> Arc<dyn ReportStats> {
+        self.shards.clone()
+    }
+
+    fn record<T>(
+        &self,
+        key: &str,
+        histogram: impl FnOnce(&mut ShardStats) -> &mut LatencyHistogram,
+        latency: Duration,
+        res: &Result<T>,
+    ) {
+        let shard = self.blobstore.shard_for_key(key);
+        let mut stats = self.shards.stats[shard].lock().expect("lock poisoned");
+        histogram(&mut stats).record(latency);
+        if res.is_err() {
+            stats.failed += 1;
+        }
+    }
+}
+
+impl fmt::Display for ShardedBlob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ShardedBlob<{}>", &self.blobstore)
+    }
+}
+
+impl fmt::Debug for ShardedBlob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardedBlob")
+            .field("blobstore", &self.blobstore)
+            .field("shards", &self.shards.stats.len())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Blobstore for ShardedBlob {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let (stats, res) = self.blobstore.get(ctx, key).timed().await;
+        self.record(key, |shard| &mut shard.get, stats.completion_time, &res);
+        res
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        let shard_key = key.clone();
+        let (stats, res) = self.blobstore.put(ctx, key, value).timed().await;
+        self.record(
+            &shard_key,
+            |shard| &mut shard.put,
+            stats.completion_time,
+            &res,
+        );
+        res
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        let (stats, res) = self.blobstore.is_present(ctx, key).timed().await;
+        self.record(key, |shard| &mut shard.get, stats.completion_time, &res);
+        res
+    }
+}
+
+#[async_trait]
+impl BlobstorePutOps for ShardedBlob {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        let shard_key = key.clone();
+        let (stats, res) = self
+            .blobstore
+            .put_explicit(ctx, key, value, put_behaviour)
+            .timed()
+            .await;
+        self.record(
+            &shard_key,
+            |shard| &mut shard.put,
+            stats.completion_time,
+            &res,
+        );
+        res
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        let shard_key = key.clone();
+        let (stats, res) = self
+            .blobstore
+            .put_with_status(ctx, key, value)
+            .timed()
+            .await;
+        self.record(
+            &shard_key,
+            |shard| &mut shard.put,
+            stats.completion_time,
+            &res,
+        );
+        res
+    }
+}
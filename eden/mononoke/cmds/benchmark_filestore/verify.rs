@@ -0,0 +1,90 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Checks of the bytes that come back from the blobstore against the hashes that the filestore
+//! computed when storing them, for `--verify`. The bytes are hashed as they are streamed, so the
+//! time spent hashing is part of the latency of the reads.
+
+use anyhow::{bail, Error};
+use bytes::Bytes;
+use mononoke_types::{hash, typed_hash::ContentIdContext, ContentMetadata};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+pub struct Verifier {
+    size: u64,
+    content_id: ContentIdContext,
+    sha1: Sha1,
+    sha256: Sha256,
+    git_sha1: Sha1,
+}
+
+impl Verifier {
+    pub fn new(metadata: &ContentMetadata) -> Self {
+        let mut git_sha1 = Sha1::new();
+        let prototype = hash::RichGitSha1::from_byte_array([0; 20], "blob", metadata.total_size);
+        git_sha1.input(&prototype.prefix());
+        Self {
+            size: 0,
+            content_id: ContentIdContext::new(),
+            sha1: Sha1::new(),
+            sha256: Sha256::new(),
+            git_sha1,
+        }
+    }
+
+    pub fn update(&mut self, bytes: &Bytes) {
+        self.size += bytes.len() as u64;
+        self.content_id.update(bytes);
+        self.sha1.input(bytes);
+        self.sha256.input(bytes);
+        self.git_sha1.input(bytes);
+    }
+
+    /// Fails with every way in which the bytes don't match `metadata`.
+    pub fn finish(self, metadata: &ContentMetadata) -> Result<(), Error> {
+        let mut mismatches = Vec::new();
+        if self.size != metadata.total_size {
+            mismatches.push(format!(
+                "size {} (expected {})",
+                self.size, metadata.total_size
+            ));
+        }
+        let content_id = self.content_id.finish();
+        if content_id != metadata.content_id {
+            mismatches.push(format!(
+                "content id {} (expected {})",
+                content_id, metadata.content_id
+            ));
+        }
+        let sha1 = hash::Sha1::from_byte_array(self.sha1.result().into());
+        if sha1 != metadata.sha1 {
+            mismatches.push(format!("sha1 {} (expected {})", sha1, metadata.sha1));
+        }
+        let sha256 = hash::Sha256::from_byte_array(self.sha256.result().into());
+        if sha256 != metadata.sha256 {
+            mismatches.push(format!("sha256 {} (expected {})", sha256, metadata.sha256));
+        }
+        let git_sha1 = hash::GitSha1::from_byte_array(self.git_sha1.result().into());
+        if git_sha1 != metadata.git_sha1.sha1() {
+            mismatches.push(format!(
+                "git sha1 {} (expected {})",
+                git_sha1,
+                metadata.git_sha1.sha1()
+            ));
+        }
+
+        if !mismatches.is_empty() {
+            bail!(
+                "Corrupt contents for {}: {}",
+                metadata.content_id,
+                mismatches.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,499 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A sustained mix of concurrent reads and writes, closer to the traffic of a server than a
+//! single write followed by reads, e.g. `--workload mixed --read-ratio 0.8 --duration 60s`.
+//!
+//! Workers pick an operation at random until the deadline: a write stores the input again with a
+//! random prefix, so that every write is of new contents, and a read fetches one of the contents
+//! written so far. Operations that start in the warm-up period are not counted, so that the
+//! results are of the steady state rather than of e.g. the caches filling up.
+//!
+//! Over a long `--duration`, this is a soak test: with `--progress-interval`, the throughput
+//! over each interval is logged as the workload runs, so that a degradation over time (e.g. the
+//! caches evicting, or a shard getting hot) shows up when it happens rather than being averaged
+//! away in the steady state, and an interval in which no operation completes is logged as a
+//! stall. The memory of the workload doesn't grow with its duration: the
+//! operations are passed on as they complete rather than kept, and reads pick from a sample of
+//! `MAX_READABLE` of the contents written.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, format_err, Error};
+use blobstore::Blobstore;
+use bytes::Bytes;
+use cmdlib_progress::{Progress, ProgressOptions, ProgressReport, ProgressSink};
+use context::CoreContext;
+use filestore::{self, FetchKey, FilestoreConfig, StoreRequest};
+use futures::{
+    channel::mpsc,
+    future::{self, Either},
+    stream::{self, TryStreamExt},
+    SinkExt,
+};
+use futures_stats::TimedFutureExt;
+use mononoke_types::ContentMetadata;
+use rand::{seq::SliceRandom, Rng};
+
+use crate::latency::{Latencies, LatencyBlob, LatencyHistogram, PERCENTILES};
+use crate::output::{Operation, OperationRecord};
+use crate::{throughput_mb_per_s, BenchmarkOptions};
+
+pub const WORKLOAD_SINGLE: &str = "single";
+pub const WORKLOAD_MIXED: &str = "mixed";
+pub const WORKLOADS: &[&str] = &[WORKLOAD_SINGLE, WORKLOAD_MIXED];
+
+/// How many of the contents written are kept for the reads to pick from.
+const MAX_READABLE: usize = 10_000;
+
+/// How often the progress is checked for stalls.
+const STALL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug)]
+pub struct MixedWorkload {
+    /// The odds of an operation being a read, between 0 and 1.
+    pub read_ratio: f64,
+    pub duration: Duration,
+    pub warmup: Duration,
+    /// How many operations are in flight at once.
+    pub workers: usize,
+    /// How often to log the throughput, if at all.
+    pub progress_interval: Option<Duration>,
+}
+
+impl MixedWorkload {
+    pub fn new(
+        read_ratio: f64,
+        duration: Duration,
+        warmup: Duration,
+        workers: usize,
+        progress_interval: Option<Duration>,
+    ) -> Result<Self, Error> {
+        if !(0.0..=1.0).contains(&read_ratio) {
+            bail!("Read ratio must be between 0 and 1, not {}", read_ratio);
+        }
+        if warmup >= duration {
+            bail!(
+                "Warm-up ({:?}) must be shorter than the duration ({:?})",
+                warmup,
+                duration
+            );
+        }
+        if workers == 0 {
+            bail!("At least one worker is needed");
+        }
+        Ok(Self {
+            read_ratio,
+            duration,
+            warmup,
+            workers,
+            progress_interval,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum SoakCounter {
+    Failed,
+    BytesRead,
+    BytesWritten,
+}
+
+impl fmt::Display for SoakCounter {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Failed => write!(fmt, "failed"),
+            Self::BytesRead => write!(fmt, "read B"),
+            Self::BytesWritten => write!(fmt, "written B"),
+        }
+    }
+}
+
+/// Logs the progress of the workload along with the throughput since the previous report.
+#[derive(Default)]
+struct IntervalThroughput {
+    /// The elapsed time, bytes read and bytes written as of the previous report.
+    last: Mutex<(Duration, u64, u64)>,
+}
+
+impl ProgressSink<SoakCounter> for IntervalThroughput {
+    fn report(&self, report: &ProgressReport<'_, SoakCounter>) {
+        let counter = |key| report.counters.get(&key).copied().unwrap_or(0);
+        let read = counter(SoakCounter::BytesRead);
+        let written = counter(SoakCounter::BytesWritten);
+
+        let mut last = self.last.lock().expect("lock poisoned");
+        let (last_elapsed, last_read, last_written) = *last;
+        *last = (report.elapsed, read, written);
+
+        // Reports are made one at a time, so they come in order.
+        let secs = (report.elapsed - last_elapsed).as_secs_f64();
+        let mb_per_s = |bytes: u64| {
+            if secs > 0.0 {
+                bytes as f64 / secs / (2_u128.pow(20) as f64)
+            } else {
+                0.0
+            }
+        };
+        eprintln!(
+            "{}; over the last {:.0}s: {:.2} MB/s read, {:.2} MB/s written",
+            report,
+            secs,
+            mb_per_s(read - last_read),
+            mb_per_s(written - last_written)
+        );
+    }
+}
+
+/// A uniform sample of the contents written so far, for the reads to pick from, kept by
+/// reservoir sampling so that it is at most `MAX_READABLE` of them however many are written.
+struct Written {
+    /// How many contents were written, including the ones that are not in the sample.
+    count: usize,
+    sample: Vec<ContentMetadata>,
+}
+
+impl Written {
+    fn new(seed: ContentMetadata) -> Self {
+        Self {
+            count: 1,
+            sample: vec![seed],
+        }
+    }
+
+    fn add(&mut self, metadata: ContentMetadata) {
+        self.count += 1;
+        if self.sample.len() < MAX_READABLE {
+            self.sample.push(metadata);
+        } else {
+            let idx = rand::thread_rng().gen_range(0, self.count);
+            if idx < MAX_READABLE {
+                self.sample[idx] = metadata;
+            }
+        }
+    }
+
+    fn pick(&self) -> ContentMetadata {
+        self.sample
+            .choose(&mut rand::thread_rng())
+            .cloned()
+            .expect("there is always something written")
+    }
+}
+
+/// What the operations of one type did in the steady state.
+struct OperationSummary {
+    operation: Operation,
+    count: usize,
+    failed: usize,
+    /// Of the operations that succeeded.
+    bytes: u64,
+    /// Of the operations that succeeded.
+    latencies: LatencyHistogram,
+}
+
+impl OperationSummary {
+    fn new(operation: Operation) -> Self {
+        Self {
+            operation,
+            count: 0,
+            failed: 0,
+            bytes: 0,
+            latencies: LatencyHistogram::default(),
+        }
+    }
+
+    fn add(&mut self, record: &OperationRecord, latency: Duration) {
+        self.count += 1;
+        match record.throughput_mb_per_s() {
+            Some(_) => {
+                self.bytes += record.bytes();
+                self.latencies.record(latency);
+            }
+            None => self.failed += 1,
+        }
+    }
+
+    fn merge(&mut self, other: &OperationSummary) {
+        self.count += other.count;
+        self.failed += other.failed;
+        self.bytes += other.bytes;
+        self.latencies.merge(&other.latencies);
+    }
+
+    fn ops_per_s(&self, window: Duration) -> f64 {
+        self.count as f64 / window.as_secs_f64()
+    }
+
+    fn mb_per_s(&self, window: Duration) -> f64 {
+        self.bytes as f64 / window.as_secs_f64() / (2_u128.pow(20) as f64)
+    }
+}
+
+/// Read and write summaries, in that order, for the workers to fill in.
+fn new_summaries() -> Vec<OperationSummary> {
+    vec![
+        OperationSummary::new(Operation::Read),
+        OperationSummary::new(Operation::Write),
+    ]
+}
+
+fn format_latency(latency_ms: Option<f64>) -> String {
+    latency_ms.map_or_else(|| "-".to_string(), |latency| format!("{:.2}", latency))
+}
+
+/// The steady state of a mixed workload.
+pub struct WorkloadResult {
+    window: Duration,
+    summaries: Vec<OperationSummary>,
+    /// Of the steady state.
+    pub latencies: Latencies,
+}
+
+impl WorkloadResult {
+    /// Print a table of the throughput and latency percentiles of each type of operation to
+    /// `out`.
+    pub fn print(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "Steady state over {:?}:", self.window)?;
+        write!(
+            out,
+            "{:<10} {:>10} {:>8} {:>10} {:>10}",
+            "operation", "count", "failed", "ops/s", "MB/s"
+        )?;
+        for percentile in PERCENTILES {
+            write!(out, " {:>10}", format!("p{} ms", percentile))?;
+        }
+        writeln!(out, " {:>10}", "max ms")?;
+
+        for summary in &self.summaries {
+            write!(
+                out,
+                "{:<10} {:>10} {:>8} {:>10.2} {:>10.2}",
+                summary.operation.to_string(),
+                summary.count,
+                summary.failed,
+                summary.ops_per_s(self.window),
+                summary.mb_per_s(self.window),
+            )?;
+            for percentile in PERCENTILES {
+                let latency = summary.latencies.percentile_ms(*percentile);
+                write!(out, " {:>10}", format_latency(latency))?;
+            }
+            writeln!(out, " {:>10}", format_latency(summary.latencies.max_ms()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Store `data` with a random prefix, so that it is new contents every time. Also returns the
+/// latency of the store, and the metadata of the contents if it succeeded.
+pub async fn write(
+    ctx: &CoreContext,
+    blob: &Arc<dyn Blobstore>,
+    config: FilestoreConfig,
+    data: &Bytes,
+) -> (OperationRecord, Duration, Option<ContentMetadata>) {
+    let prefix = rand::thread_rng().gen::<[u8; 32]>();
+    let prefix = Bytes::copy_from_slice(&prefix[..]);
+    let len = (prefix.len() + data.len()) as u64;
+    let contents = stream::iter(vec![Ok(prefix), Ok(data.clone())]);
+
+    let req = StoreRequest::new(len);
+    let (stats, res) = filestore::store(blob, config, ctx, &req, contents)
+        .timed()
+        .await;
+    let throughput = res.as_ref().ok().map(|_| throughput_mb_per_s(&stats, len));
+    let record = OperationRecord::new(Operation::Write, &stats, len, throughput);
+    (record, stats.completion_time, res.ok())
+}
+
+async fn read(
+    ctx: &CoreContext,
+    blob: &Arc<dyn Blobstore>,
+    metadata: &ContentMetadata,
+) -> (OperationRecord, Duration) {
+    let key = FetchKey::Canonical(metadata.content_id);
+    let (stats, res) = async {
+        filestore::fetch(blob, ctx.clone(), &key)
+            .await?
+            .ok_or_else(|| format_err!("Fetch failed: no stream"))?
+            .try_for_each(|_| async { Ok(()) })
+            .await?;
+        Ok::<_, Error>(())
+    }
+    .timed()
+    .await;
+    let throughput = res
+        .ok()
+        .map(|()| throughput_mb_per_s(&stats, metadata.total_size));
+    let record = OperationRecord::new(Operation::Read, &stats, metadata.total_size, throughput);
+    (record, stats.completion_time)
+}
+
+/// Run the workload, and send every operation of the steady state to `records` as it
+/// completes.
+pub async fn run_mixed_workload(
+    ctx: &CoreContext,
+    options: &BenchmarkOptions,
+    workload: &MixedWorkload,
+    blob: Arc<dyn Blobstore>,
+    records: mpsc::Sender<OperationRecord>,
+) -> Result<WorkloadResult, Error> {
+    let config = FilestoreConfig {
+        chunk_size: Some(options.chunk_size),
+        concurrency: options.concurrency,
+        inline_threshold: None,
+    };
+
+    // The input is read once, so that the workload isn't limited by how fast it can be read.
+    let data = Bytes::from(tokio::fs::read(&options.input).await?);
+
+    eprintln!(
+        "Mixed workload with {:?}, {} workers, {:.0}% reads for {:?} (warm-up {:?}), writing into {:?}",
+        config,
+        workload.workers,
+        workload.read_ratio * 100.0,
+        workload.duration,
+        workload.warmup,
+        blob
+    );
+
+    let blob_latencies = Arc::new(Mutex::new(Latencies::default()));
+    let blob: Arc<dyn Blobstore> = Arc::new(LatencyBlob::new(blob, blob_latencies.clone()));
+
+    // Reads need something to read from the start.
+    let (_, _, seed) = write(ctx, &blob, config, &data).await;
+    let seed = seed.ok_or_else(|| format_err!("Failed to write the initial contents"))?;
+    let written = Mutex::new(Written::new(seed));
+
+    let start = Instant::now();
+    let deadline = start + workload.duration;
+    let warmed_up = Once::new();
+    let progress = workload.progress_interval.map(|interval| {
+        let options = ProgressOptions {
+            interval,
+            // The rate in each report is the one over the interval, not since the start.
+            rate_window: interval,
+            // A whole interval without an operation completing is worth knowing about then.
+            stall_timeout: Some(interval),
+            ..ProgressOptions::default()
+        };
+        Progress::new("mixed workload operations", None, options)
+            .with_sink(IntervalThroughput::default())
+    });
+
+    let worker = |_| {
+        let (blob, data, written, warmed_up) = (&blob, &data, &written, &warmed_up);
+        let progress = &progress;
+        let blob_latencies = &blob_latencies;
+        let mut records = records.clone();
+        async move {
+            let mut summaries = new_summaries();
+            while Instant::now() < deadline {
+                let started = start.elapsed();
+                if started >= workload.warmup {
+                    // The gets and puts of the warm-up are forgotten, as its operations are.
+                    warmed_up.call_once(|| {
+                        *blob_latencies.lock().expect("lock poisoned") = Latencies::default();
+                    });
+                }
+                let (record, latency) = if rand::thread_rng().gen_bool(workload.read_ratio) {
+                    let metadata = written.lock().expect("lock poisoned").pick();
+                    read(ctx, blob, &metadata).await
+                } else {
+                    let (record, latency, metadata) = write(ctx, blob, config, data).await;
+                    if let Some(metadata) = metadata {
+                        written.lock().expect("lock poisoned").add(metadata);
+                    }
+                    (record, latency)
+                };
+                if let Some(progress) = progress {
+                    match (record.throughput_mb_per_s(), record.operation()) {
+                        (None, _) => progress.increment(SoakCounter::Failed, 1),
+                        (Some(_), Operation::Write) => {
+                            progress.increment(SoakCounter::BytesWritten, record.bytes())
+                        }
+                        (Some(_), _) => progress.increment(SoakCounter::BytesRead, record.bytes()),
+                    }
+                    progress.record(1);
+                    progress.report_throttled();
+                }
+                if started >= workload.warmup {
+                    match record.operation() {
+                        Operation::Read => summaries[0].add(&record, latency),
+                        _ => summaries[1].add(&record, latency),
+                    }
+                    records
+                        .send(record)
+                        .await
+                        .map_err(|_| format_err!("The operations are no longer recorded"))?;
+                }
+            }
+            Ok::<_, Error>(summaries)
+        }
+    };
+
+    let workers = future::try_join_all((0..workload.workers).map(worker));
+    let worker_summaries = match &progress {
+        Some(progress) => {
+            // The workers can't report that their operations stopped completing, so this does,
+            // for as long as they run.
+            let watch_stalls = async {
+                loop {
+                    tokio_shim::time::sleep(STALL_CHECK_INTERVAL).await;
+                    progress.report_if_stalled();
+                }
+            };
+            futures::pin_mut!(workers, watch_stalls);
+            match future::select(workers, watch_stalls).await {
+                Either::Left((worker_summaries, _)) => worker_summaries?,
+                Either::Right(..) => {
+                    unreachable!("The stalls are watched until the workers are done")
+                }
+            }
+        }
+        None => workers.await?,
+    };
+    // The records are all sent, for whoever records them to be done once they are.
+    drop(records);
+    if let Some(progress) = &progress {
+        progress.report();
+    }
+
+    let window = workload.duration - workload.warmup;
+    let mut summaries = new_summaries();
+    for worker_summaries in &worker_summaries {
+        for (summary, worker_summary) in summaries.iter_mut().zip(worker_summaries) {
+            summary.merge(worker_summary);
+        }
+    }
+
+    let mut latencies = blob_latencies.lock().expect("lock poisoned").clone();
+    for summary in &summaries {
+        match summary.operation {
+            Operation::Read => latencies.read = summary.latencies.clone(),
+            Operation::Write => latencies.write = summary.latencies.clone(),
+            Operation::RangeRead => latencies.range_read = summary.latencies.clone(),
+            // The mixed workload makes no lookups.
+            Operation::Sha1Lookup
+            | Operation::Sha256Lookup
+            | Operation::GitSha1Lookup
+            | Operation::MetadataLookup => {}
+        }
+    }
+
+    latencies.log();
+
+    Ok(WorkloadResult {
+        window,
+        summaries,
+        latencies,
+    })
+}
@@ -0,0 +1,161 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Rewrite blobs in an encrypted blobstore with its active key, so that older keys can be dropped
+//! from the keyring after a key rotation. Keys to rewrite are read from stdin, one per line.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Error, Result};
+use clap::Arg;
+use futures::stream::{StreamExt, TryStreamExt};
+use slog::{info, warn};
+use tokio::io::{stdin, AsyncBufReadExt, BufReader};
+
+use blobstore_factory::{make_blobstore_put_ops, ReadOnlyStorage};
+use cmdlib::args;
+use context::CoreContext;
+use encryptedblob::{EncryptedBlob, Keyring, ReencryptOutcome};
+use metaconfig_types::BlobConfig;
+
+const ARG_STORAGE_CONFIG_NAME: &str = "storage-config-name";
+const ARG_SCHEDULED_MAX: &str = "scheduled-max";
+
+/// Find the encrypted layer of `blob_config`, looking through the stores that only wrap another.
+fn find_encrypted(mut blob_config: BlobConfig) -> Result<(BlobConfig, PathBuf, String)> {
+    loop {
+        blob_config = match blob_config {
+            BlobConfig::Encrypted {
+                blobconfig,
+                keyring_path,
+                active_key_id,
+            } => return Ok((*blobconfig, keyring_path, active_key_id)),
+            BlobConfig::Pack { blobconfig } | BlobConfig::Logging { blobconfig, .. } => *blobconfig,
+            _ => bail!("Storage config does not have an encrypted blobstore"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    reencrypted: AtomicUsize,
+    already_active: AtomicUsize,
+    missing: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+#[fbinit::main]
+fn main(fb: fbinit::FacebookInit) -> Result<()> {
+    let app = args::MononokeAppBuilder::new("blobstore re-encryption")
+        .with_advanced_args_hidden()
+        .with_all_repos()
+        .build()
+        .about(
+            "Rewrite the blobs whose keys are given on stdin with the active encryption key. \
+             Keys are the ones seen by the encrypted blobstore, so they include the suffixes \
+             added by the stores above it, e.g. .pack",
+        )
+        .arg(
+            Arg::with_name(ARG_STORAGE_CONFIG_NAME)
+                .long(ARG_STORAGE_CONFIG_NAME)
+                .takes_value(true)
+                .required(true)
+                .help("the name of the storage config to re-encrypt"),
+        )
+        .arg(
+            Arg::with_name(ARG_SCHEDULED_MAX)
+                .long(ARG_SCHEDULED_MAX)
+                .takes_value(true)
+                .required(false)
+                .help("Maximum number of keys to re-encrypt at once.  Default 100."),
+        );
+
+    let matches = app.get_matches();
+    let (_, logger, mut runtime) =
+        args::init_mononoke(fb, &matches).context("failed to initialise mononoke")?;
+    let config_store = args::init_config_store(fb, &logger, &matches)?;
+
+    let scheduled_max = args::get_usize_opt(&matches, ARG_SCHEDULED_MAX).unwrap_or(100) as usize;
+
+    let storage_config = args::load_storage_configs(config_store, &matches)
+        .context("Could not read storage configs")?
+        .storage
+        .remove(
+            matches
+                .value_of(ARG_STORAGE_CONFIG_NAME)
+                .context("No storage config name")?,
+        )
+        .context("Requested storage config not found")?;
+    let (inner_config, keyring_path, active_key_id) = find_encrypted(storage_config.blobstore)?;
+    let keyring = Keyring::load(&keyring_path, active_key_id)?;
+
+    let mysql_options = args::parse_mysql_options(&matches);
+    let blobstore_options = args::parse_blobstore_options(&matches)?;
+    let ctx = CoreContext::new_bulk_with_logger(fb, logger.clone());
+
+    let reencrypt = async move {
+        let inner = make_blobstore_put_ops(
+            fb,
+            inner_config,
+            &mysql_options,
+            ReadOnlyStorage(false),
+            &blobstore_options,
+            &logger,
+            config_store,
+        )
+        .await?;
+        let blobstore = EncryptedBlob::new(inner, Arc::new(keyring));
+
+        let counts = Counts::default();
+        BufReader::new(stdin())
+            .lines()
+            .map_err(Error::from)
+            .for_each_concurrent(scheduled_max, |key| {
+                let (blobstore, ctx, counts, logger) = (&blobstore, &ctx, &counts, &logger);
+                async move {
+                    let res = match key {
+                        Ok(key) => blobstore
+                            .reencrypt(ctx, &key)
+                            .await
+                            .with_context(|| format!("Re-encrypting key {}", key)),
+                        Err(e) => Err(e),
+                    };
+                    let count = match res {
+                        Ok(ReencryptOutcome::Reencrypted { .. }) => &counts.reencrypted,
+                        Ok(ReencryptOutcome::AlreadyActive) => &counts.already_active,
+                        Ok(ReencryptOutcome::Missing) => &counts.missing,
+                        Err(e) => {
+                            warn!(logger, "{:?}", e);
+                            &counts.failed
+                        }
+                    };
+                    count.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+            .await;
+
+        let failed = counts.failed.load(Ordering::Relaxed);
+        info!(
+            logger,
+            "Re-encrypted {}, already using the active key {}, missing {}, failed {}",
+            counts.reencrypted.load(Ordering::Relaxed),
+            counts.already_active.load(Ordering::Relaxed),
+            counts.missing.load(Ordering::Relaxed),
+            failed,
+        );
+        if failed > 0 {
+            bail!("Failed to re-encrypt {} keys", failed);
+        }
+        Ok(())
+    };
+
+    runtime.block_on(reencrypt)
+}
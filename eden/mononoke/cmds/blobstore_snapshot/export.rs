@@ -0,0 +1,136 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use blobstore::Blobstore;
+use context::CoreContext;
+use futures::stream::{Stream, TryStreamExt};
+use mononoke_types::hash::Sha256;
+use sha2::Digest;
+use slog::info;
+
+use crate::manifest::{blob_path, Manifest, ManifestEntry, MANIFEST_PATH, SNAPSHOT_VERSION};
+
+/// Hashes everything written to the archive, so that the archive can be referred to by its
+/// digest, e.g. as an OCI layer.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: sha2::Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.input(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn tar_header(size: usize, mtime: u64) -> tar::Header {
+    let mut header = tar::Header::new_ustar();
+    header.set_size(size as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header
+}
+
+/// Fetch the values of `keys` (relative to `prefix`) and write them to a tar archive at
+/// `output`, followed by a manifest mapping each key to its blob.
+pub async fn export(
+    ctx: &CoreContext,
+    blobstore: &dyn Blobstore,
+    keys: impl Stream<Item = Result<String>>,
+    prefix: &str,
+    output: &Path,
+    scheduled_max: usize,
+    allow_missing: bool,
+) -> Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let file =
+        File::create(output).with_context(|| format!("Failed to create {}", output.display()))?;
+    let mut archive = tar::Builder::new(HashingWriter {
+        inner: BufWriter::new(file),
+        hasher: sha2::Sha256::new(),
+    });
+
+    let mut manifest = Manifest {
+        version: SNAPSHOT_VERSION,
+        created: now,
+        prefix: prefix.to_string(),
+        keys: BTreeMap::new(),
+        missing: Vec::new(),
+    };
+    let mut blobs = HashSet::new();
+    let mut blob_bytes = 0;
+
+    let mut fetched = keys
+        .map_ok(|key| async move {
+            let value = blobstore
+                .get(ctx, &format!("{}{}", prefix, key))
+                .await
+                .with_context(|| format!("Failed to fetch {}", key))?;
+            Ok((key, value))
+        })
+        .try_buffered(scheduled_max);
+
+    while let Some((key, value)) = fetched.try_next().await? {
+        let value = match value {
+            Some(value) => value.into_raw_bytes(),
+            None if allow_missing => {
+                manifest.missing.push(key);
+                continue;
+            }
+            None => bail!("Key {} has no value", key),
+        };
+
+        let sha256 = Sha256::from_byte_array(sha2::Sha256::digest(&value).into()).to_string();
+        if blobs.insert(sha256.clone()) {
+            let mut header = tar_header(value.len(), now);
+            archive.append_data(&mut header, blob_path(&sha256), value.as_ref())?;
+            blob_bytes += value.len();
+        }
+        manifest.keys.insert(
+            key,
+            ManifestEntry {
+                sha256,
+                size: value.len() as u64,
+            },
+        );
+    }
+
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar_header(manifest_json.len(), now);
+    archive.append_data(&mut header, MANIFEST_PATH, manifest_json.as_slice())?;
+    let mut writer = archive.into_inner()?;
+    writer.flush()?;
+
+    info!(
+        ctx.logger(),
+        "Exported {} keys ({} distinct blobs, {} bytes, {} missing) to {}",
+        manifest.keys.len(),
+        blobs.len(),
+        blob_bytes,
+        manifest.missing.len(),
+        output.display()
+    );
+    let digest = Sha256::from_byte_array(writer.hasher.result().into());
+    println!("sha256:{}", digest);
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use anyhow::{bail, format_err, Context, Result};
+use blobstore::Blobstore;
+use bytes::Bytes;
+use context::CoreContext;
+use futures::stream::{FuturesUnordered, TryStreamExt};
+use mononoke_types::{hash::Sha256, BlobstoreBytes};
+use sha2::Digest;
+use slog::info;
+
+use crate::manifest::{blob_path, Manifest, MANIFEST_PATH, SNAPSHOT_VERSION};
+
+fn open_archive(input: &Path) -> Result<tar::Archive<BufReader<File>>> {
+    let file = File::open(input).with_context(|| format!("Failed to open {}", input.display()))?;
+    Ok(tar::Archive::new(BufReader::new(file)))
+}
+
+fn read_manifest(input: &Path) -> Result<Manifest> {
+    let mut archive = open_archive(input)?;
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.path()?.as_os_str() == MANIFEST_PATH {
+            let manifest: Manifest = serde_json::from_reader(entry)?;
+            if manifest.version != SNAPSHOT_VERSION {
+                bail!(
+                    "Snapshot version {} is not supported (expected {})",
+                    manifest.version,
+                    SNAPSHOT_VERSION
+                );
+            }
+            return Ok(manifest);
+        }
+    }
+    bail!(
+        "{} has no manifest, it is not a complete snapshot",
+        input.display()
+    )
+}
+
+/// Store the values of a snapshot made by `export`, under `prefix`. Blobs are checked against
+/// their hash before anything is stored under their keys.
+pub async fn import(
+    ctx: &CoreContext,
+    blobstore: &dyn Blobstore,
+    prefix: &str,
+    input: &Path,
+    scheduled_max: usize,
+) -> Result<()> {
+    // The manifest is at the end of the archive, so it takes a first pass to find it.
+    let manifest = read_manifest(input)?;
+    let mut keys_by_blob: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (key, entry) in manifest.keys.iter() {
+        keys_by_blob
+            .entry(entry.sha256.as_str())
+            .or_default()
+            .push(key.as_str());
+    }
+    let blob_paths: HashMap<_, _> = keys_by_blob
+        .keys()
+        .map(|sha256| (blob_path(sha256), *sha256))
+        .collect();
+
+    let mut archive = open_archive(input)?;
+    let mut puts = FuturesUnordered::new();
+    let mut imported = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        let sha256 = match blob_paths.get(&path) {
+            Some(sha256) => *sha256,
+            None => continue,
+        };
+
+        let mut value = Vec::with_capacity(entry.header().size()? as usize);
+        entry.read_to_end(&mut value)?;
+        let actual = Sha256::from_byte_array(sha2::Sha256::digest(&value).into()).to_string();
+        if actual != sha256 {
+            bail!(
+                "Blob {} is corrupt: its content hashes to {}",
+                sha256,
+                actual
+            );
+        }
+
+        let value = Bytes::from(value);
+        for key in keys_by_blob.remove(sha256).unwrap_or_default() {
+            let value = BlobstoreBytes::from_bytes(value.clone());
+            puts.push(async move {
+                blobstore
+                    .put(ctx, format!("{}{}", prefix, key), value)
+                    .await
+                    .with_context(|| format!("Failed to store {}", key))
+            });
+            imported += 1;
+            while puts.len() >= scheduled_max {
+                puts.try_next().await?;
+            }
+        }
+    }
+    while puts.try_next().await?.is_some() {}
+
+    if let Some(sha256) = keys_by_blob.keys().next() {
+        return Err(format_err!(
+            "{} blobs referenced by the manifest are not in the snapshot, e.g. {}",
+            keys_by_blob.len(),
+            sha256
+        ));
+    }
+
+    info!(
+        ctx.logger(),
+        "Imported {} keys from {} ({} were missing from the source when it was exported)",
+        imported,
+        input.display(),
+        manifest.missing.len()
+    );
+
+    Ok(())
+}
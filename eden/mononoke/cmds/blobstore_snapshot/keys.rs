@@ -0,0 +1,130 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! The keys to export, other than those listed on stdin. Keys are relative to the prefix, as
+//! `export` takes them.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::{bail, format_err, Result};
+use blobstore::{Blobstore, BlobstoreKeyParam, BlobstoreKeySource, Loadable};
+use context::CoreContext;
+use fileblob::Fileblob;
+use filestore::{Alias, FetchKey};
+use futures::{
+    future,
+    stream::{self, Stream, StreamExt, TryStreamExt},
+};
+use metaconfig_types::BlobConfig;
+use mononoke_types::{ChangesetId, ContentId, ContentMetadataId, FileContents, MononokeId};
+use prefixblob::PrefixBlobstore;
+
+/// A store to list the keys of, for the backends that can list them.
+pub fn make_key_source(blob_config: &BlobConfig) -> Result<Arc<dyn BlobstoreKeySource>> {
+    match blob_config {
+        // The blobstore factory keeps the blobs in a subdirectory of the configured path.
+        BlobConfig::Files { path } => Ok(Arc::new(Fileblob::open(
+            path.join("blobs"),
+            blobstore::DEFAULT_PUT_BEHAVIOUR,
+        )?)),
+        _ => bail!("Listing the keys of this kind of blobstore is not supported"),
+    }
+}
+
+/// All the keys under `prefix`.
+pub fn keys_with_prefix<'a>(
+    ctx: &'a CoreContext,
+    key_source: &'a dyn BlobstoreKeySource,
+    prefix: &'a str,
+) -> impl Stream<Item = Result<String>> + 'a {
+    // Keys are ASCII, so nothing under the prefix sorts after this.
+    let range = BlobstoreKeyParam::from(prefix.to_string()..format!("{}\x7f", prefix));
+    stream::try_unfold(Some(range), move |range| async move {
+        let range = match range {
+            Some(range) => range,
+            None => return Ok(None),
+        };
+        let data = key_source.enumerate(ctx, &range).await?;
+        let keys = data
+            .keys
+            .into_iter()
+            .map(|key| {
+                key.strip_prefix(prefix)
+                    .map(String::from)
+                    .ok_or_else(|| format_err!("Listed key {} is not under {}", key, prefix))
+            })
+            .collect::<Vec<_>>();
+        Ok(Some((stream::iter(keys), data.next_token)))
+    })
+    .try_flatten()
+}
+
+/// The keys of the commits, with those of the contents of the files that they change. That is
+/// all of a commit's own data: everything else can be derived from it again once it is imported.
+pub fn commit_keys<'a>(
+    ctx: &'a CoreContext,
+    blobstore: &'a PrefixBlobstore<Arc<dyn Blobstore>>,
+    commits: Vec<ChangesetId>,
+) -> impl Stream<Item = Result<String>> + 'a {
+    stream::iter(commits)
+        .map(move |cs_id| async move {
+            let bonsai = cs_id.load(ctx, blobstore).await?;
+            let contents: HashSet<ContentId> = bonsai
+                .file_changes()
+                .filter_map(|(_, change)| Some(change?.content_id()))
+                .collect();
+
+            let mut keys = vec![cs_id.blobstore_key()];
+            for content_id in contents {
+                keys.extend(content_keys(ctx, blobstore, content_id).await?);
+            }
+            Ok::<_, anyhow::Error>(stream::iter(keys.into_iter().map(Ok)))
+        })
+        .buffered(10)
+        .try_flatten()
+        // Commits share contents, which only need to be exported once.
+        .try_filter({
+            let mut seen = HashSet::new();
+            move |key| future::ready(seen.insert(key.clone()))
+        })
+}
+
+/// The keys of a file's content, its chunks, its metadata and its aliases.
+async fn content_keys(
+    ctx: &CoreContext,
+    blobstore: &PrefixBlobstore<Arc<dyn Blobstore>>,
+    content_id: ContentId,
+) -> Result<Vec<String>> {
+    let mut keys = vec![
+        content_id.blobstore_key(),
+        ContentMetadataId::from(content_id).blobstore_key(),
+    ];
+
+    let contents = content_id.load(ctx, blobstore).await?;
+    if let FileContents::Chunked(chunked) = contents {
+        keys.extend(
+            chunked
+                .iter_chunks()
+                .map(|chunk| chunk.chunk_id().blobstore_key()),
+        );
+    }
+
+    let metadata =
+        filestore::get_metadata_readonly(blobstore, ctx, &FetchKey::Canonical(content_id))
+            .await?
+            .flatten()
+            .ok_or_else(|| format_err!("Content {} has no metadata", content_id))?;
+    let aliases = vec![
+        Alias::Sha1(metadata.sha1),
+        Alias::Sha256(metadata.sha256),
+        Alias::GitSha1(metadata.git_sha1.sha1()),
+    ];
+    keys.extend(aliases.iter().map(Alias::blobstore_key));
+
+    Ok(keys)
+}
@@ -0,0 +1,293 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Export blobs to a self-contained snapshot, and import them back, e.g. to move a repo to a
+//! storage that can't be reached from where it is now, or to keep a copy for disaster recovery.
+//!
+//! A snapshot is a tar archive holding each distinct value once, named by its SHA-256, followed
+//! by a manifest mapping keys to values. As it is a plain tar file, it can also be used as an
+//! OCI image layer, with the digest that the export prints.
+//!
+//! The keys to export are read from stdin, one per line, e.g. as listed by the walker. Otherwise,
+//! the export can be of all the keys under the prefix, for the backends that can list their
+//! keys, or of the data of some commits, i.e. the changesets and the contents of the files they
+//! change. Derived data isn't part of the latter, as it can be derived again once imported.
+
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error, Result};
+use clap::{App, Arg, ArgGroup, SubCommand};
+use futures::{
+    future,
+    stream::{StreamExt, TryStreamExt},
+};
+use tokio::io::{stdin, AsyncBufReadExt, BufReader};
+
+use blobstore_factory::{make_blobstore, ReadOnlyStorage};
+use cmdlib::args;
+use context::CoreContext;
+use mononoke_types::ChangesetId;
+use prefixblob::PrefixBlobstore;
+
+mod export;
+mod import;
+mod keys;
+mod manifest;
+
+const ARG_STORAGE_CONFIG_NAME: &str = "storage-config-name";
+const ARG_SCHEDULED_MAX: &str = "scheduled-max";
+const ARG_PREFIX: &str = "prefix";
+const ARG_ARCHIVE: &str = "archive";
+const ARG_ALLOW_MISSING: &str = "allow-missing";
+const ARG_ALL_KEYS: &str = "all-keys";
+const ARG_COMMIT: &str = "commit";
+
+const SUBCOMMAND_EXPORT: &str = "export";
+const SUBCOMMAND_IMPORT: &str = "import";
+
+fn archive_arg<'a, 'b>(help: &'static str) -> Arg<'a, 'b> {
+    Arg::with_name(ARG_ARCHIVE)
+        .long(ARG_ARCHIVE)
+        .takes_value(true)
+        .required(true)
+        .help(help)
+}
+
+fn build_export_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(SUBCOMMAND_EXPORT)
+        .about("export the values of the keys read from stdin to a snapshot")
+        .arg(archive_arg("the file to write the snapshot to"))
+        .arg(
+            Arg::with_name(ARG_ALLOW_MISSING)
+                .long(ARG_ALLOW_MISSING)
+                .takes_value(false)
+                .help("list keys that have no value in the manifest, instead of failing"),
+        )
+        .arg(
+            Arg::with_name(ARG_ALL_KEYS)
+                .long(ARG_ALL_KEYS)
+                .takes_value(false)
+                .help("export all the keys under the prefix, instead of those read from stdin"),
+        )
+        .arg(
+            Arg::with_name(ARG_COMMIT)
+                .long(ARG_COMMIT)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help(
+                    "export the data of this bonsai commit, instead of the keys read from stdin \
+                     (can be repeated)",
+                ),
+        )
+        .group(ArgGroup::with_name("keys").args(&[ARG_ALL_KEYS, ARG_COMMIT]))
+}
+
+fn build_import_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(SUBCOMMAND_IMPORT)
+        .about("store the values of a snapshot")
+        .arg(archive_arg("the snapshot to import"))
+}
+
+#[fbinit::main]
+fn main(fb: fbinit::FacebookInit) -> Result<()> {
+    let app = args::MononokeAppBuilder::new("blobstore snapshot")
+        .with_advanced_args_hidden()
+        .with_all_repos()
+        .build()
+        .arg(
+            Arg::with_name(ARG_STORAGE_CONFIG_NAME)
+                .long(ARG_STORAGE_CONFIG_NAME)
+                .takes_value(true)
+                .required(true)
+                .help("the name of the storage config to export from or import to"),
+        )
+        .arg(
+            Arg::with_name(ARG_SCHEDULED_MAX)
+                .long(ARG_SCHEDULED_MAX)
+                .takes_value(true)
+                .required(false)
+                .help("Maximum number of keys to fetch or store at once.  Default 100."),
+        )
+        .arg(
+            Arg::with_name(ARG_PREFIX)
+                .long(ARG_PREFIX)
+                .takes_value(true)
+                .required(false)
+                .help(
+                    "Prefix of the keys in the blobstore, e.g. repo0042. to export the keys of \
+                     a repo, and import them into another one under its own prefix",
+                ),
+        )
+        .subcommand(build_export_subcommand())
+        .subcommand(build_import_subcommand());
+
+    let matches = app.get_matches();
+    let (_, logger, mut runtime) =
+        args::init_mononoke(fb, &matches).context("failed to initialise mononoke")?;
+    let config_store = args::init_config_store(fb, &logger, &matches)?;
+
+    let scheduled_max = args::get_usize_opt(&matches, ARG_SCHEDULED_MAX).unwrap_or(100);
+    let prefix = matches.value_of(ARG_PREFIX).unwrap_or("").to_string();
+
+    let storage_config = args::load_storage_configs(config_store, &matches)
+        .context("Could not read storage configs")?
+        .storage
+        .remove(
+            matches
+                .value_of(ARG_STORAGE_CONFIG_NAME)
+                .context("No storage config name")?,
+        )
+        .context("Requested storage config not found")?;
+
+    let (subcommand, sub_m) = match matches.subcommand() {
+        (name, Some(sub_m)) => (name, sub_m),
+        _ => bail!("Expected {} or {}", SUBCOMMAND_EXPORT, SUBCOMMAND_IMPORT),
+    };
+    let archive = Path::new(sub_m.value_of_os(ARG_ARCHIVE).context("No archive")?);
+
+    let mysql_options = args::parse_mysql_options(&matches);
+    let blobstore_options = args::parse_blobstore_options(&matches)?;
+    let ctx = CoreContext::new_bulk_with_logger(fb, logger.clone());
+
+    let blob_config = storage_config.blobstore;
+    let key_source = if sub_m.is_present(ARG_ALL_KEYS) {
+        Some(keys::make_key_source(&blob_config)?)
+    } else {
+        None
+    };
+    let commits = sub_m
+        .values_of(ARG_COMMIT)
+        .into_iter()
+        .flatten()
+        .map(ChangesetId::from_str)
+        .collect::<Result<Vec<_>>>()?;
+
+    let run = async move {
+        let blobstore = make_blobstore(
+            fb,
+            blob_config,
+            &mysql_options,
+            ReadOnlyStorage(subcommand == SUBCOMMAND_EXPORT),
+            &blobstore_options,
+            &logger,
+            config_store,
+        )
+        .await?;
+
+        match subcommand {
+            SUBCOMMAND_EXPORT => {
+                let prefixed = PrefixBlobstore::new(blobstore.clone(), prefix.clone());
+                let keys = if let Some(key_source) = &key_source {
+                    keys::keys_with_prefix(&ctx, &**key_source, &prefix).boxed_local()
+                } else if !commits.is_empty() {
+                    keys::commit_keys(&ctx, &prefixed, commits).boxed_local()
+                } else {
+                    BufReader::new(stdin())
+                        .lines()
+                        .map_err(Error::from)
+                        .try_filter(|key| future::ready(!key.is_empty()))
+                        .boxed_local()
+                };
+                export::export(
+                    &ctx,
+                    &*blobstore,
+                    keys,
+                    &prefix,
+                    archive,
+                    scheduled_max,
+                    sub_m.is_present(ARG_ALLOW_MISSING),
+                )
+                .await
+            }
+            SUBCOMMAND_IMPORT => {
+                import::import(&ctx, &*blobstore, &prefix, archive, scheduled_max).await
+            }
+            _ => bail!("Unknown subcommand {}", subcommand),
+        }
+    };
+
+    runtime.block_on(run)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    use blobrepo_factory::TestRepoBuilder;
+    use blobstore::{Blobstore, Loadable};
+    use fbinit::FacebookInit;
+    use memblob::Memblob;
+    use mononoke_types::MononokeId;
+    use mononoke_types_mocks::repo::REPO_ONE;
+    use tests_utils::CreateCommitContext;
+
+    #[fbinit::test]
+    async fn test_export_import_commit(fb: FacebookInit) -> Result<()> {
+        let ctx = CoreContext::test_mock(fb);
+        let source: Arc<dyn Blobstore> = Arc::new(Memblob::default());
+        let repo = TestRepoBuilder::new().blobstore(source.clone()).build()?;
+        let cs_id = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file", "content")
+            .add_file("other", "content")
+            .commit()
+            .await?;
+
+        let source_prefix = repo.get_repoid().prefix();
+        let prefixed = PrefixBlobstore::new(source.clone(), source_prefix.clone());
+        let keys = keys::commit_keys(&ctx, &prefixed, vec![cs_id])
+            .try_collect::<Vec<_>>()
+            .await?;
+        // The changeset, and the content, metadata and three aliases of the one file content.
+        assert_eq!(keys.len(), 6);
+        assert!(keys.contains(&cs_id.blobstore_key()));
+
+        let dir = tempfile::tempdir()?;
+        let archive = dir.path().join("snapshot.tar");
+        export::export(
+            &ctx,
+            &*source,
+            futures::stream::iter(keys.clone().into_iter().map(Ok)),
+            &source_prefix,
+            &archive,
+            10,
+            false,
+        )
+        .await?;
+
+        // Snapshots can be imported for another repo.
+        let target: Arc<dyn Blobstore> = Arc::new(Memblob::default());
+        let target_prefix = REPO_ONE.prefix();
+        import::import(&ctx, &*target, &target_prefix, &archive, 10).await?;
+        for key in keys.iter() {
+            let expected = source
+                .get(&ctx, &format!("{}{}", source_prefix, key))
+                .await?;
+            let imported = target
+                .get(&ctx, &format!("{}{}", target_prefix, key))
+                .await?;
+            assert!(expected.is_some());
+            assert_eq!(
+                imported.map(|value| value.into_raw_bytes()),
+                expected.map(|value| value.into_raw_bytes())
+            );
+        }
+
+        let repo = TestRepoBuilder::new()
+            .id(REPO_ONE)
+            .blobstore(target)
+            .build()?;
+        let bonsai = cs_id.load(&ctx, repo.blobstore()).await?;
+        assert_eq!(bonsai.file_changes().count(), 2);
+
+        Ok(())
+    }
+}
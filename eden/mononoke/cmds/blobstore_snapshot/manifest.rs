@@ -0,0 +1,41 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Bumped on incompatible changes to the layout of snapshots.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Name of the manifest in the archive. It is the last entry, as it is only complete once all
+/// the blobs have been fetched.
+pub const MANIFEST_PATH: &str = "manifest.json";
+
+/// Blobs are stored by the SHA-256 of their content, so that keys with the same value (e.g. the
+/// aliases of a file) share one entry.
+pub fn blob_path(sha256: &str) -> String {
+    format!("blobs/{}", sha256)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub sha256: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Seconds since the epoch at which the export finished.
+    pub created: u64,
+    /// The prefix the keys were exported from. Keys below are relative to it.
+    pub prefix: String,
+    pub keys: BTreeMap<String, ManifestEntry>,
+    /// Keys that were asked for but had no value, when those were allowed.
+    pub missing: Vec<String>,
+}
@@ -0,0 +1,360 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Export bonsai changesets as JSON lines, and import them back into a repo. The file contents
+//! referenced by the changesets are not exported: they must already be present in the blobstore
+//! of the repo being imported into.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+use anyhow::{bail, format_err, Context, Error};
+use blobrepo::{save_bonsai_changesets, BlobRepo};
+use blobstore::Loadable;
+use clap::{Arg, ArgMatches, SubCommand};
+use cmdlib::{
+    args::{self, MononokeMatches},
+    helpers::{block_execute, csid_resolve},
+};
+use context::CoreContext;
+use fbinit::FacebookInit;
+use filestore::FetchKey;
+use futures::{
+    compat::{Future01CompatExt, Stream01CompatExt},
+    stream::{self, StreamExt, TryStreamExt},
+};
+use mononoke_types::{
+    BonsaiChangeset, BonsaiChangesetMut, ChangesetId, DateTime, FileChange, MPath,
+};
+use revset::RangeNodeStream;
+use serde_derive::{Deserialize, Serialize};
+use slog::info;
+
+const SUBCOMMAND_EXPORT: &str = "export";
+const SUBCOMMAND_IMPORT: &str = "import";
+
+const ARG_START: &str = "start";
+const ARG_END: &str = "end";
+const ARG_OUTPUT: &str = "output";
+const ARG_INPUT: &str = "INPUT";
+const ARG_BATCH_SIZE: &str = "batch-size";
+
+/// A bonsai changeset, in a form that can be written as JSON. Paths are stored as strings, so
+/// changesets touching paths that aren't valid UTF-8 can't be exported.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct ExportedChangeset {
+    id: ChangesetId,
+    parents: Vec<ChangesetId>,
+    author: String,
+    author_date: DateTime,
+    committer: Option<String>,
+    committer_date: Option<DateTime>,
+    message: String,
+    extra: BTreeMap<String, Vec<u8>>,
+    file_changes: BTreeMap<String, Option<FileChange>>,
+}
+
+impl ExportedChangeset {
+    fn from_bonsai(bcs: BonsaiChangeset) -> Result<Self, Error> {
+        let id = bcs.get_changeset_id();
+        let bcs = bcs.into_mut();
+
+        let file_changes = bcs
+            .file_changes
+            .into_iter()
+            .map(|(path, change)| {
+                let path = String::from_utf8(path.to_vec())
+                    .with_context(|| format!("Path in {} is not valid UTF-8", id))?;
+                Ok((path, change))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Self {
+            id,
+            parents: bcs.parents,
+            author: bcs.author,
+            author_date: bcs.author_date,
+            committer: bcs.committer,
+            committer_date: bcs.committer_date,
+            message: bcs.message,
+            extra: bcs.extra.into_iter().collect(),
+            file_changes,
+        })
+    }
+
+    fn into_bonsai(self) -> Result<BonsaiChangeset, Error> {
+        let file_changes = self
+            .file_changes
+            .into_iter()
+            .map(|(path, change)| Ok((MPath::new(path)?, change)))
+            .collect::<Result<_, Error>>()?;
+
+        let bcs = BonsaiChangesetMut {
+            parents: self.parents,
+            author: self.author,
+            author_date: self.author_date,
+            committer: self.committer,
+            committer_date: self.committer_date,
+            message: self.message,
+            extra: self.extra.into_iter().collect(),
+            file_changes,
+        }
+        .freeze()?;
+
+        if bcs.get_changeset_id() != self.id {
+            bail!(
+                "Changeset {} was imported as {}. Was the export modified?",
+                self.id,
+                bcs.get_changeset_id()
+            );
+        }
+
+        Ok(bcs)
+    }
+}
+
+async fn export(ctx: &CoreContext, repo: &BlobRepo, sub_m: &ArgMatches<'_>) -> Result<(), Error> {
+    let start = sub_m.value_of(ARG_START).unwrap();
+    let end = sub_m.value_of(ARG_END).unwrap();
+    let (start, end) = futures::try_join!(
+        csid_resolve(ctx.clone(), repo.clone(), start).compat(),
+        csid_resolve(ctx.clone(), repo.clone(), end).compat(),
+    )?;
+
+    let mut writer: Box<dyn Write> = match sub_m.value_of(ARG_OUTPUT) {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+
+    // Sort by generation, so that parents are always written (and later imported) before their
+    // children.
+    let changeset_fetcher = repo.get_changeset_fetcher();
+    let mut cs_ids: Vec<_> =
+        RangeNodeStream::new(ctx.clone(), changeset_fetcher.clone(), start, end)
+            .compat()
+            .map_ok(|cs_id| {
+                let changeset_fetcher = changeset_fetcher.clone();
+                async move {
+                    let generation = changeset_fetcher
+                        .get_generation_number(ctx.clone(), cs_id)
+                        .await?;
+                    Result::<_, Error>::Ok((generation, cs_id))
+                }
+            })
+            .try_buffer_unordered(100)
+            .try_collect()
+            .await?;
+    cs_ids.sort();
+
+    let count = cs_ids.len();
+    let mut changesets = stream::iter(cs_ids)
+        .map(|(_, cs_id)| async move { cs_id.load(ctx, repo.blobstore()).await })
+        .buffered(100);
+
+    while let Some(bcs) = changesets.try_next().await? {
+        let exported = ExportedChangeset::from_bonsai(bcs)?;
+        serde_json::to_writer(&mut writer, &exported)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+
+    info!(ctx.logger(), "Exported {} changesets", count);
+
+    Ok(())
+}
+
+async fn check_content_present(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    bcs: &BonsaiChangeset,
+) -> Result<(), Error> {
+    for (path, change) in bcs.file_changes() {
+        if let Some(change) = change {
+            let key = FetchKey::Canonical(change.content_id());
+            if !filestore::exists(repo.blobstore(), ctx, &key).await? {
+                bail!(
+                    "Content {} for {} in {} is missing from the repo",
+                    change.content_id(),
+                    path,
+                    bcs.get_changeset_id()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn import(ctx: &CoreContext, repo: &BlobRepo, sub_m: &ArgMatches<'_>) -> Result<(), Error> {
+    let input = sub_m.value_of(ARG_INPUT).unwrap();
+    let batch_size = args::get_usize(sub_m, ARG_BATCH_SIZE, 100);
+
+    let reader: Box<dyn BufRead> = if input == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(input)?))
+    };
+
+    let mut imported = 0;
+    let mut skipped = 0;
+    let mut batch = Vec::new();
+
+    for (line_num, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let exported: ExportedChangeset = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid changeset on line {}", line_num + 1))?;
+        let bcs = exported.into_bonsai()?;
+
+        if repo
+            .changeset_exists_by_bonsai(ctx.clone(), bcs.get_changeset_id())
+            .await?
+        {
+            skipped += 1;
+            continue;
+        }
+
+        check_content_present(ctx, repo, &bcs).await?;
+        batch.push(bcs);
+
+        if batch.len() >= batch_size {
+            imported += batch.len();
+            save_bonsai_changesets(std::mem::take(&mut batch), ctx.clone(), repo.clone()).await?;
+            info!(ctx.logger(), "Imported {} changesets", imported);
+        }
+    }
+
+    imported += batch.len();
+    save_bonsai_changesets(batch, ctx.clone(), repo.clone()).await?;
+
+    info!(
+        ctx.logger(),
+        "Imported {} changesets, skipped {} that were already present", imported, skipped
+    );
+
+    Ok(())
+}
+
+async fn run<'a>(ctx: CoreContext, matches: &'a MononokeMatches<'a>) -> Result<(), Error> {
+    let repo = args::open_repo(ctx.fb, ctx.logger(), matches).await?;
+
+    match matches.subcommand() {
+        (SUBCOMMAND_EXPORT, Some(sub_m)) => export(&ctx, &repo, sub_m).await,
+        (SUBCOMMAND_IMPORT, Some(sub_m)) => import(&ctx, &repo, sub_m).await,
+        _ => Err(format_err!("Invalid subcommand")),
+    }
+}
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<(), Error> {
+    let matches = args::MononokeAppBuilder::new("Export and import bonsai changesets as JSON")
+        .with_advanced_args_hidden()
+        .build()
+        .subcommand(
+            SubCommand::with_name(SUBCOMMAND_EXPORT)
+                .about("export the changesets between two commits (inclusive) as JSON lines")
+                .arg(
+                    Arg::with_name(ARG_START)
+                        .long(ARG_START)
+                        .takes_value(true)
+                        .required(true)
+                        .help("the first changeset to export (hash or bookmark)"),
+                )
+                .arg(
+                    Arg::with_name(ARG_END)
+                        .long(ARG_END)
+                        .takes_value(true)
+                        .required(true)
+                        .help("the last changeset to export (hash or bookmark)"),
+                )
+                .arg(
+                    Arg::with_name(ARG_OUTPUT)
+                        .long(ARG_OUTPUT)
+                        .takes_value(true)
+                        .help("file to write to, instead of stdout"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUBCOMMAND_IMPORT)
+                .about("import changesets from a file written by export")
+                .arg(
+                    Arg::with_name(ARG_INPUT)
+                        .required(true)
+                        .help("file to read changesets from, or - for stdin"),
+                )
+                .arg(
+                    Arg::with_name(ARG_BATCH_SIZE)
+                        .long(ARG_BATCH_SIZE)
+                        .takes_value(true)
+                        .help("number of changesets to save at once"),
+                ),
+        )
+        .get_matches();
+
+    args::init_cachelib(fb, &matches);
+    let logger = args::init_logging(fb, &matches)?;
+    args::init_config_store(fb, &logger, &matches)?;
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+
+    block_execute(
+        run(ctx, &matches),
+        fb,
+        "bonsai_json",
+        &logger,
+        &matches,
+        cmdlib::monitoring::AliveService,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mononoke_types::{ContentId, FileType};
+
+    #[test]
+    fn test_round_trip() -> Result<(), Error> {
+        let bcs = BonsaiChangesetMut {
+            parents: vec![ChangesetId::from_byte_array([1; 32])],
+            author: "author".to_string(),
+            author_date: DateTime::from_timestamp(1000, 0)?,
+            committer: None,
+            committer_date: None,
+            message: "message".to_string(),
+            extra: vec![("key".to_string(), b"value".to_vec())]
+                .into_iter()
+                .collect(),
+            file_changes: vec![
+                (
+                    MPath::new("dir/file")?,
+                    Some(FileChange::new(
+                        ContentId::from_byte_array([2; 32]),
+                        FileType::Regular,
+                        5,
+                        None,
+                    )),
+                ),
+                (MPath::new("deleted")?, None),
+            ]
+            .into_iter()
+            .collect(),
+        }
+        .freeze()?;
+
+        let exported = ExportedChangeset::from_bonsai(bcs.clone())?;
+        let json = serde_json::to_string(&exported)?;
+        let imported: ExportedChangeset = serde_json::from_str(&json)?;
+        assert_eq!(imported, exported);
+        assert_eq!(imported.into_bonsai()?, bcs);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Keeps the derived data of the bookmarks of all repos warm, so that it doesn't have to be done
+//! by the servers when they start, or when a bookmark moves: servers run with
+//! `--external-bookmark-warmer` then only wait for it. How far behind the bookmarks it is gets
+//! reported per repo, along with whether that is within the freshness SLO.
+
+use anyhow::Error;
+use blobrepo_factory::BlobrepoBuilder;
+use clap::Arg;
+use cmdlib::{
+    args::{self, MononokeMatches},
+    helpers,
+    monitoring::ReadyFlagService,
+};
+use context::CoreContext;
+use fbinit::FacebookInit;
+use futures::future::try_join_all;
+use slog::{info, o, warn, Logger};
+use stats::prelude::*;
+use std::time::Duration;
+use warm_bookmarks_cache::{BookmarkUpdateDelay, WarmBookmarksCacheBuilder};
+
+const ARG_FRESHNESS_SLO: &str = "freshness-slo";
+const ARG_REPORT_INTERVAL: &str = "report-interval";
+
+const DEFAULT_FRESHNESS_SLO_SECS: u64 = 300;
+const DEFAULT_REPORT_INTERVAL_SECS: u64 = 10;
+
+define_stats! {
+    prefix = "mononoke.bookmark_warmer";
+    lag_secs: dynamic_singleton_counter("{}.lag_secs", (reponame: String)),
+    slo_violated: dynamic_singleton_counter("{}.slo_violated", (reponame: String)),
+}
+
+async fn run<'a>(
+    fb: FacebookInit,
+    matches: &'a MononokeMatches<'a>,
+    logger: &Logger,
+    service: &ReadyFlagService,
+) -> Result<(), Error> {
+    let freshness_slo_secs = args::get_u64(matches, ARG_FRESHNESS_SLO, DEFAULT_FRESHNESS_SLO_SECS);
+    let report_interval = Duration::from_secs(args::get_u64(
+        matches,
+        ARG_REPORT_INTERVAL,
+        DEFAULT_REPORT_INTERVAL_SECS,
+    ));
+
+    let mysql_options = args::parse_mysql_options(matches);
+    let readonly_storage = args::parse_readonly_storage(matches);
+    let blobstore_options = args::parse_blobstore_options(matches)?;
+    let caching = args::init_cachelib(fb, matches);
+    let config_store = args::init_config_store(fb, logger, matches)?;
+    let configs = args::load_repo_configs(config_store, matches)?;
+    let censored_scuba_params = configs.common.censored_scuba_params;
+
+    let caches = configs
+        .repos
+        .into_iter()
+        .filter(|(_, config)| config.enabled)
+        .map(|(name, config)| {
+            let mysql_options = &mysql_options;
+            let blobstore_options = blobstore_options.clone();
+            let censored_scuba_params = censored_scuba_params.clone();
+            async move {
+                let logger = logger.new(o!("repo" => name.clone()));
+                let repo = BlobrepoBuilder::new(
+                    fb,
+                    name.clone(),
+                    &config,
+                    mysql_options,
+                    caching,
+                    censored_scuba_params,
+                    readonly_storage,
+                    blobstore_options,
+                    &logger,
+                    config_store,
+                )
+                .build()
+                .await?;
+
+                let ctx = CoreContext::new_with_logger(fb, logger);
+                let mut builder = WarmBookmarksCacheBuilder::new(&ctx, &repo);
+                builder.add_all_derived_data_warmers()?;
+                // The point is to warm bookmarks as soon as they move, so that servers don't
+                // have to, so there is no reason to wait.
+                let cache = builder.build(BookmarkUpdateDelay::Disallow).await?;
+                Result::<_, Error>::Ok((name, cache))
+            }
+        });
+    let caches = try_join_all(caches).await?;
+
+    info!(logger, "Warming bookmarks of {} repos", caches.len());
+    service.set_ready();
+
+    loop {
+        for (name, cache) in &caches {
+            let lag_secs = cache.max_staleness_secs();
+            let slo_violated = lag_secs > freshness_slo_secs as i64;
+            STATS::lag_secs.set_value(fb, lag_secs, (name.clone(),));
+            STATS::slo_violated.set_value(fb, slo_violated as i64, (name.clone(),));
+            if slo_violated {
+                warn!(
+                    logger,
+                    "{} is {}s behind its bookmarks, over the freshness SLO of {}s",
+                    name,
+                    lag_secs,
+                    freshness_slo_secs
+                );
+            }
+        }
+        tokio::time::delay_for(report_interval).await;
+    }
+}
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<(), Error> {
+    let matches = args::MononokeAppBuilder::new("Keeps the derived data of bookmarks warm")
+        .with_advanced_args_hidden()
+        .with_fb303_args()
+        .with_all_repos()
+        .build()
+        .arg(
+            Arg::with_name(ARG_FRESHNESS_SLO)
+                .long(ARG_FRESHNESS_SLO)
+                .takes_value(true)
+                .required(false)
+                .help("How many seconds the warm bookmarks may lag behind the bookmarks of a repo"),
+        )
+        .arg(
+            Arg::with_name(ARG_REPORT_INTERVAL)
+                .long(ARG_REPORT_INTERVAL)
+                .takes_value(true)
+                .required(false)
+                .help("How often the lag is reported, in seconds"),
+        )
+        .get_matches();
+
+    let logger = args::init_logging(fb, &matches)?;
+    args::init_config_store(fb, &logger, &matches)?;
+
+    let service = ReadyFlagService::new();
+    let main = run(fb, &matches, &logger, &service);
+    helpers::block_execute(
+        main,
+        fb,
+        &std::env::var("TW_JOB_NAME").unwrap_or_else(|_| "bookmark_warmer".to_string()),
+        &logger,
+        &matches,
+        service.clone(),
+    )
+}
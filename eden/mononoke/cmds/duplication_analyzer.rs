@@ -0,0 +1,305 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Report how much file content is duplicated in a repo, to help decide on chunking and
+//! compression policies for it. Files with identical contents share a ContentId, so they are only
+//! stored once: the bytes saved by dedup are the bytes a store without dedup would have written
+//! for the extra copies. With --chunks, the same is reported for chunks shared between distinct
+//! contents.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Error, Result};
+use clap::Arg;
+use fbinit::FacebookInit;
+use futures::{
+    compat::{Future01CompatExt, Stream01CompatExt},
+    future::{self, TryFutureExt},
+    stream::{self, TryStreamExt},
+};
+use serde::Serialize;
+use slog::info;
+
+use blobrepo::BlobRepo;
+use blobstore::Loadable;
+use cmdlib::{args, helpers};
+use context::CoreContext;
+use derived_data::BonsaiDerived;
+use fsnodes::RootFsnodeId;
+use manifest::ManifestOps;
+use mononoke_types::{ChangesetId, ContentChunkId, ContentId, FileContents, MPath};
+use revset::RangeNodeStream;
+
+const ARG_COMMIT: &str = "commit";
+const ARG_BASE: &str = "base";
+const ARG_TOP: &str = "top";
+const ARG_CHUNKS: &str = "chunks";
+const ARG_JSON: &str = "json";
+
+// How many paths are kept for each content, as examples for the report.
+const EXAMPLE_PATHS: usize = 3;
+const CONTENT_LOAD_CONCURRENCY: usize = 100;
+
+#[derive(Default)]
+struct ContentUse {
+    size: u64,
+    count: u64,
+    example_paths: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct DuplicateGroup {
+    id: String,
+    size: u64,
+    count: u64,
+    bytes_saved: u64,
+    example_paths: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    references: u64,
+    distinct_contents: u64,
+    duplicate_groups: u64,
+    total_bytes: u64,
+    stored_bytes: u64,
+    bytes_saved: u64,
+    largest_offenders: Vec<DuplicateGroup>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    contents: Summary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunks: Option<Summary>,
+}
+
+fn summarize<K: ToString>(uses: &HashMap<K, ContentUse>, top: usize) -> Summary {
+    let mut groups: Vec<_> = uses
+        .iter()
+        .filter(|(_, content_use)| content_use.count > 1)
+        .map(|(id, content_use)| DuplicateGroup {
+            id: id.to_string(),
+            size: content_use.size,
+            count: content_use.count,
+            bytes_saved: content_use.size * (content_use.count - 1),
+            example_paths: content_use.example_paths.clone(),
+        })
+        .collect();
+    groups.sort_by(|a, b| b.bytes_saved.cmp(&a.bytes_saved));
+
+    let total_bytes = uses.values().map(|u| u.size * u.count).sum();
+    let stored_bytes = uses.values().map(|u| u.size).sum();
+    Summary {
+        references: uses.values().map(|u| u.count).sum(),
+        distinct_contents: uses.len() as u64,
+        duplicate_groups: groups.len() as u64,
+        total_bytes,
+        stored_bytes,
+        bytes_saved: total_bytes - stored_bytes,
+        largest_offenders: groups.into_iter().take(top).collect(),
+    }
+}
+
+fn record(uses: &mut HashMap<ContentId, ContentUse>, path: &MPath, id: ContentId, size: u64) {
+    let content_use = uses.entry(id).or_default();
+    content_use.size = size;
+    content_use.count += 1;
+    if content_use.example_paths.len() < EXAMPLE_PATHS {
+        content_use.example_paths.push(path.to_string());
+    }
+}
+
+/// All the files in the working copy of `cs_id`.
+async fn contents_in_commit(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    cs_id: ChangesetId,
+) -> Result<HashMap<ContentId, ContentUse>, Error> {
+    let root = RootFsnodeId::derive(ctx, repo, cs_id).await?;
+    root.fsnode_id()
+        .list_leaf_entries(ctx.clone(), repo.get_blobstore())
+        .try_fold(HashMap::new(), |mut uses, (path, file)| {
+            record(&mut uses, &path, *file.content_id(), file.size());
+            future::ready(Ok(uses))
+        })
+        .await
+}
+
+/// All the files added or modified by the commits that are ancestors of `head` and descendants of
+/// `base`, excluding `base` itself.
+async fn contents_in_range(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    base: ChangesetId,
+    head: ChangesetId,
+) -> Result<HashMap<ContentId, ContentUse>, Error> {
+    RangeNodeStream::new(ctx.clone(), repo.get_changeset_fetcher(), base, head)
+        .compat()
+        .try_filter(|cs_id| future::ready(*cs_id != base))
+        .map_ok(|cs_id| cs_id.load(ctx, repo.blobstore()).map_err(Error::from))
+        .try_buffer_unordered(CONTENT_LOAD_CONCURRENCY)
+        .try_fold(HashMap::new(), |mut uses, bcs| {
+            for (path, change) in bcs.file_changes() {
+                if let Some(change) = change {
+                    record(&mut uses, path, change.content_id(), change.size());
+                }
+            }
+            future::ready(Ok(uses))
+        })
+        .await
+}
+
+/// Chunks shared between the distinct contents in `contents`. Contents that are stored in a single
+/// blob only have one chunk, the content itself, which is never shared.
+async fn chunks_in_contents(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    contents: &HashMap<ContentId, ContentUse>,
+) -> Result<HashMap<ContentChunkId, ContentUse>, Error> {
+    stream::iter(contents.iter().map(Ok))
+        .map_ok(|(content_id, content_use)| async move {
+            let file_contents = content_id
+                .load(ctx, repo.blobstore())
+                .await
+                .with_context(|| format!("Loading content {}", content_id))?;
+            Ok::<_, Error>((file_contents, content_use))
+        })
+        .try_buffer_unordered(CONTENT_LOAD_CONCURRENCY)
+        .try_fold(HashMap::new(), |mut uses, (file_contents, content_use)| {
+            if let FileContents::Chunked(chunked) = file_contents {
+                for chunk in chunked.iter_chunks() {
+                    let chunk_use: &mut ContentUse = uses.entry(chunk.chunk_id()).or_default();
+                    chunk_use.size = chunk.size();
+                    chunk_use.count += 1;
+                    if let Some(path) = content_use.example_paths.first() {
+                        if chunk_use.example_paths.len() < EXAMPLE_PATHS {
+                            chunk_use.example_paths.push(path.clone());
+                        }
+                    }
+                }
+            }
+            future::ready(Ok(uses))
+        })
+        .await
+}
+
+fn print_summary(name: &str, summary: &Summary) {
+    println!("{}:", name);
+    println!("  references: {}", summary.references);
+    println!("  distinct: {}", summary.distinct_contents);
+    println!("  duplicate groups: {}", summary.duplicate_groups);
+    println!("  total bytes: {}", summary.total_bytes);
+    println!("  stored bytes: {}", summary.stored_bytes);
+    println!("  bytes saved by dedup: {}", summary.bytes_saved);
+    println!("  largest offenders:");
+    for group in &summary.largest_offenders {
+        println!(
+            "    {} size={} count={} saved={} e.g. {}",
+            group.id,
+            group.size,
+            group.count,
+            group.bytes_saved,
+            group.example_paths.join(", "),
+        );
+    }
+}
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<()> {
+    let matches = args::MononokeAppBuilder::new("content duplication analyzer")
+        .build()
+        .about("Report duplicated file contents in a commit, or in the commits of a range")
+        .arg(
+            Arg::with_name(ARG_COMMIT)
+                .long(ARG_COMMIT)
+                .takes_value(true)
+                .required(true)
+                .help("commit hash or bookmark to analyze"),
+        )
+        .arg(
+            Arg::with_name(ARG_BASE)
+                .long(ARG_BASE)
+                .takes_value(true)
+                .help(
+                    "if set, analyze the files changed by the commits between this commit \
+                     (excluded) and --commit, instead of the whole working copy of --commit",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_TOP)
+                .long(ARG_TOP)
+                .takes_value(true)
+                .default_value("20")
+                .help("how many of the largest duplicate groups to report"),
+        )
+        .arg(
+            Arg::with_name(ARG_CHUNKS)
+                .long(ARG_CHUNKS)
+                .help("also report chunks shared between distinct contents"),
+        )
+        .arg(
+            Arg::with_name(ARG_JSON)
+                .long(ARG_JSON)
+                .help("print the report as JSON"),
+        )
+        .get_matches();
+
+    let (_, logger, mut runtime) = args::init_mononoke(fb, &matches)?;
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+    let top = args::get_usize(&matches, ARG_TOP, 20);
+
+    let analyze = async {
+        let repo = args::open_repo(fb, &logger, &matches).await?;
+        let commit = helpers::csid_resolve(
+            ctx.clone(),
+            repo.clone(),
+            matches.value_of(ARG_COMMIT).unwrap(),
+        )
+        .compat()
+        .await?;
+
+        let contents = match matches.value_of(ARG_BASE) {
+            Some(base) => {
+                let base = helpers::csid_resolve(ctx.clone(), repo.clone(), base)
+                    .compat()
+                    .await?;
+                contents_in_range(&ctx, &repo, base, commit).await?
+            }
+            None => contents_in_commit(&ctx, &repo, commit).await?,
+        };
+        info!(logger, "Found {} distinct contents", contents.len());
+
+        let chunks = if matches.is_present(ARG_CHUNKS) {
+            Some(summarize(
+                &chunks_in_contents(&ctx, &repo, &contents).await?,
+                top,
+            ))
+        } else {
+            None
+        };
+
+        Ok::<_, Error>(Report {
+            contents: summarize(&contents, top),
+            chunks,
+        })
+    };
+
+    let report = runtime.block_on(analyze)?;
+    if matches.is_present(ARG_JSON) {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_summary("contents", &report.contents);
+        if let Some(chunks) = &report.chunks {
+            print_summary("chunks", chunks);
+        }
+    }
+
+    Ok(())
+}
@@ -11,7 +11,7 @@ use anyhow::{Context, Error, Result};
 use clap::Arg;
 use futures::{
     channel::mpsc,
-    stream::{FuturesUnordered, StreamExt, TryStreamExt},
+    stream::{StreamExt, TryStreamExt},
 };
 use tokio::{
     fs::File,
@@ -23,8 +23,10 @@ use cmdlib::args::{self, ArgType};
 use context::CoreContext;
 
 mod scrub;
+mod summary;
 
 use crate::scrub::scrub;
+use crate::summary::ScrubSummary;
 
 const ARG_STORAGE_CONFIG_NAME: &str = "storage-config-name";
 const ARG_SCHEDULED_MAX: &str = "scheduled-max";
@@ -32,26 +34,35 @@ const ARG_SCHEDULED_MAX: &str = "scheduled-max";
 const ARG_SUCCESSFUL_KEYS: &str = "success-keys-output";
 const ARG_MISSING_KEYS: &str = "missing-keys-output";
 const ARG_ERROR_KEYS: &str = "error-keys-output";
+const ARG_TOP_ERROR_CAUSES: &str = "top-error-causes";
+const ARG_SUMMARY_OUTPUT: &str = "summary-output";
 
-async fn bridge_to_file(mut file: File, mut recv: mpsc::Receiver<String>) -> Result<()> {
+async fn bridge_to_file(mut file: File, mut recv: mpsc::Receiver<String>) -> Result<u64> {
+    let mut count = 0;
     while let Some(string) = recv.next().await {
         file.write_all(string.as_bytes()).await?;
         file.write(b"\n").await?;
+        count += 1;
     }
     // Best effort to flush
     let _ = file.flush().await;
-    Ok(())
+    Ok(count)
 }
 
-async fn handle_errors(mut file: File, mut recv: mpsc::Receiver<(String, Error)>) -> Result<()> {
+async fn handle_errors(
+    mut file: File,
+    mut recv: mpsc::Receiver<(String, Error)>,
+) -> Result<ScrubSummary> {
+    let mut summary = ScrubSummary::default();
     while let Some((key, err)) = recv.next().await {
+        summary.add_error(&key, &err);
         eprintln!("Error: {:?}", err.context(format!("Scrubbing key {}", key)));
         file.write_all(key.as_bytes()).await?;
         file.write(b"\n").await?;
     }
     // Best effort to flush
     let _ = file.flush().await;
-    Ok(())
+    Ok(summary)
 }
 
 #[fbinit::main]
@@ -96,6 +107,20 @@ fn main(fb: fbinit::FacebookInit) -> Result<()> {
                 .takes_value(true)
                 .required(true)
                 .help("A file to write error fetching data key IDs to"),
+        )
+        .arg(
+            Arg::with_name(ARG_TOP_ERROR_CAUSES)
+                .long(ARG_TOP_ERROR_CAUSES)
+                .takes_value(true)
+                .required(false)
+                .help("How many error causes to report at the end of the scrub. Default 10."),
+        )
+        .arg(
+            Arg::with_name(ARG_SUMMARY_OUTPUT)
+                .long(ARG_SUMMARY_OUTPUT)
+                .takes_value(true)
+                .required(false)
+                .help("A file to write a JSON summary of the scrub to, as well as printing it"),
         );
 
     let matches = app.get_matches();
@@ -104,6 +129,8 @@ fn main(fb: fbinit::FacebookInit) -> Result<()> {
     let config_store = args::init_config_store(fb, &logger, &matches)?;
 
     let scheduled_max = args::get_usize_opt(&matches, ARG_SCHEDULED_MAX).unwrap_or(100) as usize;
+    let top_error_causes = args::get_usize_opt(&matches, ARG_TOP_ERROR_CAUSES).unwrap_or(10);
+    let summary_file_name = matches.value_of_os(ARG_SUMMARY_OUTPUT);
 
     let storage_config = args::load_storage_configs(config_store, &matches)
         .context("Could not read storage configs")?
@@ -142,24 +169,20 @@ fn main(fb: fbinit::FacebookInit) -> Result<()> {
         .await?;
 
         let stdin = BufReader::new(stdin());
-        let mut output_handles = FuturesUnordered::new();
-        let success = {
+        let (success, success_handle) = {
             let (send, recv) = mpsc::channel(100);
             let file = File::create(success_file_name).await?;
-            output_handles.push(tokio::spawn(bridge_to_file(file, recv)));
-            send
+            (send, tokio::spawn(bridge_to_file(file, recv)))
         };
-        let missing = {
+        let (missing, missing_handle) = {
             let (send, recv) = mpsc::channel(100);
             let file = File::create(missing_keys_file_name).await?;
-            output_handles.push(tokio::spawn(bridge_to_file(file, recv)));
-            send
+            (send, tokio::spawn(bridge_to_file(file, recv)))
         };
-        let error = {
+        let (error, error_handle) = {
             let (send, recv) = mpsc::channel(100);
             let file = File::create(errors_file_name).await?;
-            output_handles.push(tokio::spawn(handle_errors(file, recv)));
-            send
+            (send, tokio::spawn(handle_errors(file, recv)))
         };
         let res = scrub(
             &blobstore,
@@ -173,8 +196,18 @@ fn main(fb: fbinit::FacebookInit) -> Result<()> {
         .await
         .context("Scrub failed");
 
-        while let Some(task_result) = output_handles.try_next().await? {
-            task_result.context("Writing output files failed")?;
+        let (success, missing, summary) =
+            futures::try_join!(success_handle, missing_handle, error_handle)?;
+        let mut summary = summary.context("Writing output files failed")?;
+        summary.success = success.context("Writing output files failed")?;
+        summary.missing = missing.context("Writing output files failed")?;
+
+        summary.print(top_error_causes);
+        if let Some(summary_file_name) = summary_file_name {
+            let json = serde_json::to_string_pretty(&summary.to_json(top_error_causes))?;
+            let mut file = File::create(summary_file_name).await?;
+            file.write_all(json.as_bytes()).await?;
+            file.flush().await?;
         }
         res
     };
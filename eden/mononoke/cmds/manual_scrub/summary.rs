@@ -0,0 +1,154 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashMap;
+
+use anyhow::Error;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::{json, Value};
+
+// How many keys to keep per cause, as examples to start investigating from.
+const SAMPLE_KEYS_PER_CAUSE: usize = 5;
+
+lazy_static! {
+    // Hashes, ids, counts, durations, addresses...: anything with a digit in it is specific to
+    // one failure, and would stop identical failures from being grouped together.
+    static ref VARYING_WORD: Regex = Regex::new(r"[[:alnum:]_.:-]*[0-9][[:alnum:]_.:-]*").unwrap();
+}
+
+/// The cause of an error, with the parts that vary from one key to the other (including the
+/// key itself) replaced, so that errors with the same cause can be counted together.
+pub fn normalize_error(key: &str, err: &Error) -> String {
+    let message = format!("{:#}", err).replace(key, "<key>");
+    VARYING_WORD.replace_all(&message, "<_>").into_owned()
+}
+
+struct Cause {
+    count: u64,
+    sample_keys: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct ScrubSummary {
+    pub success: u64,
+    pub missing: u64,
+    errors: u64,
+    causes: HashMap<String, Cause>,
+}
+
+impl ScrubSummary {
+    pub fn add_error(&mut self, key: &str, err: &Error) {
+        self.errors += 1;
+        let cause = self
+            .causes
+            .entry(normalize_error(key, err))
+            .or_insert_with(|| Cause {
+                count: 0,
+                sample_keys: Vec::new(),
+            });
+        cause.count += 1;
+        if cause.sample_keys.len() < SAMPLE_KEYS_PER_CAUSE {
+            cause.sample_keys.push(key.to_string());
+        }
+    }
+
+    /// The causes with the most errors first.
+    fn top_causes(&self, limit: usize) -> Vec<(&str, &Cause)> {
+        let mut causes: Vec<_> = self
+            .causes
+            .iter()
+            .map(|(cause, stats)| (cause.as_str(), stats))
+            .collect();
+        causes.sort_by(|(a_cause, a), (b_cause, b)| {
+            b.count.cmp(&a.count).then_with(|| a_cause.cmp(b_cause))
+        });
+        causes.truncate(limit);
+        causes
+    }
+
+    pub fn print(&self, top_causes: usize) {
+        println!(
+            "Scrubbed {} keys: {} ok, {} missing, {} errors",
+            self.success + self.missing + self.errors,
+            self.success,
+            self.missing,
+            self.errors
+        );
+        if self.errors == 0 {
+            return;
+        }
+        println!(
+            "Top {} of {} error causes:",
+            top_causes.min(self.causes.len()),
+            self.causes.len()
+        );
+        for (cause, stats) in self.top_causes(top_causes) {
+            println!("  {} errors: {}", stats.count, cause);
+            println!("    e.g. {}", stats.sample_keys.join(", "));
+        }
+    }
+
+    pub fn to_json(&self, top_causes: usize) -> Value {
+        let causes: Vec<_> = self
+            .top_causes(top_causes)
+            .into_iter()
+            .map(|(cause, stats)| {
+                json!({
+                    "cause": cause,
+                    "count": stats.count,
+                    "sample_keys": stats.sample_keys,
+                })
+            })
+            .collect();
+        json!({
+            "success": self.success,
+            "missing": self.missing,
+            "errors": self.errors,
+            "distinct_error_causes": self.causes.len(),
+            "top_error_causes": causes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_summary() {
+        let mut summary = ScrubSummary::default();
+        let key1 = "repo0001.content.blake2.aaa111";
+        let key2 = "repo0001.content.blake2.bbb222";
+        summary.add_error(
+            key1,
+            &anyhow!("Failed to fetch {} from blobstore 3 after 1.5s", key1),
+        );
+        summary.add_error(
+            key2,
+            &anyhow!("Failed to fetch {} from blobstore 12 after 30ms", key2),
+        );
+        summary.add_error(key2, &anyhow!("Connection reset"));
+        summary.success = 10;
+
+        let causes = summary.top_causes(10);
+        assert_eq!(causes.len(), 2);
+        assert_eq!(
+            causes[0].0,
+            "Failed to fetch <key> from blobstore <_> after <_>"
+        );
+        assert_eq!(causes[0].1.count, 2);
+        assert_eq!(causes[0].1.sample_keys, vec![key1, key2]);
+        assert_eq!(causes[1].0, "Connection reset");
+
+        let json = summary.to_json(1);
+        assert_eq!(json["errors"], 3);
+        assert_eq!(json["distinct_error_causes"], 2);
+        assert_eq!(json["top_error_causes"].as_array().unwrap().len(), 1);
+    }
+}
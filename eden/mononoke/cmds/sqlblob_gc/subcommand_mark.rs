@@ -5,19 +5,19 @@
  * GNU General Public License version 2.
  */
 
-use std::{ops::Range, sync::Arc, time::Duration};
+use std::{num::NonZeroU32, ops::Range, sync::Arc, time::Duration};
 
-use anyhow::{anyhow, Result};
+use anyhow::{Context, Result};
 use clap::{App, Arg, ArgMatches, SubCommand};
+use context::CoreContext;
 use fbinit::FacebookInit;
 use futures::{
     channel::mpsc,
     sink::SinkExt,
     stream::{self, StreamExt, TryStreamExt},
 };
-use rand::{thread_rng, Rng};
+use retryblob::{retry, BackendType, RetryOptions};
 use slog::{info, Logger};
-use tokio::time::delay_for;
 
 use sqlblob::Sqlblob;
 
@@ -25,9 +25,18 @@ pub const MARK_SAFE: &str = "mark";
 const ARG_INITIAL_GENERATION_ONLY: &str = "initial-generation-only";
 const ARG_SKIP_INITIAL_GENERATION: &str = "skip-initial-generation";
 
-const MIN_RETRY_DELAY: Duration = Duration::from_millis(10);
 const MAX_RETRY_DELAY: Duration = Duration::from_millis(100);
-const RETRIES: usize = 3;
+const RETRIES: u32 = 3;
+
+fn retry_options() -> RetryOptions {
+    RetryOptions {
+        max_attempts: NonZeroU32::new(RETRIES).unwrap(),
+        base_delay: MAX_RETRY_DELAY,
+        max_delay: MAX_RETRY_DELAY,
+        // The whole mark runs as one context, so only the attempts per key limit the retries.
+        budget_per_context: i64::MAX,
+    }
+}
 
 pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name(MARK_SAFE)
@@ -48,59 +57,45 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
         )
 }
 
-async fn handle_one_key(key: String, store: Arc<Sqlblob>) -> Result<()> {
-    for _retry in 0..RETRIES {
-        let res = store.set_generation(&key).await;
-        if res.is_ok() {
-            return res;
-        }
-        let delay = thread_rng().gen_range(MIN_RETRY_DELAY, MAX_RETRY_DELAY);
-        eprintln!(
-            "Failure {:#?} on key {} - delaying for {:#?}",
-            res, &key, delay
-        );
-        delay_for(delay).await;
-    }
-    Err(anyhow!(
-        "Failed to handle {} after {} retries",
-        &key,
-        RETRIES
-    ))
+async fn handle_one_key(ctx: CoreContext, key: String, store: Arc<Sqlblob>) -> Result<()> {
+    retry(
+        &ctx,
+        BackendType::Sql,
+        &retry_options(),
+        "set_generation",
+        || store.set_generation(&key),
+    )
+    .await
+    .with_context(|| format!("Failed to handle {}", &key))
 }
 
-async fn handle_initial_generation(store: &Sqlblob, shard: usize) -> Result<()> {
-    for _retry in 0..RETRIES {
-        let res = store.set_initial_generation(shard).await;
-        if res.is_ok() {
-            return res;
-        }
-        let delay = thread_rng().gen_range(MIN_RETRY_DELAY, MAX_RETRY_DELAY);
-        eprintln!(
-            "Failure {:#?} on shard {} - delaying for {:#?}",
-            res, shard, delay
-        );
-        delay_for(delay).await;
-    }
-    Err(anyhow!(
-        "Failed to handle initial generation on shard {} after {} retries",
-        &shard,
-        RETRIES
-    ))
+async fn handle_initial_generation(ctx: &CoreContext, store: &Sqlblob, shard: usize) -> Result<()> {
+    retry(
+        ctx,
+        BackendType::Sql,
+        &retry_options(),
+        "set_initial_generation",
+        || store.set_initial_generation(shard),
+    )
+    .await
+    .with_context(|| format!("Failed to handle initial generation on shard {}", shard))
 }
 
 pub async fn subcommand_mark<'a>(
-    _fb: FacebookInit,
+    fb: FacebookInit,
     logger: Logger,
     sub_matches: &'a ArgMatches<'_>,
     max_parallelism: usize,
     sqlblob: Sqlblob,
     shard_range: Range<usize>,
 ) -> Result<()> {
+    let ctx = CoreContext::new_with_logger(fb, logger.clone());
+
     if !sub_matches.is_present(ARG_SKIP_INITIAL_GENERATION) {
         info!(logger, "Starting initial generation set");
         let set_initial_generation_futures: Vec<_> = shard_range
             .clone()
-            .map(|shard| Ok(handle_initial_generation(&sqlblob, shard)))
+            .map(|shard| Ok(handle_initial_generation(&ctx, &sqlblob, shard)))
             .collect();
         stream::iter(set_initial_generation_futures.into_iter())
             .try_for_each_concurrent(max_parallelism, |fut| fut)
@@ -118,13 +113,15 @@ pub async fn subcommand_mark<'a>(
     // Set up a task to process each key in parallel in its own task.
     let (key_channel, processor) = {
         let sqlblob = Arc::clone(&sqlblob);
+        let ctx = ctx.clone();
         let (tx, rx) = mpsc::channel(10);
         let task = tokio::spawn(async move {
             rx.map(Ok)
                 .try_for_each_concurrent(max_parallelism, {
                     |key| {
                         let sqlblob = sqlblob.clone();
-                        async move { tokio::spawn(handle_one_key(key, sqlblob)).await? }
+                        let ctx = ctx.clone();
+                        async move { tokio::spawn(handle_one_key(ctx, key, sqlblob)).await? }
                     }
                 })
                 .await
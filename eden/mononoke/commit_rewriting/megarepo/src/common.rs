@@ -16,6 +16,7 @@ use slog::info;
 use sorted_vector_map::SortedVectorMap;
 
 use crate::chunking::Chunker;
+use crate::tool_identity::{freeze_with_tool_identity, MegarepoOperation};
 
 #[derive(Clone, Debug)]
 pub struct ChangesetArgs {
@@ -32,23 +33,50 @@ pub struct StackPosition(pub usize);
 /// For creating stacks of changesets
 pub trait ChangesetArgsFactory = Fn(StackPosition) -> ChangesetArgs;
 
+/// A bookmark that is moved to the latest commit of a stack each time a commit of the stack is
+/// created, so that the progress of a long stack can be followed (and a stack that was
+/// interrupted resumed) with the usual bookmark APIs.
+#[derive(Clone, Debug)]
+pub struct CheckpointBookmark(pub BookmarkName);
+
+impl CheckpointBookmark {
+    pub async fn advance(
+        &self,
+        ctx: &CoreContext,
+        repo: &BlobRepo,
+        position: StackPosition,
+        bcs_id: ChangesetId,
+    ) -> Result<(), Error> {
+        info!(
+            ctx.logger(),
+            "Checkpointing stack position #{} at {:?}", position.0, bcs_id
+        );
+        create_bookmark(ctx, repo, self.0.clone(), bcs_id).await
+    }
+}
+
 pub async fn create_save_and_generate_hg_changeset(
     ctx: &CoreContext,
     repo: &BlobRepo,
     parents: Vec<ChangesetId>,
     file_changes: SortedVectorMap<MPath, Option<FileChange>>,
     changeset_args: ChangesetArgs,
+    operation: MegarepoOperation,
 ) -> Result<HgChangesetId, Error> {
-    let bcs_id = create_and_save_bonsai(ctx, repo, parents, file_changes, changeset_args).await?;
+    let bcs_id =
+        create_and_save_bonsai(ctx, repo, parents, file_changes, changeset_args, operation).await?;
     generate_hg_changeset(ctx, repo, bcs_id).await
 }
 
+/// Create a commit on behalf of the megarepo tooling. Its extra records that it was created by
+/// the tooling for `operation`, see `tool_identity`.
 pub async fn create_and_save_bonsai(
     ctx: &CoreContext,
     repo: &BlobRepo,
     parents: Vec<ChangesetId>,
     file_changes: SortedVectorMap<MPath, Option<FileChange>>,
     changeset_args: ChangesetArgs,
+    operation: MegarepoOperation,
 ) -> Result<ChangesetId, Error> {
     let ChangesetArgs {
         author,
@@ -57,7 +85,8 @@ pub async fn create_and_save_bonsai(
         bookmark: maybe_bookmark,
         mark_public,
     } = changeset_args;
-    let bcs = create_bonsai_changeset_only(parents, file_changes, author, message, datetime)?;
+    let bcs =
+        create_bonsai_changeset_only(parents, file_changes, author, message, datetime, operation)?;
     let bcs_id = save_and_maybe_mark_public(&ctx, &repo, bcs, mark_public).await?;
 
     if let Some(bookmark) = maybe_bookmark {
@@ -131,8 +160,9 @@ fn create_bonsai_changeset_only(
     author: String,
     message: String,
     datetime: DateTime,
+    operation: MegarepoOperation,
 ) -> Result<BonsaiChangeset, Error> {
-    BonsaiChangesetMut {
+    let bcs = BonsaiChangesetMut {
         parents,
         author: author.clone(),
         author_date: datetime,
@@ -141,8 +171,8 @@ fn create_bonsai_changeset_only(
         message,
         extra: Default::default(),
         file_changes,
-    }
-    .freeze()
+    };
+    freeze_with_tool_identity(bcs, operation)
 }
 
 pub async fn delete_files_in_chunks<'a>(
@@ -153,6 +183,7 @@ pub async fn delete_files_in_chunks<'a>(
     chunker: Chunker<MPath>,
     delete_commits_changeset_args_factory: impl ChangesetArgsFactory,
     skip_last_chunk: bool,
+    checkpoint: Option<&'a CheckpointBookmark>,
 ) -> Result<Vec<ChangesetId>, Error> {
     info!(ctx.logger(), "Chunking mpaths");
     let mpath_chunks: Vec<Vec<MPath>> = chunker(mpaths);
@@ -176,9 +207,21 @@ pub async fn delete_files_in_chunks<'a>(
             changeset_args,
             file_changes.len()
         );
-        let delete_cs_id =
-            create_and_save_bonsai(ctx, repo, vec![parent], file_changes, changeset_args).await?;
+        let delete_cs_id = create_and_save_bonsai(
+            ctx,
+            repo,
+            vec![parent],
+            file_changes,
+            changeset_args,
+            MegarepoOperation::Deletion,
+        )
+        .await?;
         info!(ctx.logger(), "Done creating delete commit #{}", i);
+        if let Some(checkpoint) = checkpoint {
+            checkpoint
+                .advance(ctx, repo, StackPosition(i), delete_cs_id)
+                .await?;
+        }
         delete_commits.push(delete_cs_id);
 
         // move one step forward
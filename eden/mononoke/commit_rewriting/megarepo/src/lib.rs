@@ -30,11 +30,15 @@ pub mod chunking;
 pub mod commit_sync_config_utils;
 pub mod common;
 pub mod pre_merge_delete;
+pub mod sync_check;
+pub mod tool_identity;
+pub mod trailers;
 pub mod working_copy;
 
 use crate::common::{
     create_save_and_generate_hg_changeset, ChangesetArgs, ChangesetArgsFactory, StackPosition,
 };
+use crate::tool_identity::MegarepoOperation;
 
 const BUFFER_SIZE: usize = 100;
 const REPORTING_INTERVAL_FILES: usize = 10000;
@@ -204,6 +208,7 @@ where
             vec![parent_bcs_id],
             file_changes.into(),
             resulting_changeset_args(StackPosition(idx)),
+            MegarepoOperation::Move,
         )
         .await?;
 
@@ -233,6 +238,8 @@ mod test {
     use std::sync::Arc;
     use tests_utils::resolve_cs_id;
 
+    use crate::tool_identity::{tool_provenance, ToolProvenance};
+
     fn identity_mover(p: &MPath) -> Result<Option<MPath>> {
         Ok(Some(p.clone()))
     }
@@ -324,6 +331,12 @@ mod test {
             .await
             .unwrap();
             let newcs = get_bonsai_by_hg_cs_id(ctx.clone(), repo.clone(), newcs).await;
+            match tool_provenance(&newcs).unwrap() {
+                ToolProvenance::Generated(identity) => {
+                    assert_eq!(identity.operation, MegarepoOperation::Move)
+                }
+                provenance => panic!("unexpected provenance {:?}", provenance),
+            }
 
             let BonsaiChangesetMut {
                 parents,
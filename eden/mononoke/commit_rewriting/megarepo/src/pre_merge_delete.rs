@@ -12,7 +12,7 @@ use mercurial_types::MPath;
 use mononoke_types::ChangesetId;
 
 use crate::chunking::Chunker;
-use crate::common::{delete_files_in_chunks, ChangesetArgsFactory};
+use crate::common::{delete_files_in_chunks, ChangesetArgsFactory, CheckpointBookmark};
 use crate::working_copy::{get_changed_working_copy_paths, get_working_copy_paths};
 
 /// A struct containing pre-merge delete information
@@ -47,6 +47,7 @@ pub async fn create_pre_merge_delete<'a>(
     chunker: Chunker<MPath>,
     delete_commits_changeset_args_factory: impl ChangesetArgsFactory,
     base_cs_id: Option<ChangesetId>,
+    checkpoint: Option<&'a CheckpointBookmark>,
 ) -> Result<PreMergeDelete, Error> {
     let mpaths = match base_cs_id {
         Some(base_cs_id) => {
@@ -62,6 +63,7 @@ pub async fn create_pre_merge_delete<'a>(
         chunker,
         delete_commits_changeset_args_factory,
         true, /* skip_last_chunk */
+        checkpoint,
     )
     .await?;
 
@@ -72,6 +74,7 @@ pub async fn create_pre_merge_delete<'a>(
 mod test {
     use super::*;
     use crate::common::{ChangesetArgs, StackPosition};
+    use bookmarks::BookmarkName;
     use cloned::cloned;
     use fbinit::FacebookInit;
     use fixtures::linear;
@@ -119,9 +122,16 @@ mod test {
             }
         });
 
-        let pmd =
-            create_pre_merge_delete(&ctx, &repo, bcs_id, chunker, create_delete_cs_args, None)
-                .await?;
+        let pmd = create_pre_merge_delete(
+            &ctx,
+            &repo,
+            bcs_id,
+            chunker,
+            create_delete_cs_args,
+            None,
+            None,
+        )
+        .await?;
 
         let PreMergeDelete { delete_commits } = pmd;
 
@@ -153,6 +163,45 @@ mod test {
         Ok(())
     }
 
+    #[fbinit::test]
+    async fn test_create_pre_merge_delete_checkpoint(fb: FacebookInit) -> Result<(), Error> {
+        let repo = linear::getrepo(fb).await;
+        let ctx = CoreContext::test_mock(fb);
+
+        let bcs_id = resolve_cs_id(&ctx, &repo, "master").await?;
+        let create_delete_cs_args = |num: StackPosition| ChangesetArgs {
+            author: "user".to_string(),
+            message: format!("Delete: {}", num.0),
+            datetime: DateTime::from_rfc3339("1985-04-12T23:20:50.52Z").unwrap(),
+            bookmark: None,
+            mark_public: false,
+        };
+
+        // Delete the files one by one, keeping the last one
+        let chunker: Chunker<MPath> =
+            Box::new(|mpaths| mpaths.into_iter().map(|mpath| vec![mpath]).collect());
+
+        let checkpoint = CheckpointBookmark(BookmarkName::new("checkpoint")?);
+        let PreMergeDelete { delete_commits } = create_pre_merge_delete(
+            &ctx,
+            &repo,
+            bcs_id,
+            chunker,
+            create_delete_cs_args,
+            None,
+            Some(&checkpoint),
+        )
+        .await?;
+
+        // The checkpoint is at the top of the stack
+        assert!(delete_commits.len() > 1);
+        assert_eq!(
+            resolve_cs_id(&ctx, &repo, "checkpoint").await?,
+            *delete_commits.last().unwrap()
+        );
+        Ok(())
+    }
+
     #[fbinit::test]
     async fn test_create_pre_merge_delete_with_base(fb: FacebookInit) -> Result<(), Error> {
         let repo = linear::getrepo(fb).await;
@@ -228,6 +277,7 @@ mod test {
             chunker,
             create_delete_cs_args,
             Some(commit_b),
+            None,
         )
         .await?;
 
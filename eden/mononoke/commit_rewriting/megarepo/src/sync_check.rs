@@ -0,0 +1,441 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Consistency checks for commit sync configs, meant to be run in CI so that a broken config is
+//! caught before the syncer picks it up. Problems are reported as structured errors rather than
+//! by failing on the first one, so that everything wrong with a config shows up in one run.
+
+use std::collections::HashMap;
+
+use anyhow::Error;
+use blobrepo::BlobRepo;
+use context::CoreContext;
+use itertools::Itertools;
+use metaconfig_types::{CommitSyncConfig, DefaultSmallToLargeCommitSyncPathAction};
+use mononoke_types::{MPath, RepositoryId};
+use movers::{get_large_to_small_mover, get_small_to_large_mover};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::working_copy::get_working_copy_paths;
+
+/// Stop reporting path errors for a bookmark after this many, as a broken mover would otherwise
+/// report every file in the repo.
+const MAX_PATH_ERRORS_PER_BOOKMARK: usize = 20;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Error)]
+#[serde(tag = "error", rename_all = "snake_case")]
+pub enum SyncCheckError {
+    #[error("Failed to load commit sync configs: {message}")]
+    ConfigLoadFailed { message: String },
+    #[error("{version}: version name is empty")]
+    EmptyVersionName { version: String },
+    #[error("{version}: config is stored under version {stored_as}")]
+    VersionNameMismatch { version: String, stored_as: String },
+    #[error("{version}: large repo {repo_id} is also a small repo")]
+    LargeRepoIsSmallRepo { version: String, repo_id: i32 },
+    #[error(
+        "{version}: bookmark prefix {first_prefix:?} of repo {first_repo_id} overlaps with \
+         {second_prefix:?} of repo {second_repo_id}"
+    )]
+    OverlappingBookmarkPrefixes {
+        version: String,
+        first_repo_id: i32,
+        first_prefix: String,
+        second_repo_id: i32,
+        second_prefix: String,
+    },
+    #[error(
+        "{version}: common pushrebase bookmark {bookmark} starts with the bookmark prefix \
+         {prefix:?} of repo {repo_id}"
+    )]
+    CommonBookmarkHasSmallRepoPrefix {
+        version: String,
+        bookmark: String,
+        repo_id: i32,
+        prefix: String,
+    },
+    #[error(
+        "{version}: {first_path} of repo {first_repo_id} overlaps with {second_path} of repo \
+         {second_repo_id} in the large repo"
+    )]
+    OverlappingLargeRepoPaths {
+        version: String,
+        first_repo_id: i32,
+        first_path: String,
+        second_repo_id: i32,
+        second_path: String,
+    },
+    #[error("{version}: failed to create movers for repo {repo_id}: {message}")]
+    InvalidMover {
+        version: String,
+        repo_id: i32,
+        message: String,
+    },
+    #[error(
+        "{version}: {path} of repo {repo_id} is moved to {large_path}, which is moved back to \
+         {roundtrip_path:?}"
+    )]
+    MoverRoundTripMismatch {
+        version: String,
+        repo_id: i32,
+        path: String,
+        large_path: String,
+        roundtrip_path: Option<String>,
+    },
+    #[error("{version}: bookmark {bookmark} does not exist in repo {repo_id}")]
+    MissingBookmark {
+        version: String,
+        repo_id: i32,
+        bookmark: String,
+    },
+    #[error("{version}: moving {path} of repo {repo_id} at {bookmark} failed: {message}")]
+    MoverFailed {
+        version: String,
+        repo_id: i32,
+        bookmark: String,
+        path: String,
+        message: String,
+    },
+    #[error(
+        "{version}: {first_path} and {second_path} of repo {repo_id} at {bookmark} are both \
+         moved to {large_path}"
+    )]
+    ConflictingPaths {
+        version: String,
+        repo_id: i32,
+        bookmark: String,
+        first_path: String,
+        second_path: String,
+        large_path: String,
+    },
+}
+
+// The paths of the large repo that a small repo syncs into, and whether each comes from the
+// default action rather than the map.
+fn large_repo_prefixes(config: &CommitSyncConfig, repo_id: RepositoryId) -> Vec<(MPath, bool)> {
+    let small_repo = &config.small_repos[&repo_id];
+    let mut prefixes: Vec<_> = small_repo
+        .map
+        .values()
+        .map(|path| (path.clone(), false))
+        .collect();
+    if let DefaultSmallToLargeCommitSyncPathAction::PrependPrefix(prefix) =
+        &small_repo.default_action
+    {
+        prefixes.push((prefix.clone(), true));
+    }
+    prefixes.sort();
+    prefixes
+}
+
+/// Check that `config` is internally consistent: prefixes don't collide, and movers can be
+/// created and round trip the paths they are configured with.
+pub fn check_commit_sync_config(config: &CommitSyncConfig) -> Vec<SyncCheckError> {
+    let version = config.version_name.0.clone();
+    let mut errors = vec![];
+
+    if version.is_empty() {
+        errors.push(SyncCheckError::EmptyVersionName {
+            version: version.clone(),
+        });
+    }
+
+    if config.small_repos.contains_key(&config.large_repo_id) {
+        errors.push(SyncCheckError::LargeRepoIsSmallRepo {
+            version: version.clone(),
+            repo_id: config.large_repo_id.id(),
+        });
+    }
+
+    let mut small_repo_ids: Vec<_> = config.small_repos.keys().copied().collect();
+    small_repo_ids.sort();
+
+    for (first, second) in small_repo_ids.iter().tuple_combinations::<(_, _)>() {
+        let first_prefix = config.small_repos[first].bookmark_prefix.as_str();
+        let second_prefix = config.small_repos[second].bookmark_prefix.as_str();
+        if first_prefix.starts_with(second_prefix) || second_prefix.starts_with(first_prefix) {
+            errors.push(SyncCheckError::OverlappingBookmarkPrefixes {
+                version: version.clone(),
+                first_repo_id: first.id(),
+                first_prefix: first_prefix.to_string(),
+                second_repo_id: second.id(),
+                second_prefix: second_prefix.to_string(),
+            });
+        }
+
+        for (first_path, first_is_default) in large_repo_prefixes(config, *first) {
+            for (second_path, second_is_default) in large_repo_prefixes(config, *second) {
+                // Several small repos can map the same directory to the same place on purpose,
+                // in which case changes to it are synced to all of them.
+                if first_path == second_path && !first_is_default && !second_is_default {
+                    continue;
+                }
+                if first_path.is_prefix_of(&second_path) || second_path.is_prefix_of(&first_path) {
+                    errors.push(SyncCheckError::OverlappingLargeRepoPaths {
+                        version: version.clone(),
+                        first_repo_id: first.id(),
+                        first_path: first_path.to_string(),
+                        second_repo_id: second.id(),
+                        second_path: second_path.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    for bookmark in &config.common_pushrebase_bookmarks {
+        for repo_id in &small_repo_ids {
+            let prefix = config.small_repos[repo_id].bookmark_prefix.as_str();
+            if !prefix.is_empty() && bookmark.as_str().starts_with(prefix) {
+                errors.push(SyncCheckError::CommonBookmarkHasSmallRepoPrefix {
+                    version: version.clone(),
+                    bookmark: bookmark.to_string(),
+                    repo_id: repo_id.id(),
+                    prefix: prefix.to_string(),
+                });
+            }
+        }
+    }
+
+    for repo_id in &small_repo_ids {
+        let invalid_mover = |e: Error| SyncCheckError::InvalidMover {
+            version: version.clone(),
+            repo_id: repo_id.id(),
+            message: format!("{:#}", e),
+        };
+        let movers = get_small_to_large_mover(config, *repo_id).and_then(|small_to_large| {
+            Ok((small_to_large, get_large_to_small_mover(config, *repo_id)?))
+        });
+        let (small_to_large, large_to_small) = match movers {
+            Ok(movers) => movers,
+            Err(e) => {
+                errors.push(invalid_mover(e));
+                continue;
+            }
+        };
+
+        let mut paths: Vec<_> = config.small_repos[repo_id].map.keys().collect();
+        paths.sort();
+        for path in paths {
+            let large_path = match small_to_large(path) {
+                Ok(Some(large_path)) => large_path,
+                Ok(None) => continue,
+                Err(e) => {
+                    errors.push(invalid_mover(e));
+                    continue;
+                }
+            };
+            let roundtrip_path = match large_to_small(&large_path) {
+                Ok(roundtrip_path) => roundtrip_path,
+                Err(e) => {
+                    errors.push(invalid_mover(e));
+                    continue;
+                }
+            };
+            if roundtrip_path.as_ref() != Some(path) {
+                errors.push(SyncCheckError::MoverRoundTripMismatch {
+                    version: version.clone(),
+                    repo_id: repo_id.id(),
+                    path: path.to_string(),
+                    large_path: large_path.to_string(),
+                    roundtrip_path: roundtrip_path.map(|p| p.to_string()),
+                });
+            }
+        }
+    }
+
+    errors
+}
+
+/// Check that `config` applies to the current heads of `small_repo` and `large_repo`: the common
+/// pushrebase bookmarks exist in both, and the small-to-large mover moves every file at the small
+/// repo heads to a distinct path.
+pub async fn check_commit_sync_config_at_heads(
+    ctx: &CoreContext,
+    config: &CommitSyncConfig,
+    small_repo: &BlobRepo,
+    large_repo: &BlobRepo,
+) -> Result<Vec<SyncCheckError>, Error> {
+    let version = config.version_name.0.clone();
+    let small_repo_id = small_repo.get_repoid();
+    let mut errors = vec![];
+
+    let small_to_large = match get_small_to_large_mover(config, small_repo_id) {
+        Ok(mover) => mover,
+        Err(e) => {
+            errors.push(SyncCheckError::InvalidMover {
+                version,
+                repo_id: small_repo_id.id(),
+                message: format!("{:#}", e),
+            });
+            return Ok(errors);
+        }
+    };
+
+    for bookmark in &config.common_pushrebase_bookmarks {
+        let missing_bookmark = |repo: &BlobRepo| SyncCheckError::MissingBookmark {
+            version: version.clone(),
+            repo_id: repo.get_repoid().id(),
+            bookmark: bookmark.to_string(),
+        };
+
+        if large_repo
+            .get_bonsai_bookmark(ctx.clone(), bookmark)
+            .await?
+            .is_none()
+        {
+            errors.push(missing_bookmark(large_repo));
+        }
+
+        let small_head = match small_repo
+            .get_bonsai_bookmark(ctx.clone(), bookmark)
+            .await?
+        {
+            Some(small_head) => small_head,
+            None => {
+                errors.push(missing_bookmark(small_repo));
+                continue;
+            }
+        };
+
+        let mut path_errors = vec![];
+        let mut moved: HashMap<MPath, MPath> = HashMap::new();
+        for path in get_working_copy_paths(ctx, small_repo, small_head).await? {
+            match small_to_large(&path) {
+                Ok(Some(large_path)) => {
+                    if let Some(first_path) = moved.insert(large_path.clone(), path.clone()) {
+                        path_errors.push(SyncCheckError::ConflictingPaths {
+                            version: version.clone(),
+                            repo_id: small_repo_id.id(),
+                            bookmark: bookmark.to_string(),
+                            first_path: first_path.to_string(),
+                            second_path: path.to_string(),
+                            large_path: large_path.to_string(),
+                        });
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => path_errors.push(SyncCheckError::MoverFailed {
+                    version: version.clone(),
+                    repo_id: small_repo_id.id(),
+                    bookmark: bookmark.to_string(),
+                    path: path.to_string(),
+                    message: format!("{:#}", e),
+                }),
+            }
+            if path_errors.len() >= MAX_PATH_ERRORS_PER_BOOKMARK {
+                break;
+            }
+        }
+        errors.extend(path_errors);
+    }
+
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ascii::AsciiString;
+    use bookmarks::BookmarkName;
+    use maplit::hashmap;
+    use metaconfig_types::{
+        CommitSyncConfigVersion, CommitSyncDirection, SmallRepoCommitSyncConfig,
+    };
+
+    fn small_repo_config(
+        prefix: &str,
+        bookmark_prefix: &str,
+        map: HashMap<MPath, MPath>,
+    ) -> SmallRepoCommitSyncConfig {
+        SmallRepoCommitSyncConfig {
+            default_action: DefaultSmallToLargeCommitSyncPathAction::PrependPrefix(
+                MPath::new(prefix).unwrap(),
+            ),
+            map,
+            bookmark_prefix: AsciiString::from_ascii(bookmark_prefix).unwrap(),
+            direction: CommitSyncDirection::SmallToLarge,
+        }
+    }
+
+    fn commit_sync_config(
+        small_repos: HashMap<RepositoryId, SmallRepoCommitSyncConfig>,
+    ) -> CommitSyncConfig {
+        CommitSyncConfig {
+            large_repo_id: RepositoryId::new(0),
+            common_pushrebase_bookmarks: vec![BookmarkName::new("master").unwrap()],
+            small_repos,
+            version_name: CommitSyncConfigVersion("TEST_VERSION".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let config = commit_sync_config(hashmap! {
+            RepositoryId::new(1) => small_repo_config("repo1", "repo1/", hashmap! {
+                MPath::new("special").unwrap() => MPath::new("special_repo1").unwrap(),
+            }),
+            RepositoryId::new(2) => small_repo_config("repo2", "repo2/", hashmap! {}),
+        });
+        assert_eq!(check_commit_sync_config(&config), vec![]);
+    }
+
+    #[test]
+    fn test_overlapping_config() {
+        let mut config = commit_sync_config(hashmap! {
+            RepositoryId::new(1) => small_repo_config("repos", "repo", hashmap! {}),
+            RepositoryId::new(2) => small_repo_config("repos/two", "repo2/", hashmap! {}),
+        });
+        config
+            .common_pushrebase_bookmarks
+            .push(BookmarkName::new("repo_release").unwrap());
+
+        assert_eq!(
+            check_commit_sync_config(&config),
+            vec![
+                SyncCheckError::OverlappingBookmarkPrefixes {
+                    version: "TEST_VERSION".to_string(),
+                    first_repo_id: 1,
+                    first_prefix: "repo".to_string(),
+                    second_repo_id: 2,
+                    second_prefix: "repo2/".to_string(),
+                },
+                SyncCheckError::OverlappingLargeRepoPaths {
+                    version: "TEST_VERSION".to_string(),
+                    first_repo_id: 1,
+                    first_path: "repos".to_string(),
+                    second_repo_id: 2,
+                    second_path: "repos/two".to_string(),
+                },
+                SyncCheckError::CommonBookmarkHasSmallRepoPrefix {
+                    version: "TEST_VERSION".to_string(),
+                    bookmark: "repo_release".to_string(),
+                    repo_id: 1,
+                    prefix: "repo".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mover_round_trip() {
+        // Two paths are moved to the same place, so only one of them can be moved back.
+        let config = commit_sync_config(hashmap! {
+            RepositoryId::new(1) => small_repo_config("repo1", "repo1/", hashmap! {
+                MPath::new("first").unwrap() => MPath::new("shared").unwrap(),
+                MPath::new("second").unwrap() => MPath::new("shared").unwrap(),
+            }),
+        });
+        let errors = check_commit_sync_config(&config);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            SyncCheckError::MoverRoundTripMismatch { large_path, .. } if large_path == "shared"
+        ));
+    }
+}
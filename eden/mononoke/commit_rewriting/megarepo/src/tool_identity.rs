@@ -0,0 +1,282 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Identity of the megarepo tooling, recorded in the extra of every commit it creates, so that
+//! audit and revert workflows can tell the commits created by tooling apart from the others.
+//! The identity includes the id the commit would have without it, so that a generated commit
+//! that was changed since (e.g. amended, or rewritten by cross-repo sync) is told apart. It is
+//! not a signature: anyone who can create commits can write the extra, so it is for audits to
+//! go by, not for anything to trust. Pushrebase rewrites the commits that it lands, so the
+//! commits that the tooling pushrebases have their identity recorded again after the rebase by
+//! `ToolIdentityPushrebaseHook`.
+
+use anyhow::{Context, Error};
+use async_trait::async_trait;
+use blobrepo::BlobRepo;
+use blobstore::Loadable;
+use bookmarks::BookmarkTransactionError;
+use context::CoreContext;
+use mononoke_types::{BonsaiChangeset, BonsaiChangesetMut, ChangesetId};
+use pushrebase::{
+    PushrebaseCommitHook, PushrebaseHook, PushrebaseTransactionHook, RebasedChangesets,
+};
+use serde::{Deserialize, Serialize};
+use sql::Transaction;
+
+pub const TOOL_IDENTITY_EXTRA: &str = "megarepo-tool-identity";
+const TOOL_NAME: &str = "megarepotool";
+
+/// What the tooling created a commit for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MegarepoOperation {
+    Move,
+    Merge,
+    Deletion,
+    CatchupDeletion,
+    GradualMerge,
+    DiamondMerge,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ToolIdentity {
+    pub tool: String,
+    pub tool_version: String,
+    pub operation: MegarepoOperation,
+    /// The id of the commit with this identity removed from its extra
+    pub unannotated_id: ChangesetId,
+}
+
+/// Whether a commit was created by the megarepo tooling, as per its extra.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ToolProvenance {
+    /// The commit has no tool identity.
+    NotGenerated,
+    /// The commit was created by the tooling, and is as the tooling created it.
+    Generated(ToolIdentity),
+    /// The commit has a tool identity that was recorded for another commit, i.e. it is a
+    /// generated commit that was changed since.
+    Modified(ToolIdentity),
+}
+
+impl ToolProvenance {
+    pub fn created_by_tooling(&self) -> bool {
+        match self {
+            Self::Generated(_) => true,
+            Self::NotGenerated | Self::Modified(_) => false,
+        }
+    }
+}
+
+/// Freeze `bcs`, recording in its extra that the tooling created it for `operation`.
+pub fn freeze_with_tool_identity(
+    mut bcs: BonsaiChangesetMut,
+    operation: MegarepoOperation,
+) -> Result<BonsaiChangeset, Error> {
+    record_tool_identity(&mut bcs, operation)?;
+    bcs.freeze()
+}
+
+fn record_tool_identity(
+    bcs: &mut BonsaiChangesetMut,
+    operation: MegarepoOperation,
+) -> Result<(), Error> {
+    bcs.extra.remove(TOOL_IDENTITY_EXTRA);
+    let identity = ToolIdentity {
+        tool: TOOL_NAME.to_string(),
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        operation,
+        unannotated_id: bcs.clone().freeze()?.get_changeset_id(),
+    };
+    bcs.extra.insert(
+        TOOL_IDENTITY_EXTRA.to_string(),
+        serde_json::to_vec(&identity)?,
+    );
+    Ok(())
+}
+
+fn parse_tool_identity(identity: &[u8]) -> Result<ToolIdentity, Error> {
+    serde_json::from_slice(identity)
+        .with_context(|| format!("invalid {} extra", TOOL_IDENTITY_EXTRA))
+}
+
+/// Records the tool identity of the commits that the tooling pushrebases again once they are
+/// rebased, as the rebase changes their parents, date and extra.
+pub struct ToolIdentityPushrebaseHook;
+
+impl ToolIdentityPushrebaseHook {
+    pub fn new() -> Box<dyn PushrebaseHook> {
+        Box::new(Self)
+    }
+}
+
+#[async_trait]
+impl PushrebaseHook for ToolIdentityPushrebaseHook {
+    async fn prepushrebase(&self) -> Result<Box<dyn PushrebaseCommitHook>, Error> {
+        Ok(Box::new(ToolIdentityCommitHook) as Box<dyn PushrebaseCommitHook>)
+    }
+}
+
+struct ToolIdentityCommitHook;
+
+#[async_trait]
+impl PushrebaseCommitHook for ToolIdentityCommitHook {
+    fn post_rebase_changeset(
+        &mut self,
+        _bcs_old: ChangesetId,
+        bcs_new: &mut BonsaiChangesetMut,
+    ) -> Result<(), Error> {
+        let identity = match bcs_new.extra.get(TOOL_IDENTITY_EXTRA) {
+            Some(identity) => parse_tool_identity(identity)?,
+            None => return Ok(()),
+        };
+        record_tool_identity(bcs_new, identity.operation)
+    }
+
+    async fn into_transaction_hook(
+        self: Box<Self>,
+        _ctx: &CoreContext,
+        _rebased: &RebasedChangesets,
+    ) -> Result<Box<dyn PushrebaseTransactionHook>, Error> {
+        Ok(Box::new(ToolIdentityCommitHook) as Box<dyn PushrebaseTransactionHook>)
+    }
+}
+
+#[async_trait]
+impl PushrebaseTransactionHook for ToolIdentityCommitHook {
+    async fn populate_transaction(
+        &self,
+        _ctx: &CoreContext,
+        txn: Transaction,
+    ) -> Result<Transaction, BookmarkTransactionError> {
+        Ok(txn)
+    }
+}
+
+/// Find out from the extra of `bcs` whether the tooling created it. Fails if the commit has a
+/// tool identity that can't be parsed.
+pub fn tool_provenance(bcs: &BonsaiChangeset) -> Result<ToolProvenance, Error> {
+    let mut bcs = bcs.clone().into_mut();
+    let identity = match bcs.extra.remove(TOOL_IDENTITY_EXTRA) {
+        Some(identity) => identity,
+        None => return Ok(ToolProvenance::NotGenerated),
+    };
+    let identity = parse_tool_identity(&identity)?;
+
+    if bcs.freeze()?.get_changeset_id() == identity.unannotated_id {
+        Ok(ToolProvenance::Generated(identity))
+    } else {
+        Ok(ToolProvenance::Modified(identity))
+    }
+}
+
+/// Whether the commit `cs_id` of `repo` was created by the megarepo tooling.
+pub async fn commit_tool_provenance(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    cs_id: ChangesetId,
+) -> Result<ToolProvenance, Error> {
+    let bcs = cs_id.load(ctx, repo.blobstore()).await?;
+    tool_provenance(&bcs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mononoke_types::DateTime;
+
+    fn bonsai(message: &str) -> BonsaiChangesetMut {
+        BonsaiChangesetMut {
+            parents: vec![ChangesetId::from_byte_array([1; 32])],
+            author: "megarepo".to_string(),
+            author_date: DateTime::from_timestamp(0, 0).unwrap(),
+            committer: None,
+            committer_date: None,
+            message: message.to_string(),
+            extra: Default::default(),
+            file_changes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_generated() -> Result<(), Error> {
+        let bcs = freeze_with_tool_identity(bonsai("merge"), MegarepoOperation::Merge)?;
+        match tool_provenance(&bcs)? {
+            ToolProvenance::Generated(identity) => {
+                assert_eq!(identity.operation, MegarepoOperation::Merge);
+                assert_eq!(identity.tool, TOOL_NAME);
+                assert_eq!(
+                    identity.unannotated_id,
+                    bonsai("merge").freeze()?.get_changeset_id()
+                );
+            }
+            provenance => panic!("unexpected provenance {:?}", provenance),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_not_generated() -> Result<(), Error> {
+        let bcs = bonsai("by hand").freeze()?;
+        assert_eq!(tool_provenance(&bcs)?, ToolProvenance::NotGenerated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_modified() -> Result<(), Error> {
+        let bcs = freeze_with_tool_identity(bonsai("merge"), MegarepoOperation::Merge)?;
+        let mut amended = bcs.into_mut();
+        amended.message = "amended merge".to_string();
+        let provenance = tool_provenance(&amended.freeze()?)?;
+        assert!(!provenance.created_by_tooling());
+        match provenance {
+            ToolProvenance::Modified(_) => {}
+            provenance => panic!("unexpected provenance {:?}", provenance),
+        }
+
+        let mut garbled = bonsai("merge");
+        garbled
+            .extra
+            .insert(TOOL_IDENTITY_EXTRA.to_string(), b"garbage".to_vec());
+        assert!(tool_provenance(&garbled.freeze()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rebased() -> Result<(), Error> {
+        let bcs = freeze_with_tool_identity(bonsai("merge"), MegarepoOperation::GradualMerge)?;
+        let old_id = bcs.get_changeset_id();
+        // What pushrebase does to a commit that it lands
+        let mut rebased = bcs.into_mut();
+        rebased.parents = vec![ChangesetId::from_byte_array([2; 32])];
+        rebased.author_date = DateTime::from_timestamp(100, 0).unwrap();
+        rebased
+            .extra
+            .insert("pushrebase".to_string(), b"extra".to_vec());
+        match tool_provenance(&rebased.clone().freeze()?)? {
+            ToolProvenance::Modified(_) => {}
+            provenance => panic!("unexpected provenance {:?}", provenance),
+        }
+
+        let mut hook = ToolIdentityCommitHook;
+        hook.post_rebase_changeset(old_id, &mut rebased)?;
+        match tool_provenance(&rebased.freeze()?)? {
+            ToolProvenance::Generated(identity) => {
+                assert_eq!(identity.operation, MegarepoOperation::GradualMerge);
+            }
+            provenance => panic!("unexpected provenance {:?}", provenance),
+        }
+
+        let mut by_hand = bonsai("by hand");
+        hook.post_rebase_changeset(old_id, &mut by_hand)?;
+        assert_eq!(
+            tool_provenance(&by_hand.freeze()?)?,
+            ToolProvenance::NotGenerated
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{format_err, Context, Error};
+use mononoke_types::ChangesetId;
+use std::str::FromStr;
+
+const CHUNK_TRAILER: &str = "Megarepo-Catchup-Chunk";
+const TOOL_VERSION_TRAILER: &str = "Megarepo-Catchup-Tool-Version";
+const SOURCE_COMMIT_TRAILER: &str = "Megarepo-Catchup-Source";
+
+/// Structured trailers recorded at the end of the message of each deletion commit
+/// generated by the catchup tool. They allow later tooling to recognize generated
+/// stacks and find out where a given commit sits in its stack.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CatchupDeletionTrailers {
+    /// Zero-based index of this commit's chunk in the stack
+    pub chunk_index: usize,
+    pub total_chunks: usize,
+    pub tool_version: String,
+    /// The commit that is being caught up with, i.e. the one whose files are not deleted
+    pub source_commit: ChangesetId,
+}
+
+impl CatchupDeletionTrailers {
+    /// Return `message` with the trailers appended as a separate paragraph
+    pub fn append_to_message(&self, message: &str) -> String {
+        format!(
+            "{}\n\n{}: {}/{}\n{}: {}\n{}: {}",
+            message.trim_end(),
+            CHUNK_TRAILER,
+            self.chunk_index,
+            self.total_chunks,
+            TOOL_VERSION_TRAILER,
+            self.tool_version,
+            SOURCE_COMMIT_TRAILER,
+            self.source_commit,
+        )
+    }
+
+    /// Parse the trailers out of a commit message. Returns `None` if the message has
+    /// no catchup trailers at all, and an error if only some of them are present or
+    /// they are malformed.
+    pub fn parse(message: &str) -> Result<Option<Self>, Error> {
+        let last_paragraph = match message.trim_end().rsplit("\n\n").next() {
+            Some(paragraph) => paragraph,
+            None => return Ok(None),
+        };
+
+        let mut chunk = None;
+        let mut tool_version = None;
+        let mut source_commit = None;
+        for line in last_paragraph.lines() {
+            let mut kv = line.splitn(2, ": ");
+            let (key, value) = match (kv.next(), kv.next()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => continue,
+            };
+            match key {
+                CHUNK_TRAILER => chunk = Some(value),
+                TOOL_VERSION_TRAILER => tool_version = Some(value),
+                SOURCE_COMMIT_TRAILER => source_commit = Some(value),
+                _ => {}
+            }
+        }
+
+        if chunk.is_none() && tool_version.is_none() && source_commit.is_none() {
+            return Ok(None);
+        }
+
+        let missing = |name| format_err!("{} trailer is missing", name);
+
+        let chunk = chunk.ok_or_else(|| missing(CHUNK_TRAILER))?;
+        let mut parts = chunk.splitn(2, '/');
+        let (chunk_index, total_chunks) = match (parts.next(), parts.next()) {
+            (Some(chunk_index), Some(total_chunks)) => (chunk_index, total_chunks),
+            _ => return Err(format_err!("invalid {} trailer: {}", CHUNK_TRAILER, chunk)),
+        };
+        let chunk_index = chunk_index
+            .parse()
+            .with_context(|| format!("invalid {} trailer: {}", CHUNK_TRAILER, chunk))?;
+        let total_chunks = total_chunks
+            .parse()
+            .with_context(|| format!("invalid {} trailer: {}", CHUNK_TRAILER, chunk))?;
+
+        let tool_version = tool_version
+            .ok_or_else(|| missing(TOOL_VERSION_TRAILER))?
+            .to_string();
+
+        let source_commit = source_commit.ok_or_else(|| missing(SOURCE_COMMIT_TRAILER))?;
+        let source_commit = ChangesetId::from_str(source_commit).with_context(|| {
+            format!(
+                "invalid {} trailer: {}",
+                SOURCE_COMMIT_TRAILER, source_commit
+            )
+        })?;
+
+        Ok(Some(Self {
+            chunk_index,
+            total_chunks,
+            tool_version,
+            source_commit,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn trailers() -> CatchupDeletionTrailers {
+        CatchupDeletionTrailers {
+            chunk_index: 2,
+            total_chunks: 5,
+            tool_version: "1".to_string(),
+            source_commit: ChangesetId::from_byte_array([1; 32]),
+        }
+    }
+
+    #[test]
+    fn test_trailers_round_trip() -> Result<(), Error> {
+        let message = trailers().append_to_message("[MEGAREPO CATCHUP DELETE] msg (2)\n");
+        assert!(message.starts_with("[MEGAREPO CATCHUP DELETE] msg (2)\n\n"));
+        assert_eq!(CatchupDeletionTrailers::parse(&message)?, Some(trailers()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_trailers_absent() -> Result<(), Error> {
+        assert_eq!(CatchupDeletionTrailers::parse("")?, None);
+        assert_eq!(
+            CatchupDeletionTrailers::parse("some commit\n\nSummary: stuff")?,
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_trailers_malformed() {
+        let message = trailers().append_to_message("msg");
+        let truncated = message.replace("Megarepo-Catchup-Source", "Other");
+        assert!(CatchupDeletionTrailers::parse(&truncated).is_err());
+
+        let bad_chunk = message.replace("2/5", "2");
+        assert!(CatchupDeletionTrailers::parse(&bad_chunk).is_err());
+    }
+}
@@ -0,0 +1,308 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Report of what merging a commit into a bookmark would change in its working copy, broken
+//! down by top-level directory. Nothing is written to the repo, so this can be run while a merge
+//! is still being reviewed.
+
+use anyhow::{anyhow, Error};
+use blobrepo::BlobRepo;
+use context::CoreContext;
+use derived_data::BonsaiDerived;
+use fsnodes::RootFsnodeId;
+use futures::{future::try_join, TryStreamExt};
+use manifest::{Diff, Entry, ManifestOps};
+use mononoke_types::{ChangesetId, MPath, MPathElement};
+use regex::Regex;
+use std::collections::{BTreeMap, HashSet};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChangeCounts {
+    pub added: u64,
+    pub deleted: u64,
+    pub changed: u64,
+}
+
+impl ChangeCounts {
+    fn add(&mut self, other: &ChangeCounts) {
+        self.added += other.added;
+        self.deleted += other.deleted;
+        self.changed += other.changed;
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlastRadius {
+    pub files: ChangeCounts,
+    pub directories: ChangeCounts,
+}
+
+/// Blast radius of each top-level directory. Files at the root of the repo are under `None`.
+pub type BlastRadiusByDirectory = BTreeMap<Option<MPathElement>, BlastRadius>;
+
+/// What merging `commit_to_merge` into `head` changes in the working copy of `head`. The merge
+/// keeps everything in `head`, so what only exists in `commit_to_merge` is added, what exists in
+/// both but differs is changed, and what only exists in `head` stays as it is.
+///
+/// If `path_regex` is set, this is the merge of a catchup instead: only the paths that match it
+/// are counted, and the files of `head` that match it but aren't in `commit_to_merge` are
+/// deleted before the merge, as `catchup::create_deletion_head_commits` does. A directory of
+/// `head` is then deleted if all its files are.
+///
+/// This only reads the repo: it fails if fsnodes aren't derived for both commits, rather than
+/// deriving them.
+pub async fn compute_merge_blast_radius(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    head: ChangesetId,
+    commit_to_merge: ChangesetId,
+    path_regex: Option<&Regex>,
+) -> Result<BlastRadiusByDirectory, Error> {
+    // Fsnodes, unlike unodes, only depend on the contents of the files, and not on their history.
+    let (head_root, commit_to_merge_root) = try_join(
+        fetch_root_fsnode(ctx, repo, head),
+        fetch_root_fsnode(ctx, repo, commit_to_merge),
+    )
+    .await?;
+
+    let diffs = head_root
+        .fsnode_id()
+        .diff(
+            ctx.clone(),
+            repo.get_blobstore(),
+            *commit_to_merge_root.fsnode_id(),
+        )
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let matches = |path: &MPath| path_regex.map_or(true, |re| path.matches_regex(re));
+
+    // What happens to each file, and which directories have files that are added, deleted or
+    // changed, or that stay.
+    let mut file_changes = Vec::new();
+    let mut affected_dirs = HashSet::new();
+    let mut kept_dirs = HashSet::new();
+    for diff in &diffs {
+        let (path, change) = match diff {
+            Diff::Added(Some(path), Entry::Leaf(_)) if matches(path) => (path, Change::Added),
+            Diff::Removed(Some(path), Entry::Leaf(_)) => {
+                if path_regex.is_some() && matches(path) {
+                    (path, Change::Deleted)
+                } else {
+                    kept_dirs.extend(parent_dirs(path));
+                    continue;
+                }
+            }
+            Diff::Changed(Some(path), _, Entry::Leaf(_)) if matches(path) => {
+                (path, Change::Changed)
+            }
+            _ => continue,
+        };
+        affected_dirs.extend(parent_dirs(path));
+        file_changes.push((path, change));
+    }
+
+    let mut dir_changes = Vec::new();
+    for diff in &diffs {
+        let (path, change) = match diff {
+            // The root directory itself is always there.
+            Diff::Added(Some(path), Entry::Tree(_)) => (path, Change::Added),
+            Diff::Removed(Some(path), Entry::Tree(_)) if kept_dirs.contains(path) => {
+                (path, Change::Changed)
+            }
+            Diff::Removed(Some(path), Entry::Tree(_)) => (path, Change::Deleted),
+            Diff::Changed(Some(path), _, Entry::Tree(_)) => (path, Change::Changed),
+            _ => continue,
+        };
+        if affected_dirs.contains(path) {
+            dir_changes.push((path, change));
+        }
+    }
+
+    let mut by_directory = BlastRadiusByDirectory::new();
+    for (changes, is_tree) in vec![(file_changes, false), (dir_changes, true)] {
+        for (path, change) in changes {
+            let radius = by_directory
+                .entry(top_level_directory(path, is_tree)?)
+                .or_default();
+            let counts = if is_tree {
+                &mut radius.directories
+            } else {
+                &mut radius.files
+            };
+            match change {
+                Change::Added => counts.added += 1,
+                Change::Deleted => counts.deleted += 1,
+                Change::Changed => counts.changed += 1,
+            }
+        }
+    }
+
+    Ok(by_directory)
+}
+
+enum Change {
+    Added,
+    Deleted,
+    Changed,
+}
+
+async fn fetch_root_fsnode(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    cs_id: ChangesetId,
+) -> Result<RootFsnodeId, Error> {
+    RootFsnodeId::fetch_derived(ctx, repo, &cs_id)
+        .await?
+        .ok_or_else(|| anyhow!("fsnodes are not derived for {}, derive them first", cs_id))
+}
+
+/// The directories that `path` is in, up to the top-level one.
+fn parent_dirs(path: &MPath) -> impl Iterator<Item = MPath> {
+    path.split_dirname()
+        .0
+        .into_iter()
+        .flat_map(MPath::into_parent_dir_iter)
+}
+
+fn top_level_directory(path: &MPath, is_tree: bool) -> Result<Option<MPathElement>, Error> {
+    if path.num_components() == 1 && !is_tree {
+        return Ok(None);
+    }
+    let element = path
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("path {} is empty", path))?;
+    Ok(Some(element.clone()))
+}
+
+pub fn print_blast_radius(by_directory: &BlastRadiusByDirectory) {
+    let mut total = BlastRadius::default();
+    for (directory, radius) in by_directory {
+        let name = match directory {
+            Some(directory) => String::from_utf8_lossy(directory.as_ref()).into_owned(),
+            None => "(root)".to_string(),
+        };
+        print_line(&name, radius);
+        total.files.add(&radius.files);
+        total.directories.add(&radius.directories);
+    }
+    print_line("total", &total);
+}
+
+fn print_line(name: &str, radius: &BlastRadius) {
+    println!(
+        "{}\tfiles: {} added, {} deleted, {} changed\tdirectories: {} added, {} deleted, {} changed",
+        name,
+        radius.files.added,
+        radius.files.deleted,
+        radius.files.changed,
+        radius.directories.added,
+        radius.directories.deleted,
+        radius.directories.changed,
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fbinit::FacebookInit;
+    use maplit::btreemap;
+    use tests_utils::CreateCommitContext;
+
+    #[fbinit::test]
+    async fn test_compute_merge_blast_radius(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = blobrepo_factory::new_memblob_empty(None)?;
+
+        let root_commit = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("unchanged/a", "a")
+            .add_file("file", "a")
+            .commit()
+            .await?;
+
+        let head = CreateCommitContext::new(&ctx, &repo, vec![root_commit])
+            .add_file("changed/a", "oldcontent")
+            .add_file("toremove/dir/file", "content")
+            .add_file("toremove/kept", "content")
+            .commit()
+            .await?;
+
+        // A file that is reverted to its old contents isn't changed.
+        let commit_to_merge = CreateCommitContext::new(&ctx, &repo, vec![root_commit])
+            .add_file("file", "b")
+            .commit()
+            .await?;
+        let commit_to_merge = CreateCommitContext::new(&ctx, &repo, vec![commit_to_merge])
+            .add_file("file", "a")
+            .add_file("changed/a", "newcontent")
+            .add_file("added/file", "content")
+            .commit()
+            .await?;
+
+        // Nothing is derived to compute the blast radius.
+        assert!(
+            compute_merge_blast_radius(&ctx, &repo, head, commit_to_merge, None)
+                .await
+                .is_err()
+        );
+        RootFsnodeId::derive(&ctx, &repo, head).await?;
+        RootFsnodeId::derive(&ctx, &repo, commit_to_merge).await?;
+
+        // What is only in head stays.
+        let by_directory =
+            compute_merge_blast_radius(&ctx, &repo, head, commit_to_merge, None).await?;
+        assert_eq!(
+            by_directory,
+            btreemap! {
+                Some(MPathElement::new(b"added".to_vec())?) => BlastRadius {
+                    files: ChangeCounts { added: 1, deleted: 0, changed: 0 },
+                    directories: ChangeCounts { added: 1, deleted: 0, changed: 0 },
+                },
+                Some(MPathElement::new(b"changed".to_vec())?) => BlastRadius {
+                    files: ChangeCounts { added: 0, deleted: 0, changed: 1 },
+                    directories: ChangeCounts { added: 0, deleted: 0, changed: 1 },
+                },
+            }
+        );
+
+        let by_directory = compute_merge_blast_radius(
+            &ctx,
+            &repo,
+            head,
+            commit_to_merge,
+            Some(&Regex::new("^changed/.*")?),
+        )
+        .await?;
+        assert_eq!(
+            by_directory.keys().cloned().collect::<Vec<_>>(),
+            vec![Some(MPathElement::new(b"changed".to_vec())?)]
+        );
+
+        // A catchup deletes what matches the regex in head but isn't in the commit to merge, and
+        // the directories that it leaves empty.
+        let by_directory = compute_merge_blast_radius(
+            &ctx,
+            &repo,
+            head,
+            commit_to_merge,
+            Some(&Regex::new("^toremove/dir/.*")?),
+        )
+        .await?;
+        assert_eq!(
+            by_directory,
+            btreemap! {
+                Some(MPathElement::new(b"toremove".to_vec())?) => BlastRadius {
+                    files: ChangeCounts { added: 0, deleted: 1, changed: 0 },
+                    directories: ChangeCounts { added: 0, deleted: 1, changed: 1 },
+                },
+            }
+        );
+
+        Ok(())
+    }
+}
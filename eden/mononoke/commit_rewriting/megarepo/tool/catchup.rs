@@ -5,30 +5,62 @@
  * GNU General Public License version 2.
  */
 
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, format_err, Error};
 use blobrepo::BlobRepo;
 use blobrepo_hg::BlobRepoHg;
 use blobstore::Loadable;
-use bookmarks::BookmarkName;
+use bookmarks::{BookmarkName, Freshness};
+use cmdlib_progress::{Progress, ProgressOptions};
 use context::CoreContext;
 use derived_data::BonsaiDerived;
 use futures::{
+    compat::Stream01CompatExt,
     future::{self, try_join},
-    TryStreamExt,
+    stream, StreamExt, TryStreamExt,
 };
 use itertools::Itertools;
 use manifest::{Diff, ManifestOps};
-use maplit::hashset;
-use megarepolib::common::{create_and_save_bonsai, ChangesetArgsFactory, StackPosition};
+use maplit::{btreeset, hashset};
+use megarepolib::{
+    common::{create_and_save_bonsai, ChangesetArgsFactory, CheckpointBookmark, StackPosition},
+    tool_identity::{MegarepoOperation, ToolIdentityPushrebaseHook},
+    trailers::CatchupDeletionTrailers,
+};
 use metaconfig_types::PushrebaseFlags;
-use mononoke_types::{ChangesetId, MPath};
+use mononoke_types::{ChangesetId, DateTime, FileUnodeId, MPath, Timestamp};
 use pushrebase::do_pushrebase_bonsai;
 use regex::Regex;
+use repo_read_write_status::RepoReadWriteFetcher;
+use revset::DifferenceOfUnionsOfAncestorsNodeStream;
+use skiplist::SkiplistIndex;
 use slog::{error, info};
+use sorted_vector_map::SortedVectorMap;
+use std::collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::delay_for;
 use unodes::RootUnodeManifestId;
 
+// Recorded in the trailers of generated deletion commits. Bump it whenever the way
+// deletion stacks are generated changes, so that tooling can tell the stacks apart.
+const CATCHUP_TOOL_VERSION: &str = "1";
+
+const LOAD_LINKNODES_CONCURRENCY: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum CatchupCounter {
+    DeletedFiles,
+}
+
+impl fmt::Display for CatchupCounter {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CatchupCounter::DeletedFiles => write!(fmt, "deleted files"),
+        }
+    }
+}
+
 pub async fn create_deletion_head_commits<'a>(
     ctx: &'a CoreContext,
     repo: &'a BlobRepo,
@@ -38,32 +70,68 @@ pub async fn create_deletion_head_commits<'a>(
     deletion_chunk_size: usize,
     cs_args_factory: Box<dyn ChangesetArgsFactory>,
     pushrebase_flags: &'a PushrebaseFlags,
+    readonly_fetcher: &'a RepoReadWriteFetcher,
     wait_secs: u64,
+    skip_modified_after: Option<DateTime>,
+    checkpoint: Option<&'a CheckpointBookmark>,
 ) -> Result<(), Error> {
-    let files =
-        find_files_that_need_to_be_deleted(ctx, repo, &head_bookmark, commit_to_merge, path_regex)
-            .await?;
+    let files = find_files_that_need_to_be_deleted(
+        ctx,
+        repo,
+        &head_bookmark,
+        commit_to_merge,
+        path_regex,
+        skip_modified_after,
+    )
+    .await?;
 
     info!(ctx.logger(), "total files to delete is {}", files.len());
+
+    // Pushrebase would only find these once the deletion commits are all done, and the merge is
+    // being landed.
+    let head_bookmark_val = repo
+        .get_bonsai_bookmark(ctx.clone(), &head_bookmark)
+        .await?
+        .ok_or(anyhow!("{} not found", head_bookmark))?;
+    check_case_collisions(ctx, repo, head_bookmark_val, commit_to_merge, &files).await?;
+
+    let total_chunks = (files.len() + deletion_chunk_size - 1) / deletion_chunk_size;
+    let progress = Progress::new(
+        "catchup deletion commits",
+        Some(total_chunks as u64),
+        ProgressOptions::default(),
+    )
+    .with_logger(ctx.logger().clone());
     for (num, chunk) in files
         .into_iter()
         .chunks(deletion_chunk_size)
         .into_iter()
         .enumerate()
     {
-        let files = chunk.into_iter().map(|path| (path, None)).collect();
+        let files: SortedVectorMap<_, _> = chunk.into_iter().map(|path| (path, None)).collect();
+        let deleted_files = files.len() as u64;
         let maybe_head_bookmark_val = repo
             .get_bonsai_bookmark(ctx.clone(), &head_bookmark)
             .await?;
         let head_bookmark_val =
             maybe_head_bookmark_val.ok_or(anyhow!("{} not found", head_bookmark))?;
 
+        let mut cs_args = cs_args_factory(StackPosition(num));
+        let trailers = CatchupDeletionTrailers {
+            chunk_index: num,
+            total_chunks,
+            tool_version: CATCHUP_TOOL_VERSION.to_string(),
+            source_commit: commit_to_merge,
+        };
+        cs_args.message = trailers.append_to_message(&cs_args.message);
+
         let bcs_id = create_and_save_bonsai(
             &ctx,
             &repo,
             vec![head_bookmark_val],
             files,
-            cs_args_factory(StackPosition(num)),
+            cs_args,
+            MegarepoOperation::CatchupDeletion,
         )
         .await?;
         info!(
@@ -76,6 +144,8 @@ pub async fn create_deletion_head_commits<'a>(
 
         info!(ctx.logger(), "derived {}, pushrebasing...", hg_cs_id);
 
+        // The repo may have been locked since the previous deletion commit.
+        readonly_fetcher.check_writable().await?;
         let bcs = bcs_id.load(&ctx, repo.blobstore()).await?;
         let pushrebase_res = do_pushrebase_bonsai(
             &ctx,
@@ -84,10 +154,18 @@ pub async fn create_deletion_head_commits<'a>(
             &head_bookmark,
             &hashset![bcs],
             None,
-            &[],
+            &[ToolIdentityPushrebaseHook::new()],
         )
         .await?;
         info!(ctx.logger(), "Pushrebased to {}", pushrebase_res.head);
+        if let Some(checkpoint) = checkpoint {
+            checkpoint
+                .advance(&ctx, &repo, StackPosition(num), pushrebase_res.head)
+                .await?;
+        }
+        progress.record(1);
+        progress.increment(CatchupCounter::DeletedFiles, deleted_files);
+        progress.report();
         if wait_secs > 0 {
             info!(ctx.logger(), "waiting for {} seconds", wait_secs);
             delay_for(Duration::from_secs(wait_secs)).await;
@@ -160,15 +238,145 @@ pub async fn validate(
     Ok(())
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum PathOrigin {
+    /// In head, and not deleted by the catchup
+    Head,
+    /// In head, and deleted by the catchup
+    Deleted,
+    CommitToMerge,
+}
+
+impl fmt::Display for PathOrigin {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathOrigin::Head => write!(fmt, "head"),
+            PathOrigin::Deleted => write!(fmt, "deleted from head"),
+            PathOrigin::CommitToMerge => write!(fmt, "commit to merge"),
+        }
+    }
+}
+
+/// Paths, or directories of paths, that are the same if case is ignored, with where they are.
+type CaseCollision = BTreeSet<(MPath, PathOrigin)>;
+
+fn find_case_collisions(
+    paths: impl IntoIterator<Item = (MPath, PathOrigin)>,
+) -> Result<Vec<CaseCollision>, Error> {
+    let mut first_seen: HashMap<String, (MPath, PathOrigin)> = HashMap::new();
+    let mut collisions: BTreeMap<String, CaseCollision> = BTreeMap::new();
+    for (path, origin) in paths {
+        for depth in 1..=path.num_components() {
+            let prefix = path
+                .take_prefix_components(depth)?
+                .ok_or_else(|| anyhow!("path {} has no component {}", path, depth))?;
+            let lowercase = String::from_utf8_lossy(&prefix.to_vec()).to_lowercase();
+            match first_seen.entry(lowercase) {
+                Entry::Vacant(entry) => {
+                    entry.insert((prefix, origin));
+                }
+                Entry::Occupied(entry) => {
+                    if entry.get().0 != prefix {
+                        collisions
+                            .entry(entry.key().clone())
+                            .or_insert_with(|| btreeset! { entry.get().clone() })
+                            .insert((prefix, origin));
+                    }
+                }
+            }
+        }
+    }
+
+    // Once two directories collide, so does everything in them that has the same name. Only the
+    // directories are reported.
+    let mut reported: Vec<String> = Vec::new();
+    let mut res = Vec::new();
+    for (lowercase, collision) in collisions {
+        if reported
+            .iter()
+            .any(|dir| lowercase.starts_with(&format!("{}/", dir)))
+        {
+            continue;
+        }
+        reported.push(lowercase);
+        res.push(collision);
+    }
+    Ok(res)
+}
+
+// Fails if the files that are left in head once the deletion commits are landed would collide
+// with the files of `commit_to_merge` on a case-insensitive filesystem, or if the files that are
+// deleted collide with each other. All the collisions are reported at once.
+async fn check_case_collisions(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    head: ChangesetId,
+    commit_to_merge: ChangesetId,
+    files_to_delete: &[MPath],
+) -> Result<(), Error> {
+    let (head_root_unode, commit_to_merge_root_unode) = try_join(
+        RootUnodeManifestId::derive(ctx, repo, head),
+        RootUnodeManifestId::derive(ctx, repo, commit_to_merge),
+    )
+    .await?;
+    let list_paths = |root: &RootUnodeManifestId| {
+        root.manifest_unode_id()
+            .list_leaf_entries(ctx.clone(), repo.get_blobstore())
+            .map_ok(|(path, _)| path)
+            .try_collect::<Vec<_>>()
+    };
+    let (head_paths, commit_to_merge_paths) = try_join(
+        list_paths(&head_root_unode),
+        list_paths(&commit_to_merge_root_unode),
+    )
+    .await?;
+
+    let mut collisions = find_case_collisions(
+        files_to_delete
+            .iter()
+            .map(|path| (path.clone(), PathOrigin::Deleted)),
+    )?;
+    let deleted: HashSet<_> = files_to_delete.iter().collect();
+    let kept = head_paths
+        .into_iter()
+        .filter(|path| !deleted.contains(&path))
+        .map(|path| (path, PathOrigin::Head));
+    let merged = commit_to_merge_paths
+        .into_iter()
+        .map(|path| (path, PathOrigin::CommitToMerge));
+    collisions.extend(find_case_collisions(kept.chain(merged))?);
+
+    if collisions.is_empty() {
+        return Ok(());
+    }
+
+    let mut report = String::new();
+    for collision in &collisions {
+        let paths: Vec<_> = collision
+            .iter()
+            .map(|(path, origin)| format!("{} ({})", path, origin))
+            .collect();
+        report.push_str(&format!("\n  {}", paths.join(", ")));
+    }
+    Err(anyhow!(
+        "found {} case collisions, no deletion commits were created:{}",
+        collisions.len(),
+        report
+    ))
+}
+
 // Returns paths of the files that:
 // 1) Match `path_regex`
 // 2) Either do not exist in `commit_to_merge` or have different content/filetype.
+// 3) Were not modified on the head bookmark after `skip_modified_after`, if it is set. Those were
+//    changed while the catchup was being prepared, and deleting them would clobber fresh work.
 async fn find_files_that_need_to_be_deleted(
     ctx: &CoreContext,
     repo: &BlobRepo,
     head_bookmark: &BookmarkName,
     commit_to_merge: ChangesetId,
     path_regex: Regex,
+    skip_modified_after: Option<DateTime>,
 ) -> Result<Vec<MPath>, Error> {
     let maybe_head_bookmark_val = repo.get_bonsai_bookmark(ctx.clone(), head_bookmark).await?;
 
@@ -190,31 +398,112 @@ async fn find_files_that_need_to_be_deleted(
         )
         .try_filter_map(|diff| async move {
             use Diff::*;
+            // Keep the unode of the file in head, it tells when the file was last modified there.
             let maybe_path = match diff {
                 Added(_maybe_path, _entry) => None,
-                Removed(maybe_path, entry) => entry.into_leaf().and_then(|_| maybe_path),
-                Changed(maybe_path, _old_entry, new_entry) => {
-                    new_entry.into_leaf().and_then(|_| maybe_path)
-                }
+                Removed(maybe_path, entry) => entry
+                    .into_leaf()
+                    .and_then(|head_unode| Some((maybe_path?, head_unode))),
+                Changed(maybe_path, old_entry, new_entry) => new_entry
+                    .into_leaf()
+                    .and(old_entry.into_leaf())
+                    .and_then(|head_unode| Some((maybe_path?, head_unode))),
             };
 
             Ok(maybe_path)
         })
-        .try_filter(|path| future::ready(path.matches_regex(&path_regex)))
+        .try_filter(|(path, _)| future::ready(path.matches_regex(&path_regex)))
         .try_collect::<Vec<_>>()
         .await?;
 
+    if let Some(cutoff) = skip_modified_after {
+        let landed_after_cutoff =
+            find_landed_after_cutoff(ctx, repo, head_bookmark, head_bookmark_val, &cutoff).await?;
+        let modified_after_cutoff = find_modified_by(
+            ctx,
+            repo,
+            paths.iter().map(|(_, unode)| *unode),
+            &landed_after_cutoff,
+        )
+        .await?;
+        let before = paths.len();
+        paths.retain(|(_, unode)| !modified_after_cutoff.contains(unode));
+        info!(
+            ctx.logger(),
+            "skipping {} files modified on {} after {}",
+            before - paths.len(),
+            head_bookmark,
+            cutoff
+        );
+    }
+
+    let mut paths: Vec<_> = paths.into_iter().map(|(path, _)| path).collect();
     paths.sort();
     Ok(paths)
 }
 
+// Returns the commits that landed on `head_bookmark` after `cutoff`: those that are ancestors of
+// `head` but not of where the bookmark pointed to at `cutoff`, as recorded in the bookmark update
+// log. Author dates can't tell this, as they are set when a commit is made, not when it lands.
+async fn find_landed_after_cutoff(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    head_bookmark: &BookmarkName,
+    head: ChangesetId,
+    cutoff: &DateTime,
+) -> Result<HashSet<ChangesetId>, Error> {
+    let head_at_cutoff = repo
+        .bookmarks_log()
+        .get_bookmark_log_entry_at_timestamp(
+            ctx.clone(),
+            head_bookmark.clone(),
+            Timestamp::from(*cutoff),
+            Freshness::MostRecent,
+        )
+        .await?
+        .and_then(|(_id, cs_id, _reason, _timestamp)| cs_id)
+        .ok_or_else(|| format_err!("{} did not exist at {}", head_bookmark, cutoff))?;
+
+    DifferenceOfUnionsOfAncestorsNodeStream::new_with_excludes(
+        ctx.clone(),
+        &repo.get_changeset_fetcher(),
+        Arc::new(SkiplistIndex::new()),
+        vec![head],
+        vec![head_at_cutoff],
+    )
+    .compat()
+    .try_collect()
+    .await
+}
+
+// Returns the file unodes whose linknode, i.e. the commit that last modified the file, is one of
+// `commits`.
+async fn find_modified_by(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    unodes: impl Iterator<Item = FileUnodeId>,
+    commits: &HashSet<ChangesetId>,
+) -> Result<HashSet<FileUnodeId>, Error> {
+    stream::iter(unodes)
+        .map(|unode_id| async move {
+            let unode = unode_id.load(ctx, repo.blobstore()).await?;
+            Ok::<_, Error>((unode_id, *unode.linknode()))
+        })
+        .buffer_unordered(LOAD_LINKNODES_CONCURRENCY)
+        .try_filter_map(|(unode_id, linknode)| {
+            future::ready(Ok(Some(unode_id).filter(|_| commits.contains(&linknode))))
+        })
+        .try_collect()
+        .await
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use fbinit::FacebookInit;
     use futures::compat::Stream01CompatExt;
     use megarepolib::common::ChangesetArgs;
-    use mononoke_types::DateTime;
+    use metaconfig_types::{HgsqlName, RepoReadOnly};
     use revset::RangeNodeStream;
     use tests_utils::{bookmark, resolve_cs_id, CreateCommitContext};
 
@@ -233,6 +522,7 @@ mod test {
             &book,
             commit_to_merge,
             Regex::new(PATH_REGEX)?,
+            None,
         )
         .await?;
 
@@ -283,6 +573,7 @@ mod test {
             &book,
             commit_to_merge,
             Regex::new(".*")?,
+            None,
         )
         .await?;
 
@@ -292,6 +583,132 @@ mod test {
         Ok(())
     }
 
+    #[fbinit::test]
+    async fn test_find_files_skip_modified_after(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = prepare_repo(&ctx).await?;
+
+        // A change to one of the files lands before the cutoff, even though it was authored
+        // after it.
+        let head = resolve_cs_id(&ctx, &repo, "book").await?;
+        let head = CreateCommitContext::new(&ctx, &repo, vec![head])
+            .add_file("toremove/file1", "othercontent")
+            .set_author_date(DateTime::from_timestamp(i64::from(i32::MAX), 0)?)
+            .commit()
+            .await?;
+        bookmark(&ctx, &repo, "book").set_to(head).await?;
+
+        delay_for(Duration::from_millis(1)).await;
+        let cutoff = DateTime::now();
+        delay_for(Duration::from_millis(1)).await;
+
+        // Someone changes one of the files on head after the cutoff, in a commit that was
+        // authored long before it.
+        let head = CreateCommitContext::new(&ctx, &repo, vec![head])
+            .add_file("changed/b", "freshcontent")
+            .set_author_date(DateTime::from_timestamp(1000, 0)?)
+            .commit()
+            .await?;
+        bookmark(&ctx, &repo, "book").set_to(head).await?;
+
+        let commit_to_merge = resolve_cs_id(&ctx, &repo, "commit_to_merge").await?;
+        let book = BookmarkName::new("book")?;
+        let paths = find_files_that_need_to_be_deleted(
+            &ctx,
+            &repo,
+            &book,
+            commit_to_merge,
+            Regex::new(PATH_REGEX)?,
+            Some(cutoff),
+        )
+        .await?;
+
+        assert_eq!(
+            paths,
+            vec![
+                MPath::new("changed/a")?,
+                MPath::new("toremove/file1")?,
+                MPath::new("toremove/file2")?,
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_case_collisions() -> Result<(), Error> {
+        let path = |p: &str| MPath::new(p).unwrap();
+        let collisions = find_case_collisions(vec![
+            (path("same/file"), PathOrigin::Head),
+            (path("same/file"), PathOrigin::CommitToMerge),
+            (path("Dir/a"), PathOrigin::Head),
+            (path("Dir/b"), PathOrigin::Head),
+            (path("dir/a"), PathOrigin::CommitToMerge),
+            (path("file"), PathOrigin::Head),
+            (path("FILE/x"), PathOrigin::CommitToMerge),
+        ])?;
+        assert_eq!(
+            collisions,
+            vec![
+                btreeset! {
+                    (path("Dir"), PathOrigin::Head),
+                    (path("dir"), PathOrigin::CommitToMerge),
+                },
+                btreeset! {
+                    (path("file"), PathOrigin::Head),
+                    (path("FILE"), PathOrigin::CommitToMerge),
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_create_deletion_head_commits_case_collision(
+        fb: FacebookInit,
+    ) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = prepare_repo(&ctx).await?;
+        let book = BookmarkName::new("book")?;
+
+        // Not deleted, as it doesn't match the regex, but it collides with the commit to merge.
+        let head = resolve_cs_id(&ctx, &repo, "book").await?;
+        let head = CreateCommitContext::new(&ctx, &repo, vec![head])
+            .add_file("Changed/c", "content")
+            .commit()
+            .await?;
+        bookmark(&ctx, &repo, "book").set_to(head).await?;
+
+        let commit_to_merge = resolve_cs_id(&ctx, &repo, "commit_to_merge").await?;
+        let args_factory = Box::new(|stack_pos: StackPosition| ChangesetArgs {
+            author: "author".to_string(),
+            message: format!("{}", stack_pos.0),
+            datetime: DateTime::now(),
+            bookmark: None,
+            mark_public: false,
+        });
+        let res = create_deletion_head_commits(
+            &ctx,
+            &repo,
+            book.clone(),
+            commit_to_merge,
+            Regex::new(PATH_REGEX)?,
+            1,
+            args_factory,
+            &PushrebaseFlags::default(),
+            &read_write_fetcher(),
+            0,
+            None,
+            None,
+        )
+        .await;
+
+        let err = format!("{}", res.expect_err("should fail on the case collision"));
+        assert!(err.contains("Changed (head), changed (commit to merge)"));
+        assert_eq!(resolve_cs_id(&ctx, &repo, book).await?, head);
+        Ok(())
+    }
+
     #[fbinit::test]
     async fn test_create_deletion_head_commits(fb: FacebookInit) -> Result<(), Error> {
         let ctx = CoreContext::test_mock(fb);
@@ -326,7 +743,10 @@ mod test {
             1,
             args_factory,
             &pushrebase_flags,
+            &read_write_fetcher(),
             0,
+            None,
+            None,
         )
         .await?;
         let commit_after_push = resolve_cs_id(&ctx, &repo, book.clone()).await?;
@@ -349,6 +769,7 @@ mod test {
             &book,
             commit_to_merge,
             Regex::new(PATH_REGEX)?,
+            None,
         )
         .await?;
 
@@ -386,4 +807,8 @@ mod test {
 
         Ok(repo)
     }
+
+    fn read_write_fetcher() -> RepoReadWriteFetcher {
+        RepoReadWriteFetcher::new(None, RepoReadOnly::ReadWrite, HgsqlName("repo".to_string()))
+    }
 }
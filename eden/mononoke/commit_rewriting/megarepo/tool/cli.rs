@@ -11,7 +11,7 @@ use clap::{App, Arg, ArgMatches, SubCommand};
 use cmdlib::args::{self, MononokeClapApp};
 use futures_ext::{try_boxfuture, BoxFuture, FutureExt};
 use futures_old::future::{err, ok};
-use megarepolib::common::{ChangesetArgs, ChangesetArgsFactory, StackPosition};
+use megarepolib::common::{ChangesetArgs, ChangesetArgsFactory, CheckpointBookmark, StackPosition};
 use mononoke_types::DateTime;
 
 pub const BACKFILL_NOOP_MAPPING: &str = "backfill-noop-mapping";
@@ -23,6 +23,7 @@ pub const CATCHUP_DELETE_HEAD: &str = "create-catchup-head-deletion-commits";
 pub const CATCHUP_VALIDATE_COMMAND: &str = "catchup-validate";
 pub const CHANGESET: &str = "commit";
 pub const CHECK_PUSH_REDIRECTION_PREREQS: &str = "check-push-redirection-prereqs";
+pub const CHECKPOINT_BOOKMARK: &str = "checkpoint-bookmark";
 pub const CHUNKING_HINT_FILE: &str = "chunking-hint-file";
 pub const COMMIT_AUTHOR: &str = "commit-author";
 pub const COMMIT_BOOKMARK: &str = "bookmark";
@@ -47,6 +48,7 @@ pub const MARK_NOT_SYNCED_COMMAND: &str = "mark-not-synced";
 pub const MARK_PUBLIC: &str = "mark-public";
 pub const MAX_NUM_OF_MOVES_IN_COMMIT: &str = "max-num-of-moves-in-commit";
 pub const MERGE: &str = "merge";
+pub const MERGE_BLAST_RADIUS: &str = "merge-blast-radius";
 pub const MOVE: &str = "move";
 pub const ORIGIN_REPO: &str = "origin-repo";
 pub const PARENTS: &str = "parents";
@@ -56,7 +58,9 @@ pub const PRE_DELETION_COMMIT: &str = "pre-deletion-commit";
 pub const PRE_MERGE_DELETE: &str = "pre-merge-delete";
 pub const RUN_MOVER: &str = "run-mover";
 pub const SECOND_PARENT: &str = "second-parent";
+pub const SKIP_MODIFIED_AFTER: &str = "skip-modified-after";
 pub const SOURCE_CHANGESET: &str = "source-changeset";
+pub const SYNC_CHECK: &str = "sync-check";
 pub const SYNC_COMMIT_AND_ANCESTORS: &str = "sync-commit-and-ancestors";
 pub const SYNC_DIAMOND_MERGE: &str = "sync-diamond-merge";
 pub const TARGET_CHANGESET: &str = "target-changeset";
@@ -131,6 +135,29 @@ pub fn get_gradual_merge_commits_cs_args_factory<'a>(
     })
 }
 
+pub fn get_checkpoint_bookmark<'a>(
+    sub_m: &ArgMatches<'a>,
+) -> Result<Option<CheckpointBookmark>, Error> {
+    sub_m
+        .value_of(CHECKPOINT_BOOKMARK)
+        .map(|bookmark| Ok(CheckpointBookmark(BookmarkName::new(bookmark)?)))
+        .transpose()
+}
+
+fn add_checkpoint_bookmark_arg<'a, 'b>(subcommand: App<'a, 'b>) -> App<'a, 'b> {
+    subcommand.arg(
+        Arg::with_name(CHECKPOINT_BOOKMARK)
+            .help(
+                "bookmark to move to the latest created commit after each commit of the stack, \
+                to follow the progress of the stack (no sanity checks, will move existing \
+                bookmark, be careful)",
+            )
+            .long(CHECKPOINT_BOOKMARK)
+            .takes_value(true)
+            .required(false),
+    )
+}
+
 fn get_commit_factory<'a>(
     sub_m: &ArgMatches<'a>,
     msg_factory: impl Fn(&String, usize) -> String + Send + Sync + 'static,
@@ -299,6 +326,7 @@ pub fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
                 .takes_value(true)
                 .required(false)
         );
+    let pre_merge_delete_subcommand = add_checkpoint_bookmark_arg(pre_merge_delete_subcommand);
 
     // PLease don't move `add_light_resulting_commit_args` to be applied
     // after `PATH` arg is added, as in that case `PATH` won't be the last
@@ -326,6 +354,7 @@ pub fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
                     .required(true)
                     .multiple(true),
             );
+    let gradual_delete_subcommand = add_checkpoint_bookmark_arg(gradual_delete_subcommand);
 
     let bonsai_merge_subcommand = SubCommand::with_name(BONSAI_MERGE)
         .about("create a bonsai merge commit")
@@ -489,7 +518,20 @@ pub fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
                 .default_value("0")
                 .takes_value(true)
                 .required(false),
+        )
+        .arg(
+            Arg::with_name(SKIP_MODIFIED_AFTER)
+                .long(SKIP_MODIFIED_AFTER)
+                .help(
+                    "don't delete files that were modified on the head bookmark after this \
+                    time (in RFC 3339 format), so that work that landed while the catchup was \
+                    being prepared isn't clobbered",
+                )
+                .takes_value(true)
+                .required(false),
         );
+    let catchup_delete_head_subcommand =
+        add_checkpoint_bookmark_arg(catchup_delete_head_subcommand);
 
 
     let catchup_validate_subcommand = SubCommand::with_name(CATCHUP_VALIDATE_COMMAND)
@@ -516,6 +558,37 @@ pub fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
                 .required(true),
         );
 
+    let merge_blast_radius_subcommand = SubCommand::with_name(MERGE_BLAST_RADIUS)
+        .about(
+            "report how many files and directories merging a commit into a bookmark would add, \
+            delete or change, per top-level directory. No commits are created, and nothing is \
+            derived: fsnodes must already be derived for both commits",
+        )
+        .arg(
+            Arg::with_name(HEAD_BOOKMARK)
+                .long(HEAD_BOOKMARK)
+                .help("bookmark to merge into")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(TO_MERGE_CS_ID)
+                .long(TO_MERGE_CS_ID)
+                .help("commit to merge")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(PATH_REGEX)
+                .long(PATH_REGEX)
+                .help(
+                    "count the merge of a catchup with this regex instead: only the paths that \
+                    match it, deleting those of the bookmark that aren't in the commit",
+                )
+                .takes_value(true)
+                .required(false),
+        );
+
     let mark_not_synced_candidate = SubCommand::with_name(MARK_NOT_SYNCED_COMMAND)
         .about("mark all commits that do not have any mapping as not synced candidate, but leave those that have the mapping alone")
         .arg(
@@ -617,6 +690,19 @@ pub fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
                 .required(true),
         );
 
+    let sync_check = SubCommand::with_name(SYNC_CHECK)
+        .about(
+            "Check that commit sync configs are consistent and apply to the current heads of \
+            source and target repos. Problems are printed to stdout as json, one per line. \
+            Meant to be run in CI.",
+        )
+        .arg(
+            Arg::with_name(MAPPING_VERSION_NAME)
+                .help("mapping versions to check. All versions are checked if none are given")
+                .takes_value(true)
+                .multiple(true)
+                .required(false),
+        );
 
     args::MononokeAppBuilder::new("megarepo preparation tool")
         .with_advanced_args_hidden()
@@ -635,10 +721,12 @@ pub fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
             catchup_delete_head_subcommand,
         ))
         .subcommand(catchup_validate_subcommand)
+        .subcommand(merge_blast_radius_subcommand)
         .subcommand(mark_not_synced_candidate)
         .subcommand(check_push_redirection_prereqs_subcommand)
         .subcommand(run_mover_subcommand)
         .subcommand(backfill_noop_mapping)
         .subcommand(sync_commit_and_ancestors)
         .subcommand(diff_mapping_versions)
+        .subcommand(sync_check)
 }
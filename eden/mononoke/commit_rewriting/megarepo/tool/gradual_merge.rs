@@ -11,6 +11,7 @@ use blobrepo_hg::BlobRepoHg;
 use blobstore::Loadable;
 use bookmarks::BookmarkName;
 use cmdlib::helpers;
+use cmdlib_progress::{Progress, ProgressOptions};
 use context::CoreContext;
 use futures::{
     compat::{Future01CompatExt, Stream01CompatExt},
@@ -20,10 +21,12 @@ use maplit::hashset;
 use megarepolib::common::{
     create_and_save_bonsai, ChangesetArgs, ChangesetArgsFactory, StackPosition,
 };
+use megarepolib::tool_identity::{MegarepoOperation, ToolIdentityPushrebaseHook};
 use metaconfig_types::PushrebaseFlags;
 use mononoke_types::ChangesetId;
 use pushrebase::do_pushrebase_bonsai;
 use reachabilityindex::LeastCommonAncestorsHint;
+use repo_read_write_status::RepoReadWriteFetcher;
 use revset::RangeNodeStream;
 use skiplist::SkiplistIndex;
 use slog::info;
@@ -129,6 +132,7 @@ pub async fn gradual_merge(
     skiplist: &SkiplistIndex,
     params: &GradualMergeParams,
     pushrebase_flags: &PushrebaseFlags,
+    readonly_fetcher: &RepoReadWriteFetcher,
 ) -> Result<HashMap<ChangesetId, ChangesetId>, Error> {
     let GradualMergeParams {
         pre_deletion_commit,
@@ -158,6 +162,12 @@ pub async fn gradual_merge(
 
     let mut res = HashMap::new();
     if !dry_run {
+        let progress: Progress = Progress::new(
+            "gradual merge commits",
+            Some(unmerged_commits.len() as u64),
+            ProgressOptions::default(),
+        )
+        .with_logger(ctx.logger().clone());
         for (cs_id, stack_pos) in unmerged_commits {
             let merge_changeset_args = merge_changeset_args_factory(stack_pos);
             let res_cs_id = push_merge_commit(
@@ -167,10 +177,13 @@ pub async fn gradual_merge(
                 &bookmark_to_merge_into,
                 merge_changeset_args,
                 pushrebase_flags,
+                readonly_fetcher,
             )
             .await?;
 
             res.insert(cs_id, res_cs_id);
+            progress.record(1);
+            progress.report();
         }
     } else {
         for (cs_id, stack_pos) in unmerged_commits {
@@ -299,6 +312,7 @@ async fn push_merge_commit(
     bookmark_to_merge_into: &BookmarkName,
     merge_changeset_args: ChangesetArgs,
     pushrebase_flags: &PushrebaseFlags,
+    readonly_fetcher: &RepoReadWriteFetcher,
 ) -> Result<ChangesetId, Error> {
     info!(ctx.logger(), "Preparing to merge {}", cs_id_to_merge);
     let bookmark_value = helpers::csid_resolve(ctx.clone(), repo.clone(), bookmark_to_merge_into)
@@ -311,6 +325,7 @@ async fn push_merge_commit(
         vec![bookmark_value, cs_id_to_merge],
         Default::default(),
         merge_changeset_args,
+        MegarepoOperation::GradualMerge,
     )
     .await?;
 
@@ -323,6 +338,9 @@ async fn push_merge_commit(
         .await?;
 
     info!(ctx.logger(), "Generated hg changeset {}", merge_hg_cs_id);
+
+    // The repo may have been locked since the merge started.
+    readonly_fetcher.check_writable().await?;
     info!(ctx.logger(), "Now running pushrebase...");
 
     let merge_cs = merge_cs_id.load(&ctx, repo.blobstore()).await?;
@@ -333,7 +351,7 @@ async fn push_merge_commit(
         &bookmark_to_merge_into,
         &hashset![merge_cs],
         None,
-        &[],
+        &[ToolIdentityPushrebaseHook::new()],
     )
     .await?;
 
@@ -347,6 +365,7 @@ mod test {
     use blobrepo_factory::new_memblob_empty;
     use fbinit::FacebookInit;
     use maplit::hashmap;
+    use metaconfig_types::{HgsqlName, RepoReadOnly};
     use mononoke_types::{DateTime, MPath};
     use tests_utils::{
         bookmark, drawdag::create_from_dag, list_working_copy_utf8, CreateCommitContext,
@@ -478,6 +497,7 @@ mod test {
             flags.recursion_limit = None;
             flags
         };
+        let readonly_fetcher = read_write_fetcher();
 
         // Test dry-run mode
         params.dry_run = true;
@@ -487,6 +507,7 @@ mod test {
             &SkiplistIndex::new(),
             &params,
             &pushrebase_flags,
+            &readonly_fetcher,
         )
         .await?;
         assert!(merged.is_empty());
@@ -498,6 +519,7 @@ mod test {
             &SkiplistIndex::new(),
             &params,
             &pushrebase_flags,
+            &readonly_fetcher,
         )
         .await?;
         verify_gradual_merges(&ctx, &repo, merged, pre_deletion_commit, &deletion_commits).await?;
@@ -528,6 +550,7 @@ mod test {
             flags.recursion_limit = None;
             flags
         };
+        let readonly_fetcher = read_write_fetcher();
 
         let mut result = HashMap::new();
         for _ in 0..3 {
@@ -537,6 +560,7 @@ mod test {
                 &SkiplistIndex::new(),
                 &params,
                 &pushrebase_flags,
+                &readonly_fetcher,
             )
             .await?;
             assert_eq!(merged.len(), 1);
@@ -576,6 +600,7 @@ mod test {
             flags.recursion_limit = None;
             flags
         };
+        let readonly_fetcher = read_write_fetcher();
 
         let mut result = HashMap::new();
         for i in 0..3 {
@@ -585,6 +610,7 @@ mod test {
                 &SkiplistIndex::new(),
                 &params,
                 &pushrebase_flags,
+                &readonly_fetcher,
             )
             .await?;
             assert_eq!(merged.len(), 1);
@@ -598,6 +624,45 @@ mod test {
         Ok(())
     }
 
+    #[fbinit::test]
+    async fn test_gradual_merge_locked(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let (repo, pre_deletion_commit, deletion_commits) = create_repo(&ctx).await?;
+        let head = BookmarkName::new("head")?;
+        let head_before = repo.get_bonsai_bookmark(ctx.clone(), &head).await?;
+
+        let params = GradualMergeParams {
+            pre_deletion_commit,
+            last_deletion_commit: *deletion_commits.last().unwrap(),
+            bookmark_to_merge_into: head.clone(),
+            merge_changeset_args_factory: get_default_merge_args_factory(),
+            limit: None,
+            dry_run: false,
+        };
+        let readonly_fetcher = RepoReadWriteFetcher::new(
+            None,
+            RepoReadOnly::ReadOnly("locked for the test".to_string()),
+            HgsqlName("repo".to_string()),
+        );
+
+        let res = gradual_merge(
+            &ctx,
+            &repo,
+            &SkiplistIndex::new(),
+            &params,
+            &PushrebaseFlags::default(),
+            &readonly_fetcher,
+        )
+        .await;
+        assert!(res.is_err());
+        assert_eq!(
+            repo.get_bonsai_bookmark(ctx.clone(), &head).await?,
+            head_before
+        );
+
+        Ok(())
+    }
+
     async fn create_repo(
         ctx: &CoreContext,
     ) -> Result<(BlobRepo, ChangesetId, Vec<ChangesetId>), Error> {
@@ -716,4 +781,8 @@ mod test {
             mark_public: false,
         })
     }
+
+    fn read_write_fetcher() -> RepoReadWriteFetcher {
+        RepoReadWriteFetcher::new(None, RepoReadOnly::ReadWrite, HgsqlName("repo".to_string()))
+    }
 }
@@ -34,9 +34,10 @@ use futures::{
 use live_commit_sync_config::{CfgrLiveCommitSyncConfig, LiveCommitSyncConfig};
 use metaconfig_types::RepoConfig;
 use metaconfig_types::{CommitSyncConfigVersion, MetadataDatabaseConfig};
-use mononoke_types::{MPath, RepositoryId};
+use mononoke_types::{DateTime, MPath, RepositoryId};
 use movers::get_small_to_large_mover;
 use regex::Regex;
+use repo_read_write_status::RepoReadWriteFetcher;
 use skiplist::fetch_skiplist_index;
 use slog::{info, warn};
 #[cfg(fbcode_build)]
@@ -53,6 +54,7 @@ use tokio::{
     io::{AsyncBufReadExt, BufReader},
 };
 
+mod blast_radius;
 mod catchup;
 mod cli;
 mod gradual_merge;
@@ -61,17 +63,18 @@ mod merging;
 mod sync_diamond_merge;
 
 use crate::cli::{
-    cs_args_from_matches, get_catchup_head_delete_commits_cs_args_factory,
+    cs_args_from_matches, get_catchup_head_delete_commits_cs_args_factory, get_checkpoint_bookmark,
     get_delete_commits_cs_args_factory, get_gradual_merge_commits_cs_args_factory, setup_app,
     BACKFILL_NOOP_MAPPING, BASE_COMMIT_HASH, BONSAI_MERGE, BONSAI_MERGE_P1, BONSAI_MERGE_P2,
     CATCHUP_DELETE_HEAD, CATCHUP_VALIDATE_COMMAND, CHANGESET, CHECK_PUSH_REDIRECTION_PREREQS,
     CHUNKING_HINT_FILE, COMMIT_BOOKMARK, COMMIT_HASH, DELETION_CHUNK_SIZE, DIFF_MAPPING_VERSIONS,
     DRY_RUN, EVEN_CHUNK_SIZE, FIRST_PARENT, GRADUAL_DELETE, GRADUAL_MERGE, GRADUAL_MERGE_PROGRESS,
     HEAD_BOOKMARK, INPUT_FILE, LAST_DELETION_COMMIT, LIMIT, MANUAL_COMMIT_SYNC,
-    MAPPING_VERSION_NAME, MARK_NOT_SYNCED_COMMAND, MAX_NUM_OF_MOVES_IN_COMMIT, MERGE, MOVE,
-    ORIGIN_REPO, PARENTS, PATH, PATH_REGEX, PRE_DELETION_COMMIT, PRE_MERGE_DELETE, RUN_MOVER,
-    SECOND_PARENT, SOURCE_CHANGESET, SYNC_COMMIT_AND_ANCESTORS, SYNC_DIAMOND_MERGE,
-    TARGET_CHANGESET, TO_MERGE_CS_ID, VERSION, WAIT_SECS,
+    MAPPING_VERSION_NAME, MARK_NOT_SYNCED_COMMAND, MAX_NUM_OF_MOVES_IN_COMMIT, MERGE,
+    MERGE_BLAST_RADIUS, MOVE, ORIGIN_REPO, PARENTS, PATH, PATH_REGEX, PRE_DELETION_COMMIT,
+    PRE_MERGE_DELETE, RUN_MOVER, SECOND_PARENT, SKIP_MODIFIED_AFTER, SOURCE_CHANGESET, SYNC_CHECK,
+    SYNC_COMMIT_AND_ANCESTORS, SYNC_DIAMOND_MERGE, TARGET_CHANGESET, TO_MERGE_CS_ID, VERSION,
+    WAIT_SECS,
 };
 use crate::merging::perform_merge;
 use megarepolib::chunking::{
@@ -80,6 +83,10 @@ use megarepolib::chunking::{
 use megarepolib::commit_sync_config_utils::diff_small_repo_commit_sync_configs;
 use megarepolib::common::{create_and_save_bonsai, delete_files_in_chunks};
 use megarepolib::pre_merge_delete::{create_pre_merge_delete, PreMergeDelete};
+use megarepolib::sync_check::{
+    check_commit_sync_config, check_commit_sync_config_at_heads, SyncCheckError,
+};
+use megarepolib::tool_identity::MegarepoOperation;
 use megarepolib::working_copy::get_working_copy_paths_by_prefixes;
 use megarepolib::{common::StackPosition, perform_move, perform_stack_move};
 
@@ -273,6 +280,7 @@ async fn run_pre_merge_delete<'a>(
         chunker,
         delete_cs_args_factory,
         base_bcs_id,
+        get_checkpoint_bookmark(sub_m)?.as_ref(),
     )
     .await?;
 
@@ -336,6 +344,7 @@ async fn run_gradual_delete<'a>(
         chunker,
         delete_cs_args_factory,
         false, /* skip_last_chunk */
+        get_checkpoint_bookmark(sub_m)?.as_ref(),
     )
     .await?;
     info!(ctx.logger(), "Deletion finished");
@@ -375,8 +384,15 @@ async fn run_bonsai_merge<'a>(
 
     let cs_args = cs_args_from_matches(sub_m).compat().await?;
 
-    let merge_cs_id =
-        create_and_save_bonsai(&ctx, &repo, vec![p1, p2], Default::default(), cs_args).await?;
+    let merge_cs_id = create_and_save_bonsai(
+        &ctx,
+        &repo,
+        vec![p1, p2],
+        Default::default(),
+        cs_args,
+        MegarepoOperation::Merge,
+    )
+    .await?;
 
     println!("{}", merge_cs_id);
 
@@ -425,12 +441,14 @@ async fn run_gradual_merge<'a>(
         limit,
         dry_run,
     };
+    let readonly_fetcher = open_readonly_fetcher(&ctx, matches, &repo_config).await?;
     gradual_merge::gradual_merge(
         &ctx,
         &repo,
         &skiplist,
         &params,
         &repo_config.pushrebase.flags,
+        &readonly_fetcher,
     )
     .await?;
 
@@ -630,7 +648,12 @@ async fn run_catchup_delete_head<'a>(
     let (_, repo_config) = args::get_config(config_store, &matches)?;
 
     let wait_secs = args::get_u64(&sub_m, WAIT_SECS, 0);
+    let skip_modified_after = sub_m
+        .value_of(SKIP_MODIFIED_AFTER)
+        .map(DateTime::from_rfc3339)
+        .transpose()?;
 
+    let readonly_fetcher = open_readonly_fetcher(&ctx, matches, &repo_config).await?;
     catchup::create_deletion_head_commits(
         &ctx,
         &repo,
@@ -640,12 +663,52 @@ async fn run_catchup_delete_head<'a>(
         deletion_chunk_size,
         cs_args_factory,
         &repo_config.pushrebase.flags,
+        &readonly_fetcher,
         wait_secs,
+        skip_modified_after,
+        get_checkpoint_bookmark(sub_m)?.as_ref(),
     )
     .await?;
     Ok(())
 }
 
+async fn run_merge_blast_radius<'a>(
+    ctx: CoreContext,
+    matches: &MononokeMatches<'a>,
+    sub_m: &ArgMatches<'a>,
+) -> Result<(), Error> {
+    let repo = args::open_repo(ctx.fb, &ctx.logger().clone(), &matches).await?;
+
+    let head_bookmark = sub_m
+        .value_of(HEAD_BOOKMARK)
+        .ok_or_else(|| format_err!("{} not set", HEAD_BOOKMARK))?;
+    let head_bookmark = BookmarkName::new(head_bookmark)?;
+    let head = repo
+        .get_bonsai_bookmark(ctx.clone(), &head_bookmark)
+        .await?
+        .ok_or_else(|| format_err!("{} not found", head_bookmark))?;
+
+    let to_merge_cs_id = sub_m
+        .value_of(TO_MERGE_CS_ID)
+        .ok_or_else(|| format_err!("{} not set", TO_MERGE_CS_ID))?;
+    let to_merge_cs_id = helpers::csid_resolve(ctx.clone(), repo.clone(), to_merge_cs_id)
+        .compat()
+        .await?;
+
+    let path_regex = sub_m.value_of(PATH_REGEX).map(Regex::new).transpose()?;
+
+    let by_directory = blast_radius::compute_merge_blast_radius(
+        &ctx,
+        &repo,
+        head,
+        to_merge_cs_id,
+        path_regex.as_ref(),
+    )
+    .await?;
+    blast_radius::print_blast_radius(&by_directory);
+    Ok(())
+}
+
 async fn run_mover<'a>(
     ctx: CoreContext,
     matches: &MononokeMatches<'a>,
@@ -946,6 +1009,85 @@ async fn run_diff_mapping_versions<'a>(
     Ok(())
 }
 
+async fn run_sync_check<'a>(
+    ctx: CoreContext,
+    matches: &MononokeMatches<'a>,
+    sub_m: &ArgMatches<'a>,
+) -> Result<(), Error> {
+    let config_store = args::init_config_store(ctx.fb, ctx.logger(), matches)?;
+    let target_repo_id = args::get_target_repo_id(&config_store, matches)?;
+    let live_commit_sync_config = CfgrLiveCommitSyncConfig::new(ctx.logger(), &config_store)?;
+
+    let mut errors = vec![];
+    match live_commit_sync_config
+        .get_all_commit_sync_config_versions(target_repo_id)
+        .await
+    {
+        Ok(all_versions) => {
+            let mut all_versions: Vec<_> = all_versions.into_iter().collect();
+            all_versions.sort_by(|(a, _), (b, _)| a.0.cmp(&b.0));
+
+            let requested: Option<Vec<_>> = sub_m
+                .values_of(MAPPING_VERSION_NAME)
+                .map(|versions| versions.collect());
+            if let Some(requested) = &requested {
+                for version in requested {
+                    if !all_versions.iter().any(|(v, _)| v.0 == *version) {
+                        bail!("mapping version {} not found", version);
+                    }
+                }
+            }
+
+            for (stored_as, config) in &all_versions {
+                if let Some(requested) = &requested {
+                    if !requested.contains(&stored_as.0.as_str()) {
+                        continue;
+                    }
+                }
+                if &config.version_name != stored_as {
+                    errors.push(SyncCheckError::VersionNameMismatch {
+                        version: config.version_name.0.clone(),
+                        stored_as: stored_as.0.clone(),
+                    });
+                }
+                errors.extend(check_commit_sync_config(config));
+            }
+
+            let current = live_commit_sync_config
+                .get_current_commit_sync_config(&ctx, target_repo_id)
+                .await?;
+            let check_heads = requested.map_or(true, |requested| {
+                requested.contains(&current.version_name.0.as_str())
+            });
+            if check_heads {
+                let commit_syncer = create_commit_syncer_from_matches(&ctx, matches).await?;
+                errors.extend(
+                    check_commit_sync_config_at_heads(
+                        &ctx,
+                        &current,
+                        commit_syncer.get_small_repo(),
+                        commit_syncer.get_large_repo(),
+                    )
+                    .await?,
+                );
+            }
+        }
+        Err(e) => errors.push(SyncCheckError::ConfigLoadFailed {
+            message: format!("{:#}", e),
+        }),
+    }
+
+    for error in &errors {
+        println!("{}", serde_json::to_string(error)?);
+    }
+
+    if !errors.is_empty() {
+        bail!("commit sync config check found {} problems", errors.len());
+    }
+    info!(ctx.logger(), "commit sync configs are consistent");
+    Ok(())
+}
+
 async fn process_stream_and_wait_for_replication<'a>(
     ctx: &CoreContext,
     matches: &MononokeMatches<'a>,
@@ -1088,6 +1230,62 @@ fn get_and_verify_repo_config<'a>(
     })
 }
 
+/// The repo that a subcommand writes commits to, if it writes to any.
+fn written_repo_id<'a>(
+    config_store: &ConfigStore,
+    matches: &MononokeMatches<'a>,
+) -> Result<Option<RepositoryId>> {
+    match matches.as_ref().subcommand_name() {
+        Some(BONSAI_MERGE)
+        | Some(CATCHUP_DELETE_HEAD)
+        | Some(GRADUAL_DELETE)
+        | Some(GRADUAL_MERGE)
+        | Some(MERGE)
+        | Some(MOVE)
+        | Some(PRE_MERGE_DELETE) => Ok(Some(args::get_repo_id(config_store, matches)?)),
+        Some(MANUAL_COMMIT_SYNC) | Some(SYNC_COMMIT_AND_ANCESTORS) | Some(SYNC_DIAMOND_MERGE) => {
+            Ok(Some(args::get_target_repo_id(config_store, matches)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The lock state of a repo, for the subcommands that push many commits to check it again
+/// before each of them.
+async fn open_readonly_fetcher<'a>(
+    ctx: &CoreContext,
+    matches: &MononokeMatches<'a>,
+    config: &RepoConfig,
+) -> Result<RepoReadWriteFetcher> {
+    RepoReadWriteFetcher::from_repo_config(
+        ctx.fb,
+        config,
+        &args::parse_mysql_options(matches),
+        args::parse_readonly_storage(matches).0,
+    )
+    .await
+}
+
+/// Refuse to run a subcommand that writes to a repo that is locked, the same way the server
+/// refuses pushes to it.
+async fn check_repo_writable<'a>(
+    ctx: &CoreContext,
+    config_store: &ConfigStore,
+    matches: &MononokeMatches<'a>,
+) -> Result<()> {
+    let repo_id = match written_repo_id(config_store, matches)? {
+        Some(repo_id) => repo_id,
+        None => return Ok(()),
+    };
+    let (repo_name, config) = args::get_config_by_repoid(config_store, matches, repo_id)?;
+    let fetcher = open_readonly_fetcher(ctx, matches, &config).await?;
+    fetcher
+        .check_writable()
+        .await
+        .with_context(|| format!("Cannot write to {}", repo_name))?;
+    Ok(())
+}
+
 #[fbinit::main]
 fn main(fb: FacebookInit) -> Result<()> {
     let app = setup_app();
@@ -1098,6 +1296,7 @@ fn main(fb: FacebookInit) -> Result<()> {
     let ctx = CoreContext::new_with_logger(fb, logger.clone());
 
     let subcommand_future = async {
+        check_repo_writable(&ctx, config_store, &matches).await?;
         match matches.subcommand() {
             (BACKFILL_NOOP_MAPPING, Some(sub_m)) => {
                 run_backfill_noop_mapping(ctx, &matches, sub_m).await
@@ -1114,11 +1313,13 @@ fn main(fb: FacebookInit) -> Result<()> {
                 run_mark_not_synced(ctx, &matches, sub_m).await
             }
             (MERGE, Some(sub_m)) => run_merge(ctx, &matches, sub_m).await,
+            (MERGE_BLAST_RADIUS, Some(sub_m)) => run_merge_blast_radius(ctx, &matches, sub_m).await,
             (MOVE, Some(sub_m)) => {
                 let repo_config = get_and_verify_repo_config(config_store, &matches)?;
                 run_move(ctx, &matches, sub_m, repo_config).await
             }
             (RUN_MOVER, Some(sub_m)) => run_mover(ctx, &matches, sub_m).await,
+            (SYNC_CHECK, Some(sub_m)) => run_sync_check(ctx, &matches, sub_m).await,
             (SYNC_COMMIT_AND_ANCESTORS, Some(sub_m)) => {
                 run_sync_commit_and_ancestors(ctx, &matches, sub_m).await
             }
@@ -22,6 +22,7 @@ use std::collections::HashSet;
 use std::iter::FromIterator;
 
 use megarepolib::common::{create_save_and_generate_hg_changeset, ChangesetArgs};
+use megarepolib::tool_identity::MegarepoOperation;
 
 fn get_all_files_in_working_copy(
     ctx: CoreContext,
@@ -118,6 +119,7 @@ pub fn perform_merge(
                     vec![first_bcs_id, second_bcs_id],
                     Default::default(),
                     resulting_changeset_args,
+                    MegarepoOperation::Merge,
                 )
                 .await
             }
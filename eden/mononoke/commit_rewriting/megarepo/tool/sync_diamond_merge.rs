@@ -32,6 +32,7 @@ use futures_old::{Future, IntoFuture, Stream};
 use live_commit_sync_config::LiveCommitSyncConfig;
 use manifest::{bonsai_diff, BonsaiDiffFileChange};
 use maplit::hashmap;
+use megarepolib::tool_identity::{freeze_with_tool_identity, MegarepoOperation};
 use mercurial_types::{HgFileNodeId, HgManifestId};
 use metaconfig_types::{CommitSyncConfigVersion, RepoConfig};
 use mononoke_types::{BonsaiChangeset, ChangesetId, FileChange, MPath};
@@ -273,7 +274,7 @@ async fn create_rewritten_merge_commit(
         additional_file_changes.insert(path, fc);
     }
     rewritten.file_changes = additional_file_changes;
-    rewritten.freeze()
+    freeze_with_tool_identity(rewritten, MegarepoOperation::DiamondMerge)
 }
 
 /// This function finds all the changed file between root and onto that are from another small repo.
@@ -28,3 +28,16 @@ CREATE TABLE `synced_working_copy_equivalence` (
  -- Small bcs id can map to multiple large bcs ids
  CREATE INDEX small_bcs_key ON synced_working_copy_equivalence
   (`large_repo_id`,`small_repo_id`,`small_bcs_id`);
+
+CREATE TABLE `synced_commit_mapping_quarantine` (
+  `quarantine_id` INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  `small_repo_id` int(11) NOT NULL,
+  `small_bcs_id` binary(32) NOT NULL,
+  `large_repo_id` int(11) NOT NULL,
+  `large_bcs_id` binary(32) NOT NULL,
+  `sync_map_version_name` varchar(255),
+  `existing_small_bcs_id` binary(32) NOT NULL,
+  `existing_sync_map_version_name` varchar(255),
+  `quarantined_at` bigint(20) NOT NULL,
+  UNIQUE (`large_repo_id`,`small_repo_id`,`large_bcs_id`,`small_bcs_id`)
+);
@@ -21,7 +21,7 @@ use futures::future::{FutureExt, TryFutureExt};
 use futures_ext::{BoxFuture, FutureExt as _};
 use futures_old::Future as Future01;
 use metaconfig_types::CommitSyncConfigVersion;
-use mononoke_types::{ChangesetId, RepositoryId};
+use mononoke_types::{ChangesetId, RepositoryId, Timestamp};
 use sql::queries;
 use stats::prelude::*;
 use thiserror::Error;
@@ -37,6 +37,14 @@ pub enum ErrorKind {
         actual_bcs_id: Option<ChangesetId>,
         actual_config_version: Option<CommitSyncConfigVersion>,
     },
+    #[error(
+        "can't map {large_bcs_id} to {small_bcs_id}, as {small_bcs_id} is already mapped to {existing_large_bcs_id}"
+    )]
+    SmallCommitAlreadyMapped {
+        large_bcs_id: ChangesetId,
+        small_bcs_id: ChangesetId,
+        existing_large_bcs_id: ChangesetId,
+    },
 }
 
 // TODO(simonfar): Once we've proven the concept, we want to cache these
@@ -49,6 +57,7 @@ define_stats! {
     add_bulks: timeseries(Rate, Sum),
     insert_working_copy_eqivalence: timeseries(Rate, Sum),
     get_equivalent_working_copy: timeseries(Rate, Sum),
+    conflicts_quarantined: timeseries(Rate, Sum),
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
@@ -105,6 +114,26 @@ pub struct EquivalentWorkingCopyEntry {
     pub version_name: Option<CommitSyncConfigVersion>,
 }
 
+/// A mapping that couldn't be added because a different mapping was already stored for the same
+/// large commit, e.g. because the forward syncer and the backsyncer raced on it. It's kept aside
+/// until someone decides which of the two is right.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QuarantinedMapping {
+    pub id: u64,
+    pub entry: SyncedCommitMappingEntry,
+    pub existing_small_bcs_id: ChangesetId,
+    pub existing_version_name: Option<CommitSyncConfigVersion>,
+    pub quarantined_at: Timestamp,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QuarantineResolution {
+    /// The mapping that was already stored is right: drop the quarantined one.
+    KeepExisting,
+    /// The quarantined mapping is right: it replaces the one that was stored.
+    ReplaceExisting,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum WorkingCopyEquivalence {
     /// There's no matching working copy. It can happen if a pre-big-merge commit from one small
@@ -116,7 +145,8 @@ pub enum WorkingCopyEquivalence {
 
 pub trait SyncedCommitMapping: Send + Sync {
     /// Given the full large, small mapping, store it in the DB.
-    /// Future resolves to true if the mapping was saved, false otherwise
+    /// Future resolves to true if the mapping was saved, false otherwise. If it wasn't saved
+    /// because a different mapping is stored for the same large commit, it is quarantined.
     fn add(&self, ctx: CoreContext, entry: SyncedCommitMappingEntry) -> BoxFuture<bool, Error>;
 
     /// Bulk insert a set of large, small mappings
@@ -157,6 +187,24 @@ pub trait SyncedCommitMapping: Send + Sync {
         source_bcs_id: ChangesetId,
         target_repo_id: RepositoryId,
     ) -> BoxFuture<Option<WorkingCopyEquivalence>, Error>;
+
+    /// The most recently quarantined mappings between two repos
+    fn get_quarantined(
+        &self,
+        ctx: CoreContext,
+        large_repo_id: RepositoryId,
+        small_repo_id: RepositoryId,
+        limit: u64,
+    ) -> BoxFuture<Vec<QuarantinedMapping>, Error>;
+
+    /// Resolve a quarantined mapping, and remove it from the quarantine.
+    /// Future resolves to false if there is no quarantined mapping with this id.
+    fn resolve_quarantined(
+        &self,
+        ctx: CoreContext,
+        id: u64,
+        resolution: QuarantineResolution,
+    ) -> BoxFuture<bool, Error>;
 }
 
 impl SyncedCommitMapping for Arc<dyn SyncedCommitMapping> {
@@ -199,6 +247,25 @@ impl SyncedCommitMapping for Arc<dyn SyncedCommitMapping> {
     ) -> BoxFuture<Option<WorkingCopyEquivalence>, Error> {
         (**self).get_equivalent_working_copy(ctx, source_repo_id, source_bcs_id, target_repo_id)
     }
+
+    fn get_quarantined(
+        &self,
+        ctx: CoreContext,
+        large_repo_id: RepositoryId,
+        small_repo_id: RepositoryId,
+        limit: u64,
+    ) -> BoxFuture<Vec<QuarantinedMapping>, Error> {
+        (**self).get_quarantined(ctx, large_repo_id, small_repo_id, limit)
+    }
+
+    fn resolve_quarantined(
+        &self,
+        ctx: CoreContext,
+        id: u64,
+        resolution: QuarantineResolution,
+    ) -> BoxFuture<bool, Error> {
+        (**self).resolve_quarantined(ctx, id, resolution)
+    }
 }
 
 #[derive(Clone)]
@@ -258,6 +325,116 @@ queries! {
           LIMIT 1
           "
     }
+
+    write InsertQuarantinedMapping(values: (
+        large_repo_id: RepositoryId,
+        large_bcs_id: ChangesetId,
+        small_repo_id: RepositoryId,
+        small_bcs_id: ChangesetId,
+        sync_map_version_name: Option<CommitSyncConfigVersion>,
+        existing_small_bcs_id: ChangesetId,
+        existing_sync_map_version_name: Option<CommitSyncConfigVersion>,
+        quarantined_at: Timestamp,
+    )) {
+        insert_or_ignore,
+        "{insert_or_ignore}
+         INTO synced_commit_mapping_quarantine
+         (large_repo_id, large_bcs_id, small_repo_id, small_bcs_id, sync_map_version_name,
+          existing_small_bcs_id, existing_sync_map_version_name, quarantined_at)
+         VALUES {values}"
+    }
+
+    read SelectQuarantinedMappings(
+        large_repo_id: RepositoryId,
+        small_repo_id: RepositoryId,
+        limit: u64,
+    ) -> (u64, RepositoryId, ChangesetId, RepositoryId, ChangesetId, Option<CommitSyncConfigVersion>, ChangesetId, Option<CommitSyncConfigVersion>, Timestamp) {
+        "SELECT quarantine_id, large_repo_id, large_bcs_id, small_repo_id, small_bcs_id, sync_map_version_name,
+          existing_small_bcs_id, existing_sync_map_version_name, quarantined_at
+          FROM synced_commit_mapping_quarantine
+          WHERE large_repo_id = {large_repo_id} AND small_repo_id = {small_repo_id}
+          ORDER BY quarantine_id DESC
+          LIMIT {limit}"
+    }
+
+    read SelectQuarantinedMappingById(
+        id: u64,
+    ) -> (u64, RepositoryId, ChangesetId, RepositoryId, ChangesetId, Option<CommitSyncConfigVersion>, ChangesetId, Option<CommitSyncConfigVersion>, Timestamp) {
+        "SELECT quarantine_id, large_repo_id, large_bcs_id, small_repo_id, small_bcs_id, sync_map_version_name,
+          existing_small_bcs_id, existing_sync_map_version_name, quarantined_at
+          FROM synced_commit_mapping_quarantine
+          WHERE quarantine_id = {id}"
+    }
+
+    write DeleteQuarantinedMapping(id: u64) {
+        none,
+        "DELETE FROM synced_commit_mapping_quarantine WHERE quarantine_id = {id}"
+    }
+
+    write ReplaceMapping(
+        large_repo_id: RepositoryId,
+        large_bcs_id: ChangesetId,
+        small_repo_id: RepositoryId,
+        small_bcs_id: ChangesetId,
+        sync_map_version_name: Option<CommitSyncConfigVersion>,
+    ) {
+        none,
+        "UPDATE synced_commit_mapping
+         SET small_bcs_id = {small_bcs_id}, sync_map_version_name = {sync_map_version_name}
+         WHERE large_repo_id = {large_repo_id} AND small_repo_id = {small_repo_id} AND large_bcs_id = {large_bcs_id}"
+    }
+
+    write ReplaceWorkingCopyEquivalence(
+        large_repo_id: RepositoryId,
+        large_bcs_id: ChangesetId,
+        small_repo_id: RepositoryId,
+        small_bcs_id: ChangesetId,
+        sync_map_version_name: Option<CommitSyncConfigVersion>,
+    ) {
+        none,
+        "UPDATE synced_working_copy_equivalence
+         SET small_bcs_id = {small_bcs_id}, sync_map_version_name = {sync_map_version_name}
+         WHERE large_repo_id = {large_repo_id} AND small_repo_id = {small_repo_id} AND large_bcs_id = {large_bcs_id}"
+    }
+}
+
+type QuarantineRow = (
+    u64,
+    RepositoryId,
+    ChangesetId,
+    RepositoryId,
+    ChangesetId,
+    Option<CommitSyncConfigVersion>,
+    ChangesetId,
+    Option<CommitSyncConfigVersion>,
+    Timestamp,
+);
+
+fn quarantined_mapping_from_row(row: QuarantineRow) -> QuarantinedMapping {
+    let (
+        id,
+        large_repo_id,
+        large_bcs_id,
+        small_repo_id,
+        small_bcs_id,
+        version_name,
+        existing_small_bcs_id,
+        existing_version_name,
+        quarantined_at,
+    ) = row;
+    QuarantinedMapping {
+        id,
+        entry: SyncedCommitMappingEntry {
+            large_repo_id,
+            large_bcs_id,
+            small_repo_id,
+            small_bcs_id,
+            version_name,
+        },
+        existing_small_bcs_id,
+        existing_version_name,
+        quarantined_at,
+    }
 }
 
 impl SqlConstruct for SqlSyncedCommitMapping {
@@ -298,7 +475,14 @@ impl SyncedCommitMapping for SqlSyncedCommitMapping {
     fn add(&self, _ctx: CoreContext, entry: SyncedCommitMappingEntry) -> BoxFuture<bool, Error> {
         STATS::adds.add_value(1);
 
-        self.add_many(vec![entry]).map(|count| count == 1).boxify()
+        let this = self.clone();
+        async move {
+            let count = this.add_many(vec![entry]).compat().await?;
+            Ok(count == 1)
+        }
+        .boxed()
+        .compat()
+        .boxify()
     }
 
     fn add_bulk(
@@ -308,7 +492,11 @@ impl SyncedCommitMapping for SqlSyncedCommitMapping {
     ) -> BoxFuture<u64, Error> {
         STATS::add_bulks.add_value(1);
 
-        self.add_many(entries).boxify()
+        let this = self.clone();
+        async move { this.add_many(entries).compat().await }
+            .boxed()
+            .compat()
+            .boxify()
     }
 
     fn get(
@@ -490,8 +678,112 @@ impl SyncedCommitMapping for SqlSyncedCommitMapping {
         .compat()
         .boxify()
     }
+
+    fn get_quarantined(
+        &self,
+        _ctx: CoreContext,
+        large_repo_id: RepositoryId,
+        small_repo_id: RepositoryId,
+        limit: u64,
+    ) -> BoxFuture<Vec<QuarantinedMapping>, Error> {
+        cloned!(self.read_master_connection);
+        async move {
+            let rows = SelectQuarantinedMappings::query(
+                &read_master_connection,
+                &large_repo_id,
+                &small_repo_id,
+                &limit,
+            )
+            .await?;
+            Ok(rows.into_iter().map(quarantined_mapping_from_row).collect())
+        }
+        .boxed()
+        .compat()
+        .boxify()
+    }
+
+    fn resolve_quarantined(
+        &self,
+        _ctx: CoreContext,
+        id: u64,
+        resolution: QuarantineResolution,
+    ) -> BoxFuture<bool, Error> {
+        cloned!(self.write_connection, self.read_master_connection);
+        async move {
+            let rows = SelectQuarantinedMappingById::query(&read_master_connection, &id).await?;
+            let quarantined = match rows.into_iter().next() {
+                Some(row) => quarantined_mapping_from_row(row),
+                None => return Ok(false),
+            };
+
+            let txn = write_connection.start_transaction().await?;
+            let (txn, result) = DeleteQuarantinedMapping::query_with_transaction(txn, &id).await?;
+            if result.affected_rows() != 1 {
+                // Resolved concurrently.
+                txn.rollback().await?;
+                return Ok(false);
+            }
+
+            let txn = match resolution {
+                QuarantineResolution::KeepExisting => txn,
+                QuarantineResolution::ReplaceExisting => {
+                    let entry = &quarantined.entry;
+                    // The small commit may be mapped to another large commit already, and a
+                    // small commit can't be mapped twice.
+                    let (txn, rows) = SelectMapping::query_with_transaction(
+                        txn,
+                        &entry.small_repo_id,
+                        &entry.small_bcs_id,
+                        &entry.large_repo_id,
+                    )
+                    .await?;
+                    let existing_large_bcs_id = rows
+                        .into_iter()
+                        .filter(|row| row.2 == entry.small_repo_id && row.3 == entry.small_bcs_id)
+                        .map(|row| row.1)
+                        .find(|large_bcs_id| *large_bcs_id != entry.large_bcs_id);
+                    if let Some(existing_large_bcs_id) = existing_large_bcs_id {
+                        txn.rollback().await?;
+                        return Err(ErrorKind::SmallCommitAlreadyMapped {
+                            large_bcs_id: entry.large_bcs_id,
+                            small_bcs_id: entry.small_bcs_id,
+                            existing_large_bcs_id,
+                        }
+                        .into());
+                    }
+
+                    let (txn, _) = ReplaceMapping::query_with_transaction(
+                        txn,
+                        &entry.large_repo_id,
+                        &entry.large_bcs_id,
+                        &entry.small_repo_id,
+                        &entry.small_bcs_id,
+                        &entry.version_name,
+                    )
+                    .await?;
+                    let (txn, _) = ReplaceWorkingCopyEquivalence::query_with_transaction(
+                        txn,
+                        &entry.large_repo_id,
+                        &entry.large_bcs_id,
+                        &entry.small_repo_id,
+                        &entry.small_bcs_id,
+                        &entry.version_name,
+                    )
+                    .await?;
+                    txn
+                }
+            };
+            txn.commit().await?;
+            Ok(true)
+        }
+        .boxed()
+        .compat()
+        .boxify()
+    }
 }
 
+/// Add the mappings in `txn`. The ones that weren't added because a different mapping is stored
+/// for their large commit are quarantined.
 pub async fn add_many_in_txn(
     txn: Transaction,
     entries: Vec<SyncedCommitMappingEntry>,
@@ -511,7 +803,12 @@ pub async fn add_many_in_txn(
         })
         .collect();
 
-    let (txn, _result) = InsertMapping::query_with_transaction(txn, &insert_entries).await?;
+    let (txn, result) = InsertMapping::query_with_transaction(txn, &insert_entries).await?;
+    let txn = if result.affected_rows() < entries.len() as u64 {
+        quarantine_conflicts_in_txn(txn, &entries).await?
+    } else {
+        txn
+    };
     let owned_entries: Vec<_> = entries
         .into_iter()
         .map(|entry| entry.into_equivalent_working_copy_entry())
@@ -534,3 +831,50 @@ pub async fn add_many_in_txn(
         InsertWorkingCopyEquivalence::query_with_transaction(txn, &ref_entries).await?;
     Ok((txn, result.affected_rows()))
 }
+
+async fn quarantine_conflicts_in_txn(
+    mut txn: Transaction,
+    entries: &[SyncedCommitMappingEntry],
+) -> Result<Transaction, Error> {
+    for entry in entries {
+        let (txn_, rows) = SelectMapping::query_with_transaction(
+            txn,
+            &entry.large_repo_id,
+            &entry.large_bcs_id,
+            &entry.small_repo_id,
+        )
+        .await?;
+        txn = txn_;
+
+        let existing = rows
+            .into_iter()
+            .find(|row| row.0 == entry.large_repo_id && row.1 == entry.large_bcs_id);
+        let (existing_small_bcs_id, existing_version_name) = match existing {
+            Some((_, _, _, small_bcs_id, version_name)) => (small_bcs_id, version_name),
+            None => continue,
+        };
+        if existing_small_bcs_id == entry.small_bcs_id
+            && existing_version_name == entry.version_name
+        {
+            continue;
+        }
+
+        STATS::conflicts_quarantined.add_value(1);
+        let (txn_, _) = InsertQuarantinedMapping::query_with_transaction(
+            txn,
+            &[(
+                &entry.large_repo_id,
+                &entry.large_bcs_id,
+                &entry.small_repo_id,
+                &entry.small_bcs_id,
+                &entry.version_name,
+                &existing_small_bcs_id,
+                &existing_version_name,
+                &Timestamp::now(),
+            )],
+        )
+        .await?;
+        txn = txn_;
+    }
+    Ok(txn)
+}
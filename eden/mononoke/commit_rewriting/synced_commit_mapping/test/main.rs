@@ -18,7 +18,7 @@ use mononoke_types_mocks::changesetid as bonsai;
 use mononoke_types_mocks::repo::{REPO_ONE, REPO_ZERO};
 use sql_construct::SqlConstruct;
 use synced_commit_mapping::{
-    EquivalentWorkingCopyEntry, SqlSyncedCommitMapping, SyncedCommitMapping,
+    EquivalentWorkingCopyEntry, QuarantineResolution, SqlSyncedCommitMapping, SyncedCommitMapping,
     SyncedCommitMappingEntry, WorkingCopyEquivalence,
 };
 
@@ -201,13 +201,172 @@ async fn equivalent_working_copy<M: SyncedCommitMapping>(fb: FacebookInit, mappi
         small_bcs_id: Some(bonsai::TWOS_CSID),
         version_name: None,
     };
-    assert!(
+    assert!(mapping
+        .insert_equivalent_working_copy(ctx.clone(), should_fail)
+        .compat()
+        .await
+        .is_err());
+}
+
+async fn quarantine<M: SyncedCommitMapping>(fb: FacebookInit, mapping: M) {
+    let version_name = CommitSyncConfigVersion("TEST_VERSION_NAME".to_string());
+    let ctx = CoreContext::test_mock(fb);
+    let entry = SyncedCommitMappingEntry::new(
+        REPO_ZERO,
+        bonsai::ONES_CSID,
+        REPO_ONE,
+        bonsai::TWOS_CSID,
+        version_name.clone(),
+    );
+    let conflicting = SyncedCommitMappingEntry::new(
+        REPO_ZERO,
+        bonsai::ONES_CSID,
+        REPO_ONE,
+        bonsai::THREES_CSID,
+        version_name.clone(),
+    );
+    for (entry, expected) in vec![(entry.clone(), true), (conflicting.clone(), false)] {
+        assert_eq!(
+            expected,
+            mapping
+                .add(ctx.clone(), entry)
+                .compat()
+                .await
+                .expect("Adding entry failed")
+        );
+    }
+
+    let quarantined = mapping
+        .get_quarantined(ctx.clone(), REPO_ZERO, REPO_ONE, 10)
+        .compat()
+        .await
+        .expect("Getting quarantined mappings failed");
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].entry, conflicting);
+    assert_eq!(quarantined[0].existing_small_bcs_id, bonsai::TWOS_CSID);
+
+    // Adding the same conflicting entry again doesn't quarantine it twice, and adding the entry
+    // that is stored isn't a conflict.
+    for entry in vec![conflicting, entry] {
         mapping
-            .insert_equivalent_working_copy(ctx.clone(), should_fail)
+            .add(ctx.clone(), entry)
             .compat()
             .await
-            .is_err()
+            .expect("Adding entry failed");
+    }
+    let quarantined = mapping
+        .get_quarantined(ctx.clone(), REPO_ZERO, REPO_ONE, 10)
+        .compat()
+        .await
+        .expect("Getting quarantined mappings failed");
+    assert_eq!(quarantined.len(), 1);
+
+    let id = quarantined[0].id;
+    for expected in vec![true, false] {
+        assert_eq!(
+            expected,
+            mapping
+                .resolve_quarantined(ctx.clone(), id, QuarantineResolution::ReplaceExisting)
+                .compat()
+                .await
+                .expect("Resolving quarantined mapping failed")
+        );
+    }
+
+    let res = mapping
+        .get(ctx.clone(), REPO_ZERO, bonsai::ONES_CSID, REPO_ONE)
+        .compat()
+        .await
+        .expect("Get failed");
+    assert_eq!(res, vec![(bonsai::THREES_CSID, Some(version_name.clone()))]);
+    let res = mapping
+        .get_equivalent_working_copy(ctx.clone(), REPO_ZERO, bonsai::ONES_CSID, REPO_ONE)
+        .compat()
+        .await
+        .expect("get equivalent wc failed, should succeed");
+    assert_eq!(
+        res,
+        Some(WorkingCopyEquivalence::WorkingCopy(
+            bonsai::THREES_CSID,
+            Some(version_name),
+        ))
     );
+    let quarantined = mapping
+        .get_quarantined(ctx.clone(), REPO_ZERO, REPO_ONE, 10)
+        .compat()
+        .await
+        .expect("Getting quarantined mappings failed");
+    assert!(quarantined.is_empty());
+}
+
+async fn quarantine_replace_conflict<M: SyncedCommitMapping>(fb: FacebookInit, mapping: M) {
+    let version_name = CommitSyncConfigVersion("TEST_VERSION_NAME".to_string());
+    let ctx = CoreContext::test_mock(fb);
+    let entries = vec![
+        SyncedCommitMappingEntry::new(
+            REPO_ZERO,
+            bonsai::ONES_CSID,
+            REPO_ONE,
+            bonsai::TWOS_CSID,
+            version_name.clone(),
+        ),
+        SyncedCommitMappingEntry::new(
+            REPO_ZERO,
+            bonsai::FOURS_CSID,
+            REPO_ONE,
+            bonsai::THREES_CSID,
+            version_name.clone(),
+        ),
+    ];
+    mapping
+        .add_bulk(ctx.clone(), entries)
+        .compat()
+        .await
+        .expect("Adding entries failed");
+
+    // Bulk adds quarantine their conflicts too.
+    let conflicting = SyncedCommitMappingEntry::new(
+        REPO_ZERO,
+        bonsai::ONES_CSID,
+        REPO_ONE,
+        bonsai::THREES_CSID,
+        version_name.clone(),
+    );
+    mapping
+        .add_bulk(ctx.clone(), vec![conflicting.clone()])
+        .compat()
+        .await
+        .expect("Adding entries failed");
+    let quarantined = mapping
+        .get_quarantined(ctx.clone(), REPO_ZERO, REPO_ONE, 10)
+        .compat()
+        .await
+        .expect("Getting quarantined mappings failed");
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].entry, conflicting);
+
+    // THREES is mapped to FOURS already, so it can't replace the mapping of ONES.
+    assert!(mapping
+        .resolve_quarantined(
+            ctx.clone(),
+            quarantined[0].id,
+            QuarantineResolution::ReplaceExisting
+        )
+        .compat()
+        .await
+        .is_err());
+    let res = mapping
+        .get(ctx.clone(), REPO_ZERO, bonsai::ONES_CSID, REPO_ONE)
+        .compat()
+        .await
+        .expect("Get failed");
+    assert_eq!(res, vec![(bonsai::TWOS_CSID, Some(version_name))]);
+    let quarantined = mapping
+        .get_quarantined(ctx.clone(), REPO_ZERO, REPO_ONE, 10)
+        .compat()
+        .await
+        .expect("Getting quarantined mappings failed");
+    assert_eq!(quarantined.len(), 1);
 }
 
 #[fbinit::test]
@@ -230,3 +389,18 @@ fn test_equivalent_working_copy(fb: FacebookInit) {
         equivalent_working_copy(fb, SqlSyncedCommitMapping::with_sqlite_in_memory().unwrap()).await
     });
 }
+
+#[fbinit::test]
+fn test_quarantine(fb: FacebookInit) {
+    async_unit::tokio_unit_test(async move {
+        quarantine(fb, SqlSyncedCommitMapping::with_sqlite_in_memory().unwrap()).await
+    });
+}
+
+#[fbinit::test]
+fn test_quarantine_replace_conflict(fb: FacebookInit) {
+    async_unit::tokio_unit_test(async move {
+        quarantine_replace_conflict(fb, SqlSyncedCommitMapping::with_sqlite_in_memory().unwrap())
+            .await
+    });
+}
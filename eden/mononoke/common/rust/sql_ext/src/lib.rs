@@ -8,10 +8,12 @@
 #[cfg(not(fbcode_build))]
 mod oss;
 pub mod replication;
+mod slow_query;
 mod sqlite;
 
 use sql::{Connection, Transaction};
 
+pub use slow_query::QueryAttribution;
 pub use sqlite::{open_existing_sqlite_path, open_sqlite_in_memory, open_sqlite_path};
 
 #[derive(Clone)]
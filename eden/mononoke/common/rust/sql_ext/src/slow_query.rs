@@ -0,0 +1,188 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Logging of slow SQL statements, attributed to the repo, session and server feature that ran
+//! them. Statements are timed by the connections themselves, so every query is covered; what they
+//! are attributed to is whatever `QueryAttribution` is entered on the thread running them.
+
+use std::cell::RefCell;
+use std::convert::TryInto;
+use std::sync::Arc;
+use std::time::Duration;
+
+use context::{CoreContext, PerfCounterType};
+use slog::warn;
+use stats::prelude::*;
+
+define_stats! {
+    prefix = "mononoke.sql";
+    slow_queries: dynamic_timeseries("{}.slow_queries", (caller: &'static str); Rate, Sum),
+}
+
+const UNKNOWN_CALLER: &str = "unknown";
+
+// Statements can be long, and the start is enough to tell which query it is.
+const MAX_LOGGED_STATEMENT_LEN: usize = 200;
+
+thread_local! {
+    static CURRENT: RefCell<Option<QueryAttribution>> = RefCell::new(None);
+}
+
+/// What the SQL statements run while it is entered are attributed to.
+#[derive(Clone)]
+pub struct QueryAttribution(Arc<(CoreContext, String)>);
+
+impl QueryAttribution {
+    pub fn new(ctx: CoreContext, repo: impl Into<String>) -> Self {
+        Self(Arc::new((ctx, repo.into())))
+    }
+
+    /// Run `f`, attributing the statements that it runs to this. This is meant to be called
+    /// around each poll of the futures that serve a request.
+    pub fn enter<T>(&self, f: impl FnOnce() -> T) -> T {
+        struct Restore(Option<QueryAttribution>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+            }
+        }
+
+        let _restore = Restore(CURRENT.with(|current| current.replace(Some(self.clone()))));
+        f()
+    }
+}
+
+fn should_log_slow_query(duration: Duration) -> bool {
+    let threshold = tunables::tunables().get_sql_slow_query_threshold_ms();
+    let threshold = match threshold.try_into() {
+        Ok(t) if t > 0 => t,
+        _ => return false,
+    };
+    duration > Duration::from_millis(threshold)
+}
+
+fn truncate_statement(statement: &str) -> &str {
+    match statement.char_indices().nth(MAX_LOGGED_STATEMENT_LEN) {
+        Some((end, _)) => &statement[..end],
+        None => statement,
+    }
+}
+
+/// Called by the connections with each statement they ran, and how long it took. Statements
+/// that take longer than the `sql_slow_query_threshold_ms` tunable are logged with the session
+/// id and the caller tag of the current attribution, so slow queries can be traced back to the
+/// feature issuing them.
+pub(crate) fn log_slow_statement(statement: &str, duration: Duration) {
+    if !should_log_slow_query(duration) {
+        return;
+    }
+
+    CURRENT.with(|current| {
+        let current = current.borrow();
+        let (ctx, repo) = match current.as_ref() {
+            Some(attribution) => &*attribution.0,
+            None => {
+                STATS::slow_queries.add_value(1, (UNKNOWN_CALLER,));
+                return;
+            }
+        };
+        let statement = truncate_statement(statement);
+        let session_id = ctx.metadata().session_id();
+        let caller = ctx.caller_tag().unwrap_or(UNKNOWN_CALLER);
+
+        warn!(
+            ctx.logger(),
+            "slow SQL query for repo {} took {:.2?} (session {}, caller {}): {}",
+            repo,
+            duration,
+            session_id,
+            caller,
+            statement,
+        );
+        STATS::slow_queries.add_value(1, (caller,));
+        ctx.perf_counters()
+            .increment_counter(PerfCounterType::SqlSlowQueries);
+        ctx.scuba()
+            .clone()
+            .add("sql_query", statement)
+            .add("sql_query_duration_us", duration.as_micros() as u64)
+            .add("repo", repo.as_str())
+            .add("session_uuid", session_id.to_string())
+            .add("caller_tag", caller)
+            .log_with_msg("Slow SQL query", None);
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::open_sqlite_in_memory;
+    use fbinit::FacebookInit;
+    use maplit::hashmap;
+    use tunables::{with_tunables, MononokeTunables};
+
+    #[test]
+    fn test_should_log_slow_query() {
+        let d10 = Duration::from_millis(10);
+        let d20 = Duration::from_millis(20);
+
+        with_tunables(MononokeTunables::default(), || {
+            assert!(!should_log_slow_query(d10));
+            assert!(!should_log_slow_query(d20));
+        });
+
+        let tunables = MononokeTunables::default();
+        tunables.update_ints(&hashmap! {"sql_slow_query_threshold_ms".into() => -1});
+        with_tunables(tunables, || {
+            assert!(!should_log_slow_query(d20));
+        });
+
+        let tunables = MononokeTunables::default();
+        tunables.update_ints(&hashmap! {"sql_slow_query_threshold_ms".into() => 15});
+        with_tunables(tunables, || {
+            assert!(!should_log_slow_query(d10));
+            assert!(should_log_slow_query(d20));
+        });
+    }
+
+    #[fbinit::test]
+    fn test_slow_statement_attributed(fb: FacebookInit) {
+        let ctx = CoreContext::test_mock(fb).with_caller_tag("test");
+        let con = open_sqlite_in_memory().unwrap();
+        let run_slow_query = || {
+            let _: i64 = con
+                .query_row(
+                    "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c WHERE x < 1000000)
+                     SELECT count(*) FROM c",
+                    sql::rusqlite::NO_PARAMS,
+                    |row| row.get(0),
+                )
+                .unwrap();
+        };
+
+        let tunables = MononokeTunables::default();
+        tunables.update_ints(&hashmap! {"sql_slow_query_threshold_ms".into() => 1});
+        with_tunables(tunables, || {
+            // Not attributed to anything.
+            run_slow_query();
+            assert_eq!(
+                ctx.perf_counters()
+                    .get_counter(PerfCounterType::SqlSlowQueries),
+                0
+            );
+
+            QueryAttribution::new(ctx.clone(), "repo").enter(run_slow_query);
+            assert_eq!(
+                ctx.perf_counters()
+                    .get_counter(PerfCounterType::SqlSlowQueries),
+                1
+            );
+        });
+    }
+}
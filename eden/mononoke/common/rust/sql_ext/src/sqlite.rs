@@ -9,7 +9,9 @@ use anyhow::Result;
 use sql::rusqlite::{Connection as SqliteConnection, OpenFlags as SqliteOpenFlags};
 use std::{fs::create_dir_all, path::Path, time::Duration};
 
-fn sqlite_setup_connection(con: &SqliteConnection) {
+use crate::slow_query::log_slow_statement;
+
+fn sqlite_setup_connection(con: &mut SqliteConnection) {
     // By default, when there's a read/write contention, SQLite will not wait,
     // but rather throw a `SQLITE_BUSY` error. See https://www.sqlite.org/lockingv3.html
     // This means that tests will fail in cases when production setup (e.g. one with MySQL)
@@ -19,12 +21,15 @@ fn sqlite_setup_connection(con: &SqliteConnection) {
     // By default, the `LIKE` operator is case-insensitive.  This doesn't
     // match MySQL, so change it to case-sensitive.
     let _ = con.pragma_update(None, "case_sensitive_like", &true);
+
+    // Time every statement, so that the slow ones are logged whichever query issued them.
+    con.profile(Some(log_slow_statement));
 }
 
 // Open a single sqlite connection to a new in memory database
 pub fn open_sqlite_in_memory() -> Result<SqliteConnection> {
-    let con = SqliteConnection::open_in_memory()?;
-    sqlite_setup_connection(&con);
+    let mut con = SqliteConnection::open_in_memory()?;
+    sqlite_setup_connection(&mut con);
     Ok(con)
 }
 
@@ -44,14 +49,14 @@ pub fn open_sqlite_path<P: AsRef<Path>>(path: P, readonly: bool) -> Result<Sqlit
         SqliteConnection::open_with_flags(&path, flags)?
     };
 
-    let con = if readonly {
+    let mut con = if readonly {
         let flags = SqliteOpenFlags::SQLITE_OPEN_READ_ONLY;
         SqliteConnection::open_with_flags(path, flags)?
     } else {
         con
     };
 
-    sqlite_setup_connection(&con);
+    sqlite_setup_connection(&mut con);
     Ok(con)
 }
 
@@ -67,7 +72,7 @@ pub fn open_existing_sqlite_path<P: AsRef<Path>>(
         // NB no creation flag, as db should already exist at this point
         SqliteOpenFlags::SQLITE_OPEN_READ_WRITE
     };
-    let con = SqliteConnection::open_with_flags(path, flags)?;
-    sqlite_setup_connection(&con);
+    let mut con = SqliteConnection::open_with_flags(path, flags)?;
+    sqlite_setup_connection(&mut con);
     Ok(con)
 }
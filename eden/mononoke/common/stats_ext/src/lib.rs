@@ -0,0 +1,43 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Naming of the counters that are broken down by repo.
+//!
+//! Host-level counters add up the traffic of all the repos a host serves, so a regression in one
+//! repo is invisible in them. Counters about work done for a given repo are published per repo
+//! too, named `<prefix>.<repo>.<counter>`: they are declared in `define_stats!` as, e.g.,
+//! `dynamic_timeseries("{}.<counter>", (repo: String); ...)`, and get the `repo_key` of the repo
+//! as their first argument.
+
+#![deny(warnings)]
+
+/// The component that identifies a repo in the names of its counters. Repo names are sanitized
+/// so that they are always exactly one component, since ODS uses dots to structure names.
+pub fn repo_key(repo_name: &str) -> String {
+    repo_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_repo_key() {
+        assert_eq!(repo_key("fbsource"), "fbsource");
+        assert_eq!(repo_key("www-merge_test"), "www-merge_test");
+        assert_eq!(repo_key("ovrsource/v1.2 test"), "ovrsource_v1_2_test");
+    }
+}
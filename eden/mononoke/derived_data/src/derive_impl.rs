@@ -23,6 +23,7 @@ use scuba_ext::MononokeScubaSampleBuilder;
 use slog::debug;
 use slog::warn;
 use stats::prelude::*;
+use stats_ext::repo_key;
 use std::convert::TryInto;
 use std::{
     collections::HashSet,
@@ -36,8 +37,10 @@ define_stats! {
     prefix = "mononoke.derived_data";
     derived_data_latency:
         dynamic_timeseries("{}.deriving.latency_ms", (derived_data_type: &'static str); Average),
+    derived_data_repo_latency:
+        dynamic_timeseries("{}.{}.deriving.latency_ms", (repo: String, derived_data_type: &'static str); Average),
     derived_data_disabled:
-        dynamic_timeseries("{}.{}.derived_data_disabled", (repo_id: i32, derived_data_type: &'static str); Count),
+        dynamic_timeseries("{}.{}.derived_data_disabled", (repo: String, derived_data_type: &'static str); Count),
 }
 
 const LEASE_WARNING_THRESHOLD: Duration = Duration::from_secs(60);
@@ -53,7 +56,7 @@ pub fn enabled_type_config<'repo>(
     if config.enabled.types.contains(name) {
         Ok(&config.enabled)
     } else {
-        STATS::derived_data_disabled.add_value(1, (repo.get_repoid().id(), name));
+        STATS::derived_data_disabled.add_value(1, (repo_key(repo.name()), name));
         Err(DeriveError::Disabled(
             name,
             repo.get_repoid(),
@@ -372,6 +375,7 @@ where
                     let (stats, res) = deriver.timed().await;
                     log_derivation_end::<Derivable>(
                         &ctx,
+                        repo.name(),
                         &mut derived_data_scuba,
                         &bcs_id,
                         &stats,
@@ -467,6 +471,7 @@ fn log_derivation_start<Derivable>(
 
 fn log_derivation_end<Derivable>(
     ctx: &CoreContext,
+    repo_name: &str,
     derived_data_scuba: &mut MononokeScubaSampleBuilder,
     bcs_id: &ChangesetId,
     stats: &FutureStats,
@@ -500,10 +505,9 @@ fn log_derivation_end<Derivable>(
         // derived data name and bcs_id already logged as separate fields
         .log_with_msg(tag, msg);
 
-    STATS::derived_data_latency.add_value(
-        stats.completion_time.as_millis_unchecked() as i64,
-        (Derivable::NAME,),
-    );
+    let latency_ms = stats.completion_time.as_millis_unchecked() as i64;
+    STATS::derived_data_latency.add_value(latency_ms, (Derivable::NAME,));
+    STATS::derived_data_repo_latency.add_value(latency_ms, (repo_key(repo_name), Derivable::NAME));
 }
 
 #[derive(Clone, Copy)]
@@ -26,13 +26,14 @@ use derived_data_filenodes::{FilenodesOnlyPublic, FilenodesOnlyPublicMapping};
 use fastlog::{RootFastlog, RootFastlogMapping};
 use fsnodes::{RootFsnodeId, RootFsnodeMapping};
 use futures::{
-    future::{self, ready, try_join_all, BoxFuture, FutureExt},
+    future::{self, try_join_all, BoxFuture, FutureExt},
     stream::{self, futures_unordered::FuturesUnordered},
     Future, Stream, StreamExt, TryFutureExt, TryStreamExt,
 };
 use lazy_static::lazy_static;
 use lock_ext::LockExt;
 use mercurial_derived_data::{HgChangesetIdMapping, MappedHgChangesetId};
+use metaconfig_types::DerivedDataConfig;
 use mononoke_types::{BonsaiChangeset, ChangesetId};
 use scuba_ext::MononokeScubaSampleBuilder;
 use skeleton_manifest::{RootSkeletonManifestId, RootSkeletonManifestMapping};
@@ -95,37 +96,104 @@ lazy_static! {
     };
 }
 
+/// Group derived data types into levels, such that every type only depends on types from earlier
+/// levels. Dependencies of the requested types are added as needed. Types within a level are
+/// independent of each other and can be derived concurrently.
+pub fn derivation_levels(derived_data_types: &[String]) -> Result<Vec<Vec<&'static str>>, Error> {
+    let mut levels: HashMap<&'static str, usize> = HashMap::new();
+    let mut stack: Vec<&str> = derived_data_types.iter().map(|t| t.as_str()).collect();
+
+    while let Some(name) = stack.last().copied() {
+        let (&name, deps) = DERIVED_DATA_DEPS
+            .get_key_value(name)
+            .ok_or_else(|| anyhow!("unknown derived data type: {}", name))?;
+
+        if levels.contains_key(name) {
+            stack.pop();
+            continue;
+        }
+
+        let missing: Vec<_> = deps
+            .iter()
+            .filter(|dep| !levels.contains_key(*dep))
+            .collect();
+        if missing.is_empty() {
+            let level = deps.iter().map(|dep| levels[dep] + 1).max().unwrap_or(0);
+            levels.insert(name, level);
+            stack.pop();
+        } else {
+            // DERIVED_DATA_DEPS is acyclic (see DERIVED_DATA_ORDER), so this terminates.
+            stack.extend(missing.into_iter().copied());
+        }
+    }
+
+    let mut grouped = Vec::new();
+    for (name, level) in levels {
+        if grouped.len() <= level {
+            grouped.resize_with(level + 1, Vec::new);
+        }
+        grouped[level].push(name);
+    }
+    for level in grouped.iter_mut() {
+        level.sort_by_key(|name| DERIVED_DATA_ORDER.get(name));
+    }
+
+    Ok(grouped)
+}
+
+/// Derive data of several types for `csids`, which should be topologically sorted (ancestors
+/// first). All types are derived in a single pass over the changesets: for each changeset, types
+/// are derived level by level (see `derivation_levels`), so that e.g. blame never walks commits
+/// whose unodes are still being derived by another future.
+///
+/// The requested types must be enabled for the repo, but their dependencies are derived along
+/// with them even if they are not.
 pub fn derive_data_for_csids(
     ctx: &CoreContext,
     repo: &BlobRepo,
     csids: Vec<ChangesetId>,
     derived_data_types: &[String],
 ) -> Result<impl Future<Output = Result<(), Error>>, Error> {
-    let derivations = FuturesUnordered::new();
-
-    for data_type in derived_data_types {
-        let derived_utils = derived_data_utils(repo, data_type)?;
+    let levels = derivation_levels(derived_data_types)?;
 
-        let mut futs = vec![];
-        for csid in &csids {
-            let fut = derived_utils
-                .derive(ctx.clone(), repo.clone(), *csid)
-                .map_ok(|_| ());
-            futs.push(fut);
-        }
+    let enabled = &repo.get_derived_data_config().enabled.types;
+    let implicit: Vec<String> = levels
+        .iter()
+        .flatten()
+        .filter(|name| !enabled.contains(**name) && !derived_data_types.iter().any(|t| t == **name))
+        .map(|name| name.to_string())
+        .collect();
+    let repo = if implicit.is_empty() {
+        repo.clone()
+    } else {
+        repo.dangerous_override(|mut config: DerivedDataConfig| {
+            config.enabled.types.extend(implicit);
+            config
+        })
+    };
 
-        derivations.push(async move {
-            // Call functions sequentially because derived data is sequential
-            // so there's no point in trying to derive it in parallel
-            for f in futs {
-                f.await?;
-            }
-            Result::<_, Error>::Ok(())
-        });
-    }
+    let levels = levels
+        .into_iter()
+        .map(|level| {
+            level
+                .into_iter()
+                .map(|name| derived_data_utils(&repo, name))
+                .collect::<Result<Vec<_>, Error>>()
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
 
+    cloned!(ctx);
     Ok(async move {
-        derivations.try_for_each(|_| ready(Ok(()))).await?;
+        for csid in csids {
+            for level in levels.iter() {
+                try_join_all(
+                    level
+                        .iter()
+                        .map(|derived_utils| derived_utils.derive(ctx.clone(), repo.clone(), csid)),
+                )
+                .await?;
+            }
+        }
         Ok(())
     })
 }
@@ -1036,6 +1104,72 @@ mod tests {
         Ok::<_, Error>(())
     }
 
+    #[test]
+    fn test_derivation_levels() -> Result<(), Error> {
+        let mut levels = derivation_levels(&["blame".to_string(), "filenodes".to_string()])?;
+        for level in levels.iter_mut() {
+            level.sort();
+        }
+        assert_eq!(
+            levels,
+            vec![vec!["hgchangesets", "unodes"], vec!["blame", "filenodes"]]
+        );
+
+        let levels = derivation_levels(&["fsnodes".to_string(), "fsnodes".to_string()])?;
+        assert_eq!(levels, vec![vec!["fsnodes"]]);
+
+        assert!(derivation_levels(&["unknown".to_string()]).is_err());
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_derive_data_for_csids(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = blobrepo_factory::new_memblob_empty(None)?;
+        let dag = create_from_dag(&ctx, &repo, "A-B-C").await?;
+        let csids: Vec<_> = ["A", "B", "C"].iter().map(|name| dag[*name]).collect();
+
+        derive_data_for_csids(&ctx, &repo, csids.clone(), &["blame".to_string()])?.await?;
+
+        for name in &["unodes", "blame"] {
+            let pending = derived_data_utils(&repo, name)?
+                .pending(ctx.clone(), repo.clone(), csids.clone())
+                .await?;
+            assert!(pending.is_empty(), "{} is not derived", name);
+        }
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_derive_data_for_csids_implicit_dependency(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = blobrepo_factory::new_memblob_empty(None)?;
+        let dag = create_from_dag(&ctx, &repo, "A-B-C").await?;
+        let csids: Vec<_> = ["A", "B", "C"].iter().map(|name| dag[*name]).collect();
+
+        // Unodes are derived for blame even though they aren't enabled themselves.
+        let blame_only = repo.dangerous_override(|mut config: DerivedDataConfig| {
+            config.enabled.types.remove("unodes");
+            config
+        });
+        assert!(derived_data_utils(&blame_only, "unodes").is_err());
+        derive_data_for_csids(&ctx, &blame_only, csids.clone(), &["blame".to_string()])?.await?;
+
+        for name in &["unodes", "blame"] {
+            let pending = derived_data_utils(&repo, name)?
+                .pending(ctx.clone(), repo.clone(), csids.clone())
+                .await?;
+            assert!(pending.is_empty(), "{} is not derived", name);
+        }
+
+        // Types that were asked for still have to be enabled.
+        assert!(derive_data_for_csids(&ctx, &blame_only, csids, &["unodes".to_string()]).is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_thin_out() {
         let mut thin_out = ThinOut::new(3.0, 2.0);
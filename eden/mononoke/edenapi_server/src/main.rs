@@ -134,6 +134,7 @@ async fn start(
         logger.clone(),
         scuba_logger,
         mononoke,
+        None,
         will_exit.clone(),
         matches.is_present(ARG_TEST_FRIENDLY_LOGGING),
         tls_session_data_log.map(AsRef::as_ref),
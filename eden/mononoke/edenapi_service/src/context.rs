@@ -14,6 +14,8 @@ use gotham_derive::StateData;
 
 use mononoke_api::Mononoke;
 
+use crate::mirror::ReadMirror;
+
 /// Struct containing the EdenAPI server's global shared state.
 /// Intended to be exposed throughout the server by being inserted into
 /// the `State` for each request via Gotham's `StateMiddleware`. As such,
@@ -26,8 +28,12 @@ pub struct ServerContext {
 }
 
 impl ServerContext {
-    pub fn new(mononoke: Mononoke, will_exit: Arc<AtomicBool>) -> Self {
-        let inner = ServerContextInner::new(mononoke);
+    pub fn new(
+        mononoke: Mononoke,
+        read_mirror: Option<ReadMirror>,
+        will_exit: Arc<AtomicBool>,
+    ) -> Self {
+        let inner = ServerContextInner::new(mononoke, read_mirror);
         Self {
             inner: Arc::new(Mutex::new(inner)),
             will_exit,
@@ -43,6 +49,15 @@ impl ServerContext {
     pub fn mononoke_api(&self) -> Arc<Mononoke> {
         self.inner.lock().expect("lock poisoned").mononoke.clone()
     }
+
+    /// The repos that EdenAPI reads are mirrored to, if any.
+    pub fn read_mirror(&self) -> Option<Arc<ReadMirror>> {
+        self.inner
+            .lock()
+            .expect("lock poisoned")
+            .read_mirror
+            .clone()
+    }
 }
 
 /// Underlying global state for a ServerContext. Any data that needs to
@@ -50,12 +65,14 @@ impl ServerContext {
 /// be placed here.
 struct ServerContextInner {
     mononoke: Arc<Mononoke>,
+    read_mirror: Option<Arc<ReadMirror>>,
 }
 
 impl ServerContextInner {
-    fn new(mononoke: Mononoke) -> Self {
+    fn new(mononoke: Mononoke, read_mirror: Option<ReadMirror>) -> Self {
         Self {
             mononoke: Arc::new(mononoke),
+            read_mirror: read_mirror.map(Arc::new),
         }
     }
 }
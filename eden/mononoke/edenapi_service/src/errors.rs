@@ -64,6 +64,20 @@ pub enum ErrorKind {
     CommitRevlogDataRequestFailed,
     #[error("HgId not found: {0}")]
     HgIdNotFound(HgId),
+    #[error("Invalid snapshot id: {0}")]
+    InvalidSnapshotId(String),
+    #[error("Snapshot does not exist or has expired: {0}")]
+    SnapshotNotFound(String),
+    #[error("Snapshot upload failed")]
+    SnapshotUploadFailed,
+    #[error("Failed to fetch snapshot: {0}")]
+    SnapshotFetchFailed(String),
+    #[error("File upload failed")]
+    FileUploadFailed,
+    #[error("File of {0} bytes is over the limit of {1} bytes")]
+    FileTooLarge(u64, u64),
+    #[error("EdenAPI method is disabled: {0}")]
+    MethodDisabled(String),
 }
 
 /// Extension trait for converting `MononokeError`s into `HttpErrors`.
@@ -90,6 +104,7 @@ impl MononokeErrorExt for MononokeError {
             ServicePermissionDenied { .. } => HttpError::e403,
             ServiceRestricted { .. } => HttpError::e403,
             NotAvailable { .. } => HttpError::e503,
+            RepoLocked(_) => HttpError::e503,
             HookFailure(_) => HttpError::e400,
             InternalError(_) => HttpError::e500,
         })(Error::from(self).context(context))
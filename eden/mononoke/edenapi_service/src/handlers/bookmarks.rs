@@ -13,6 +13,7 @@ use gotham::state::{FromState, State};
 use gotham_derive::{StateData, StaticResponseExtender};
 use gotham_ext::{error::HttpError, response::BytesBody};
 use mercurial_types::HgChangesetId;
+use mononoke_types::Timestamp;
 use serde::{Deserialize, Serialize};
 
 use crate::context::ServerContext;
@@ -36,16 +37,31 @@ pub struct BookmarksParams {
     bookmark: String,
 }
 
+#[derive(Debug, Deserialize, StateData, StaticResponseExtender)]
+pub struct BookmarksQueryString {
+    /// Resolve the bookmark to where it pointed to at this time (in seconds since the epoch)
+    /// instead of where it points to now.
+    at: Option<i64>,
+}
+
 pub async fn bookmarks(state: &mut State) -> Result<BytesBody<Bytes>, HttpError> {
     let params = BookmarksParams::take_from(state);
+    let query_string = BookmarksQueryString::take_from(state);
     state.put(HandlerInfo::new(&params.repo, EdenApiMethod::Bookmarks));
     let sctx = ServerContext::borrow_from(state);
     let rctx = RequestContext::borrow_from(state).clone();
     let repo = get_repo(&sctx, &rctx, &params.repo, None).await?;
-    let bookmark_value = repo
-        .resolve_bookmark(params.bookmark, Freshness::MaybeStale)
-        .await
-        .map_err(|e| e.into_http_error("error resolving bookmark"))?;
+    let bookmark_value = match query_string.at {
+        Some(at) => {
+            repo.resolve_bookmark_at_timestamp(params.bookmark, Timestamp::from_timestamp_secs(at))
+                .await
+        }
+        None => {
+            repo.resolve_bookmark(params.bookmark, Freshness::MaybeStale)
+                .await
+        }
+    }
+    .map_err(|e| e.into_http_error("error resolving bookmark"))?;
 
     // TODO: add cbor serialization when the response type is changed to an
     // Edenapi wire type.
@@ -0,0 +1,158 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! What this server supports, for clients to adapt to it rather than probe the endpoints and
+//! guess from 404s and 503s. Methods that are switched off by the `edenapi_disabled_methods`
+//! tunable are listed as disabled, and fail with a 503 if called anyway.
+
+use anyhow::Context;
+use bytes::Bytes;
+use gotham::state::State;
+use mononoke_api_hg::snapshot::MAX_SNAPSHOT_TTL;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tunables::tunables;
+
+use gotham_ext::{error::HttpError, response::BytesBody};
+
+use super::{commit, files, history, trees, upload, EdenApiMethod};
+use crate::errors::ErrorKind;
+
+/// Versions of the EdenAPI protocol that this server speaks.
+const PROTOCOL_VERSIONS: &[u32] = &[1];
+
+#[derive(Clone, Serialize, Debug)]
+struct Limits {
+    /// How many keys of a request are fetched at once, per method.
+    max_concurrent_fetches_per_request: BTreeMap<String, usize>,
+    /// How many keys of a request are handled per batch, for the methods that split requests
+    /// into batches. Requests of any size are accepted.
+    max_batch_sizes: BTreeMap<String, usize>,
+    /// The largest request body, for the methods that limit it. Bodies over it are rejected
+    /// with a 413.
+    max_body_bytes: BTreeMap<String, u64>,
+    /// Snapshots are kept for at most this long, whatever TTL is asked for.
+    max_snapshot_ttl_secs: u64,
+}
+
+#[derive(Clone, Serialize, Debug)]
+struct CapabilitiesResponse {
+    protocol_versions: &'static [u32],
+    enabled_methods: Vec<String>,
+    disabled_methods: Vec<String>,
+    limits: Limits,
+    maintenance_message: Option<String>,
+}
+
+fn by_method<T>(limits: Vec<(EdenApiMethod, T)>) -> BTreeMap<String, T> {
+    limits
+        .into_iter()
+        .map(|(method, limit)| (method.to_string(), limit))
+        .collect()
+}
+
+fn capabilities_response() -> CapabilitiesResponse {
+    let (disabled, enabled): (Vec<_>, Vec<_>) =
+        EdenApiMethod::all().partition(|method| method.is_disabled());
+
+    let max_concurrent_fetches_per_request = by_method(vec![
+        (
+            EdenApiMethod::Files,
+            files::MAX_CONCURRENT_FILE_FETCHES_PER_REQUEST,
+        ),
+        (
+            EdenApiMethod::Trees,
+            trees::MAX_CONCURRENT_TREE_FETCHES_PER_REQUEST,
+        ),
+        (
+            EdenApiMethod::History,
+            history::MAX_CONCURRENT_FETCHES_PER_REQUEST,
+        ),
+        (
+            EdenApiMethod::CommitLocationToHash,
+            commit::MAX_CONCURRENT_FETCHES_PER_REQUEST,
+        ),
+        (
+            EdenApiMethod::CommitRevlogData,
+            commit::MAX_CONCURRENT_FETCHES_PER_REQUEST,
+        ),
+    ]);
+    let max_batch_sizes = by_method(vec![(
+        EdenApiMethod::CommitHashToLocation,
+        commit::HASH_TO_LOCATION_BATCH_SIZE,
+    )]);
+    let max_body_bytes = by_method(vec![(
+        EdenApiMethod::UploadFile,
+        upload::MAX_UPLOAD_FILE_BYTES,
+    )]);
+
+    let maintenance_message = tunables().get_maintenance_message();
+
+    CapabilitiesResponse {
+        protocol_versions: PROTOCOL_VERSIONS,
+        enabled_methods: enabled.iter().map(ToString::to_string).collect(),
+        disabled_methods: disabled.iter().map(ToString::to_string).collect(),
+        limits: Limits {
+            max_concurrent_fetches_per_request,
+            max_batch_sizes,
+            max_body_bytes,
+            max_snapshot_ttl_secs: MAX_SNAPSHOT_TTL.as_secs(),
+        },
+        maintenance_message: if maintenance_message.is_empty() {
+            None
+        } else {
+            Some(maintenance_message.to_string())
+        },
+    }
+}
+
+pub async fn capabilities(_state: &mut State) -> Result<BytesBody<Bytes>, HttpError> {
+    let bytes: Bytes = serde_json::to_vec(&capabilities_response())
+        .context(ErrorKind::SerializationFailed)
+        .map_err(HttpError::e500)?
+        .into();
+
+    Ok(BytesBody::new(bytes, mime::APPLICATION_JSON))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+    use tunables::{with_tunables, MononokeTunables};
+
+    #[test]
+    fn test_capabilities() {
+        let tunables = MononokeTunables::default();
+        let mut strings = HashMap::new();
+        strings.insert(
+            "edenapi_disabled_methods".to_string(),
+            "trees, upload_file".to_string(),
+        );
+        tunables.update_strings(&strings);
+
+        let response = with_tunables(tunables, capabilities_response);
+        assert_eq!(response.disabled_methods, vec!["trees", "upload_file"]);
+        assert_eq!(
+            response.enabled_methods.len() + response.disabled_methods.len(),
+            EdenApiMethod::all().count()
+        );
+        assert!(!response.enabled_methods.contains(&"trees".to_string()));
+        assert_eq!(
+            response.limits.max_body_bytes.get("upload_file"),
+            Some(&upload::MAX_UPLOAD_FILE_BYTES)
+        );
+        assert_eq!(
+            response
+                .limits
+                .max_batch_sizes
+                .get("commit_hash_to_location"),
+            Some(&commit::HASH_TO_LOCATION_BATCH_SIZE)
+        );
+        assert_eq!(response.maintenance_message, None);
+    }
+}
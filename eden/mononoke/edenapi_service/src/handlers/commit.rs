@@ -29,8 +29,8 @@ use crate::utils::{cbor_stream, get_repo, parse_cbor_request, parse_wire_request
 use super::{EdenApiMethod, HandlerInfo};
 
 /// XXX: This number was chosen arbitrarily.
-const MAX_CONCURRENT_FETCHES_PER_REQUEST: usize = 100;
-const HASH_TO_LOCATION_BATCH_SIZE: usize = 100;
+pub(super) const MAX_CONCURRENT_FETCHES_PER_REQUEST: usize = 100;
+pub(super) const HASH_TO_LOCATION_BATCH_SIZE: usize = 100;
 
 #[derive(Debug, Deserialize, StateData, StaticResponseExtender)]
 pub struct LocationToHashParams {
@@ -24,12 +24,13 @@ use types::Key;
 use crate::context::ServerContext;
 use crate::errors::ErrorKind;
 use crate::middleware::RequestContext;
+use crate::mirror::sample_read_mirror;
 use crate::utils::{cbor_stream, get_repo, parse_wire_request};
 
 use super::{EdenApiMethod, HandlerInfo};
 
 /// XXX: This number was chosen arbitrarily.
-const MAX_CONCURRENT_FILE_FETCHES_PER_REQUEST: usize = 10;
+pub(super) const MAX_CONCURRENT_FILE_FETCHES_PER_REQUEST: usize = 10;
 
 #[derive(Debug, Deserialize, StateData, StaticResponseExtender)]
 pub struct FileParams {
@@ -46,12 +47,23 @@ pub async fn files(state: &mut State) -> Result<impl TryIntoResponse, HttpError>
     let sctx = ServerContext::borrow_from(state);
 
     let repo = get_repo(&sctx, &rctx, &params.repo, Metric::EgressGetpackFiles).await?;
+    // Sampled before the request is parsed, as parsing it needs the state.
+    let mirror = sample_read_mirror(&sctx, &rctx, &params.repo, EdenApiMethod::Files).await;
     let request = parse_wire_request::<WireFileRequest>(state).await?;
 
-    Ok(cbor_stream(
-        rctx,
-        fetch_all_files(repo, request).map(|r| r.map(|v| v.to_wire())),
-    ))
+    let files = match mirror {
+        Some(mirror) => {
+            let mirrored = request.clone();
+            mirror
+                .compare(fetch_all_files(repo, request), move |repo| {
+                    fetch_all_files(repo, mirrored)
+                })
+                .left_stream()
+        }
+        None => fetch_all_files(repo, request).right_stream(),
+    };
+
+    Ok(cbor_stream(rctx, files.map(|r| r.map(|v| v.to_wire()))))
 }
 
 /// Fetch files for all of the requested keys concurrently.
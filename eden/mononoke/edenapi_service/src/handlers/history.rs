@@ -22,28 +22,37 @@ use edenapi_types::{
 };
 use gotham_ext::{error::HttpError, response::TryIntoResponse};
 use mercurial_types::{HgFileNodeId, HgNodeHash};
+use mononoke_api::path::MononokePath;
 use mononoke_api_hg::HgRepoContext;
 use types::Key;
 
 use crate::context::ServerContext;
 use crate::errors::ErrorKind;
 use crate::middleware::RequestContext;
-use crate::utils::{cbor_stream, get_repo, parse_wire_request, to_mpath};
+use crate::utils::{cbor_stream, get_repo, parse_wire_request, to_hg_path, to_mpath};
 
 use super::{EdenApiMethod, HandlerInfo};
 
 type HistoryStream = BoxStream<'static, Result<WireHistoryEntry, Error>>;
 
 /// XXX: This number was chosen arbitrarily.
-const MAX_CONCURRENT_FETCHES_PER_REQUEST: usize = 10;
+pub(super) const MAX_CONCURRENT_FETCHES_PER_REQUEST: usize = 10;
 
 #[derive(Debug, Deserialize, StateData, StaticResponseExtender)]
 pub struct HistoryParams {
     repo: String,
 }
 
+#[derive(Debug, Deserialize, StateData, StaticResponseExtender)]
+pub struct HistoryQueryString {
+    /// Carry on with the history of the files that each file was copied or moved from, so that
+    /// the client doesn't need a round trip per copy to follow the history of a file.
+    follow_copies: Option<bool>,
+}
+
 pub async fn history(state: &mut State) -> Result<impl TryIntoResponse, HttpError> {
     let params = HistoryParams::take_from(state);
+    let query_string = HistoryQueryString::take_from(state);
 
     state.put(HandlerInfo::new(&params.repo, EdenApiMethod::History));
 
@@ -55,7 +64,7 @@ pub async fn history(state: &mut State) -> Result<impl TryIntoResponse, HttpErro
 
     Ok(cbor_stream(
         rctx,
-        fetch_history(repo, request)
+        fetch_history(repo, request, query_string.follow_copies.unwrap_or(false))
             .await
             .map(|r| r.map(|e| e.to_wire())),
     ))
@@ -65,6 +74,7 @@ pub async fn history(state: &mut State) -> Result<impl TryIntoResponse, HttpErro
 async fn fetch_history(
     repo: HgRepoContext,
     request: HistoryRequest,
+    follow_copies: bool,
 ) -> impl Stream<Item = Result<HistoryResponseChunk, Error>> {
     let HistoryRequest { keys, length } = request;
 
@@ -75,14 +85,71 @@ async fn fetch_history(
         // amount of buffered data should be reasonable.
         cloned!(repo);
         async move {
+            if follow_copies {
+                return fetch_history_following_copies(repo, key, length).await;
+            }
             let path = key.path.clone();
             let stream = fetch_history_for_key(repo, key, length).await?;
             let entries = stream.try_collect().await?;
-            Ok(HistoryResponseChunk { path, entries })
+            Ok(vec![HistoryResponseChunk::new(path, entries)])
         }
     });
 
-    stream::iter(fetches).buffer_unordered(MAX_CONCURRENT_FETCHES_PER_REQUEST)
+    stream::iter(fetches)
+        .buffer_unordered(MAX_CONCURRENT_FETCHES_PER_REQUEST)
+        .map_ok(|chunks| stream::iter(chunks.into_iter().map(Ok)))
+        .try_flatten()
+}
+
+/// Fetch the history of a file, and then the history of the files it was copied from, up to
+/// `length` entries in total. As the entries of a chunk all have the same path, the history of
+/// each of the paths is returned in its own chunk. If the history was cut short, the last chunk
+/// says where it carries on from.
+async fn fetch_history_following_copies(
+    repo: HgRepoContext,
+    key: Key,
+    length: Option<u32>,
+) -> Result<Vec<HistoryResponseChunk>, Error> {
+    let filenode_id = HgFileNodeId::new(HgNodeHash::from(key.hgid));
+    let mpath = to_mpath(&key.path)?.context(ErrorKind::UnexpectedEmptyPath)?;
+
+    let history = repo
+        .file_history_following_copies(mpath, filenode_id, length)
+        .await
+        .with_context(|| ErrorKind::HistoryFetchFailed(key.clone()))?;
+
+    let mut chunks = history
+        .paths
+        .into_iter()
+        .map(|(path, entries)| {
+            let path = to_hg_path(&MononokePath::new(Some(path)))?;
+            let entries = entries
+                .into_iter()
+                .map(WireHistoryEntry::try_from)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(HistoryResponseChunk::new(path, entries))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let next = history
+        .next
+        .into_iter()
+        .map(|(path, filenode_id)| {
+            let path = to_hg_path(&MononokePath::new(Some(path)))?;
+            Ok(Key::new(path, filenode_id.into_nodehash().into()))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    if !next.is_empty() {
+        match chunks.last_mut() {
+            Some(chunk) => chunk.next = next,
+            None => chunks.push(HistoryResponseChunk {
+                next,
+                ..HistoryResponseChunk::new(key.path, Vec::new())
+            }),
+        }
+    }
+
+    Ok(chunks)
 }
 
 async fn fetch_history_for_key(
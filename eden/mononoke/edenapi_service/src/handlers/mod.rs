@@ -21,19 +21,24 @@ use gotham::{
 };
 use gotham_derive::StateData;
 
-use gotham_ext::response::build_response;
+use gotham_ext::{error::HttpError, response::build_response};
+use tunables::tunables;
 
 use crate::context::ServerContext;
+use crate::errors::ErrorKind;
 use crate::middleware::RequestContext;
 
 mod bookmarks;
+mod capabilities;
 mod clone;
 mod commit;
 mod complete_trees;
 mod files;
 mod history;
 mod repos;
+mod snapshot;
 mod trees;
+mod upload;
 
 /// Enum identifying the EdenAPI method that each handler corresponds to.
 /// Used to identify the handler for logging and stats collection.
@@ -49,6 +54,9 @@ pub enum EdenApiMethod {
     Clone,
     FullIdMapClone,
     Bookmarks,
+    UploadSnapshot,
+    FetchSnapshot,
+    UploadFile,
 }
 
 impl fmt::Display for EdenApiMethod {
@@ -64,11 +72,60 @@ impl fmt::Display for EdenApiMethod {
             Self::Clone => "clone",
             Self::FullIdMapClone => "full_idmap_clone",
             Self::Bookmarks => "bookmarks",
+            Self::UploadSnapshot => "upload_snapshot",
+            Self::FetchSnapshot => "fetch_snapshot",
+            Self::UploadFile => "upload_file",
         };
         write!(f, "{}", name)
     }
 }
 
+impl EdenApiMethod {
+    /// All the methods, in the order the capabilities endpoint lists them.
+    pub fn all() -> impl Iterator<Item = EdenApiMethod> {
+        std::iter::successors(Some(Self::Files), |method| method.next())
+    }
+
+    /// The method listed after this one. The match is exhaustive, so that a new method can't be
+    /// left out of `all`.
+    fn next(&self) -> Option<EdenApiMethod> {
+        match self {
+            Self::Files => Some(Self::Trees),
+            Self::Trees => Some(Self::CompleteTrees),
+            Self::CompleteTrees => Some(Self::History),
+            Self::History => Some(Self::CommitLocationToHash),
+            Self::CommitLocationToHash => Some(Self::CommitHashToLocation),
+            Self::CommitHashToLocation => Some(Self::CommitRevlogData),
+            Self::CommitRevlogData => Some(Self::Clone),
+            Self::Clone => Some(Self::FullIdMapClone),
+            Self::FullIdMapClone => Some(Self::Bookmarks),
+            Self::Bookmarks => Some(Self::UploadSnapshot),
+            Self::UploadSnapshot => Some(Self::FetchSnapshot),
+            Self::FetchSnapshot => Some(Self::UploadFile),
+            Self::UploadFile => None,
+        }
+    }
+
+    /// Whether this method is switched off through the `edenapi_disabled_methods` tunable.
+    pub fn is_disabled(&self) -> bool {
+        let name = self.to_string();
+        tunables()
+            .get_edenapi_disabled_methods()
+            .split(',')
+            .map(str::trim)
+            .any(|disabled| disabled == name)
+    }
+}
+
+fn check_method_enabled(method: impl Into<Option<EdenApiMethod>>) -> Result<(), HttpError> {
+    match method.into() {
+        Some(method) if method.is_disabled() => Err(HttpError::e503(ErrorKind::MethodDisabled(
+            method.to_string(),
+        ))),
+        _ => Ok(()),
+    }
+}
+
 /// Information about the handler that served the request.
 ///
 /// This should be inserted into the request's `State` by each handler. It will
@@ -99,11 +156,20 @@ impl HandlerInfo {
 /// ```rust,ignore
 /// fn wrapped(mut state: State) -> Pin<Box<HandlerFuture>>
 /// ```
+///
+/// If the EdenAPI method that the handler serves is given, the handler fails with a 503 without
+/// being run while the method is disabled.
 macro_rules! define_handler {
     ($name:ident, $func:path) => {
+        define_handler!($name, $func, None);
+    };
+    ($name:ident, $func:path, $method:expr) => {
         fn $name(mut state: State) -> Pin<Box<HandlerFuture>> {
             async move {
-                let res = $func(&mut state).await;
+                let res = match check_method_enabled($method) {
+                    Ok(()) => $func(&mut state).await,
+                    Err(e) => Err(e),
+                };
 
                 if let Err(e) = res.as_ref() {
                     if let Some(log_ctx) = state.try_borrow_mut::<RequestContext>() {
@@ -119,16 +185,56 @@ macro_rules! define_handler {
 }
 
 define_handler!(repos_handler, repos::repos);
-define_handler!(files_handler, files::files);
-define_handler!(trees_handler, trees::trees);
-define_handler!(complete_trees_handler, complete_trees::complete_trees);
-define_handler!(history_handler, history::history);
-define_handler!(commit_location_to_hash_handler, commit::location_to_hash);
-define_handler!(commit_hash_to_location_handler, commit::hash_to_location);
-define_handler!(commit_revlog_data_handler, commit::revlog_data);
-define_handler!(clone_handler, clone::clone_data);
-define_handler!(full_idmap_clone_handler, clone::full_idmap_clone_data);
-define_handler!(bookmarks_handler, bookmarks::bookmarks);
+define_handler!(capabilities_handler, capabilities::capabilities);
+define_handler!(files_handler, files::files, EdenApiMethod::Files);
+define_handler!(trees_handler, trees::trees, EdenApiMethod::Trees);
+define_handler!(
+    complete_trees_handler,
+    complete_trees::complete_trees,
+    EdenApiMethod::CompleteTrees
+);
+define_handler!(history_handler, history::history, EdenApiMethod::History);
+define_handler!(
+    commit_location_to_hash_handler,
+    commit::location_to_hash,
+    EdenApiMethod::CommitLocationToHash
+);
+define_handler!(
+    commit_hash_to_location_handler,
+    commit::hash_to_location,
+    EdenApiMethod::CommitHashToLocation
+);
+define_handler!(
+    commit_revlog_data_handler,
+    commit::revlog_data,
+    EdenApiMethod::CommitRevlogData
+);
+define_handler!(clone_handler, clone::clone_data, EdenApiMethod::Clone);
+define_handler!(
+    full_idmap_clone_handler,
+    clone::full_idmap_clone_data,
+    EdenApiMethod::FullIdMapClone
+);
+define_handler!(
+    bookmarks_handler,
+    bookmarks::bookmarks,
+    EdenApiMethod::Bookmarks
+);
+define_handler!(
+    upload_snapshot_handler,
+    snapshot::upload_snapshot,
+    EdenApiMethod::UploadSnapshot
+);
+define_handler!(
+    fetch_snapshot_handler,
+    snapshot::fetch_snapshot,
+    EdenApiMethod::FetchSnapshot
+);
+define_handler!(
+    upload_file_handler,
+    upload::upload_file,
+    EdenApiMethod::UploadFile
+);
 
 fn health_handler(state: State) -> (State, &'static str) {
     if ServerContext::borrow_from(&state).will_exit() {
@@ -145,6 +251,7 @@ pub fn build_router(ctx: ServerContext) -> Router {
     gotham_build_router(chain, pipelines, |route| {
         route.get("/health_check").to(health_handler);
         route.get("/repos").to(repos_handler);
+        route.get("/capabilities").to(capabilities_handler);
         route
             .post("/:repo/files")
             .with_path_extractor::<files::FileParams>()
@@ -160,6 +267,7 @@ pub fn build_router(ctx: ServerContext) -> Router {
         route
             .post("/:repo/history")
             .with_path_extractor::<history::HistoryParams>()
+            .with_query_string_extractor::<history::HistoryQueryString>()
             .to(history_handler);
         route
             .post("/:repo/commit/location_to_hash")
@@ -184,6 +292,36 @@ pub fn build_router(ctx: ServerContext) -> Router {
         route
             .get("/:repo/bookmarks/:bookmark")
             .with_path_extractor::<bookmarks::BookmarksParams>()
+            .with_query_string_extractor::<bookmarks::BookmarksQueryString>()
             .to(bookmarks_handler);
+        route
+            .post("/:repo/snapshot")
+            .with_path_extractor::<snapshot::UploadSnapshotParams>()
+            .to(upload_snapshot_handler);
+        route
+            .get("/:repo/snapshot/:id")
+            .with_path_extractor::<snapshot::FetchSnapshotParams>()
+            .to(fetch_snapshot_handler);
+        route
+            .put("/:repo/upload/file")
+            .with_path_extractor::<upload::UploadFileParams>()
+            .to(upload_file_handler);
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_all_methods() {
+        let names: Vec<_> = EdenApiMethod::all()
+            .map(|method| method.to_string())
+            .collect();
+        assert_eq!(names.len(), 13);
+        assert_eq!(names.iter().collect::<HashSet<_>>().len(), names.len());
+        assert_eq!(names.first().map(String::as_str), Some("files"));
+        assert_eq!(names.last().map(String::as_str), Some("upload_file"));
+    }
+}
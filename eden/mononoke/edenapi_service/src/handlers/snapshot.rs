@@ -0,0 +1,153 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Error};
+use bytes::Bytes;
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use gotham_ext::{error::HttpError, response::BytesBody};
+use mercurial_types::HgChangesetId;
+use mononoke_api_hg::{SnapshotFile, SnapshotId};
+use mononoke_types::{FileType, MPath};
+use serde::{Deserialize, Serialize};
+
+use crate::context::ServerContext;
+use crate::errors::{ErrorKind, MononokeErrorExt};
+use crate::middleware::RequestContext;
+use crate::utils::{cbor_mime, get_repo, parse_cbor_request, to_cbor_bytes};
+
+use super::{EdenApiMethod, HandlerInfo};
+
+/// TODO: move the request and response types to edenapi_types once the client side exists.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct WireSnapshotFile {
+    path: String,
+    file_type: FileType,
+    /// `None` if the file was removed from the working copy.
+    content: Option<Bytes>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct UploadSnapshotRequest {
+    parent: Option<HgChangesetId>,
+    files: Vec<WireSnapshotFile>,
+    /// How long to keep the snapshot for. The server caps this, and picks a default if unset.
+    ttl_secs: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct UploadSnapshotResponse {
+    id: String,
+    expires_at: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct FetchSnapshotResponse {
+    parent: Option<HgChangesetId>,
+    files: Vec<WireSnapshotFile>,
+    expires_at: i64,
+}
+
+#[derive(Debug, Deserialize, StateData, StaticResponseExtender)]
+pub struct UploadSnapshotParams {
+    repo: String,
+}
+
+#[derive(Debug, Deserialize, StateData, StaticResponseExtender)]
+pub struct FetchSnapshotParams {
+    repo: String,
+    id: String,
+}
+
+/// Store an ephemeral snapshot of the client's working copy.
+pub async fn upload_snapshot(state: &mut State) -> Result<BytesBody<Bytes>, HttpError> {
+    let params = UploadSnapshotParams::take_from(state);
+    state.put(HandlerInfo::new(
+        &params.repo,
+        EdenApiMethod::UploadSnapshot,
+    ));
+    let sctx = ServerContext::borrow_from(state);
+    let rctx = RequestContext::borrow_from(state).clone();
+    let repo = get_repo(&sctx, &rctx, &params.repo, None).await?;
+
+    let request: UploadSnapshotRequest = parse_cbor_request(state).await?;
+    let files = request
+        .files
+        .into_iter()
+        .map(|file| {
+            let WireSnapshotFile {
+                path,
+                file_type,
+                content,
+            } = file;
+            Ok(SnapshotFile {
+                path: MPath::new(&path)
+                    .with_context(|| ErrorKind::InvalidPath(path.into_bytes()))?,
+                file_type,
+                content,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()
+        .map_err(HttpError::e400)?;
+
+    let (id, expires_at) = repo
+        .upload_snapshot(
+            request.parent,
+            files,
+            request.ttl_secs.map(Duration::from_secs),
+        )
+        .await
+        .map_err(|e| e.into_http_error(ErrorKind::SnapshotUploadFailed))?;
+
+    let bytes = to_cbor_bytes(UploadSnapshotResponse {
+        id: id.to_string(),
+        expires_at,
+    })
+    .map_err(HttpError::e500)?;
+    Ok(BytesBody::new(bytes, cbor_mime()))
+}
+
+/// Fetch a snapshot previously stored by `upload_snapshot`, so that the client can restore it.
+pub async fn fetch_snapshot(state: &mut State) -> Result<BytesBody<Bytes>, HttpError> {
+    let params = FetchSnapshotParams::take_from(state);
+    state.put(HandlerInfo::new(&params.repo, EdenApiMethod::FetchSnapshot));
+    let sctx = ServerContext::borrow_from(state);
+    let rctx = RequestContext::borrow_from(state).clone();
+    let repo = get_repo(&sctx, &rctx, &params.repo, None).await?;
+
+    let id = SnapshotId::from_str(&params.id)
+        .with_context(|| ErrorKind::InvalidSnapshotId(params.id.clone()))
+        .map_err(HttpError::e400)?;
+
+    let snapshot = repo
+        .fetch_snapshot(id)
+        .await
+        .map_err(|e| e.into_http_error(ErrorKind::SnapshotFetchFailed(params.id.clone())))?
+        .ok_or(ErrorKind::SnapshotNotFound(params.id))
+        .map_err(HttpError::e404)?;
+
+    let files = snapshot
+        .files
+        .into_iter()
+        .map(|file| WireSnapshotFile {
+            path: String::from_utf8_lossy(&file.path.to_vec()).into_owned(),
+            file_type: file.file_type,
+            content: file.content,
+        })
+        .collect();
+
+    let bytes = to_cbor_bytes(FetchSnapshotResponse {
+        parent: snapshot.parent,
+        files,
+        expires_at: snapshot.expires_at,
+    })
+    .map_err(HttpError::e500)?;
+    Ok(BytesBody::new(bytes, cbor_mime()))
+}
@@ -25,12 +25,13 @@ use types::{Key, RepoPathBuf};
 use crate::context::ServerContext;
 use crate::errors::ErrorKind;
 use crate::middleware::RequestContext;
+use crate::mirror::sample_read_mirror;
 use crate::utils::{cbor_stream, get_repo, parse_wire_request};
 
 use super::{EdenApiMethod, HandlerInfo};
 
 /// XXX: This number was chosen arbitrarily.
-const MAX_CONCURRENT_TREE_FETCHES_PER_REQUEST: usize = 10;
+pub(super) const MAX_CONCURRENT_TREE_FETCHES_PER_REQUEST: usize = 10;
 const MAX_CONCURRENT_METADATA_FETCHES_PER_TREE_FETCH: usize = 100;
 
 #[derive(Debug, Deserialize, StateData, StaticResponseExtender)]
@@ -48,12 +49,23 @@ pub async fn trees(state: &mut State) -> Result<impl TryIntoResponse, HttpError>
     let sctx = ServerContext::borrow_from(state);
 
     let repo = get_repo(&sctx, &rctx, &params.repo, Metric::EgressTotalManifests).await?;
+    // Sampled before the request is parsed, as parsing it needs the state.
+    let mirror = sample_read_mirror(&sctx, &rctx, &params.repo, EdenApiMethod::Trees).await;
     let request = parse_wire_request::<WireTreeRequest>(state).await?;
 
-    Ok(cbor_stream(
-        rctx,
-        fetch_all_trees(repo, request).map(|r| Ok(r.to_wire())),
-    ))
+    let trees = match mirror {
+        Some(mirror) => {
+            let mirrored = request.clone();
+            mirror
+                .compare(fetch_all_trees(repo, request), move |repo| {
+                    fetch_all_trees(repo, mirrored)
+                })
+                .left_stream()
+        }
+        None => fetch_all_trees(repo, request).right_stream(),
+    };
+
+    Ok(cbor_stream(rctx, trees.map(|r| Ok(r.to_wire()))))
 }
 
 /// Fetch trees for all of the requested keys concurrently.
@@ -0,0 +1,90 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{Context, Error};
+use bytes::Bytes;
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use gotham_ext::{body_stream::SizedBodyStream, error::HttpError, response::BytesBody};
+use http::header::{HeaderMap, CONTENT_LENGTH};
+use hyper::Body;
+use mononoke_types::{hash, ContentId};
+use serde::{Deserialize, Serialize};
+
+use crate::context::ServerContext;
+use crate::errors::{ErrorKind, MononokeErrorExt};
+use crate::middleware::RequestContext;
+use crate::utils::{cbor_mime, get_repo, to_cbor_bytes};
+
+use super::{EdenApiMethod, HandlerInfo};
+
+/// The largest file that can be uploaded.
+pub const MAX_UPLOAD_FILE_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// TODO: move the response type to edenapi_types once the client side exists.
+#[derive(Clone, Debug, Serialize)]
+struct UploadFileResponse {
+    content_id: ContentId,
+    sha1: hash::Sha1,
+    sha256: hash::Sha256,
+    size: u64,
+}
+
+#[derive(Debug, Deserialize, StateData, StaticResponseExtender)]
+pub struct UploadFileParams {
+    repo: String,
+}
+
+fn content_length(state: &State) -> Result<u64, Error> {
+    let value = HeaderMap::borrow_from(state)
+        .get(CONTENT_LENGTH)
+        .context("Uploads must have a Content-Length")?;
+    Ok(value.to_str()?.parse()?)
+}
+
+/// Store the content of a file. The request body is the raw content, which is passed through to
+/// the filestore as it is received rather than buffered, so that large files can be uploaded, up
+/// to `MAX_UPLOAD_FILE_BYTES`.
+pub async fn upload_file(state: &mut State) -> Result<BytesBody<Bytes>, HttpError> {
+    let params = UploadFileParams::take_from(state);
+    state.put(HandlerInfo::new(&params.repo, EdenApiMethod::UploadFile));
+    let size = content_length(state)
+        .context(ErrorKind::InvalidContentLength)
+        .map_err(HttpError::e400)?;
+    if size > MAX_UPLOAD_FILE_BYTES {
+        return Err(HttpError::e413(ErrorKind::FileTooLarge(
+            size,
+            MAX_UPLOAD_FILE_BYTES,
+        )));
+    }
+
+    let sctx = ServerContext::borrow_from(state);
+    let rctx = RequestContext::borrow_from(state).clone();
+    let repo = get_repo(&sctx, &rctx, &params.repo, None).await?;
+    let data = SizedBodyStream::new(Body::take_from(state), size);
+    let received = data.received_size();
+
+    let metadata = match repo.upload_file_content(size, data).await {
+        Ok(metadata) => metadata,
+        // The client sent less or more than it said it would, or went away mid-upload.
+        Err(e) if received.get() != size => {
+            return Err(HttpError::e400(
+                Error::from(e).context(ErrorKind::FileUploadFailed),
+            ));
+        }
+        Err(e) => return Err(e.into_http_error(ErrorKind::FileUploadFailed)),
+    };
+
+    let bytes = to_cbor_bytes(UploadFileResponse {
+        content_id: metadata.content_id,
+        sha1: metadata.sha1,
+        sha256: metadata.sha256,
+        size: metadata.total_size,
+    })
+    .map_err(HttpError::e500)?;
+    Ok(BytesBody::new(bytes, cbor_mime()))
+}
@@ -11,6 +11,7 @@ mod context;
 mod errors;
 mod handlers;
 mod middleware;
+mod mirror;
 mod scuba;
 mod utils;
 
@@ -34,9 +35,12 @@ use std::sync::{atomic::AtomicBool, Arc};
 
 use crate::context::ServerContext;
 use crate::handlers::build_router;
-use crate::middleware::{OdsMiddleware, RequestContextMiddleware};
+use crate::middleware::{MaintenanceMiddleware, OdsMiddleware, RequestContextMiddleware};
+use crate::mirror::ReadMirror;
 use crate::scuba::EdenApiScubaHandler;
 
+pub use crate::mirror::read_mirror_configs;
+
 pub type EdenApi = MononokeHttpHandler<Router>;
 
 pub fn build(
@@ -44,12 +48,17 @@ pub fn build(
     logger: Logger,
     scuba: MononokeScubaSampleBuilder,
     mononoke: Mononoke,
+    read_mirror: Option<Mononoke>,
     will_exit: Arc<AtomicBool>,
     test_friendly_loging: bool,
     tls_session_data_log_path: Option<&Path>,
     load_limiter: Option<LoadLimiterEnvironment>,
 ) -> Result<EdenApi, Error> {
-    let ctx = ServerContext::new(mononoke, will_exit.clone());
+    let ctx = ServerContext::new(
+        mononoke,
+        read_mirror.map(ReadMirror::new),
+        will_exit.clone(),
+    );
 
     let log_middleware = if test_friendly_loging {
         LogMiddleware::test_friendly()
@@ -69,6 +78,7 @@ pub fn build(
         .add(ServerIdentityMiddleware::new(HeaderValue::from_static(
             "edenapi_server",
         )))
+        .add(MaintenanceMiddleware::new())
         .add(PostRequestMiddleware::default())
         .add(RequestContextMiddleware::new(fb, logger, load_limiter))
         .add(LoadMiddleware::new())
@@ -0,0 +1,41 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use gotham::state::State;
+use gotham_ext::middleware::Middleware;
+use hyper::header::HeaderValue;
+use hyper::{Body, Response};
+use tunables::tunables;
+
+const HEADER_MAINTENANCE: &str = "x-mononoke-maintenance";
+
+/// Add the maintenance message from tunables, if any, to every response, so that clients can
+/// let users know about planned maintenance.
+#[derive(Default)]
+pub struct MaintenanceMiddleware;
+
+impl MaintenanceMiddleware {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for MaintenanceMiddleware {
+    async fn outbound(&self, _state: &mut State, response: &mut Response<Body>) {
+        let message = tunables().get_maintenance_message();
+        if message.is_empty() {
+            return;
+        }
+
+        // Messages that can't be sent in a header (e.g. with newlines) are dropped rather than
+        // failing the request.
+        if let Ok(value) = HeaderValue::from_str(&message) {
+            response.headers_mut().insert(HEADER_MAINTENANCE, value);
+        }
+    }
+}
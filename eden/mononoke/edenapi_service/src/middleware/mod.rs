@@ -5,8 +5,10 @@
  * GNU General Public License version 2.
  */
 
+pub mod maintenance;
 pub mod ods;
 pub mod request_context;
 
+pub use self::maintenance::MaintenanceMiddleware;
 pub use self::ods::OdsMiddleware;
 pub use self::request_context::{RequestContext, RequestContextMiddleware};
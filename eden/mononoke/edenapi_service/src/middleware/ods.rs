@@ -30,6 +30,9 @@ define_stats! {
     clone_duration: dynamic_histogram("{}.clone_data_ms", (repo: String); 10, 0, 500, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     full_idmap_clone_duration: dynamic_histogram("{}.full_idmap_clone_data_ms", (repo: String); 10, 0, 500, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     bookmarks_duration: dynamic_histogram("{}.full_idmap_clone_data_ms", (repo: String); 10, 0, 500, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    upload_snapshot_duration: dynamic_histogram("{}.upload_snapshot_ms", (repo: String); 100, 0, 5000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    fetch_snapshot_duration: dynamic_histogram("{}.fetch_snapshot_ms", (repo: String); 100, 0, 5000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    upload_file_duration: dynamic_histogram("{}.upload_file_ms", (repo: String); 1000, 0, 120000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
 }
 
 fn log_stats(state: &mut State, status: StatusCode) -> Option<()> {
@@ -68,6 +71,9 @@ fn log_stats(state: &mut State, status: StatusCode) -> Option<()> {
                 Clone => STATS::clone_duration.add_value(dur_ms, (repo,)),
                 FullIdMapClone => STATS::full_idmap_clone_duration.add_value(dur_ms, (repo,)),
                 Bookmarks => STATS::bookmarks_duration.add_value(dur_ms, (repo,)),
+                UploadSnapshot => STATS::upload_snapshot_duration.add_value(dur_ms, (repo,)),
+                FetchSnapshot => STATS::fetch_snapshot_duration.add_value(dur_ms, (repo,)),
+                UploadFile => STATS::upload_file_duration.add_value(dur_ms, (repo,)),
             }
         }
 
@@ -90,7 +90,10 @@ impl Middleware for RequestContextMiddleware {
             .clone()
             .unwrap_or_default();
 
-        let load_limiter = self.load_limiter.as_ref().map(|l| l.get(&identities, None));
+        let load_limiter = self
+            .load_limiter
+            .as_ref()
+            .map(|l| l.get(&identities, None, None));
         let metadata = Metadata::default().set_identities(identities);
         let session = SessionContainer::builder(self.fb)
             .metadata(metadata)
@@ -0,0 +1,337 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Mirroring of EdenAPI reads to another storage config. For the repos that have a read mirror
+//! configured, a sample of the files and trees requests is served again, once the response to the
+//! client is complete, from a copy of the repo that is backed by the mirror storage. Whatever the
+//! mirror returns is discarded after it is compared with what was served, and the differences are
+//! logged. This way, a storage migration can be validated against real traffic before any client
+//! depends on it.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use context::CoreContext;
+use edenapi_types::{FileEntry, TreeEntry};
+use futures::{future, stream, Stream, StreamExt};
+use metaconfig_types::RepoConfigs;
+use mononoke_api::Mononoke;
+use mononoke_api_hg::HgRepoContext;
+use rand::{thread_rng, Rng};
+use slog::{debug, warn, Logger};
+use stats::prelude::*;
+use types::Key;
+
+use crate::context::ServerContext;
+use crate::handlers::EdenApiMethod;
+use crate::middleware::RequestContext;
+
+// Mirroring must never get in the way of the requests it mirrors, so requests past this many are
+// not mirrored, rather than queued up if the mirror storage is slow.
+const MAX_IN_FLIGHT: usize = 20;
+// Mismatches past this many in a request are only counted.
+const MAX_LOGGED_MISMATCHES: usize = 10;
+// What is served is kept until the response is complete, to be compared. Responses larger than
+// this are not compared, rather than held on to whole.
+const MAX_SERVED_BYTES: usize = 64 * 1024 * 1024;
+
+define_stats! {
+    prefix = "mononoke.edenapi.read_mirror";
+    compared: dynamic_timeseries("{}.compared", (method: String); Rate, Sum),
+    mismatched: dynamic_timeseries("{}.mismatched", (method: String); Rate, Sum),
+    skipped_in_flight: timeseries(Rate, Sum),
+    skipped_too_large: dynamic_timeseries("{}.skipped_too_large", (method: String); Rate, Sum),
+}
+
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+struct InFlight(());
+
+impl InFlight {
+    fn start() -> Option<Self> {
+        if IN_FLIGHT.fetch_add(1, Ordering::Relaxed) >= MAX_IN_FLIGHT {
+            IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+            STATS::skipped_in_flight.add_value(1);
+            return None;
+        }
+        Some(Self(()))
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The configs of the repos that have a read mirror, with their storage replaced by the mirror
+/// storage, for the reads to be mirrored to. `None` if no repo has a read mirror.
+pub fn read_mirror_configs(configs: &RepoConfigs) -> Option<RepoConfigs> {
+    let repos: HashMap<_, _> = configs
+        .repos
+        .iter()
+        .filter(|(_, config)| config.enabled)
+        .filter_map(|(name, config)| {
+            let mirror = config.edenapi_read_mirror.as_ref()?;
+            let mut config = config.clone();
+            config.storage_config = mirror.storage_config.clone();
+            Some((name.clone(), config))
+        })
+        .collect();
+
+    if repos.is_empty() {
+        return None;
+    }
+
+    Some(RepoConfigs {
+        repos,
+        common: configs.common.clone(),
+    })
+}
+
+/// The repos that reads are mirrored to, built from `read_mirror_configs`.
+pub struct ReadMirror {
+    mononoke: Mononoke,
+    /// The sample percentage of each repo, for requests to be sampled before their repo is even
+    /// looked up.
+    sample_percentages: HashMap<String, u32>,
+}
+
+impl ReadMirror {
+    pub fn new(mononoke: Mononoke) -> Self {
+        let sample_percentages = mononoke
+            .repos()
+            .filter_map(|repo| {
+                let mirror = repo.config().edenapi_read_mirror.as_ref()?;
+                Some((repo.name().clone(), mirror.sample_percentage))
+            })
+            .collect();
+        Self {
+            mononoke,
+            sample_percentages,
+        }
+    }
+}
+
+/// Whether this request should be mirrored, as per the sample percentage of its repo. If so,
+/// returns what it takes to mirror it.
+pub async fn sample_read_mirror(
+    sctx: &ServerContext,
+    rctx: &RequestContext,
+    repo: &str,
+    method: EdenApiMethod,
+) -> Option<MirroredRead> {
+    let mirror = sctx.read_mirror()?;
+
+    let percentage = *mirror.sample_percentages.get(repo)?;
+    if percentage == 0 || thread_rng().gen_range(0, 100) >= percentage {
+        return None;
+    }
+    let in_flight = InFlight::start()?;
+
+    // The mirrored reads get their own context, so that they don't count towards the load and
+    // the perf counters of the client.
+    let ctx = CoreContext::new_with_logger(rctx.ctx.fb, rctx.logger.clone());
+    let repo = match mirror.mononoke.repo(ctx, repo).await {
+        Ok(repo) => repo?,
+        Err(e) => {
+            debug!(
+                rctx.logger,
+                "Failed to load read mirror of {}: {:#}", repo, e
+            );
+            return None;
+        }
+    };
+
+    Some(MirroredRead {
+        repo: repo.hg(),
+        method,
+        logger: rctx.logger.clone(),
+        _in_flight: in_flight,
+    })
+}
+
+/// What the mirrored reads return, for them to be matched with what was served.
+pub trait MirroredEntry: Clone + PartialEq + Send + 'static {
+    fn key(&self) -> &Key;
+
+    /// Roughly how much memory keeping the entry around takes.
+    fn size(&self) -> usize;
+}
+
+impl MirroredEntry for FileEntry {
+    fn key(&self) -> &Key {
+        &self.key
+    }
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl MirroredEntry for TreeEntry {
+    fn key(&self) -> &Key {
+        &self.key
+    }
+
+    fn size(&self) -> usize {
+        self.data.as_ref().map_or(0, |data| data.len())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Mismatch {
+    MissingFromMirror,
+    OnlyInMirror,
+    Different,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Self::MissingFromMirror => "missing from the mirror",
+            Self::OnlyInMirror => "only in the mirror",
+            Self::Different => "different in the mirror",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// Match entries by key. An entry that failed to be fetched counts as missing.
+fn find_mismatches<T: MirroredEntry>(served: Vec<T>, mirrored: Vec<T>) -> Vec<(Key, Mismatch)> {
+    let mut mirrored: HashMap<Key, T> = mirrored
+        .into_iter()
+        .map(|entry| (entry.key().clone(), entry))
+        .collect();
+
+    let mut mismatches = Vec::new();
+    for entry in served {
+        match mirrored.remove(entry.key()) {
+            None => mismatches.push((entry.key().clone(), Mismatch::MissingFromMirror)),
+            Some(mirrored_entry) if mirrored_entry != entry => {
+                mismatches.push((entry.key().clone(), Mismatch::Different))
+            }
+            Some(_) => {}
+        }
+    }
+    mismatches.extend(
+        mirrored
+            .into_iter()
+            .map(|(key, _)| (key, Mismatch::OnlyInMirror)),
+    );
+    mismatches.sort();
+    mismatches
+}
+
+/// What was served so far, for it to be compared once the response is complete.
+struct Served<T> {
+    entries: Vec<T>,
+    bytes: usize,
+}
+
+impl<T> Default for Served<T> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            bytes: 0,
+        }
+    }
+}
+
+/// A request that was sampled to be mirrored.
+pub struct MirroredRead {
+    repo: HgRepoContext,
+    method: EdenApiMethod,
+    logger: Logger,
+    _in_flight: InFlight,
+}
+
+impl MirroredRead {
+    /// Record the entries of the response as they are served, and once it is complete, compare
+    /// them with what `fetch` gets from the mirror. A response that isn't complete, for example
+    /// because the client went away, isn't compared, and nor is one of more than
+    /// `MAX_SERVED_BYTES`.
+    pub fn compare<S, T, E, F, M, ME>(
+        self,
+        response: S,
+        fetch: F,
+    ) -> impl Stream<Item = Result<T, E>>
+    where
+        S: Stream<Item = Result<T, E>>,
+        T: MirroredEntry,
+        F: FnOnce(HgRepoContext) -> M + Send + 'static,
+        M: Stream<Item = Result<T, ME>> + Send + 'static,
+        ME: Send + 'static,
+    {
+        // None once the response is too large to be compared.
+        let served = Arc::new(Mutex::new(Some(Served::default())));
+
+        let complete = {
+            let served = served.clone();
+            stream::once(async move {
+                match served.lock().expect("lock poisoned").take() {
+                    Some(served) => {
+                        tokio::spawn(self.run(served.entries, fetch));
+                    }
+                    None => {
+                        STATS::skipped_too_large.add_value(1, (self.method.to_string(),));
+                    }
+                }
+            })
+            .filter_map(|()| future::ready(None))
+        };
+
+        response
+            .inspect(move |res| {
+                if let Ok(entry) = res {
+                    let mut served = served.lock().expect("lock poisoned");
+                    if let Some(kept) = served.as_mut() {
+                        kept.bytes += entry.size();
+                        if kept.bytes > MAX_SERVED_BYTES {
+                            *served = None;
+                        } else {
+                            kept.entries.push(entry.clone());
+                        }
+                    }
+                }
+            })
+            .chain(complete)
+    }
+
+    async fn run<T, F, M, ME>(self, served: Vec<T>, fetch: F)
+    where
+        T: MirroredEntry,
+        F: FnOnce(HgRepoContext) -> M,
+        M: Stream<Item = Result<T, ME>>,
+    {
+        let mirrored = fetch(self.repo.clone())
+            .filter_map(|res| future::ready(res.ok()))
+            .collect::<Vec<_>>()
+            .await;
+
+        let method = self.method.to_string();
+        STATS::compared.add_value(1, (method.clone(),));
+
+        let mismatches = find_mismatches(served, mirrored);
+        if mismatches.is_empty() {
+            return;
+        }
+
+        STATS::mismatched.add_value(1, (method,));
+        warn!(
+            self.logger,
+            "EdenAPI {} read mirror returned {} mismatched entries",
+            self.method,
+            mismatches.len()
+        );
+        for (key, mismatch) in mismatches.iter().take(MAX_LOGGED_MISMATCHES) {
+            warn!(self.logger, "{} is {}", key, mismatch);
+        }
+    }
+}
@@ -23,6 +23,7 @@ pub struct ReplayData<'a> {
     basemfnodes: Cow<'a, str>,
     directories: Cow<'a, str>,
     depth: Option<Cow<'a, str>>,
+    sparseprefixes: Option<Cow<'a, str>>,
 }
 
 fn parse_directories(dirs: &str) -> Result<Vec<Vec<u8>>, Error> {
@@ -72,6 +73,13 @@ impl FromStr for RequestGettreepackArgs {
                 .map(|d| d.into())
                 .collect(),
             depth: json.depth.map(|d| d.parse()).transpose()?,
+            sparse_prefixes: match json.sparseprefixes {
+                Some(prefixes) => parse_directories(prefixes.as_ref())?
+                    .into_iter()
+                    .map(|d| d.into())
+                    .collect(),
+                None => vec![],
+            },
         };
 
         Ok(RequestGettreepackArgs(args))
@@ -11,7 +11,7 @@ use thiserror::Error;
 use crate::{expected_size::ExpectedSize, FetchKey};
 use mononoke_types::{
     hash::{RichGitSha1, Sha1, Sha256},
-    ContentId,
+    ContentChunkId, ContentId,
 };
 
 #[derive(Debug)]
@@ -39,4 +39,10 @@ pub enum ErrorKind {
 
     #[error("Missing content: {0:?}")]
     MissingContent(FetchKey),
+
+    #[error("Missing chunk {1:?} of {0:?}")]
+    MissingChunk(ContentId, ContentChunkId),
+
+    #[error("Unexpected layout for {0}")]
+    UnexpectedLayout(String),
 }
@@ -162,21 +162,28 @@ pub fn stream_file_bytes<'a, B: Blobstore + Clone + 'a>(
     }
 }
 
+/// Fetch the contents of `content_id`, unless they are given as `inline` (i.e. they were inline
+/// in the alias they were fetched by).
 pub async fn fetch_with_size<'a, B: Blobstore + Clone + 'a>(
     blobstore: B,
     ctx: impl Borrow<CoreContext> + Clone + Send + Sync + 'a,
     content_id: ContentId,
+    inline: Option<Bytes>,
     range: Range,
 ) -> Result<Option<(impl Stream<Item = Result<Bytes, Error>> + 'a, u64)>, Error> {
-    let maybe_file_contents = {
-        cloned!(ctx, blobstore);
-        async move { content_id.load(ctx.borrow(), &blobstore).await }.await
-    }
-    .map(Some)
-    .or_else(|err| match err {
-        LoadableError::Error(err) => Err(err),
-        LoadableError::Missing(_) => Ok(None),
-    })?;
+    let maybe_file_contents = match inline {
+        Some(bytes) => Some(FileContents::Bytes(bytes)),
+        None => {
+            cloned!(ctx, blobstore);
+            async move { content_id.load(ctx.borrow(), &blobstore).await }
+                .await
+                .map(Some)
+                .or_else(|err| match err {
+                    LoadableError::Error(err) => Err(err),
+                    LoadableError::Missing(_) => Ok(None),
+                })?
+        }
+    };
 
     Ok(maybe_file_contents.map(|file_contents| {
         let file_size = file_contents.size();
@@ -8,6 +8,7 @@
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use blobstore::{Blobstore, Loadable, LoadableError, Storable};
+use bytes::Bytes;
 use context::CoreContext;
 use mononoke_types::{errors::ErrorKind, hash, ContentAlias, ContentId};
 
@@ -62,6 +63,24 @@ impl Loadable for FetchKey {
     }
 }
 
+impl FetchKey {
+    /// Like `load`, but also returns the contents if the key is an alias that has them inline,
+    /// in which case they don't need to be fetched separately.
+    pub async fn resolve<B: Blobstore>(
+        &self,
+        ctx: &CoreContext,
+        blobstore: &B,
+    ) -> Result<(ContentId, Option<Bytes>), LoadableError> {
+        match self {
+            FetchKey::Canonical(content_id) => Ok((*content_id, None)),
+            FetchKey::Aliased(alias) => {
+                let alias = alias.load_alias(ctx, blobstore).await?;
+                Ok((alias.content_id(), alias.into_inline_contents()))
+            }
+        }
+    }
+}
+
 impl Alias {
     pub fn blobstore_key(&self) -> String {
         match self {
@@ -79,6 +98,21 @@ impl Alias {
             Alias::Sha256(sha256) => sha256.sampling_fingerprint(),
         }
     }
+
+    async fn load_alias<B: Blobstore>(
+        &self,
+        ctx: &CoreContext,
+        blobstore: &B,
+    ) -> Result<ContentAlias, LoadableError> {
+        let key = self.blobstore_key();
+        let get = blobstore.get(ctx, &key);
+        let maybe_alias = get.await?;
+        let blob = maybe_alias.ok_or_else(|| LoadableError::Missing(key.clone()))?;
+
+        ContentAlias::from_bytes(blob.into_raw_bytes())
+            .with_context(|| ErrorKind::BlobKeyError(key.clone()))
+            .map_err(LoadableError::Error)
+    }
 }
 
 #[async_trait]
@@ -90,15 +124,8 @@ impl Loadable for Alias {
         ctx: &'a CoreContext,
         blobstore: &'a B,
     ) -> Result<Self::Value, LoadableError> {
-        let key = self.blobstore_key();
-        let get = blobstore.get(ctx, &key);
-        let maybe_alias = get.await?;
-        let blob = maybe_alias.ok_or_else(|| LoadableError::Missing(key.clone()))?;
-
-        ContentAlias::from_bytes(blob.into_raw_bytes())
-            .map(|alias| alias.content_id())
-            .with_context(|| ErrorKind::BlobKeyError(key.clone()))
-            .map_err(LoadableError::Error)
+        let alias = self.load_alias(ctx, blobstore).await?;
+        Ok(alias.content_id())
     }
 }
 
@@ -0,0 +1,208 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::sync::Arc;
+
+use anyhow::{format_err, Error};
+use blobstore::BlobstoreWithFileHandle;
+use bytes::{Bytes, BytesMut};
+use context::CoreContext;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use mononoke_types::{ContentId, FileContents, MononokeId};
+
+use crate::errors::ErrorKind;
+
+// Compact protocol field headers for the first and second fields of a union: `(id << 4) | type`.
+const FIELD_1_BINARY: u8 = 0x18;
+const FIELD_2_STRUCT: u8 = 0x2c;
+
+// A field header, plus a 64 bit varint length.
+const MAX_HEADER_LEN: usize = 11;
+
+/// A region within a local file that holds part of a file's contents, verbatim. Segments can be
+/// handed to sendfile(2) or similar, or read with `stream_file_segments`.
+#[derive(Debug, Clone)]
+pub struct FileSegment {
+    pub file: Arc<File>,
+    pub offset: u64,
+    pub len: u64,
+}
+
+// Both FileContents and ContentChunk are thrift unions serialized with the compact protocol. When
+// the variant holding raw bytes is set, the encoding is a single field header, the payload length
+// as a varint, the payload, and a stop byte. Returns the offset and length of the payload.
+fn parse_inline_header(header: &[u8]) -> Option<(u64, u64)> {
+    if header.first() != Some(&FIELD_1_BINARY) {
+        return None;
+    }
+
+    let mut len: u64 = 0;
+    for (idx, byte) in header.iter().enumerate().skip(1) {
+        len |= u64::from(byte & 0x7f) << (7 * (idx - 1));
+        if byte & 0x80 == 0 {
+            return Some(((idx + 1) as u64, len));
+        }
+    }
+
+    None
+}
+
+async fn read_at(file: Arc<File>, offset: u64, len: usize) -> Result<Bytes, Error> {
+    tokio::task::spawn_blocking(move || {
+        let mut buf = BytesMut::new();
+        buf.resize(len, 0);
+        let mut filled = 0;
+        while filled < len {
+            let n = file.read_at(&mut buf[filled..], offset + filled as u64)?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        buf.truncate(filled);
+        Result::<_, Error>::Ok(buf.freeze())
+    })
+    .await?
+}
+
+async fn inline_segment(key: &str, file: File) -> Result<Option<FileSegment>, Error> {
+    let file = Arc::new(file);
+    let header = read_at(file.clone(), 0, MAX_HEADER_LEN).await?;
+    inline_segment_with_header(key, file, &header)
+}
+
+fn inline_segment_with_header(
+    key: &str,
+    file: Arc<File>,
+    header: &[u8],
+) -> Result<Option<FileSegment>, Error> {
+    let file_len = file.metadata()?.len();
+
+    match parse_inline_header(header) {
+        // The payload must be followed by exactly one stop byte.
+        Some((offset, len)) if offset + len + 1 == file_len => {
+            Ok(Some(FileSegment { file, offset, len }))
+        }
+        Some(_) => Err(ErrorKind::UnexpectedLayout(key.to_string()).into()),
+        None => Ok(None),
+    }
+}
+
+/// Resolve a file's contents into segments of local files. This returns None if the content does
+/// not exist.
+pub async fn fetch_file_segments<B: BlobstoreWithFileHandle>(
+    blobstore: &B,
+    ctx: &CoreContext,
+    content_id: ContentId,
+) -> Result<Option<(Vec<FileSegment>, u64)>, Error> {
+    let key = content_id.blobstore_key();
+
+    let file = match blobstore.open_file(ctx, &key).await? {
+        Some(file) => file,
+        None => return Ok(None),
+    };
+
+    let file = Arc::new(file);
+    let header = read_at(file.clone(), 0, MAX_HEADER_LEN).await?;
+
+    match header.first() {
+        Some(&FIELD_1_BINARY) => {
+            let segment = inline_segment_with_header(&key, file, &header)?
+                .ok_or_else(|| ErrorKind::UnexpectedLayout(key.clone()))?;
+            let size = segment.len;
+            return Ok(Some((vec![segment], size)));
+        }
+        Some(&FIELD_2_STRUCT) => {}
+        _ => return Err(ErrorKind::UnexpectedLayout(key).into()),
+    }
+
+    // The chunk list is small, so just decode it normally.
+    let file_len = file.metadata()?.len().try_into()?;
+    let encoded = read_at(file, 0, file_len).await?;
+    let chunked = match FileContents::from_encoded_bytes(encoded)? {
+        FileContents::Chunked(chunked) => chunked,
+        FileContents::Bytes(..) => return Err(ErrorKind::UnexpectedLayout(key).into()),
+    };
+
+    let size = chunked.size();
+
+    let segments = stream::iter(chunked.into_chunks())
+        .map(|chunk| async move {
+            let chunk_key = chunk.chunk_id().blobstore_key();
+            let file = blobstore
+                .open_file(ctx, &chunk_key)
+                .await?
+                .ok_or_else(|| ErrorKind::MissingChunk(content_id, chunk.chunk_id()))?;
+
+            let segment = inline_segment(&chunk_key, file)
+                .await?
+                .ok_or_else(|| ErrorKind::UnexpectedLayout(chunk_key.clone()))?;
+
+            if segment.len != chunk.size() {
+                return Err(ErrorKind::UnexpectedLayout(chunk_key).into());
+            }
+
+            Result::<_, Error>::Ok(segment)
+        })
+        .buffered(16)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    Ok(Some((segments, size)))
+}
+
+/// Read segments into a stream of Bytes, using reads of at most `read_size`. This skips decoding
+/// the blobs entirely, so that each byte is only copied once on its way to the consumer.
+pub fn stream_file_segments(
+    segments: Vec<FileSegment>,
+    read_size: u64,
+) -> impl Stream<Item = Result<Bytes, Error>> {
+    let read_size = std::cmp::max(read_size, 1);
+
+    stream::iter(segments)
+        .flat_map(move |segment| {
+            let FileSegment { file, offset, len } = segment;
+            let reads = (0..len).step_by(read_size as usize).map(move |start| {
+                let read_len = std::cmp::min(read_size, len - start);
+                (file.clone(), offset + start, read_len)
+            });
+            stream::iter(reads)
+        })
+        .then(|(file, offset, len)| async move {
+            let len = len.try_into()?;
+            let bytes = read_at(file, offset, len).await?;
+            if bytes.len() != len {
+                return Err(format_err!(
+                    "Short read: expected {} bytes at offset {}, got {}",
+                    len,
+                    offset,
+                    bytes.len()
+                ));
+            }
+            Ok(bytes)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_inline_header() {
+        assert_eq!(parse_inline_header(&[FIELD_1_BINARY, 0x05]), Some((2, 5)));
+        assert_eq!(
+            parse_inline_header(&[FIELD_1_BINARY, 0xac, 0x02]),
+            Some((3, 300))
+        );
+        assert_eq!(parse_inline_header(&[FIELD_2_STRUCT, 0x05]), None);
+        assert_eq!(parse_inline_header(&[FIELD_1_BINARY, 0x80]), None);
+        assert_eq!(parse_inline_header(&[]), None);
+    }
+}
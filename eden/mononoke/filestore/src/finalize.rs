@@ -7,9 +7,11 @@
 
 use anyhow::Error;
 use blobstore::{Blobstore, Storable};
+use bytes::Bytes;
 use context::CoreContext;
 use futures::future;
-use mononoke_types::{BlobstoreValue, ContentAlias, ContentMetadata};
+use mononoke_types::{BlobstoreValue, ContentAlias, ContentMetadata, FileContents};
+use tunables::tunables;
 
 use crate::errors::{ErrorKind, InvalidHash};
 use crate::fetch_key::{Alias, AliasBlob};
@@ -32,10 +34,25 @@ fn check_hash<T: std::fmt::Debug + PartialEq + Copy>(
     Ok(())
 }
 
+/// The contents to store inline in the aliases of a file, if it is small enough for that and
+/// writing inline aliases is enabled.
+pub fn inline_contents(inline_threshold: Option<u64>, contents: &FileContents) -> Option<Bytes> {
+    if !tunables().get_filestore_write_inline_aliases() {
+        return None;
+    }
+    match (inline_threshold, contents) {
+        (Some(threshold), FileContents::Bytes(bytes)) if contents.size() <= threshold => {
+            Some(bytes.clone())
+        }
+        _ => None,
+    }
+}
+
 pub async fn finalize<B: Blobstore>(
     blobstore: &B,
     ctx: &CoreContext,
     req: Option<&StoreRequest>,
+    inline_threshold: Option<u64>,
     outcome: Prepared,
 ) -> Result<ContentMetadata, Error> {
     let Prepared {
@@ -46,6 +63,7 @@ pub async fn finalize<B: Blobstore>(
     } = outcome;
 
     let total_size = contents.size();
+    let inline = inline_contents(inline_threshold, &contents);
 
     let blob = contents.into_blob();
     let content_id = *blob.id();
@@ -71,10 +89,13 @@ pub async fn finalize<B: Blobstore>(
         }
     }
 
-    let alias = ContentAlias::from_content_id(content_id);
-    let put_sha1 = AliasBlob(Alias::Sha1(sha1), alias.clone()).store(ctx, blobstore);
-    let put_sha256 = AliasBlob(Alias::Sha256(sha256), alias.clone()).store(ctx, blobstore);
-    let put_git_sha1 = AliasBlob(Alias::GitSha1(git_sha1.sha1()), alias).store(ctx, blobstore);
+    let metadata = ContentMetadata {
+        total_size,
+        content_id,
+        sha1,
+        git_sha1,
+        sha256,
+    };
 
     // Since we don't have atomicity for multiple puts, we need to make sure they're ordered
     // correctly:
@@ -92,21 +113,41 @@ pub async fn finalize<B: Blobstore>(
     // Once the data blob is written we can write the metadata object. This is just a
     // cache, as everything in it can be computed from the content id. Therefore, in principle,
     // if it doesn't get written we can fix it up later.
+    //
+    // Inline contents don't change this: the data blob is still written, since that's what
+    // everything that isn't fetching by alias reads. An alias with inline contents can serve
+    // them before the data blob exists, but those contents were hashed above, so they are
+    // the right ones.
 
-
-    future::try_join3(put_sha1, put_sha256, put_git_sha1).await?;
+    store_aliases(blobstore, ctx, &metadata, inline).await?;
 
     blob.store(ctx, blobstore).await?;
 
-    let metadata = ContentMetadata {
-        total_size,
-        content_id,
-        sha1,
-        git_sha1,
-        sha256,
-    };
-
     metadata.clone().into_blob().store(ctx, blobstore).await?;
 
     Ok(metadata)
 }
+
+/// Write the forward-mapping aliases for the content described by `metadata`, with the
+/// contents inline if given.
+pub async fn store_aliases<B: Blobstore>(
+    blobstore: &B,
+    ctx: &CoreContext,
+    metadata: &ContentMetadata,
+    inline: Option<Bytes>,
+) -> Result<(), Error> {
+    let alias = match inline {
+        Some(contents) => {
+            ContentAlias::from_content_id_with_contents(metadata.content_id, contents)
+        }
+        None => ContentAlias::from_content_id(metadata.content_id),
+    };
+    let put_sha1 = AliasBlob(Alias::Sha1(metadata.sha1), alias.clone()).store(ctx, blobstore);
+    let put_sha256 = AliasBlob(Alias::Sha256(metadata.sha256), alias.clone()).store(ctx, blobstore);
+    let put_git_sha1 =
+        AliasBlob(Alias::GitSha1(metadata.git_sha1.sha1()), alias).store(ctx, blobstore);
+
+    future::try_join3(put_sha1, put_sha256, put_git_sha1).await?;
+
+    Ok(())
+}
@@ -18,7 +18,7 @@ use futures::{
 };
 use std::{borrow::Borrow, convert::TryInto};
 
-use blobstore::{Blobstore, Loadable, LoadableError};
+use blobstore::{Blobstore, BlobstoreWithFileHandle, Loadable, LoadableError};
 use context::CoreContext;
 use mononoke_types::{hash, ContentId, ContentMetadata, FileContents, MononokeId};
 
@@ -28,16 +28,24 @@ mod errors;
 mod expected_size;
 mod fetch;
 mod fetch_key;
+mod file_segments;
 mod finalize;
 mod incremental_hash;
 mod metadata;
+mod metadata_cache;
 mod multiplexer;
 mod prepare;
 mod rechunk;
+mod register;
+mod rehash;
 mod streamhash;
 
 pub use fetch_key::{Alias, AliasBlob, FetchKey};
+pub use file_segments::{stream_file_segments, FileSegment};
+pub use metadata_cache::MetadataCacheBlobstore;
 pub use rechunk::{force_rechunk, rechunk};
+pub use register::register_existing;
+pub use rehash::rehash;
 
 #[cfg(test)]
 mod test;
@@ -64,6 +72,10 @@ mod test;
 pub struct FilestoreConfig {
     pub chunk_size: Option<u64>,
     pub concurrency: usize,
+    /// Files up to this size are also stored inline in their aliases, so that fetching them by
+    /// alias doesn't need a second round-trip to the blobstore for the contents. Such aliases are
+    /// only written while the `filestore_write_inline_aliases` tunable is set.
+    pub inline_threshold: Option<u64>,
 }
 
 impl Default for FilestoreConfig {
@@ -71,6 +83,7 @@ impl Default for FilestoreConfig {
         FilestoreConfig {
             chunk_size: None,
             concurrency: 1,
+            inline_threshold: None,
         }
     }
 }
@@ -227,18 +240,18 @@ pub async fn fetch_with_size<'a, B: Blobstore + Clone + 'a>(
     ctx: impl Borrow<CoreContext> + Clone + Send + Sync + 'a,
     key: &FetchKey,
 ) -> Result<Option<(impl Stream<Item = Result<Bytes, Error>> + 'a, u64)>, Error> {
-    let content_id =
-        key.load(ctx.borrow(), &blobstore)
-            .await
-            .map(Some)
-            .or_else(|err| match err {
-                LoadableError::Error(err) => Err(err),
-                LoadableError::Missing(_) => Ok(None),
-            })?;
+    let resolved = key
+        .resolve(ctx.borrow(), &blobstore)
+        .await
+        .map(Some)
+        .or_else(|err| match err {
+            LoadableError::Error(err) => Err(err),
+            LoadableError::Missing(_) => Ok(None),
+        })?;
 
-    match content_id {
-        Some(content_id) => {
-            fetch::fetch_with_size(blobstore, ctx, content_id, fetch::Range::All).await
+    match resolved {
+        Some((content_id, inline)) => {
+            fetch::fetch_with_size(blobstore, ctx, content_id, inline, fetch::Range::All).await
         }
         None => Ok(None),
     }
@@ -256,8 +269,8 @@ pub async fn fetch_range_with_size<'a, B: Blobstore>(
     start: u64,
     size: u64,
 ) -> Result<Option<(impl Stream<Item = Result<Bytes, Error>> + 'a, u64)>, Error> {
-    let content_id = key
-        .load(ctx, blobstore)
+    let resolved = key
+        .resolve(ctx, blobstore)
         .await
         .map(Some)
         .or_else(|err| match err {
@@ -265,12 +278,13 @@ pub async fn fetch_range_with_size<'a, B: Blobstore>(
             LoadableError::Missing(_) => Ok(None),
         })?;
 
-    match content_id {
-        Some(content_id) => {
+    match resolved {
+        Some((content_id, inline)) => {
             fetch::fetch_with_size(
                 blobstore,
                 ctx,
                 content_id,
+                inline,
                 fetch::Range::Span {
                     start,
                     end: start.saturating_add(size),
@@ -282,6 +296,30 @@ pub async fn fetch_range_with_size<'a, B: Blobstore>(
     }
 }
 
+/// Fetch a file as a list of segments of local files, along with its size. This is only available
+/// for blobstores that keep their values in local files (i.e. Fileblob), and lets callers serve
+/// large content with sendfile(2), or `stream_file_segments`, rather than by decoding each chunk
+/// in userspace. Returns None if the file does not exist.
+pub async fn fetch_file_segments<B: BlobstoreWithFileHandle>(
+    blobstore: &B,
+    ctx: &CoreContext,
+    key: &FetchKey,
+) -> Result<Option<(Vec<FileSegment>, u64)>, Error> {
+    let content_id = key
+        .load(ctx, blobstore)
+        .await
+        .map(Some)
+        .or_else(|err| match err {
+            LoadableError::Error(err) => Err(err),
+            LoadableError::Missing(_) => Ok(None),
+        })?;
+
+    match content_id {
+        Some(content_id) => file_segments::fetch_file_segments(blobstore, ctx, content_id).await,
+        None => Ok(None),
+    }
+}
+
 /// This function has the same functionality as fetch_with_size, but doesn't return the file size.
 pub async fn fetch<'a, B: Blobstore + Clone + 'a>(
     blobstore: B,
@@ -411,7 +449,14 @@ pub async fn store<B: Blobstore + Clone + 'static>(
         }
     };
 
-    finalize::finalize(blobstore, ctx, Some(&req), prepared).await
+    finalize::finalize(
+        blobstore,
+        ctx,
+        Some(&req),
+        config.inline_threshold,
+        prepared,
+    )
+    .await
 }
 
 /// Store a set of bytes, and immediately return their Contentid and size. This function is
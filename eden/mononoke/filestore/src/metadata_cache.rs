@@ -0,0 +1,163 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::fmt;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use blobstore::{Blobstore, BlobstoreGetData, PutBehaviour};
+use bytes::Bytes;
+use cachelib::VolatileLruCachePool;
+use caching_ext::{CachelibHandler, MemcacheHandler};
+use context::CoreContext;
+use fbinit::FacebookInit;
+use memcache::{KeyGen, MemcacheClient};
+use mononoke_types::{BlobstoreBytes, ContentMetadataId, MononokeId};
+use stats::prelude::*;
+
+// Bump these to drop everything that is in memcache, e.g. if the encoding of the metadata changes.
+const MC_CODEVER: u32 = 0;
+const MC_SITEVER: u32 = 0;
+
+define_stats! {
+    prefix = "mononoke.filestore.metadata_cache";
+    cachelib_hit: timeseries(Rate, Sum),
+    memcache_hit: timeseries(Rate, Sum),
+    miss: timeseries(Rate, Sum),
+    overwrite: timeseries(Rate, Sum),
+}
+
+/// A layer over a blobstore that keeps the ContentMetadata blobs of the filestore in cachelib and
+/// memcache. Those are fetched for every file that is served, and are small enough for a
+/// dedicated pool to hold a lot of them, so that they don't get evicted by the file contents.
+///
+/// If the blobstore overwrites existing keys, a put of a metadata blob replaces what is cached
+/// for it, so that the caches don't keep serving the old metadata. With `IfAbsent`, the blob
+/// that is already stored stays, and so does its cached copy.
+pub struct MetadataCacheBlobstore<B> {
+    blobstore: B,
+    cachelib: CachelibHandler<Vec<u8>>,
+    memcache: MemcacheHandler,
+    keygen: KeyGen,
+    put_behaviour: PutBehaviour,
+}
+
+impl<B> MetadataCacheBlobstore<B> {
+    pub fn new(
+        fb: FacebookInit,
+        blobstore: B,
+        cache_pool: VolatileLruCachePool,
+        put_behaviour: PutBehaviour,
+    ) -> Result<Self> {
+        Ok(Self::new_with_handlers(
+            blobstore,
+            cache_pool.into(),
+            MemcacheClient::new(fb)?.into(),
+            put_behaviour,
+        ))
+    }
+
+    pub fn new_with_handlers(
+        blobstore: B,
+        cachelib: CachelibHandler<Vec<u8>>,
+        memcache: MemcacheHandler,
+        put_behaviour: PutBehaviour,
+    ) -> Self {
+        Self {
+            blobstore,
+            cachelib,
+            memcache,
+            keygen: KeyGen::new("scm.mononoke.filestore.metadata", MC_CODEVER, MC_SITEVER),
+            put_behaviour,
+        }
+    }
+
+    async fn fill_caches(&self, key: &str, value: &Bytes) {
+        let _ = self.cachelib.set_cached(&key.to_string(), &value.to_vec());
+        let _ = self.memcache.set(self.keygen.key(key), value.clone()).await;
+    }
+}
+
+// Keys might be prefixed (e.g. with the repo), so look for the metadata prefix anywhere.
+fn is_metadata_key(key: &str) -> bool {
+    key.contains(ContentMetadataId::blobstore_key_prefix())
+}
+
+impl<B: fmt::Display> fmt::Display for MetadataCacheBlobstore<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MetadataCacheBlobstore<{}>", self.blobstore)
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for MetadataCacheBlobstore<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetadataCacheBlobstore")
+            .field("blobstore", &self.blobstore)
+            .field("put_behaviour", &self.put_behaviour)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<B: Blobstore> Blobstore for MetadataCacheBlobstore<B> {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        if !is_metadata_key(key) {
+            return self.blobstore.get(ctx, key).await;
+        }
+
+        if let Ok(Some(value)) = self.cachelib.get_cached(&key.to_string()) {
+            STATS::cachelib_hit.add_value(1);
+            return Ok(Some(BlobstoreGetData::from_bytes(value)));
+        }
+
+        if let Ok(Some(value)) = self.memcache.get(self.keygen.key(key)).await {
+            STATS::memcache_hit.add_value(1);
+            let _ = self.cachelib.set_cached(&key.to_string(), &value.to_vec());
+            return Ok(Some(BlobstoreGetData::from_bytes(value)));
+        }
+
+        STATS::miss.add_value(1);
+        let value = self.blobstore.get(ctx, key).await?;
+        if let Some(value) = &value {
+            self.fill_caches(key, value.as_raw_bytes()).await;
+        }
+        Ok(value)
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        if !is_metadata_key(&key) {
+            return self.blobstore.put(ctx, key, value).await;
+        }
+
+        let bytes = value.as_bytes().clone();
+        self.blobstore.put(ctx, key.clone(), value).await?;
+        if self.put_behaviour.should_overwrite() {
+            STATS::overwrite.add_value(1);
+            self.fill_caches(&key, &bytes).await;
+        }
+        Ok(())
+    }
+
+    async fn is_present<'a>(&'a self, ctx: &'a CoreContext, key: &'a str) -> Result<bool> {
+        if is_metadata_key(key) {
+            if let Ok(Some(_)) = self.cachelib.get_cached(&key.to_string()) {
+                STATS::cachelib_hit.add_value(1);
+                return Ok(true);
+            }
+        }
+        self.blobstore.is_present(ctx, key).await
+    }
+}
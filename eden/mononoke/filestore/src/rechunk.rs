@@ -155,6 +155,7 @@ async fn rechunk_if_uses_larger_chunk_size<B: Blobstore + Clone + 'static>(
         let filestore_config = FilestoreConfig {
             chunk_size: Some(expected_chunk_size),
             concurrency,
+            inline_threshold: None,
         };
 
         let content_metadata: ContentMetadata =
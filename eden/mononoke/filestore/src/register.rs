@@ -0,0 +1,103 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use futures::{
+    future::TryFutureExt,
+    stream::{self, StreamExt, TryStreamExt},
+};
+
+use blobstore::{Blobstore, Loadable, LoadableError, Storable};
+use context::CoreContext;
+use mononoke_types::{BlobstoreValue, ContentMetadata, FileContents, MononokeId};
+
+use crate::errors::ErrorKind;
+use crate::finalize::{inline_contents, store_aliases};
+use crate::{fetch, store, FetchKey, FilestoreConfig, StoreRequest};
+
+/// Return true if `file_contents` is chunked the way `store` would chunk it with `chunk_size`.
+fn has_chunk_size(file_contents: &FileContents, chunk_size: Option<u64>) -> bool {
+    match (file_contents, chunk_size) {
+        (FileContents::Bytes(_), None) => true,
+        (FileContents::Bytes(bytes), Some(chunk_size)) => bytes.len() as u64 <= chunk_size,
+        (FileContents::Chunked(_), None) => false,
+        (FileContents::Chunked(chunked), Some(chunk_size)) => {
+            let num_chunks = chunked.num_chunks();
+            chunked.size() > chunk_size
+                && chunked.iter_chunks().enumerate().all(|(idx, chunk)| {
+                    // Only the last chunk can be smaller.
+                    chunk.size() == chunk_size
+                        || (idx == num_chunks - 1 && chunk.size() < chunk_size)
+                })
+        }
+    }
+}
+
+/// Register content that is already in the blobstore, e.g. blobs that were copied over by an
+/// import, so that it can be fetched through its aliases and has its metadata. When the content
+/// is chunked the way `config` would chunk it, the data is not read at all: only the chunks'
+/// presence is checked. Otherwise, the content is read and stored again with `config`.
+///
+/// NOTE: Hashes in `metadata` are trusted when the data isn't read, so they must have been
+/// computed from the content itself, e.g. by the system it is imported from.
+///
+/// Returns whether the content had to be rechunked.
+pub async fn register_existing<B: Blobstore + Clone + 'static>(
+    blobstore: &B,
+    config: FilestoreConfig,
+    ctx: &CoreContext,
+    metadata: ContentMetadata,
+) -> Result<bool, Error> {
+    let content_id = metadata.content_id;
+    let file_contents: FileContents = content_id
+        .load(ctx, blobstore)
+        .map_err(move |err| match err {
+            LoadableError::Error(err) => err,
+            LoadableError::Missing(_) => {
+                ErrorKind::MissingContent(FetchKey::Canonical(content_id)).into()
+            }
+        })
+        .await?;
+
+    let req = StoreRequest {
+        canonical: Some(content_id),
+        sha1: Some(metadata.sha1),
+        sha256: Some(metadata.sha256),
+        git_sha1: Some(metadata.git_sha1),
+        ..StoreRequest::new(metadata.total_size)
+    };
+    req.expected_size.check_equals(file_contents.size())?;
+
+    if !has_chunk_size(&file_contents, config.chunk_size) {
+        let file_stream =
+            fetch::stream_file_bytes(blobstore, ctx, file_contents, fetch::Range::All);
+        store(blobstore, config, ctx, &req, file_stream).await?;
+        return Ok(true);
+    }
+
+    if let FileContents::Chunked(chunked) = &file_contents {
+        stream::iter(chunked.iter_chunks().map(|chunk| chunk.chunk_id()))
+            .map(|chunk_id| async move {
+                if blobstore.is_present(ctx, &chunk_id.blobstore_key()).await? {
+                    Ok(())
+                } else {
+                    Err(ErrorKind::MissingChunk(content_id, chunk_id).into())
+                }
+            })
+            .buffer_unordered(config.concurrency)
+            .try_collect::<()>()
+            .await?;
+    }
+
+    // Same order as when storing: the content is already there, so the aliases are valid as soon
+    // as they are written, and the metadata comes last.
+    let inline = inline_contents(config.inline_threshold, &file_contents);
+    store_aliases(blobstore, ctx, &metadata, inline).await?;
+    metadata.into_blob().store(ctx, blobstore).await?;
+
+    Ok(false)
+}
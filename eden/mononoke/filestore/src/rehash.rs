@@ -0,0 +1,79 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use blobstore::{Blobstore, Loadable, LoadableError};
+use bytes::Bytes;
+use context::CoreContext;
+use futures::{
+    future::{self, TryFutureExt},
+    stream::StreamExt,
+    task::Poll,
+};
+use mononoke_types::{ContentId, ContentMetadata};
+
+use crate::alias::add_aliases_to_multiplexer;
+use crate::expected_size::ExpectedSize;
+use crate::fetch;
+use crate::incremental_hash::ContentIdIncrementalHasher;
+use crate::multiplexer::{Multiplexer, MultiplexerError};
+use crate::streamhash::hash_stream;
+
+/// Recompute the metadata of a content from its bytes, rather than trusting what is stored: the
+/// content id in the result is the hash of the bytes, which doesn't match `content_id` if they
+/// are corrupt, and the size is how many bytes there are. Returns None if the content does not
+/// exist. Fails if one of its chunks is missing, or if its bytes don't add up to the size it
+/// claims, since the git sha1 can't be computed then.
+pub async fn rehash<B: Blobstore>(
+    blobstore: &B,
+    ctx: &CoreContext,
+    content_id: ContentId,
+) -> Result<Option<ContentMetadata>, Error> {
+    let file_contents = match content_id.load(ctx, blobstore).await {
+        Ok(file_contents) => file_contents,
+        Err(LoadableError::Missing(_)) => return Ok(None),
+        Err(LoadableError::Error(e)) => return Err(e),
+    };
+
+    let expected_size = ExpectedSize::new(file_contents.size());
+    let bytes = fetch::stream_file_bytes(blobstore, ctx, file_contents, fetch::Range::All);
+
+    let mut multiplexer = Multiplexer::<Bytes>::new();
+    let content_id =
+        multiplexer.add(|stream| hash_stream(ContentIdIncrementalHasher::new(), stream));
+    let total_size = multiplexer
+        .add(|stream| stream.fold(0, |size, bytes| future::ready(size + bytes.len() as u64)));
+    let aliases = add_aliases_to_multiplexer(&mut multiplexer, expected_size);
+
+    let res = multiplexer.drain(bytes).await;
+
+    let futs = future::try_join3(
+        content_id.map_err(Error::from),
+        total_size.map_err(Error::from),
+        aliases,
+    );
+
+    match res {
+        Ok(()) => {
+            let (content_id, total_size, aliases) = futs.await?;
+            let (sha1, sha256, git_sha1) = aliases.redeem(total_size)?;
+            Ok(Some(ContentMetadata {
+                total_size,
+                content_id,
+                sha1,
+                sha256,
+                git_sha1,
+            }))
+        }
+        // As when storing, a reader that failed is a better error than the cancellation.
+        Err(m @ MultiplexerError::Cancelled) => match futures::poll!(futs) {
+            Poll::Ready(Err(e)) => Err(e),
+            _ => Err(m.into()),
+        },
+        Err(m @ MultiplexerError::InputError(..)) => Err(m.into()),
+    }
+}
@@ -12,24 +12,31 @@ use crate::{errors, Alias, FetchKey, FilestoreConfig, StoreRequest};
 use super::failing_blobstore::{FailingBlobstore, FailingBlobstoreError};
 use anyhow::{Error, Result};
 use assert_matches::assert_matches;
-use blobstore::{Blobstore, PutBehaviour};
+use blobstore::{Blobstore, PutBehaviour, Storable};
 use borrowed::borrowed;
 use bytes::{Bytes, BytesMut};
+use caching_ext::{CachelibHandler, MemcacheHandler};
 use context::CoreContext;
 use fbinit::FacebookInit;
 use futures::{
-    future::{self, TryFutureExt},
+    future::{self, FutureExt, TryFutureExt},
     stream::{self, TryStreamExt},
 };
 use lazy_static::lazy_static;
-use mononoke_types::{hash, typed_hash::MononokeId, ContentId, ContentMetadata, ContentMetadataId};
+use maplit::hashmap;
+use mononoke_types::{
+    content_chunk, hash, typed_hash::MononokeId, BlobstoreValue, ChunkedFileContents, ContentId,
+    ContentMetadata, ContentMetadataId, FileContents,
+};
 use mononoke_types_mocks::contentid::ONES_CTID;
+use tunables::{with_tunables_async, MononokeTunables};
 
 const HELLO_WORLD: &[u8] = b"hello, world";
 const HELLO_WORLD_LENGTH: u64 = 12;
 const DEFAULT_CONFIG: FilestoreConfig = FilestoreConfig {
     chunk_size: None,
     concurrency: 1,
+    inline_threshold: None,
 };
 
 lazy_static! {
@@ -216,6 +223,7 @@ async fn filestore_chunked_put_get(fb: FacebookInit) -> Result<()> {
     let config = FilestoreConfig {
         chunk_size: Some(1),
         concurrency: 5,
+        inline_threshold: None,
     };
 
     let ctx = CoreContext::test_mock(fb);
@@ -245,10 +253,12 @@ async fn filestore_chunked_put_get_nested(fb: FacebookInit) -> Result<()> {
     let small = FilestoreConfig {
         chunk_size: Some(1),
         concurrency: 5,
+        inline_threshold: None,
     };
     let large = FilestoreConfig {
         chunk_size: Some(3),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -309,6 +319,7 @@ async fn filestore_chunk_not_found(fb: FacebookInit) -> Result<()> {
     let config = FilestoreConfig {
         chunk_size: Some(3),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -350,6 +361,7 @@ async fn filestore_put_invalid_size(fb: FacebookInit) -> Result<()> {
     let config = FilestoreConfig {
         chunk_size: Some(3),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -379,6 +391,7 @@ async fn filestore_put_content_id(fb: FacebookInit) -> Result<()> {
     let config = FilestoreConfig {
         chunk_size: Some(3),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -422,6 +435,7 @@ async fn filestore_put_sha1(fb: FacebookInit) -> Result<()> {
     let config = FilestoreConfig {
         chunk_size: Some(3),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -464,6 +478,7 @@ async fn filestore_put_git_sha1(fb: FacebookInit) -> Result<()> {
     let config = FilestoreConfig {
         chunk_size: Some(3),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -510,6 +525,7 @@ async fn filestore_put_sha256(fb: FacebookInit) -> Result<()> {
     let config = FilestoreConfig {
         chunk_size: Some(3),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -599,6 +615,7 @@ async fn filestore_get_chunked_range(fb: FacebookInit) -> Result<()> {
     let small = FilestoreConfig {
         chunk_size: Some(3),
         concurrency: 5,
+        inline_threshold: None,
     };
 
     let blob = memblob::Memblob::default();
@@ -794,6 +811,7 @@ async fn filestore_test_chunked_peek(fb: FacebookInit) -> Result<()> {
     let small = FilestoreConfig {
         chunk_size: Some(1),
         concurrency: 5,
+        inline_threshold: None,
     };
 
     let blob = memblob::Memblob::default();
@@ -903,6 +921,7 @@ async fn filestore_store_error(fb: FacebookInit) -> Result<()> {
     let config = FilestoreConfig {
         chunk_size: Some(1),
         concurrency: 5,
+        inline_threshold: None,
     };
 
     let res = filestore::store(
@@ -929,10 +948,12 @@ async fn filestore_test_rechunk(fb: FacebookInit) -> Result<()> {
     let small = FilestoreConfig {
         chunk_size: Some(1),
         concurrency: 5,
+        inline_threshold: None,
     };
     let large = FilestoreConfig {
         chunk_size: Some(3),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -966,10 +987,12 @@ async fn filestore_test_rechunk_larger(fb: FacebookInit) -> Result<()> {
     let small = FilestoreConfig {
         chunk_size: Some(1),
         concurrency: 5,
+        inline_threshold: None,
     };
     let large = FilestoreConfig {
         chunk_size: Some(3),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -1003,11 +1026,13 @@ async fn filestore_test_rechunk_unchunked(fb: FacebookInit) -> Result<()> {
     let small = FilestoreConfig {
         chunk_size: Some(1),
         concurrency: 5,
+        inline_threshold: None,
     };
     // This is large enough that the data we upload won't be chunked.
     let large = FilestoreConfig {
         chunk_size: Some(100),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -1039,6 +1064,7 @@ async fn filestore_test_rechunk_missing_content(fb: FacebookInit) -> Result<()>
     let conf = FilestoreConfig {
         chunk_size: Some(1),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
     borrowed!(ctx, blob);
@@ -1067,6 +1093,7 @@ async fn filestore_chunked_put_get_with_size(fb: FacebookInit) -> Result<()> {
     let config = FilestoreConfig {
         chunk_size: Some(1),
         concurrency: 5,
+        inline_threshold: None,
     };
 
     let ctx = CoreContext::test_mock(fb);
@@ -1110,10 +1137,12 @@ async fn filestore_test_rechunk_if_needed_tiny_unchunked_file(fb: FacebookInit)
     let large1 = FilestoreConfig {
         chunk_size: Some(100),
         concurrency: 5,
+        inline_threshold: None,
     };
     let large2 = FilestoreConfig {
         chunk_size: Some(200),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -1153,10 +1182,12 @@ async fn filestore_test_rechunk_if_needed_large_unchunked_file(fb: FacebookInit)
     let large = FilestoreConfig {
         chunk_size: Some(100),
         concurrency: 5,
+        inline_threshold: None,
     };
     let small = FilestoreConfig {
         chunk_size: Some(1),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -1191,10 +1222,12 @@ async fn filestore_test_rechunk_if_needed_large_chunks(fb: FacebookInit) -> Resu
     let large = FilestoreConfig {
         chunk_size: Some(5),
         concurrency: 5,
+        inline_threshold: None,
     };
     let small = FilestoreConfig {
         chunk_size: Some(1),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -1229,6 +1262,7 @@ async fn filestore_test_rechunk_if_needed_tiny_chunks(fb: FacebookInit) -> Resul
     let large = FilestoreConfig {
         chunk_size: Some(4),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
 
@@ -1260,6 +1294,128 @@ async fn filestore_test_rechunk_if_needed_tiny_chunks(fb: FacebookInit) -> Resul
     assert_fetches_as(ctx, blob, full_id, vec!["foob", "ar"]).await
 }
 
+// Write `data` to the blobstore the way an import that copies raw blobs would: content and chunks,
+// but no aliases or metadata.
+async fn store_raw_chunks<B: Blobstore>(
+    ctx: &CoreContext,
+    blobstore: &B,
+    content_id: ContentId,
+    data: Vec<&'static [u8]>,
+) -> Result<()> {
+    let mut pointers = vec![];
+    for chunk in data {
+        let (blob, pointer) = content_chunk::new_blob_and_pointer(Bytes::from(chunk));
+        blob.store(ctx, blobstore).await?;
+        pointers.push(pointer);
+    }
+    FileContents::Chunked(ChunkedFileContents::new(content_id, pointers))
+        .into_blob()
+        .store(ctx, blobstore)
+        .await?;
+    Ok(())
+}
+
+#[fbinit::test]
+async fn filestore_test_register_existing(fb: FacebookInit) -> Result<()> {
+    let blob = memblob::Memblob::default();
+    let conf = FilestoreConfig {
+        chunk_size: Some(5),
+        concurrency: 5,
+        inline_threshold: None,
+    };
+    let ctx = CoreContext::test_mock(fb);
+    let content_id = canonical(HELLO_WORLD);
+    borrowed!(ctx, blob);
+
+    store_raw_chunks(ctx, blob, content_id, vec![b"hello", b", wor", b"ld"]).await?;
+    assert_eq!(
+        filestore::get_metadata_readonly(blob, ctx, &FetchKey::Canonical(content_id)).await?,
+        Some(None)
+    );
+
+    let metadata = ContentMetadata {
+        total_size: HELLO_WORLD_LENGTH,
+        content_id,
+        sha1: *HELLO_WORLD_SHA1,
+        git_sha1: *HELLO_WORLD_GIT_SHA1,
+        sha256: *HELLO_WORLD_SHA256,
+    };
+    let rechunked = filestore::register_existing(blob, conf, ctx, metadata.clone()).await?;
+    assert!(!rechunked);
+
+    let key = FetchKey::Aliased(Alias::Sha256(*HELLO_WORLD_SHA256));
+    assert_eq!(
+        filestore::get_metadata_readonly(blob, ctx, &key).await?,
+        Some(Some(metadata))
+    );
+    assert_fetches_as(ctx, blob, content_id, vec!["hello", ", wor", "ld"]).await
+}
+
+#[fbinit::test]
+async fn filestore_test_register_existing_rechunk(fb: FacebookInit) -> Result<()> {
+    let blob = memblob::Memblob::new(PutBehaviour::Overwrite);
+    let conf = FilestoreConfig {
+        chunk_size: Some(6),
+        concurrency: 5,
+        inline_threshold: None,
+    };
+    let ctx = CoreContext::test_mock(fb);
+    let content_id = canonical(HELLO_WORLD);
+    borrowed!(ctx, blob);
+
+    store_raw_chunks(ctx, blob, content_id, vec![b"hello", b", wor", b"ld"]).await?;
+
+    let metadata = ContentMetadata {
+        total_size: HELLO_WORLD_LENGTH,
+        content_id,
+        sha1: *HELLO_WORLD_SHA1,
+        git_sha1: *HELLO_WORLD_GIT_SHA1,
+        sha256: *HELLO_WORLD_SHA256,
+    };
+    let rechunked = filestore::register_existing(blob, conf, ctx, metadata).await?;
+    assert!(rechunked);
+
+    assert_fetches_as(ctx, blob, content_id, vec!["hello,", " world"]).await
+}
+
+#[fbinit::test]
+async fn filestore_test_register_existing_missing_chunk(fb: FacebookInit) -> Result<()> {
+    let blob = memblob::Memblob::default();
+    let conf = FilestoreConfig {
+        chunk_size: Some(6),
+        concurrency: 5,
+        inline_threshold: None,
+    };
+    let ctx = CoreContext::test_mock(fb);
+    let content_id = canonical(HELLO_WORLD);
+    borrowed!(ctx, blob);
+
+    // Only write the content, not its chunks.
+    let (_, first) = content_chunk::new_blob_and_pointer(Bytes::from(&b"hello,"[..]));
+    let (_, second) = content_chunk::new_blob_and_pointer(Bytes::from(&b" world"[..]));
+    FileContents::Chunked(ChunkedFileContents::new(content_id, vec![first, second]))
+        .into_blob()
+        .store(ctx, blob)
+        .await?;
+
+    let metadata = ContentMetadata {
+        total_size: HELLO_WORLD_LENGTH,
+        content_id,
+        sha1: *HELLO_WORLD_SHA1,
+        git_sha1: *HELLO_WORLD_GIT_SHA1,
+        sha256: *HELLO_WORLD_SHA256,
+    };
+    let res = filestore::register_existing(blob, conf, ctx, metadata).await;
+
+    println!("res = {:#?}", res);
+    assert_matches!(
+        res.unwrap_err().downcast::<errors::ErrorKind>(),
+        Ok(errors::ErrorKind::MissingChunk(..))
+    );
+
+    Ok(())
+}
+
 async fn assert_fetches_as<B: Blobstore, S: Into<Bytes>>(
     ctx: &CoreContext,
     blobstore: &B,
@@ -1306,3 +1462,239 @@ async fn filestore_exists(fb: FacebookInit) -> Result<()> {
 
     Ok(())
 }
+
+async fn check_metadata_cache_put_behaviour(
+    fb: FacebookInit,
+    put_behaviour: PutBehaviour,
+) -> Result<()> {
+    let req = request(HELLO_WORLD);
+    let content_id = canonical(HELLO_WORLD);
+    let key = FetchKey::Canonical(content_id);
+
+    let blob = filestore::MetadataCacheBlobstore::new_with_handlers(
+        memblob::Memblob::new(put_behaviour),
+        CachelibHandler::create_mock(),
+        MemcacheHandler::create_mock(),
+        put_behaviour,
+    );
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx, blob, req);
+
+    filestore::store(
+        blob,
+        DEFAULT_CONFIG,
+        ctx,
+        req,
+        stream::once(future::ready(Ok(Bytes::from(HELLO_WORLD)))),
+    )
+    .await?;
+    let metadata = filestore::get_metadata(blob, ctx, &key)
+        .await?
+        .expect("metadata is missing");
+    assert_eq!(metadata.total_size, HELLO_WORLD_LENGTH);
+
+    let other = ContentMetadata {
+        total_size: 1,
+        ..metadata.clone()
+    };
+    other.clone().into_blob().store(ctx, blob).await?;
+
+    let expected = if put_behaviour.should_overwrite() {
+        other
+    } else {
+        metadata
+    };
+    assert_eq!(
+        filestore::get_metadata(blob, ctx, &key).await?,
+        Some(expected)
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn filestore_metadata_cache_overwrite(fb: FacebookInit) -> Result<()> {
+    check_metadata_cache_put_behaviour(fb, PutBehaviour::Overwrite).await
+}
+
+#[fbinit::test]
+async fn filestore_metadata_cache_if_absent(fb: FacebookInit) -> Result<()> {
+    check_metadata_cache_put_behaviour(fb, PutBehaviour::IfAbsent).await
+}
+
+#[fbinit::test]
+async fn filestore_fetch_file_segments(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+
+    for chunk_size in vec![None, Some(1), Some(5)] {
+        // Use a fresh blobstore every time so that we don't reuse existing layouts.
+        let dir = tempfile::tempdir()?;
+        let blob = fileblob::Fileblob::create(dir.path(), PutBehaviour::IfAbsent)?;
+
+        let missing = filestore::fetch_file_segments(
+            &blob,
+            &ctx,
+            &FetchKey::Canonical(canonical(HELLO_WORLD)),
+        )
+        .await?;
+        assert!(missing.is_none());
+
+        let config = FilestoreConfig {
+            chunk_size,
+            concurrency: 5,
+            inline_threshold: None,
+        };
+
+        let req = request(HELLO_WORLD);
+        borrowed!(ctx, blob, req);
+
+        let metadata = filestore::store(
+            blob,
+            config,
+            ctx,
+            req,
+            stream::once(future::ready(Ok(Bytes::from(HELLO_WORLD)))),
+        )
+        .await?;
+
+        let (segments, size) =
+            filestore::fetch_file_segments(blob, ctx, &FetchKey::Canonical(metadata.content_id))
+                .await?
+                .expect("content is missing");
+        assert_eq!(size, HELLO_WORLD_LENGTH);
+
+        let bytes = filestore::stream_file_segments(segments, 3)
+            .try_fold(BytesMut::new(), |mut buff, chunk| async move {
+                buff.extend_from_slice(&chunk);
+                Result::<_, Error>::Ok(buff)
+            })
+            .await?;
+        assert_eq!(bytes.freeze(), Bytes::from(HELLO_WORLD));
+    }
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn filestore_inline_small_files(fb: FacebookInit) -> Result<()> {
+    let req = request(HELLO_WORLD);
+    let content_id = canonical(HELLO_WORLD);
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx, req);
+
+    let inline = FilestoreConfig {
+        inline_threshold: Some(HELLO_WORLD_LENGTH),
+        ..DEFAULT_CONFIG
+    };
+    let not_inline = FilestoreConfig {
+        inline_threshold: Some(HELLO_WORLD_LENGTH - 1),
+        ..DEFAULT_CONFIG
+    };
+    let alias = FetchKey::Aliased(Alias::Sha256(*HELLO_WORLD_SHA256));
+
+    let cases = &[
+        (inline, true, Some(Bytes::from(HELLO_WORLD))),
+        (not_inline, true, None),
+        // Inline aliases are only written once the tunable allows it.
+        (inline, false, None),
+    ];
+    for (config, write_inline, expected) in cases {
+        let blob = memblob::Memblob::default();
+        let tunables = MononokeTunables::default();
+        tunables.update_bools(&hashmap! {
+            "filestore_write_inline_aliases".to_string() => *write_inline,
+        });
+        with_tunables_async(
+            tunables,
+            filestore::store(
+                &blob,
+                *config,
+                ctx,
+                req,
+                stream::once(future::ready(Ok(Bytes::from(HELLO_WORLD)))),
+            )
+            .boxed(),
+        )
+        .await?;
+
+        // Both layouts can be fetched.
+        let res = filestore::fetch_concat_opt(&blob, ctx, &alias).await?;
+        assert_eq!(res, Some(Bytes::from(HELLO_WORLD)));
+
+        // Only inline contents can be fetched by alias without the content blob.
+        assert!(blob.unlink(content_id.blobstore_key()).await?.is_some());
+        let res = filestore::fetch_concat_opt(&blob, ctx, &alias).await?;
+        assert_eq!(&res, expected);
+
+        let res = filestore::fetch_range_with_size(&blob, ctx, &alias, 7, 5).await?;
+        match res {
+            Some((stream, size)) => {
+                assert!(expected.is_some());
+                assert_eq!(size, HELLO_WORLD_LENGTH);
+                let bytes = stream
+                    .try_fold(BytesMut::new(), |mut buff, chunk| async move {
+                        buff.extend_from_slice(&chunk);
+                        Result::<_, Error>::Ok(buff)
+                    })
+                    .await?
+                    .freeze();
+                assert_eq!(bytes, Bytes::from(&b"world"[..]));
+            }
+            None => assert!(expected.is_none()),
+        }
+    }
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn filestore_rehash(fb: FacebookInit) -> Result<()> {
+    let req = request(HELLO_WORLD);
+    let content_id = canonical(HELLO_WORLD);
+
+    let blob = memblob::Memblob::default();
+    let config = FilestoreConfig {
+        chunk_size: Some(5),
+        concurrency: 5,
+        inline_threshold: None,
+    };
+    let ctx = CoreContext::test_mock(fb);
+    borrowed!(ctx, blob, req);
+
+    filestore::store(
+        blob,
+        config,
+        ctx,
+        req,
+        stream::once(future::ready(Ok(Bytes::from(HELLO_WORLD)))),
+    )
+    .await?;
+
+    let res = filestore::rehash(blob, ctx, content_id).await;
+    println!("res = {:#?}", res);
+    assert_eq!(
+        res?,
+        Some(ContentMetadata {
+            total_size: HELLO_WORLD_LENGTH,
+            content_id,
+            sha1: *HELLO_WORLD_SHA1,
+            git_sha1: *HELLO_WORLD_GIT_SHA1,
+            sha256: *HELLO_WORLD_SHA256,
+        })
+    );
+
+    assert_eq!(filestore::rehash(blob, ctx, ONES_CTID).await?, None);
+
+    // Replace the contents with other bytes, which don't hash to the same content id.
+    let corrupt = FileContents::new_bytes(&b"hello, world!"[..]).into_blob();
+    blob.put(ctx, content_id.blobstore_key(), corrupt.into())
+        .await?;
+
+    let res = filestore::rehash(blob, ctx, content_id)
+        .await?
+        .expect("content is missing");
+    assert_ne!(res.content_id, content_id);
+    assert_eq!(res.total_size, HELLO_WORLD_LENGTH + 1);
+
+    Ok(())
+}
@@ -94,6 +94,7 @@ fn test_invariants(fb: FacebookInit) -> Result<()> {
     let config = FilestoreConfig {
         chunk_size: Some(16),
         concurrency: 5,
+        inline_threshold: None,
     };
     let ctx = CoreContext::test_mock(fb);
     borrowed!(ctx, blob, memblob: &Arc<_>);
@@ -140,16 +141,19 @@ fn test_store_bytes_consistency(fb: FacebookInit) -> Result<(), Error> {
             let no_chunking = FilestoreConfig {
                 chunk_size: None,
                 concurrency: 1,
+                inline_threshold: None,
             };
 
             let chunked = FilestoreConfig {
                 chunk_size: Some(std::cmp::max(1, (bytes.len() as u64) / 2)),
                 concurrency: 1,
+                inline_threshold: None,
             };
 
             let too_small_to_chunk = FilestoreConfig {
                 chunk_size: Some(std::cmp::max(1, (bytes.len() as u64) * 2)),
                 concurrency: 1,
+                inline_threshold: None,
             };
 
             let ((id1, len1), fut1) =
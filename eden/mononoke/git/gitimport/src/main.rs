@@ -52,6 +52,7 @@ const ARG_DERIVE_HG: &str = "derive-hg";
 const ARG_HGGIT_COMPATIBILITY: &str = "hggit-compatibility";
 const ARG_BONSAI_GIT_MAPPING: &str = "bonsai-git-mapping";
 const ARG_SUPPRESS_REF_MAPPING: &str = "suppress-ref-mapping";
+const ARG_FLATTEN_SUBMODULES: &str = "flatten-submodules";
 
 const ARG_GIT_FROM: &str = "git-from";
 const ARG_GIT_TO: &str = "git-to";
@@ -97,6 +98,13 @@ fn main(fb: FacebookInit) -> Result<(), Error> {
                 .required(false)
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name(ARG_FLATTEN_SUBMODULES)
+                .long(ARG_FLATTEN_SUBMODULES)
+                .help("Import git submodules as regular directories with their contents. The submodule commits must be fetched into the repository first.")
+                .required(false)
+                .takes_value(false),
+        )
         .arg(Arg::with_name(ARG_GIT_REPOSITORY_PATH).help("Path to a git repository to import"))
         .subcommand(SubCommand::with_name(SUBCOMMAND_FULL_REPO))
         .subcommand(
@@ -147,6 +155,10 @@ fn main(fb: FacebookInit) -> Result<(), Error> {
         prefs.enable_bonsai_git_mapping();
     }
 
+    if matches.is_present(ARG_FLATTEN_SUBMODULES) {
+        prefs.enable_flatten_submodules();
+    }
+
     let path = Path::new(matches.value_of(ARG_GIT_REPOSITORY_PATH).unwrap());
 
     args::init_cachelib(fb, &matches);
@@ -43,7 +43,17 @@ impl Manifest for GitManifest {
     }
 }
 
-async fn load_git_tree(oid: Oid, pool: &GitPool) -> Result<GitManifest, Error> {
+/// A GitPool that loads trees with each git submodule replaced by the tree of the commit it
+/// points to, so that submodules are imported as regular subdirectories. The submodule commits
+/// must be present in the repository being imported, e.g. by fetching the submodules into it.
+#[derive(Clone)]
+pub struct FlattenSubmodules(pub GitPool);
+
+async fn load_git_tree(
+    oid: Oid,
+    pool: &GitPool,
+    flatten_submodules: bool,
+) -> Result<GitManifest, Error> {
     pool.with(move |repo| {
         let tree = repo.find_tree(oid)?;
 
@@ -62,9 +72,23 @@ async fn load_git_tree(oid: Oid, pool: &GitPool) -> Result<GitManifest, Error> {
                     Some(ObjectType::Tree) => Some((name, Entry::Tree(GitTree(entry.id())))),
 
                     // git-sub-modules are represented as ObjectType::Commit inside the tree.
-                    // For now we do not support git-sub-modules but we still need to import
+                    // Unless we were asked to flatten them, we still need to import
                     // repositories that has sub-modules in them (just not synchronized), so
-                    // ignoring any sub-module for now.
+                    // ignore them.
+                    Some(ObjectType::Commit) if flatten_submodules => {
+                        let commit = repo.find_commit(entry.id()).map_err(|e| {
+                            format_err!(
+                                "Submodule {} in tree {} points to commit {}, which is not in \
+                                the repository. Fetch the submodule into the repository to \
+                                flatten it: {}",
+                                name,
+                                oid,
+                                entry.id(),
+                                e
+                            )
+                        })?;
+                        Some((name, Entry::Tree(GitTree(commit.tree_id()))))
+                    }
                     Some(ObjectType::Commit) => None,
 
                     k => {
@@ -112,7 +136,22 @@ impl StoreLoadable<GitPool> for GitTree {
         _ctx: &'a CoreContext,
         pool: &'a GitPool,
     ) -> Result<Self::Value, LoadableError> {
-        load_git_tree(self.0, pool)
+        load_git_tree(self.0, pool, false)
+            .await
+            .map_err(LoadableError::from)
+    }
+}
+
+#[async_trait]
+impl StoreLoadable<FlattenSubmodules> for GitTree {
+    type Value = GitManifest;
+
+    async fn load<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        store: &'a FlattenSubmodules,
+    ) -> Result<Self::Value, LoadableError> {
+        load_git_tree(self.0, &store.0, true)
             .await
             .map_err(LoadableError::from)
     }
@@ -125,6 +164,7 @@ pub struct GitimportPreferences {
     pub hggit_compatibility: bool,
     pub bonsai_git_mapping: bool,
     pub gitrepo_name: Option<String>,
+    pub flatten_submodules: bool,
 }
 
 impl GitimportPreferences {
@@ -144,6 +184,10 @@ impl GitimportPreferences {
         self.bonsai_git_mapping = true
     }
 
+    pub fn enable_flatten_submodules(&mut self) {
+        self.flatten_submodules = true
+    }
+
     /// Only for logging purpuses,
     /// useful when several repos are imported simultainously.
     pub fn set_gitrepo_name(&mut self, name: String) {
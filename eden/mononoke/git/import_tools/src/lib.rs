@@ -10,9 +10,9 @@
 mod gitimport_objects;
 
 pub use crate::gitimport_objects::{
-    convert_git_filemode, oid_to_sha1, CommitMetadata, ExtractedCommit, FullRepoImport, GitLeaf,
-    GitManifest, GitRangeImport, GitTree, GitimportPreferences, GitimportTarget,
-    ImportMissingForCommit,
+    convert_git_filemode, oid_to_sha1, CommitMetadata, ExtractedCommit, FlattenSubmodules,
+    FullRepoImport, GitLeaf, GitManifest, GitRangeImport, GitTree, GitimportPreferences,
+    GitimportTarget, ImportMissingForCommit,
 };
 use anyhow::{format_err, Context, Error};
 use blobrepo::{save_bonsai_changesets, BlobRepo};
@@ -182,16 +182,22 @@ pub async fn gitimport(
                 .await
                 .with_context(|| format!("While extracting {}", oid))?;
 
+            let flatten_submodules = prefs.flatten_submodules;
             let file_changes = task::spawn({
                 cloned!(ctx, repo, pool);
                 async move {
-                    find_file_changes(
-                        &ctx,
-                        repo.blobstore(),
-                        pool.clone(),
-                        bonsai_diff(ctx.clone(), pool, tree, parent_trees),
-                    )
-                    .await
+                    let changes = if flatten_submodules {
+                        bonsai_diff(
+                            ctx.clone(),
+                            FlattenSubmodules(pool.clone()),
+                            tree,
+                            parent_trees,
+                        )
+                        .boxed()
+                    } else {
+                        bonsai_diff(ctx.clone(), pool.clone(), tree, parent_trees).boxed()
+                    };
+                    find_file_changes(&ctx, repo.blobstore(), pool, changes).await
                 }
             })
             .await??;
@@ -241,7 +247,13 @@ pub async fn gitimport(
         )
         .await?;
 
-    if prefs.derive_trees {
+    if prefs.derive_trees && prefs.flatten_submodules {
+        // The derived trees contain the submodules' contents, so they never match git's trees.
+        info!(
+            ctx.logger(),
+            "Not validating derived trees, since submodules were flattened"
+        );
+    } else if prefs.derive_trees {
         for (id, (bcs_id, _bcs)) in import_map.iter() {
             let commit = walk_repo.find_commit(*id)?;
             let tree_id = commit.tree()?.id();
@@ -0,0 +1,167 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::pin::Pin;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use anyhow::Error;
+use bytes::Bytes;
+use futures::{
+    ready,
+    stream::Stream,
+    task::{Context, Poll},
+};
+use pin_project::pin_project;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SizedBodyError {
+    #[error("Request body is larger than the expected {expected} bytes")]
+    TooLarge { expected: u64 },
+    #[error("Request body ended after {received} bytes, expected {expected}")]
+    TooSmall { expected: u64, received: u64 },
+}
+
+/// How much of a `SizedBodyStream` was received so far. This can be read while the stream is
+/// being consumed, or after it was dropped, e.g. to log how much of an upload made it.
+#[derive(Clone, Debug, Default)]
+pub struct ReceivedSize(Arc<AtomicU64>);
+
+impl ReceivedSize {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Adapts a request body to the stream of `Bytes` that e.g. the filestore expects, so that large
+/// uploads can be passed through as they arrive rather than buffered. The body is only polled
+/// when the consumer asks for more data, so a consumer that falls behind slows the client down
+/// instead of filling up memory.
+///
+/// The stream fails as soon as the body goes over `expected_size`, and if it ends short of it.
+#[pin_project]
+pub struct SizedBodyStream<S> {
+    #[pin]
+    body: S,
+    expected_size: u64,
+    received: ReceivedSize,
+    done: bool,
+}
+
+impl<S> SizedBodyStream<S> {
+    pub fn new(body: S, expected_size: u64) -> Self {
+        Self {
+            body,
+            expected_size,
+            received: ReceivedSize::default(),
+            done: false,
+        }
+    }
+
+    pub fn received_size(&self) -> ReceivedSize {
+        self.received.clone()
+    }
+}
+
+impl<S, E> Stream for SizedBodyStream<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: Into<Error>,
+{
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        let res = match ready!(this.body.poll_next(ctx)) {
+            Some(Ok(chunk)) => {
+                let received = this.received.0.load(Ordering::Relaxed) + chunk.len() as u64;
+                this.received.0.store(received, Ordering::Relaxed);
+                if received > *this.expected_size {
+                    Some(Err(SizedBodyError::TooLarge {
+                        expected: *this.expected_size,
+                    }
+                    .into()))
+                } else {
+                    Some(Ok(chunk))
+                }
+            }
+            Some(Err(e)) => Some(Err(e.into())),
+            None => {
+                let received = this.received.get();
+                if received < *this.expected_size {
+                    Some(Err(SizedBodyError::TooSmall {
+                        expected: *this.expected_size,
+                        received,
+                    }
+                    .into()))
+                } else {
+                    None
+                }
+            }
+        };
+
+        // Stop after the first error or the end of the body.
+        *this.done = !matches!(res, Some(Ok(_)));
+        Poll::Ready(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::stream::{self, TryStreamExt};
+
+    fn make_body(chunks: Vec<&'static str>) -> impl Stream<Item = Result<Bytes, Error>> {
+        stream::iter(chunks.into_iter().map(|c| Ok(Bytes::from(c))))
+    }
+
+    #[tokio::test]
+    async fn test_exact_size() -> Result<(), Error> {
+        let stream = SizedBodyStream::new(make_body(vec!["12", "34"]), 4);
+        let received = stream.received_size();
+        let chunks: Vec<_> = stream.try_collect().await?;
+        assert_eq!(chunks, vec![Bytes::from("12"), Bytes::from("34")]);
+        assert_eq!(received.get(), 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_too_large() -> Result<(), Error> {
+        let stream = SizedBodyStream::new(make_body(vec!["12", "34", "56"]), 3);
+        let received = stream.received_size();
+        let res: Result<Vec<_>, _> = stream.try_collect().await;
+        match res.unwrap_err().downcast::<SizedBodyError>()? {
+            SizedBodyError::TooLarge { expected: 3 } => {}
+            e => panic!("Unexpected error: {:?}", e),
+        }
+        // The rest of the body isn't read.
+        assert_eq!(received.get(), 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_too_small() -> Result<(), Error> {
+        let stream = SizedBodyStream::new(make_body(vec!["12"]), 3);
+        let res: Result<Vec<_>, _> = stream.try_collect().await;
+        match res.unwrap_err().downcast::<SizedBodyError>()? {
+            SizedBodyError::TooSmall {
+                expected: 3,
+                received: 2,
+            } => {}
+            e => panic!("Unexpected error: {:?}", e),
+        }
+        Ok(())
+    }
+}
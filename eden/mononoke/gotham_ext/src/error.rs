@@ -54,6 +54,13 @@ impl HttpError {
         }
     }
 
+    pub fn e413<E: Into<Error>>(err: E) -> Self {
+        Self {
+            error: err.into(),
+            status_code: StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+
     pub fn e429<E: Into<Error>>(err: E) -> Self {
         Self {
             error: err.into(),
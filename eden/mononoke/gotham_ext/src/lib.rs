@@ -6,6 +6,7 @@
  */
 
 pub mod body_ext;
+pub mod body_stream;
 pub mod content;
 pub mod error;
 pub mod handler;
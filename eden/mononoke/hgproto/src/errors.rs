@@ -17,6 +17,8 @@ pub enum ErrorKind {
     UnconsumedData(String),
     #[error("malformed batch with command '{0}'")]
     BatchInvalid(String),
+    #[error("command '{0}' cannot be batched")]
+    BatchUnsupported(String),
     #[error("malformed bundle2 '{0}'")]
     Bundle2Invalid(String),
     #[error("unknown escape character in batch command '{0}'")]
@@ -15,9 +15,11 @@ use futures::future::{err, ok, Either};
 use futures::sync::oneshot;
 use futures::{stream, Future, Poll, Stream};
 use futures_ext::{BoxFuture, BoxStream, BytesStream, FutureExt, StreamExt};
+use futures_stats::TimedStreamTrait;
 use slog::Logger;
 use std::io;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio_io::codec::Decoder;
 
 pub type OutputStream = BoxStream<Bytes, Error>;
@@ -35,6 +37,7 @@ struct HgProtoHandlerInner<H, Dec, Enc> {
     reqdec: Dec,
     respenc: Enc,
     wireproto_calls: Arc<Mutex<Vec<String>>>,
+    wireproto_timings: Arc<Mutex<Vec<(String, Duration)>>>,
 }
 
 impl HgProtoHandler {
@@ -45,6 +48,7 @@ impl HgProtoHandler {
         reqdec: Dec,
         respenc: Enc,
         wireproto_calls: Arc<Mutex<Vec<String>>>,
+        wireproto_timings: Arc<Mutex<Vec<(String, Duration)>>>,
     ) -> Self
     where
         In: Stream<Item = Bytes, Error = io::Error> + Send + 'static,
@@ -59,6 +63,7 @@ impl HgProtoHandler {
             reqdec,
             respenc,
             wireproto_calls,
+            wireproto_timings,
         });
 
         HgProtoHandler {
@@ -167,6 +172,11 @@ where
     req.record_request(&handler.wireproto_calls);
     match req {
         Request::Batch(reqs) => {
+            if let Some(req) = reqs.iter().find(|req| !req.is_batchable()) {
+                let e = ErrorKind::BatchUnsupported(req.name().to_string());
+                return (stream::once(Err(e.into())).boxify(), ok(input).boxify());
+            }
+
             let (send, recv) = oneshot::channel();
             let responses = stream::unfold(
                 (reqs.into_iter(), ok(input).boxify(), send),
@@ -198,8 +208,88 @@ where
             )
         }
         Request::Single(req) => {
+            // Only single commands are timed: batches only contain cheap commands, and timing
+            // the batch as a whole wouldn't tell which of them was slow.
+            let command = req.name();
             let (resps, remainder) = handler.commands_handler.handle(req, input);
-            (resps.map(Response::Single).boxify(), remainder)
+            let resps = resps.map(Response::Single).timed(move |stats, _| {
+                handler
+                    .wireproto_timings
+                    .lock()
+                    .expect("lock poisoned")
+                    .push((command.to_string(), stats.completion_time));
+                Ok(())
+            });
+            (resps.boxify(), remainder)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::sshproto::{HgSshCommandDecode, HgSshCommandEncode};
+    use crate::{HgCommandRes, SingleRequest, SingleResponse};
+    use mercurial_types::HgChangesetId;
+    use slog::{o, Discard};
+    use std::collections::HashSet;
+
+    struct Dummy;
+    impl HgCommands for Dummy {
+        fn heads(&self) -> HgCommandRes<HashSet<HgChangesetId>> {
+            ok(HashSet::new()).boxify()
+        }
+    }
+
+    fn run_request(req: Request) -> Result<Vec<Response>, Error> {
+        let handler = Arc::new(HgProtoHandlerInner {
+            commands_handler: HgCommandHandler::new(Logger::root(Discard, o!()), Dummy),
+            reqdec: HgSshCommandDecode,
+            respenc: HgSshCommandEncode,
+            wireproto_calls: Arc::new(Mutex::new(Vec::new())),
+            wireproto_timings: Arc::new(Mutex::new(Vec::new())),
+        });
+        let input = BytesStream::new(stream::empty::<Bytes, io::Error>());
+        let (resps, _) = handle_request(req, input, handler);
+        resps.collect().wait()
+    }
+
+    #[test]
+    fn batch() {
+        let resps = run_request(Request::Batch(vec![
+            SingleRequest::Heads,
+            SingleRequest::Heads,
+        ]))
+        .expect("batch failed");
+        match resps.as_slice() {
+            [Response::Batch(batch)] => {
+                assert_eq!(batch.len(), 2);
+                for resp in batch {
+                    match resp {
+                        SingleResponse::Heads(heads) => assert!(heads.is_empty()),
+                        bad => panic!("Bad response {:?}", bad),
+                    }
+                }
+            }
+            bad => panic!("Bad responses {:?}", bad),
+        }
+    }
+
+    #[test]
+    fn batch_unsupported() {
+        for req in vec![
+            SingleRequest::Hello,
+            SingleRequest::GetpackV1,
+            SingleRequest::Unbundle { heads: vec![] },
+        ] {
+            let name = req.name();
+            let err = run_request(Request::Batch(vec![SingleRequest::Heads, req]))
+                .expect_err("batch should fail");
+            match err.downcast_ref::<ErrorKind>() {
+                Some(ErrorKind::BatchUnsupported(command)) => assert_eq!(command, name),
+                _ => panic!("Bad error {:?}", err),
+            }
         }
     }
 }
@@ -121,6 +121,24 @@ impl SingleRequest {
             &SingleRequest::GetCommitData { .. } => "getcommitdata",
         }
     }
+
+    /// Whether the command can be part of a batch. Each command in a batch gets a single result,
+    /// so only the cheap commands with a single response that clients batch are allowed, and new
+    /// commands aren't batchable until they are added here.
+    pub fn is_batchable(&self) -> bool {
+        use SingleRequest::*;
+
+        match self {
+            &Lookup { .. }
+            | &Listkeys { .. }
+            | &ListKeysPatterns { .. }
+            | &Heads
+            | &Known { .. }
+            | &Knownnodes { .. }
+            | &Between { .. } => true,
+            _ => false,
+        }
+    }
 }
 
 /// The arguments that `getbundle` accepts, in a separate struct for
@@ -181,6 +199,9 @@ pub struct GettreepackArgs {
     pub directories: Vec<Bytes>,
     /// The depth from the root that should be sent.
     pub depth: Option<usize>,
+    /// The fullpath of the directories a sparse client wants. If set, only the trees on the way
+    /// to them and the trees under them are sent.
+    pub sparse_prefixes: Vec<Bytes>,
 }
 
 #[derive(Debug)]
@@ -637,6 +637,7 @@ fn parse_with_params(
                         usize::from_str
                     )
                 ))?,
+                sparse_prefixes: parseval_default(&kv, "sparseprefixes", gettreepack_directories)?,
             })))
         | call!(parse_command, "stream_out_shallow", parse_params, 0+1, |_kv| Ok(StreamOutShallow))
         | command_star!("getpackv1", GetpackV1, parse_params, {})
@@ -1449,6 +1450,7 @@ mod test_parse {
                 basemfnodes: btreeset![hash_ones_manifest()],
                 directories: vec![],
                 depth: None,
+                sparse_prefixes: vec![],
             })),
         );
 
@@ -1473,6 +1475,7 @@ mod test_parse {
                 basemfnodes: btreeset![hash_twos_manifest(), hash_ones_manifest()],
                 directories: vec![Bytes::from("".as_bytes())],
                 depth: Some(1),
+                sparse_prefixes: vec![],
             })),
         );
 
@@ -1497,6 +1500,7 @@ mod test_parse {
                 basemfnodes: btreeset![hash_twos_manifest(), hash_ones_manifest()],
                 directories: vec![Bytes::from(",".as_bytes()), Bytes::from(";".as_bytes())],
                 depth: Some(1),
+                sparse_prefixes: vec![],
             })),
         );
 
@@ -1518,6 +1522,33 @@ mod test_parse {
                 basemfnodes: btreeset![hash_ones_manifest()],
                 directories: vec![Bytes::from(b"".as_ref()), Bytes::from(b"foo".as_ref())],
                 depth: None,
+                sparse_prefixes: vec![],
+            })),
+        );
+
+        let inp = "gettreepack\n\
+                   * 5\n\
+                   rootdir 0\n\
+                   mfnodes 40\n\
+                   1111111111111111111111111111111111111111\
+                   basemfnodes 40\n\
+                   1111111111111111111111111111111111111111\
+                   directories 0\n\
+                   sparseprefixes 12\n\
+                   foo,bar/baz,";
+
+        test_parse(
+            inp,
+            Request::Single(SingleRequest::Gettreepack(GettreepackArgs {
+                rootdir: None,
+                mfnodes: vec![hash_ones_manifest()],
+                basemfnodes: btreeset![hash_ones_manifest()],
+                directories: vec![],
+                depth: None,
+                sparse_prefixes: vec![
+                    Bytes::from(b"foo".as_ref()),
+                    Bytes::from(b"bar/baz".as_ref()),
+                ],
             })),
         );
     }
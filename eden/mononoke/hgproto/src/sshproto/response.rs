@@ -176,3 +176,36 @@ fn encode_cmd(response: SingleResponse) -> Bytes {
         r => panic!("Response for {:?} unimplemented", r),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::Future;
+    use maplit::{hashmap, hashset};
+    use mercurial_types_mocks::nodehash::ONES_CSID;
+
+    #[test]
+    fn test_encode_batch() {
+        let hash = "1111111111111111111111111111111111111111";
+        let response = Response::Batch(vec![
+            SingleResponse::Heads(hashset! { ONES_CSID }),
+            SingleResponse::Lookup(Bytes::from(&b"0 unknown revision 'a=b;c'\n"[..])),
+            SingleResponse::Listkeys(hashmap! {
+                b"master".to_vec() => hash.as_bytes().to_vec(),
+            }),
+        ]);
+
+        let chunks = encode(response).collect().wait().unwrap();
+        let encoded: Vec<u8> = chunks.iter().flat_map(|c| c.iter().cloned()).collect();
+
+        // One result for each command, in order, escaped and separated by ';'.
+        let results = format!(
+            "{}\n;0 unknown revision 'a:eb:sc'\n;master\t{}\n",
+            hash, hash
+        );
+        assert_eq!(
+            String::from_utf8(encoded).unwrap(),
+            format!("{}\n{}", results.len(), results)
+        );
+    }
+}
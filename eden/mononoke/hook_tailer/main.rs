@@ -26,9 +26,10 @@ use futures::{
     stream::{FuturesUnordered, StreamExt, TryStreamExt},
 };
 use hooks::CrossRepoPushSource;
+use metaconfig_types::RepoConfig;
 use mononoke_types::ChangesetId;
 use slog::{debug, info, Logger};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::time::Duration;
 use time_ext::DurationExt;
 use tokio::{
@@ -91,7 +92,7 @@ fn main(fb: FacebookInit) -> Result<()> {
 async fn run_hook_tailer<'a>(
     fb: FacebookInit,
     ctx: &CoreContext,
-    config: &metaconfig_types::RepoConfig,
+    config: &RepoConfig,
     repo_name: &str,
     matches: &'a MononokeMatches<'a>,
     logger: &Logger,
@@ -104,6 +105,7 @@ async fn run_hook_tailer<'a>(
     let concurrency = cmdlib::args::get_usize(matches, "concurrency", 20);
     let log_interval = cmdlib::args::get_usize(matches, "log_interval", 500);
     let exclude_merges = matches.is_present("exclude_merges");
+    let sample_size = cmdlib::args::get_usize(matches, "sample_size", 5);
     let stats_file = matches.value_of("stats_file");
     let cross_repo_push_source = match matches.value_of("push_source") {
         Some("native-to-this-repo") => CrossRepoPushSource::NativeToThisRepo,
@@ -130,6 +132,12 @@ async fn run_hook_tailer<'a>(
 
     let disabled_hooks = cmdlib::args::parse_disabled_hooks_no_repo_prefix(&matches, &logger);
 
+    let mut config = config.clone();
+    if let Some(hooks) = matches.values_of("hook") {
+        let hooks = hooks.map(|hook| hook.to_string()).collect();
+        restrict_to_hooks(&mut config, &bookmark, &hooks)?;
+    }
+
     let caching = cmdlib::args::init_cachelib(fb, matches);
     let readonly_storage = cmdlib::args::parse_readonly_storage(matches);
     let mysql_options = cmdlib::args::parse_mysql_options(matches);
@@ -157,7 +165,7 @@ async fn run_hook_tailer<'a>(
     let tail = &Tailer::new(
         ctx.clone(),
         blobrepo.clone(),
-        config.clone(),
+        config,
         bookmark,
         concurrency,
         log_interval,
@@ -174,7 +182,7 @@ async fn run_hook_tailer<'a>(
         tail.run_changesets(inclusions).boxed()
     };
 
-    let mut summary = HookExecutionSummary::default();
+    let mut summary = HookExecutionSummary::new(sample_size);
 
     info!(logger, "==== Hooks results ====");
 
@@ -210,6 +218,22 @@ async fn run_hook_tailer<'a>(
     info!(logger, "Changesets accepted: {}", summary.accepted);
     info!(logger, "Changesets rejected: {}", summary.rejected);
 
+    info!(logger, "==== Rejections by hook ====");
+    let total = summary.accepted + summary.rejected;
+    for (hook_name, rejections) in summary.by_hook.iter() {
+        info!(
+            logger,
+            "{}: {} of {} changesets rejected ({:.2}%)",
+            hook_name,
+            rejections.changesets,
+            total,
+            rejections.changesets as f64 * 100.0 / total.max(1) as f64,
+        );
+        for sample in rejections.samples.iter() {
+            info!(logger, "    {}", sample);
+        }
+    }
+
     if summary.rejected > 0 {
         return Err(format_err!("Hook rejections: {}", summary.rejected));
     }
@@ -217,22 +241,89 @@ async fn run_hook_tailer<'a>(
     Ok(())
 }
 
+/// Only run the named hooks, and run them on the bookmark even if they are not enabled on it yet.
+/// This lets a proposed hook be evaluated against history before it is enforced.
+fn restrict_to_hooks(
+    config: &mut RepoConfig,
+    bookmark: &BookmarkName,
+    hooks: &HashSet<String>,
+) -> Result<()> {
+    for hook in hooks.iter() {
+        if !config.hooks.iter().any(|params| &params.name == hook) {
+            bail!("Hook {} is not configured for this repo", hook);
+        }
+    }
+    config.hooks.retain(|params| hooks.contains(&params.name));
+
+    let mut matched = false;
+    for params in config.bookmarks.iter_mut() {
+        params.hooks.retain(|hook| hooks.contains(hook));
+        if params.bookmark.matches(bookmark) {
+            matched = true;
+            for hook in hooks.iter() {
+                if !params.hooks.contains(hook) {
+                    params.hooks.push(hook.clone());
+                }
+            }
+        }
+    }
+
+    if !matched {
+        bail!(
+            "Bookmark {} has no configuration to attach hooks to",
+            bookmark
+        );
+    }
+
+    Ok(())
+}
+
 #[derive(Default)]
+struct HookRejections {
+    changesets: u64,
+    samples: Vec<String>,
+}
+
 struct HookExecutionSummary {
     accepted: u64,
     rejected: u64,
     completion_time: Duration,
     poll_time: Duration,
+    by_hook: BTreeMap<String, HookRejections>,
+    sample_size: usize,
 }
 
 impl HookExecutionSummary {
+    pub fn new(sample_size: usize) -> Self {
+        Self {
+            accepted: 0,
+            rejected: 0,
+            completion_time: Duration::default(),
+            poll_time: Duration::default(),
+            by_hook: BTreeMap::new(),
+            sample_size,
+        }
+    }
+
     pub fn add_instance(&mut self, instance: &HookExecutionInstance, logger: &Logger) {
         let mut is_rejected = false;
+        let mut rejected_by = HashSet::new();
 
         for outcome in instance.outcomes.iter() {
             if outcome.is_rejection() {
                 is_rejected = true;
                 info!(logger, "{}", outcome);
+
+                let rejections = self
+                    .by_hook
+                    .entry(outcome.get_hook_name().to_string())
+                    .or_default();
+                if rejected_by.insert(outcome.get_hook_name()) {
+                    rejections.changesets += 1;
+                }
+                if rejections.samples.len() < self.sample_size {
+                    rejections.samples.push(outcome.to_string());
+                }
             } else {
                 debug!(logger, "{}", outcome);
             }
@@ -312,6 +403,23 @@ fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
                 .long("exclude-merges")
                 .help("exclude changesets that are merges (more than one parent)"),
         )
+        .arg(
+            Arg::with_name("hook")
+                .long("hook")
+                .multiple(true)
+                .help(
+                    "only run the named hooks, attaching them to the bookmark if they are not \
+                    enabled on it yet (useful to evaluate a new hook against recent history)",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sample_size")
+                .long("sample-size")
+                .takes_value(true)
+                .help("number of sample rejection messages to show for each hook")
+                .default_value("5"),
+        )
         .arg(
             Arg::with_name("limit")
                 .long("limit")
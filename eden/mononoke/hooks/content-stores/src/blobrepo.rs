@@ -17,7 +17,7 @@ use context::CoreContext;
 use derived_data::BonsaiDerived;
 use futures::{future, stream::TryStreamExt};
 use futures_util::future::TryFutureExt;
-use manifest::{Diff, Entry, ManifestOps};
+use manifest::{Diff, Entry, Manifest, ManifestOps};
 use mercurial_types::{FileType, HgFileNodeId, HgManifestId};
 use mononoke_types::{ChangesetId, ContentId, MPath, ManifestUnodeId};
 use std::collections::HashMap;
@@ -86,6 +86,41 @@ impl FileContentManager for BlobRepoFileContentManager {
             .await
     }
 
+    async fn directory_sizes<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        bookmark: BookmarkName,
+        paths: Vec<Option<MPath>>,
+    ) -> Result<HashMap<Option<MPath>, u64>, ErrorKind> {
+        let changeset_id = self
+            .repo
+            .get_bonsai_bookmark(ctx.clone(), &bookmark)
+            .await
+            .with_context(|| format!("Error fetching bookmark: {}", bookmark))?
+            .ok_or_else(|| format_err!("Bookmark {} does not exist", bookmark))?;
+
+        let master_mf = derive_hg_manifest(ctx, &self.repo, changeset_id).await?;
+        master_mf
+            .find_entries(ctx.clone(), self.repo.get_blobstore(), paths)
+            .map_ok(|(path, entry)| async move {
+                match entry {
+                    Entry::Tree(tree_id) => {
+                        let tree = tree_id
+                            .load(ctx, &self.repo.get_blobstore())
+                            .await
+                            .with_context(|| format!("Error loading manifest: {}", tree_id))?;
+                        Ok(Some((path, tree.list().count() as u64)))
+                    }
+                    Entry::Leaf(_) => Ok(None),
+                }
+            })
+            .try_buffer_unordered(100)
+            .try_filter_map(future::ok)
+            .try_collect::<HashMap<_, _>>()
+            .map_err(ErrorKind::from)
+            .await
+    }
+
     async fn file_changes<'a>(
         &'a self,
         ctx: &'a CoreContext,
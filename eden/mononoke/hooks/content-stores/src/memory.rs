@@ -88,6 +88,18 @@ impl FileContentManager for InMemoryFileContentManager {
         )
     }
 
+    async fn directory_sizes<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        _bookmark: BookmarkName,
+        _paths: Vec<Option<MPath>>,
+    ) -> Result<HashMap<Option<MPath>, u64>, ErrorKind> {
+        Err(
+            format_err!("`directory_sizes` is not implemented for `InMemoryFileContentManager`")
+                .into(),
+        )
+    }
+
     async fn file_changes<'a>(
         &'a self,
         _ctx: &'a CoreContext,
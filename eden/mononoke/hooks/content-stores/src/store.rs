@@ -36,6 +36,15 @@ pub trait FileContentManager: Send + Sync {
         paths: Vec<MPath>,
     ) -> Result<HashMap<MPath, PathContent>, ErrorKind>;
 
+    /// The number of entries of each of the paths that is a directory at the bookmark. `None` is
+    /// the root directory. Paths that aren't directories are omitted.
+    async fn directory_sizes<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        bookmark: BookmarkName,
+        paths: Vec<Option<MPath>>,
+    ) -> Result<HashMap<Option<MPath>, u64>, ErrorKind>;
+
     async fn file_changes<'a>(
         &'a self,
         ctx: &'a CoreContext,
@@ -75,6 +75,15 @@ impl<T: FileContentManager + 'static> FileContentManager for TextOnlyFileContent
         self.inner.find_content(ctx, bookmark, paths).await
     }
 
+    async fn directory_sizes<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        bookmark: BookmarkName,
+        paths: Vec<Option<MPath>>,
+    ) -> Result<HashMap<Option<MPath>, u64>, ErrorKind> {
+        self.inner.directory_sizes(ctx, bookmark, paths).await
+    }
+
     async fn file_changes<'a>(
         &'a self,
         ctx: &'a CoreContext,
@@ -1479,6 +1479,8 @@ fn test_verify_integrity_fast_failure(fb: FacebookInit) {
             allow_only_external_sync: None,
             rewrite_dates: None,
             hooks_skip_ancestors_of: vec![],
+            pushrebase_author_format: None,
+            pushrebase_added_trailers: None,
         }];
         config.hooks = vec![HookParams {
             name: "verify_integrity".into(),
@@ -1507,6 +1509,8 @@ fn test_load_hooks_bad_rust_hook(fb: FacebookInit) {
             allow_only_external_sync: None,
             rewrite_dates: None,
             hooks_skip_ancestors_of: vec![],
+            pushrebase_author_format: None,
+            pushrebase_added_trailers: None,
         }];
 
         config.hooks = vec![HookParams {
@@ -1562,6 +1566,8 @@ fn test_load_disabled_hooks_referenced_by_bookmark(fb: FacebookInit) {
             allow_only_external_sync: None,
             rewrite_dates: None,
             hooks_skip_ancestors_of: vec![],
+            pushrebase_author_format: None,
+            pushrebase_added_trailers: None,
         }];
 
         config.hooks = vec![HookParams {
@@ -21,6 +21,7 @@ pub(crate) mod no_bad_filenames;
 mod no_insecure_filenames;
 pub(crate) mod no_questionable_filenames;
 pub(crate) mod no_windows_filenames;
+mod structural_limits;
 
 use anyhow::Result;
 use fbinit::FacebookInit;
@@ -54,6 +55,9 @@ pub fn hook_name_to_changeset_hook<'a>(
             "limit_commitsize" => Some(b(limit_commitsize::LimitCommitsize::builder()
                 .set_from_config(config)
                 .build()?)),
+            "structural_limits" => Some(b(structural_limits::StructuralLimits::builder()
+                .set_from_config(config)
+                .build()?)),
             _ => None,
         })
     }
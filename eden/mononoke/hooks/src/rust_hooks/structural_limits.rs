@@ -0,0 +1,364 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Limits on the shape of commits: how many files they change, how deep their paths are, and
+//! how many entries the directories they add to end up with. Commits over those limits are
+//! expensive for everything that derives data or manifests from them, and can't be fixed
+//! afterwards, so only allowlisted identities may bypass this hook.
+
+use crate::{
+    ChangesetHook, CrossRepoPushSource, FileContentManager, HookConfig, HookExecution,
+    HookRejectionInfo,
+};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use bookmarks::BookmarkName;
+use context::CoreContext;
+use mononoke_types::{BonsaiChangeset, MPath};
+use permission_checker::{MononokeIdentity, MononokeIdentitySet};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Default)]
+pub struct StructuralLimitsBuilder {
+    max_changed_files: Option<u64>,
+    max_path_depth: Option<u64>,
+    max_directory_entries: Option<u64>,
+    bypass_identities: Option<Vec<String>>,
+}
+
+impl StructuralLimitsBuilder {
+    pub fn set_from_config(mut self, config: &HookConfig) -> Self {
+        if let Some(v) = config.ints.get("max_changed_files") {
+            self = self.max_changed_files(*v as u64)
+        }
+        if let Some(v) = config.ints.get("max_path_depth") {
+            self = self.max_path_depth(*v as u64)
+        }
+        if let Some(v) = config.ints.get("max_directory_entries") {
+            self = self.max_directory_entries(*v as u64)
+        }
+        if let Some(v) = config.string_lists.get("bypass_identities") {
+            self = self.bypass_identities(v)
+        }
+        self
+    }
+
+    pub fn max_changed_files(mut self, limit: u64) -> Self {
+        self.max_changed_files = Some(limit);
+        self
+    }
+
+    pub fn max_path_depth(mut self, limit: u64) -> Self {
+        self.max_path_depth = Some(limit);
+        self
+    }
+
+    pub fn max_directory_entries(mut self, limit: u64) -> Self {
+        self.max_directory_entries = Some(limit);
+        self
+    }
+
+    pub fn bypass_identities(mut self, strs: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.bypass_identities = Some(strs.into_iter().map(|s| String::from(s.as_ref())).collect());
+        self
+    }
+
+    pub fn build(self) -> Result<StructuralLimits> {
+        if self.max_changed_files.is_none()
+            && self.max_path_depth.is_none()
+            && self.max_directory_entries.is_none()
+        {
+            return Err(anyhow!(
+                "At least one of max_changed_files, max_path_depth or max_directory_entries must be configured"
+            ));
+        }
+
+        Ok(StructuralLimits {
+            max_changed_files: self.max_changed_files,
+            max_path_depth: self.max_path_depth,
+            max_directory_entries: self.max_directory_entries,
+            bypass_identities: self
+                .bypass_identities
+                .unwrap_or_else(Vec::new)
+                .iter()
+                .map(|s| s.parse::<MononokeIdentity>())
+                .collect::<Result<MononokeIdentitySet>>()
+                .context("Failed to parse bypass_identities")?,
+        })
+    }
+}
+
+pub struct StructuralLimits {
+    max_changed_files: Option<u64>,
+    max_path_depth: Option<u64>,
+    max_directory_entries: Option<u64>,
+    bypass_identities: MononokeIdentitySet,
+}
+
+impl StructuralLimits {
+    pub fn builder() -> StructuralLimitsBuilder {
+        StructuralLimitsBuilder::default()
+    }
+
+    fn is_bypassed(&self, ctx: &CoreContext) -> bool {
+        ctx.metadata()
+            .identities()
+            .iter()
+            .any(|identity| self.bypass_identities.contains(identity))
+    }
+
+    async fn check_directory_entries(
+        &self,
+        ctx: &CoreContext,
+        bookmark: &BookmarkName,
+        changeset: &BonsaiChangeset,
+        content_manager: &dyn FileContentManager,
+        max_directory_entries: u64,
+    ) -> Result<Option<HookExecution>> {
+        // The entries that the commit adds to or removes from each directory, if they don't
+        // already exist, or do exist, respectively.
+        let mut added: HashMap<Option<MPath>, HashSet<MPath>> = HashMap::new();
+        let mut removed: HashMap<Option<MPath>, HashSet<MPath>> = HashMap::new();
+        for (path, file_change) in changeset.file_changes() {
+            if file_change.is_some() {
+                for depth in 1..=path.num_components() {
+                    let dir = path.take_prefix_components(depth - 1)?;
+                    let entry = path
+                        .take_prefix_components(depth)?
+                        .ok_or_else(|| anyhow!("Path {} has no component {}", path, depth))?;
+                    added.entry(dir).or_default().insert(entry);
+                }
+            } else {
+                let (dir, _) = path.split_dirname();
+                removed.entry(dir).or_default().insert(path.clone());
+            }
+        }
+
+        let entries = added
+            .values()
+            .chain(removed.values())
+            .flatten()
+            .cloned()
+            .collect();
+        let existing = content_manager
+            .find_content(ctx, bookmark.clone(), entries)
+            .await?;
+
+        let mut growth = HashMap::new();
+        for (dir, entries) in added {
+            let new_entries = entries
+                .iter()
+                .filter(|entry| !existing.contains_key(entry))
+                .count() as u64;
+            if new_entries > 0 {
+                growth.insert(dir, new_entries);
+            }
+        }
+        if growth.is_empty() {
+            return Ok(None);
+        }
+
+        let sizes = content_manager
+            .directory_sizes(ctx, bookmark.clone(), growth.keys().cloned().collect())
+            .await?;
+        for (dir, new_entries) in growth {
+            let removed_entries = removed.get(&dir).map_or(0, |entries| {
+                entries
+                    .iter()
+                    .filter(|entry| existing.contains_key(entry))
+                    .count() as u64
+            });
+            let entries = (sizes.get(&dir).copied().unwrap_or(0) + new_entries)
+                .saturating_sub(removed_entries);
+            if entries > max_directory_entries {
+                return Ok(Some(HookExecution::Rejected(HookRejectionInfo::new_long(
+                    "Directory too large",
+                    format!(
+                        "Directory '{}' would have {} entries but at most {} are allowed. Split the files over subdirectories.",
+                        MPath::display_opt(dir.as_ref()),
+                        entries,
+                        max_directory_entries,
+                    ),
+                ))));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl ChangesetHook for StructuralLimits {
+    async fn run<'this: 'cs, 'ctx: 'this, 'cs, 'fetcher: 'cs>(
+        &'this self,
+        ctx: &'ctx CoreContext,
+        bookmark: &BookmarkName,
+        changeset: &'cs BonsaiChangeset,
+        content_manager: &'fetcher dyn FileContentManager,
+        cross_repo_push_source: CrossRepoPushSource,
+    ) -> Result<HookExecution> {
+        if cross_repo_push_source == CrossRepoPushSource::PushRedirected {
+            // For push-redirected commits, we rely on running source-repo hooks
+            return Ok(HookExecution::Accepted);
+        }
+
+        if self.is_bypassed(ctx) {
+            return Ok(HookExecution::Accepted);
+        }
+
+        if let Some(max_changed_files) = self.max_changed_files {
+            let changed_files = changeset.file_changes_map().len() as u64;
+            if changed_files > max_changed_files {
+                return Ok(HookExecution::Rejected(HookRejectionInfo::new_long(
+                    "Commit too large",
+                    format!(
+                        "Commit changed {} files but at most {} are allowed. Split it into smaller commits.",
+                        changed_files, max_changed_files,
+                    ),
+                )));
+            }
+        }
+
+        if let Some(max_path_depth) = self.max_path_depth {
+            for (path, file_change) in changeset.file_changes() {
+                if file_change.is_none() {
+                    // You can always delete paths
+                    continue;
+                }
+                let depth = path.num_components() as u64;
+                if depth > max_path_depth {
+                    return Ok(HookExecution::Rejected(HookRejectionInfo::new_long(
+                        "Path too deep",
+                        format!(
+                            "Path '{}' is {} levels deep but at most {} are allowed.",
+                            path, depth, max_path_depth,
+                        ),
+                    )));
+                }
+            }
+        }
+
+        if let Some(max_directory_entries) = self.max_directory_entries {
+            if let Some(rejection) = self
+                .check_directory_entries(
+                    ctx,
+                    bookmark,
+                    changeset,
+                    content_manager,
+                    max_directory_entries,
+                )
+                .await?
+            {
+                return Ok(rejection);
+            }
+        }
+
+        Ok(HookExecution::Accepted)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::Error;
+    use blobrepo_factory::new_memblob_empty;
+    use blobstore::Loadable;
+    use borrowed::borrowed;
+    use context::SessionContainer;
+    use fbinit::FacebookInit;
+    use hooks_content_stores::BlobRepoFileContentManager;
+    use maplit::{btreeset, hashmap};
+    use sshrelay::Metadata;
+    use std::collections::HashMap;
+    use tests_utils::{bookmark, CreateCommitContext};
+
+    #[fbinit::test]
+    async fn test_structural_limits(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = new_memblob_empty(None)?;
+        borrowed!(ctx, repo);
+
+        let root = CreateCommitContext::new_root(ctx, repo)
+            .add_file("dir/a", "a")
+            .add_file("dir/b", "b")
+            .commit()
+            .await?;
+        let book = bookmark(ctx, repo, "book").set_to(root).await?;
+
+        let cs_id = CreateCommitContext::new(ctx, repo, vec![root])
+            .add_file("dir/c", "c")
+            .add_file("dir/sub/deep/d", "d")
+            .delete_file("dir/a")
+            .commit()
+            .await?;
+        let bcs = cs_id.load(ctx, repo.blobstore()).await?;
+        let content_manager = BlobRepoFileContentManager::new(repo.clone());
+
+        let run = |ints: HashMap<String, i32>, ctx: CoreContext| {
+            let (bcs, book, content_manager) = (&bcs, &book, &content_manager);
+            async move {
+                build_hook(ints)?
+                    .run(
+                        &ctx,
+                        book,
+                        bcs,
+                        content_manager,
+                        CrossRepoPushSource::NativeToThisRepo,
+                    )
+                    .await
+            }
+        };
+
+        // dir/ ends up with b, c and sub.
+        let accepted = hashmap! {
+            "max_changed_files".to_string() => 3,
+            "max_path_depth".to_string() => 4,
+            "max_directory_entries".to_string() => 3,
+        };
+        assert_eq!(
+            run(accepted.clone(), ctx.clone()).await?,
+            HookExecution::Accepted
+        );
+
+        for (name, limit) in &[
+            ("max_changed_files", 2),
+            ("max_path_depth", 3),
+            ("max_directory_entries", 2),
+        ] {
+            let mut ints = accepted.clone();
+            ints.insert(name.to_string(), *limit);
+            match run(ints.clone(), ctx.clone()).await? {
+                HookExecution::Rejected(_) => {}
+                HookExecution::Accepted => {
+                    return Err(anyhow!("should be rejected by {}", name));
+                }
+            }
+
+            let identities = btreeset! { MononokeIdentity::new("USER", "admin")? };
+            let session = SessionContainer::builder(fb)
+                .metadata(Metadata::default().set_identities(identities))
+                .build();
+            let bypassing_ctx = CoreContext::test_mock_session(session);
+            assert_eq!(run(ints, bypassing_ctx).await?, HookExecution::Accepted);
+        }
+
+        Ok(())
+    }
+
+    fn build_hook(ints: HashMap<String, i32>) -> Result<StructuralLimits> {
+        let config = HookConfig {
+            bypass: None,
+            strings: hashmap! {},
+            ints,
+            string_lists: hashmap! {
+                "bypass_identities".to_string() => vec!["USER:admin".to_string()],
+            },
+            int_lists: hashmap! {},
+        };
+        StructuralLimits::builder().set_from_config(&config).build()
+    }
+}
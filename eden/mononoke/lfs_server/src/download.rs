@@ -7,13 +7,15 @@
 
 use std::str::FromStr;
 
-use anyhow::Context;
-use futures::stream::{StreamExt, TryStreamExt};
+use anyhow::{Context, Error};
+use bytes::Bytes;
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
 use gotham::state::State;
 use gotham_derive::{StateData, StaticResponseExtender};
 use serde::Deserialize;
 use slog::error;
 
+use blobstore::{Loadable, LoadableError};
 use filestore::{self, Alias, FetchKey};
 use gotham_ext::{
     content::{CompressedContentStream, ContentEncoding, ContentStream},
@@ -22,8 +24,8 @@ use gotham_ext::{
     response::{StreamBody, TryIntoResponse},
     stream_ext::GothamTryStreamExt,
 };
-use mononoke_types::{hash::Sha256, ContentId};
-use redactedblobstore::has_redaction_root_cause;
+use mononoke_types::{hash::Sha256, ContentId, MononokeId};
+use redactedblobstore::{config::GET_OPERATION, has_redaction_root_cause};
 use stats::prelude::*;
 
 use crate::errors::ErrorKind;
@@ -39,6 +41,10 @@ define_stats! {
         Duration::from_secs(5), Duration::from_secs(15), Duration::from_secs(60)
     ),
 }
+
+// Size of the reads of content that is served from local files.
+const LOCAL_FILE_READ_SIZE: u64 = 1024 * 1024;
+
 #[derive(Deserialize, StateData, StaticResponseExtender)]
 pub struct DownloadParamsContentId {
     repository: String,
@@ -51,22 +57,52 @@ pub struct DownloadParamsSha256 {
     oid: String,
 }
 
+/// Fetch the content, straight from the files that it is stored in if the repo's blobs are local.
+async fn fetch_stream(
+    ctx: &RepositoryRequestContext,
+    key: &FetchKey,
+) -> Result<Option<(BoxStream<'static, Result<Bytes, Error>>, u64)>, Error> {
+    let blobstore = ctx.repo.get_blobstore();
+
+    let local_files = match &ctx.local_files {
+        Some(local_files) => local_files,
+        None => {
+            let fetched = filestore::fetch_with_size(blobstore, ctx.ctx.clone(), key).await?;
+            return Ok(fetched.map(|(stream, size)| (stream.boxed(), size)));
+        }
+    };
+
+    // The files are read without going through the repo blobstore, so access to the content
+    // needs checking here.
+    let content_id = match key.load(&ctx.ctx, &blobstore).await {
+        Ok(content_id) => content_id,
+        Err(LoadableError::Missing(_)) => return Ok(None),
+        Err(LoadableError::Error(e)) => return Err(e),
+    };
+    blobstore.access_blobstore(&ctx.ctx, &content_id.blobstore_key(), GET_OPERATION)?;
+
+    let fetched =
+        filestore::fetch_file_segments(local_files, &ctx.ctx, &FetchKey::Canonical(content_id))
+            .await?;
+    Ok(fetched.map(|(segments, size)| {
+        let stream = filestore::stream_file_segments(segments, LOCAL_FILE_READ_SIZE);
+        (stream.boxed(), size)
+    }))
+}
+
 async fn fetch_by_key(
     ctx: RepositoryRequestContext,
     key: FetchKey,
     content_encoding: ContentEncoding,
     scuba: &mut Option<&mut ScubaMiddlewareState>,
 ) -> Result<impl TryIntoResponse, HttpError> {
-    // Query a stream out of the Filestore
-    let fetched = filestore::fetch_with_size(ctx.repo.get_blobstore(), ctx.ctx.clone(), &key)
-        .await
-        .map_err(|e| {
-            if has_redaction_root_cause(&e) {
-                HttpError::e410(e)
-            } else {
-                HttpError::e500(e.context(ErrorKind::FilestoreReadFailure))
-            }
-        })?;
+    let fetched = fetch_stream(&ctx, &key).await.map_err(|e| {
+        if has_redaction_root_cause(&e) {
+            HttpError::e410(e)
+        } else {
+            HttpError::e500(e.context(ErrorKind::FilestoreReadFailure))
+        }
+    })?;
 
     // Return a 404 if the stream doesn't exist.
     let (stream, size) = fetched
@@ -145,13 +181,20 @@ pub async fn download_sha256(state: &mut State) -> Result<impl TryIntoResponse,
 mod test {
     use super::*;
 
+    use std::sync::Arc;
+
     use anyhow::Error;
     use blobrepo_factory::TestRepoBuilder;
+    use blobstore::PutBehaviour;
     use fbinit::FacebookInit;
+    use fileblob::Fileblob;
+    use filestore::{FilestoreConfig, StoreRequest};
+    use futures::stream;
     use http::StatusCode;
     use maplit::hashmap;
     use mononoke_types::typed_hash::MononokeId;
     use mononoke_types_mocks::contentid::ONES_CTID;
+    use prefixblob::PrefixBlobstore;
     use redactedblobstore::RedactedMetadata;
 
     #[fbinit::test]
@@ -182,4 +225,78 @@ mod test {
         assert!(err.error.to_string().contains(reason));
         Ok(())
     }
+
+    #[fbinit::test]
+    async fn test_fetch_local_files(fb: FacebookInit) -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let fileblob = Fileblob::create(dir.path(), PutBehaviour::IfAbsent)?;
+        let repo = TestRepoBuilder::new()
+            .blobstore(Arc::new(fileblob.clone()))
+            .build()?;
+        let local_files = PrefixBlobstore::new(fileblob, repo.get_repoid().prefix());
+
+        let ctx = RepositoryRequestContext::test_builder(fb)?
+            .repo(repo)
+            .local_files(local_files)
+            .build()?;
+
+        let config = FilestoreConfig {
+            chunk_size: Some(4),
+            ..ctx.repo.filestore_config()
+        };
+        let meta = filestore::store(
+            ctx.repo.blobstore(),
+            config,
+            &ctx.ctx,
+            &StoreRequest::new(11),
+            stream::once(async move { Ok(Bytes::from("hello world")) }),
+        )
+        .await?;
+
+        let (stream, size) = fetch_stream(&ctx, &FetchKey::Aliased(Alias::Sha256(meta.sha256)))
+            .await?
+            .expect("content is missing");
+        assert_eq!(size, 11);
+        let bytes = stream.try_collect::<Vec<_>>().await?.concat();
+        assert_eq!(bytes, b"hello world");
+
+        let missing = fetch_stream(&ctx, &FetchKey::Canonical(ONES_CTID)).await?;
+        assert!(missing.is_none());
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_redacted_fetch_local_files(fb: FacebookInit) -> Result<(), Error> {
+        let content_id = ONES_CTID;
+        let reason = "test reason";
+
+        let dir = tempfile::tempdir()?;
+        let fileblob = Fileblob::create(dir.path(), PutBehaviour::IfAbsent)?;
+        let repo = TestRepoBuilder::new()
+            .blobstore(Arc::new(fileblob.clone()))
+            .redacted(Some(
+                hashmap! { content_id.blobstore_key() => RedactedMetadata {
+                   task: reason.to_string(),
+                   log_only: false,
+                }},
+            ))
+            .build()?;
+        let local_files = PrefixBlobstore::new(fileblob, repo.get_repoid().prefix());
+
+        let ctx = RepositoryRequestContext::test_builder(fb)?
+            .repo(repo)
+            .local_files(local_files)
+            .build()?;
+
+        let key = FetchKey::Canonical(content_id);
+
+        // Content served from local files is redacted too.
+        let err = fetch_by_key(ctx, key, ContentEncoding::Identity, &mut None)
+            .await
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(err.status_code, StatusCode::GONE);
+        Ok(())
+    }
 }
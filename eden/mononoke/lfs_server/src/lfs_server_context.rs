@@ -34,11 +34,13 @@ use tokio::runtime::Handle;
 
 use blobrepo::BlobRepo;
 use context::CoreContext;
+use fileblob::Fileblob;
 use hyper::{client::HttpConnector, Client};
 use hyper_openssl::HttpsConnector;
 use lfs_protocol::{RequestBatch, RequestObject, ResponseBatch};
 use metaconfig_types::RepoConfig;
 use mononoke_types::ContentId;
+use prefixblob::PrefixBlobstore;
 
 use crate::config::ServerConfig;
 use crate::errors::{ErrorKind, LfsServerContextErrorKind};
@@ -46,11 +48,21 @@ use crate::middleware::{LfsMethod, RequestContext};
 
 pub type HttpsHyperClient = Client<HttpsConnector<HttpConnector>>;
 
+/// The files of a repo whose blobs are stored locally, for serving content straight from them.
+pub type LocalFiles = PrefixBlobstore<Fileblob>;
+
+pub type Repository = (
+    BlobRepo,
+    ArcPermissionChecker,
+    RepoConfig,
+    Option<LocalFiles>,
+);
+
 // For some reason Source Control uses the read action to decide if a user can write to a repo...
 const ACL_CHECK_ACTION: &str = "read";
 
 struct LfsServerContextInner {
-    repositories: HashMap<String, (BlobRepo, ArcPermissionChecker, RepoConfig)>,
+    repositories: HashMap<String, Repository>,
     client: Arc<HttpsHyperClient>,
     server: Arc<ServerUris>,
     always_wait_for_upstream: bool,
@@ -66,7 +78,7 @@ pub struct LfsServerContext {
 
 impl LfsServerContext {
     pub fn new(
-        repositories: HashMap<String, (BlobRepo, ArcPermissionChecker, RepoConfig)>,
+        repositories: HashMap<String, Repository>,
         server: ServerUris,
         always_wait_for_upstream: bool,
         max_upload_size: Option<u64>,
@@ -101,6 +113,7 @@ impl LfsServerContext {
     ) -> Result<RepositoryRequestContext, LfsServerContextErrorKind> {
         let (
             repo,
+            local_files,
             aclchecker,
             client,
             server,
@@ -112,8 +125,9 @@ impl LfsServerContext {
             let inner = self.inner.lock().expect("poisoned lock");
 
             match inner.repositories.get(&repository) {
-                Some((repo, aclchecker, repo_config)) => (
+                Some((repo, aclchecker, repo_config, local_files)) => (
                     repo.clone(),
+                    local_files.clone(),
                     aclchecker.clone(),
                     inner.client.clone(),
                     inner.server.clone(),
@@ -137,6 +151,7 @@ impl LfsServerContext {
         Ok(RepositoryRequestContext {
             ctx,
             repo,
+            local_files,
             uri_builder: UriBuilder { repository, server },
             client: HttpClient::Enabled(client),
             config,
@@ -196,6 +211,7 @@ enum HttpClient {
 pub struct RepositoryRequestContext {
     pub ctx: CoreContext,
     pub repo: BlobRepo,
+    pub local_files: Option<LocalFiles>,
     pub uri_builder: UriBuilder,
     pub config: Arc<ServerConfig>,
     always_wait_for_upstream: bool,
@@ -490,6 +506,7 @@ mod test {
     pub struct TestContextBuilder {
         fb: FacebookInit,
         repo: BlobRepo,
+        local_files: Option<LocalFiles>,
         self_uri: String,
         upstream_uri: Option<String>,
         config: ServerConfig,
@@ -501,6 +518,11 @@ mod test {
             self
         }
 
+        pub fn local_files(mut self, local_files: LocalFiles) -> Self {
+            self.local_files = Some(local_files);
+            self
+        }
+
         pub fn upstream_uri(mut self, upstream_uri: Option<String>) -> Self {
             self.upstream_uri = upstream_uri;
             self
@@ -515,6 +537,7 @@ mod test {
             let Self {
                 fb,
                 repo,
+                local_files,
                 self_uri,
                 upstream_uri,
                 config,
@@ -525,6 +548,7 @@ mod test {
             Ok(RepositoryRequestContext {
                 ctx: CoreContext::test_mock(fb),
                 repo,
+                local_files,
                 config: Arc::new(config),
                 uri_builder,
                 always_wait_for_upstream: false,
@@ -539,6 +563,7 @@ mod test {
             Ok(TestContextBuilder {
                 fb,
                 repo: TestRepoBuilder::new().build()?,
+                local_files: None,
                 self_uri: "http://foo.com/".to_string(),
                 upstream_uri: Some("http://bar.com".to_string()),
                 config: ServerConfig::default(),
@@ -37,17 +37,19 @@ use std::str::FromStr;
 use std::sync::{atomic::AtomicBool, atomic::Ordering, Arc};
 use tokio::net::TcpListener;
 
-use blobrepo::BlobRepo;
 use blobrepo_factory::BlobrepoBuilder;
+use blobstore::PutBehaviour;
 use cmdlib::{
     args::{self, parse_config_spec_to_path, CachelibSettings},
     helpers::serve_forever,
     monitoring::{start_fb303_server, AliveService},
 };
+use fileblob::{Fileblob, FileblobOptions};
 use metaconfig_parser::RepoConfigs;
-use metaconfig_types::RepoConfig;
+use metaconfig_types::{BlobConfig, RepoConfig};
+use prefixblob::PrefixBlobstore;
 
-use crate::lfs_server_context::{LfsServerContext, ServerUris};
+use crate::lfs_server_context::{LfsServerContext, LocalFiles, ServerUris};
 use crate::middleware::{OdsMiddleware, RequestContextMiddleware};
 use crate::scuba::LfsScubaHandler;
 use crate::service::build_router;
@@ -267,6 +269,8 @@ fn main(fb: FacebookInit) -> Result<(), Error> {
     )?;
 
     let RepoConfigs { repos, common } = args::load_repo_configs(config_store, &matches)?;
+    let put_behaviour = blobstore_options.put_behaviour;
+    let fileblob_options = blobstore_options.fileblob_options;
 
     let futs = repos
         .into_iter()
@@ -311,11 +315,9 @@ fn main(fb: FacebookInit) -> Result<(), Error> {
                 };
 
                 let (repo, aclchecker) = try_join!(builder.build(), aclchecker)?;
+                let local_files = open_local_files(&config, put_behaviour, fileblob_options)?;
 
-                Result::<(String, (BlobRepo, ArcPermissionChecker, RepoConfig)), Error>::Ok((
-                    name,
-                    (repo, aclchecker, config),
-                ))
+                Result::<_, Error>::Ok((name, (repo, aclchecker, config, local_files)))
             }
         });
 
@@ -433,6 +435,22 @@ fn main(fb: FacebookInit) -> Result<(), Error> {
     Ok(())
 }
 
+/// Repos whose blobs are in local files serve downloads straight from those files.
+fn open_local_files(
+    config: &RepoConfig,
+    put_behaviour: PutBehaviour,
+    fileblob_options: FileblobOptions,
+) -> Result<Option<LocalFiles>, Error> {
+    match &config.storage_config.blobstore {
+        BlobConfig::Files { path } => {
+            let fileblob =
+                Fileblob::open_with_options(path.join("blobs"), put_behaviour, fileblob_options)?;
+            Ok(Some(PrefixBlobstore::new(fileblob, config.repoid.prefix())))
+        }
+        _ => Ok(None),
+    }
+}
+
 fn idents_from_values(matches: Option<Values>) -> Result<MononokeIdentitySet, Error> {
     match matches {
         Some(matches) => matches.map(FromStr::from_str).collect(),
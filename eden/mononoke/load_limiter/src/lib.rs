@@ -11,7 +11,7 @@ use anyhow::Result;
 use async_trait::async_trait;
 use cached_config::ConfigHandle;
 use fbinit::FacebookInit;
-use limits::types::{MononokeThrottleLimit, RateLimits};
+use limits::types::{MononokeThrottleLimit, MononokeThrottleLimits, RateLimits};
 use permission_checker::{MononokeIdentitySet, MononokeIdentitySetExt};
 pub use session_id::SessionId;
 use std::collections::hash_map::DefaultHasher;
@@ -44,6 +44,22 @@ pub enum Metric {
     EgressTotalManifests,
     EgressGetpackFiles,
     EgressCommits,
+    /// CPU time spent polling the session, in milliseconds.
+    CpuTimeMs,
+}
+
+impl Metric {
+    /// The limit on this metric, if there is one.
+    pub fn limit(&self, limits: &MononokeThrottleLimit) -> Option<f64> {
+        match self {
+            Self::EgressBytes => Some(limits.egress_bytes),
+            Self::IngressBlobstoreBytes => Some(limits.ingress_blobstore_bytes),
+            Self::EgressTotalManifests => Some(limits.total_manifests),
+            Self::EgressGetpackFiles => Some(limits.getpack_files),
+            Self::EgressCommits => Some(limits.commits),
+            Self::CpuTimeMs => limits.cpu_time_ms,
+        }
+    }
 }
 
 #[must_use]
@@ -90,7 +106,15 @@ impl LoadLimiterEnvironment {
         }
     }
 
-    pub fn get(&self, identities: &MononokeIdentitySet, hostname: Option<&str>) -> BoxLoadLimiter {
+    /// Build a load limiter for a client. If `reponame` is given and the config has limits for that
+    /// repo, load for the repo is tracked under its own category, so that it has a budget separate
+    /// from the other repos on this host.
+    pub fn get(
+        &self,
+        identities: &MononokeIdentitySet,
+        hostname: Option<&str>,
+        reponame: Option<&str>,
+    ) -> BoxLoadLimiter {
         let config = self.handle.get();
 
         let region_percentage =
@@ -101,26 +125,31 @@ impl LoadLimiterEnvironment {
             .hostprefix()
             .or_else(|| Some(extract_hostprefix(hostname?)));
 
-        let hostprefix_config = hostprefix
-            .and_then(|hostprefix| config.raw_config.hostprefixes.get(hostprefix))
-            .unwrap_or(&config.raw_config.defaults);
+        let (limits_config, repo_multiplier, has_repo_limits) =
+            select_limits(&config.raw_config, hostprefix, reponame);
 
         let multiplier = if identities.is_quicksand() {
             region_percentage / 100.0 * config.raw_config.quicksand_multiplier
         } else {
             region_percentage / 100.0
         };
+        let multiplier = multiplier * repo_multiplier;
 
         let throttle_limits = MononokeThrottleLimit {
-            egress_bytes: hostprefix_config.egress_bytes * multiplier,
-            ingress_blobstore_bytes: hostprefix_config.ingress_blobstore_bytes * multiplier,
-            total_manifests: hostprefix_config.total_manifests * multiplier,
-            quicksand_manifests: hostprefix_config.quicksand_manifests * multiplier,
-            getfiles_files: hostprefix_config.getfiles_files * multiplier,
-            getpack_files: hostprefix_config.getpack_files * multiplier,
-            commits: hostprefix_config.commits * multiplier,
+            egress_bytes: limits_config.egress_bytes * multiplier,
+            ingress_blobstore_bytes: limits_config.ingress_blobstore_bytes * multiplier,
+            total_manifests: limits_config.total_manifests * multiplier,
+            quicksand_manifests: limits_config.quicksand_manifests * multiplier,
+            getfiles_files: limits_config.getfiles_files * multiplier,
+            getpack_files: limits_config.getpack_files * multiplier,
+            commits: limits_config.commits * multiplier,
+            cpu_time_ms: limits_config.cpu_time_ms.map(|limit| limit * multiplier),
         };
 
+        let category = match reponame {
+            Some(reponame) if has_repo_limits => format!("{}.{}", self.category, reponame),
+            _ => self.category.as_ref().clone(),
+        };
 
         let in_throttled_slice = if let Some(ssl) = config.static_sliced_limits.as_ref() {
             is_client_in_throttled_slice(identities, ssl)
@@ -132,7 +161,7 @@ impl LoadLimiterEnvironment {
             self.fb,
             throttle_limits,
             config.raw_config.rate_limits.clone(),
-            self.category.as_ref().clone(),
+            category,
             in_throttled_slice,
         )
     }
@@ -164,6 +193,32 @@ pub(crate) fn is_client_in_throttled_slice(
     false
 }
 
+/// Pick the limits that apply to a client: the repo's own limits if it has any, otherwise those for
+/// the client's hostprefix, falling back to the defaults. Also returns the repo multiplier, and
+/// whether the limits came from the repo.
+fn select_limits<'a>(
+    config: &'a MononokeThrottleLimits,
+    hostprefix: Option<&str>,
+    reponame: Option<&str>,
+) -> (&'a MononokeThrottleLimit, f64, bool) {
+    let repo_limits = reponame.and_then(|reponame| config.repos.as_ref()?.get(reponame));
+
+    let repo_multiplier = reponame
+        .and_then(|reponame| config.repo_multipliers.as_ref()?.get(reponame))
+        .copied()
+        .unwrap_or(1.0);
+
+    match repo_limits {
+        Some(repo_limits) => (repo_limits, repo_multiplier, true),
+        None => {
+            let hostprefix_config = hostprefix
+                .and_then(|hostprefix| config.hostprefixes.get(hostprefix))
+                .unwrap_or(&config.defaults);
+            (hostprefix_config, repo_multiplier, false)
+        }
+    }
+}
+
 fn extract_hostprefix(hostname: &str) -> &str {
     let index = hostname.find(|c: char| !c.is_ascii_alphabetic());
     match index {
@@ -183,4 +238,62 @@ mod test {
         assert_eq!(extract_hostprefix("ololo"), "ololo");
         assert_eq!(extract_hostprefix(""), "");
     }
+
+    #[test]
+    fn test_select_limits() {
+        let limit = |egress_bytes| MononokeThrottleLimit {
+            egress_bytes,
+            ..Default::default()
+        };
+
+        let config = MononokeThrottleLimits {
+            defaults: limit(1.0),
+            hostprefixes: vec![("devvm".to_string(), limit(2.0))]
+                .into_iter()
+                .collect(),
+            repos: Some(
+                vec![("large".to_string(), limit(3.0))]
+                    .into_iter()
+                    .collect(),
+            ),
+            repo_multipliers: Some(
+                vec![("large".to_string(), 0.5), ("small".to_string(), 2.0)]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+
+        let (limits, multiplier, has_repo_limits) = select_limits(&config, None, None);
+        assert_eq!(limits.egress_bytes, 1.0);
+        assert_eq!(multiplier, 1.0);
+        assert!(!has_repo_limits);
+
+        let (limits, multiplier, has_repo_limits) =
+            select_limits(&config, Some("devvm"), Some("small"));
+        assert_eq!(limits.egress_bytes, 2.0);
+        assert_eq!(multiplier, 2.0);
+        assert!(!has_repo_limits);
+
+        let (limits, multiplier, has_repo_limits) =
+            select_limits(&config, Some("devvm"), Some("large"));
+        assert_eq!(limits.egress_bytes, 3.0);
+        assert_eq!(multiplier, 0.5);
+        assert!(has_repo_limits);
+    }
+
+    #[test]
+    fn test_metric_limit() {
+        let mut limits = MononokeThrottleLimit {
+            egress_bytes: 1.0,
+            commits: 2.0,
+            ..Default::default()
+        };
+        assert_eq!(Metric::EgressBytes.limit(&limits), Some(1.0));
+        assert_eq!(Metric::EgressCommits.limit(&limits), Some(2.0));
+        assert_eq!(Metric::CpuTimeMs.limit(&limits), None);
+
+        limits.cpu_time_ms = Some(3.0);
+        assert_eq!(Metric::CpuTimeMs.limit(&limits), Some(3.0));
+    }
 }
@@ -169,11 +169,14 @@ fn parse_common_config(common: RawCommonConfig) -> Result<CommonConfig> {
         table: scuba_censored_table,
         local_path: scuba_censored_local_path,
     };
+    let tcp_socket_options = common.tcp_socket_options.convert()?.unwrap_or_default();
+
     Ok(CommonConfig {
         security_config,
         loadlimiter_category,
         enable_http_control_api: common.enable_http_control_api,
         censored_scuba_params,
+        tcp_socket_options,
     })
 }
 
@@ -225,6 +228,8 @@ fn parse_repo_config(
         warm_bookmark_cache_check_blobimport,
         repo_client_knobs,
         phabricator_callsign,
+        edenapi_read_mirror,
+        ephemeral_blobstore,
         ..
     } = repo_config;
 
@@ -252,6 +257,14 @@ fn parse_repo_config(
         &storage_config.ok_or_else(|| anyhow!("missing storage_config from configuration"))?,
     )?;
 
+    let edenapi_read_mirror = edenapi_read_mirror
+        .map(|raw| crate::convert::repo::convert_edenapi_read_mirror_config(raw, &get_storage))
+        .transpose()?;
+
+    let ephemeral_blobstore = ephemeral_blobstore
+        .map(|raw| crate::convert::repo::convert_ephemeral_blobstore_config(raw, &get_storage))
+        .transpose()?;
+
     let wireproto_logging = wireproto_logging
         .map(|raw| crate::convert::repo::convert_wireproto_logging_config(raw, get_storage))
         .transpose()?
@@ -386,6 +399,8 @@ fn parse_repo_config(
         warm_bookmark_cache_check_blobimport,
         repo_client_knobs,
         phabricator_callsign,
+        edenapi_read_mirror,
+        ephemeral_blobstore,
     })
 }
 
@@ -431,15 +446,16 @@ mod test {
     use maplit::{btreemap, hashmap, hashset};
     use metaconfig_types::{
         BlobConfig, BlobstoreId, BookmarkParams, Bundle2ReplayParams, CacheWarmupParams,
-        CommitSyncConfigVersion, CommitSyncDirection, DatabaseConfig,
-        DefaultSmallToLargeCommitSyncPathAction, DerivedDataConfig, DerivedDataTypesConfig,
-        FilestoreParams, HookBypass, HookConfig, HookManagerParams, HookParams,
-        InfinitepushNamespace, InfinitepushParams, LfsParams, LocalDatabaseConfig,
-        MetadataDatabaseConfig, MultiplexId, MultiplexedStoreType, PushParams, PushrebaseFlags,
-        PushrebaseParams, RemoteDatabaseConfig, RemoteMetadataDatabaseConfig, RepoClientKnobs,
-        SegmentedChangelogConfig, ShardableRemoteDatabaseConfig, ShardedRemoteDatabaseConfig,
-        SmallRepoCommitSyncConfig, SourceControlServiceMonitoring, SourceControlServiceParams,
-        UnodeVersion, WireprotoLoggingConfig,
+        CommandDeprecation, CommitSyncConfigVersion, CommitSyncDirection, ComparableRegex,
+        DatabaseConfig, DefaultSmallToLargeCommitSyncPathAction, DerivedDataConfig,
+        DerivedDataTypesConfig, EdenApiReadMirrorConfig, EphemeralBlobstoreConfig, FilestoreParams,
+        HookBypass, HookConfig, HookManagerParams, HookParams, InfinitepushNamespace,
+        InfinitepushParams, LfsParams, LocalDatabaseConfig, MetadataDatabaseConfig, MultiplexId,
+        MultiplexedStoreType, PushParams, PushrebaseFlags, PushrebaseParams, RemoteDatabaseConfig,
+        RemoteMetadataDatabaseConfig, RepoClientKnobs, SegmentedChangelogConfig,
+        ShardableRemoteDatabaseConfig, ShardedRemoteDatabaseConfig, SmallRepoCommitSyncConfig,
+        SourceControlServiceMonitoring, SourceControlServiceParams, TcpSocketOptions, UnodeVersion,
+        WireprotoLoggingConfig,
     };
     use mononoke_types::MPath;
     use nonzero_ext::nonzero;
@@ -746,6 +762,7 @@ mod test {
 
             [[bookmarks]]
             regex="[^/]*/stable"
+            pushrebase_added_trailers=["Release-Branch: stable"]
 
             [[hooks]]
             name="hook1"
@@ -768,6 +785,7 @@ mod test {
             casefolding_check = false
             emit_obsmarkers = false
             allow_change_xrepo_mapping_extra = true
+            author_format = "^.+ <.+@.+>$"
 
             [lfs]
             threshold = 1000
@@ -786,6 +804,7 @@ mod test {
             [filestore]
             chunk_size = 768
             concurrency = 48
+            inline_threshold = 64
 
             [source_control_service_monitoring]
             bookmarks_to_report_age= ["master", "master2"]
@@ -793,6 +812,10 @@ mod test {
             [repo_client_knobs]
             allow_short_getpack_history = true
 
+            [repo_client_knobs.deprecated_commands.getpackv1]
+            sunset_date = "2021-01-01"
+            replacement = "EdenAPI"
+
             [segmented_changelog_config]
             enabled = true
             update_algorithm = "ondemand"
@@ -815,12 +838,32 @@ mod test {
 
             [storage.files.blobstore.blob_files]
             path = "/tmp/www"
+
+            [storage.files_mirror.metadata.local]
+            local_db_path = "/tmp/www"
+
+            [storage.files_mirror.blobstore.blob_files]
+            path = "/tmp/www_mirror"
+
+            [edenapi_read_mirror]
+            storage_config = "files_mirror"
+            sample_percentage = 10
+
+            [ephemeral_blobstore]
+            storage_config = "files_mirror"
+            default_ttl_secs = 3600
         "#;
         let common_content = r#"
             loadlimiter_category="test-category"
             scuba_censored_table="censored_table"
             scuba_local_path_censored="censored_local_path"
 
+            [tcp_socket_options]
+            keepalive_idle_secs=60
+            keepalive_interval_secs=10
+            keepalive_probes=6
+            user_timeout_millis=120000
+
             [[whitelist_entry]]
             tier = "tier1"
 
@@ -915,6 +958,8 @@ mod test {
                         allow_only_external_sync: None,
                         rewrite_dates: None,
                         hooks_skip_ancestors_of: vec![],
+                        pushrebase_author_format: None,
+                        pushrebase_added_trailers: None,
                     },
                     BookmarkParams {
                         bookmark: Regex::new("[^/]*/stable").unwrap().into(),
@@ -924,6 +969,8 @@ mod test {
                         allow_only_external_sync: None,
                         rewrite_dates: None,
                         hooks_skip_ancestors_of: vec![],
+                        pushrebase_author_format: None,
+                        pushrebase_added_trailers: Some(vec!["Release-Branch: stable".to_string()]),
                     },
                 ],
                 hooks: vec![
@@ -963,6 +1010,10 @@ mod test {
                         forbid_p2_root_rebases: false,
                         casefolding_check: false,
                         not_generated_filenodes_limit: 500,
+                        author_format: Some(ComparableRegex::new(
+                            Regex::new("^.+ <.+@.+>$").unwrap(),
+                        )),
+                        added_trailers: vec![],
                     },
                     block_merges: false,
                     emit_obsmarkers: false,
@@ -1006,6 +1057,7 @@ mod test {
                 filestore: Some(FilestoreParams {
                     chunk_size: 768,
                     concurrency: 48,
+                    inline_threshold: Some(64),
                 }),
                 commit_sync_config: None,
                 hipster_acl: Some("foo/test".to_string()),
@@ -1051,8 +1103,16 @@ mod test {
                 warm_bookmark_cache_check_blobimport: true,
                 repo_client_knobs: RepoClientKnobs {
                     allow_short_getpack_history: true,
+                    deprecated_commands: hashmap! {
+                        "getpackv1".to_string() => CommandDeprecation {
+                            sunset_date: "2021-01-01".to_string(),
+                            replacement: Some("EdenAPI".to_string()),
+                        },
+                    },
                 },
                 phabricator_callsign: Some("FBS".to_string()),
+                edenapi_read_mirror: None,
+                ephemeral_blobstore: None,
             },
         );
 
@@ -1112,6 +1172,28 @@ mod test {
                 warm_bookmark_cache_check_blobimport: false,
                 repo_client_knobs: RepoClientKnobs::default(),
                 phabricator_callsign: Some("WWW".to_string()),
+                edenapi_read_mirror: Some(EdenApiReadMirrorConfig {
+                    storage_config: StorageConfig {
+                        metadata: MetadataDatabaseConfig::Local(LocalDatabaseConfig {
+                            path: "/tmp/www".into(),
+                        }),
+                        blobstore: BlobConfig::Files {
+                            path: "/tmp/www_mirror".into(),
+                        },
+                    },
+                    sample_percentage: 10,
+                }),
+                ephemeral_blobstore: Some(EphemeralBlobstoreConfig {
+                    storage_config: StorageConfig {
+                        metadata: MetadataDatabaseConfig::Local(LocalDatabaseConfig {
+                            path: "/tmp/www".into(),
+                        }),
+                        blobstore: BlobConfig::Files {
+                            path: "/tmp/www_mirror".into(),
+                        },
+                    },
+                    default_ttl: Duration::from_secs(3600),
+                }),
             },
         );
         assert_eq!(
@@ -1130,6 +1212,12 @@ mod test {
                     table: Some("censored_table".to_string()),
                     local_path: Some("censored_local_path".to_string()),
                 },
+                tcp_socket_options: TcpSocketOptions {
+                    keepalive_idle: Some(Duration::from_secs(60)),
+                    keepalive_interval: Some(Duration::from_secs(10)),
+                    keepalive_probes: Some(6),
+                    user_timeout: Some(Duration::from_millis(120_000)),
+                },
             }
         );
         assert_eq!(
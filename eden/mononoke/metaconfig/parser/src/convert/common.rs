@@ -0,0 +1,38 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::convert::TryInto;
+use std::time::Duration;
+
+use anyhow::Result;
+use metaconfig_types::TcpSocketOptions;
+use repos::RawTcpSocketOptions;
+
+use crate::convert::Convert;
+
+impl Convert for RawTcpSocketOptions {
+    type Output = TcpSocketOptions;
+
+    fn convert(self) -> Result<Self::Output> {
+        let secs = |v: Option<i64>| -> Result<_> {
+            Ok(v.map(|v| v.try_into())
+                .transpose()?
+                .map(Duration::from_secs))
+        };
+
+        Ok(TcpSocketOptions {
+            keepalive_idle: secs(self.keepalive_idle_secs)?,
+            keepalive_interval: secs(self.keepalive_interval_secs)?,
+            keepalive_probes: self.keepalive_probes.map(|v| v.try_into()).transpose()?,
+            user_timeout: self
+                .user_timeout_millis
+                .map(|v| v.try_into())
+                .transpose()?
+                .map(Duration::from_millis),
+        })
+    }
+}
@@ -8,6 +8,7 @@
 use anyhow::Result;
 
 mod commit_sync;
+mod common;
 pub(crate) mod repo;
 mod storage;
 
@@ -12,19 +12,21 @@ use std::time::Duration;
 use anyhow::{anyhow, Context, Result};
 use bookmarks_types::BookmarkName;
 use metaconfig_types::{
-    BookmarkOrRegex, BookmarkParams, Bundle2ReplayParams, CacheWarmupParams,
+    BookmarkOrRegex, BookmarkParams, Bundle2ReplayParams, CacheWarmupParams, CommandDeprecation,
     CommitcloudBookmarksFillerMode, ComparableRegex, DerivedDataConfig, DerivedDataTypesConfig,
-    HookBypass, HookConfig, HookManagerParams, HookParams, InfinitepushNamespace,
-    InfinitepushParams, LfsParams, PushParams, PushrebaseFlags, PushrebaseParams, RepoClientKnobs,
-    SegmentedChangelogConfig, ServiceWriteRestrictions, SourceControlServiceMonitoring,
-    SourceControlServiceParams, StorageConfig, UnodeVersion, WireprotoLoggingConfig,
+    EdenApiReadMirrorConfig, EphemeralBlobstoreConfig, HookBypass, HookConfig, HookManagerParams,
+    HookParams, InfinitepushNamespace, InfinitepushParams, LfsParams, PushParams, PushrebaseFlags,
+    PushrebaseParams, RepoClientKnobs, SegmentedChangelogConfig, ServiceWriteRestrictions,
+    SourceControlServiceMonitoring, SourceControlServiceParams, StorageConfig, UnodeVersion,
+    WireprotoLoggingConfig,
 };
 use mononoke_types::{MPath, PrefixTrie};
 use regex::Regex;
 use repos::{
     RawBookmarkConfig, RawBundle2ReplayParams, RawCacheWarmupConfig, RawCommitcloudBookmarksFiller,
-    RawDerivedDataConfig, RawDerivedDataTypesConfig, RawHookConfig, RawHookManagerParams,
-    RawInfinitepushParams, RawLfsParams, RawPushParams, RawPushrebaseParams, RawRepoClientKnobs,
+    RawDerivedDataConfig, RawDerivedDataTypesConfig, RawEdenApiReadMirrorConfig,
+    RawEphemeralBlobstoreConfig, RawHookConfig, RawHookManagerParams, RawInfinitepushParams,
+    RawLfsParams, RawPushParams, RawPushrebaseParams, RawRepoClientKnobs,
     RawSegmentedChangelogConfig, RawServiceWriteRestrictions, RawSourceControlServiceMonitoring,
     RawSourceControlServiceParams, RawWireprotoLoggingConfig,
 };
@@ -69,6 +71,36 @@ pub(crate) fn convert_wireproto_logging_config(
     })
 }
 
+pub(crate) fn convert_edenapi_read_mirror_config(
+    raw: RawEdenApiReadMirrorConfig,
+    get_storage: impl Fn(&str) -> Result<StorageConfig>,
+) -> Result<EdenApiReadMirrorConfig> {
+    let sample_percentage: u32 = raw.sample_percentage.try_into()?;
+    if sample_percentage > 100 {
+        return Err(anyhow!(
+            "Invalid configuration: EdenAPI read mirror sample percentage is {}, over 100",
+            sample_percentage
+        ));
+    }
+
+    Ok(EdenApiReadMirrorConfig {
+        storage_config: get_storage(&raw.storage_config)?,
+        sample_percentage,
+    })
+}
+
+pub(crate) fn convert_ephemeral_blobstore_config(
+    raw: RawEphemeralBlobstoreConfig,
+    get_storage: impl Fn(&str) -> Result<StorageConfig>,
+) -> Result<EphemeralBlobstoreConfig> {
+    let default_ttl_secs: u64 = raw.default_ttl_secs.try_into()?;
+
+    Ok(EphemeralBlobstoreConfig {
+        storage_config: get_storage(&raw.storage_config)?,
+        default_ttl: Duration::from_secs(default_ttl_secs),
+    })
+}
+
 impl Convert for RawCacheWarmupConfig {
     type Output = CacheWarmupParams;
 
@@ -177,6 +209,12 @@ impl Convert for RawBookmarkConfig {
             .into_iter()
             .map(BookmarkName::new)
             .collect::<Result<Vec<_>, _>>()?;
+        let pushrebase_author_format = self
+            .pushrebase_author_format
+            .map(|re| Regex::new(&re))
+            .transpose()?
+            .map(ComparableRegex::new);
+        let pushrebase_added_trailers = self.pushrebase_added_trailers;
 
         Ok(BookmarkParams {
             bookmark: bookmark_or_regex,
@@ -186,6 +224,8 @@ impl Convert for RawBookmarkConfig {
             allow_only_external_sync,
             rewrite_dates,
             hooks_skip_ancestors_of,
+            pushrebase_author_format,
+            pushrebase_added_trailers,
         })
     }
 }
@@ -222,6 +262,12 @@ impl Convert for RawPushrebaseParams {
                     .casefolding_check
                     .unwrap_or(default.flags.casefolding_check),
                 not_generated_filenodes_limit: 500,
+                author_format: self
+                    .author_format
+                    .map(|re| Regex::new(&re))
+                    .transpose()?
+                    .map(ComparableRegex::new),
+                added_trailers: self.added_trailers.unwrap_or_default(),
             },
             commit_scribe_category: self.commit_scribe_category,
             block_merges: self.block_merges.unwrap_or(default.block_merges),
@@ -411,8 +457,22 @@ impl Convert for RawRepoClientKnobs {
     type Output = RepoClientKnobs;
 
     fn convert(self) -> Result<Self::Output> {
+        let deprecated_commands = self
+            .deprecated_commands
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(command, deprecation)| {
+                let deprecation = CommandDeprecation {
+                    sunset_date: deprecation.sunset_date,
+                    replacement: deprecation.replacement,
+                };
+                (command, deprecation)
+            })
+            .collect();
+
         Ok(RepoClientKnobs {
             allow_short_getpack_history: self.allow_short_getpack_history,
+            deprecated_commands,
         })
     }
 }
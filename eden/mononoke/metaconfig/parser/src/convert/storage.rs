@@ -126,6 +126,11 @@ impl Convert for RawBlobstoreConfig {
                     .map(|x| x.try_into())
                     .transpose()?,
             },
+            RawBlobstoreConfig::encrypted(raw) => BlobConfig::Encrypted {
+                blobconfig: Box::new(raw.blobstore.convert()?),
+                keyring_path: PathBuf::from(raw.keyring_path),
+                active_key_id: raw.active_key_id,
+            },
             RawBlobstoreConfig::UnknownField(f) => {
                 return Err(anyhow!("unsupported blobstore configuration ({})", f));
             }
@@ -240,6 +245,7 @@ impl Convert for RawFilestoreParams {
         Ok(FilestoreParams {
             chunk_size: self.chunk_size.try_into()?,
             concurrency: self.concurrency.try_into()?,
+            inline_threshold: self.inline_threshold.map(TryInto::try_into).transpose()?,
         })
     }
 }
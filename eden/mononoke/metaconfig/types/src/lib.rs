@@ -106,6 +106,23 @@ pub struct CommonConfig {
     /// Whether to enable the control API over HTTP. At this time, this is only meant to be used in
     /// tests.
     pub enable_http_control_api: bool,
+    /// Options for the sockets of the connections that the server accepts
+    pub tcp_socket_options: TcpSocketOptions,
+}
+
+/// Options for the sockets of the connections that the server accepts, so that half-open
+/// connections from clients that went away get closed. What isn't set is left to the kernel.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TcpSocketOptions {
+    /// How long a connection can be idle before keepalive probes are sent. Keepalives are
+    /// disabled if this isn't set.
+    pub keepalive_idle: Option<Duration>,
+    /// Time between two keepalive probes
+    pub keepalive_interval: Option<Duration>,
+    /// Number of unanswered keepalive probes after which the connection is closed
+    pub keepalive_probes: Option<u32>,
+    /// How long sent data can remain unacknowledged before the connection is closed
+    pub user_timeout: Option<Duration>,
 }
 
 /// Configuration for logging of censored blobstore accesses
@@ -199,13 +216,29 @@ pub struct RepoConfig {
     pub repo_client_knobs: RepoClientKnobs,
     /// Callsign to check phabricator commits
     pub phabricator_callsign: Option<String>,
+    /// Mirroring of EdenAPI reads to another storage config
+    pub edenapi_read_mirror: Option<EdenApiReadMirrorConfig>,
+    /// Storage for blobs that are only kept for a while
+    pub ephemeral_blobstore: Option<EphemeralBlobstoreConfig>,
 }
 
 /// Configuration for repo_client module
-#[derive(Eq, Copy, Clone, Default, Debug, PartialEq)]
+#[derive(Eq, Clone, Default, Debug, PartialEq)]
 pub struct RepoClientKnobs {
     /// Return shorter file history in getpack call
     pub allow_short_getpack_history: bool,
+    /// Wireproto commands that are going away, by name. They still work, but the clients that
+    /// use them are warned once per session.
+    pub deprecated_commands: HashMap<String, CommandDeprecation>,
+}
+
+/// Deprecation of a wireproto command
+#[derive(Eq, Clone, Debug, PartialEq)]
+pub struct CommandDeprecation {
+    /// When the command will stop working, as shown to the users
+    pub sunset_date: String,
+    /// What to use instead
+    pub replacement: Option<String>,
 }
 
 /// Config for derived data
@@ -391,6 +424,33 @@ impl BookmarkAttrs {
         self.select(bookmark).any(|params| params.only_fast_forward)
     }
 
+    /// The pushrebase flags to use for pushes onto `bookmark`: the repo's `flags`, with the
+    /// overrides from the bookmark config applied.
+    pub fn pushrebase_flags(
+        &self,
+        flags: &PushrebaseFlags,
+        bookmark: &BookmarkName,
+    ) -> PushrebaseFlags {
+        let mut flags = flags.clone();
+        if let Some(rewritedates) = self.should_rewrite_dates(bookmark) {
+            flags.rewritedates = rewritedates;
+        }
+        // NOTE: As for dates, the first matching bookmark config that sets a policy wins.
+        if let Some(author_format) = self
+            .select(bookmark)
+            .find_map(|params| params.pushrebase_author_format.as_ref())
+        {
+            flags.author_format = Some(author_format.clone());
+        }
+        if let Some(added_trailers) = self
+            .select(bookmark)
+            .find_map(|params| params.pushrebase_added_trailers.as_ref())
+        {
+            flags.added_trailers = added_trailers.clone();
+        }
+        flags
+    }
+
     /// Check if a bookmark config overrides whether date should be rewritten during pushrebase.
     /// Return None if there are no bookmark config overriding rewrite_dates.
     pub fn should_rewrite_dates(&self, bookmark: &BookmarkName) -> Option<bool> {
@@ -443,6 +503,10 @@ pub struct BookmarkParams {
     /// Skip hooks for changesets that are already ancestors of these
     /// bookmarks
     pub hooks_skip_ancestors_of: Vec<BookmarkName>,
+    /// Overrides the repo's pushrebase author format for this bookmark
+    pub pushrebase_author_format: Option<ComparableRegex>,
+    /// Overrides the repo's pushrebase added trailers for this bookmark
+    pub pushrebase_added_trailers: Option<Vec<String>>,
 }
 
 /// The type of the hook
@@ -560,7 +624,7 @@ impl Default for PushParams {
 }
 
 /// Flags for the pushrebase inner loop
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct PushrebaseFlags {
     /// Update dates of rebased commits
     pub rewritedates: bool,
@@ -572,6 +636,11 @@ pub struct PushrebaseFlags {
     pub casefolding_check: bool,
     /// How many commits are allowed to not have filenodes generated.
     pub not_generated_filenodes_limit: u64,
+    /// If set, the authors of pushrebased commits must match this pattern
+    pub author_format: Option<ComparableRegex>,
+    /// Trailers (e.g. "Release-Branch: 1.2") added to the messages of pushrebased commits, unless
+    /// they already have them
+    pub added_trailers: Vec<String>,
 }
 
 impl Default for PushrebaseFlags {
@@ -582,6 +651,8 @@ impl Default for PushrebaseFlags {
             forbid_p2_root_rebases: true,
             casefolding_check: true,
             not_generated_filenodes_limit: 500,
+            author_format: None,
+            added_trailers: Vec::new(),
         }
     }
 }
@@ -827,6 +898,15 @@ pub enum BlobConfig {
         /// Limit the number of concurrent operations to S3 blobstore.
         num_concurrent_operations: Option<usize>,
     },
+    /// A blobstore that encrypts blobs before storing them in the blobstore it wraps
+    Encrypted {
+        /// The config for the blobstore that is wrapped.
+        blobconfig: Box<BlobConfig>,
+        /// Path to the JSON file mapping key ids to hex-encoded AES-256 keys.
+        keyring_path: PathBuf,
+        /// The key used to encrypt new blobs. It must be in the keyring.
+        active_key_id: String,
+    },
 }
 
 impl BlobConfig {
@@ -844,6 +924,7 @@ impl BlobConfig {
                 .all(BlobConfig::is_local),
             Logging { blobconfig, .. } => blobconfig.is_local(),
             Pack { blobconfig, .. } => blobconfig.is_local(),
+            Encrypted { blobconfig, .. } => blobconfig.is_local(),
         }
     }
 }
@@ -1049,6 +1130,8 @@ pub struct FilestoreParams {
     pub chunk_size: u64,
     /// Max number of concurrent chunk uploads to perform in the Filestore.
     pub concurrency: usize,
+    /// Files up to this size, in bytes, are stored inline in their aliases.
+    pub inline_threshold: Option<u64>,
 }
 
 /// Default path action to perform when syncing commits
@@ -1169,6 +1252,27 @@ impl Default for WireprotoLoggingConfig {
     }
 }
 
+/// Mirroring of a sample of the EdenAPI read requests to another storage config, so that it can
+/// be validated against real traffic before a migration. The mirrored reads are compared with
+/// what was served, and the differences are logged.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EdenApiReadMirrorConfig {
+    /// Storage config that the reads are mirrored to
+    pub storage_config: StorageConfig,
+    /// Percentage of the read requests that are mirrored
+    pub sample_percentage: u32,
+}
+
+/// Storage for blobs that are only kept for a while, e.g. snapshots of working copies.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EphemeralBlobstoreConfig {
+    /// The blobstore keeps the blobs, and the metadata database keeps track of them. The
+    /// blobstore is expected to drop blobs on its own once they are older than any TTL in use.
+    pub storage_config: StorageConfig,
+    /// How long blobs are kept for if nothing else is asked for
+    pub default_ttl: Duration,
+}
+
 /// Source Control Service options
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct SourceControlServiceParams {
@@ -72,6 +72,8 @@ pub enum MononokeError {
     HookFailure(Vec<HookRejection>),
     #[error("not available: {0}")]
     NotAvailable(String),
+    #[error("repo is locked: {0}")]
+    RepoLocked(String),
     #[error("internal error: {0}")]
     InternalError(#[source] InternalError),
 }
@@ -108,6 +110,7 @@ impl From<BookmarkMovementError> for MononokeError {
         use BookmarkMovementError::*;
         match e {
             HookFailure(rejections) => MononokeError::HookFailure(rejections),
+            RepoLocked(reason) => MononokeError::RepoLocked(reason),
             Error(e) => MononokeError::InternalError(InternalError::from(e)),
             _ => MononokeError::InvalidRequest(e.to_string()),
         }
@@ -170,6 +170,9 @@ pub struct MononokeEnvironment<'a> {
 pub enum WarmBookmarksCacheDerivedData {
     HgOnly,
     AllKinds,
+    /// Move bookmarks once their hg changesets are derived, but don't derive them: that is left
+    /// to the bookmark_warmer.
+    WaitForHg,
     None,
 }
 
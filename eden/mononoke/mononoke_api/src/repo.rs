@@ -17,7 +17,7 @@ use blobrepo::BlobRepo;
 use blobrepo_factory::{BlobrepoBuilder, ReadOnlyStorage};
 use blobrepo_hg::BlobRepoHg;
 use blobstore::Loadable;
-use blobstore_factory::make_metadata_sql_factory;
+use blobstore_factory::{make_blobstore, make_metadata_sql_factory};
 pub use bookmarks::Freshness as BookmarkFreshness;
 use bookmarks::{BookmarkKind, BookmarkName, BookmarkPagination, BookmarkPrefix, Bookmarks};
 use changeset_info::ChangesetInfo;
@@ -26,6 +26,7 @@ use cross_repo_sync::{
     types::Target, CandidateSelectionHint, CommitSyncContext, CommitSyncRepos, CommitSyncer,
 };
 use derived_data::BonsaiDerivable;
+use ephemeral_blobstore::{EphemeralBlobstore, SqlBubbleStore};
 use fbinit::FacebookInit;
 use filestore::{Alias, FetchKey};
 use futures::compat::Stream01CompatExt;
@@ -45,18 +46,18 @@ use metaconfig_types::{
 };
 use mononoke_types::{
     hash::{GitSha1, Sha1, Sha256},
-    Generation, RepositoryId, Svnrev,
+    Generation, RepositoryId, Svnrev, Timestamp,
 };
 use mutable_counters::SqlMutableCounters;
+use mutable_renames::{MutableRenames, SqlMutableRenames};
 use permission_checker::{ArcPermissionChecker, PermissionCheckerBuilder};
 use reachabilityindex::LeastCommonAncestorsHint;
 use regex::Regex;
-use repo_read_write_status::{RepoReadWriteFetcher, SqlRepoReadWriteStatus};
+use repo_read_write_status::{RepoReadWriteFetcher, RepoWriteError};
 use revset::AncestorsNodeStream;
 use segmented_changelog::{CloneData, Location, SegmentedChangelog, StreamCloneData};
 use skiplist::{fetch_skiplist_index, SkiplistIndex};
 use slog::{debug, error, o, Logger};
-use sql_construct::facebook::FbSqlConstruct;
 use sql_construct::SqlConstruct;
 use sql_construct::SqlConstructFromMetadataDatabaseConfig;
 use sql_ext::facebook::MysqlOptions;
@@ -107,6 +108,8 @@ pub struct Repo {
     pub(crate) live_commit_sync_config: Arc<dyn LiveCommitSyncConfig>,
     pub(crate) hook_manager: Arc<HookManager>,
     pub(crate) readonly_fetcher: RepoReadWriteFetcher,
+    pub(crate) ephemeral_blobstore: Option<EphemeralBlobstore>,
+    pub(crate) mutable_renames: Arc<dyn MutableRenames>,
 }
 
 #[derive(Clone)]
@@ -222,6 +225,10 @@ impl Repo {
             WarmBookmarksCacheDerivedData::AllKinds => {
                 warm_bookmarks_cache_builder.add_all_derived_data_warmers()?;
             }
+            WarmBookmarksCacheDerivedData::WaitForHg => {
+                warm_bookmarks_cache_builder
+                    .add_derived_data_waiters(Some(MappedHgChangesetId::NAME))?;
+            }
             WarmBookmarksCacheDerivedData::None => {}
         }
 
@@ -239,18 +246,53 @@ impl Repo {
         let warm_bookmarks_cache =
             warm_bookmarks_cache_builder.build(env.warm_bookmarks_cache_delay);
 
-        let sql_read_write_status = async {
-            if let Some(addr) = &config.write_lock_db_address {
-                let r = SqlRepoReadWriteStatus::with_xdb(
+        let readonly_fetcher = RepoReadWriteFetcher::from_repo_config(
+            env.fb,
+            &config,
+            &env.mysql_options,
+            env.readonly_storage.0,
+        );
+
+        let mutable_renames = SqlMutableRenames::with_metadata_database_config(
+            env.fb,
+            &config.storage_config.metadata,
+            &env.mysql_options,
+            env.readonly_storage.0,
+        );
+
+        let ephemeral_blobstore = {
+            let logger = &logger;
+            let config = &config;
+            let persistent = blob_repo.get_blobstore().boxed();
+            async move {
+                let ephemeral_config = match &config.ephemeral_blobstore {
+                    Some(ephemeral_config) => ephemeral_config,
+                    None => return Ok(None),
+                };
+                let storage_config = &ephemeral_config.storage_config;
+                let ephemeral = make_blobstore(
                     env.fb,
-                    addr.clone(),
+                    storage_config.blobstore.clone(),
+                    &env.mysql_options,
+                    env.readonly_storage,
+                    &env.blobstore_options,
+                    logger,
+                    env.config_store,
+                )
+                .await?;
+                let bubbles = SqlBubbleStore::with_metadata_database_config(
+                    env.fb,
+                    &storage_config.metadata,
                     &env.mysql_options,
                     env.readonly_storage.0,
                 )
                 .await?;
-                Ok(Some(r))
-            } else {
-                Ok(None)
+                Ok(Some(EphemeralBlobstore::new(
+                    ephemeral,
+                    persistent,
+                    bubbles,
+                    ephemeral_config.default_ttl,
+                )))
             }
         };
 
@@ -260,22 +302,20 @@ impl Repo {
             skiplist_index,
             warm_bookmarks_cache,
             hook_manager,
-            sql_read_write_status,
+            readonly_fetcher,
+            ephemeral_blobstore,
+            mutable_renames,
         ) = try_join!(
             repo_permission_checker.watched(&logger),
             service_permission_checker.watched(&logger),
             skiplist_index.watched(&logger),
             warm_bookmarks_cache.watched(&logger),
             hook_manager.watched(&logger),
-            sql_read_write_status.watched(&logger),
+            readonly_fetcher.watched(&logger),
+            ephemeral_blobstore.watched(&logger),
+            mutable_renames.watched(&logger),
         )?;
 
-        let readonly_fetcher = RepoReadWriteFetcher::new(
-            sql_read_write_status,
-            config.readonly.clone(),
-            config.hgsql_name.clone(),
-        );
-
         Ok(Self {
             name,
             blob_repo,
@@ -288,6 +328,8 @@ impl Repo {
             live_commit_sync_config,
             hook_manager,
             readonly_fetcher,
+            ephemeral_blobstore,
+            mutable_renames: Arc::new(mutable_renames),
         })
     }
 
@@ -389,9 +431,17 @@ impl Repo {
             live_commit_sync_config,
             hook_manager,
             readonly_fetcher,
+            ephemeral_blobstore: None,
+            mutable_renames: Arc::new(SqlMutableRenames::with_sqlite_in_memory()?),
         })
     }
 
+    /// Attach an ephemeral blobstore to a test repo.
+    pub fn with_ephemeral_blobstore(mut self, ephemeral_blobstore: EphemeralBlobstore) -> Self {
+        self.ephemeral_blobstore = Some(ephemeral_blobstore);
+        self
+    }
+
     /// The name of the underlying repo.
     pub fn name(&self) -> &String {
         &self.name
@@ -437,6 +487,16 @@ impl Repo {
         &self.readonly_fetcher
     }
 
+    /// The ephemeral blobstore of the repository, if it has one.
+    pub fn ephemeral_blobstore(&self) -> Option<&EphemeralBlobstore> {
+        self.ephemeral_blobstore.as_ref()
+    }
+
+    /// The renames of the repository that were recorded after they were committed.
+    pub fn mutable_renames(&self) -> &Arc<dyn MutableRenames> {
+        &self.mutable_renames
+    }
+
     /// The configuration for the referenced repository.
     pub fn config(&self) -> &RepoConfig {
         &self.config
@@ -775,6 +835,34 @@ impl RepoContext {
         self.repo.readonly_fetcher()
     }
 
+    /// Check that the repo isn't locked, before writing to it. Writes that move bookmarks go
+    /// through `bookmarks_movement`, which does this check itself.
+    pub async fn check_writable(&self) -> Result<(), MononokeError> {
+        self.readonly_fetcher()
+            .check_writable()
+            .await
+            .map_err(|e| match e {
+                RepoWriteError::Locked(reason) => MononokeError::RepoLocked(reason),
+                RepoWriteError::Error(e) => MononokeError::from(e),
+            })
+    }
+
+    /// Check that the user is permitted to write to this repo, for writes that don't go through
+    /// `write`, e.g. those that don't create commits.
+    pub async fn check_write_permission(&self) -> Result<(), MononokeError> {
+        self.repo.check_permissions(&self.ctx, "write").await
+    }
+
+    /// The ephemeral blobstore of the referenced repository, if it has one.
+    pub fn ephemeral_blobstore(&self) -> Option<&EphemeralBlobstore> {
+        self.repo.ephemeral_blobstore()
+    }
+
+    /// The renames of the referenced repository that were recorded after they were committed.
+    pub fn mutable_renames(&self) -> &Arc<dyn MutableRenames> {
+        self.repo.mutable_renames()
+    }
+
     /// The configuration for the referenced repository.
     pub fn config(&self) -> &RepoConfig {
         self.repo.config()
@@ -862,6 +950,32 @@ impl RepoContext {
         Ok(cs_id.map(|cs_id| ChangesetContext::new(self.clone(), cs_id)))
     }
 
+    /// Resolve a bookmark to the changeset it pointed to at a given time, as per the bookmark
+    /// update log. Scratch bookmarks are not logged, so they can't be resolved this way.
+    pub async fn resolve_bookmark_at_timestamp(
+        &self,
+        bookmark: impl AsRef<str>,
+        timestamp: Timestamp,
+    ) -> Result<Option<ChangesetContext>, MononokeError> {
+        let bookmark = BookmarkName::new(bookmark.as_ref())
+            .map_err(|e| MononokeError::InvalidRequest(e.to_string()))?;
+
+        let entry = self
+            .blob_repo()
+            .bookmarks_log()
+            .get_bookmark_log_entry_at_timestamp(
+                self.ctx.clone(),
+                bookmark,
+                timestamp,
+                BookmarkFreshness::MaybeStale,
+            )
+            .await?;
+
+        Ok(entry
+            .and_then(|(_id, cs_id, _reason, _timestamp)| cs_id)
+            .map(|cs_id| ChangesetContext::new(self.clone(), cs_id)))
+    }
+
     /// Resolve a changeset id by its prefix
     pub async fn resolve_changeset_id_prefix(
         &self,
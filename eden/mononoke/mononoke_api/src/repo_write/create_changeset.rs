@@ -276,6 +276,7 @@ impl RepoWriteContext {
         changes: BTreeMap<MononokePath, CreateChange>,
     ) -> Result<ChangesetContext, MononokeError> {
         self.check_method_permitted("create_changeset")?;
+        self.check_writable().await?;
 
         // Merge rules are not validated yet, so only a single parent is supported.
         if parents.len() != 1 {
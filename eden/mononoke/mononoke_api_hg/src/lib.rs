@@ -13,10 +13,12 @@ pub mod data;
 pub mod ext;
 pub mod file;
 pub mod repo;
+pub mod snapshot;
 pub mod tree;
 
 pub use data::{HgDataContext, HgDataId};
 pub use ext::RepoContextHgExt;
 pub use file::HgFileContext;
-pub use repo::HgRepoContext;
+pub use repo::{FollowedFileHistory, HgRepoContext};
+pub use snapshot::{Snapshot, SnapshotFile, SnapshotId};
 pub use tree::HgTreeContext;
@@ -5,27 +5,40 @@
  * GNU General Public License version 2.
  */
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use anyhow::{self, format_err, Context};
 use blobrepo::BlobRepo;
 use blobrepo_hg::BlobRepoHg;
+use bonsai_hg_mapping::BonsaiHgMapping;
 use bookmarks::Freshness;
 use bytes::Bytes;
 use context::CoreContext;
+use filestore::StoreRequest;
 use futures::compat::Stream01CompatExt;
 use futures::{future, stream, Stream, StreamExt, TryStream, TryStreamExt};
 use hgproto::GettreepackArgs;
 use mercurial_types::blobs::RevlogChangeset;
-use mercurial_types::{HgChangesetId, HgFileNodeId, HgManifestId};
+use mercurial_types::{HgChangesetId, HgFileHistoryEntry, HgFileNodeId, HgManifestId};
 use metaconfig_types::RepoConfig;
 use mononoke_api::{errors::MononokeError, path::MononokePath, repo::RepoContext};
-use mononoke_types::{ChangesetId, MPath};
+use mononoke_types::{ChangesetId, ContentMetadata, MPath, Timestamp};
+use mutable_renames::MutableRenames;
 use repo_client::gettreepack_entries;
 use segmented_changelog::{CloneData, Location, StreamCloneData, Vertex};
 
 use super::{HgFileContext, HgTreeContext};
 
+/// The history of a file, followed across the copies and renames that it came from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FollowedFileHistory {
+    /// The history of each of the paths, in the order that they were reached.
+    pub paths: Vec<(MPath, Vec<HgFileHistoryEntry>)>,
+    /// Where the history carries on from, if it was cut short by the maximum length. Following
+    /// the history from each of these returns the rest of it.
+    pub next: Vec<(MPath, HgFileNodeId)>,
+}
+
 #[derive(Clone)]
 pub struct HgRepoContext {
     repo: RepoContext,
@@ -102,6 +115,7 @@ impl HgRepoContext {
             basemfnodes: base_versions.into_iter().collect(),
             directories: vec![], // Not supported.
             depth,
+            sparse_prefixes: vec![],
         };
 
         gettreepack_entries(ctx, blob_repo, args)
@@ -145,6 +159,14 @@ impl HgRepoContext {
             .repo()
             .location_to_changeset_id(cs_location, count)
             .await?;
+        self.blob_repo()
+            .get_bonsai_hg_mapping()
+            .prefetch(
+                self.ctx(),
+                self.blob_repo().get_repoid(),
+                result_csids.clone().into(),
+            )
+            .await?;
         let hg_id_futures = result_csids.iter().map(|result_csid| {
             self.blob_repo()
                 .get_hg_from_bonsai_changeset(self.ctx().clone(), *result_csid)
@@ -329,6 +351,120 @@ impl HgRepoContext {
             None => Ok(None),
         }
     }
+
+    /// resolve a bookmark name to the Hg Changeset it pointed to at a given time
+    pub async fn resolve_bookmark_at_timestamp(
+        &self,
+        bookmark: impl AsRef<str>,
+        timestamp: Timestamp,
+    ) -> Result<Option<HgChangesetId>, MononokeError> {
+        match self
+            .repo
+            .resolve_bookmark_at_timestamp(bookmark, timestamp)
+            .await?
+        {
+            Some(c) => c.hg_id().await,
+            None => Ok(None),
+        }
+    }
+
+    /// Store the content of a file as it is streamed in, without holding all of it in memory.
+    /// `data` must be exactly `size` bytes long. Fails if the user can't write to the repo, or if
+    /// the repo is locked.
+    pub async fn upload_file_content(
+        &self,
+        size: u64,
+        data: impl Stream<Item = Result<Bytes, anyhow::Error>> + Send,
+    ) -> Result<ContentMetadata, MononokeError> {
+        self.repo().check_write_permission().await?;
+        self.repo().check_writable().await?;
+        let blob_repo = self.blob_repo();
+        let metadata = filestore::store(
+            blob_repo.blobstore(),
+            blob_repo.filestore_config(),
+            self.ctx(),
+            &StoreRequest::new(size),
+            data,
+        )
+        .await?;
+        Ok(metadata)
+    }
+
+    /// Get the history of a file, and then the history of the files that it was copied or
+    /// renamed from, up to `max_length` entries in total. A rename that was recorded as a
+    /// mutable rename takes precedence over the copy information of the file, and is returned
+    /// as the copy information of its entry.
+    pub async fn file_history_following_copies(
+        &self,
+        path: MPath,
+        filenode_id: HgFileNodeId,
+        max_length: Option<u32>,
+    ) -> Result<FollowedFileHistory, MononokeError> {
+        let repo_id = self.blob_repo().get_repoid();
+        let mut history = FollowedFileHistory::default();
+        let mut remaining = max_length;
+        let mut to_fetch = VecDeque::new();
+        to_fetch.push_back((path, filenode_id));
+        let mut seen = HashSet::new();
+
+        while let Some((path, filenode_id)) = to_fetch.pop_front() {
+            if seen.contains(&(path.clone(), filenode_id)) {
+                continue;
+            }
+            if remaining == Some(0) {
+                if !history.next.contains(&(path.clone(), filenode_id)) {
+                    history.next.push((path, filenode_id));
+                }
+                continue;
+            }
+
+            let file = self.file(filenode_id).await?.ok_or_else(|| {
+                MononokeError::InvalidRequest(format!("{} {} does not exist", path, filenode_id))
+            })?;
+            let entries: Vec<HgFileHistoryEntry> =
+                file.history(path.clone(), remaining).try_collect().await?;
+            let filenode_ids: Vec<_> = entries.iter().map(|entry| *entry.filenode()).collect();
+            seen.extend(filenode_ids.iter().map(|id| (path.clone(), *id)));
+            let mut renames = self
+                .repo()
+                .mutable_renames()
+                .get_renames(self.ctx(), repo_id, &path, &filenode_ids)
+                .await?;
+
+            let entries: Vec<_> = entries
+                .into_iter()
+                .map(|entry| match renames.remove(entry.filenode()) {
+                    Some(rename) => HgFileHistoryEntry::new(
+                        *entry.filenode(),
+                        *entry.parents(),
+                        *entry.linknode(),
+                        Some(rename),
+                    ),
+                    None => entry,
+                })
+                .collect();
+            for entry in &entries {
+                if let Some((copy_path, copy_filenode_id)) = entry.copyfrom() {
+                    to_fetch.push_back((copy_path.clone(), *copy_filenode_id));
+                }
+                // The parents that weren't returned are where the history of this path was cut
+                // short.
+                for parent in entry.parents() {
+                    let parent = (path.clone(), HgFileNodeId::new(parent));
+                    if !seen.contains(&parent) && !history.next.contains(&parent) {
+                        history.next.push(parent);
+                    }
+                }
+            }
+
+            remaining = remaining.map(|remaining| remaining.saturating_sub(entries.len() as u32));
+            history.paths.push((path, entries));
+        }
+
+        // Some of the history may have been reached through another copy after all.
+        history.next.retain(|key| !seen.contains(key));
+        Ok(history)
+    }
 }
 
 async fn hg_convert_idmap_chunk(
@@ -366,8 +502,10 @@ mod tests {
     use anyhow::Error;
     use blobstore::Loadable;
     use fbinit::FacebookInit;
+    use manifest::{Entry, ManifestOps};
     use mononoke_api::repo::Repo;
     use mononoke_types::ChangesetId;
+    use mutable_renames::MutableRename;
     use tests_utils::CreateCommitContext;
 
     use crate::RepoContextHgExt;
@@ -435,6 +573,142 @@ mod tests {
         Ok(())
     }
 
+    #[fbinit::test]
+    async fn test_file_history_following_copies(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let blob_repo = blobrepo_factory::new_memblob_empty(None)?;
+
+        // a is copied to b, which is then added again as c without its copy information, as a
+        // rename that gets recorded as a mutable rename.
+        let commit_1 = CreateCommitContext::new_root(&ctx, &blob_repo)
+            .add_file("a", "1")
+            .commit()
+            .await?;
+        let commit_2 = CreateCommitContext::new(&ctx, &blob_repo, vec![commit_1])
+            .add_file("a", "2")
+            .commit()
+            .await?;
+        let commit_3 = CreateCommitContext::new(&ctx, &blob_repo, vec![commit_2])
+            .add_file_with_copy_info("b", "2", (commit_2, "a"))
+            .commit()
+            .await?;
+        let commit_4 = CreateCommitContext::new(&ctx, &blob_repo, vec![commit_3])
+            .add_file("b", "3")
+            .commit()
+            .await?;
+        let commit_5 = CreateCommitContext::new(&ctx, &blob_repo, vec![commit_4])
+            .delete_file("b")
+            .add_file("c", "3")
+            .commit()
+            .await?;
+
+        let a_1 = filenode_id(&ctx, &blob_repo, commit_1, "a").await?;
+        let a_2 = filenode_id(&ctx, &blob_repo, commit_2, "a").await?;
+        let b_3 = filenode_id(&ctx, &blob_repo, commit_3, "b").await?;
+        let b_4 = filenode_id(&ctx, &blob_repo, commit_4, "b").await?;
+        let c_5 = filenode_id(&ctx, &blob_repo, commit_5, "c").await?;
+        let (a, b, c) = (MPath::new("a")?, MPath::new("b")?, MPath::new("c")?);
+
+        let repo = Repo::new_test(ctx.clone(), blob_repo).await?;
+        let repo_ctx = RepoContext::new(ctx.clone(), Arc::new(repo)).await?;
+        let hg = repo_ctx.hg();
+
+        let history_of = |history: &FollowedFileHistory| -> Vec<(MPath, Vec<HgFileNodeId>)> {
+            history
+                .paths
+                .iter()
+                .map(|(path, entries)| {
+                    let ids = entries.iter().map(|entry| *entry.filenode()).collect();
+                    (path.clone(), ids)
+                })
+                .collect()
+        };
+
+        // Without the mutable rename, the history of c stops at c.
+        let history = hg
+            .file_history_following_copies(c.clone(), c_5, None)
+            .await?;
+        assert_eq!(history_of(&history), vec![(c.clone(), vec![c_5])]);
+        assert_eq!(history.next, vec![]);
+
+        hg.repo()
+            .mutable_renames()
+            .add_renames(
+                &ctx,
+                hg.blob_repo().get_repoid(),
+                &[MutableRename {
+                    dst_path: c.clone(),
+                    dst_filenode: c_5,
+                    src_path: b.clone(),
+                    src_filenode: b_4,
+                }],
+            )
+            .await?;
+
+        let history = hg
+            .file_history_following_copies(c.clone(), c_5, None)
+            .await?;
+        assert_eq!(
+            history_of(&history),
+            vec![
+                (c.clone(), vec![c_5]),
+                (b.clone(), vec![b_4, b_3]),
+                (a.clone(), vec![a_2, a_1]),
+            ]
+        );
+        assert_eq!(history.paths[0].1[0].copyfrom(), &Some((b.clone(), b_4)));
+        assert_eq!(history.next, vec![]);
+
+        // When the history is cut short, it carries on from where it stopped, across copies as
+        // well as on the same path.
+        let history = hg
+            .file_history_following_copies(c.clone(), c_5, Some(3))
+            .await?;
+        assert_eq!(
+            history_of(&history),
+            vec![(c.clone(), vec![c_5]), (b.clone(), vec![b_4, b_3])]
+        );
+        assert_eq!(history.next, vec![(a.clone(), a_2)]);
+
+        let history = hg
+            .file_history_following_copies(c.clone(), c_5, Some(2))
+            .await?;
+        assert_eq!(
+            history_of(&history),
+            vec![(c.clone(), vec![c_5]), (b.clone(), vec![b_4])]
+        );
+        assert_eq!(history.next, vec![(b.clone(), b_3)]);
+
+        let history = hg
+            .file_history_following_copies(b.clone(), b_3, Some(1))
+            .await?;
+        assert_eq!(history_of(&history), vec![(b.clone(), vec![b_3])]);
+        assert_eq!(history.next, vec![(a.clone(), a_2)]);
+
+        Ok(())
+    }
+
+    /// The filenode of the file at `path` in the given commit.
+    async fn filenode_id(
+        ctx: &CoreContext,
+        blob_repo: &BlobRepo,
+        csid: ChangesetId,
+        path: &str,
+    ) -> Result<HgFileNodeId, Error> {
+        let root_mfid = root_manifest_id(ctx.clone(), blob_repo, csid).await?;
+        match root_mfid
+            .find_entry(
+                ctx.clone(),
+                blob_repo.get_blobstore(),
+                Some(MPath::new(path)?),
+            )
+            .await?
+        {
+            Some(Entry::Leaf((_, filenode_id))) => Ok(filenode_id),
+            _ => Err(format_err!("No file {} in {}", path, csid)),
+        }
+    }
+
     /// Get the HgManifestId of the root tree manifest for the given commit.
     async fn root_manifest_id(
         ctx: CoreContext,
@@ -0,0 +1,399 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Ephemeral snapshots of a working copy: the contents of the files changed on top of a
+//! commit, so that the working copy can be restored elsewhere.
+//!
+//! Each snapshot gets a bubble of the ephemeral blobstore of the repo, with the TTL of the
+//! snapshot. The contents of its files are stored through the filestore into the bubble, so
+//! that nothing of the snapshot reaches the persistent blobstore, and the snapshot stops being
+//! served once the bubble expires.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{format_err, Context};
+use blobstore::{Blobstore, BlobstoreBytes};
+use bytes::Bytes;
+use ephemeral_blobstore::{Bubble, BubbleId, EphemeralBlobstore};
+use filestore::{FetchKey, StoreRequest};
+use futures::{stream, StreamExt, TryStreamExt};
+use mercurial_types::HgChangesetId;
+use mononoke_api::errors::MononokeError;
+use mononoke_types::{
+    hash::{self, Blake2},
+    ContentId, FileType, MPath,
+};
+use serde::{Deserialize, Serialize};
+
+use super::HgRepoContext;
+
+/// How long snapshots are kept if the client doesn't ask for anything else.
+pub const DEFAULT_SNAPSHOT_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Clients can't ask for snapshots to be kept for longer than this.
+pub const MAX_SNAPSHOT_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// The most files that a snapshot can have.
+pub const MAX_SNAPSHOT_FILES: usize = 10_000;
+/// The most bytes that the files of a snapshot can add up to.
+pub const MAX_SNAPSHOT_BYTES: u64 = 512 * 1024 * 1024;
+
+const SNAPSHOT_KEY_PREFIX: &str = "ephemeral_snapshot.blake2.";
+const SNAPSHOT_HASH_KEY: &[u8] = b"ephemeralsnapshot";
+const CONCURRENT_FILE_OPS: usize = 10;
+
+/// A snapshot is found by the bubble that it is in, and the hash of its contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SnapshotId {
+    bubble_id: BubbleId,
+    hash: Blake2,
+}
+
+impl SnapshotId {
+    fn blobstore_key(&self) -> String {
+        format!("{}{}", SNAPSHOT_KEY_PREFIX, self.hash)
+    }
+}
+
+impl fmt::Display for SnapshotId {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{}.{}", self.bubble_id, self.hash)
+    }
+}
+
+impl FromStr for SnapshotId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '.');
+        let (bubble_id, hash) = match (parts.next(), parts.next()) {
+            (Some(bubble_id), Some(hash)) => (bubble_id, hash),
+            _ => return Err(format_err!("Invalid snapshot id {}", s)),
+        };
+        Ok(Self {
+            bubble_id: BubbleId::new(bubble_id.parse()?),
+            hash: Blake2::from_str(hash)?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotFile {
+    pub path: MPath,
+    pub file_type: FileType,
+    /// `None` if the file was removed from the working copy.
+    pub content: Option<Bytes>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    /// The commit that the working copy was based on.
+    pub parent: Option<HgChangesetId>,
+    pub files: Vec<SnapshotFile>,
+    /// Unix timestamp after which the snapshot is no longer served.
+    pub expires_at: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredSnapshot {
+    parent: Option<HgChangesetId>,
+    files: Vec<StoredSnapshotFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredSnapshotFile {
+    path: MPath,
+    file_type: FileType,
+    content_id: Option<ContentId>,
+}
+
+fn check_limits(files: &[SnapshotFile]) -> Result<(), MononokeError> {
+    if files.len() > MAX_SNAPSHOT_FILES {
+        return Err(MononokeError::InvalidRequest(format!(
+            "Snapshot has {} files, the limit is {}",
+            files.len(),
+            MAX_SNAPSHOT_FILES
+        )));
+    }
+    let bytes: u64 = files
+        .iter()
+        .filter_map(|file| file.content.as_ref())
+        .map(|content| content.len() as u64)
+        .sum();
+    if bytes > MAX_SNAPSHOT_BYTES {
+        return Err(MononokeError::InvalidRequest(format!(
+            "Snapshot has {} bytes of files, the limit is {}",
+            bytes, MAX_SNAPSHOT_BYTES
+        )));
+    }
+    Ok(())
+}
+
+impl HgRepoContext {
+    fn ephemeral_blobstore(&self) -> Result<&EphemeralBlobstore, MononokeError> {
+        self.repo().ephemeral_blobstore().ok_or_else(|| {
+            MononokeError::NotAvailable(format!(
+                "Snapshots are not enabled for {}",
+                self.repo().name()
+            ))
+        })
+    }
+
+    /// Store a snapshot of a working copy. A `ttl` longer than `MAX_SNAPSHOT_TTL` is capped.
+    /// Fails if the user can't write to the repo, if the repo is locked, or if the snapshot is
+    /// over `MAX_SNAPSHOT_FILES` or `MAX_SNAPSHOT_BYTES`.
+    pub async fn upload_snapshot(
+        &self,
+        parent: Option<HgChangesetId>,
+        files: Vec<SnapshotFile>,
+        ttl: Option<Duration>,
+    ) -> Result<(SnapshotId, i64), MononokeError> {
+        self.repo().check_write_permission().await?;
+        self.repo().check_writable().await?;
+        check_limits(&files)?;
+
+        let ctx = self.ctx();
+        let filestore_config = self.blob_repo().filestore_config();
+        let ttl = ttl.unwrap_or(DEFAULT_SNAPSHOT_TTL).min(MAX_SNAPSHOT_TTL);
+        let bubble = self.ephemeral_blobstore()?.create_bubble(Some(ttl)).await?;
+
+        let files = stream::iter(files)
+            .map(|file| {
+                let bubble = &bubble;
+                async move {
+                    let content_id = match file.content {
+                        Some(bytes) => {
+                            let meta = filestore::store(
+                                bubble,
+                                filestore_config,
+                                ctx,
+                                &StoreRequest::new(bytes.len() as u64),
+                                stream::once(async move { Ok(bytes) }),
+                            )
+                            .await?;
+                            Some(meta.content_id)
+                        }
+                        None => None,
+                    };
+                    Result::<_, MononokeError>::Ok(StoredSnapshotFile {
+                        path: file.path,
+                        file_type: file.file_type,
+                        content_id,
+                    })
+                }
+            })
+            .buffered(CONCURRENT_FILE_OPS)
+            .try_collect()
+            .await?;
+
+        let stored = StoredSnapshot { parent, files };
+        let bytes = serde_json::to_vec(&stored).context("Failed to serialize snapshot")?;
+
+        let mut hasher = hash::Context::new(SNAPSHOT_HASH_KEY);
+        hasher.update(&bytes);
+        let id = SnapshotId {
+            bubble_id: bubble.bubble_id(),
+            hash: hasher.finish(),
+        };
+
+        bubble
+            .put(ctx, id.blobstore_key(), BlobstoreBytes::from_bytes(bytes))
+            .await?;
+
+        Ok((id, bubble.expires_at()))
+    }
+
+    async fn open_snapshot_bubble(&self, id: SnapshotId) -> Result<Option<Bubble>, MononokeError> {
+        match self.ephemeral_blobstore()?.open_bubble(id.bubble_id).await {
+            Ok(bubble) => Ok(Some(bubble)),
+            Err(e) => match e.downcast_ref::<ephemeral_blobstore::ErrorKind>() {
+                Some(ephemeral_blobstore::ErrorKind::NoSuchBubble(_))
+                | Some(ephemeral_blobstore::ErrorKind::BubbleExpired(_)) => Ok(None),
+                _ => Err(e.into()),
+            },
+        }
+    }
+
+    /// Fetch a snapshot, along with the content of its files. Returns `None` if the snapshot
+    /// does not exist or has expired.
+    pub async fn fetch_snapshot(&self, id: SnapshotId) -> Result<Option<Snapshot>, MononokeError> {
+        let ctx = self.ctx();
+        let bubble = match self.open_snapshot_bubble(id).await? {
+            Some(bubble) => bubble,
+            None => return Ok(None),
+        };
+
+        let data = match bubble.get(ctx, &id.blobstore_key()).await? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let stored: StoredSnapshot = serde_json::from_slice(data.as_raw_bytes())
+            .with_context(|| format!("Failed to deserialize snapshot {}", id))?;
+
+        let files = stream::iter(stored.files)
+            .map(|file| {
+                let bubble = &bubble;
+                async move {
+                    let content = match file.content_id {
+                        Some(content_id) => {
+                            let key = FetchKey::Canonical(content_id);
+                            let bytes = filestore::fetch_concat_opt(bubble, ctx, &key)
+                                .await?
+                                .ok_or_else(|| {
+                                    format_err!(
+                                        "Content {} of snapshot {} is missing",
+                                        content_id,
+                                        id
+                                    )
+                                })?;
+                            Some(bytes)
+                        }
+                        None => None,
+                    };
+                    Result::<_, MononokeError>::Ok(SnapshotFile {
+                        path: file.path,
+                        file_type: file.file_type,
+                        content,
+                    })
+                }
+            })
+            .buffered(CONCURRENT_FILE_OPS)
+            .try_collect()
+            .await?;
+
+        Ok(Some(Snapshot {
+            parent: stored.parent,
+            files,
+            expires_at: bubble.expires_at(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use context::CoreContext;
+    use ephemeral_blobstore::SqlBubbleStore;
+    use fbinit::FacebookInit;
+    use memblob::Memblob;
+    use mononoke_api::repo::{Repo, RepoContext};
+    use sql_construct::SqlConstruct;
+
+    use crate::{HgRepoContext, RepoContextHgExt};
+
+    async fn snapshot_repo(
+        fb: FacebookInit,
+        with_ephemeral_blobstore: bool,
+    ) -> Result<HgRepoContext, MononokeError> {
+        let ctx = CoreContext::test_mock(fb);
+        let blob_repo = blobrepo_factory::new_memblob_empty(None)?;
+        let persistent = blob_repo.get_blobstore().boxed();
+        let mut repo = Repo::new_test(ctx.clone(), blob_repo).await?;
+        if with_ephemeral_blobstore {
+            repo = repo.with_ephemeral_blobstore(EphemeralBlobstore::new(
+                Arc::new(Memblob::default()),
+                persistent,
+                SqlBubbleStore::with_sqlite_in_memory()?,
+                DEFAULT_SNAPSHOT_TTL,
+            ));
+        }
+        Ok(RepoContext::new(ctx, Arc::new(repo)).await?.hg())
+    }
+
+    #[fbinit::test]
+    async fn test_snapshot_round_trip(fb: FacebookInit) -> Result<(), MononokeError> {
+        let hg = snapshot_repo(fb, true).await?;
+
+        let files = vec![
+            SnapshotFile {
+                path: MPath::new("dir/changed")?,
+                file_type: FileType::Regular,
+                content: Some(Bytes::from("new content")),
+            },
+            SnapshotFile {
+                path: MPath::new("removed")?,
+                file_type: FileType::Regular,
+                content: None,
+            },
+        ];
+
+        let (id, expires_at) = hg.upload_snapshot(None, files.clone(), None).await?;
+        let snapshot = hg.fetch_snapshot(id).await?;
+        assert_eq!(
+            snapshot,
+            Some(Snapshot {
+                parent: None,
+                files,
+                expires_at,
+            })
+        );
+        assert_eq!(id.to_string().parse::<SnapshotId>()?, id);
+
+        // Nothing of the snapshot reaches the persistent blobstore
+        assert!(
+            !hg.blob_repo()
+                .blobstore()
+                .is_present(hg.ctx(), &id.blobstore_key())
+                .await?
+        );
+
+        // Snapshots that don't exist, or that have expired, are not served
+        let missing = SnapshotId {
+            bubble_id: BubbleId::new(1000),
+            hash: Blake2::from_byte_array([1; 32]),
+        };
+        assert_eq!(hg.fetch_snapshot(missing).await?, None);
+
+        let (id, _) = hg
+            .upload_snapshot(None, vec![], Some(Duration::from_secs(0)))
+            .await?;
+        assert_eq!(hg.fetch_snapshot(id).await?, None);
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_snapshot_limits(fb: FacebookInit) -> Result<(), MononokeError> {
+        let hg = snapshot_repo(fb, true).await?;
+
+        let file = SnapshotFile {
+            path: MPath::new("file")?,
+            file_type: FileType::Regular,
+            content: None,
+        };
+        let files = vec![file; MAX_SNAPSHOT_FILES + 1];
+        match hg.upload_snapshot(None, files, None).await {
+            Err(MononokeError::InvalidRequest(_)) => {}
+            res => panic!("Expected too many files to be rejected, got {:?}", res),
+        }
+
+        let files = vec![SnapshotFile {
+            path: MPath::new("large")?,
+            file_type: FileType::Regular,
+            content: Some(Bytes::from(vec![0; MAX_SNAPSHOT_BYTES as usize + 1])),
+        }];
+        match hg.upload_snapshot(None, files, None).await {
+            Err(MononokeError::InvalidRequest(_)) => {}
+            res => panic!("Expected too many bytes to be rejected, got {:?}", res),
+        }
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_snapshot_not_enabled(fb: FacebookInit) -> Result<(), MononokeError> {
+        let hg = snapshot_repo(fb, false).await?;
+        match hg.upload_snapshot(None, vec![], None).await {
+            Err(MononokeError::NotAvailable(_)) => {}
+            res => panic!("Expected snapshots to be unavailable, got {:?}", res),
+        }
+        Ok(())
+    }
+}
@@ -143,9 +143,16 @@ union ContentChunk {
   1: binary Bytes,
 }
 
+// File content alias, along with the file contents themselves, for small files.
+struct InlineContentAlias {
+  1: ContentId content_id,
+  2: binary contents,
+}
+
 // Payload of object which is an alias
 union ContentAlias {
   1: ContentId ContentId, // File content alias
+  2: InlineContentAlias InlineContentAlias,
 }
 
 // Metadata about a file. This includes hahs aliases, or the file's size.
@@ -20,12 +20,27 @@ use crate::{
     typed_hash::{ContentId, ContentMetadataId},
 };
 
+/// The content an alias refers to. Aliases of small files can also carry the contents, so that
+/// fetching those by alias only takes one blobstore round-trip.
 #[derive(Debug, Clone, Eq, PartialEq)]
-pub struct ContentAlias(ContentId);
+pub struct ContentAlias {
+    content_id: ContentId,
+    inline_contents: Option<Bytes>,
+}
 
 impl ContentAlias {
     pub fn from_content_id(id: ContentId) -> Self {
-        ContentAlias(id)
+        ContentAlias {
+            content_id: id,
+            inline_contents: None,
+        }
+    }
+
+    pub fn from_content_id_with_contents(id: ContentId, contents: Bytes) -> Self {
+        ContentAlias {
+            content_id: id,
+            inline_contents: Some(contents),
+        }
     }
 
     pub fn from_bytes(blob: Bytes) -> Result<Self> {
@@ -39,6 +54,12 @@ impl ContentAlias {
             thrift::ContentAlias::ContentId(id) => {
                 Ok(Self::from_content_id(ContentId::from_thrift(id)?))
             }
+            thrift::ContentAlias::InlineContentAlias(inline) => {
+                Ok(Self::from_content_id_with_contents(
+                    ContentId::from_thrift(inline.content_id)?,
+                    Bytes::from(inline.contents),
+                ))
+            }
             thrift::ContentAlias::UnknownField(x) => bail!(ErrorKind::InvalidThrift(
                 "ContentAlias".into(),
                 format!("unknown content alias field: {}", x)
@@ -47,13 +68,29 @@ impl ContentAlias {
     }
 
     pub fn into_blob(self) -> BlobstoreBytes {
-        let alias = thrift::ContentAlias::ContentId(self.0.into_thrift());
+        let alias = match self.inline_contents {
+            None => thrift::ContentAlias::ContentId(self.content_id.into_thrift()),
+            Some(contents) => {
+                thrift::ContentAlias::InlineContentAlias(thrift::InlineContentAlias {
+                    content_id: self.content_id.into_thrift(),
+                    contents: contents.to_vec(),
+                })
+            }
+        };
         let data = compact_protocol::serialize(&alias);
         BlobstoreBytes::from_bytes(data)
     }
 
     pub fn content_id(&self) -> ContentId {
-        self.0
+        self.content_id
+    }
+
+    pub fn inline_contents(&self) -> Option<&Bytes> {
+        self.inline_contents.as_ref()
+    }
+
+    pub fn into_inline_contents(self) -> Option<Bytes> {
+        self.inline_contents
     }
 }
 
@@ -149,5 +186,15 @@ mod test {
                 .expect("blob roundtrips should always be valid");
             cab == cab2
         }
+
+        fn content_alias_blob_roundtrip(id: ContentId, contents: Option<Vec<u8>>) -> bool {
+            let alias = match contents {
+                Some(contents) => ContentAlias::from_content_id_with_contents(id, contents.into()),
+                None => ContentAlias::from_content_id(id),
+            };
+            let alias2 = ContentAlias::from_bytes(alias.clone().into_blob().into_bytes())
+                .expect("blob roundtrips should always be valid");
+            alias == alias2
+        }
     }
 }
@@ -0,0 +1,15 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+CREATE TABLE IF NOT EXISTS mutable_renames (
+  repo_id INT UNSIGNED NOT NULL,
+  dst_path VARBINARY(4096) NOT NULL,
+  dst_filenode BINARY(20) NOT NULL,
+  src_path VARBINARY(4096) NOT NULL,
+  src_filenode BINARY(20) NOT NULL,
+  PRIMARY KEY (repo_id, dst_path, dst_filenode)
+);
@@ -0,0 +1,154 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+//! Renames that are recorded after the fact. The copy information of a file is part of the
+//! commit that made it, so a rename that wasn't recorded as one when it was committed (e.g. a
+//! file deleted and added again elsewhere) can't be fixed up there. This is where such renames
+//! are recorded instead, for the history of the file to carry on from where it came from, as if
+//! the commit had said it was a copy.
+//!
+//! A mutable rename takes precedence over the copy information of the file, if it has any.
+
+use std::collections::HashMap;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use context::{CoreContext, PerfCounterType};
+use mercurial_types::HgFileNodeId;
+use mononoke_types::{MPath, RepositoryId};
+use sql::{queries, Connection};
+use sql_construct::{SqlConstruct, SqlConstructFromMetadataDatabaseConfig};
+use sql_ext::SqlConnections;
+
+/// A version of a file, and where it was renamed from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MutableRename {
+    pub dst_path: MPath,
+    pub dst_filenode: HgFileNodeId,
+    pub src_path: MPath,
+    pub src_filenode: HgFileNodeId,
+}
+
+#[async_trait]
+pub trait MutableRenames: Send + Sync + 'static {
+    /// Where the versions `filenodes` of the file at `path` were renamed from, for those that a
+    /// rename was recorded for.
+    async fn get_renames(
+        &self,
+        ctx: &CoreContext,
+        repo_id: RepositoryId,
+        path: &MPath,
+        filenodes: &[HgFileNodeId],
+    ) -> Result<HashMap<HgFileNodeId, (MPath, HgFileNodeId)>, Error>;
+
+    /// Record the renames, replacing the ones already recorded for the same versions of files.
+    async fn add_renames(
+        &self,
+        ctx: &CoreContext,
+        repo_id: RepositoryId,
+        renames: &[MutableRename],
+    ) -> Result<(), Error>;
+}
+
+queries! {
+    write AddRenames(values: (
+        repo_id: RepositoryId,
+        dst_path: Vec<u8>,
+        dst_filenode: HgFileNodeId,
+        src_path: Vec<u8>,
+        src_filenode: HgFileNodeId,
+    )) {
+        none,
+        "REPLACE INTO mutable_renames (repo_id, dst_path, dst_filenode, src_path, src_filenode)
+         VALUES {values}"
+    }
+
+    read GetRenames(repo_id: RepositoryId, dst_path: Vec<u8>, >list dst_filenodes: HgFileNodeId)
+        -> (HgFileNodeId, Vec<u8>, HgFileNodeId) {
+        "SELECT dst_filenode, src_path, src_filenode FROM mutable_renames
+         WHERE repo_id = {repo_id} AND dst_path = {dst_path} AND dst_filenode IN {dst_filenodes}"
+    }
+}
+
+#[derive(Clone)]
+pub struct SqlMutableRenames {
+    write_connection: Connection,
+    read_connection: Connection,
+}
+
+impl SqlConstruct for SqlMutableRenames {
+    const LABEL: &'static str = "mutable_renames";
+
+    const CREATION_QUERY: &'static str = include_str!("../schemas/sqlite-mutable-renames.sql");
+
+    fn from_sql_connections(connections: SqlConnections) -> Self {
+        Self {
+            write_connection: connections.write_connection,
+            read_connection: connections.read_connection,
+        }
+    }
+}
+
+impl SqlConstructFromMetadataDatabaseConfig for SqlMutableRenames {}
+
+#[async_trait]
+impl MutableRenames for SqlMutableRenames {
+    async fn get_renames(
+        &self,
+        ctx: &CoreContext,
+        repo_id: RepositoryId,
+        path: &MPath,
+        filenodes: &[HgFileNodeId],
+    ) -> Result<HashMap<HgFileNodeId, (MPath, HgFileNodeId)>, Error> {
+        if filenodes.is_empty() {
+            return Ok(HashMap::new());
+        }
+        ctx.perf_counters()
+            .increment_counter(PerfCounterType::SqlReadsReplica);
+        let rows =
+            GetRenames::query(&self.read_connection, &repo_id, &path.to_vec(), filenodes).await?;
+        rows.into_iter()
+            .map(|(dst_filenode, src_path, src_filenode)| {
+                Ok((dst_filenode, (MPath::new(src_path)?, src_filenode)))
+            })
+            .collect()
+    }
+
+    async fn add_renames(
+        &self,
+        ctx: &CoreContext,
+        repo_id: RepositoryId,
+        renames: &[MutableRename],
+    ) -> Result<(), Error> {
+        ctx.perf_counters()
+            .increment_counter(PerfCounterType::SqlWrites);
+        let rows: Vec<_> = renames
+            .iter()
+            .map(|rename| {
+                (
+                    repo_id,
+                    rename.dst_path.to_vec(),
+                    rename.dst_filenode,
+                    rename.src_path.to_vec(),
+                    rename.src_filenode,
+                )
+            })
+            .collect();
+        let rows: Vec<_> = rows
+            .iter()
+            .map(
+                |(repo_id, dst_path, dst_filenode, src_path, src_filenode)| {
+                    (repo_id, dst_path, dst_filenode, src_path, src_filenode)
+                },
+            )
+            .collect();
+        AddRenames::query(&self.write_connection, &rows[..]).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use anyhow::Error;
+use context::CoreContext;
+use fbinit::FacebookInit;
+use maplit::hashmap;
+use mercurial_types_mocks::nodehash::{ONES_FNID, THREES_FNID, TWOS_FNID};
+use mononoke_types::MPath;
+use mononoke_types_mocks::repo::{REPO_ONE, REPO_ZERO};
+use mutable_renames::{MutableRename, MutableRenames, SqlMutableRenames};
+use sql_construct::SqlConstruct;
+
+#[fbinit::test]
+async fn test_add_and_get(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let renames = SqlMutableRenames::with_sqlite_in_memory()?;
+    let (dst, src) = (MPath::new("dst")?, MPath::new("src")?);
+
+    assert_eq!(
+        renames
+            .get_renames(&ctx, REPO_ZERO, &dst, &[ONES_FNID])
+            .await?,
+        hashmap! {}
+    );
+
+    let rename = MutableRename {
+        dst_path: dst.clone(),
+        dst_filenode: ONES_FNID,
+        src_path: src.clone(),
+        src_filenode: TWOS_FNID,
+    };
+    renames
+        .add_renames(&ctx, REPO_ZERO, &[rename.clone()])
+        .await?;
+    // Renames are of a version of a file, in a repo.
+    assert_eq!(
+        renames
+            .get_renames(&ctx, REPO_ZERO, &dst, &[ONES_FNID, TWOS_FNID])
+            .await?,
+        hashmap! {ONES_FNID => (src.clone(), TWOS_FNID)}
+    );
+    assert_eq!(
+        renames
+            .get_renames(&ctx, REPO_ZERO, &src, &[ONES_FNID])
+            .await?,
+        hashmap! {}
+    );
+    assert_eq!(
+        renames
+            .get_renames(&ctx, REPO_ONE, &dst, &[ONES_FNID])
+            .await?,
+        hashmap! {}
+    );
+
+    // Recording the rename again replaces it.
+    let other = MPath::new("other")?;
+    renames
+        .add_renames(
+            &ctx,
+            REPO_ZERO,
+            &[MutableRename {
+                src_path: other.clone(),
+                src_filenode: THREES_FNID,
+                ..rename
+            }],
+        )
+        .await?;
+    assert_eq!(
+        renames
+            .get_renames(&ctx, REPO_ZERO, &dst, &[ONES_FNID])
+            .await?,
+        hashmap! {ONES_FNID => (other, THREES_FNID)}
+    );
+
+    Ok(())
+}
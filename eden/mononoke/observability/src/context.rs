@@ -11,6 +11,7 @@ use slog::Level;
 use std::sync::{Arc, Mutex};
 
 use crate::config::{ObservabilityConfig, ScubaVerbosityLevel};
+use crate::module_levels::ModuleLevels;
 use crate::scuba::{should_log_scuba_sample, ScubaLoggingDecisionFields};
 
 const CONFIGERATOR_OBSERVABILITY_CONFIG: &str = "scm/mononoke/observability/observability_config";
@@ -149,24 +150,28 @@ impl ObservabilityContextInner {
 #[derive(Clone)]
 pub struct ObservabilityContext {
     inner: ObservabilityContextInner,
+    module_levels: ModuleLevels,
 }
 
 impl ObservabilityContext {
     pub fn new(config_store: &ConfigStore) -> Result<Self, Error> {
         Ok(Self {
             inner: ObservabilityContextInner::new(config_store)?,
+            module_levels: ModuleLevels::default(),
         })
     }
 
     pub fn new_test(inner: Arc<Mutex<TestObservabilityContextInner>>) -> Self {
         Self {
             inner: ObservabilityContextInner::new_test(inner),
+            module_levels: ModuleLevels::default(),
         }
     }
 
     pub fn new_static(level: Level) -> Self {
         Self {
             inner: ObservabilityContextInner::new_static(level),
+            module_levels: ModuleLevels::default(),
         }
     }
 
@@ -174,6 +179,18 @@ impl ObservabilityContext {
         self.inner.get_logging_level()
     }
 
+    /// Levels set at runtime for specific modules, which take precedence over the logging level.
+    pub fn module_levels(&self) -> &ModuleLevels {
+        &self.module_levels
+    }
+
+    /// The level to log at for records logged from `module`.
+    pub fn get_logging_level_for_module(&self, module: &str) -> Level {
+        self.module_levels
+            .get(module)
+            .unwrap_or_else(|| self.get_logging_level())
+    }
+
     pub fn should_log_scuba_sample(
         &self,
         verbosity_level: ScubaVerbosityLevel,
@@ -22,8 +22,9 @@ impl<'a, D> DynamicLevelDrain<'a, D> {
         }
     }
 
-    fn current_level(&self) -> Level {
-        self.observability_context.get_logging_level()
+    fn current_level(&self, module: &str) -> Level {
+        self.observability_context
+            .get_logging_level_for_module(module)
     }
 }
 
@@ -32,7 +33,10 @@ impl<D: Drain<Ok = (), Err = Never>> Drain for DynamicLevelDrain<'_, D> {
     type Err = Never;
 
     fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
-        if record.level().is_at_least(self.current_level()) {
+        if record
+            .level()
+            .is_at_least(self.current_level(record.module()))
+        {
             self.inner.log(record, values)
         } else {
             Ok(())
@@ -10,9 +10,11 @@
 mod config;
 mod context;
 mod drain;
+mod module_levels;
 mod scuba;
 
 pub use crate::config::ScubaVerbosityLevel;
 pub use crate::scuba::ScubaLoggingDecisionFields;
 pub use context::ObservabilityContext;
 pub use drain::DynamicLevelDrain;
+pub use module_levels::ModuleLevels;
@@ -0,0 +1,86 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use slog::Level;
+use std::sync::{Arc, RwLock};
+
+/// Log levels set at runtime for specific modules, e.g. to get debug logs from `repo_client`
+/// only. A level set for a module applies to its submodules too, unless they have their own.
+#[derive(Clone, Default)]
+pub struct ModuleLevels {
+    levels: Arc<RwLock<Vec<(String, Level)>>>,
+}
+
+fn is_within(module: &str, prefix: &str) -> bool {
+    match module.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+impl ModuleLevels {
+    /// Set the level for `module`, or go back to the default level if `level` is `None`.
+    pub fn set(&self, module: &str, level: Option<Level>) {
+        let mut levels = self.levels.write().expect("poisoned lock");
+        levels.retain(|(m, _)| m != module);
+        if let Some(level) = level {
+            levels.push((module.to_string(), level));
+        }
+    }
+
+    pub fn get_all(&self) -> Vec<(String, Level)> {
+        let mut levels = self.levels.read().expect("poisoned lock").clone();
+        levels.sort_by(|(a, _), (b, _)| a.cmp(b));
+        levels
+    }
+
+    /// The level of the innermost module with a level set that `module` is within.
+    pub fn get(&self, module: &str) -> Option<Level> {
+        let levels = self.levels.read().expect("poisoned lock");
+        levels
+            .iter()
+            .filter(|(prefix, _)| is_within(module, prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, level)| *level)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_module_levels() {
+        let levels = ModuleLevels::default();
+        assert_eq!(levels.get("repo_client"), None);
+
+        levels.set("repo_client", Some(Level::Debug));
+        levels.set(
+            "repo_client::client::session_bookmarks_cache",
+            Some(Level::Error),
+        );
+        assert_eq!(levels.get("repo_client"), Some(Level::Debug));
+        assert_eq!(levels.get("repo_client::client"), Some(Level::Debug));
+        assert_eq!(
+            levels.get("repo_client::client::session_bookmarks_cache"),
+            Some(Level::Error)
+        );
+        assert_eq!(levels.get("repo_client_other"), None);
+
+        levels.set("repo_client", Some(Level::Warning));
+        assert_eq!(levels.get("repo_client"), Some(Level::Warning));
+        levels.set("repo_client", None);
+        assert_eq!(levels.get("repo_client::client"), None);
+        assert_eq!(
+            levels.get_all(),
+            vec![(
+                "repo_client::client::session_bookmarks_cache".to_string(),
+                Level::Error
+            )]
+        );
+    }
+}
@@ -140,6 +140,12 @@ pub enum PushrebaseError {
         "Force failed pushrebase, please do a manual rebase. (Bonsai changeset id that triggered it is {0})"
     )]
     ForceFailPushrebase(ChangesetId),
+    #[error("Author of {cs_id} ({author}) does not match the required format {format}")]
+    InvalidAuthor {
+        cs_id: ChangesetId,
+        author: String,
+        format: String,
+    },
     #[error(transparent)]
     Error(#[from] Error),
 }
@@ -276,6 +282,7 @@ pub async fn do_pushrebase_bonsai(
     maybe_hg_replay_data: Option<&HgReplayData>,
     prepushrebase_hooks: &[Box<dyn PushrebaseHook>],
 ) -> Result<PushrebaseOutcome, PushrebaseError> {
+    check_author_format(config, pushed)?;
     let head = find_only_head_or_fail(&pushed)?;
     let roots = find_roots(&pushed);
 
@@ -310,6 +317,65 @@ pub async fn do_pushrebase_bonsai(
     Ok(res)
 }
 
+fn check_author_format(
+    config: &PushrebaseFlags,
+    pushed: &HashSet<BonsaiChangeset>,
+) -> Result<(), PushrebaseError> {
+    if let Some(format) = &config.author_format {
+        for bcs in pushed {
+            if !format.is_match(bcs.author()) {
+                return Err(PushrebaseError::InvalidAuthor {
+                    cs_id: bcs.get_changeset_id(),
+                    author: bcs.author().to_string(),
+                    format: format.as_str().to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Add the `trailers` that `message` doesn't already have, in the trailer block at the end of
+/// the message.
+fn add_trailers(message: &str, trailers: &[String]) -> String {
+    let message = message.trim_end();
+    let missing: Vec<_> = trailers
+        .iter()
+        .filter(|trailer| !message.lines().any(|line| line.trim() == trailer.trim()))
+        .collect();
+    if missing.is_empty() {
+        return message.to_string();
+    }
+
+    let mut message = message.to_string();
+    // Trailers go in the last paragraph, so it is only started if the message doesn't end with
+    // trailers already.
+    let ends_with_trailer = message
+        .lines()
+        .last()
+        .map_or(false, |line| is_trailer(line) && message.contains('\n'));
+    if !message.is_empty() {
+        message.push_str(if ends_with_trailer { "\n" } else { "\n\n" });
+    }
+    for (idx, trailer) in missing.into_iter().enumerate() {
+        if idx > 0 {
+            message.push('\n');
+        }
+        message.push_str(trailer.trim());
+    }
+    message
+}
+
+fn is_trailer(line: &str) -> bool {
+    match line.find(": ") {
+        Some(idx) => {
+            let key = &line[..idx];
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        }
+        None => false,
+    }
+}
+
 async fn check_filenodes_backfilled<'a>(
     ctx: &CoreContext,
     repo: &BlobRepo,
@@ -914,6 +980,7 @@ async fn create_rebased_changesets(
             bcs_old,
             &remapping,
             date.as_ref(),
+            &config.added_trailers,
             &root,
             &onto,
             &repo,
@@ -946,6 +1013,7 @@ async fn rebase_changeset(
     bcs: BonsaiChangeset,
     remapping: &HashMap<ChangesetId, (ChangesetId, Timestamp)>,
     timestamp: Option<&Timestamp>,
+    trailers: &[String],
     root: &ChangesetId,
     onto: &ChangesetId,
     repo: &BlobRepo,
@@ -972,6 +1040,10 @@ async fn rebase_changeset(
         None => {}
     }
 
+    if !trailers.is_empty() {
+        bcs.message = add_trailers(&bcs.message, trailers);
+    }
+
     // Mutation information from the original commit must be stripped.
     for key in MUTATION_KEYS {
         bcs.extra.remove(*key);
@@ -1287,11 +1359,13 @@ mod tests {
     };
     use manifest::{Entry, ManifestOps};
     use maplit::{btreemap, hashmap, hashset};
+    use metaconfig_types::ComparableRegex;
     use mononoke_types::FileType;
     use mononoke_types::{BonsaiChangesetMut, RepositoryId};
     use mononoke_types_mocks::hash::AS;
     use mutable_counters::{MutableCounters, SqlMutableCounters};
     use rand::Rng;
+    use regex::Regex;
     use sql::{rusqlite::Connection as SqliteConnection, Connection, Transaction};
     use sql_construct::SqlConstruct;
     use sql_ext::{SqlConnections, TransactionResult};
@@ -2255,6 +2329,79 @@ mod tests {
         })
     }
 
+    #[fbinit::test]
+    fn pushrebase_author_format_and_trailers(fb: FacebookInit) -> Result<(), Error> {
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async move {
+            let ctx = CoreContext::test_mock(fb);
+            let repo = linear::getrepo(fb).await;
+            let root = repo
+                .get_bonsai_from_hg(
+                    ctx.clone(),
+                    HgChangesetId::from_str("2d7d4ba9ce0a6ffd222de7785b249ead9c51c536")?,
+                )
+                .await?
+                .ok_or(Error::msg("Root is missing"))?;
+            let book = master_bookmark();
+            let bcs = CreateCommitContext::new(&ctx, &repo, vec![root])
+                .add_file("file", "data")
+                .set_author("author")
+                .set_message("message")
+                .commit()
+                .await?;
+            let hgcss = hashset![repo.get_hg_from_bonsai_changeset(ctx.clone(), bcs).await?];
+            set_bookmark(
+                ctx.clone(),
+                repo.clone(),
+                &book,
+                "a5ffa77602a066db7d5cfb9fb5823a0895717c5a",
+            )
+            .await?;
+
+            let config = PushrebaseFlags {
+                author_format: Some(ComparableRegex::new(Regex::new("^.+ <.+@.+>$")?)),
+                ..Default::default()
+            };
+            match do_pushrebase(&ctx, &repo, &config, &book, &hgcss, None).await {
+                Err(PushrebaseError::InvalidAuthor { cs_id, .. }) if cs_id == bcs => {}
+                res => panic!("Unexpected result: {:?}", res.map(|outcome| outcome.head)),
+            }
+
+            let config = PushrebaseFlags {
+                author_format: Some(ComparableRegex::new(Regex::new("^author$")?)),
+                added_trailers: vec!["Release-Branch: 1.2".to_string()],
+                ..Default::default()
+            };
+            let outcome = do_pushrebase(&ctx, &repo, &config, &book, &hgcss, None).await?;
+            let rebased = outcome.head.load(&ctx, repo.blobstore()).await?;
+            assert_eq!(rebased.message(), "message\n\nRelease-Branch: 1.2");
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_add_trailers() {
+        let trailers = vec![
+            "Release-Branch: 1.2".to_string(),
+            "Reviewed-By: a".to_string(),
+        ];
+        assert_eq!(
+            add_trailers("summary\n", &trailers),
+            "summary\n\nRelease-Branch: 1.2\nReviewed-By: a"
+        );
+        assert_eq!(
+            add_trailers("summary\n\nbody\n\nReviewed-By: a", &trailers),
+            "summary\n\nbody\n\nReviewed-By: a\nRelease-Branch: 1.2"
+        );
+        // The summary line is not a trailer block, even if it looks like one.
+        assert_eq!(
+            add_trailers("fix: something", &trailers[..1]),
+            "fix: something\n\nRelease-Branch: 1.2"
+        );
+        assert_eq!(add_trailers("", &trailers[..1]), "Release-Branch: 1.2");
+    }
+
     #[fbinit::test]
     fn pushrebase_case_conflict(fb: FacebookInit) -> Result<(), Error> {
         let mut runtime = tokio::runtime::Runtime::new().unwrap();
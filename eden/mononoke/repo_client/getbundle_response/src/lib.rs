@@ -12,6 +12,7 @@ use anyhow::{anyhow, Error, Result};
 use blobrepo::{BlobRepo, ChangesetFetcher};
 use blobrepo_hg::BlobRepoHg;
 use blobstore::Loadable;
+use bonsai_hg_mapping::BonsaiHgMapping;
 use bytes::Bytes;
 use bytes_old::Bytes as BytesOld;
 use cloned::cloned;
@@ -58,6 +59,7 @@ use std::{
     collections::{HashMap, HashSet},
     iter::FromIterator,
     sync::Arc,
+    time::{Duration, Instant},
 };
 use tunables::tunables;
 
@@ -127,6 +129,12 @@ pub async fn create_getbundle_response(
 
     report_draft_commits(ctx, &draft_commits);
 
+    // The changegroup, phases and mutation parts all need the hg hashes of these commits.
+    blobrepo
+        .get_bonsai_hg_mapping()
+        .prefetch(ctx, blobrepo.get_repoid(), commits_to_send.clone().into())
+        .await?;
+
     let mut parts = vec![];
     if heads_len != 0 {
         // no heads means bookmark-only pushrebase, and the client
@@ -501,6 +509,51 @@ fn warn_expensive_getbundle(ctx: &CoreContext) {
     );
 }
 
+/// Reports to the client how many of the changesets of a getbundle response have been prepared,
+/// so that large pulls show progress rather than stalling until the changegroup is complete.
+/// Pulls that are done before the first interval report nothing.
+struct ChangesetProgress {
+    ctx: CoreContext,
+    total: usize,
+    prepared: usize,
+    last_report: Instant,
+    reported: bool,
+}
+
+impl ChangesetProgress {
+    fn new(ctx: CoreContext, total: usize) -> Self {
+        Self {
+            ctx,
+            total,
+            prepared: 0,
+            last_report: Instant::now(),
+            reported: false,
+        }
+    }
+
+    fn prepared_one(&mut self) {
+        self.prepared += 1;
+
+        let interval = tunables().get_getbundle_progress_interval_secs();
+        if interval <= 0 {
+            return;
+        }
+        let done = self.prepared == self.total;
+        let due = self.last_report.elapsed() >= Duration::from_secs(interval as u64);
+        if !(due || (done && self.reported)) {
+            return;
+        }
+
+        info!(
+            self.ctx.logger(),
+            "{} of {} changesets prepared", self.prepared, self.total
+            ; o!("remote" => "true")
+        );
+        self.last_report = Instant::now();
+        self.reported = true;
+    }
+}
+
 async fn create_hg_changeset_part(
     ctx: &CoreContext,
     blobrepo: &BlobRepo,
@@ -510,6 +563,7 @@ async fn create_hg_changeset_part(
 ) -> Result<PartEncodeBuilder> {
     let map_chunk_size = 100;
     let load_buffer_size = 1000;
+    let mut progress = ChangesetProgress::new(ctx.clone(), nodes_to_send.len());
 
     let changelogentries = stream::iter(nodes_to_send)
         .chunks(map_chunk_size)
@@ -580,6 +634,7 @@ async fn create_hg_changeset_part(
                 HgBlobNode::new(Bytes::from(v), revlogcs.p1(), revlogcs.p2()),
             ))
         })
+        .inspect_ok(move |_| progress.prepared_one())
         .boxed()
         .compat();
 
@@ -604,6 +659,10 @@ async fn hg_to_bonsai_stream(
     repo: &BlobRepo,
     nodes: Vec<HgChangesetId>,
 ) -> Result<Vec<(ChangesetId, Generation)>, Error> {
+    repo.get_bonsai_hg_mapping()
+        .prefetch(ctx, repo.get_repoid(), nodes.clone().into())
+        .await?;
+
     stream::iter(nodes)
         .map({
             move |node| async move {
@@ -12,14 +12,11 @@ use blobrepo::BlobRepo;
 
 use blobrepo_factory::ReadOnlyStorage;
 use fbinit::FacebookInit;
-use futures::future::{FutureExt, TryFutureExt};
-use futures_01_ext::{BoxFuture, FutureExt as _};
 use getbundle_response::SessionLfsParams;
 use hooks::HookManager;
 use live_commit_sync_config::LiveCommitSyncConfig;
 use metaconfig_types::{
     BookmarkAttrs, InfinitepushParams, MetadataDatabaseConfig, PushParams, PushrebaseParams,
-    RepoReadOnly,
 };
 use mononoke_api::Repo;
 use mononoke_types::RepositoryId;
@@ -265,14 +262,6 @@ impl MononokeRepo {
         self.repo.repoid()
     }
 
-    pub fn readonly(&self) -> BoxFuture<RepoReadOnly, Error> {
-        let repo = self.repo.clone();
-        async move { repo.readonly_fetcher().readonly().await }
-            .boxed()
-            .compat()
-            .boxify()
-    }
-
     pub fn readonly_fetcher(&self) -> &RepoReadWriteFetcher {
         &self.repo.readonly_fetcher()
     }
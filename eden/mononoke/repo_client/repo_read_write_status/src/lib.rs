@@ -8,11 +8,15 @@
 #![deny(warnings)]
 
 use anyhow::Error;
-use metaconfig_types::HgsqlName;
+use fbinit::FacebookInit;
+use metaconfig_types::{HgsqlName, RepoConfig};
 use sql::mysql;
 use sql::{queries, Connection};
-use sql_construct::{SqlConstruct, SqlConstructFromMetadataDatabaseConfig};
-use sql_ext::SqlConnections;
+use sql_construct::{
+    facebook::FbSqlConstruct, SqlConstruct, SqlConstructFromMetadataDatabaseConfig,
+};
+use sql_ext::{facebook::MysqlOptions, SqlConnections};
+use thiserror::Error;
 
 use sql::mysql_async::{
     prelude::{ConvIr, FromValue},
@@ -25,6 +29,15 @@ static DEFAULT_MSG: &str = "Defaulting to locked as the lock state isn't initial
 static NOT_CONNECTED_MSG: &str = "Defaulting to locked as no database connection passed";
 static DB_MSG: &str = "Repo is locked in DB";
 
+/// Why a write to a repo was refused by `RepoReadWriteFetcher::check_writable`.
+#[derive(Debug, Error)]
+pub enum RepoWriteError {
+    #[error("Repo is locked: {0}")]
+    Locked(String),
+    #[error("Failed to fetch repo lock state")]
+    Error(#[source] Error),
+}
+
 #[derive(Clone, mysql::OptTryFromRowField)]
 enum HgMononokeReadWrite {
     NoWrite,
@@ -146,6 +159,29 @@ impl RepoReadWriteFetcher {
         }
     }
 
+    /// The fetcher for a repo as per its config: the lock state is read from the write lock db
+    /// if the repo has one, and a repo that is set as read-only in its config stays read-only
+    /// regardless of it.
+    pub async fn from_repo_config(
+        fb: FacebookInit,
+        config: &RepoConfig,
+        mysql_options: &MysqlOptions,
+        readonly_storage: bool,
+    ) -> Result<Self, Error> {
+        let sql_repo_read_write_status = match &config.write_lock_db_address {
+            Some(addr) => Some(
+                SqlRepoReadWriteStatus::with_xdb(fb, addr.clone(), mysql_options, readonly_storage)
+                    .await?,
+            ),
+            None => None,
+        };
+        Ok(Self::new(
+            sql_repo_read_write_status,
+            config.readonly.clone(),
+            config.hgsql_name.clone(),
+        ))
+    }
+
     async fn query_read_write_state(&self) -> Result<RepoReadOnly, Error> {
         match &self.sql_repo_read_write_status {
             Some(status) => status
@@ -173,6 +209,16 @@ impl RepoReadWriteFetcher {
         }
     }
 
+    /// The guard that all the paths that write to the repo go through, so that a repo that is
+    /// locked refuses every write, with the reason it is locked for.
+    pub async fn check_writable(&self) -> Result<(), RepoWriteError> {
+        let state = self.readonly().await.map_err(RepoWriteError::Error)?;
+        match state {
+            RepoReadOnly::ReadOnly(reason) => Err(RepoWriteError::Locked(reason)),
+            RepoReadOnly::ReadWrite => Ok(()),
+        }
+    }
+
     async fn set_state(&self, state: &HgMononokeReadWrite, reason: &String) -> Result<bool, Error> {
         match &self.sql_repo_read_write_status {
             Some(status) => status.set_state(&self.hgsql_name, &state, &reason).await,
@@ -235,6 +281,24 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_check_writable() {
+        async_unit::tokio_unit_test(async move {
+            let fetcher = RepoReadWriteFetcher::new(
+                None,
+                ReadOnly(CONFIG_MSG.to_string()),
+                HgsqlName("repo".to_string()),
+            );
+            match fetcher.check_writable().await {
+                Err(RepoWriteError::Locked(reason)) => assert_eq!(reason, CONFIG_MSG),
+                res => panic!("unexpected result: {:?}", res),
+            }
+
+            let fetcher = RepoReadWriteFetcher::new(None, ReadWrite, HgsqlName("repo".to_string()));
+            assert!(fetcher.check_writable().await.is_ok());
+        });
+    }
+
     #[test]
     fn test_readonly_config_with_sqlite() {
         async_unit::tokio_unit_test(async move {
@@ -20,6 +20,7 @@ use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use scribe::ScribeClient;
 use scuba_ext::{MononokeScubaSampleBuilder, ScribeClientImplementation, ScubaValue};
 use stats::prelude::*;
+use stats_ext::repo_key;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
@@ -33,10 +34,15 @@ define_stats! {
     wireproto_scribe_success: timeseries(Rate, Sum),
     wireproto_scribe_failure: timeseries(Rate, Sum),
     wireproto_serialization_failure: timeseries(Rate, Sum),
+
+    command_ms: dynamic_histogram("{}.command.{}.ms", (repo: String, command: String); 100, 0, 5000, Average, Sum, Count; P 50; P 95; P 99),
+    // Quicksand is kept out of command_ms, for it to reflect interactive traffic only.
+    quicksand_command_ms: dynamic_histogram("{}.quicksand.command.{}.ms", (repo: String, command: String); 100, 0, 5000, Average, Sum, Count; P 50; P 95; P 99),
 }
 
 pub struct WireprotoLogging {
     reponame: String,
+    repo_key: String,
     scribe_args: Option<(ScribeClientImplementation, String)>,
     blobstore_and_threshold: Option<(Arc<dyn Blobstore>, u64)>,
     scuba_builder: MononokeScubaSampleBuilder,
@@ -61,6 +67,7 @@ impl WireprotoLogging {
         }
 
         Ok(Self {
+            repo_key: repo_key(&reponame),
             reponame,
             scribe_args,
             blobstore_and_threshold,
@@ -126,7 +133,12 @@ impl CommandLogger {
         wireproto: Arc<WireprotoLogging>,
         request_perf_counters: Arc<PerfCounters>,
     ) -> Self {
-        let inner = ScubaOnlyCommandLogger::new(ctx, request_perf_counters);
+        let inner = ScubaOnlyCommandLogger::new(
+            ctx,
+            wireproto.repo_key.clone(),
+            command.clone(),
+            request_perf_counters,
+        );
 
         Self {
             inner,
@@ -176,14 +188,23 @@ impl CommandLogger {
 #[must_use = "A CommandLogger does not do anything if you don't use it"]
 pub struct ScubaOnlyCommandLogger {
     ctx: CoreContext,
+    repo_key: String,
+    command: String,
     request_perf_counters: Arc<PerfCounters>,
     extra: HashMap<String, ScubaValue>,
 }
 
 impl ScubaOnlyCommandLogger {
-    fn new(ctx: CoreContext, request_perf_counters: Arc<PerfCounters>) -> Self {
+    fn new(
+        ctx: CoreContext,
+        repo_key: String,
+        command: String,
+        request_perf_counters: Arc<PerfCounters>,
+    ) -> Self {
         Self {
             ctx,
+            repo_key,
+            command,
             request_perf_counters,
             extra: HashMap::new(),
         }
@@ -198,6 +219,12 @@ impl ScubaOnlyCommandLogger {
     }
 
     fn log_command_processed(self, stats: CommandStats) {
+        let ms = stats.completion_time().as_millis_unchecked() as i64;
+        if self.ctx.session().is_quicksand() {
+            STATS::quicksand_command_ms.add_value(ms, (self.repo_key, self.command));
+        } else {
+            STATS::command_ms.add_value(ms, (self.repo_key, self.command));
+        }
         self.request_perf_counters
             .update_with_counters(self.ctx.perf_counters().top());
         let mut scuba = self.ctx.scuba().clone();
@@ -57,7 +57,7 @@ use mercurial_types::{
     HgChangesetIdsResolvedFromPrefix, HgFileNodeId, HgManifestId, HgNodeHash, HgParents, MPath,
     RepoPath, NULL_CSID, NULL_HASH,
 };
-use metaconfig_types::{RepoClientKnobs, RepoReadOnly};
+use metaconfig_types::RepoClientKnobs;
 use mononoke_repo::{MononokeRepo, SqlStreamingCloneConfig};
 use mononoke_types::hash::GitSha1;
 use nonzero_ext::nonzero;
@@ -69,7 +69,8 @@ use remotefilelog::{
 };
 use revisionstore_types::Metadata;
 use serde_json::{self, json};
-use slog::{debug, error, info, o};
+use slog::{debug, error, info, o, warn};
+use sql_ext::QueryAttribution;
 use stats::prelude::*;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryInto;
@@ -85,7 +86,7 @@ use std::time::{Duration, Instant};
 use streaming_clone::RevlogStreamingChunks;
 use time_ext::DurationExt;
 use tokio::time::delay_for;
-use tunables::tunables;
+use tunables::{tunables, DerivedTunable, MononokeTunables};
 
 mod logging;
 mod monitor;
@@ -107,6 +108,7 @@ define_stats! {
         histogram(20, 0, 2_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     getcommitdata_ms:
         histogram(2, 0, 200, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    deprecated_command_warnings: timeseries(Rate, Sum),
     total_tree_count: timeseries(Rate, Sum),
     quicksand_tree_count: timeseries(Rate, Sum),
     total_tree_size: timeseries(Rate, Sum),
@@ -153,6 +155,10 @@ struct SamplingRate(NonZeroU64);
 const GETTREEPACK_FEW_MFNODES_SAMPLING_RATE: SamplingRate = SamplingRate(nonzero!(100u64));
 const UNSAMPLED: SamplingRate = SamplingRate(nonzero!(1u64));
 
+// Default number of commits that getcommitdata loads concurrently, ahead of what the client has
+// consumed.
+const GETCOMMITDATA_DEFAULT_CONCURRENCY: usize = 100;
+
 fn gettreepack_scuba_sampling_rate(params: &GettreepackArgs) -> SamplingRate {
     if params.mfnodes.len() == 1 {
         GETTREEPACK_FEW_MFNODES_SAMPLING_RATE
@@ -217,6 +223,15 @@ lazy_static! {
     static ref SLOW_REQUEST_THRESHOLD: Duration = Duration::from_secs(1);
 }
 
+fn getcommitdata_concurrency() -> usize {
+    let concurrency = tunables().get_repo_client_getcommitdata_concurrency();
+    if concurrency > 0 {
+        concurrency as usize
+    } else {
+        GETCOMMITDATA_DEFAULT_CONCURRENCY
+    }
+}
+
 fn clone_timeout() -> Duration {
     let timeout = tunables().get_repo_client_clone_timeout_secs();
     if timeout > 0 {
@@ -254,6 +269,7 @@ fn getpack_timeout() -> Duration {
 
 fn wireprotocaps() -> Vec<String> {
     vec![
+        "batch".to_string(),
         "clienttelemetry".to_string(),
         "lookup".to_string(),
         "known".to_string(),
@@ -270,6 +286,7 @@ fn wireprotocaps() -> Vec<String> {
         "knownnodes".to_string(),
         "designatednodes".to_string(),
         "getcommitdata".to_string(),
+        "sparsetreepack".to_string(),
     ]
 }
 
@@ -311,6 +328,44 @@ fn bundle2caps() -> String {
     percent_encode(&encodedcaps.join("\n"))
 }
 
+struct UndesiredPathConfig {
+    repo_name: String,
+    // The prefix and regex to log, or the reason they are invalid.
+    paths: Result<(Option<MPath>, Option<Regex>), String>,
+}
+
+impl UndesiredPathConfig {
+    fn from_tunables(tunables: &MononokeTunables) -> Self {
+        let paths = || -> Result<_, Error> {
+            let prefix = MPath::new_opt(tunables.get_undesired_path_prefix_to_log().as_str())?;
+            let regex = tunables.get_undesired_path_regex_to_log();
+            let regex = if regex.is_empty() {
+                None
+            } else {
+                Some(Regex::new(regex.as_str())?)
+            };
+            Ok((prefix, regex))
+        };
+
+        Self {
+            repo_name: tunables.get_undesired_path_repo_name_to_log().to_string(),
+            paths: paths().map_err(|e| format!("{:#}", e)),
+        }
+    }
+}
+
+lazy_static! {
+    // Parsed when the tunables change rather than for every getpack and gettreepack.
+    static ref UNDESIRED_PATH_CONFIG: DerivedTunable<UndesiredPathConfig> = DerivedTunable::new(
+        &[
+            "undesired_path_repo_name_to_log",
+            "undesired_path_prefix_to_log",
+            "undesired_path_regex_to_log",
+        ],
+        UndesiredPathConfig::from_tunables,
+    );
+}
+
 struct UndesiredPathLogger {
     ctx: CoreContext,
     repo_needs_logging: bool,
@@ -320,32 +375,21 @@ struct UndesiredPathLogger {
 
 impl UndesiredPathLogger {
     fn new(ctx: CoreContext, repo: &BlobRepo) -> Result<Self, Error> {
-        let tunables = tunables();
-        let repo_needs_logging =
-            repo.name() == tunables.get_undesired_path_repo_name_to_log().as_str();
+        let config = UNDESIRED_PATH_CONFIG.get();
+        let repo_needs_logging = repo.name() == &config.repo_name;
 
-        let path_prefix_to_log = if repo_needs_logging {
-            MPath::new_opt(tunables.get_undesired_path_prefix_to_log().as_str())?
-        } else {
-            None
-        };
-
-        let path_regex_to_log = if repo_needs_logging
-            && !tunables.get_undesired_path_regex_to_log().is_empty()
-        {
-            Some(
-                Regex::new(tunables.get_undesired_path_regex_to_log().as_str()).map_err(|e| {
-                    error!(
-                        ctx.logger(),
-                        "Error initializing undesired path regex for {}: {}",
-                        repo.name(),
-                        e
-                    );
+        let (path_prefix_to_log, path_regex_to_log) = if repo_needs_logging {
+            config.paths.clone().map_err(|e| {
+                error!(
+                    ctx.logger(),
+                    "Error initializing undesired paths for {}: {}",
+                    repo.name(),
                     e
-                })?,
-            )
+                );
+                format_err!("{}", e)
+            })?
         } else {
-            None
+            (None, None)
         };
 
         Ok(Self {
@@ -420,7 +464,24 @@ pub struct RepoClient {
     maybe_push_redirector_args: Option<PushRedirectorArgs>,
     force_lfs: Arc<AtomicBool>,
     knobs: RepoClientKnobs,
+    // Deprecated commands that the client was already warned about in this session.
+    deprecation_warnings_sent: Arc<Mutex<HashSet<&'static str>>>,
     request_perf_counters: Arc<PerfCounters>,
+    // Token returned in `hello`, which the client can present when reconnecting to resume
+    // this session.
+    resumption_token: Option<String>,
+    // Advertised in `hello`. Clients include it in the keys of the responses they cache, so
+    // that caches can be invalidated by bumping it.
+    cache_epoch: Option<u64>,
+}
+
+/// The part of a RepoClient's state that can be carried over to a new connection when a client
+/// resumes its session. Keeping the bookmarks cache means that a pull interrupted between
+/// discovery and getbundle is retried against the same heads the client already discovered.
+#[derive(Clone)]
+pub struct ResumableClientState {
+    session_bookmarks_cache: Arc<SessionBookmarkCache>,
+    deprecation_warnings_sent: Arc<Mutex<HashSet<&'static str>>>,
 }
 
 impl RepoClient {
@@ -445,7 +506,36 @@ impl RepoClient {
             maybe_push_redirector_args,
             force_lfs: Arc::new(AtomicBool::new(false)),
             knobs,
+            deprecation_warnings_sent: Arc::new(Mutex::new(HashSet::new())),
             request_perf_counters: Arc::new(PerfCounters::default()),
+            resumption_token: None,
+            cache_epoch: None,
+        }
+    }
+
+    /// Pick up the state left behind by the session this one resumes.
+    pub fn with_resumed_state(mut self, state: ResumableClientState) -> Self {
+        self.session_bookmarks_cache = state.session_bookmarks_cache;
+        self.deprecation_warnings_sent = state.deprecation_warnings_sent;
+        self
+    }
+
+    /// Advertise `resumption_token` to the client in `hello`.
+    pub fn with_resumption_token(mut self, resumption_token: String) -> Self {
+        self.resumption_token = Some(resumption_token);
+        self
+    }
+
+    /// Advertise `cache_epoch` to the client in `hello`.
+    pub fn with_cache_epoch(mut self, cache_epoch: u64) -> Self {
+        self.cache_epoch = Some(cache_epoch);
+        self
+    }
+
+    pub fn resumable_state(&self) -> ResumableClientState {
+        ResumableClientState {
+            session_bookmarks_cache: self.session_bookmarks_cache.clone(),
+            deprecation_warnings_sent: self.deprecation_warnings_sent.clone(),
         }
     }
 
@@ -455,7 +545,7 @@ impl RepoClient {
 
     fn command_future<F, I, E, H>(
         &self,
-        command: &str,
+        command: &'static str,
         sampling_rate: SamplingRate,
         handler: H,
     ) -> BoxFuture<I, E>
@@ -464,12 +554,13 @@ impl RepoClient {
         H: FnOnce(CoreContext, CommandLogger) -> F,
     {
         let (ctx, command_logger) = self.start_command(command, sampling_rate);
-        with_command_monitor(ctx.clone(), handler(ctx, command_logger)).boxify()
+        let reponame = self.repo.reponame().clone();
+        with_command_monitor(ctx.clone(), reponame, handler(ctx, command_logger)).boxify()
     }
 
     fn command_stream<S, I, E, H>(
         &self,
-        command: &str,
+        command: &'static str,
         sampling_rate: SamplingRate,
         handler: H,
     ) -> BoxStream<I, E>
@@ -478,12 +569,13 @@ impl RepoClient {
         H: FnOnce(CoreContext, CommandLogger) -> S,
     {
         let (ctx, command_logger) = self.start_command(command, sampling_rate);
-        with_command_monitor(ctx.clone(), handler(ctx, command_logger)).boxify()
+        let reponame = self.repo.reponame().clone();
+        with_command_monitor(ctx.clone(), reponame, handler(ctx, command_logger)).boxify()
     }
 
     fn start_command(
         &self,
-        command: &str,
+        command: &'static str,
         sampling_rate: SamplingRate,
     ) -> (CoreContext, CommandLogger) {
         info!(self.logging.logger(), "{}", command);
@@ -499,9 +591,10 @@ impl RepoClient {
             .add("command", command);
         scuba.clone().log_with_msg("Start processing", None);
 
-        let ctx =
-            self.session
-                .new_context_with_scribe(logger, scuba, self.logging.scribe().clone());
+        let ctx = self
+            .session
+            .new_context_with_scribe(logger, scuba, self.logging.scribe().clone())
+            .with_caller_tag(command);
 
         let command_logger = CommandLogger::new(
             ctx.clone(),
@@ -510,9 +603,46 @@ impl RepoClient {
             self.request_perf_counters.clone(),
         );
 
+        self.maybe_warn_deprecated(&ctx, command);
+
         (ctx, command_logger)
     }
 
+    // Deprecated commands are still served as usual, but the user is told (once per session)
+    // when the command goes away and what to use instead.
+    fn maybe_warn_deprecated(&self, ctx: &CoreContext, command: &'static str) {
+        let deprecation = match self.knobs.deprecated_commands.get(command) {
+            Some(deprecation) => deprecation,
+            None => return,
+        };
+        let newly_warned = self
+            .deprecation_warnings_sent
+            .lock()
+            .expect("lock poisoned")
+            .insert(command);
+        if !newly_warned {
+            return;
+        }
+
+        STATS::deprecated_command_warnings.add_value(1);
+        ctx.scuba()
+            .clone()
+            .add("deprecated_command", command)
+            .log_with_msg("Deprecated command", None);
+        let replacement = match &deprecation.replacement {
+            Some(replacement) => format!(", please use {} instead", replacement),
+            None => "".to_string(),
+        };
+        warn!(
+            ctx.logger(),
+            "Warning: the '{}' command is deprecated and will stop working on {}{}",
+            command,
+            deprecation.sunset_date,
+            replacement;
+            "remote" => "remote_only"
+        );
+    }
+
     fn get_publishing_bookmarks_maybe_stale(
         &self,
         ctx: CoreContext,
@@ -742,17 +872,29 @@ impl RepoClient {
 
                                     // NOTE: We don't otherwise await history_fut until we have the results
                                     // from blob_futs, so we need to spawn this to start fetching history
-                                    // before we have resoved hg filenodes.
+                                    // before we have resoved hg filenodes. Spawned tasks outlive the
+                                    // command, so stop if the client goes away, and they aren't polled
+                                    // by the session, so account for their CPU time separately.
                                     let history_fut = tokio::task::spawn(
-                                        get_unordered_file_history_for_multiple_nodes(
-                                            ctx.clone(),
-                                            repo.clone(),
-                                            filenodes.into_iter().collect(),
-                                            &path,
-                                            allow_short_getpack_history,
-                                        )
-                                        .compat()
-                                        .try_collect::<Vec<_>>(),
+                                        ctx.session().account_cpu(
+                                            ctx.session()
+                                                .unless_cancelled(
+                                                    get_unordered_file_history_for_multiple_nodes(
+                                                        ctx.clone(),
+                                                        repo.clone(),
+                                                        filenodes.into_iter().collect(),
+                                                        &path,
+                                                        allow_short_getpack_history,
+                                                    )
+                                                    .compat()
+                                                    .try_collect::<Vec<_>>(),
+                                                )
+                                                .map(|res| {
+                                                    res.unwrap_or_else(|| {
+                                                        Err(format_err!("Session was cancelled"))
+                                                    })
+                                                }),
+                                        ),
                                     )
                                     .flatten_err();
 
@@ -1013,22 +1155,8 @@ where
 
 async fn check_lock_repo(repo: MononokeRepo) -> Result<Bytes, BundleResolverError> {
     loop {
-        match repo
-            .readonly()
-            .or_else(|_| {
-                ok::<RepoReadOnly, Error>(RepoReadOnly::ReadOnly(
-                    "Failed to fetch repo lock status".to_string(),
-                ))
-            })
-            .compat()
-            .await?
-        {
-            RepoReadOnly::ReadOnly(reason) => {
-                let e = Error::from(ErrorKind::RepoReadOnly(reason));
-                return Err(e.into());
-            }
-            RepoReadOnly::ReadWrite => delay_for(Duration::from_secs(1)).await,
-        }
+        unbundle::check_writable(repo.readonly_fetcher()).await?;
+        delay_for(Duration::from_secs(1)).await;
     }
 }
 
@@ -1516,6 +1644,12 @@ impl HgCommands for RepoClient {
             let mut caps = wireprotocaps();
             caps.push(format!("bundle2={}", bundle2caps()));
             res.insert("capabilities".to_string(), caps);
+            if let Some(token) = &self.resumption_token {
+                res.insert("resumption_token".to_string(), vec![token.clone()]);
+            }
+            if let Some(epoch) = self.cache_epoch {
+                res.insert("cache_epoch".to_string(), vec![epoch.to_string()]);
+            }
 
             future_old::ok(res).timed(move |stats, _| {
                 command_logger.without_wireproto().finalize_command(&stats);
@@ -1640,194 +1774,181 @@ impl HgCommands for RepoClient {
 
         let lfs_params = self.lfs_params();
 
-        self.repo
-            .readonly()
-            // Assume read only if we have an error.
-            .or_else(|_| {
-                ok(RepoReadOnly::ReadOnly(
-                    "Failed to fetch repo lock status".to_string(),
-                ))
-            })
-            .and_then(move |read_write| {
-                let client = repoclient.clone();
-                repoclient.command_future(ops::UNBUNDLE, UNSAMPLED, move |ctx, command_logger| {
-                    async move {
-                        let blobrepo = client.repo.blobrepo();
-                        let bookmark_attrs = client.repo.bookmark_attrs();
-                        let lca_hint = client.repo.lca_hint().clone();
-                        let infinitepush_params = client.repo.infinitepush().clone();
-                        let infinitepush_writes_allowed = infinitepush_params.allow_writes;
-                        let pushrebase_params = client.repo.pushrebase_params().clone();
-                        let push_params = client.repo.push_params().clone();
-                        let pure_push_allowed = push_params.pure_push_allowed;
-                        let reponame = client.repo.reponame().clone();
-
-                        let pushrebase_flags = pushrebase_params.flags.clone();
-                        let res = unbundle::resolve(
-                            &ctx,
-                            &blobrepo,
-                            infinitepush_writes_allowed,
-                            stream.compat().boxed(),
-                            read_write,
-                            maybe_full_content,
-                            pure_push_allowed,
-                            pushrebase_flags,
-                        )
-                        .await;
-                        match res {
-                            Err(e) => Err(e),
-                            Ok((action, bypass_readonly)) => {
-                                let unbundle_future = async {
-                                    let response = match client
-                                        .maybe_get_pushredirector_for_action(&ctx, &action)?
-                                    {
-                                        Some(push_redirector) => {
-                                            // Push-redirection will cause
-                                            // hooks to be run in the large
-                                            // repo, but we must also run them
-                                            // in the small repo.
-                                            run_hooks(
-                                                &ctx,
-                                                &blobrepo,
-                                                hook_manager.as_ref(),
-                                                &action,
-                                                CrossRepoPushSource::NativeToThisRepo,
-                                            )
-                                            .await?;
-
-                                            let ctx = ctx.with_mutated_scuba(|mut sample| {
-                                                sample.add(
-                                                    "target_repo_name",
-                                                    push_redirector.repo.reponame().as_ref(),
-                                                );
-                                                sample.add(
-                                                    "target_repo_id",
-                                                    push_redirector.repo.repoid().id(),
-                                                );
-                                                sample
-                                            });
-                                            ctx.scuba().clone().log_with_msg(
-                                                "Push redirected to large repo",
-                                                None,
+        let client = repoclient.clone();
+        repoclient.command_future(ops::UNBUNDLE, UNSAMPLED, move |ctx, command_logger| {
+            async move {
+                let blobrepo = client.repo.blobrepo();
+                let bookmark_attrs = client.repo.bookmark_attrs();
+                let lca_hint = client.repo.lca_hint().clone();
+                let infinitepush_params = client.repo.infinitepush().clone();
+                let infinitepush_writes_allowed = infinitepush_params.allow_writes;
+                let pushrebase_params = client.repo.pushrebase_params().clone();
+                let push_params = client.repo.push_params().clone();
+                let pure_push_allowed = push_params.pure_push_allowed;
+                let reponame = client.repo.reponame().clone();
+
+                let pushrebase_flags = pushrebase_params.flags.clone();
+                let res = unbundle::resolve(
+                    &ctx,
+                    &blobrepo,
+                    infinitepush_writes_allowed,
+                    stream.compat().boxed(),
+                    client.repo.readonly_fetcher(),
+                    maybe_full_content,
+                    pure_push_allowed,
+                    pushrebase_flags,
+                )
+                .await;
+                match res {
+                    Err(e) => Err(e),
+                    Ok((action, bypass_readonly)) => {
+                        let unbundle_future = async {
+                            let response =
+                                match client.maybe_get_pushredirector_for_action(&ctx, &action)? {
+                                    Some(push_redirector) => {
+                                        // Push-redirection will cause
+                                        // hooks to be run in the large
+                                        // repo, but we must also run them
+                                        // in the small repo.
+                                        run_hooks(
+                                            &ctx,
+                                            &blobrepo,
+                                            hook_manager.as_ref(),
+                                            &action,
+                                            CrossRepoPushSource::NativeToThisRepo,
+                                        )
+                                        .await?;
+
+                                        let ctx = ctx.with_mutated_scuba(|mut sample| {
+                                            sample.add(
+                                                "target_repo_name",
+                                                push_redirector.repo.reponame().as_ref(),
                                             );
-                                            push_redirector
-                                                .run_redirected_post_resolve_action(&ctx, action)
-                                                .await
-                                        }
-                                        None => {
-                                            let maybe_reverse_filler_queue =
-                                                client.repo.maybe_reverse_filler_queue();
-                                            let readonly_fetcher = client.repo.readonly_fetcher();
-                                            run_post_resolve_action(
-                                                &ctx,
-                                                &blobrepo,
-                                                &bookmark_attrs,
-                                                &lca_hint,
-                                                &infinitepush_params,
-                                                &pushrebase_params,
-                                                &push_params,
-                                                hook_manager.as_ref(),
-                                                maybe_reverse_filler_queue,
-                                                readonly_fetcher,
-                                                action,
-                                                CrossRepoPushSource::NativeToThisRepo,
-                                            )
+                                            sample.add(
+                                                "target_repo_id",
+                                                push_redirector.repo.repoid().id(),
+                                            );
+                                            sample
+                                        });
+                                        ctx.scuba()
+                                            .clone()
+                                            .log_with_msg("Push redirected to large repo", None);
+                                        push_redirector
+                                            .run_redirected_post_resolve_action(&ctx, action)
                                             .await
-                                        }
-                                    };
-                                    let response = response?
-                                        .generate_bytes(
+                                    }
+                                    None => {
+                                        let maybe_reverse_filler_queue =
+                                            client.repo.maybe_reverse_filler_queue();
+                                        let readonly_fetcher = client.repo.readonly_fetcher();
+                                        run_post_resolve_action(
                                             &ctx,
                                             &blobrepo,
-                                            &reponame,
-                                            pushrebase_params,
+                                            &bookmark_attrs,
                                             &lca_hint,
-                                            &lfs_params,
-                                            respondlightly,
+                                            &infinitepush_params,
+                                            &pushrebase_params,
+                                            &push_params,
+                                            hook_manager.as_ref(),
+                                            maybe_reverse_filler_queue,
+                                            readonly_fetcher,
+                                            action,
+                                            CrossRepoPushSource::NativeToThisRepo,
                                         )
-                                        .await?;
-
-                                    Ok(response)
-                                };
-
-                                let response = if bypass_readonly {
-                                    unbundle_future.await
-                                } else {
-                                    let repo_lock = check_lock_repo(mononoke_repo);
-                                    pin_mut!(repo_lock, unbundle_future);
-                                    select(repo_lock, unbundle_future)
-                                        .then(|either| async move {
-                                            match either {
-                                                Either::Left((repo_locked, _)) => repo_locked,
-                                                Either::Right((unbundle, _)) => unbundle,
-                                            }
-                                        })
                                         .await
+                                    }
                                 };
-                                if response.is_ok() {
-                                    // There's a bookmarks race condition where the client requests bookmarks after we return commits to it,
-                                    // and is then confused because the bookmarks refer to commits that it doesn't know about. Ultimately,
-                                    // this is something we need to resolve by sending down the commits we know the client doesn't have,
-                                    // or by getting bookmarks atomically with the commits we send back.
-                                    //
-                                    // This tries to minimise the duration of the bookmarks race condition - we've just updated bookmarks,
-                                    // and now we fill the cache with new bookmark data, so that, with luck, the bookmark update we see
-                                    // will just be from this client's push, rather than from a later push that came in during the RTT
-                                    // needed to get the `listkeys` request from the client.
-                                    //
-                                    // Ultimately, it would be better to not have the client `listkeys` after the push, but instead
-                                    // depend on the reply part with a bookmark change in - T57874233
-                                    session_bookmarks_cache
-                                        .update_publishing_bookmarks_after_push(ctx.clone())
-                                        .compat()
-                                        .await?;
-                                }
-                                response
-                            }
-                        }
-                    }
-                    .inspect_err({
-                        cloned!(reponame);
-                        move |err| {
-                            use unbundle::BundleResolverError::*;
-                            match err {
-                                HookError(hooks) => {
-                                    let failed_hooks: HashSet<String> = hooks
-                                        .iter()
-                                        .map(|fail| fail.get_hook_name().to_string())
-                                        .collect();
+                            let response = response?
+                                .generate_bytes(
+                                    &ctx,
+                                    &blobrepo,
+                                    &reponame,
+                                    pushrebase_params,
+                                    &lca_hint,
+                                    &lfs_params,
+                                    respondlightly,
+                                )
+                                .await?;
 
-                                    for failed_hook in failed_hooks {
-                                        STATS::push_hook_failure
-                                            .add_value(1, (reponame.clone(), failed_hook));
+                            Ok(response)
+                        };
+
+                        let response = if bypass_readonly {
+                            unbundle_future.await
+                        } else {
+                            let repo_lock = check_lock_repo(mononoke_repo);
+                            pin_mut!(repo_lock, unbundle_future);
+                            select(repo_lock, unbundle_future)
+                                .then(|either| async move {
+                                    match either {
+                                        Either::Left((repo_locked, _)) => repo_locked,
+                                        Either::Right((unbundle, _)) => unbundle,
                                     }
-                                }
-                                PushrebaseConflicts(..) => {
-                                    STATS::push_conflicts.add_value(1, (reponame,));
-                                }
-                                RateLimitExceeded { .. } => {
-                                    STATS::rate_limits_exceeded.add_value(1, (reponame,));
-                                }
-                                Error(..) => {
-                                    STATS::push_error.add_value(1, (reponame,));
-                                }
-                            };
+                                })
+                                .await
+                        };
+                        if response.is_ok() {
+                            // There's a bookmarks race condition where the client requests bookmarks after we return commits to it,
+                            // and is then confused because the bookmarks refer to commits that it doesn't know about. Ultimately,
+                            // this is something we need to resolve by sending down the commits we know the client doesn't have,
+                            // or by getting bookmarks atomically with the commits we send back.
+                            //
+                            // This tries to minimise the duration of the bookmarks race condition - we've just updated bookmarks,
+                            // and now we fill the cache with new bookmark data, so that, with luck, the bookmark update we see
+                            // will just be from this client's push, rather than from a later push that came in during the RTT
+                            // needed to get the `listkeys` request from the client.
+                            //
+                            // Ultimately, it would be better to not have the client `listkeys` after the push, but instead
+                            // depend on the reply part with a bookmark change in - T57874233
+                            session_bookmarks_cache
+                                .update_publishing_bookmarks_after_push(ctx.clone())
+                                .compat()
+                                .await?;
                         }
-                    })
-                    .inspect_ok(move |_| STATS::push_success.add_value(1, (reponame,)))
-                    .map_ok(bytes_ext::copy_from_new)
-                    .map_err(Error::from)
-                    .timeout(default_timeout())
-                    .flatten_err()
-                    .boxed()
-                    .compat()
-                    .timed(move |stats, _| {
-                        command_logger.without_wireproto().finalize_command(&stats);
-                        Ok(())
-                    })
-                })
+                        response
+                    }
+                }
+            }
+            .inspect_err({
+                cloned!(reponame);
+                move |err| {
+                    use unbundle::BundleResolverError::*;
+                    match err {
+                        HookError(hooks) => {
+                            let failed_hooks: HashSet<String> = hooks
+                                .iter()
+                                .map(|fail| fail.get_hook_name().to_string())
+                                .collect();
+
+                            for failed_hook in failed_hooks {
+                                STATS::push_hook_failure
+                                    .add_value(1, (reponame.clone(), failed_hook));
+                            }
+                        }
+                        PushrebaseConflicts(..) => {
+                            STATS::push_conflicts.add_value(1, (reponame,));
+                        }
+                        RateLimitExceeded { .. } => {
+                            STATS::rate_limits_exceeded.add_value(1, (reponame,));
+                        }
+                        Error(..) => {
+                            STATS::push_error.add_value(1, (reponame,));
+                        }
+                    };
+                }
             })
-            .boxify()
+            .inspect_ok(move |_| STATS::push_success.add_value(1, (reponame,)))
+            .map_ok(bytes_ext::copy_from_new)
+            .map_err(Error::from)
+            .timeout(default_timeout())
+            .flatten_err()
+            .boxed()
+            .compat()
+            .timed(move |stats, _| {
+                command_logger.without_wireproto().finalize_command(&stats);
+                Ok(())
+            })
+        })
     }
 
     // @wireprotocommand('gettreepack', 'rootdir mfnodes basemfnodes directories')
@@ -1857,6 +1978,12 @@ impl HgCommands for RepoClient {
                 if let Some(depth) = params.depth {
                     args.insert("depth".to_string(), depth.to_string().into());
                 }
+                if !params.sparse_prefixes.is_empty() {
+                    args.insert(
+                        "sparseprefixes".to_string(),
+                        debug_format_directories(&params.sparse_prefixes).into(),
+                    );
+                }
 
                 let args = json!(vec![args]);
 
@@ -2088,20 +2215,22 @@ impl HgCommands for RepoClient {
                 .add("getcommitdata_nodes", nodes.len())
                 .log_with_msg("GetCommitData Params", None);
 
+            // Commits are only loaded as far ahead of what the client has consumed as the
+            // buffer allows, which bounds how much serialized data we hold on to when a client
+            // asks for a lot of commits and reads them slowly.
             let s = stream::iter(nodes.into_iter())
                 .map({
                     cloned!(ctx, blobrepo);
                     move |hg_cs_id| {
-                        cloned!(ctx, blobrepo, hg_cs_id);
+                        cloned!(ctx, blobrepo);
                         async move {
                             let revlog_cs =
                                 RevlogChangeset::load(&ctx, blobrepo.blobstore(), hg_cs_id).await?;
-                            let bytes = serialize_getcommitdata(hg_cs_id, revlog_cs)?;
-                            Result::<_, Error>::Ok(bytes)
+                            serialize_getcommitdata(hg_cs_id, revlog_cs)
                         }
                     }
                 })
-                .buffered(100)
+                .buffered(getcommitdata_concurrency())
                 .inspect_ok({
                     cloned!(ctx);
                     move |bytes| {
@@ -2156,9 +2285,28 @@ pub fn gettreepack_entries(
         basemfnodes,
         depth: fetchdepth,
         directories,
+        sparse_prefixes,
     } = params;
 
+    let sparse_prefixes = try_boxstream!(sparse_prefixes
+        .iter()
+        .map(|prefix| MPath::new_opt(prefix.as_ref()))
+        .collect::<Result<Vec<_>, Error>>());
+    // The root (`None`) is in every sparse profile, so it means the whole tree too.
+    let sparse_prefixes = if sparse_prefixes.iter().any(Option::is_none) {
+        None
+    } else {
+        Some(Arc::new(
+            sparse_prefixes.into_iter().flatten().collect::<Vec<_>>(),
+        ))
+    };
+
     if fetchdepth == Some(1) && !directories.is_empty() {
+        if sparse_prefixes.is_some() {
+            let e = Error::msg("sparseprefixes can't be used with designated nodes");
+            return stream_old::once(Err(e)).boxify();
+        }
+
         if directories.len() != mfnodes.len() {
             let e = format_err!(
                 "invalid directories count ({}, expected {})",
@@ -2236,6 +2384,7 @@ pub fn gettreepack_entries(
                     cur_basemfnode,
                     rootdir.clone(),
                     fetchdepth,
+                    sparse_prefixes.clone(),
                 )
             }),
     )
@@ -2250,33 +2399,60 @@ fn get_changed_manifests_stream(
     basemfid: HgManifestId,
     rootpath: Option<MPath>,
     max_depth: usize,
+    sparse_prefixes: Option<Arc<Vec<MPath>>>,
 ) -> BoxStream<(HgManifestId, Option<MPath>), Error> {
     if max_depth == 1 {
         return stream_old::iter_ok(vec![(mfid, rootpath)]).boxify();
     }
 
+    // With sparse prefixes, only the trees that lead to one of them, or are under one of them,
+    // are needed.
+    let in_sparse_profile = {
+        cloned!(rootpath);
+        move |path: &Option<MPath>| -> bool {
+            let prefixes = match &sparse_prefixes {
+                Some(prefixes) => prefixes,
+                None => return true,
+            };
+            let mut full_path = rootpath.clone();
+            full_path.extend(MPath::into_iter_opt(path.clone()));
+            match full_path {
+                Some(full_path) => prefixes.iter().any(|prefix| {
+                    prefix.is_prefix_of(&full_path) || full_path.is_prefix_of(prefix)
+                }),
+                None => true,
+            }
+        }
+    };
+
     basemfid
         .filtered_diff(
             ctx,
             repo.get_blobstore(),
             mfid,
-            |output_diff| {
-                let (path, entry) = match output_diff {
-                    Diff::Added(path, entry) | Diff::Changed(path, _, entry) => (path, entry),
-                    Diff::Removed(..) => {
-                        return None;
+            {
+                cloned!(in_sparse_profile);
+                move |output_diff| {
+                    let (path, entry) = match output_diff {
+                        Diff::Added(path, entry) | Diff::Changed(path, _, entry) => (path, entry),
+                        Diff::Removed(..) => {
+                            return None;
+                        }
+                    };
+                    match entry {
+                        Entry::Tree(hg_mf_id) if in_sparse_profile(&path) => Some((path, hg_mf_id)),
+                        Entry::Tree(_) | Entry::Leaf(_) => None,
                     }
-                };
-                match entry {
-                    Entry::Tree(hg_mf_id) => Some((path, hg_mf_id)),
-                    Entry::Leaf(_) => None,
                 }
             },
             move |tree_diff| match tree_diff {
-                Diff::Added(path, ..) | Diff::Changed(path, ..) => match path {
-                    Some(path) => path.num_components() <= max_depth,
-                    None => true,
-                },
+                Diff::Added(path, ..) | Diff::Changed(path, ..) => {
+                    let within_depth = match path {
+                        Some(path) => path.num_components() <= max_depth,
+                        None => true,
+                    };
+                    within_depth && in_sparse_profile(path)
+                }
                 Diff::Removed(..) => false,
             },
         )
@@ -2481,8 +2657,9 @@ fn serialize_getcommitdata(
     Ok(buffer.freeze())
 }
 
-fn with_command_monitor<T>(ctx: CoreContext, t: T) -> Monitor<T, Sender<()>> {
+fn with_command_monitor<T>(ctx: CoreContext, reponame: String, t: T) -> Monitor<T, Sender<()>> {
     let (sender, receiver) = oneshot::channel();
+    let attribution = QueryAttribution::new(ctx.clone(), reponame);
 
     let reporting_loop = async move {
         let start = Instant::now();
@@ -2522,7 +2699,7 @@ fn with_command_monitor<T>(ctx: CoreContext, t: T) -> Monitor<T, Sender<()>> {
         let _ = future::select(reporting_loop, receiver).await;
     });
 
-    Monitor::new(t, sender)
+    Monitor::new(t, attribution, sender)
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -6,9 +6,12 @@
  */
 
 use futures_old::{Future, Poll, Stream};
+use sql_ext::QueryAttribution;
 
 pub struct Monitor<T, P> {
     inner: T,
+    // The SQL queries run while polling inner are attributed to the command.
+    attribution: QueryAttribution,
     // We don't actually do anything with this. We just rely on the fact that upon this Monitor
     // being dropped, P will be dropped.
     #[allow(dead_code)]
@@ -16,8 +19,12 @@ pub struct Monitor<T, P> {
 }
 
 impl<T, P> Monitor<T, P> {
-    pub fn new(inner: T, payload: P) -> Self {
-        Self { inner, payload }
+    pub fn new(inner: T, attribution: QueryAttribution, payload: P) -> Self {
+        Self {
+            inner,
+            attribution,
+            payload,
+        }
     }
 }
 
@@ -26,7 +33,8 @@ impl<F: Future, P> Future for Monitor<F, P> {
     type Error = F::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        self.inner.poll()
+        let inner = &mut self.inner;
+        self.attribution.enter(|| inner.poll())
     }
 }
 
@@ -35,6 +43,7 @@ impl<S: Stream, P> Stream for Monitor<S, P> {
     type Error = S::Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        self.inner.poll()
+        let inner = &mut self.inner;
+        self.attribution.enter(|| inner.poll())
     }
 }
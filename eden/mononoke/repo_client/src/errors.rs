@@ -24,6 +24,4 @@ pub enum ErrorKind {
         #[source]
         reason: ThrottleReason,
     },
-    #[error("Repo is marked as read-only: {0}")]
-    RepoReadOnly(String),
 }
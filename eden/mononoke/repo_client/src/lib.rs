@@ -13,7 +13,10 @@
 mod client;
 mod errors;
 
-pub use client::{fetch_treepack_part_input, gettreepack_entries, RepoClient, WireprotoLogging};
+pub use client::{
+    fetch_treepack_part_input, gettreepack_entries, RepoClient, ResumableClientState,
+    WireprotoLogging,
+};
 pub use mononoke_repo::MononokeRepo;
 pub use repo_read_write_status::RepoReadWriteFetcher;
 pub use unbundle::{PushRedirector, PushRedirectorArgs};
@@ -12,6 +12,16 @@ use thiserror::Error;
 pub(crate) enum ErrorKind {
     #[error("Error while uploading data for changesets, hashes: {0:?}")]
     WhileUploadingData(Vec<HgChangesetId>),
+}
+
+/// Why a push was rejected, for the rejections to still be told apart from other failures once
+/// they are converted to an `Error`. The messages are the ones that clients get.
+#[derive(Debug, Error)]
+pub enum PushRejection {
+    #[error("{0}")]
+    HooksFailed(String),
+    #[error("{0}")]
+    RateLimitExceeded(String),
     #[error("Repo is marked as read-only: {0}")]
     RepoReadOnly(String),
 }
@@ -23,12 +23,13 @@ mod stats;
 mod upload_blobs;
 mod upload_changesets;
 
+pub use errors::PushRejection;
 pub use hook_running::run_hooks;
 pub use hooks::CrossRepoPushSource;
 pub use processing::run_post_resolve_action;
 pub use push_redirector::{PushRedirector, PushRedirectorArgs};
 pub use resolver::{
-    resolve, BundleResolverError, BundleResolverResultExt, Changesets, CommonHeads,
+    check_writable, resolve, BundleResolverError, BundleResolverResultExt, Changesets, CommonHeads,
     InfiniteBookmarkPush, NonFastForwardPolicy, PlainBookmarkPush, PostResolveAction,
     PostResolveBookmarkOnlyPushRebase, PostResolveInfinitePush, PostResolvePush,
     PostResolvePushRebase, PushrebaseBookmarkSpec, UploadedBonsais, UploadedHgChangesetIds,
@@ -30,9 +30,10 @@ use mercurial_bundles::{
 use mercurial_mutation::HgMutationEntry;
 use mercurial_revlog::changeset::RevlogChangeset;
 use mercurial_types::HgChangesetId;
-use metaconfig_types::{PushrebaseFlags, RepoReadOnly};
+use metaconfig_types::PushrebaseFlags;
 use mononoke_types::{BlobstoreValue, BonsaiChangeset, ChangesetId, RawBundle2, RawBundle2Id};
 use pushrebase::HgReplayData;
+use repo_read_write_status::{RepoReadWriteFetcher, RepoWriteError};
 use slog::{debug, trace};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
@@ -143,7 +144,7 @@ impl From<BundleResolverError> for Error {
                         )
                     })
                     .collect();
-                format_err!("hooks failed:\n{}", err_msgs.join("\n"))
+                PushRejection::HooksFailed(format!("hooks failed:\n{}", err_msgs.join("\n"))).into()
             }
             PushrebaseConflicts(conflicts) => {
                 format_err!("pushrebase failed Conflicts({:?})", conflicts)
@@ -152,17 +153,13 @@ impl From<BundleResolverError> for Error {
                 limit,
                 entity,
                 value,
-            } => format_err!(
+            } => PushRejection::RateLimitExceeded(format!(
                 "Rate limit exceeded: {} for {}. \
                  The maximum allowed value is {} over a sliding {}s interval. \
                  If allowed, the value would be {}. For help: {}.",
-                limit.name,
-                entity,
-                limit.max_value,
-                limit.interval,
-                value,
-                limit.help,
-            ),
+                limit.name, entity, limit.max_value, limit.interval, value, limit.help,
+            ))
+            .into(),
             Error(err) => err,
         }
     }
@@ -243,6 +240,19 @@ pub enum PostResolveAction {
     BookmarkOnlyPushRebase(PostResolveBookmarkOnlyPushRebase),
 }
 
+/// Reject the push if the repo is locked, with the reason it is locked for. A repo whose lock
+/// state can't be fetched is treated as locked.
+pub async fn check_writable(
+    readonly_fetcher: &RepoReadWriteFetcher,
+) -> Result<(), BundleResolverError> {
+    let reason = match readonly_fetcher.check_writable().await {
+        Ok(()) => return Ok(()),
+        Err(RepoWriteError::Locked(reason)) => reason,
+        Err(RepoWriteError::Error(_)) => "Failed to fetch repo lock status".to_string(),
+    };
+    Err(Error::from(PushRejection::RepoReadOnly(reason)).into())
+}
+
 /// The resolve function takes a bundle2, interprets it's content as Changesets, Filelogs and
 /// Manifests and uploades all of them to the provided BlobRepo in the correct order.
 /// It returns a Future that contains the response that should be send back to the requester.
@@ -251,7 +261,7 @@ pub async fn resolve<'a>(
     repo: &'a BlobRepo,
     infinitepush_writes_allowed: bool,
     bundle2: BoxStream<'static, Result<Bundle2Item<'static>>>,
-    readonly: RepoReadOnly,
+    readonly_fetcher: &'a RepoReadWriteFetcher,
     maybe_full_content: Option<Arc<Mutex<BytesOld>>>,
     pure_push_allowed: bool,
     pushrebase_flags: PushrebaseFlags,
@@ -278,11 +288,8 @@ pub async fn resolve<'a>(
             == Some("true".into());
     }
 
-    if let RepoReadOnly::ReadOnly(reason) = readonly {
-        if bypass_readonly == false {
-            let e = Error::from(ErrorKind::RepoReadOnly(reason));
-            return Err(e.into());
-        }
+    if !bypass_readonly {
+        check_writable(readonly_fetcher).await?;
     }
 
     let (pushkey_next, bundle2) = resolver.is_next_part_pushkey(bundle2).await?;
@@ -26,6 +26,7 @@ pub const ARG_COMMIT_MESSAGE: &str = "commit-message";
 pub const ARG_COMMIT_AUTHOR: &str = "commit-author";
 pub const ARG_COMMIT_DATE_RFC3339: &str = "commit-date-rfc3339";
 pub const ARG_RECOVERY_FILE_PATH: &str = "recovery-file-path";
+pub const ARG_FLATTEN_SUBMODULES: &str = "flatten-submodules";
 pub const RECOVER_PROCESS: &str = "recover-process";
 pub const SAVED_RECOVERY_FILE_PATH: &str = "saved-recovery-file-path";
 pub const CHECK_ADDITIONAL_SETUP_STEPS: &str = "check-additional-setup-steps";
@@ -151,6 +152,12 @@ pub fn setup_app<'a, 'b>() -> MononokeClapApp<'a, 'b> {
                         .takes_value(true)
                         .help("File path to store the importing state for recovery in case the tool breaks"),
                 )
+                .arg(
+                    Arg::with_name(ARG_FLATTEN_SUBMODULES)
+                        .long(ARG_FLATTEN_SUBMODULES)
+                        .help("Import git submodules as subdirectories with their contents, instead of skipping them. \
+                            The submodule commits must be fetched into the git repository first.")
+                )
 
         )
         .subcommand(
@@ -176,6 +183,7 @@ pub fn setup_import_args(matches: &ArgMatches<'_>) -> Result<RecoveryFields, Err
     let phab_check_disabled = matches.is_present(ARG_PHAB_CHECK_DISABLED);
     let x_repo_check_disabled = matches.is_present(ARG_X_REPO_CHECK_DISABLED);
     let hg_sync_check_disabled = matches.is_present(ARG_HG_SYNC_CHECK_DISABLED);
+    let flatten_submodules = matches.is_present(ARG_FLATTEN_SUBMODULES);
     let sleep_time = matches.value_of(ARG_SLEEP_TIME).unwrap();
     let sleep_time = sleep_time.parse::<u64>()?;
     let dest_bookmark_name = matches.value_of(ARG_DEST_BOOKMARK).unwrap();
@@ -197,6 +205,7 @@ pub fn setup_import_args(matches: &ArgMatches<'_>) -> Result<RecoveryFields, Err
         phab_check_disabled,
         x_repo_check_disabled,
         hg_sync_check_disabled,
+        flatten_submodules,
         sleep_time,
         dest_bookmark_name: dest_bookmark_name.to_string(),
         commit_author: commit_author.to_string(),
@@ -135,6 +135,9 @@ pub struct RecoveryFields {
     phab_check_disabled: bool,
     x_repo_check_disabled: bool,
     hg_sync_check_disabled: bool,
+    /// Recovery files written before this option existed don't have it
+    #[serde(default)]
+    flatten_submodules: bool,
     sleep_time: u64,
     dest_bookmark_name: String,
     commit_author: String,
@@ -577,7 +580,7 @@ async fn push_merge_commit(
     info!(ctx.logger(), "Running pushrebase");
 
     let merged_cs = merged_cs_id.load(ctx, repo.blobstore()).await?;
-    let pushrebase_flags = repo_config.pushrebase.flags;
+    let pushrebase_flags = repo_config.pushrebase.flags.clone();
     let pushrebase_hooks = bookmarks_movement::get_pushrebase_hooks(
         &ctx,
         &repo,
@@ -1042,7 +1045,10 @@ async fn repo_import(
 
     // Importing process starts here
     if recovery_fields.import_stage == ImportStage::GitImport {
-        let prefs = GitimportPreferences::default();
+        let mut prefs = GitimportPreferences::default();
+        if recovery_fields.flatten_submodules {
+            prefs.enable_flatten_submodules();
+        }
         let target = FullRepoImport {};
         info!(ctx.logger(), "Started importing git commits to Mononoke");
         let import_map = import_tools::gitimport(&ctx, &repo, &path, &target, prefs).await?;
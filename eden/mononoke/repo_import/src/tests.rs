@@ -101,6 +101,7 @@ mod tests {
             phab_check_disabled: true,
             x_repo_check_disabled: true,
             hg_sync_check_disabled: true,
+            flatten_submodules: false,
             sleep_time: 1,
             dest_bookmark_name: "dest_bookmark_name".to_string(),
             commit_author: "commit_author".to_string(),
@@ -16,6 +16,7 @@ use std::sync::Arc;
 use crate::logging::{LoggingContainer, SamplingKey};
 use crate::perf_counters::PerfCounters;
 use crate::perf_counters_stack::PerfCountersStack;
+use crate::request_cache::RequestCache;
 use crate::session::{SessionClass, SessionContainer};
 
 #[derive(Clone)]
@@ -23,6 +24,7 @@ pub struct CoreContext {
     pub fb: FacebookInit,
     session: SessionContainer,
     logging: LoggingContainer,
+    request_cache: RequestCache,
 }
 
 impl CoreContext {
@@ -60,10 +62,13 @@ impl CoreContext {
     }
 
     /// Create a new CoreContext, with a reset LoggingContainer. This is useful to reset perf
-    /// counters. The existing CoreContext is unaffected.
+    /// counters. The existing CoreContext is unaffected. The request cache is still shared.
     pub fn clone_and_reset(&self) -> Self {
-        self.session
-            .new_context(self.logger().clone(), self.scuba().clone())
+        let mut ctx = self
+            .session
+            .new_context(self.logger().clone(), self.scuba().clone());
+        ctx.request_cache = self.request_cache.clone();
+        ctx
     }
 
     pub fn clone_and_sample(&self, sampling_key: SamplingKey) -> Self {
@@ -71,6 +76,18 @@ impl CoreContext {
             fb: self.fb,
             session: self.session.clone(),
             logging: self.logging.clone_and_sample(sampling_key),
+            request_cache: self.request_cache.clone(),
+        }
+    }
+
+    /// Tag the work done with this context (and the contexts cloned from it) with the feature
+    /// that originated it, so that low level operations like SQL queries can be attributed.
+    pub fn with_caller_tag(&self, caller_tag: &'static str) -> Self {
+        Self {
+            fb: self.fb,
+            session: self.session.clone(),
+            logging: self.logging.clone_with_caller_tag(caller_tag),
+            request_cache: self.request_cache.clone(),
         }
     }
 
@@ -78,11 +95,16 @@ impl CoreContext {
         &self,
         sample: impl FnOnce(MononokeScubaSampleBuilder) -> MononokeScubaSampleBuilder,
     ) -> Self {
-        self.session.new_context_with_scribe(
+        let mut ctx = self.session.new_context_with_scribe(
             self.logger().clone(),
             sample(self.scuba().clone()),
             self.scribe().clone(),
-        )
+        );
+        ctx.request_cache = self.request_cache.clone();
+        match self.caller_tag() {
+            Some(caller_tag) => ctx.with_caller_tag(caller_tag),
+            None => ctx,
+        }
     }
 
     pub(crate) fn new_with_containers(
@@ -94,6 +116,7 @@ impl CoreContext {
             fb,
             logging,
             session,
+            request_cache: RequestCache::default(),
         }
     }
 
@@ -129,6 +152,16 @@ impl CoreContext {
         self.logging.scribe()
     }
 
+    /// Data cached for the rest of the request this context belongs to. Contexts created from the
+    /// session start with an empty cache, while contexts derived from this one share it.
+    pub fn request_cache(&self) -> &RequestCache {
+        &self.request_cache
+    }
+
+    pub fn caller_tag(&self) -> Option<&'static str> {
+        self.logging.caller_tag()
+    }
+
     pub fn fork_perf_counters(&mut self) -> Arc<PerfCounters> {
         self.logging.fork_perf_counters()
     }
@@ -13,10 +13,12 @@ pub use session_id::SessionId;
 pub use crate::core::CoreContext;
 pub use crate::logging::{LoggingContainer, SamplingKey};
 pub use crate::perf_counters::{PerfCounterType, PerfCounters};
-pub use crate::session::{SessionClass, SessionContainer, SessionContainerBuilder};
+pub use crate::request_cache::RequestCache;
+pub use crate::session::{SessionClass, SessionContainer, SessionContainerBuilder, TrafficClass};
 
 mod core;
 mod logging;
 mod perf_counters;
 mod perf_counters_stack;
+mod request_cache;
 mod session;
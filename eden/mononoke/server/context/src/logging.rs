@@ -45,6 +45,7 @@ pub struct LoggingContainer {
     perf_counters: PerfCountersStack,
     sampling_key: Option<SamplingKey>,
     scribe: Scribe,
+    caller_tag: Option<&'static str>,
 }
 
 impl LoggingContainer {
@@ -55,6 +56,7 @@ impl LoggingContainer {
             perf_counters: Default::default(),
             sampling_key: None,
             scribe: Scribe::new(fb),
+            caller_tag: None,
         }
     }
 
@@ -71,6 +73,14 @@ impl LoggingContainer {
             perf_counters: self.perf_counters.clone(),
             sampling_key: Some(sampling_key),
             scribe: self.scribe.clone(),
+            caller_tag: self.caller_tag,
+        }
+    }
+
+    pub fn clone_with_caller_tag(&self, caller_tag: &'static str) -> Self {
+        Self {
+            caller_tag: Some(caller_tag),
+            ..self.clone()
         }
     }
 
@@ -98,4 +108,8 @@ impl LoggingContainer {
     pub fn scribe(&self) -> &Scribe {
         &self.scribe
     }
+
+    pub fn caller_tag(&self) -> Option<&'static str> {
+        self.caller_tag
+    }
 }
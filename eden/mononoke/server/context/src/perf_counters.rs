@@ -59,6 +59,8 @@ define_perf_counters! {
         BlobPutsShardAccessWait,
         BlobPutsMaxLatency,
         BlobPutsDeduplicated,
+        BlobRetries,
+        BlobRetriesBudgetExhausted,
         BytesSent,
         CachelibHits,
         CachelibMisses,
@@ -93,6 +95,7 @@ define_perf_counters! {
         SkiplistSkippedGenerations,
         SqlReadsMaster,
         SqlReadsReplica,
+        SqlSlowQueries,
         SqlWrites,
         SumManifoldPollTime,
         UndesiredTreeFetch,
@@ -149,6 +152,8 @@ impl PerfCounterType {
             | BlobPutsAccessWait
             | BlobPutsShardAccessWait
             | BlobPutsDeduplicated
+            | BlobRetries
+            | BlobRetriesBudgetExhausted
             | BytesSent
             | CachelibHits
             | CachelibMisses
@@ -182,6 +187,7 @@ impl PerfCounterType {
             | SkiplistSkippedGenerations
             | SqlReadsMaster
             | SqlReadsReplica
+            | SqlSlowQueries
             | SqlWrites
             | SumManifoldPollTime
             | UndesiredTreeFetch
@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Data cached for the duration of a request, shared by all the contexts derived from the one the
+/// request was started with. Entries are keyed by type, so each crate that caches something here
+/// uses its own type and can't clash with the others.
+#[derive(Clone, Default)]
+pub struct RequestCache {
+    entries: Arc<Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>>,
+}
+
+impl RequestCache {
+    /// The `T` cached for this request, created with its `Default` the first time it is asked for.
+    pub fn get_or_default<T: Any + Default + Send + Sync>(&self) -> Arc<T> {
+        let entry = self
+            .entries
+            .lock()
+            .expect("poisoned lock")
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(T::default()))
+            .clone();
+        entry
+            .downcast()
+            .expect("request cache entries are keyed by their type")
+    }
+
+    /// The `T` cached for this request, if anything created it already.
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        let entry = self
+            .entries
+            .lock()
+            .expect("poisoned lock")
+            .get(&TypeId::of::<T>())?
+            .clone();
+        Some(
+            entry
+                .downcast()
+                .expect("request cache entries are keyed by their type"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    #[test]
+    fn test_shared_between_clones() {
+        let cache = RequestCache::default();
+        assert!(cache.get::<Counter>().is_none());
+
+        cache
+            .get_or_default::<Counter>()
+            .0
+            .fetch_add(1, Ordering::Relaxed);
+        let clone = cache.clone();
+        clone
+            .get_or_default::<Counter>()
+            .0
+            .fetch_add(1, Ordering::Relaxed);
+
+        let counter = cache.get::<Counter>().expect("counter was created");
+        assert_eq!(counter.0.load(Ordering::Relaxed), 2);
+        assert!(RequestCache::default().get::<Counter>().is_none());
+    }
+}
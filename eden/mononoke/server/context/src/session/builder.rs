@@ -7,14 +7,16 @@
 
 use async_limiter::AsyncLimiter;
 use fbinit::FacebookInit;
-use load_limiter::BoxLoadLimiter;
+use load_limiter::{ArcLoadLimiter, BoxLoadLimiter};
 use ratelimit_meter::{algorithms::LeakyBucket, DirectRateLimiter};
 use sshrelay::Metadata;
 use std::convert::TryInto;
 use std::num::NonZeroU32;
 use std::sync::Arc;
 
-use super::{SessionClass, SessionContainer, SessionContainerInner};
+use super::{
+    Cancellation, CpuTime, SessionClass, SessionContainer, SessionContainerInner, TrafficClass,
+};
 
 pub struct SessionContainerBuilder {
     fb: FacebookInit,
@@ -36,21 +38,32 @@ impl SessionContainerBuilder {
             fb,
             inner: SessionContainerInner {
                 metadata: Metadata::default(),
+                traffic_class: TrafficClass::Interactive,
                 load_limiter: None,
                 blobstore_write_limiter: None,
                 blobstore_read_limiter: None,
+                cancellation: Cancellation::new(),
+                cpu_time: CpuTime::default(),
             },
             session_class: SessionClass::UserWaiting,
         }
     }
 
     pub fn metadata(mut self, value: Metadata) -> Self {
+        self.inner.traffic_class = TrafficClass::from_identities(value.identities());
         self.inner.metadata = value;
         self
     }
 
     pub fn load_limiter(mut self, value: impl Into<Option<BoxLoadLimiter>>) -> Self {
-        self.inner.load_limiter = value.into();
+        self.inner.load_limiter = value.into().map(ArcLoadLimiter::from);
+        self
+    }
+
+    /// Use the load limiter of another session, for the load of both to count against the same
+    /// budgets.
+    pub fn shared_load_limiter(mut self, value: Option<ArcLoadLimiter>) -> Self {
+        self.inner.load_limiter = value;
         self
     }
 
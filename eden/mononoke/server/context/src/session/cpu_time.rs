@@ -0,0 +1,75 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! CPU time accounting for sessions. Egress bytes are a poor proxy for the cost of commands that
+//! are CPU heavy but send little back (e.g. known or getbundle discovery), so the CPU time the
+//! session spends being polled is tracked as well, and counted as load once the request is done.
+//!
+//! This is the CPU time of the threads that poll the futures accounted to the session, so work
+//! that the session spawns off as separate tasks is only counted if it is accounted too.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use pin_project::pin_project;
+
+fn thread_cpu_time() -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // This can't fail for the current thread on the platforms we run on, and if it did the
+    // session would only be under-accounted.
+    unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+/// Total CPU time of a session so far, shared with the futures that are accounted to it.
+#[derive(Clone, Default)]
+pub(crate) struct CpuTime(Arc<AtomicU64>);
+
+impl CpuTime {
+    pub(crate) fn get(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn account<F: Future>(&self, future: F) -> CpuTimed<F> {
+        CpuTimed {
+            future,
+            total: self.clone(),
+        }
+    }
+}
+
+#[pin_project]
+pub struct CpuTimed<F> {
+    #[pin]
+    future: F,
+    total: CpuTime,
+}
+
+impl<F: Future> Future for CpuTimed<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        let start = thread_cpu_time();
+        let res = this.future.poll(cx);
+        let nanos = thread_cpu_time()
+            .checked_sub(start)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+
+        this.total.0.fetch_add(nanos, Ordering::Relaxed);
+        res
+    }
+}
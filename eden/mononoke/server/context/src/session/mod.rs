@@ -7,20 +7,30 @@
 
 use async_limiter::AsyncLimiter;
 use fbinit::FacebookInit;
-use load_limiter::{BoxLoadLimiter, LoadCost, LoadLimiter, Metric, ThrottleReason};
-use permission_checker::MononokeIdentitySetExt;
+use futures::{
+    channel::oneshot,
+    future::{self, Either, Future, FutureExt, Shared},
+};
+use load_limiter::{ArcLoadLimiter, LoadCost, LoadLimiter, Metric, ThrottleReason};
+use permission_checker::{MononokeIdentitySet, MononokeIdentitySetExt};
 use scribe_ext::Scribe;
 use scuba_ext::MononokeScubaSampleBuilder;
 use slog::Logger;
 use sshrelay::Metadata;
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
 use std::time::Duration;
 
 pub use self::builder::SessionContainerBuilder;
+use self::cpu_time::CpuTime;
+pub use self::cpu_time::CpuTimed;
 use crate::core::CoreContext;
 use crate::logging::LoggingContainer;
 
 mod builder;
+mod cpu_time;
 
 #[derive(Clone)]
 pub struct SessionContainer {
@@ -40,11 +50,59 @@ pub enum SessionClass {
     Background,
 }
 
+/// Who the session is serving, for its stats to be kept apart from the other kinds of traffic.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TrafficClass {
+    /// Someone using a repo, whose latency is what the SLOs are about.
+    Interactive,
+    /// Quicksand, i.e. CI, which sends a lot of large requests that would skew the latency of
+    /// the interactive traffic.
+    Quicksand,
+}
+
+impl TrafficClass {
+    pub fn from_identities(identities: &MononokeIdentitySet) -> Self {
+        if identities.is_quicksand() {
+            Self::Quicksand
+        } else {
+            Self::Interactive
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Interactive => "interactive",
+            Self::Quicksand => "quicksand",
+        }
+    }
+}
+
 struct SessionContainerInner {
     metadata: Metadata,
-    load_limiter: Option<BoxLoadLimiter>,
+    traffic_class: TrafficClass,
+    load_limiter: Option<ArcLoadLimiter>,
     blobstore_write_limiter: Option<AsyncLimiter>,
     blobstore_read_limiter: Option<AsyncLimiter>,
+    cancellation: Cancellation,
+    cpu_time: CpuTime,
+}
+
+/// Tracks whether the session was cancelled, e.g. because the client went away.
+struct Cancellation {
+    cancelled: AtomicBool,
+    sender: Mutex<Option<oneshot::Sender<()>>>,
+    receiver: Shared<oneshot::Receiver<()>>,
+}
+
+impl Cancellation {
+    fn new() -> Self {
+        let (sender, receiver) = oneshot::channel();
+        Self {
+            cancelled: AtomicBool::new(false),
+            sender: Mutex::new(Some(sender)),
+            receiver: receiver.shared(),
+        }
+    }
 }
 
 impl SessionContainer {
@@ -89,6 +147,11 @@ impl SessionContainer {
         }
     }
 
+    /// The load limiter itself, for a session that takes over from this one to share its load.
+    pub fn shared_load_limiter(&self) -> Option<ArcLoadLimiter> {
+        self.inner.load_limiter.clone()
+    }
+
     pub fn bump_load(&self, metric: Metric, load: LoadCost) {
         if let Some(limiter) = self.load_limiter() {
             limiter.bump_load(metric, load)
@@ -107,8 +170,12 @@ impl SessionContainer {
         }
     }
 
+    pub fn traffic_class(&self) -> TrafficClass {
+        self.inner.traffic_class
+    }
+
     pub fn is_quicksand(&self) -> bool {
-        self.metadata().identities().is_quicksand()
+        self.traffic_class() == TrafficClass::Quicksand
     }
 
     pub fn is_external_sync(&self) -> bool {
@@ -130,4 +197,80 @@ impl SessionContainer {
     pub fn override_session_class(&mut self, session_class: SessionClass) {
         self.session_class = session_class;
     }
+
+    /// Cancel the session, e.g. because the client went away. Work that outlives the future
+    /// that started it (such as spawned tasks) should check for this and stop early.
+    pub fn cancel(&self) {
+        let cancellation = &self.inner.cancellation;
+        cancellation.cancelled.store(true, Ordering::Relaxed);
+        if let Some(sender) = cancellation.sender.lock().expect("lock poisoned").take() {
+            let _ = sender.send(());
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancellation.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Resolves once the session is cancelled.
+    pub fn cancelled(&self) -> impl Future<Output = ()> + Send + 'static {
+        let receiver = self.inner.cancellation.receiver.clone();
+        async move {
+            // The sender only goes away without sending if the session is dropped without being
+            // cancelled.
+            if receiver.await.is_err() {
+                future::pending::<()>().await
+            }
+        }
+    }
+
+    /// Count the CPU time spent polling `fut` as the session's. This is for the future that drives
+    /// the session and for the work that it spawns off as separate tasks: a future that is polled
+    /// by one that is already accounted would be counted twice.
+    pub fn account_cpu<F: Future>(&self, fut: F) -> CpuTimed<F> {
+        self.inner.cpu_time.account(fut)
+    }
+
+    /// CPU time of the session so far.
+    pub fn cpu_time(&self) -> Duration {
+        self.inner.cpu_time.get()
+    }
+
+    /// Run `fut` to completion, unless the session is cancelled first, in which case `fut` is
+    /// dropped and this returns `None`.
+    pub fn unless_cancelled<F: Future>(&self, fut: F) -> impl Future<Output = Option<F::Output>> {
+        let cancelled = self.cancelled();
+        async move {
+            futures::pin_mut!(fut);
+            futures::pin_mut!(cancelled);
+            match future::select(fut, cancelled).await {
+                Either::Left((output, _)) => Some(output),
+                Either::Right(((), _)) => None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[fbinit::test]
+    fn test_cancel(fb: FacebookInit) {
+        let session = SessionContainer::new_with_defaults(fb);
+        assert!(!session.is_cancelled());
+        assert_eq!(
+            block_on(session.unless_cancelled(future::ready(1))),
+            Some(1)
+        );
+
+        session.clone().cancel();
+        assert!(session.is_cancelled());
+        block_on(session.cancelled());
+        assert_eq!(
+            block_on(session.unless_cancelled(future::pending::<()>())),
+            None
+        );
+    }
 }
@@ -0,0 +1,45 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! The cache epoch is advertised to clients in `hello`, and clients include it in the keys of
+//! the responses they cache, so bumping it invalidates every cache that sits between us and
+//! them, e.g. after fixing data that was served corrupt. The epoch is picked when a session
+//! starts and stays the same for all of it, so that a session never mixes cache generations.
+//!
+//! The epoch comes from the `cache_epoch` tunable, so that all the hosts advertise the same one:
+//! caches are shared between clients that talk to different hosts.
+
+use tunables::tunables;
+
+/// The epoch to advertise to a session that is starting.
+pub fn current() -> u64 {
+    tunables().get_cache_epoch().max(0) as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maplit::hashmap;
+    use tunables::{with_tunables, MononokeTunables};
+
+    fn with_epoch(epoch: i64) -> u64 {
+        let tunables = MononokeTunables::default();
+        tunables.update_ints(&hashmap! {
+            "cache_epoch".to_string() => epoch,
+        });
+        with_tunables(tunables, current)
+    }
+
+    #[test]
+    fn test_current() {
+        assert_eq!(with_tunables(MononokeTunables::default(), current), 0);
+        assert_eq!(with_epoch(3), 3);
+        assert_eq!(with_epoch(4), 4);
+        // An epoch that was set to something invalid doesn't go back to before the first one.
+        assert_eq!(with_epoch(-1), 0);
+    }
+}
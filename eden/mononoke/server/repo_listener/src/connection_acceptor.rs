@@ -47,17 +47,22 @@ use sshrelay::{
 };
 use stats::prelude::*;
 
+use crate::cache_epoch;
 use crate::errors::ErrorKind;
 use crate::http_service::MononokeHttpService;
 use crate::repo_handlers::RepoHandler;
 use crate::request_handler::{create_conn_logger, request_handler};
 use crate::security_checker::ConnectionsSecurityChecker;
+use crate::session_resumption::{SessionResumptionCache, EXPIRY_SWEEP_INTERVAL};
+use crate::shadowing::Shadowing;
+use crate::socket_options::set_socket_options;
 use crate::stream::QuietShutdownStream;
 
 define_stats! {
     prefix = "mononoke.connection_acceptor";
     http_accepted: timeseries(Sum),
     hgcli_accepted: timeseries(Sum),
+    socket_options_failed: timeseries(Sum),
 }
 
 pub trait MononokeStream: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static {}
@@ -98,8 +103,10 @@ pub async fn connection_acceptor(
     edenapi: EdenApi,
     will_exit: Arc<AtomicBool>,
     config_store: &ConfigStore,
+    shadowing: Option<Shadowing>,
 ) -> Result<()> {
     let enable_http_control_api = common_config.enable_http_control_api;
+    let tcp_socket_options = common_config.tcp_socket_options.clone();
 
     let security_checker =
         ConnectionsSecurityChecker::new(fb, common_config, &repo_handlers, &root_log).await?;
@@ -118,6 +125,7 @@ pub async fn connection_acceptor(
         tls_acceptor,
         repo_handlers,
         security_checker,
+        session_resumption: SessionResumptionCache::new(),
         load_limiter,
         scribe,
         logger: root_log.clone(),
@@ -126,6 +134,21 @@ pub async fn connection_acceptor(
         server_hostname: get_hostname().unwrap_or_else(|_| "unknown_hostname".to_string()),
         will_exit,
         config_store: config_store.clone(),
+        shadowing: shadowing.map(Arc::new),
+    });
+
+    // Sessions that are never resumed would otherwise be kept for as long as the server runs.
+    tokio::spawn({
+        let acceptor = Arc::downgrade(&acceptor);
+        async move {
+            loop {
+                tokio::time::delay_for(EXPIRY_SWEEP_INTERVAL).await;
+                match acceptor.upgrade() {
+                    Some(acceptor) => acceptor.session_resumption.remove_expired(),
+                    None => break,
+                }
+            }
+        }
     });
 
     loop {
@@ -136,6 +159,12 @@ pub async fn connection_acceptor(
             },
             sock_tuple = listener.accept().fuse() => match sock_tuple {
                 Ok((stream, addr)) => {
+                    // The connection still works without its options, only it may linger
+                    // longer once the client is gone.
+                    if let Err(e) = set_socket_options(&stream, &tcp_socket_options) {
+                        STATS::socket_options_failed.add_value(1);
+                        warn!(root_log, "Failed to set socket options for {}: {}", addr, e);
+                    }
                     let conn = PendingConnection { acceptor: acceptor.clone(), addr };
                     let task = handle_connection(conn.clone(), stream);
                     conn.spawn_task(task, "Failed to handle_connection");
@@ -154,6 +183,7 @@ pub struct Acceptor {
     pub tls_acceptor: SslAcceptor,
     pub repo_handlers: HashMap<String, RepoHandler>,
     pub security_checker: ConnectionsSecurityChecker,
+    pub session_resumption: SessionResumptionCache,
     pub load_limiter: Option<LoadLimiterEnvironment>,
     pub scribe: Scribe,
     pub logger: Logger,
@@ -162,6 +192,7 @@ pub struct Acceptor {
     pub server_hostname: String,
     pub will_exit: Arc<AtomicBool>,
     pub config_store: ConfigStore,
+    pub shadowing: Option<Arc<Shadowing>>,
 }
 
 /// Details for a socket we've just opened.
@@ -331,6 +362,7 @@ pub async fn handle_wireproto(
         logger,
         keep_alive,
         join_handle,
+        client_closed,
     } = channels;
 
     if metadata.client_debug() {
@@ -355,10 +387,14 @@ pub async fn handle_wireproto(
         reponame,
         &conn.pending.acceptor.repo_handlers,
         &conn.pending.acceptor.security_checker,
+        &conn.pending.acceptor.session_resumption,
+        cache_epoch::current(),
         stdio,
+        client_closed,
         conn.pending.acceptor.load_limiter.clone(),
         conn.pending.addr.ip(),
         conn.pending.acceptor.scribe.clone(),
+        &conn.pending.acceptor.will_exit,
     )
     .await
     .context("Failed to execute request_handler");
@@ -399,6 +435,8 @@ pub struct ChannelConn {
     logger: Logger,
     keep_alive: AbortHandle,
     join_handle: JoinHandle<Result<(), io::Error>>,
+    /// Resolves once nothing more can be sent to the client, e.g. because it went away.
+    client_closed: oneshot::Receiver<()>,
 }
 
 impl ChannelConn {
@@ -417,7 +455,7 @@ impl ChannelConn {
             }
         }));
 
-        let (stdout, stderr, keep_alive, join_handle) = {
+        let (stdout, stderr, keep_alive, join_handle, client_closed) = {
             let (otx, orx) = mpsc::channel(1);
             let (etx, erx) = mpsc::unbounded();
             let (ktx, krx) = mpsc::unbounded();
@@ -454,10 +492,16 @@ impl ChannelConn {
             // spawn a task for sending keepalive messages
             tokio::spawn(keep_alive_sender);
 
-            // spawn a task for forwarding stdout/err into stream
-            let join_handle = tokio::spawn(fwd);
+            // spawn a task for forwarding stdout/err into stream. It only finishes early if
+            // writing to the client fails, so let the request handler know when that happens.
+            let (closed_tx, closed_rx) = oneshot::channel();
+            let join_handle = tokio::spawn(async move {
+                let res = fwd.await;
+                let _ = closed_tx.send(());
+                res
+            });
 
-            (otx, etx, keep_alive_abort, join_handle)
+            (otx, etx, keep_alive_abort, join_handle, closed_rx)
         };
 
         let logger = create_conn_logger(stderr.clone(), None, None);
@@ -469,8 +513,24 @@ impl ChannelConn {
             logger,
             keep_alive,
             join_handle,
+            client_closed,
         }
     }
+
+    /// Copy everything the client sends to `tee`, for as long as it accepts it. The client is
+    /// never held up by `tee`: once it is full, nothing more is copied to it, since what it
+    /// gets would have a gap.
+    pub fn tee_stdin(&mut self, tee: futures::channel::mpsc::Sender<Bytes>) {
+        let stdin = std::mem::replace(&mut self.stdin, Box::new(stream::empty()));
+        let mut tee = Some(tee);
+        self.stdin = Box::new(stdin.inspect(move |chunk| {
+            if let Some(sender) = tee.as_mut() {
+                if sender.try_send(chunk.clone()).is_err() {
+                    tee = None;
+                }
+            }
+        }));
+    }
 }
 
 async fn try_convert_preamble_to_metadata(
@@ -542,7 +602,8 @@ async fn try_convert_preamble_to_metadata(
             .unwrap_or_default(),
         Some(client_ip),
     )
-    .await)
+    .await
+    .set_resumption_token(preamble.resumption_token().map(String::from)))
 }
 
 // TODO(stash): T33775046 we had to chunk responses because hgcli
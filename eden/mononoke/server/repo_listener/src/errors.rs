@@ -18,4 +18,6 @@ pub enum ErrorKind {
     AuthorizationFailed,
     #[error("Large repo not found: {0}")]
     LargeRepoNotFound(RepositoryId),
+    #[error("Client disconnected before the request completed")]
+    ClientDisconnected,
 }
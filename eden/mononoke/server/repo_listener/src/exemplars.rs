@@ -0,0 +1,212 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Latency histograms that keep, for each bucket, the session of its slowest recent sample, and
+//! can be exported in the OpenMetrics text format with those sessions as exemplars. That lets a
+//! dashboard go from a latency spike to the scuba logs of a session that was in it.
+//!
+//! The histograms of `define_stats` can't carry exemplars, so these mirror the ones of the
+//! request handler, with the same buckets.
+
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+
+// After this long, an exemplar is replaced by the next sample in its bucket even if that sample
+// was faster, so that exemplars point to sessions that still have logs around.
+const EXEMPLAR_MAX_AGE: Duration = Duration::from_secs(300);
+
+struct Exemplar {
+    value_ms: u64,
+    trace_id: String,
+    recorded_at: SystemTime,
+}
+
+struct HistogramData {
+    // One count per bucket, the last one being the +Inf bucket.
+    counts: Vec<u64>,
+    sum_ms: u64,
+    exemplars: Vec<Option<Exemplar>>,
+}
+
+pub struct ExemplarHistogram {
+    name: &'static str,
+    bucket_width_ms: u64,
+    data: Mutex<HistogramData>,
+}
+
+impl ExemplarHistogram {
+    /// The `name` has to end with `_milliseconds`, as OpenMetrics requires of the name of a
+    /// metric with a unit.
+    fn new(name: &'static str, bucket_width_ms: u64, max_ms: u64) -> Self {
+        debug_assert!(name.ends_with("_milliseconds"));
+        let buckets = (max_ms / bucket_width_ms) as usize + 1;
+        Self {
+            name,
+            bucket_width_ms,
+            data: Mutex::new(HistogramData {
+                counts: vec![0; buckets],
+                sum_ms: 0,
+                exemplars: (0..buckets).map(|_| None).collect(),
+            }),
+        }
+    }
+
+    pub fn record(&self, value_ms: u64, trace_id: &str) {
+        let now = SystemTime::now();
+        let mut data = self.data.lock().expect("lock poisoned");
+        // Buckets are inclusive of their upper bound.
+        let bucket = ((value_ms.saturating_sub(1) / self.bucket_width_ms) as usize)
+            .min(data.counts.len() - 1);
+        data.counts[bucket] += 1;
+        data.sum_ms += value_ms;
+
+        let replace = match &data.exemplars[bucket] {
+            None => true,
+            Some(exemplar) => {
+                value_ms >= exemplar.value_ms
+                    || now
+                        .duration_since(exemplar.recorded_at)
+                        .map_or(false, |age| age > EXEMPLAR_MAX_AGE)
+            }
+        };
+        if replace {
+            data.exemplars[bucket] = Some(Exemplar {
+                value_ms,
+                trace_id: trace_id.to_string(),
+                recorded_at: now,
+            });
+        }
+    }
+
+    fn write_openmetrics(&self, out: &mut String) {
+        let data = self.data.lock().expect("lock poisoned");
+        let _ = writeln!(out, "# TYPE {} histogram", self.name);
+        let _ = writeln!(out, "# UNIT {} milliseconds", self.name);
+
+        let mut cumulative = 0;
+        let last = data.counts.len() - 1;
+        for (bucket, count) in data.counts.iter().enumerate() {
+            cumulative += count;
+            let le = if bucket == last {
+                "+Inf".to_string()
+            } else {
+                ((bucket as u64 + 1) * self.bucket_width_ms).to_string()
+            };
+            let _ = write!(out, "{}_bucket{{le=\"{}\"}} {}", self.name, le, cumulative);
+            if let Some(exemplar) = &data.exemplars[bucket] {
+                let timestamp = exemplar
+                    .recorded_at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+                let _ = write!(
+                    out,
+                    " # {{trace_id=\"{}\"}} {} {:.3}",
+                    escape_label_value(&exemplar.trace_id),
+                    exemplar.value_ms,
+                    timestamp
+                );
+            }
+            out.push('\n');
+        }
+        let _ = writeln!(out, "{}_sum {}", self.name, data.sum_ms);
+        let _ = writeln!(out, "{}_count {}", self.name, cumulative);
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+lazy_static! {
+    pub static ref WIREPROTO_MS: ExemplarHistogram = ExemplarHistogram::new(
+        "mononoke_request_handler_wireproto_milliseconds",
+        500,
+        100_000
+    );
+    pub static ref GETBUNDLE_MS: ExemplarHistogram = ExemplarHistogram::new(
+        "mononoke_request_handler_getbundle_milliseconds",
+        500,
+        100_000
+    );
+    pub static ref GETTREEPACK_MS: ExemplarHistogram = ExemplarHistogram::new(
+        "mononoke_request_handler_gettreepack_milliseconds",
+        100,
+        20_000
+    );
+    pub static ref GETPACK_MS: ExemplarHistogram =
+        ExemplarHistogram::new("mononoke_request_handler_getpack_milliseconds", 100, 20_000);
+    pub static ref GETCOMMITDATA_MS: ExemplarHistogram = ExemplarHistogram::new(
+        "mononoke_request_handler_getcommitdata_milliseconds",
+        100,
+        20_000
+    );
+    pub static ref UNBUNDLE_MS: ExemplarHistogram = ExemplarHistogram::new(
+        "mononoke_request_handler_unbundle_milliseconds",
+        500,
+        100_000
+    );
+    pub static ref OTHER_COMMAND_MS: ExemplarHistogram = ExemplarHistogram::new(
+        "mononoke_request_handler_other_command_milliseconds",
+        100,
+        20_000
+    );
+}
+
+/// All the histograms, in the OpenMetrics text format.
+pub fn render_openmetrics() -> String {
+    let mut out = String::new();
+    for histogram in [
+        &*WIREPROTO_MS,
+        &*GETBUNDLE_MS,
+        &*GETTREEPACK_MS,
+        &*GETPACK_MS,
+        &*GETCOMMITDATA_MS,
+        &*UNBUNDLE_MS,
+        &*OTHER_COMMAND_MS,
+    ]
+    .iter()
+    {
+        histogram.write_openmetrics(&mut out);
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_openmetrics_exemplars() {
+        let histogram = ExemplarHistogram::new("test_milliseconds", 10, 20);
+        histogram.record(5, "fast");
+        histogram.record(7, "slower");
+        histogram.record(3, "fastest");
+        histogram.record(100, "way\"off");
+
+        let mut out = String::new();
+        histogram.write_openmetrics(&mut out);
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[0], "# TYPE test_milliseconds histogram");
+        assert_eq!(lines[1], "# UNIT test_milliseconds milliseconds");
+        assert!(lines[2]
+            .starts_with("test_milliseconds_bucket{le=\"10\"} 3 # {trace_id=\"slower\"} 7 "));
+        assert_eq!(lines[3], "test_milliseconds_bucket{le=\"20\"} 3");
+        assert!(lines[4].starts_with(
+            "test_milliseconds_bucket{le=\"+Inf\"} 4 # {trace_id=\"way\\\"off\"} 100 "
+        ));
+        assert_eq!(lines[5], "test_milliseconds_sum 115");
+        assert_eq!(lines[6], "test_milliseconds_count 4");
+    }
+}
@@ -5,14 +5,19 @@
  * GNU General Public License version 2.
  */
 
-use anyhow::{Context, Error, Result};
+use anyhow::{anyhow, Context, Error, Result};
+use circuitbreakerblob::circuit_breaker_states;
+use cmdlib::args::get_observability_context;
 use futures::future::{BoxFuture, FutureExt};
 use gotham_ext::socket_data::TlsSocketData;
 use http::{HeaderMap, HeaderValue, Method, Request, Response, Uri};
 use hyper::{service::Service, Body};
+use lazy_static::lazy_static;
+use redactedblobstore::recent_redaction_hits;
 use sha1::{Digest, Sha1};
-use slog::{debug, error, Logger};
+use slog::{debug, error, info, Level, Logger};
 use sshrelay::Metadata;
+use std::collections::HashMap;
 use std::io::Cursor;
 use std::marker::PhantomData;
 use std::str::FromStr;
@@ -20,16 +25,37 @@ use std::sync::atomic::Ordering;
 use std::task;
 use thiserror::Error;
 use tokio::io::AsyncReadExt;
-use tunables::{force_update_tunables, tunables};
+use tunables::{
+    force_update_tunables, tunables, tunables_with_overrides, with_tunables_async, DerivedTunable,
+    MononokeTunables,
+};
 
 use crate::connection_acceptor::{
     self, AcceptedConnection, Acceptor, ChannelConn, FramedConn, MononokeStream,
 };
+use crate::load_shedding::{self, InFlightRequest};
+use crate::shadowing;
 
 const HEADER_CLIENT_DEBUG: &str = "x-client-debug";
 const HEADER_WEBSOCKET_KEY: &str = "sec-websocket-key";
 const HEADER_WEBSOCKET_ACCEPT: &str = "sec-websocket-accept";
 const HEADER_MONONOKE_HOST: &str = "x-mononoke-host";
+const HEADER_TUNABLES_OVERRIDE: &str = "x-mononoke-tunables-override";
+
+const DEFAULT_REDACTION_HITS_LIMIT: usize = 100;
+
+lazy_static! {
+    static ref TUNABLES_OVERRIDE_ALLOWLIST: DerivedTunable<Vec<String>> =
+        DerivedTunable::new(&["http_tunables_override_allowlist"], |tunables| {
+            tunables
+                .get_http_tunables_override_allowlist()
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect()
+        });
+}
 
 // See https://tools.ietf.org/html/rfc6455#section-1.3
 const WEBSOCKET_MAGIC_KEY: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
@@ -50,6 +76,12 @@ pub enum HttpError {
 
     #[error("Internal server error")]
     InternalServerError(#[source] Error),
+
+    #[error("Service unavailable: {message}")]
+    ServiceUnavailable {
+        message: &'static str,
+        retry_after_secs: u64,
+    },
 }
 
 impl HttpError {
@@ -64,6 +96,7 @@ impl HttpError {
             Self::NotFound => http::StatusCode::NOT_FOUND,
             Self::MethodNotAllowed => http::StatusCode::METHOD_NOT_ALLOWED,
             Self::InternalServerError(..) => http::StatusCode::INTERNAL_SERVER_ERROR,
+            Self::ServiceUnavailable { .. } => http::StatusCode::SERVICE_UNAVAILABLE,
         };
 
         let body = match self {
@@ -72,9 +105,17 @@ impl HttpError {
             Self::NotFound => Body::empty(),
             Self::MethodNotAllowed => Body::empty(),
             Self::InternalServerError(ref e) => Body::from(format!("{:#}", e)),
+            Self::ServiceUnavailable { message, .. } => Body::from(*message),
         };
 
-        Response::builder().status(status).body(body)
+        let mut res = Response::builder().status(status);
+        if let Self::ServiceUnavailable {
+            retry_after_secs, ..
+        } = self
+        {
+            res = res.header(http::header::RETRY_AFTER, *retry_after_secs);
+        }
+        res.body(body)
     }
 }
 
@@ -125,12 +166,14 @@ where
             .map_err(HttpError::BadRequest)?;
 
         if upgrade == Some("websocket") {
+            shed_if_overloaded("wireproto")?;
             return self
                 .handle_websocket_request(&req.uri, &req.headers, body)
                 .await;
         }
 
         if req.uri.path() == "/netspeedtest" {
+            shed_if_overloaded("netspeedtest")?;
             return crate::netspeedtest::handle(req.method, &req.headers, body).await;
         }
 
@@ -138,8 +181,19 @@ where
             return self.handle_control_request(req.method, path).await;
         }
 
-        if req.method == Method::GET && (req.uri.path() == "/" || req.uri.path() == "/health_check")
-        {
+        if req.method == Method::GET && req.uri.path() == "/exemplars" {
+            return self.handle_exemplars_request();
+        }
+
+        if req.method == Method::GET && req.uri.path() == "/redaction_hits" {
+            return self.handle_redaction_hits_request(req.uri.query());
+        }
+
+        if req.method == Method::GET && req.uri.path() == "/circuit_breakers" {
+            return self.handle_circuit_breakers_request();
+        }
+
+        if is_health_check(&req) {
             let res = if self.acceptor().will_exit.load(Ordering::Relaxed) {
                 "EXITING"
             } else {
@@ -161,6 +215,7 @@ where
             .and_then(|pq| pq.as_str().strip_prefix("/edenapi"));
 
         if let Some(edenapi_path_and_query) = edenapi_path_and_query {
+            shed_if_overloaded("edenapi")?;
             let pq = http::uri::PathAndQuery::from_str(edenapi_path_and_query)
                 .context("Error translating EdenAPI request path")
                 .map_err(HttpError::internal)?;
@@ -196,8 +251,18 @@ where
         let debug = headers.get(HEADER_CLIENT_DEBUG).is_some();
 
         let this = self.clone();
+        let shadow = self
+            .acceptor()
+            .shadowing
+            .as_ref()
+            .filter(|shadowing| shadowing.sample())
+            .and_then(|shadowing| shadowing.shadow_wireproto(self.logger(), uri.path(), headers));
 
+        // The request is over once the connection is upgraded, but the session it starts is what
+        // loads the server.
+        let in_flight = InFlightRequest::start();
         let fut = async move {
+            let _in_flight = in_flight;
             let io = body
                 .on_upgrade()
                 .await
@@ -212,7 +277,10 @@ where
             let rx = AsyncReadExt::chain(Cursor::new(read_buf), rx);
 
             let conn = FramedConn::setup(rx, tx);
-            let channels = ChannelConn::setup(conn);
+            let mut channels = ChannelConn::setup(conn);
+            if let Some(shadow) = shadow {
+                channels.tee_stdin(shadow);
+            }
 
             connection_acceptor::handle_wireproto(this.conn, channels, reponame, metadata, debug)
                 .await
@@ -260,9 +328,144 @@ where
             return Ok(ok);
         }
 
+        if let Some(module_and_level) = path.strip_prefix("/log_level/") {
+            return self.handle_log_level_request(module_and_level);
+        }
+
         Err(HttpError::NotFound)
     }
 
+    /// Set the log level of a module (and its submodules) as `<module>/<level>`, e.g.
+    /// `repo_client/debug`, or go back to the default level with `<module>/reset`. Responds with
+    /// all the levels set this way.
+    fn handle_log_level_request(
+        &self,
+        module_and_level: &str,
+    ) -> Result<Response<Body>, HttpError> {
+        let (module, level) = match module_and_level.rsplitn(2, '/').collect::<Vec<_>>()[..] {
+            [level, module] if !module.is_empty() => (module, level),
+            _ => {
+                return Err(HttpError::BadRequest(anyhow!(
+                    "Expected <module>/<level>, got {}",
+                    module_and_level
+                )));
+            }
+        };
+
+        let level = match level {
+            "reset" => None,
+            level => Some(
+                Level::from_str(level)
+                    .map_err(|_| HttpError::BadRequest(anyhow!("Invalid log level: {}", level)))?,
+            ),
+        };
+
+        let module_levels = get_observability_context()
+            .ok_or_else(|| HttpError::internal(anyhow!("Logging is not initialized")))?
+            .module_levels();
+        module_levels.set(module, level);
+        info!(
+            self.logger(),
+            "Log level for {} set to {:?} via the control API", module, level
+        );
+
+        let body = module_levels
+            .get_all()
+            .into_iter()
+            .map(|(module, level)| format!("{} {}\n", module, level.as_str()))
+            .collect::<String>();
+
+        Response::builder()
+            .status(http::StatusCode::OK)
+            .body(body.into())
+            .map_err(HttpError::internal)
+    }
+
+    /// Latency histograms of the wireproto commands, with the sessions of their slowest recent
+    /// samples as exemplars, in the OpenMetrics text format.
+    fn handle_exemplars_request(&self) -> Result<Response<Body>, HttpError> {
+        if !self.acceptor().enable_http_control_api {
+            return Err(HttpError::Forbidden);
+        }
+
+        Response::builder()
+            .status(http::StatusCode::OK)
+            .header(
+                http::header::CONTENT_TYPE,
+                "application/openmetrics-text; version=1.0.0; charset=utf-8",
+            )
+            .body(crate::exemplars::render_openmetrics().into())
+            .map_err(HttpError::internal)
+    }
+
+    /// The most recent accesses to redacted content, newest first, as one JSON object per line.
+    /// Takes `repo=<name>` to only get those of one repo, and `limit=<n>`.
+    fn handle_redaction_hits_request(
+        &self,
+        query: Option<&str>,
+    ) -> Result<Response<Body>, HttpError> {
+        if !self.acceptor().enable_http_control_api {
+            return Err(HttpError::Forbidden);
+        }
+
+        let mut repo_id = None;
+        let mut limit = DEFAULT_REDACTION_HITS_LIMIT;
+        for param in query.unwrap_or_default().split('&') {
+            match param.splitn(2, '=').collect::<Vec<_>>()[..] {
+                [""] => {}
+                ["repo", name] => {
+                    let handler =
+                        self.acceptor().repo_handlers.get(name).ok_or_else(|| {
+                            HttpError::BadRequest(anyhow!("Unknown repo: {}", name))
+                        })?;
+                    repo_id = Some(handler.repo.blobrepo().get_repoid());
+                }
+                ["limit", n] => {
+                    limit = n
+                        .parse()
+                        .map_err(|_| HttpError::BadRequest(anyhow!("Invalid limit: {}", n)))?;
+                }
+                _ => {
+                    return Err(HttpError::BadRequest(anyhow!(
+                        "Invalid query parameter: {}",
+                        param
+                    )));
+                }
+            }
+        }
+
+        let mut body = String::new();
+        for hit in recent_redaction_hits(repo_id, limit) {
+            body.push_str(&serde_json::to_string(&hit).map_err(HttpError::internal)?);
+            body.push('\n');
+        }
+
+        Response::builder()
+            .status(http::StatusCode::OK)
+            .header(http::header::CONTENT_TYPE, "application/x-ndjson")
+            .body(body.into())
+            .map_err(HttpError::internal)
+    }
+
+    /// The state of the circuit breakers of the blobstore backends, as one JSON object per line.
+    fn handle_circuit_breakers_request(&self) -> Result<Response<Body>, HttpError> {
+        if !self.acceptor().enable_http_control_api {
+            return Err(HttpError::Forbidden);
+        }
+
+        let mut body = String::new();
+        for state in circuit_breaker_states() {
+            body.push_str(&serde_json::to_string(&state).map_err(HttpError::internal)?);
+            body.push('\n');
+        }
+
+        Response::builder()
+            .status(http::StatusCode::OK)
+            .header(http::header::CONTENT_TYPE, "application/x-ndjson")
+            .body(body.into())
+            .map_err(HttpError::internal)
+    }
+
     async fn handle_eden_api_request(
         &self,
         mut req: http::request::Parts,
@@ -270,13 +473,29 @@ where
         body: Body,
     ) -> Result<Response<Body>, HttpError> {
         if tunables().get_disable_http_service_edenapi() {
-            let res = Response::builder()
-                .status(http::StatusCode::SERVICE_UNAVAILABLE)
-                .body("EdenAPI service is killswitched".into())
-                .map_err(HttpError::internal)?;
-            return Ok(res);
+            return Err(HttpError::ServiceUnavailable {
+                message: "EdenAPI service is killswitched",
+                retry_after_secs: load_shedding::jittered_retry_after_secs(),
+            });
         }
 
+        let body = match &self.acceptor().shadowing {
+            Some(shadowing)
+                if shadowing::is_read_only_edenapi(&req.method, pq.as_str())
+                    && shadowing.sample() =>
+            {
+                let path_and_query = req.uri.path_and_query().map_or("/", |pq| pq.as_str());
+                shadowing.shadow_edenapi(
+                    self.logger(),
+                    &req.method,
+                    path_and_query,
+                    &req.headers,
+                    body,
+                )
+            }
+            _ => body,
+        };
+
         let mut uri_parts = req.uri.into_parts();
 
         uri_parts.path_and_query = Some(pq);
@@ -304,6 +523,37 @@ where
         Ok(res)
     }
 
+    /// Trusted clients can override some tunables for the duration of a single request, by passing
+    /// a comma separated list of `name=value` pairs. Only tunables in the allowlist (which is
+    /// itself a tunable) may be overridden.
+    fn tunables_override(
+        &self,
+        headers: &HeaderMap<HeaderValue>,
+    ) -> Result<Option<MononokeTunables>, HttpError> {
+        let header = match headers.get(HEADER_TUNABLES_OVERRIDE) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        if !self.conn.is_trusted {
+            return Err(HttpError::Forbidden);
+        }
+
+        let header = header
+            .to_str()
+            .with_context(|| format!("Invalid header: {}", HEADER_TUNABLES_OVERRIDE))
+            .map_err(HttpError::BadRequest)?;
+
+        let allowlist = TUNABLES_OVERRIDE_ALLOWLIST.get();
+        let allowlist = allowlist.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let overrides =
+            parse_tunables_override(header, &allowlist).map_err(HttpError::BadRequest)?;
+        debug!(self.logger(), "Overriding tunables: {:?}", overrides);
+
+        Ok(Some(tunables_with_overrides(&overrides)))
+    }
+
     fn acceptor(&self) -> &Acceptor {
         &self.conn.pending.acceptor
     }
@@ -331,13 +581,27 @@ where
         async move {
             let (req, body) = req.into_parts();
 
+            // Health checks must get through however loaded the server is, so they don't count
+            // towards the load either.
+            let in_flight = if is_health_check(&req) {
+                None
+            } else {
+                Some(InFlightRequest::start())
+            };
+
             let method = req.method.clone();
             let uri = req.uri.clone();
             debug!(this.logger(), "{} {}", method, uri);
 
-            let res = this
-                .handle(req, body)
-                .await
+            let res = match this.tunables_override(&req.headers) {
+                Ok(Some(overrides)) => {
+                    with_tunables_async(overrides, this.handle(req, body).boxed()).await
+                }
+                Ok(None) => this.handle(req, body).await,
+                Err(e) => Err(e),
+            };
+
+            let res = res
                 .and_then(|mut res| {
                     match HeaderValue::from_str(this.conn.pending.acceptor.server_hostname.as_str())
                     {
@@ -356,22 +620,65 @@ where
                     Ok(res)
                 })
                 .or_else(|e| {
-                    error!(
-                        this.logger(),
-                        "http service error: {} {}: {:#}", method, uri, e
-                    );
+                    // Shed requests are already counted, and logging each of them would only
+                    // add to the load.
+                    if let HttpError::ServiceUnavailable { .. } = e {
+                        debug!(this.logger(), "http service: {} {}: {}", method, uri, e);
+                    } else {
+                        error!(
+                            this.logger(),
+                            "http service error: {} {}: {:#}", method, uri, e
+                        );
+                    }
 
                     e.http_response()
                 });
 
             // NOTE: If we fail to even generate the response here, this will crash
             // serve_connection in Hyper, so we don't actually need to log this here.
-            res
+            match in_flight {
+                Some(in_flight) => res.map(|res| in_flight.until_sent(res)),
+                None => res,
+            }
         }
         .boxed()
     }
 }
 
+fn is_health_check(req: &http::request::Parts) -> bool {
+    req.method == Method::GET && (req.uri.path() == "/" || req.uri.path() == "/health_check")
+}
+
+fn shed_if_overloaded(endpoint: &'static str) -> Result<(), HttpError> {
+    match load_shedding::should_shed(endpoint) {
+        Some(retry_after_secs) => Err(HttpError::ServiceUnavailable {
+            message: "Server is overloaded",
+            retry_after_secs,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn parse_tunables_override(header: &str, allowlist: &[&str]) -> Result<HashMap<String, String>> {
+    header
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = match pair.find('=') {
+                Some(idx) => (pair[..idx].trim(), pair[idx + 1..].trim()),
+                None => anyhow::bail!("Invalid tunable override: {}", pair),
+            };
+
+            if !allowlist.contains(&name) {
+                anyhow::bail!("Tunable may not be overridden: {}", name);
+            }
+
+            Ok((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
 // See https://tools.ietf.org/html/rfc6455#section-1.3
 fn calculate_websocket_accept(headers: &HeaderMap<HeaderValue>) -> String {
     let mut sha1 = Sha1::new();
@@ -9,16 +9,25 @@
 #![feature(never_type)]
 #![recursion_limit = "256"]
 
+mod cache_epoch;
 mod connection_acceptor;
 mod errors;
+mod exemplars;
 mod http_service;
+mod load_shedding;
 mod netspeedtest;
 mod repo_handlers;
 mod request_handler;
 mod security_checker;
+mod session_resumption;
+mod shadowing;
+mod socket_options;
 mod stream;
+mod warm_standby;
 
 pub use crate::connection_acceptor::wait_for_connections_closed;
+pub use crate::shadowing::Shadowing;
+pub use crate::warm_standby::WarmStandby;
 
 use anyhow::{Context as _, Result};
 use blobrepo_factory::ReadOnlyStorage;
@@ -46,17 +55,20 @@ pub async fn create_repo_listeners<'a>(
     fb: FacebookInit,
     common_config: CommonConfig,
     mononoke: Mononoke,
+    read_mirror: Option<Mononoke>,
     mysql_options: &'a MysqlOptions,
     root_log: Logger,
     sockname: String,
     tls_acceptor: SslAcceptor,
     service: ReadyFlagService,
-    terminate_process: oneshot::Receiver<()>,
+    mut terminate_process: oneshot::Receiver<()>,
     config_store: &'a ConfigStore,
     readonly_storage: ReadOnlyStorage,
     scribe: Scribe,
     scuba: &'a MononokeScubaSampleBuilder,
     will_exit: Arc<AtomicBool>,
+    warm_standby: Option<WarmStandby>,
+    shadowing: Option<Shadowing>,
 ) -> Result<()> {
     let load_limiter = {
         let handle = config_store
@@ -91,6 +103,7 @@ pub async fn create_repo_listeners<'a>(
             root_log.new(o!("service" => "edenapi")),
             scuba,
             mononoke,
+            read_mirror,
             will_exit.clone(),
             false,
             None,
@@ -99,6 +112,15 @@ pub async fn create_repo_listeners<'a>(
         .context("Error instantiating EdenAPI")?
     };
 
+    if let Some(warm_standby) = warm_standby {
+        let promoted = warm_standby
+            .run_until_promoted(fb, &handlers, &root_log, &mut terminate_process)
+            .await;
+        if !promoted {
+            return Ok(());
+        }
+    }
+
     debug!(root_log, "Mononoke server is listening on {}", sockname);
     connection_acceptor(
         fb,
@@ -114,6 +136,7 @@ pub async fn create_repo_listeners<'a>(
         edenapi,
         will_exit,
         config_store,
+        shadowing,
     )
     .await
 }
@@ -0,0 +1,147 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Load shedding for http_service. Once too many requests are in flight, new ones get a 503
+//! with a `Retry-After` that grows with how far over the limit the server is. It is jittered so
+//! that the clients that got shed together don't all come back at the same time and overload
+//! the server again.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::stream::StreamExt;
+use http::Response;
+use hyper::{body::HttpBody, Body};
+use lazy_static::lazy_static;
+use rand::{thread_rng, Rng};
+use stats::prelude::*;
+use tunables::tunables;
+
+const DEFAULT_RETRY_AFTER_BASE_SECS: u64 = 1;
+const MAX_RETRY_AFTER_SECS: u64 = 300;
+
+define_stats! {
+    prefix = "mononoke.http_service";
+    shed: dynamic_timeseries("shed.{}", (endpoint: &'static str); Rate, Sum),
+}
+
+lazy_static! {
+    static ref IN_FLIGHT_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+}
+
+/// Counts a request as in flight for as long as it is alive. This has to be until the response has
+/// been sent, not just built, as the body can be streamed for much longer than that.
+pub struct InFlightRequest(());
+
+impl InFlightRequest {
+    pub fn start() -> Self {
+        IN_FLIGHT_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        Self(())
+    }
+
+    /// Keep the request in flight until the body of `res` has been sent, or the client went away.
+    pub fn until_sent(self, res: Response<Body>) -> Response<Body> {
+        if res.body().is_end_stream() {
+            return res;
+        }
+
+        let (parts, body) = res.into_parts();
+        let body = body.map(move |chunk| {
+            let _in_flight = &self;
+            chunk
+        });
+        Response::from_parts(parts, Body::wrap_stream(body))
+    }
+}
+
+impl Drop for InFlightRequest {
+    fn drop(&mut self) {
+        IN_FLIGHT_REQUESTS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn retry_after_base_secs() -> u64 {
+    match tunables().get_http_service_retry_after_base_secs() {
+        secs if secs > 0 => secs as u64,
+        _ => DEFAULT_RETRY_AFTER_BASE_SECS,
+    }
+}
+
+/// How long a client should wait before retrying, in seconds. This is the base delay scaled by
+/// how many times over the limit the server is, and spread over up to twice that by `jitter`,
+/// which is in [0, 1).
+fn retry_after_secs(in_flight: usize, max_in_flight: usize, base_secs: u64, jitter: f64) -> u64 {
+    let overload = (in_flight as f64 / max_in_flight.max(1) as f64).max(1.0);
+    let secs = (base_secs as f64 * overload * (1.0 + jitter)).ceil() as u64;
+    secs.max(1).min(MAX_RETRY_AFTER_SECS)
+}
+
+/// A jittered retry delay for a client that is turned away regardless of the load, e.g. because
+/// the endpoint is killswitched.
+pub fn jittered_retry_after_secs() -> u64 {
+    retry_after_secs(1, 1, retry_after_base_secs(), thread_rng().gen())
+}
+
+/// If the server is overloaded, count the request as shed for `endpoint`, and return how long
+/// the client should wait before retrying.
+pub fn should_shed(endpoint: &'static str) -> Option<u64> {
+    let max_in_flight = tunables().get_http_service_max_in_flight_requests();
+    if max_in_flight <= 0 {
+        return None;
+    }
+    let max_in_flight = max_in_flight as usize;
+
+    let in_flight = IN_FLIGHT_REQUESTS.load(Ordering::Relaxed);
+    if in_flight <= max_in_flight {
+        return None;
+    }
+
+    STATS::shed.add_value(1, (endpoint,));
+    Some(retry_after_secs(
+        in_flight,
+        max_in_flight,
+        retry_after_base_secs(),
+        thread_rng().gen(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_retry_after_secs() {
+        // Just over the limit: the base delay, spread over up to twice that.
+        assert_eq!(retry_after_secs(11, 10, 2, 0.0), 3);
+        assert_eq!(retry_after_secs(10, 10, 2, 0.0), 2);
+        assert_eq!(retry_after_secs(10, 10, 2, 0.99), 4);
+
+        // Further over the limit, clients are asked to wait longer.
+        assert_eq!(retry_after_secs(30, 10, 2, 0.0), 6);
+        assert_eq!(retry_after_secs(30, 10, 2, 0.5), 9);
+
+        // Never less than a second, never more than the maximum.
+        assert_eq!(retry_after_secs(1, 10, 0, 0.0), 1);
+        assert_eq!(retry_after_secs(100_000, 10, 2, 0.5), MAX_RETRY_AFTER_SECS);
+    }
+
+    #[tokio::test]
+    async fn test_until_sent() -> Result<(), hyper::Error> {
+        let in_flight = || IN_FLIGHT_REQUESTS.load(Ordering::Relaxed);
+
+        let res = InFlightRequest::start().until_sent(Response::new(Body::empty()));
+        assert_eq!(in_flight(), 0);
+        drop(res);
+
+        let res = InFlightRequest::start().until_sent(Response::new(Body::from("body")));
+        assert_eq!(in_flight(), 1);
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+        assert_eq!(body.as_ref(), b"body");
+        assert_eq!(in_flight(), 0);
+
+        Ok(())
+    }
+}
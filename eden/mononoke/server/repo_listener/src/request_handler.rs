@@ -6,32 +6,43 @@
  */
 
 use crate::errors::ErrorKind;
+use crate::exemplars;
 use crate::security_checker::ConnectionsSecurityChecker;
+use crate::session_resumption::{ResumedSession, SessionResumptionCache};
 use std::collections::HashMap;
 
 use anyhow::{anyhow, Context, Error, Result};
+use bookmarks_movement::BookmarkMovementError;
 use bytes::Bytes;
-use context::{LoggingContainer, SessionClass, SessionContainer, SessionId};
+use context::{LoggingContainer, SessionClass, SessionContainer, SessionId, TrafficClass};
 use failure_ext::SlogKVError;
 use fbinit::FacebookInit;
-use futures::compat::Future01CompatExt;
+use futures::{
+    channel::oneshot,
+    compat::Future01CompatExt,
+    future::{self, Either},
+};
 use futures_old::{sync::mpsc, Future, Stream};
 use futures_stats::TimedFutureExt;
 use hgproto::{sshproto, HgProtoHandler};
-use load_limiter::{LoadLimiterEnvironment, Metric};
+use load_limiter::{LoadLimiterEnvironment, Metric, ThrottleReason};
 use maplit::{hashmap, hashset};
 use repo_client::RepoClient;
+use repo_read_write_status::RepoWriteError;
 use scribe_ext::Scribe;
-use slog::{self, error, o, Drain, Level, Logger};
+use slog::{self, error, o, warn, Drain, Level, Logger};
 use slog_ext::SimpleFormatWithError;
 use slog_kvfilter::KVFilter;
 use sshrelay::{Priority, SenderBytesWrite, Stdio};
 use stats::prelude::*;
 use std::mem;
 use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use time_ext::DurationExt;
 use tunables::tunables;
+use unbundle::PushRejection;
 
 use crate::repo_handlers::RepoHandler;
 
@@ -39,9 +50,152 @@ define_stats! {
     prefix = "mononoke.request_handler";
     wireproto_ms:
         histogram(500, 0, 100_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    getbundle_ms:
+        histogram(500, 0, 100_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    gettreepack_ms:
+        histogram(100, 0, 20_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    getpack_ms:
+        histogram(100, 0, 20_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    getcommitdata_ms:
+        histogram(100, 0, 20_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    unbundle_ms:
+        histogram(500, 0, 100_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    other_command_ms:
+        histogram(100, 0, 20_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    session_cpu_ms:
+        histogram(100, 0, 20_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     request_success: timeseries(Rate, Sum),
     request_failure: timeseries(Rate, Sum),
+    request_cancelled: timeseries(Rate, Sum),
     request_outcome_permille: timeseries(Average),
+    request_disconnect_reason: dynamic_timeseries("disconnect_reason.{}", (reason: &'static str); Rate, Sum),
+    // Quicksand sessions are kept out of the latencies above, for them to reflect interactive
+    // traffic only.
+    quicksand_wireproto_ms:
+        histogram(500, 0, 100_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    quicksand_session_cpu_ms:
+        histogram(100, 0, 20_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    quicksand_command_ms:
+        dynamic_histogram("quicksand_{}_ms", (command: &'static str); 500, 0, 100_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+}
+
+/// Why a session ended, as recorded in its end-of-session scuba sample.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DisconnectReason {
+    /// The client closed the connection, be it after it was done or in the middle of a command.
+    ClientEof,
+    ServerTimeout,
+    Throttled,
+    Error,
+    /// The server was shutting down.
+    Shutdown,
+}
+
+impl DisconnectReason {
+    fn classify(result: &Result<()>, cancelled: bool, shutting_down: bool) -> Self {
+        let err = match result {
+            Ok(()) => return Self::ClientEof,
+            Err(err) => err,
+        };
+        if cancelled {
+            return Self::ClientEof;
+        }
+        if shutting_down {
+            return Self::Shutdown;
+        }
+        let client_disconnected =
+            err.chain()
+                .any(|cause| match cause.downcast_ref::<ErrorKind>() {
+                    Some(ErrorKind::ClientDisconnected) => true,
+                    _ => false,
+                });
+        if client_disconnected {
+            return Self::ClientEof;
+        }
+        match ClientErrorCode::classify(err) {
+            ClientErrorCode::Throttled => Self::Throttled,
+            ClientErrorCode::Timeout => Self::ServerTimeout,
+            ClientErrorCode::HookRejected
+            | ClientErrorCode::ReadOnly
+            | ClientErrorCode::Internal => Self::Error,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::ClientEof => "client_eof",
+            Self::ServerTimeout => "server_timeout",
+            Self::Throttled => "throttled",
+            Self::Error => "error",
+            Self::Shutdown => "shutdown",
+        }
+    }
+}
+
+/// Why a command failed, as told to the client along with the error. The codes are stable, for
+/// clients to branch on them, and for support to triage failures by them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ClientErrorCode {
+    Throttled,
+    HookRejected,
+    ReadOnly,
+    Timeout,
+    Internal,
+}
+
+impl ClientErrorCode {
+    /// Sessions that end with an error are put down to it by `DisconnectReason` too, so that the
+    /// two agree.
+    fn classify(err: &Error) -> Self {
+        for cause in err.chain() {
+            if cause.is::<ThrottleReason>() || cause.is::<throttledblob::ErrorKind>() {
+                return Self::Throttled;
+            }
+            if let Some(rejection) = cause.downcast_ref::<PushRejection>() {
+                return match rejection {
+                    PushRejection::HooksFailed(_) => Self::HookRejected,
+                    PushRejection::RateLimitExceeded(_) => Self::Throttled,
+                    PushRejection::RepoReadOnly(_) => Self::ReadOnly,
+                };
+            }
+            if let Some(BookmarkMovementError::RepoLocked(_)) =
+                cause.downcast_ref::<BookmarkMovementError>()
+            {
+                return Self::ReadOnly;
+            }
+            // The lock check in the pushrebase transaction fails with this one.
+            if let Some(RepoWriteError::Locked(_)) = cause.downcast_ref::<RepoWriteError>() {
+                return Self::ReadOnly;
+            }
+            if cause.is::<tokio::time::Elapsed>() {
+                return Self::Timeout;
+            }
+        }
+        Self::Internal
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Throttled => "throttled",
+            Self::HookRejected => "hook_rejected",
+            Self::ReadOnly => "read_only",
+            Self::Timeout => "timeout",
+            Self::Internal => "internal",
+        }
+    }
+
+    fn client_message(&self) -> String {
+        let help_url = tunables().get_error_help_url();
+        if help_url.is_empty() {
+            format!("Error code: {}", self.as_str())
+        } else {
+            format!(
+                "Error code: {}. For help, see {}",
+                self.as_str(),
+                help_url.replace("{code}", self.as_str())
+            )
+        }
+    }
 }
 
 pub async fn request_handler(
@@ -49,10 +203,14 @@ pub async fn request_handler(
     reponame: String,
     repo_handlers: &HashMap<String, RepoHandler>,
     security_checker: &ConnectionsSecurityChecker,
+    session_resumption: &SessionResumptionCache,
+    cache_epoch: u64,
     stdio: Stdio,
+    client_closed: oneshot::Receiver<()>,
     load_limiter: Option<LoadLimiterEnvironment>,
     addr: IpAddr,
     scribe: Scribe,
+    will_exit: &AtomicBool,
 ) -> Result<()> {
     let Stdio {
         stdin,
@@ -119,18 +277,45 @@ pub async fn request_handler(
 
     // Info per wireproto command within this session
     let wireproto_calls = Arc::new(Mutex::new(Vec::new()));
-
-    let priority = metadata.priority();
+    let wireproto_timings = Arc::new(Mutex::new(Vec::new()));
+
+    let resumed = metadata
+        .resumption_token()
+        .and_then(|token| session_resumption.resume(token, reponame, metadata.identities()));
+    let priority = match &resumed {
+        Some(resumed) => resumed.priority,
+        None => *metadata.priority(),
+    };
     scuba.add("priority", priority.to_string());
+    scuba.add("resumed_session", resumed.is_some());
+    scuba.add("cache_epoch", cache_epoch);
+    scuba.add(
+        "traffic_class",
+        TrafficClass::from_identities(metadata.identities()).as_str(),
+    );
     scuba.log_with_msg("Connection established", None);
 
-    let mut session_builder = SessionContainer::builder(fb)
-        .metadata(metadata.clone())
-        .load_limiter(
-            load_limiter.map(|l| l.get(metadata.identities(), metadata.client_hostname())),
-        );
+    let maintenance_message = tunables().get_maintenance_message();
+    if !maintenance_message.is_empty() {
+        // Only send this to the client: there is no point in logging it for every connection.
+        warn!(conn_log, "{}", maintenance_message; "remote" => "remote_only");
+    }
+
+    let session_builder = SessionContainer::builder(fb).metadata(metadata.clone());
+    // A resumed session carries on with the load of the one it replaces, rather than starting
+    // with fresh budgets.
+    let mut session_builder = match &resumed {
+        Some(resumed) => session_builder.shared_load_limiter(resumed.load_limiter.clone()),
+        None => session_builder.load_limiter(load_limiter.map(|l| {
+            l.get(
+                metadata.identities(),
+                metadata.client_hostname(),
+                Some(reponame.as_str()),
+            )
+        })),
+    };
 
-    if priority == &Priority::Wishlist {
+    if priority == Priority::Wishlist {
         session_builder = session_builder
             .session_class(SessionClass::Background)
             .blobstore_maybe_read_qps_limiter(tunables().get_wishlist_read_qps())
@@ -144,7 +329,8 @@ pub async fn request_handler(
     let mut logging = LoggingContainer::new(fb, conn_log.clone(), scuba.clone());
     logging.with_scribe(scribe);
 
-    let repo_client = RepoClient::new(
+    let reponame = reponame.clone();
+    let mut repo_client = RepoClient::new(
         repo,
         session.clone(),
         logging,
@@ -153,6 +339,22 @@ pub async fn request_handler(
         maybe_push_redirector_args,
         repo_client_knobs,
     );
+    if let Some(resumed) = resumed {
+        repo_client = repo_client.with_resumed_state(resumed.client_state);
+    }
+    let resumption_token = session_resumption.issue(
+        &reponame,
+        metadata.identities(),
+        ResumedSession {
+            priority,
+            load_limiter: session.shared_load_limiter(),
+            client_state: repo_client.resumable_state(),
+        },
+    );
+    if let Some(token) = &resumption_token {
+        repo_client = repo_client.with_resumption_token(token.clone());
+    }
+    repo_client = repo_client.with_cache_epoch(cache_epoch);
     let request_perf_counters = repo_client.request_perf_counters();
 
     // Construct a hg protocol handler
@@ -163,45 +365,113 @@ pub async fn request_handler(
         sshproto::HgSshCommandDecode,
         sshproto::HgSshCommandEncode,
         wireproto_calls.clone(),
+        wireproto_timings.clone(),
     );
 
     // send responses back
     let endres = proto_handler
-        .inspect(move |bytes| session.bump_load(Metric::EgressBytes, bytes.len() as f64))
+        .inspect({
+            let session = session.clone();
+            move |bytes| session.bump_load(Metric::EgressBytes, bytes.len() as f64)
+        })
         .map_err(Error::from)
         .map(bytes_ext::copy_from_old)
         .forward(stdout)
-        .map(|_| ());
+        .map(|_| ())
+        .compat();
+
+    // If the client goes away mid-command, there is no point in finishing it. Dropping the
+    // in-flight future cancels everything it drives, and cancelling the session lets work that
+    // was spawned off of it stop too.
+    let endres = async {
+        futures::pin_mut!(endres);
+        match future::select(endres, client_closed).await {
+            Either::Left((res, _)) => res,
+            Either::Right(_) => {
+                session.cancel();
+                Err(ErrorKind::ClientDisconnected.into())
+            }
+        }
+    };
+
+    let endres = session.account_cpu(endres);
 
     // If we got an error at this point, then catch it and print a message
-    let (stats, result) = endres.compat().timed().await;
+    let (stats, result) = endres.timed().await;
+
+    // The CPU time is counted as load once, for the whole request, rather than as it goes.
+    let cpu_time = session.cpu_time();
+    session.bump_load(Metric::CpuTimeMs, cpu_time.as_secs_f64() * 1000.0);
+
+    if let Some(token) = &resumption_token {
+        session_resumption.release(token);
+    }
 
     let wireproto_calls = {
         let mut wireproto_calls = wireproto_calls.lock().expect("lock poisoned");
         mem::replace(&mut *wireproto_calls, Vec::new())
     };
 
-    STATS::wireproto_ms.add_value(stats.completion_time.as_millis_unchecked() as i64);
+    let traffic_class = session.traffic_class();
+    let wireproto_ms = stats.completion_time.as_millis_unchecked();
+    let session_cpu_ms = cpu_time.as_millis_unchecked();
+    match traffic_class {
+        TrafficClass::Interactive => {
+            STATS::wireproto_ms.add_value(wireproto_ms as i64);
+            STATS::session_cpu_ms.add_value(session_cpu_ms as i64);
+            exemplars::WIREPROTO_MS.record(wireproto_ms, &session_id.to_string());
+        }
+        TrafficClass::Quicksand => {
+            STATS::quicksand_wireproto_ms.add_value(wireproto_ms as i64);
+            STATS::quicksand_session_cpu_ms.add_value(session_cpu_ms as i64);
+        }
+    }
+    for (command, duration) in wireproto_timings.lock().expect("lock poisoned").drain(..) {
+        record_command_duration(&command, duration, session_id, traffic_class);
+    }
 
     let mut scuba = scuba.clone();
 
     scuba
         .add_future_stats(&stats)
+        .add("cpu_time_us", cpu_time.as_micros_unchecked())
         .add("wireproto_commands", wireproto_calls);
 
     // Populate stats no matter what to avoid dead detectors firing.
     STATS::request_success.add_value(0);
     STATS::request_failure.add_value(0);
+    STATS::request_cancelled.add_value(0);
 
     // Log request level perf counters
     request_perf_counters.insert_perf_counters(&mut scuba);
 
+    let disconnect_reason = DisconnectReason::classify(
+        &result,
+        session.is_cancelled(),
+        will_exit.load(Ordering::Relaxed),
+    );
+    STATS::request_disconnect_reason.add_value(1, (disconnect_reason.as_str(),));
+    scuba.add("disconnect_reason", disconnect_reason.as_str());
+
+    // A client that went away doesn't get the error, so it doesn't get a code either.
+    let error_code = match &result {
+        Err(err) if !session.is_cancelled() => Some(ClientErrorCode::classify(err)),
+        _ => None,
+    };
+    if let Some(error_code) = error_code {
+        scuba.add("error_code", error_code.as_str());
+    }
+
     match &result {
         Ok(_) => {
             STATS::request_success.add_value(1);
             STATS::request_outcome_permille.add_value(1000);
             scuba.log_with_msg("Request finished - Success", None)
         }
+        Err(_) if session.is_cancelled() => {
+            STATS::request_cancelled.add_value(1);
+            scuba.log_with_msg("Request finished - Cancelled", None);
+        }
         Err(err) => {
             STATS::request_failure.add_value(1);
             STATS::request_outcome_permille.add_value(0);
@@ -209,16 +479,82 @@ pub async fn request_handler(
         }
     }
 
-    if let Err(err) = result {
+    if session.is_cancelled() {
+        warn!(
+            &conn_log,
+            "Client disconnected, cancelled the in-flight command"
+        );
+    } else if let Err(err) = result {
+        let error_code = error_code.unwrap_or(ClientErrorCode::Internal);
         error!(&conn_log, "Command failed";
             SlogKVError(err),
-            "remote" => "true"
+            "remote" => "true",
+            "error_code" => error_code.as_str()
         );
+        error!(&conn_log, "{}", error_code.client_message(); "remote" => "remote_only");
     }
 
     Ok(())
 }
 
+// The aggregate wireproto_ms mixes all the commands of a session, so keep the expensive ones
+// apart to be able to tell which of them regressed.
+fn command_group(command: &str) -> &'static str {
+    match command {
+        "getbundle" => "getbundle",
+        "gettreepack" => "gettreepack",
+        "getpackv1" | "getpackv2" => "getpack",
+        "getcommitdata" => "getcommitdata",
+        "unbundle" | "unbundlereplay" => "unbundle",
+        _ => "other_command",
+    }
+}
+
+fn record_command_duration(
+    command: &str,
+    duration: Duration,
+    session_id: &SessionId,
+    traffic_class: TrafficClass,
+) {
+    let ms = duration.as_millis_unchecked();
+    let group = command_group(command);
+    if traffic_class == TrafficClass::Quicksand {
+        // Exemplars are for finding the interactive sessions behind a latency regression, so
+        // quicksand doesn't get any.
+        STATS::quicksand_command_ms.add_value(ms as i64, (group,));
+        return;
+    }
+
+    let exemplars = match group {
+        "getbundle" => {
+            STATS::getbundle_ms.add_value(ms as i64);
+            &*exemplars::GETBUNDLE_MS
+        }
+        "gettreepack" => {
+            STATS::gettreepack_ms.add_value(ms as i64);
+            &*exemplars::GETTREEPACK_MS
+        }
+        "getpack" => {
+            STATS::getpack_ms.add_value(ms as i64);
+            &*exemplars::GETPACK_MS
+        }
+        "getcommitdata" => {
+            STATS::getcommitdata_ms.add_value(ms as i64);
+            &*exemplars::GETCOMMITDATA_MS
+        }
+        "unbundle" => {
+            STATS::unbundle_ms.add_value(ms as i64);
+            &*exemplars::UNBUNDLE_MS
+        }
+        _ => {
+            STATS::other_command_ms.add_value(ms as i64);
+            &*exemplars::OTHER_COMMAND_MS
+        }
+    };
+    // The session id is what the scuba logs of the session can be found by.
+    exemplars.record(ms, &session_id.to_string());
+}
+
 pub fn create_conn_logger(
     stderr: mpsc::UnboundedSender<Bytes>,
     server_logger: Option<Logger>,
@@ -255,3 +591,97 @@ pub fn create_conn_logger(
         Logger::root(client_drain.ignore_res(), decorator)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bookmarks::BookmarkTransactionError;
+
+    #[test]
+    fn test_disconnect_reason() {
+        let classify = |result: Result<()>| DisconnectReason::classify(&result, false, false);
+
+        assert_eq!(classify(Ok(())), DisconnectReason::ClientEof);
+        assert_eq!(
+            classify(Err(ErrorKind::ClientDisconnected.into())),
+            DisconnectReason::ClientEof
+        );
+        assert_eq!(
+            classify(Err(
+                Error::from(ThrottleReason::ThrottledSlice).context("Request getbundle")
+            )),
+            DisconnectReason::Throttled
+        );
+        assert_eq!(
+            classify(Err(throttledblob::ErrorKind::Throttled(
+                Duration::from_secs(1)
+            )
+            .into())),
+            DisconnectReason::Throttled
+        );
+        assert_eq!(
+            classify(Err(PushRejection::RateLimitExceeded(
+                "Rate limit exceeded".to_string()
+            )
+            .into())),
+            DisconnectReason::Throttled
+        );
+        assert_eq!(
+            classify(Err(anyhow!("something broke"))),
+            DisconnectReason::Error
+        );
+
+        let err = || Err(anyhow!("something broke"));
+        assert_eq!(
+            DisconnectReason::classify(&err(), true, false),
+            DisconnectReason::ClientEof
+        );
+        assert_eq!(
+            DisconnectReason::classify(&err(), false, true),
+            DisconnectReason::Shutdown
+        );
+    }
+
+    #[test]
+    fn test_client_error_code() {
+        let classify = |err: Error| ClientErrorCode::classify(&err);
+
+        assert_eq!(
+            classify(Error::from(ThrottleReason::ThrottledSlice).context("Request getbundle")),
+            ClientErrorCode::Throttled
+        );
+        assert_eq!(
+            classify(PushRejection::HooksFailed("hooks failed:\n".to_string()).into()),
+            ClientErrorCode::HookRejected
+        );
+        assert_eq!(
+            classify(PushRejection::RateLimitExceeded("Rate limit exceeded".to_string()).into()),
+            ClientErrorCode::Throttled
+        );
+        assert_eq!(
+            classify(
+                Error::from(BookmarkMovementError::RepoLocked("maintenance".to_string()))
+                    .context("Failed to create bookmark")
+            ),
+            ClientErrorCode::ReadOnly
+        );
+        assert_eq!(
+            classify(PushRejection::RepoReadOnly("maintenance".to_string()).into()),
+            ClientErrorCode::ReadOnly
+        );
+        assert_eq!(
+            classify(
+                BookmarkTransactionError::Other(
+                    RepoWriteError::Locked("maintenance".to_string()).into()
+                )
+                .into()
+            ),
+            ClientErrorCode::ReadOnly
+        );
+        assert_eq!(
+            classify(anyhow!("something broke")),
+            ClientErrorCode::Internal
+        );
+    }
+}
@@ -0,0 +1,267 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Wireproto sessions are given a resumption token when they start, which the client can put in
+//! the preamble when it reconnects. The new session then picks up the priority, the load limiter
+//! (and with it, what is left of the load budgets) and the discovery state of the session it
+//! replaces, instead of starting over.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use load_limiter::ArcLoadLimiter;
+use permission_checker::MononokeIdentitySet;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use repo_client::ResumableClientState;
+use sshrelay::Priority;
+use stats::prelude::*;
+use tunables::tunables;
+
+define_stats! {
+    prefix = "mononoke.session_resumption";
+    issued: timeseries(Rate, Sum),
+    resumed: timeseries(Rate, Sum),
+    rejected: timeseries(Rate, Sum),
+    expired: timeseries(Rate, Sum),
+}
+
+const TOKEN_LENGTH: usize = 32;
+
+/// How often the sessions that were never resumed are dropped.
+pub const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What a session that resumes another picks up from it.
+pub struct ResumedSession {
+    pub priority: Priority,
+    pub load_limiter: Option<ArcLoadLimiter>,
+    pub client_state: ResumableClientState,
+}
+
+struct ResumableSession<S> {
+    reponame: String,
+    identities: MononokeIdentitySet,
+    state: S,
+    // None while the session is still connected.
+    expires_at: Option<Instant>,
+}
+
+pub struct SessionResumptionCache<S = ResumedSession> {
+    sessions: Mutex<HashMap<String, ResumableSession<S>>>,
+}
+
+fn resumption_ttl() -> Option<Duration> {
+    let ttl = tunables().get_session_resumption_ttl_secs();
+    if ttl > 0 {
+        Some(Duration::from_secs(ttl as u64))
+    } else {
+        None
+    }
+}
+
+impl<S> SessionResumptionCache<S> {
+    pub fn new() -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take over the session that `token` was issued to. The session must be for the same repo
+    /// and identities, and must not have expired. Tokens can only be used once: the resumed
+    /// session is issued a new one.
+    pub fn resume(
+        &self,
+        token: &str,
+        reponame: &str,
+        identities: &MononokeIdentitySet,
+    ) -> Option<S> {
+        resumption_ttl()?;
+
+        let mut sessions = self.sessions.lock().expect("lock poisoned");
+        let session = match sessions.remove(token) {
+            Some(session) => session,
+            None => {
+                STATS::rejected.add_value(1);
+                return None;
+            }
+        };
+
+        let expired = session
+            .expires_at
+            .map_or(false, |expires_at| expires_at <= Instant::now());
+        if expired || session.reponame != reponame || &session.identities != identities {
+            STATS::rejected.add_value(1);
+            return None;
+        }
+
+        STATS::resumed.add_value(1);
+        Some(session.state)
+    }
+
+    /// Issue a token for a session that is starting, or None if session resumption is disabled.
+    pub fn issue(
+        &self,
+        reponame: &str,
+        identities: &MononokeIdentitySet,
+        state: S,
+    ) -> Option<String> {
+        resumption_ttl()?;
+
+        let token: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(TOKEN_LENGTH)
+            .collect();
+
+        let mut sessions = self.sessions.lock().expect("lock poisoned");
+        sessions.insert(
+            token.clone(),
+            ResumableSession {
+                reponame: reponame.to_string(),
+                identities: identities.clone(),
+                state,
+                expires_at: None,
+            },
+        );
+
+        STATS::issued.add_value(1);
+        Some(token)
+    }
+
+    /// Called when the session that `token` was issued to disconnects. From now on, the client
+    /// has the resumption TTL to reconnect.
+    pub fn release(&self, token: &str) {
+        let mut sessions = self.sessions.lock().expect("lock poisoned");
+        match resumption_ttl() {
+            Some(ttl) => {
+                if let Some(session) = sessions.get_mut(token) {
+                    session.expires_at = Some(Instant::now() + ttl);
+                }
+            }
+            None => {
+                sessions.remove(token);
+            }
+        }
+    }
+
+    /// Drop the sessions that expired without being resumed, so that they don't hold on to their
+    /// state for as long as the server runs. This is meant to be called every
+    /// `EXPIRY_SWEEP_INTERVAL`.
+    pub fn remove_expired(&self) {
+        self.remove_expired_at(Instant::now())
+    }
+
+    fn remove_expired_at(&self, now: Instant) {
+        let mut sessions = self.sessions.lock().expect("lock poisoned");
+        let before = sessions.len();
+        sessions.retain(|_, session| {
+            session
+                .expires_at
+                .map_or(true, |expires_at| expires_at > now)
+        });
+        STATS::expired.add_value((before - sessions.len()) as i64);
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.sessions.lock().expect("lock poisoned").len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maplit::{btreeset, hashmap};
+    use permission_checker::MononokeIdentity;
+    use tunables::{with_tunables, MononokeTunables};
+
+    fn with_ttl<T>(ttl_secs: i64, f: impl FnOnce() -> T) -> T {
+        let tunables = MononokeTunables::default();
+        tunables.update_ints(&hashmap! {
+            "session_resumption_ttl_secs".to_string() => ttl_secs,
+        });
+        with_tunables(tunables, f)
+    }
+
+    fn identities(user: &str) -> MononokeIdentitySet {
+        btreeset! { MononokeIdentity::new("USER", user).unwrap() }
+    }
+
+    #[test]
+    fn test_resume() {
+        with_ttl(10, || {
+            let cache = SessionResumptionCache::new();
+            let alice = identities("alice");
+
+            let token = cache
+                .issue("repo", &alice, 1)
+                .expect("resumption is enabled");
+            cache.release(&token);
+            assert_eq!(cache.resume(&token, "repo", &alice), Some(1));
+
+            // Tokens can only be used once.
+            assert_eq!(cache.resume(&token, "repo", &alice), None);
+            assert_eq!(cache.resume("unknown", "repo", &alice), None);
+        });
+    }
+
+    #[test]
+    fn test_resume_mismatch() {
+        with_ttl(10, || {
+            let cache = SessionResumptionCache::new();
+            let alice = identities("alice");
+
+            // A session can only be resumed for the same repo and identities, and the token
+            // is gone once someone else tried to use it.
+            let token = cache.issue("repo", &alice, 1).unwrap();
+            assert_eq!(cache.resume(&token, "other", &alice), None);
+            assert_eq!(cache.resume(&token, "repo", &alice), None);
+
+            let token = cache.issue("repo", &alice, 2).unwrap();
+            assert_eq!(cache.resume(&token, "repo", &identities("bob")), None);
+            assert_eq!(cache.len(), 0);
+        });
+    }
+
+    #[test]
+    fn test_disabled() {
+        with_ttl(0, || {
+            let cache = SessionResumptionCache::new();
+            let alice = identities("alice");
+            assert_eq!(cache.issue("repo", &alice, 1), None);
+            assert_eq!(cache.len(), 0);
+        });
+    }
+
+    #[test]
+    fn test_expiry() {
+        with_ttl(10, || {
+            let cache = SessionResumptionCache::new();
+            let alice = identities("alice");
+
+            let connected = cache.issue("repo", &alice, 1).unwrap();
+            let released = cache.issue("repo", &alice, 2).unwrap();
+            cache.release(&released);
+
+            // Sessions that are still connected don't expire.
+            cache.remove_expired_at(Instant::now() + Duration::from_secs(1));
+            assert_eq!(cache.len(), 2);
+            cache.remove_expired_at(Instant::now() + Duration::from_secs(11));
+            assert_eq!(cache.len(), 1);
+            assert_eq!(cache.resume(&released, "repo", &alice), None);
+
+            cache.release(&connected);
+            cache
+                .sessions
+                .lock()
+                .unwrap()
+                .get_mut(&connected)
+                .unwrap()
+                .expires_at = Some(Instant::now());
+            assert_eq!(cache.resume(&connected, "repo", &alice), None);
+        });
+    }
+}
@@ -0,0 +1,473 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Shadowing of production traffic to a test host: a sampled fraction of the read-only requests
+//! that come in over HTTP (EdenAPI requests, and wireproto sessions over websockets) is sent
+//! again to the test host, and whatever it responds is discarded. This way, a new release can
+//! be validated against production traffic patterns before it is rolled out.
+//!
+//! Requests are sent with this server's own certificate, so the test host has to trust it as a
+//! proxy for the identities of the clients to be used.
+
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+
+use anyhow::{anyhow, bail, format_err, Context, Error, Result};
+use bytes::{Bytes, BytesMut};
+use futures::channel::{mpsc, oneshot};
+use futures::future::try_join;
+use futures::stream::{Stream, StreamExt};
+use http::{HeaderMap, HeaderValue, Method, Request};
+use hyper::Body;
+use lazy_static::lazy_static;
+use openssl::ssl::SslConnector;
+use rand::{thread_rng, Rng};
+use slog::{debug, Logger};
+use sshrelay::{IoStream, SshEncoder, SshMsg};
+use stats::prelude::*;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_openssl::SslStream;
+use tokio_util::codec::FramedWrite;
+use tunables::tunables;
+
+// Shadowing must never get in the way of production traffic, so requests past this many are not
+// shadowed, rather than queued up if the test host is slow.
+const MAX_IN_FLIGHT: usize = 100;
+// A copy of EdenAPI bodies is kept as they are read, for them to be sent again once they are
+// complete, so only small ones are shadowed.
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+const MAX_RESPONSE_HEAD_BYTES: usize = 16 * 1024;
+// How many chunks of a wireproto session can wait to be sent to the shadow host. Past that, the
+// shadow host is not keeping up, and shadowing of the session stops.
+const WIREPROTO_BUFFER_CHUNKS: usize = 64;
+
+// The EdenAPI endpoints that only read from the repo, after the repo in the path. Endpoints that
+// are not listed here are never shadowed, so that new ones have to be vetted first.
+const READ_ONLY_EDENAPI_POST: &[&str] = &[
+    "files",
+    "trees",
+    "trees/complete",
+    "history",
+    "commit/location_to_hash",
+    "commit/hash_to_location",
+    "commit/revlog_data",
+    "clone",
+    "full_idmap_clone",
+];
+const READ_ONLY_EDENAPI_GET_PREFIXES: &[&str] = &["bookmarks/", "snapshot/"];
+
+// Wireproto commands that write to the repo. The session isn't parsed into commands here, so
+// shadowing of a session stops as soon as one of these shows up anywhere in what the client sends.
+const WIREPROTO_WRITE_COMMANDS: &[&[u8]] = &[b"unbundle", b"pushkey"];
+
+define_stats! {
+    prefix = "mononoke.shadowing";
+    shadowed: dynamic_timeseries("{}.shadowed", (kind: &'static str); Rate, Sum),
+    failed: dynamic_timeseries("{}.failed", (kind: &'static str); Rate, Sum),
+    skipped_in_flight: timeseries(Rate, Sum),
+    skipped_incomplete_body: timeseries(Rate, Sum),
+    wireproto_stopped_on_write: timeseries(Rate, Sum),
+}
+
+lazy_static! {
+    static ref IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+}
+
+struct InFlight(());
+
+impl InFlight {
+    fn start() -> Option<Self> {
+        if IN_FLIGHT.fetch_add(1, Ordering::Relaxed) >= MAX_IN_FLIGHT {
+            IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+            STATS::skipped_in_flight.add_value(1);
+            return None;
+        }
+        Some(Self(()))
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Whether an EdenAPI request is to one of the endpoints that are known to only read from the
+/// repo.
+pub fn is_read_only_edenapi(method: &Method, path: &str) -> bool {
+    let path = path.split('?').next().unwrap_or_default().trim_matches('/');
+    // The path starts with the repo.
+    let endpoint = match path.splitn(2, '/').nth(1) {
+        Some(endpoint) => endpoint,
+        None => return false,
+    };
+    match *method {
+        Method::GET => READ_ONLY_EDENAPI_GET_PREFIXES
+            .iter()
+            .any(|prefix| endpoint.starts_with(prefix)),
+        Method::POST => READ_ONLY_EDENAPI_POST.contains(&endpoint),
+        _ => false,
+    }
+}
+
+/// The body of a request, as it is served. A copy of it is kept as it is read, and sent to
+/// `complete` once it was read in full. If it is larger than `MAX_BODY_BYTES`, fails, or isn't
+/// read to the end, `complete` is dropped instead.
+struct TeeBody {
+    body: Body,
+    copy: BytesMut,
+    complete: Option<oneshot::Sender<Bytes>>,
+}
+
+impl Stream for TeeBody {
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.body).poll_next(cx);
+        match &res {
+            Poll::Ready(Some(Ok(chunk))) => {
+                if this.copy.len() + chunk.len() > MAX_BODY_BYTES as usize {
+                    this.complete = None;
+                } else if this.complete.is_some() {
+                    this.copy.extend_from_slice(chunk);
+                }
+            }
+            Poll::Ready(Some(Err(_))) => this.complete = None,
+            Poll::Ready(None) => {
+                if let Some(complete) = this.complete.take() {
+                    let _ = complete.send(std::mem::take(&mut this.copy).freeze());
+                }
+            }
+            Poll::Pending => {}
+        }
+        res
+    }
+}
+
+/// Decides whether what the client sends in a wireproto session can still be shadowed.
+#[derive(Default)]
+struct WriteCommandFilter {
+    // The end of what was seen so far, for command names that are split across chunks.
+    tail: Vec<u8>,
+    stopped: bool,
+}
+
+impl WriteCommandFilter {
+    fn allows(&mut self, chunk: &[u8]) -> bool {
+        if self.stopped {
+            return false;
+        }
+
+        let mut window = std::mem::take(&mut self.tail);
+        window.extend_from_slice(chunk);
+        if WIREPROTO_WRITE_COMMANDS
+            .iter()
+            .any(|command| window.windows(command.len()).any(|w| w == *command))
+        {
+            self.stopped = true;
+            return false;
+        }
+
+        let max_len = WIREPROTO_WRITE_COMMANDS
+            .iter()
+            .map(|command| command.len())
+            .max()
+            .unwrap_or(0);
+        let keep = window.len().min(max_len - 1);
+        self.tail = window.split_off(window.len() - keep);
+        true
+    }
+}
+
+pub struct Shadowing {
+    host: String,
+    addr: SocketAddr,
+    connector: SslConnector,
+}
+
+impl Shadowing {
+    /// Shadow to `host`, given as `host:port`, using `connector` for the TLS connections to it.
+    /// The host is resolved once, here.
+    pub fn new(host: String, connector: SslConnector) -> Result<Self> {
+        let addr = host
+            .to_socket_addrs()
+            .with_context(|| format!("Invalid shadow host {}, it must be HOST:PORT", host))?
+            .next()
+            .ok_or_else(|| format_err!("No address for shadow host {}", host))?;
+        HeaderValue::from_str(&host).with_context(|| format!("Invalid shadow host {}", host))?;
+        Ok(Self {
+            host,
+            addr,
+            connector,
+        })
+    }
+
+    /// Whether this request or session should be shadowed, as per the sampling tunable.
+    pub fn sample(&self) -> bool {
+        let percentage = tunables().get_shadow_traffic_percentage();
+        percentage > 0 && thread_rng().gen_range(0, 100) < percentage
+    }
+
+    async fn connect(&self) -> Result<SslStream<TcpStream>> {
+        let sock = TcpStream::connect(&self.addr)
+            .await
+            .with_context(|| format!("Failed to connect to {}", self.host))?;
+
+        let mut config = self.connector.configure()?;
+        // Test hosts are addressed directly, so their certificates won't match the address.
+        config.set_verify_hostname(false);
+        config.set_use_server_name_indication(false);
+        tokio_openssl::connect(config, "", sock)
+            .await
+            .map_err(|e| anyhow!("TLS handshake with {} failed: {}", self.host, e))
+    }
+
+    /// Send a copy of an EdenAPI request to the shadow host, if it is small enough. Returns the
+    /// body for the request to be served from. This is best effort: the request is served the
+    /// same whether it is shadowed or not, and the copy is only sent once the request has read
+    /// its body.
+    pub fn shadow_edenapi(
+        self: &Arc<Self>,
+        logger: &Logger,
+        method: &Method,
+        path_and_query: &str,
+        headers: &HeaderMap<HeaderValue>,
+        body: Body,
+    ) -> Body {
+        let content_length = headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse::<u64>().ok());
+        let small_enough = match (method, content_length) {
+            (&Method::GET, _) => true,
+            (_, Some(len)) => len <= MAX_BODY_BYTES,
+            (_, None) => false,
+        };
+        if !small_enough {
+            return body;
+        }
+
+        let in_flight = match InFlight::start() {
+            Some(in_flight) => in_flight,
+            None => return body,
+        };
+
+        let (complete, body_copy) = oneshot::channel();
+        let this = self.clone();
+        let logger = logger.clone();
+        let method = method.clone();
+        let path_and_query = path_and_query.to_string();
+        let mut headers = headers.clone();
+        tokio::spawn(async move {
+            let _in_flight = in_flight;
+            let body = match body_copy.await {
+                Ok(body) => body,
+                Err(_) => {
+                    STATS::skipped_incomplete_body.add_value(1);
+                    return;
+                }
+            };
+            STATS::shadowed.add_value(1, ("edenapi",));
+            let res = async {
+                headers.insert(http::header::HOST, HeaderValue::from_str(&this.host)?);
+                let mut req = Request::builder()
+                    .method(method)
+                    .uri(path_and_query)
+                    .body(Body::from(body))?;
+                *req.headers_mut() = headers;
+                this.send_edenapi(req).await
+            }
+            .await;
+            if let Err(e) = res {
+                STATS::failed.add_value(1, ("edenapi",));
+                debug!(logger, "Shadowing EdenAPI request failed: {:#}", e);
+            }
+        });
+
+        Body::wrap_stream(TeeBody {
+            body,
+            copy: BytesMut::new(),
+            complete: Some(complete),
+        })
+    }
+
+    async fn send_edenapi(&self, req: Request<Body>) -> Result<()> {
+        let stream = self.connect().await?;
+        let (mut sender, conn) = hyper::client::conn::handshake(stream).await?;
+        tokio::spawn(conn);
+        let res = sender.send_request(req).await?;
+        hyper::body::to_bytes(res.into_body()).await?;
+        Ok(())
+    }
+
+    /// Open a wireproto session to the shadow host with the same request as the client's, and
+    /// return a sender for what the client sends in the session to be copied to it. The sender
+    /// is bounded, for the session to be copied only for as long as the shadow host keeps up.
+    pub fn shadow_wireproto(
+        self: &Arc<Self>,
+        logger: &Logger,
+        path: &str,
+        headers: &HeaderMap<HeaderValue>,
+    ) -> Option<mpsc::Sender<Bytes>> {
+        let in_flight = InFlight::start()?;
+        let (tx, rx) = mpsc::channel(WIREPROTO_BUFFER_CHUNKS);
+
+        let this = self.clone();
+        let logger = logger.clone();
+        let path = path.to_string();
+        let headers = headers.clone();
+        tokio::spawn(async move {
+            let _in_flight = in_flight;
+            STATS::shadowed.add_value(1, ("wireproto",));
+            if let Err(e) = this.send_wireproto(&path, &headers, rx).await {
+                STATS::failed.add_value(1, ("wireproto",));
+                debug!(logger, "Shadowing wireproto session failed: {:#}", e);
+            }
+        });
+
+        Some(tx)
+    }
+
+    async fn send_wireproto(
+        &self,
+        path: &str,
+        headers: &HeaderMap<HeaderValue>,
+        stdin: mpsc::Receiver<Bytes>,
+    ) -> Result<()> {
+        let mut stream = self.connect().await?;
+
+        // The upgrade is done by hand, since all that's needed from the response is its status.
+        let mut head = format!("GET {} HTTP/1.1\r\nhost: {}\r\n", path, self.host);
+        for (name, value) in headers {
+            if name == http::header::HOST {
+                continue;
+            }
+            let value = value.to_str().context("Invalid header")?;
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes()).await?;
+
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() > MAX_RESPONSE_HEAD_BYTES {
+                bail!("Response head is too large");
+            }
+            let mut byte = [0u8];
+            if stream.read(&mut byte).await? == 0 {
+                bail!("Connection closed before the upgrade");
+            }
+            response.push(byte[0]);
+        }
+        if !response.starts_with(b"HTTP/1.1 101") {
+            let status = String::from_utf8_lossy(&response);
+            bail!(
+                "Upgrade refused: {}",
+                status.lines().next().unwrap_or_default()
+            );
+        }
+
+        let (mut rd, wr) = tokio::io::split(stream);
+        let mut filter = WriteCommandFilter::default();
+        let send = stdin
+            .take_while(move |chunk| {
+                let allowed = filter.allows(chunk);
+                if !allowed {
+                    STATS::wireproto_stopped_on_write.add_value(1);
+                }
+                futures::future::ready(allowed)
+            })
+            .map(|chunk| Ok::<_, std::io::Error>(SshMsg::new(IoStream::Stdin, chunk)))
+            .forward(FramedWrite::new(wr, SshEncoder::new()));
+        let discard = async move {
+            tokio::io::copy(&mut rd, &mut tokio::io::sink()).await?;
+            Result::<_, Error>::Ok(())
+        };
+
+        try_join(async move { send.await.map_err(Error::from) }, discard).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_command_filter() {
+        let mut filter = WriteCommandFilter::default();
+        assert!(filter.allows(b"hello\n"));
+        assert!(filter.allows(b"getbundle\n"));
+        // Split across chunks.
+        assert!(filter.allows(b"unbun"));
+        assert!(!filter.allows(b"dle\n"));
+        // Nothing goes through once stopped.
+        assert!(!filter.allows(b"known\n"));
+
+        let mut filter = WriteCommandFilter::default();
+        assert!(!filter.allows(b"batch\ncmds pushkey key=master"));
+    }
+
+    #[test]
+    fn test_is_read_only_edenapi() {
+        assert!(is_read_only_edenapi(&Method::GET, "/repo/bookmarks/master"));
+        assert!(is_read_only_edenapi(&Method::GET, "/repo/snapshot/1.abc"));
+        assert!(is_read_only_edenapi(&Method::POST, "/repo/trees"));
+        assert!(is_read_only_edenapi(&Method::POST, "/repo/trees/complete/"));
+        assert!(is_read_only_edenapi(&Method::POST, "/repo/files?x=1"));
+        assert!(!is_read_only_edenapi(&Method::POST, "/repo/snapshot"));
+        assert!(!is_read_only_edenapi(&Method::PUT, "/repo/upload/file"));
+        // Endpoints that aren't known to be read-only are not shadowed.
+        assert!(!is_read_only_edenapi(&Method::POST, "/repo/new_endpoint"));
+        assert!(!is_read_only_edenapi(&Method::GET, "/repo/new_endpoint"));
+        assert!(!is_read_only_edenapi(&Method::GET, "/health_check"));
+        assert!(!is_read_only_edenapi(&Method::POST, "/files"));
+    }
+
+    #[tokio::test]
+    async fn test_tee_body() -> Result<()> {
+        let chunks: Vec<Result<_, std::io::Error>> = vec![Ok("hello "), Ok("world")];
+        let (complete, copy) = oneshot::channel();
+        let body = Body::wrap_stream(TeeBody {
+            body: Body::wrap_stream(futures::stream::iter(chunks)),
+            copy: BytesMut::new(),
+            complete: Some(complete),
+        });
+        assert_eq!(
+            hyper::body::to_bytes(body).await?,
+            Bytes::from("hello world")
+        );
+        assert_eq!(copy.await?, Bytes::from("hello world"));
+
+        // Bodies that are too large aren't copied.
+        let large = vec![0u8; MAX_BODY_BYTES as usize + 1];
+        let (complete, copy) = oneshot::channel();
+        let body = Body::wrap_stream(TeeBody {
+            body: Body::from(large.clone()),
+            copy: BytesMut::new(),
+            complete: Some(complete),
+        });
+        assert_eq!(hyper::body::to_bytes(body).await?.len(), large.len());
+        assert!(copy.await.is_err());
+
+        // Nor are bodies that aren't read to the end.
+        let (complete, copy) = oneshot::channel();
+        drop(TeeBody {
+            body: Body::from("unread"),
+            copy: BytesMut::new(),
+            complete: Some(complete),
+        });
+        assert!(copy.await.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! TCP options for the accepted sockets. A client that crashes or loses its network doesn't
+//! close its connections, and with the kernel defaults it takes hours for them to be noticed as
+//! dead, during which they still count as open connections.
+
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+use metaconfig_types::TcpSocketOptions;
+use tokio::net::TcpStream;
+
+pub fn set_socket_options(sock: &TcpStream, options: &TcpSocketOptions) -> io::Result<()> {
+    if let Some(idle) = options.keepalive_idle {
+        sock.set_keepalive(Some(idle))?;
+        // These only apply once keepalives are enabled.
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(interval) = options.keepalive_interval {
+                set_tcp_option(sock, libc::TCP_KEEPINTVL, interval.as_secs())?;
+            }
+        }
+        if let Some(probes) = options.keepalive_probes {
+            set_tcp_option(sock, libc::TCP_KEEPCNT, probes.into())?;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(user_timeout) = options.user_timeout {
+            set_tcp_option(
+                sock,
+                libc::TCP_USER_TIMEOUT,
+                user_timeout.as_millis() as u64,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Set a TCP option of `sock`, unless `value` is 0: that's the kernel default for the user
+/// timeout, and isn't valid for the others, so the kernel default is kept.
+fn set_tcp_option(sock: &TcpStream, name: libc::c_int, value: u64) -> io::Result<()> {
+    if value == 0 {
+        return Ok(());
+    }
+    let value: libc::c_int = value.min(libc::c_int::MAX as u64) as libc::c_int;
+    let ret = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    fn get_tcp_option(sock: &TcpStream, name: libc::c_int) -> libc::c_int {
+        let mut value: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                sock.as_raw_fd(),
+                libc::IPPROTO_TCP,
+                name,
+                &mut value as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        assert_eq!(ret, 0, "{}", io::Error::last_os_error());
+        value
+    }
+
+    async fn connect() -> TcpStream {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (sock, _) = futures::future::try_join(TcpStream::connect(addr), listener.accept())
+            .await
+            .unwrap();
+        sock
+    }
+
+    #[tokio::test]
+    async fn test_set_socket_options() {
+        let sock = connect().await;
+        let options = TcpSocketOptions {
+            keepalive_idle: Some(Duration::from_secs(60)),
+            keepalive_interval: Some(Duration::from_secs(10)),
+            keepalive_probes: Some(3),
+            user_timeout: Some(Duration::from_secs(30)),
+        };
+        set_socket_options(&sock, &options).unwrap();
+
+        assert_eq!(sock.keepalive().unwrap(), Some(Duration::from_secs(60)));
+        assert_eq!(get_tcp_option(&sock, libc::TCP_KEEPCNT), 3);
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(get_tcp_option(&sock, libc::TCP_KEEPINTVL), 10);
+            assert_eq!(get_tcp_option(&sock, libc::TCP_USER_TIMEOUT), 30_000);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_zero_keeps_default() {
+        let sock = connect().await;
+        let default_probes = get_tcp_option(&sock, libc::TCP_KEEPCNT);
+        let options = TcpSocketOptions {
+            keepalive_idle: Some(Duration::from_secs(60)),
+            keepalive_interval: Some(Duration::from_millis(100)),
+            keepalive_probes: Some(0),
+            user_timeout: Some(Duration::from_secs(0)),
+        };
+        set_socket_options(&sock, &options).unwrap();
+        assert_eq!(get_tcp_option(&sock, libc::TCP_KEEPCNT), default_probes);
+        #[cfg(target_os = "linux")]
+        {
+            assert_ne!(get_tcp_option(&sock, libc::TCP_KEEPINTVL), 0);
+            assert_eq!(get_tcp_option(&sock, libc::TCP_USER_TIMEOUT), 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_options() {
+        let sock = connect().await;
+        set_socket_options(&sock, &TcpSocketOptions::default()).unwrap();
+        assert_eq!(sock.keepalive().unwrap(), None);
+    }
+}
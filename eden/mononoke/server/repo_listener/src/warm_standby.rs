@@ -0,0 +1,261 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A warm standby builds its repo handlers like a regular server, but doesn't listen for
+//! connections until it is promoted. Until then, it tails the bookmark update log of every repo
+//! and warms the caches for the commits that bookmarks move to, so that once promoted (which is
+//! signalled by creating the promotion file), it serves from warm caches rather than paying the
+//! cold start cost. A standby that is shut down before it is promoted never listens.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Error;
+use blobrepo::BlobRepo;
+use bookmarks::{BookmarkUpdateLog, Freshness};
+use cache_warmup::{cache_warmup, CacheWarmupRequest, CacheWarmupTarget};
+use context::CoreContext;
+use fbinit::FacebookInit;
+use futures::{
+    future::{self, AbortHandle, Either, Future},
+    pin_mut,
+    stream::TryStreamExt,
+};
+use slog::{info, o, warn, Logger};
+use stats::prelude::*;
+use tokio::time::delay_for;
+
+use crate::repo_handlers::RepoHandler;
+
+define_stats! {
+    prefix = "mononoke.warm_standby";
+    log_entries: timeseries(Rate, Sum),
+    warmup_failures: timeseries(Rate, Sum),
+}
+
+const TAIL_INTERVAL: Duration = Duration::from_secs(1);
+const PROMOTION_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+const LOG_ENTRIES_LIMIT: u64 = 100;
+// Bookmark moves usually only add a few commits, and the initial warmup took care of the rest of
+// the history.
+const COMMIT_WARMUP_LIMIT: usize = 100;
+
+pub struct WarmStandby {
+    promotion_file: PathBuf,
+}
+
+impl WarmStandby {
+    pub fn new(promotion_file: PathBuf) -> Self {
+        Self { promotion_file }
+    }
+
+    /// Keep the caches of all the repos in `handlers` warm until the promotion file exists.
+    /// Returns false if `terminate` completed first, i.e. the server is shutting down before it
+    /// was promoted.
+    pub async fn run_until_promoted(
+        &self,
+        fb: FacebookInit,
+        handlers: &HashMap<String, RepoHandler>,
+        root_log: &Logger,
+        terminate: impl Future + Unpin,
+    ) -> bool {
+        info!(
+            root_log,
+            "Running as a warm standby until {} exists",
+            self.promotion_file.display()
+        );
+
+        let abort_handles: Vec<AbortHandle> = handlers
+            .iter()
+            .map(|(reponame, handler)| {
+                let logger = root_log.new(o!("repo" => reponame.clone()));
+                let ctx = CoreContext::new_with_logger(fb, logger);
+                let repo = handler.repo.blobrepo().clone();
+                let (tail, abort_handle) = future::abortable(async move {
+                    if let Err(e) = tail_bookmark_updates(&ctx, &repo).await {
+                        warn!(ctx.logger(), "Warm standby stopped tailing: {:?}", e);
+                    }
+                });
+                tokio::spawn(tail);
+                abort_handle
+            })
+            .collect();
+
+        let promoted = wait_for_file(&self.promotion_file, terminate).await;
+
+        for abort_handle in abort_handles {
+            abort_handle.abort();
+        }
+        if promoted {
+            info!(root_log, "Promoted from warm standby");
+        } else {
+            info!(
+                root_log,
+                "Shutting down before being promoted from warm standby"
+            );
+        }
+        promoted
+    }
+}
+
+/// Wait until `path` exists, or `terminate` completes. Returns whether the file exists.
+async fn wait_for_file(path: &Path, terminate: impl Future + Unpin) -> bool {
+    let exists = async {
+        while !path.exists() {
+            delay_for(PROMOTION_CHECK_INTERVAL).await;
+        }
+    };
+    pin_mut!(exists);
+
+    match future::select(exists, terminate).await {
+        Either::Left(..) => true,
+        Either::Right(..) => false,
+    }
+}
+
+/// Warm up the caches for every commit a bookmark is moved to, starting from the current end of
+/// the bookmark update log. This never returns unless reading the log fails.
+async fn tail_bookmark_updates(ctx: &CoreContext, repo: &BlobRepo) -> Result<(), Error> {
+    let mut last_id = repo
+        .attribute_expected::<dyn BookmarkUpdateLog>()
+        .get_largest_log_id(ctx.clone(), Freshness::MostRecent)
+        .await?
+        .unwrap_or(0);
+
+    loop {
+        match warm_up_next_entries(ctx, repo, last_id).await? {
+            Some(id) => last_id = id,
+            None => delay_for(TAIL_INTERVAL).await,
+        }
+    }
+}
+
+/// Warm up the caches for the commits that the log entries after `last_id` move bookmarks to.
+/// Returns the id of the last of these entries, or None if there are none yet.
+async fn warm_up_next_entries(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    last_id: u64,
+) -> Result<Option<u64>, Error> {
+    let entries: Vec<_> = repo
+        .read_next_bookmark_log_entries(
+            ctx.clone(),
+            last_id,
+            LOG_ENTRIES_LIMIT,
+            Freshness::MostRecent,
+        )
+        .try_collect()
+        .await?;
+
+    let last_id = match entries.last() {
+        Some(entry) => entry.id as u64,
+        None => return Ok(None),
+    };
+
+    STATS::log_entries.add_value(entries.len() as i64);
+    for entry in entries {
+        let bcs_id = match entry.to_changeset_id {
+            Some(bcs_id) => bcs_id,
+            // Deleted bookmarks have nothing to warm up.
+            None => continue,
+        };
+
+        let req = CacheWarmupRequest {
+            target: CacheWarmupTarget::Changeset(bcs_id),
+            commit_limit: COMMIT_WARMUP_LIMIT,
+            microwave_preload: false,
+        };
+        if let Err(e) = cache_warmup(ctx, repo, Some(req)).await {
+            // Not fatal: the commit will just be served from cold caches after promotion.
+            STATS::warmup_failures.add_value(1);
+            warn!(
+                ctx.logger(),
+                "Failed to warm up {} for {}: {:?}", bcs_id, entry.bookmark_name, e
+            );
+        }
+    }
+
+    Ok(Some(last_id))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blobrepo_factory::TestRepoBuilder;
+    use futures::channel::oneshot;
+    use tests_utils::{bookmark, CreateCommitContext};
+
+    #[tokio::test]
+    async fn test_wait_for_file() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("promoted");
+
+        let create = {
+            let path = path.clone();
+            async move {
+                delay_for(PROMOTION_CHECK_INTERVAL * 2).await;
+                std::fs::write(path, b"")
+            }
+        };
+        let (created, promoted) =
+            future::join(create, wait_for_file(&path, future::pending::<()>())).await;
+        created?;
+        assert!(promoted);
+
+        // Once the file exists, there is no waiting at all.
+        assert!(wait_for_file(&path, future::pending::<()>()).await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_file_terminated() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("promoted");
+
+        let (terminate_sender, terminate_receiver) = oneshot::channel::<()>();
+        let terminate = async move {
+            delay_for(PROMOTION_CHECK_INTERVAL * 2).await;
+            let _ = terminate_sender.send(());
+        };
+        let ((), promoted) =
+            future::join(terminate, wait_for_file(&path, terminate_receiver)).await;
+        assert!(!promoted);
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_warm_up_next_entries(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = TestRepoBuilder::new().build()?;
+
+        assert_eq!(warm_up_next_entries(&ctx, &repo, 0).await?, None);
+
+        let root = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file", "content")
+            .commit()
+            .await?;
+        bookmark(&ctx, &repo, "master").set_to(root).await?;
+        bookmark(&ctx, &repo, "other").set_to(root).await?;
+
+        let last_id = warm_up_next_entries(&ctx, &repo, 0)
+            .await?
+            .expect("the bookmark moves are logged");
+        assert_eq!(
+            Some(last_id),
+            repo.attribute_expected::<dyn BookmarkUpdateLog>()
+                .get_largest_log_id(ctx.clone(), Freshness::MostRecent)
+                .await?
+        );
+        // Entries are only warmed up once.
+        assert_eq!(warm_up_next_entries(&ctx, &repo, last_id).await?, None);
+
+        bookmark(&ctx, &repo, "other").delete().await?;
+        assert!(warm_up_next_entries(&ctx, &repo, last_id).await?.unwrap() > last_id);
+        Ok(())
+    }
+}
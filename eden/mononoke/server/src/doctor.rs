@@ -0,0 +1,191 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Checks that the server would be able to start with its configuration, without serving
+//! anything, and reports the result in a format that automation can consume.
+
+use std::fs;
+
+use anyhow::{bail, Error, Result};
+use blobstore_factory::{
+    make_blobstore, make_metadata_sql_factory, BlobstoreOptions, ReadOnlyStorage,
+};
+use cached_config::ConfigStore;
+use context::CoreContext;
+use fbinit::FacebookInit;
+use futures::compat::Future01CompatExt;
+use metaconfig_parser::RepoConfigs;
+use mutable_counters::{MutableCounters, SqlMutableCounters};
+use openssl::asn1::Asn1Time;
+use openssl::pkey::PKey;
+use openssl::x509::X509;
+use serde_json::json;
+use slog::Logger;
+use sql_ext::facebook::MysqlOptions;
+
+// Certificates that expire sooner than this are reported, so that they get renewed before the
+// server stops accepting connections.
+const CERT_EXPIRY_WARNING_DAYS: i32 = 7;
+
+pub struct DoctorEnv<'a> {
+    pub fb: FacebookInit,
+    pub logger: &'a Logger,
+    pub config_store: &'a ConfigStore,
+    pub mysql_options: MysqlOptions,
+    pub readonly_storage: ReadOnlyStorage,
+    pub blobstore_options: BlobstoreOptions,
+    pub cert: String,
+    pub private_key: String,
+    pub ca_pem: String,
+}
+
+struct Check {
+    name: String,
+    result: Result<String>,
+}
+
+#[derive(Default)]
+struct Report {
+    checks: Vec<Check>,
+}
+
+impl Report {
+    fn add(&mut self, name: impl Into<String>, result: Result<String>) {
+        self.checks.push(Check {
+            name: name.into(),
+            result,
+        });
+    }
+
+    fn failures(&self) -> usize {
+        self.checks.iter().filter(|c| c.result.is_err()).count()
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let checks: Vec<_> = self
+            .checks
+            .iter()
+            .map(|check| match &check.result {
+                Ok(message) => json!({"name": check.name, "ok": true, "message": message}),
+                Err(e) => json!({"name": check.name, "ok": false, "message": format!("{:#}", e)}),
+            })
+            .collect();
+        json!({"ok": self.failures() == 0, "checks": checks})
+    }
+}
+
+/// Run all the checks, print the report to stdout, and fail if any of the checks failed.
+pub async fn run(env: DoctorEnv<'_>, configs: Result<RepoConfigs>) -> Result<()> {
+    let mut report = Report::default();
+
+    report.add(
+        "certificate",
+        check_certificate(&env.cert, &env.private_key),
+    );
+    report.add("ca certificate", check_ca(&env.ca_pem));
+
+    match configs {
+        Err(e) => report.add("repo configs", Err(e)),
+        Ok(configs) => {
+            report.add(
+                "repo configs",
+                Ok(format!("{} repos configured", configs.repos.len())),
+            );
+            check_storage(&env, &configs, &mut report).await;
+        }
+    }
+
+    println!("{}", serde_json::to_string_pretty(&report.to_json())?);
+
+    let failures = report.failures();
+    if failures > 0 {
+        bail!("{} of {} checks failed", failures, report.checks.len());
+    }
+    Ok(())
+}
+
+fn check_certificate(cert: &str, private_key: &str) -> Result<String> {
+    let cert = X509::from_pem(&fs::read(cert)?)?;
+    let key = PKey::private_key_from_pem(&fs::read(private_key)?)?;
+    if !cert.public_key()?.public_eq(&key) {
+        bail!("private key does not match the certificate");
+    }
+
+    let now = Asn1Time::days_from_now(0)?;
+    let since = cert.not_before().diff(&now)?;
+    if since.days < 0 || since.secs < 0 {
+        bail!("certificate is not valid before {}", cert.not_before());
+    }
+    let left = now.diff(cert.not_after())?;
+    if left.days < 0 || left.secs < 0 || (left.days == 0 && left.secs == 0) {
+        bail!("certificate expired on {}", cert.not_after());
+    }
+    if left.days < CERT_EXPIRY_WARNING_DAYS {
+        bail!(
+            "certificate expires in less than {} days, on {}",
+            CERT_EXPIRY_WARNING_DAYS,
+            cert.not_after()
+        );
+    }
+    Ok(format!("valid until {}", cert.not_after()))
+}
+
+fn check_ca(ca_pem: &str) -> Result<String> {
+    let certs = X509::stack_from_pem(&fs::read(ca_pem)?)?;
+    if certs.is_empty() {
+        bail!("no certificates in {}", ca_pem);
+    }
+    Ok(format!("{} certificates", certs.len()))
+}
+
+async fn check_storage(env: &DoctorEnv<'_>, configs: &RepoConfigs, report: &mut Report) {
+    let ctx = CoreContext::new_with_logger(env.fb, env.logger.clone());
+
+    let mut repos: Vec<_> = configs
+        .repos
+        .iter()
+        .filter(|(_, config)| config.enabled)
+        .collect();
+    repos.sort_by_key(|(name, _)| name.as_str());
+
+    for (name, config) in repos {
+        let blobstore = async {
+            let blobstore = make_blobstore(
+                env.fb,
+                config.storage_config.blobstore.clone(),
+                &env.mysql_options,
+                env.readonly_storage,
+                &env.blobstore_options,
+                env.logger,
+                env.config_store,
+            )
+            .await?;
+            // A key that doesn't exist: this is only about being able to talk to the blobstore.
+            blobstore.is_present(&ctx, "mononoke-doctor-probe").await?;
+            Ok::<_, Error>(format!("{}", blobstore))
+        };
+        report.add(format!("repo {}: blobstore", name), blobstore.await);
+
+        let metadata = async {
+            let sql_factory = make_metadata_sql_factory(
+                env.fb,
+                config.storage_config.metadata.clone(),
+                env.mysql_options.clone(),
+                env.readonly_storage,
+                env.logger,
+            )
+            .await?;
+            let counters = sql_factory.open::<SqlMutableCounters>().await?;
+            let counters = counters
+                .get_all_counters(ctx.clone(), config.repoid)
+                .compat()
+                .await?;
+            Ok::<_, Error>(format!("{} mutable counters", counters.len()))
+        };
+        report.add(format!("repo {}: metadata database", name), metadata.await);
+    }
+}
@@ -8,7 +8,11 @@
 #![deny(warnings)]
 #![feature(never_type)]
 
+mod doctor;
+
 use anyhow::{Context, Result};
+use blobrepo_factory::Caching;
+use blobstore_factory::ReadOnlyStorage;
 use clap::Arg;
 use cloned::cloned;
 use cmdlib::{args, monitoring::ReadyFlagService};
@@ -18,8 +22,9 @@ use futures_watchdog::WatchdogExt;
 use mononoke_api::{
     BookmarkUpdateDelay, Mononoke, MononokeEnvironment, WarmBookmarksCacheDerivedData,
 };
-use openssl::ssl::AlpnError;
+use openssl::ssl::{AlpnError, SslConnector, SslMethod};
 use slog::{error, info};
+use std::path::PathBuf;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
@@ -31,6 +36,10 @@ const ARG_CERT: &str = "cert";
 const ARG_PRIVATE_KEY: &str = "private-key";
 const ARG_CA_PEM: &str = "ca-pem";
 const ARG_TICKET_SEEDS: &str = "ssl-ticket-seeds";
+const ARG_WARM_STANDBY_PROMOTION_FILE: &str = "warm-standby-promotion-file";
+const ARG_SHADOW_HOST: &str = "shadow-host";
+const ARG_DOCTOR: &str = "doctor";
+const ARG_EXTERNAL_BOOKMARK_WARMER: &str = "external-bookmark-warmer";
 
 fn setup_app<'a, 'b>() -> args::MononokeClapApp<'a, 'b> {
     let app = args::MononokeAppBuilder::new("mononoke server")
@@ -81,6 +90,37 @@ fn setup_app<'a, 'b>() -> args::MononokeClapApp<'a, 'b> {
                 .long(ARG_TICKET_SEEDS)
                 .takes_value(true)
                 .help("path to a file with encryption keys for SSL tickets'"),
+        )
+        .arg(
+            Arg::with_name(ARG_WARM_STANDBY_PROMOTION_FILE)
+                .long(ARG_WARM_STANDBY_PROMOTION_FILE)
+                .takes_value(true)
+                .help(
+                    "run as a warm standby: build repos and keep their caches warm by tailing \
+                     bookmark updates, but only start listening once this file exists",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_SHADOW_HOST)
+                .long(ARG_SHADOW_HOST)
+                .takes_value(true)
+                .value_name("HOST:PORT")
+                .help(
+                    "shadow a sample of the read-only HTTP traffic (as per the \
+                     shadow_traffic_percentage tunable) to this test host, discarding its responses",
+                ),
+        )
+        .arg(Arg::with_name(ARG_DOCTOR).long(ARG_DOCTOR).help(
+            "don't serve anything: check that the configuration, certificates, blobstores and \
+             databases are usable, print a JSON report and exit",
+        ))
+        .arg(
+            Arg::with_name(ARG_EXTERNAL_BOOKMARK_WARMER)
+                .long(ARG_EXTERNAL_BOOKMARK_WARMER)
+                .help(
+                    "don't derive the data of bookmarks as they move, wait for the \
+                     bookmark_warmer to do it instead",
+                ),
         );
 
     let app = args::add_mcrouter_args(app);
@@ -93,8 +133,24 @@ fn main(fb: FacebookInit) -> Result<()> {
     let matches = setup_app().get_matches();
     cmdlib::args::maybe_enable_mcrouter(fb, &matches);
 
-    let (caching, root_log, runtime) = cmdlib::args::init_mononoke(fb, &matches)?;
+    let (caching, root_log, mut runtime) = cmdlib::args::init_mononoke(fb, &matches)?;
     let config_store = cmdlib::args::init_config_store(fb, &root_log, &matches)?;
+
+    if matches.is_present(ARG_DOCTOR) {
+        let env = doctor::DoctorEnv {
+            fb,
+            logger: &root_log,
+            config_store,
+            mysql_options: cmdlib::args::parse_mysql_options(&matches),
+            readonly_storage: cmdlib::args::parse_readonly_storage(&matches),
+            blobstore_options: cmdlib::args::parse_blobstore_options(&matches)?,
+            cert: matches.value_of(ARG_CERT).unwrap().to_string(),
+            private_key: matches.value_of(ARG_PRIVATE_KEY).unwrap().to_string(),
+            ca_pem: matches.value_of(ARG_CA_PEM).unwrap_or_default().to_string(),
+        };
+        let configs = args::load_repo_configs(config_store, &matches);
+        return runtime.block_on(doctor::run(env, configs));
+    }
     let observability_context = cmdlib::args::init_observability_context(fb, &matches, &root_log)?;
 
     info!(root_log, "Starting up");
@@ -125,6 +181,29 @@ fn main(fb: FacebookInit) -> Result<()> {
         builder.build()
     };
 
+    let shadowing = match matches.value_of(ARG_SHADOW_HOST) {
+        Some(host) => {
+            let cert = matches.value_of(ARG_CERT).unwrap().to_string();
+            let private_key = matches.value_of(ARG_PRIVATE_KEY).unwrap().to_string();
+            let ca_pem = matches.value_of(ARG_CA_PEM).unwrap().to_string();
+
+            let mut connector = SslConnector::builder(SslMethod::tls())?;
+            let pkcs12 = secure_utils::build_identity(cert, private_key)?;
+            connector.set_certificate(&pkcs12.cert)?;
+            connector.set_private_key(&pkcs12.pkey)?;
+            connector
+                .cert_store_mut()
+                .add_cert(secure_utils::read_x509(ca_pem)?)?;
+
+            info!(root_log, "Shadowing traffic to {}", host);
+            Some(repo_listener::Shadowing::new(
+                host.to_string(),
+                connector.build(),
+            )?)
+        }
+        None => None,
+    };
+
     info!(root_log, "Creating repo listeners");
 
     let service = ReadyFlagService::new();
@@ -145,6 +224,14 @@ fn main(fb: FacebookInit) -> Result<()> {
     scuba.add_common_server_data();
 
     let will_exit = Arc::new(AtomicBool::new(false));
+    let warm_standby = matches
+        .value_of(ARG_WARM_STANDBY_PROMOTION_FILE)
+        .map(|path| repo_listener::WarmStandby::new(PathBuf::from(path)));
+    let warm_bookmarks_cache_derived_data = if matches.is_present(ARG_EXTERNAL_BOOKMARK_WARMER) {
+        WarmBookmarksCacheDerivedData::WaitForHg
+    } else {
+        WarmBookmarksCacheDerivedData::HgOnly
+    };
 
     let repo_listeners = {
         cloned!(root_log, service, will_exit);
@@ -158,7 +245,7 @@ fn main(fb: FacebookInit) -> Result<()> {
                 blobstore_options,
                 config_store,
                 disabled_hooks,
-                warm_bookmarks_cache_derived_data: WarmBookmarksCacheDerivedData::HgOnly,
+                warm_bookmarks_cache_derived_data,
                 warm_bookmarks_cache_delay: BookmarkUpdateDelay::Disallow,
             };
 
@@ -167,10 +254,31 @@ fn main(fb: FacebookInit) -> Result<()> {
                 .await?;
             info!(&root_log, "Built Mononoke");
 
+            let read_mirror = match edenapi_service::read_mirror_configs(&config) {
+                Some(mirror_config) => {
+                    // Nothing is ever written to the storage that reads are mirrored to. Nor are
+                    // the mirrored reads cached: the caches are shared with the repos that are
+                    // served, under the same keys, so the reads would never reach the mirror
+                    // storage.
+                    let env = MononokeEnvironment {
+                        readonly_storage: ReadOnlyStorage(true),
+                        caching: Caching::Disabled,
+                        ..env
+                    };
+                    let read_mirror = Mononoke::new(&env, mirror_config)
+                        .watched(&root_log)
+                        .await?;
+                    info!(&root_log, "Built EdenAPI read mirror");
+                    Some(read_mirror)
+                }
+                None => None,
+            };
+
             repo_listener::create_repo_listeners(
                 fb,
                 config.common,
                 mononoke,
+                read_mirror,
                 &mysql_options,
                 root_log,
                 host_port,
@@ -182,6 +290,8 @@ fn main(fb: FacebookInit) -> Result<()> {
                 scribe,
                 &scuba,
                 will_exit,
+                warm_standby,
+                shadowing,
             )
             .await
         }
@@ -71,6 +71,7 @@ pub struct Metadata {
     client_debug: bool,
     client_ip: Option<IpAddr>,
     client_hostname: Option<String>,
+    resumption_token: Option<String>,
 }
 
 impl Metadata {
@@ -107,6 +108,7 @@ impl Metadata {
             client_debug,
             client_ip,
             client_hostname,
+            resumption_token: None,
         }
     }
 
@@ -167,6 +169,16 @@ impl Metadata {
         self
     }
 
+    /// Token presented by a reconnecting client to resume the state of an earlier session.
+    pub fn resumption_token(&self) -> Option<&str> {
+        self.resumption_token.as_deref()
+    }
+
+    pub fn set_resumption_token(mut self, resumption_token: Option<String>) -> Self {
+        self.resumption_token = resumption_token;
+        self
+    }
+
     pub fn unix_name(&self) -> Option<&str> {
         for identity in self.identities() {
             if identity.id_type() == "USER" {
@@ -211,6 +223,10 @@ impl Preamble {
     pub fn unix_name(&self) -> Option<&str> {
         self.misc.get("unix_username").map(AsRef::as_ref)
     }
+
+    pub fn resumption_token(&self) -> Option<&str> {
+        self.misc.get("resumption_token").map(AsRef::as_ref)
+    }
 }
 
 // Matches Iostream in Mercurial mononokepeer.py
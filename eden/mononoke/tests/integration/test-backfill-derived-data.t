@@ -53,3 +53,16 @@ backfill derived data
   * derived fsnodes in * (glob)
   $ backfill_derived_data single c3384961b16276f2db77df9d7c874bbe981cf0525bd6f84a502f919044f2dabd --all-types 2>&1 | grep derived | wc -l
   9
+
+invalidate derived data
+  $ mononoke_admin --log-level ERROR derived-data invalidate "$DERIVED_DATA_TYPE" --start 426bada5c67598ca65036d57d9e4b64b0c1ce7a0 --end master_bookmark --dry-run
+  Would invalidate fsnodes for 3 commits:
+  9feb8ddd3e8eddcfa3a4913b57df7842bedf84b8ea3b7b3fcb14c6424aa81fec
+  459f16ae564c501cb408c1e5b60fc98a1e8b8e97b9409c7520658bfa1577fb66
+  c3384961b16276f2db77df9d7c874bbe981cf0525bd6f84a502f919044f2dabd
+  $ echo n | mononoke_admin --log-level ERROR derived-data invalidate "$DERIVED_DATA_TYPE" --start 426bada5c67598ca65036d57d9e4b64b0c1ce7a0 --end master_bookmark
+  Invalidate fsnodes for 3 commits? [y/N] Aborted
+  $ mononoke_admin --log-level ERROR derived-data invalidate "$DERIVED_DATA_TYPE" --start 426bada5c67598ca65036d57d9e4b64b0c1ce7a0 --end master_bookmark --yes
+  Invalidated fsnodes for 3 commits
+  $ mononoke_admin --log-level ERROR derived-data exists "$DERIVED_DATA_TYPE" master_bookmark
+  Derived: c3384961b16276f2db77df9d7c874bbe981cf0525bd6f84a502f919044f2dabd
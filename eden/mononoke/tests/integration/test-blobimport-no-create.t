@@ -42,8 +42,8 @@ blobimport --no-create with no storage present, should fail due to missing direc
 blobimport, succeeding, creates directory if not existing
   $ blobimport --log repo-hg/.hg repo
   * using repo "repo" repoid RepositoryId(0) (glob)
-  * inserted commits # 0 (glob)
   * Deriving data for: ["filenodes"] (glob)
+  * inserted commits: * (glob)
   * finished uploading changesets, globalrevs and deriving data (glob)
   * uploaded chunk of 1 bookmarks (glob)
   * latest imported revision 2 (glob)
@@ -55,8 +55,8 @@ check the bookmark is there after import
 blobimport --no-create after successful import, should be fine as storage shared with previous good run
   $ blobimport --log repo-hg/.hg repo --no-create
   * using repo "repo" repoid RepositoryId(0) (glob)
-  * inserted commits # 0 (glob)
   * Deriving data for: ["filenodes"] (glob)
+  * inserted commits: * (glob)
   * finished uploading changesets, globalrevs and deriving data (glob)
   * uploaded chunk of 0 bookmarks (glob)
   * latest imported revision 2 (glob)
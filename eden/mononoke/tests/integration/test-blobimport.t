@@ -58,8 +58,8 @@
   * didn't import any commits (glob)
   $ blobimport --log repo-hg/.hg repo --commits-limit 1
   * using repo "repo" repoid RepositoryId(0) (glob)
-  * inserted commits # 0 (glob)
   * Deriving data for: ["filenodes"] (glob)
+  * inserted commits: * (glob)
   * finished uploading changesets, globalrevs and deriving data (glob)
   * latest imported revision 0 (glob)
   $ blobimport --log repo-hg/.hg repo --find-already-imported-rev-only
@@ -0,0 +1,22 @@
+# Copyright (c) Facebook, Inc. and its affiliates.
+#
+# This software may be used and distributed according to the terms of the
+# GNU General Public License found in the LICENSE file in the root
+# directory of this source tree.
+
+  $ . "${TEST_FIXTURES}/library.sh"
+  $ setup_common_config
+  $ mononoke
+  $ wait_for_mononoke
+
+  $ sslcurl -X POST -fsS "https://localhost:$MONONOKE_SOCKET/control/log_level/repo_client/debug"
+  repo_client DEBUG
+  $ sslcurl -X POST -fsS "https://localhost:$MONONOKE_SOCKET/control/log_level/repo_client::client/error"
+  repo_client DEBUG
+  repo_client::client ERROR
+  $ sslcurl -X POST -fsS "https://localhost:$MONONOKE_SOCKET/control/log_level/repo_client/reset"
+  repo_client::client ERROR
+
+  $ sslcurl -X POST -fsS "https://localhost:$MONONOKE_SOCKET/control/log_level/repo_client/loud"
+  curl: (22) The requested URL returned error: 400 Bad Request
+  [22]
@@ -6,6 +6,7 @@
  */
 
 use std::cell::RefCell;
+use std::collections::BTreeSet;
 use std::ops::Deref;
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -18,7 +19,7 @@ use cached_config::ConfigHandle;
 use futures::{future::poll_fn, Future, FutureExt};
 use once_cell::sync::OnceCell;
 use slog::{debug, warn, Logger};
-use std::sync::atomic::{AtomicBool, AtomicI64};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
 
 use tunables_derive::Tunables;
 use tunables_structs::Tunables as TunablesStruct;
@@ -27,6 +28,8 @@ use std::collections::HashMap;
 
 static TUNABLES: OnceCell<MononokeTunables> = OnceCell::new();
 static TUNABLES_WORKER_STATE: OnceCell<Mutex<TunablesWorkerState>> = OnceCell::new();
+static SUBSCRIBERS: OnceCell<Mutex<Vec<Subscriber>>> = OnceCell::new();
+static NEXT_SUBSCRIBER_ID: AtomicU64 = AtomicU64::new(0);
 const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
 
 thread_local! {
@@ -49,10 +52,14 @@ impl Deref for TunablesReference {
     }
 }
 
+fn global_tunables() -> &'static MononokeTunables {
+    TUNABLES.get_or_init(MononokeTunables::default)
+}
+
 pub fn tunables() -> TunablesReference {
     TUNABLES_OVERRIDE.with(|tunables_override| match *tunables_override.borrow() {
         Some(ref arc) => TunablesReference::Override(arc.clone()),
-        None => TunablesReference::Static(TUNABLES.get_or_init(MononokeTunables::default)),
+        None => TunablesReference::Static(global_tunables()),
     })
 }
 
@@ -63,7 +70,28 @@ pub type TunableBoolByRepo = ArcSwap<HashMap<String, bool>>;
 pub type TunableStringByRepo = ArcSwap<HashMap<String, String>>;
 pub type TunableI64ByRepo = ArcSwap<HashMap<String, i64>>;
 
-#[derive(Tunables, Default, Debug)]
+/// The type of a tunable, as seen in the tunables config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TunableKind {
+    Bool,
+    I64,
+    String,
+    BoolByRepo,
+    I64ByRepo,
+    StringByRepo,
+}
+
+/// Description of a tunable, generated from its definition in the struct deriving `Tunables`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TunableDefinition {
+    pub name: &'static str,
+    pub kind: TunableKind,
+    /// The value used when the tunables config doesn't set it, rendered as a string.
+    pub default: &'static str,
+    pub description: &'static str,
+}
+
+#[derive(Tunables, Debug)]
 pub struct MononokeTunables {
     mutation_advertise_for_infinitepush: AtomicBool,
     mutation_accept_for_infinitepush: AtomicBool,
@@ -92,7 +120,7 @@ pub struct MononokeTunables {
     // SCS scuba sampling knobs
     scs_popular_methods_sampling_rate: AtomicI64,
     scs_other_methods_sampling_rate: AtomicI64,
-    // When false error logs are never sampled
+    /// When false error logs are never sampled
     scs_error_log_sampling: AtomicBool,
     redacted_logging_sampling_rate: AtomicI64,
     getbundle_use_low_gen_optimization: AtomicBool,
@@ -105,39 +133,102 @@ pub struct MononokeTunables {
     repo_client_getbundle_timeout_secs: AtomicI64,
     repo_client_getpack_timeout_secs: AtomicI64,
     repo_client_concurrent_blob_uploads: AtomicI64,
+    repo_client_getcommitdata_concurrency: AtomicI64,
     derived_data_slow_derivation_threshold_secs: AtomicI64,
+    /// SQL queries slower than this are logged along with the session and caller that ran them.
+    /// Disabled if not positive.
+    sql_slow_query_threshold_ms: AtomicI64,
     disable_running_hooks_in_pushredirected_repo: AtomicBool,
     scs_request_read_qps: AtomicI64,
     scs_request_write_qps: AtomicI64,
     enable_logging_commit_rewrite_data: AtomicBool,
-    // All blobstore read request with size bigger than
-    // this threshold will be logged to scuba
+    /// All blobstore read request with size bigger than
+    /// this threshold will be logged to scuba
     blobstore_read_size_logging_threshold: AtomicI64,
     hash_validation_percentage: AtomicI64,
-    // Filter out commits that we already have in infinitepush. Shouldn't be needed if we have a
-    // client exchanging commits with us, but when processing bundled uploads (i.e. commit cloud
-    // filling), it might help a lot.
+    /// Filter out commits that we already have in infinitepush. Shouldn't be needed if we have a
+    /// client exchanging commits with us, but when processing bundled uploads (i.e. commit cloud
+    /// filling), it might help a lot.
     filter_pre_existing_commits_on_infinitepush: AtomicBool,
     backfill_read_qps: AtomicI64,
     backfill_write_qps: AtomicI64,
     disable_commit_scribe_logging_scs: AtomicBool,
     xrepo_sync_disable_all_syncs: AtomicBool,
 
-    // Use Background session class while deriving data. This makes derived data not write
-    // data to blobstore sync queue if a write was successful to the main blobstore.
+    /// Use Background session class while deriving data. This makes derived data not write
+    /// data to blobstore sync queue if a write was successful to the main blobstore.
     derived_data_use_background_session_class: TunableBoolByRepo,
     commit_cloud_use_background_session_class: AtomicBool,
 
     allow_change_xrepo_mapping_extra: AtomicBool,
 
-    // Disable EdenAPI in http_service.
+    /// Disable EdenAPI in http_service.
     disable_http_service_edenapi: AtomicBool,
 
-    // Disable putting hydrating manifests in .hg
+    /// http_service sheds requests with a 503 once this many are in flight. 0 disables shedding.
+    http_service_max_in_flight_requests: AtomicI64,
+    /// The shortest retry delay suggested to shed clients, which is scaled up with how overloaded
+    /// the server is, and jittered.
+    http_service_retry_after_base_secs: AtomicI64,
+
+    /// Disable putting hydrating manifests in .hg
     disable_hydrating_manifests_in_dot_hg: AtomicBool,
 
-    // Enable storing prepushrebase changeset id in bonsai changeset extra
+    /// Enable storing prepushrebase changeset id in bonsai changeset extra
     enable_storing_prepushrebase_cs_id_in_extra: AtomicBool,
+
+    /// Comma separated list of tunables that trusted clients may override for a single request
+    /// to http_service.
+    http_tunables_override_allowlist: TunableString,
+
+    /// When set, this message is shown to wireproto clients when they connect, and returned to
+    /// EdenAPI clients in a response header. Used to announce planned maintenance.
+    maintenance_message: TunableString,
+
+    /// Comma separated list of EdenAPI methods, named as in their stats, that are switched off.
+    /// Requests to them fail with a 503, and the capabilities endpoint lists them as disabled.
+    edenapi_disabled_methods: TunableString,
+
+    /// Included in the errors returned for redacted content, so that users know who to talk to.
+    /// `{task}` is replaced with the task the content was redacted for.
+    redaction_reference_url: TunableString,
+
+    /// Included in the error that wireproto clients get when a command fails, so that users know
+    /// what to do about it. `{code}` is replaced with the error code.
+    error_help_url: TunableString,
+
+    /// How long a wireproto session can be resumed for after the client disconnects. 0 disables
+    /// session resumption.
+    session_resumption_ttl_secs: AtomicI64,
+
+    /// Advertised to wireproto clients, which include it in their cache keys. Bumping it
+    /// invalidates client and proxy caches.
+    cache_epoch: AtomicI64,
+
+    /// Percentage of the read-only HTTP traffic that is shadowed to the test host, if the server
+    /// was started with one.
+    shadow_traffic_percentage: AtomicI64,
+
+    /// If set, sqlblob reads that have waited this many milliseconds on a replica are sent to
+    /// the master as well, and whichever answers first is used.
+    sqlblob_hedge_threshold_ms: AtomicI64,
+    /// How many sqlblob reads may be sent to the master per second because they were slow on a
+    /// replica (see sqlblob_hedge_threshold_ms). None are if this isn't set.
+    sqlblob_hedges_per_sec: AtomicI64,
+    /// How many queries sqlblob runs on each shard at once. The others queue until a slot frees
+    /// up. This is read when the blobstore is opened, and defaults to 100 if not positive.
+    sqlblob_max_queries_per_shard: AtomicI64,
+    /// How long a sqlblob query waits in the queue of a saturated shard before it fails. Defaults
+    /// to 5000 if not positive.
+    sqlblob_queue_deadline_ms: AtomicI64,
+
+    /// If set, getbundle tells the client how many of the changesets it sends have been
+    /// prepared, every this many seconds.
+    getbundle_progress_interval_secs: AtomicI64,
+
+    /// Store the contents of small files inline in their aliases, for filestores configured with
+    /// an inline threshold. Only to be enabled once every reader can decode such aliases.
+    filestore_write_inline_aliases: AtomicBool,
 }
 
 fn log_tunables(tunables: &TunablesStruct) -> String {
@@ -220,7 +311,9 @@ fn worker_iteration() {
         );
         match update_tunables(new_tunables.clone()) {
             Ok(_) => {
+                let changed = changed_tunables(state.old_tunables.as_deref(), &new_tunables);
                 state.old_tunables = Some(new_tunables);
+                notify_subscribers(&changed);
             }
             Err(e) => {
                 warn!(state.logger, "Failed to refresh tunables: {}", e);
@@ -232,6 +325,11 @@ fn worker_iteration() {
 
 fn update_tunables(new_tunables: Arc<TunablesStruct>) -> Result<()> {
     let tunables = tunables();
+    apply_tunables(&tunables, &new_tunables);
+    Ok(())
+}
+
+fn apply_tunables(tunables: &MononokeTunables, new_tunables: &TunablesStruct) {
     tunables.update_bools(&new_tunables.killswitches);
     tunables.update_ints(&new_tunables.ints);
     tunables.update_strings(&new_tunables.strings);
@@ -239,8 +337,180 @@ fn update_tunables(new_tunables: Arc<TunablesStruct>) -> Result<()> {
     if let Some(killswitches_by_repo) = &new_tunables.killswitches_by_repo {
         tunables.update_by_repo_bools(killswitches_by_repo);
     }
+}
 
-    Ok(())
+// Names of the tunables whose values differ between `old` and `new`. Tunables that `new` doesn't
+// set keep their value, so they haven't changed.
+fn changed_tunables(old: Option<&TunablesStruct>, new: &TunablesStruct) -> BTreeSet<String> {
+    fn changed_values<V: PartialEq>(
+        old: Option<&HashMap<String, V>>,
+        new: &HashMap<String, V>,
+        changed: &mut BTreeSet<String>,
+    ) {
+        for (name, value) in new {
+            if old.and_then(|old| old.get(name)) != Some(value) {
+                changed.insert(name.clone());
+            }
+        }
+    }
+
+    let mut changed = BTreeSet::new();
+    changed_values(
+        old.map(|old| &old.killswitches),
+        &new.killswitches,
+        &mut changed,
+    );
+    changed_values(old.map(|old| &old.ints), &new.ints, &mut changed);
+    changed_values(old.map(|old| &old.strings), &new.strings, &mut changed);
+
+    // By-repo tunables are replaced as a whole, so a tunable also changes when it is no longer set
+    // for a repo.
+    let no_repos = HashMap::new();
+    let no_values = HashMap::new();
+    let old_by_repo = old
+        .and_then(|old| old.killswitches_by_repo.as_ref())
+        .unwrap_or(&no_repos);
+    let new_by_repo = new.killswitches_by_repo.as_ref().unwrap_or(&no_repos);
+    for repo in old_by_repo.keys().chain(new_by_repo.keys()) {
+        let old_values = old_by_repo.get(repo).unwrap_or(&no_values);
+        let new_values = new_by_repo.get(repo).unwrap_or(&no_values);
+        changed_values(Some(old_values), new_values, &mut changed);
+        changed_values(Some(new_values), old_values, &mut changed);
+    }
+
+    changed
+}
+
+struct Subscriber {
+    id: u64,
+    names: Vec<String>,
+    callback: Arc<dyn Fn(&BTreeSet<String>) + Send + Sync>,
+}
+
+fn subscribers() -> &'static Mutex<Vec<Subscriber>> {
+    SUBSCRIBERS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+fn notify_subscribers(changed: &BTreeSet<String>) {
+    if changed.is_empty() {
+        return;
+    }
+
+    // Don't hold the lock while running callbacks, so that they can subscribe and unsubscribe.
+    let callbacks: Vec<_> = subscribers()
+        .lock()
+        .expect("Poisoned lock")
+        .iter()
+        .filter(|s| s.names.is_empty() || s.names.iter().any(|name| changed.contains(name)))
+        .map(|s| s.callback.clone())
+        .collect();
+
+    for callback in callbacks {
+        callback(changed);
+    }
+}
+
+/// Returned by `subscribe`. The callback is unregistered when this is dropped.
+#[must_use = "the subscription is cancelled when dropped"]
+pub struct TunablesSubscription {
+    id: u64,
+}
+
+impl Drop for TunablesSubscription {
+    fn drop(&mut self) {
+        subscribers()
+            .lock()
+            .expect("Poisoned lock")
+            .retain(|s| s.id != self.id);
+    }
+}
+
+/// Call `callback` whenever one of the tunables in `names` changes, or any of them if `names` is
+/// empty, with the names of all the tunables that changed. The new values can be read with
+/// `tunables()` as usual.
+///
+/// Callbacks are run on the thread that refreshes tunables, so they should be quick. They are not
+/// called for the values tunables have when subscribing, so read those right away if needed.
+pub fn subscribe(
+    names: &[&str],
+    callback: impl Fn(&BTreeSet<String>) + Send + Sync + 'static,
+) -> TunablesSubscription {
+    let id = NEXT_SUBSCRIBER_ID.fetch_add(1, Ordering::Relaxed);
+    subscribers()
+        .lock()
+        .expect("Poisoned lock")
+        .push(Subscriber {
+            id,
+            names: names.iter().map(|name| name.to_string()).collect(),
+            callback: Arc::new(callback),
+        });
+    TunablesSubscription { id }
+}
+
+/// A value computed from tunables, e.g. a parsed list or a compiled regex, that is only
+/// recomputed when the tunables it depends on change, rather than every time it is used. Within
+/// `with_tunables` and `with_tunables_async`, it is computed from the overrides instead.
+pub struct DerivedTunable<T> {
+    value: Arc<ArcSwap<T>>,
+    compute: Arc<dyn Fn(&MononokeTunables) -> T + Send + Sync>,
+    _subscription: TunablesSubscription,
+}
+
+impl<T: Send + Sync + 'static> DerivedTunable<T> {
+    /// Compute the value with `compute`, and again whenever one of the tunables in `names`
+    /// changes.
+    pub fn new(
+        names: &[&str],
+        compute: impl Fn(&MononokeTunables) -> T + Send + Sync + 'static,
+    ) -> Self {
+        let compute: Arc<dyn Fn(&MononokeTunables) -> T + Send + Sync> = Arc::new(compute);
+        let value = Arc::new(ArcSwap::from_pointee(compute(global_tunables())));
+        let subscription = subscribe(names, {
+            let value = value.clone();
+            let compute = compute.clone();
+            move |_| value.store(Arc::new(compute(global_tunables())))
+        });
+        Self {
+            value,
+            compute,
+            _subscription: subscription,
+        }
+    }
+
+    pub fn get(&self) -> Arc<T> {
+        match tunables() {
+            TunablesReference::Override(tunables) => Arc::new((self.compute)(&tunables)),
+            TunablesReference::Static(_) => self.value.load_full(),
+        }
+    }
+}
+
+/// Build a copy of the current tunables with some values overridden, e.g. to run a single request
+/// with `with_tunables_async`. Each value is parsed according to the type of the tunable it
+/// overrides, and names that don't match a tunable of a compatible type are ignored.
+pub fn tunables_with_overrides(overrides: &HashMap<String, String>) -> MononokeTunables {
+    let tunables = MononokeTunables::default();
+
+    let current = TUNABLES_WORKER_STATE
+        .get()
+        .and_then(|state| state.lock().expect("Poisoned lock").old_tunables.clone());
+    if let Some(current) = current {
+        apply_tunables(&tunables, &current);
+    }
+
+    let bools = overrides
+        .iter()
+        .filter_map(|(name, val)| Some((name.clone(), val.parse().ok()?)))
+        .collect();
+    let ints = overrides
+        .iter()
+        .filter_map(|(name, val)| Some((name.clone(), val.parse().ok()?)))
+        .collect();
+    tunables.update_bools(&bools);
+    tunables.update_ints(&ints);
+    tunables.update_strings(overrides);
+
+    tunables
 }
 
 /// A helper function to override tunables during a closure's execution.
@@ -278,7 +548,7 @@ mod test {
     use std::collections::HashMap;
     use std::sync::atomic::AtomicBool;
 
-    #[derive(Tunables, Default)]
+    #[derive(Tunables)]
     struct TestTunables {
         boolean: AtomicBool,
         num: AtomicI64,
@@ -294,9 +564,22 @@ mod test {
         repostr2: TunableStringByRepo,
     }
 
-    #[derive(Tunables, Default)]
+    #[derive(Tunables)]
     struct EmptyTunables {}
 
+    #[derive(Tunables)]
+    struct DefaultTunables {
+        /// A number, documented
+        /// over two lines.
+        #[tunable(default = 5)]
+        num: AtomicI64,
+        #[tunable(default = true)]
+        boolean: AtomicBool,
+        #[tunable(default = "hello")]
+        string: TunableString,
+        repobool: TunableBoolByRepo,
+    }
+
     fn s(a: &str) -> String {
         a.to_string()
     }
@@ -317,6 +600,27 @@ mod test {
         assert_eq!(tunables().get_wishlist_write_qps(), 0);
     }
 
+    #[test]
+    fn test_tunables_with_overrides() {
+        let overrides = hashmap! {
+            s("wishlist_write_qps") => s("5"),
+            s("filenodes_disabled") => s("true"),
+            s("undesired_path_prefix_to_log") => s("foo/bar"),
+            s("missing") => s("1"),
+        };
+
+        let res = with_tunables(tunables_with_overrides(&overrides), || {
+            (
+                tunables().get_wishlist_write_qps(),
+                tunables().get_filenodes_disabled(),
+                tunables().get_undesired_path_prefix_to_log(),
+            )
+        });
+
+        assert_eq!(res, (5, true, Arc::new(s("foo/bar"))));
+        assert_eq!(tunables().get_wishlist_write_qps(), 0);
+    }
+
     #[test]
     fn test_empty_tunables() {
         let bools = HashMap::new();
@@ -538,6 +842,104 @@ mod test {
         assert_eq!(test.get_by_repo_repoint2("repo"), None);
     }
 
+    #[test]
+    fn test_defaults() {
+        let test = DefaultTunables::default();
+        assert_eq!(test.get_num(), 5);
+        assert_eq!(test.get_boolean(), true);
+        assert_eq!(test.get_string().as_str(), "hello");
+        assert_eq!(test.get_by_repo_repobool("repo"), None);
+
+        test.update_ints(&hashmap! { s("num") => 10 });
+        assert_eq!(test.get_num(), 10);
+    }
+
+    #[test]
+    fn test_definitions() {
+        assert_eq!(
+            DefaultTunables::definitions(),
+            vec![
+                TunableDefinition {
+                    name: "num",
+                    kind: TunableKind::I64,
+                    default: "5",
+                    description: "A number, documented over two lines.",
+                },
+                TunableDefinition {
+                    name: "boolean",
+                    kind: TunableKind::Bool,
+                    default: "true",
+                    description: "",
+                },
+                TunableDefinition {
+                    name: "string",
+                    kind: TunableKind::String,
+                    default: "hello",
+                    description: "",
+                },
+                TunableDefinition {
+                    name: "repobool",
+                    kind: TunableKind::BoolByRepo,
+                    default: "",
+                    description: "",
+                },
+            ]
+        );
+        assert!(EmptyTunables::definitions().is_empty());
+    }
+
+    #[test]
+    fn test_changed_tunables() {
+        let old = TunablesStruct {
+            killswitches: hashmap! { s("a") => true, s("b") => true },
+            ints: hashmap! { s("c") => 1 },
+            killswitches_by_repo: Some(hashmap! {
+                s("repo") => hashmap! { s("d") => true, s("e") => true },
+            }),
+            ..Default::default()
+        };
+        let new = TunablesStruct {
+            killswitches: hashmap! { s("a") => true, s("b") => false },
+            ints: hashmap! { s("c") => 1, s("f") => 2 },
+            strings: hashmap! { s("g") => s("value") },
+            killswitches_by_repo: Some(hashmap! {
+                s("repo") => hashmap! { s("d") => true },
+                s("repo2") => hashmap! { s("h") => false },
+            }),
+            ..Default::default()
+        };
+
+        let changed: Vec<_> = changed_tunables(Some(&old), &new).into_iter().collect();
+        assert_eq!(changed, vec![s("b"), s("e"), s("f"), s("g"), s("h")]);
+
+        let changed: Vec<_> = changed_tunables(None, &old).into_iter().collect();
+        assert_eq!(changed, vec![s("a"), s("b"), s("c"), s("d"), s("e")]);
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let subscription = subscribe(&["subscribed"], {
+            let calls = calls.clone();
+            move |changed| {
+                calls
+                    .lock()
+                    .unwrap()
+                    .push(changed.iter().cloned().collect::<Vec<_>>())
+            }
+        });
+
+        notify_subscribers(&vec![s("other")].into_iter().collect());
+        notify_subscribers(&vec![s("other"), s("subscribed")].into_iter().collect());
+        drop(subscription);
+        notify_subscribers(&vec![s("subscribed")].into_iter().collect());
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![vec![s("other"), s("subscribed")]]
+        );
+    }
+
     #[fbinit::test]
     async fn test_with_tunables_async(_fb: fbinit::FacebookInit) {
         let res = with_tunables_async(
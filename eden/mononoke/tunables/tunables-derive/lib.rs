@@ -9,10 +9,15 @@ extern crate proc_macro;
 
 use proc_macro2::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, Type};
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Fields, Ident, Lit, Meta, NestedMeta, Type,
+};
 
 const UNIMPLEMENTED_MSG: &str = "Only AtomicBool and AtomicI64 are supported";
 const STRUCT_FIELD_MSG: &str = "Only implemented for named fields of a struct";
+const DEFAULT_TYPE_MSG: &str = "The default must be a literal of the type of the tunable";
+const BY_REPO_DEFAULT_MSG: &str = "By-repo tunables can't have a default";
+const ATTRIBUTE_MSG: &str = "Expected #[tunable(default = <literal>)]";
 
 #[derive(Clone, PartialEq)]
 enum TunableType {
@@ -24,31 +29,102 @@ enum TunableType {
     ByRepoI64,
 }
 
-#[proc_macro_derive(Tunables)]
+/// A field of the struct, along with what its attributes say about it.
+#[derive(Clone)]
+struct TunableField {
+    name: Ident,
+    ty: TunableType,
+    // Set with `#[tunable(default = <literal>)]`.
+    default: Option<Lit>,
+    // The doc comment of the field.
+    description: String,
+}
+
+#[proc_macro_derive(Tunables, attributes(tunable))]
 // This proc macro accepts a struct and provides methods that get the atomic
 // values stored inside of it. It does this by generating methods
 // named get_<field>(). The macro also generates methods that update the
 // atomic values inside of the struct, using a provided HashMap.
+//
+// The struct gets a `Default` implementation that uses the defaults set with
+// `#[tunable(default = ...)]`, and a `definitions()` method that describes
+// every tunable, using the doc comments of the fields as descriptions.
 pub fn derive_tunables(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let parsed_input = parse_macro_input!(input as DeriveInput);
 
     let struct_name = parsed_input.ident;
-    let names_and_types = parse_names_and_types(parsed_input.data).into_iter();
+    let fields = parse_fields(parsed_input.data);
+    let names_and_types = fields
+        .iter()
+        .map(|f| (f.name.clone(), f.ty.clone()))
+        .collect::<Vec<_>>()
+        .into_iter();
 
     let getter_methods = generate_getter_methods(names_and_types.clone());
     let updater_methods = generate_updater_methods(names_and_types);
+    let definitions_method = generate_definitions_method(&fields);
+    let default_impl = generate_default_impl(&struct_name, &fields);
 
     let expanded = quote! {
         impl #struct_name {
             #updater_methods
             #getter_methods
+            #definitions_method
         }
+
+        #default_impl
     };
 
     expanded.into()
 }
 
 impl TunableType {
+    fn kind(&self) -> TokenStream {
+        match self {
+            Self::Bool => quote! { TunableKind::Bool },
+            Self::I64 => quote! { TunableKind::I64 },
+            Self::String => quote! { TunableKind::String },
+            Self::ByRepoBool => quote! { TunableKind::BoolByRepo },
+            Self::ByRepoString => quote! { TunableKind::StringByRepo },
+            Self::ByRepoI64 => quote! { TunableKind::I64ByRepo },
+        }
+    }
+
+    fn generate_default_value(&self, default: &Option<Lit>) -> TokenStream {
+        match (self, default) {
+            (_, None) => quote! { Default::default() },
+            (Self::Bool, Some(lit @ Lit::Bool(_))) => {
+                quote! { std::sync::atomic::AtomicBool::new(#lit) }
+            }
+            (Self::I64, Some(lit @ Lit::Int(_))) => {
+                quote! { std::sync::atomic::AtomicI64::new(#lit) }
+            }
+            (Self::String, Some(lit @ Lit::Str(_))) => {
+                quote! { arc_swap::ArcSwap::from_pointee(String::from(#lit)) }
+            }
+            (Self::ByRepoBool, Some(_))
+            | (Self::ByRepoI64, Some(_))
+            | (Self::ByRepoString, Some(_)) => panic!("{}", BY_REPO_DEFAULT_MSG),
+            (_, Some(_)) => panic!("{}", DEFAULT_TYPE_MSG),
+        }
+    }
+
+    fn default_description(&self, default: &Option<Lit>) -> String {
+        match default {
+            Some(Lit::Bool(lit)) => lit.value.to_string(),
+            Some(Lit::Int(lit)) => lit.base10_digits().to_string(),
+            Some(Lit::Str(lit)) => lit.value(),
+            Some(_) => panic!("{}", DEFAULT_TYPE_MSG),
+            None => match self {
+                Self::Bool => "false".to_string(),
+                Self::I64 => "0".to_string(),
+                Self::String | Self::ByRepoBool | Self::ByRepoI64 | Self::ByRepoString => {
+                    String::new()
+                }
+            },
+        }
+    }
+
     fn external_type(&self) -> TokenStream {
         match self {
             Self::Bool => quote! { bool },
@@ -239,13 +315,61 @@ where
     }
 }
 
-fn parse_names_and_types(data: Data) -> Vec<(Ident, TunableType)> {
+fn generate_definitions_method(fields: &[TunableField]) -> TokenStream {
+    let definitions = fields.iter().map(|f| {
+        let name = &f.name;
+        let kind = f.ty.kind();
+        let default = f.ty.default_description(&f.default);
+        let description = &f.description;
+        quote! {
+            TunableDefinition {
+                name: stringify!(#name),
+                kind: #kind,
+                default: #default,
+                description: #description,
+            }
+        }
+    });
+
+    quote! {
+        pub fn definitions() -> Vec<TunableDefinition> {
+            vec![#(#definitions),*]
+        }
+    }
+}
+
+fn generate_default_impl(struct_name: &Ident, fields: &[TunableField]) -> TokenStream {
+    let names = fields.iter().map(|f| &f.name);
+    let values = fields
+        .iter()
+        .map(|f| f.ty.generate_default_value(&f.default));
+
+    quote! {
+        impl Default for #struct_name {
+            fn default() -> Self {
+                Self {
+                    #(#names: #values,)*
+                }
+            }
+        }
+    }
+}
+
+fn parse_fields(data: Data) -> Vec<TunableField> {
     match data {
         Data::Struct(data) => match data.fields {
             Fields::Named(fields) => fields
                 .named
                 .into_iter()
-                .filter_map(|f| f.clone().ident.map(|i| (i, resolve_type(f.ty))))
+                .filter_map(|f| {
+                    let name = f.ident?;
+                    Some(TunableField {
+                        name,
+                        ty: resolve_type(f.ty),
+                        default: parse_default(&f.attrs),
+                        description: parse_description(&f.attrs),
+                    })
+                })
                 .collect::<Vec<_>>(),
             _ => unimplemented!("{}", STRUCT_FIELD_MSG),
         },
@@ -253,6 +377,40 @@ fn parse_names_and_types(data: Data) -> Vec<(Ident, TunableType)> {
     }
 }
 
+fn parse_default(attrs: &[Attribute]) -> Option<Lit> {
+    let mut default = None;
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("tunable")) {
+        let nested = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list.nested,
+            _ => panic!("{}", ATTRIBUTE_MSG),
+        };
+        for meta in nested {
+            match meta {
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("default") => {
+                    default = Some(nv.lit);
+                }
+                _ => panic!("{}", ATTRIBUTE_MSG),
+            }
+        }
+    }
+    default
+}
+
+fn parse_description(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("doc"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::NameValue(nv)) => match nv.lit {
+                Lit::Str(lit) => Some(lit.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 fn resolve_type(ty: Type) -> TunableType {
     // TODO: Handle full paths to the types, such as
     // std::sync::atomic::AtomicBool, rather than just the type name.
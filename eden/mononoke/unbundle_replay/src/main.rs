@@ -37,6 +37,7 @@ use hooks_content_stores::blobrepo_text_only_fetcher;
 use mercurial_bundles::bundle2::{Bundle2Stream, StreamEvent};
 use metaconfig_types::{CensoredScubaParams, RepoConfig, RepoReadOnly};
 use mononoke_types::{BonsaiChangeset, ChangesetId, Timestamp};
+use repo_read_write_status::RepoReadWriteFetcher;
 use scuba_ext::MononokeScubaSampleBuilder;
 use slog::{info, warn, Logger};
 use std::collections::HashMap;
@@ -256,14 +257,20 @@ async fn maybe_unbundle(
     let (unbundle_stats, resolution) = task::spawn({
         let ctx = ctx.clone();
         let repo = repo.clone();
-        let pushrebase_flags = repo_config.pushrebase.flags;
+        let pushrebase_flags = repo_config.pushrebase.flags.clone();
+        // The replay doesn't take the repo lock into account.
+        let readonly_fetcher = RepoReadWriteFetcher::new(
+            None,
+            RepoReadOnly::ReadWrite,
+            repo_config.hgsql_name.clone(),
+        );
         async move {
             unbundle::resolve(
                 &ctx,
                 &repo,
                 false, // infinitepush_writes_allowed
                 bundle_stream.compat().boxed(),
-                RepoReadOnly::ReadWrite,
+                &readonly_fetcher,
                 None,  // maybe_full_content
                 false, // pure_push_allowed
                 pushrebase_flags,
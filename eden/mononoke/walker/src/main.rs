@@ -30,6 +30,7 @@ mod scrub;
 mod setup;
 mod sizing;
 mod state;
+mod stats_output;
 mod tail;
 mod validate;
 mod walk;
@@ -23,6 +23,8 @@ use std::{
     time::{Duration, Instant},
 };
 
+pub use cmdlib_progress::ProgressOptions;
+
 define_stats! {
     prefix = "mononoke.walker";
     walk_progress_walked: dynamic_timeseries("{}.progress.{}.walked", (subcommand: &'static str, repo: String); Rate, Sum),
@@ -47,12 +49,6 @@ pub trait ProgressReporterUnprotected {
     fn report_throttled(&mut self);
 }
 
-#[derive(Clone, Copy)]
-pub struct ProgressOptions {
-    pub sample_rate: u64,
-    pub interval: Duration,
-}
-
 pub struct ProgressStateByTypeParams {
     pub fb: FacebookInit,
     pub logger: Logger,
@@ -168,15 +164,13 @@ where
 
     // Throttle by sample, then time
     pub fn should_log_throttled(&mut self) -> Option<Duration> {
-        if self.work_stats.total_progress % self.params.options.sample_rate == 0 {
-            let new_update = Instant::now();
-            let delta_time = new_update.duration_since(self.reporting_stats.last_update);
-            if delta_time >= self.params.options.interval {
-                self.reporting_stats.last_update = new_update;
-                return Some(delta_time);
-            }
-        }
-        None
+        let last_update = self.reporting_stats.last_update;
+        let new_update = self
+            .params
+            .options
+            .should_report(self.work_stats.total_progress, last_update)?;
+        self.reporting_stats.last_update = new_update;
+        Some(new_update.duration_since(last_update))
     }
 }
 
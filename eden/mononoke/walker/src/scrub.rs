@@ -15,9 +15,10 @@ use crate::sampling::{SamplingOptions, SamplingWalkVisitor, WalkSampleMapping};
 use crate::setup::{
     parse_node_types, parse_progress_args, parse_sampling_args, setup_common, JobWalkParams,
     OutputFormat, RepoSubcommandParams, EXCLUDE_OUTPUT_NODE_TYPE_ARG, INCLUDE_OUTPUT_NODE_TYPE_ARG,
-    LIMIT_DATA_FETCH_ARG, OUTPUT_FORMAT_ARG, SCRUB,
+    LIMIT_DATA_FETCH_ARG, OUTPUT_FORMAT_ARG, SCRUB, STATS_OUTPUT_FILE_ARG, STATS_OUTPUT_FORMAT_ARG,
 };
 use crate::sizing::SizingSample;
+use crate::stats_output::{StatsOutput, StatsOutputFormat, StatsRow};
 use crate::tail::walk_exact_tail;
 use crate::validate::TOTAL;
 use crate::walk::{EmptyRoute, RepoWalkParams, RepoWalkTypeParams};
@@ -30,9 +31,8 @@ use context::CoreContext;
 use derive_more::{Add, Div, Mul, Sub};
 use fbinit::FacebookInit;
 use futures::{
-    future::{self, try_join_all, FutureExt},
+    future::{self, try_join_all, Either},
     stream::{Stream, TryStreamExt},
-    TryFutureExt,
 };
 use mononoke_types::BlobstoreBytes;
 use samplingblob::SamplingHandler;
@@ -94,12 +94,40 @@ impl fmt::Display for ScrubStats {
     }
 }
 
+// Rows are written as nodes complete, so their order is not deterministic.
+async fn complete_step(
+    sampler: &WalkSampleMapping<Node, ScrubSample>,
+    stats_output: Option<&(StatsOutput, String)>,
+    n: &Node,
+) -> Result<ScrubStats, Error> {
+    let sample = sampler.complete_step(n);
+    if let (Some((stats_output, repo)), Some(sample)) = (stats_output, sample.as_ref()) {
+        let node_type: &'static str = n.get_type().into();
+        let node_key = n.stats_key();
+        let path = n.stats_path().map(|path| path.to_string());
+        for (blobstore_key, size) in &sample.data {
+            stats_output
+                .write(StatsRow {
+                    repo: repo.clone(),
+                    node_type,
+                    node_key: node_key.clone(),
+                    path: path.clone(),
+                    blobstore_key: blobstore_key.clone(),
+                    size: *size,
+                })
+                .await?;
+        }
+    }
+    Ok(ScrubStats::from(sample.as_ref()))
+}
+
 // Force load of leaf data like file contents that graph traversal did not need
 fn loading_stream<InStream, SS>(
     limit_data_fetch: bool,
     scheduled_max: usize,
     s: InStream,
     sampler: Arc<WalkSampleMapping<Node, ScrubSample>>,
+    stats_output: Option<(StatsOutput, String)>,
     output_node_types: HashSet<NodeType>,
     output_format: OutputFormat,
 ) -> impl Stream<Item = Result<(Node, Option<NodeData>, Option<ScrubStats>), Error>>
@@ -107,23 +135,11 @@ where
     InStream: Stream<Item = Result<(Node, Option<NodeData>, Option<SS>), Error>> + 'static + Send,
 {
     s.map_ok(move |(n, nd, _progress_stats)| {
-        match nd {
+        let nd = match nd {
             Some(NodeData::FileContent(FileContentData::ContentStream(file_bytes_stream)))
                 if !limit_data_fetch =>
             {
-                cloned!(sampler);
-                file_bytes_stream
-                    .try_fold(0, |acc, file_bytes| future::ok(acc + file_bytes.size()))
-                    .map_ok(move |num_bytes| {
-                        let size = ScrubStats::from(sampler.complete_step(&n).as_ref());
-                        (
-                            n,
-                            Some(NodeData::FileContent(FileContentData::Consumed(num_bytes))),
-                            Some(size),
-                        )
-                    })
-                    .map_err(|e| e.context(format_err!("While scrubbing file content stream")))
-                    .left_future()
+                Either::Left(file_bytes_stream)
             }
             data_opt => {
                 if output_node_types.contains(&n.get_type()) {
@@ -135,11 +151,28 @@ where
                         }
                     }
                 }
-                let size = data_opt
-                    .as_ref()
-                    .map(|_d| ScrubStats::from(sampler.complete_step(&n).as_ref()));
-                future::ok((n, data_opt, size)).right_future()
+                Either::Right(data_opt)
             }
+        };
+        cloned!(sampler, stats_output);
+        async move {
+            let data_opt = match nd {
+                Either::Left(file_bytes_stream) => {
+                    let num_bytes = file_bytes_stream
+                        .try_fold(0, |acc, file_bytes| future::ok(acc + file_bytes.size()))
+                        .await
+                        .map_err(|e| {
+                            e.context(format_err!("While scrubbing file content stream"))
+                        })?;
+                    Some(NodeData::FileContent(FileContentData::Consumed(num_bytes)))
+                }
+                Either::Right(data_opt) => data_opt,
+            };
+            let size = match data_opt {
+                Some(_) => Some(complete_step(&sampler, stats_output.as_ref(), &n).await?),
+                None => None,
+            };
+            Ok::<_, Error>((n, data_opt, size))
         }
     })
     .try_buffer_unordered(scheduled_max)
@@ -331,6 +364,7 @@ struct ScrubCommand {
     progress_options: ProgressOptions,
     sampling_options: SamplingOptions,
     sampler: Arc<WalkSampleMapping<Node, ScrubSample>>,
+    stats_output: Option<StatsOutput>,
 }
 
 impl ScrubCommand {
@@ -362,6 +396,17 @@ pub async fn scrub_objects<'a>(
         &[],
     )?;
 
+    let stats_output_format = sub_m
+        .value_of(STATS_OUTPUT_FORMAT_ARG)
+        .map_or(Ok(StatsOutputFormat::Csv), StatsOutputFormat::from_str)?;
+    let (stats_output, stats_output_writer) = match sub_m.value_of(STATS_OUTPUT_FILE_ARG) {
+        Some(path) => {
+            let (stats_output, writer) = StatsOutput::create(path, stats_output_format)?;
+            (Some(stats_output), Some(writer))
+        }
+        None => (None, None),
+    };
+
     let command = ScrubCommand {
         limit_data_fetch: sub_m.is_present(LIMIT_DATA_FETCH_ARG),
         output_format,
@@ -369,6 +414,7 @@ pub async fn scrub_objects<'a>(
         progress_options: parse_progress_args(&sub_m),
         sampling_options: parse_sampling_args(&sub_m, 1)?,
         sampler,
+        stats_output,
     };
 
     let mut all_walks = Vec::new();
@@ -380,7 +426,14 @@ pub async fn scrub_objects<'a>(
         let walk = run_one(fb, job_params, sub_params, repo_params, command);
         all_walks.push(walk);
     }
-    try_join_all(all_walks).await.map(|_| ())
+    let res = try_join_all(all_walks).await.map(|_| ());
+
+    // The writer is done once the walks have dropped their copies of the stats output.
+    drop(command);
+    match (res, stats_output_writer) {
+        (Ok(()), Some(stats_output_writer)) => stats_output_writer.finish().await,
+        (res, _) => res,
+    }
 }
 
 async fn run_one(
@@ -404,19 +457,28 @@ async fn run_one(
         cloned!(command, job_params.quiet, sub_params.progress_state,);
         move |ctx: &CoreContext, repo_params: &RepoWalkParams| {
             cloned!(ctx, repo_params.scheduled_max);
+            let repo_name = repo_params.repo.name().clone();
             async move |walk_output| {
                 let walk_progress = progress_stream(quiet, &progress_state, walk_output);
+                let stats_output = command
+                    .stats_output
+                    .clone()
+                    .map(|stats_output| (stats_output, repo_name));
                 let loading = loading_stream(
                     command.limit_data_fetch,
                     scheduled_max,
                     walk_progress,
                     command.sampler,
+                    stats_output,
                     command.output_node_types,
                     command.output_format,
                 );
                 let report_sizing = progress_stream(quiet, &sizing_progress_state, loading);
 
                 report_state(ctx, report_sizing).await?;
+                if let Some(stats_output) = &command.stats_output {
+                    stats_output.flush().await?;
+                }
                 sizing_progress_state.report_progress();
                 progress_state.report_progress();
                 Ok(())
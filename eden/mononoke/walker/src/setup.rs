@@ -15,11 +15,12 @@ use crate::progress::{
 };
 use crate::sampling::SamplingOptions;
 use crate::state::{InternedType, StepStats};
+use crate::stats_output::StatsOutputFormat;
 use crate::tail::TailParams;
 use crate::validate::{CheckType, REPO, WALK_TYPE};
 use crate::walk::{OutgoingEdge, RepoWalkParams};
 
-use ::blobstore::Blobstore;
+use ::blobstore::{Blobstore, PutBehaviour};
 use anyhow::{bail, format_err, Context, Error};
 use blobrepo_factory::{open_blobrepo_given_datasources, Caching, ReadOnlyStorage};
 use blobstore_factory::{
@@ -128,6 +129,8 @@ pub const EXCLUDE_OUTPUT_NODE_TYPE_ARG: &str = "exclude-output-node-type";
 pub const INCLUDE_OUTPUT_NODE_TYPE_ARG: &str = "include-output-node-type";
 pub const OUTPUT_FORMAT_ARG: &str = "output-format";
 pub const OUTPUT_DIR_ARG: &str = "output-dir";
+pub const STATS_OUTPUT_FILE_ARG: &str = "stats-output-file";
+pub const STATS_OUTPUT_FORMAT_ARG: &str = "stats-output-format";
 const SCUBA_TABLE_ARG: &str = "scuba-table";
 const SCUBA_LOG_FILE_ARG: &str = "scuba-log-file";
 
@@ -536,6 +539,24 @@ pub fn setup_toplevel_app<'a, 'b>(
                 .default_value(OutputFormat::PrettyDebug.as_ref())
                 .required(false)
                 .help("Set the output format"),
+        )
+        .arg(
+            Arg::with_name(STATS_OUTPUT_FILE_ARG)
+                .long(STATS_OUTPUT_FILE_ARG)
+                .takes_value(true)
+                .required(false)
+                .help("Write a row with the node type, key, path, blobstore key and size of every sampled blobstore key to this file, for offline analysis"),
+        )
+        .arg(
+            Arg::with_name(STATS_OUTPUT_FORMAT_ARG)
+                .long(STATS_OUTPUT_FORMAT_ARG)
+                .takes_value(true)
+                .multiple(false)
+                .number_of_values(1)
+                .possible_values(StatsOutputFormat::VARIANTS)
+                .default_value(StatsOutputFormat::Csv.as_ref())
+                .required(false)
+                .help("Set the format of the stats output file. Parquet files are only complete once the walk is done, so use Csv when tailing"),
         );
 
     let compression_benefit = setup_subcommand_args(
@@ -908,6 +929,7 @@ pub fn parse_progress_args(sub_m: &ArgMatches) -> ProgressOptions {
     ProgressOptions {
         sample_rate,
         interval: Duration::from_secs(interval_secs),
+        ..ProgressOptions::default()
     }
 }
 
@@ -1386,6 +1408,7 @@ pub async fn setup_common<'a>(
                     readonly_storage,
                     caching,
                     blobstore_options.cachelib_options.clone(),
+                    blobstore_options.put_behaviour,
                     enable_redaction,
                     common_config.censored_scuba_params.clone(),
                     scheduled_max,
@@ -1427,6 +1450,7 @@ async fn setup_repo<'a>(
     readonly_storage: ReadOnlyStorage,
     caching: Caching,
     cachelib_blobstore_options: CachelibBlobstoreOptions,
+    put_behaviour: PutBehaviour,
     enable_redaction: bool,
     redaction_scuba_params: CensoredScubaParams,
     scheduled_max: usize,
@@ -1502,6 +1526,7 @@ async fn setup_repo<'a>(
         readonly_storage,
         resolved.name.clone(),
         cachelib_blobstore_options,
+        put_behaviour,
         &logger,
     );
 
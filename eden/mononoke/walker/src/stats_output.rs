@@ -0,0 +1,334 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Writes a row per sampled blobstore key to a file, for offline analysis of what a repo is made
+//! of. The rows are sent to a writer on a blocking thread, so that the file writes don't hold up
+//! the walk.
+
+use anyhow::{bail, format_err, Context, Error};
+use futures::executor;
+use parquet::{
+    column::writer::ColumnWriter,
+    data_type::ByteArray,
+    file::{
+        properties::WriterProperties,
+        writer::{FileWriter, RowGroupWriter, SerializedFileWriter},
+    },
+    schema::parser::parse_message_type,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    sync::Arc,
+};
+use strum_macros::{AsRefStr, EnumString, EnumVariantNames};
+use tokio::{
+    sync::mpsc,
+    task::{self, JoinHandle},
+};
+
+// Rows waiting to be written, before the walk has to wait for the writer.
+const CHANNEL_SIZE: usize = 10_000;
+
+const CSV_HEADER: &str = "repo,node_type,node_key,path,blobstore_key,size";
+
+const PARQUET_SCHEMA: &str = "
+    message walk_stats {
+        REQUIRED BYTE_ARRAY repo (UTF8);
+        REQUIRED BYTE_ARRAY node_type (UTF8);
+        REQUIRED BYTE_ARRAY node_key (UTF8);
+        OPTIONAL BYTE_ARRAY path (UTF8);
+        REQUIRED BYTE_ARRAY blobstore_key (UTF8);
+        REQUIRED INT64 size;
+    }
+";
+
+const PARQUET_ROW_GROUP_SIZE: usize = 100_000;
+
+#[derive(Clone, Debug, PartialEq, Eq, AsRefStr, EnumVariantNames, EnumString)]
+pub enum StatsOutputFormat {
+    Csv,
+    // The footer is only written once the walk is done, so this doesn't suit tailing.
+    Parquet,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatsRow {
+    pub repo: String,
+    pub node_type: &'static str,
+    pub node_key: String,
+    pub path: Option<String>,
+    pub blobstore_key: String,
+    pub size: u64,
+}
+
+enum Message {
+    Row(StatsRow),
+    Flush,
+}
+
+trait StatsWriter {
+    fn write(&mut self, row: StatsRow) -> Result<(), Error>;
+    fn flush(&mut self) -> Result<(), Error>;
+    fn finish(self: Box<Self>) -> Result<(), Error>;
+}
+
+/// Sends rows to the writer. Clones share the writer.
+#[derive(Clone)]
+pub struct StatsOutput {
+    sender: mpsc::Sender<Message>,
+}
+
+/// The writer, which is done once all the `StatsOutput`s are dropped.
+pub struct StatsOutputWriter(JoinHandle<Result<(), Error>>);
+
+impl StatsOutput {
+    pub fn create(
+        path: &str,
+        format: StatsOutputFormat,
+    ) -> Result<(StatsOutput, StatsOutputWriter), Error> {
+        let file =
+            File::create(path).with_context(|| format!("While creating stats output {}", path))?;
+        let (sender, mut receiver) = mpsc::channel(CHANNEL_SIZE);
+        let handle = task::spawn_blocking(move || {
+            let mut writer: Box<dyn StatsWriter> = match format {
+                StatsOutputFormat::Csv => Box::new(CsvWriter::new(file)?),
+                StatsOutputFormat::Parquet => Box::new(ParquetWriter::new(file)?),
+            };
+            while let Some(message) = executor::block_on(receiver.recv()) {
+                match message {
+                    Message::Row(row) => writer.write(row)?,
+                    Message::Flush => writer.flush()?,
+                }
+            }
+            writer.finish()
+        });
+        Ok((StatsOutput { sender }, StatsOutputWriter(handle)))
+    }
+
+    pub async fn write(&self, row: StatsRow) -> Result<(), Error> {
+        self.send(Message::Row(row)).await
+    }
+
+    /// Write out what was sent so far, e.g. at the end of a walk that is tailing.
+    pub async fn flush(&self) -> Result<(), Error> {
+        self.send(Message::Flush).await
+    }
+
+    async fn send(&self, message: Message) -> Result<(), Error> {
+        self.sender
+            .clone()
+            .send(message)
+            .await
+            .map_err(|_| format_err!("Stats output writer has stopped"))
+    }
+}
+
+impl StatsOutputWriter {
+    pub async fn finish(self) -> Result<(), Error> {
+        self.0.await?
+    }
+}
+
+// Keys and paths are mostly safe as they are, but bookmark names and paths can contain anything.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+struct CsvWriter(BufWriter<File>);
+
+impl CsvWriter {
+    fn new(file: File) -> Result<Self, Error> {
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{}", CSV_HEADER)?;
+        Ok(Self(writer))
+    }
+}
+
+impl StatsWriter for CsvWriter {
+    fn write(&mut self, row: StatsRow) -> Result<(), Error> {
+        writeln!(
+            self.0,
+            "{},{},{},{},{},{}",
+            csv_field(&row.repo),
+            row.node_type,
+            csv_field(&row.node_key),
+            row.path.as_deref().map_or_else(String::new, csv_field),
+            csv_field(&row.blobstore_key),
+            row.size
+        )?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.0.flush()?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        self.flush()
+    }
+}
+
+struct ParquetWriter {
+    writer: SerializedFileWriter<File>,
+    rows: Vec<StatsRow>,
+}
+
+impl ParquetWriter {
+    fn new(file: File) -> Result<Self, Error> {
+        let schema = Arc::new(parse_message_type(PARQUET_SCHEMA)?);
+        let props = Arc::new(WriterProperties::builder().build());
+        Ok(Self {
+            writer: SerializedFileWriter::new(file, schema, props)?,
+            rows: Vec::with_capacity(PARQUET_ROW_GROUP_SIZE),
+        })
+    }
+
+    fn write_row_group(&mut self) -> Result<(), Error> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let rows = &self.rows;
+
+        let strings = |field: fn(&StatsRow) -> Option<&str>| -> Vec<ByteArray> {
+            rows.iter().filter_map(field).map(ByteArray::from).collect()
+        };
+        let path_def_levels: Vec<i16> = rows.iter().map(|row| row.path.is_some() as i16).collect();
+        // In the order of the schema.
+        let mut string_columns = vec![
+            (strings(|row| Some(row.repo.as_str())), None),
+            (strings(|row| Some(row.node_type)), None),
+            (strings(|row| Some(row.node_key.as_str())), None),
+            (strings(|row| row.path.as_deref()), Some(path_def_levels)),
+            (strings(|row| Some(row.blobstore_key.as_str())), None),
+        ]
+        .into_iter();
+        let sizes: Vec<i64> = rows.iter().map(|row| row.size as i64).collect();
+
+        let mut row_group = self.writer.next_row_group()?;
+        while let Some(mut column) = row_group.next_column()? {
+            match &mut column {
+                ColumnWriter::ByteArrayColumnWriter(writer) => {
+                    let (values, def_levels) = string_columns
+                        .next()
+                        .ok_or_else(|| format_err!("Too many string columns in stats schema"))?;
+                    writer.write_batch(&values, def_levels.as_deref(), None)?;
+                }
+                ColumnWriter::Int64ColumnWriter(writer) => {
+                    writer.write_batch(&sizes, None, None)?;
+                }
+                _ => bail!("Unexpected column type in stats schema"),
+            }
+            row_group.close_column(column)?;
+        }
+        self.writer.close_row_group(row_group)?;
+
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+impl StatsWriter for ParquetWriter {
+    fn write(&mut self, row: StatsRow) -> Result<(), Error> {
+        self.rows.push(row);
+        if self.rows.len() >= PARQUET_ROW_GROUP_SIZE {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.write_row_group()
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        self.write_row_group()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::{
+        file::reader::{FileReader, SerializedFileReader},
+        record::RowAccessor,
+    };
+    use std::fs;
+
+    fn rows() -> Vec<StatsRow> {
+        vec![
+            StatsRow {
+                repo: "repo".to_string(),
+                node_type: "HgManifest",
+                node_key: "a,b".to_string(),
+                path: Some("dir/\"file\"".to_string()),
+                blobstore_key: "repo0000.hgmanifest.sha1.a".to_string(),
+                size: 10,
+            },
+            StatsRow {
+                repo: "repo".to_string(),
+                node_type: "Changeset",
+                node_key: "c".to_string(),
+                path: None,
+                blobstore_key: "repo0000.changeset.blake2.c".to_string(),
+                size: 20,
+            },
+        ]
+    }
+
+    async fn write_rows(path: &str, format: StatsOutputFormat) -> Result<(), Error> {
+        let (output, writer) = StatsOutput::create(path, format)?;
+        for row in rows() {
+            output.write(row).await?;
+        }
+        output.flush().await?;
+        drop(output);
+        writer.finish().await
+    }
+
+    #[tokio::test]
+    async fn test_csv() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("stats.csv");
+        write_rows(path.to_str().unwrap(), StatsOutputFormat::Csv).await?;
+
+        assert_eq!(
+            fs::read_to_string(&path)?,
+            "repo,node_type,node_key,path,blobstore_key,size\n\
+             repo,HgManifest,\"a,b\",\"dir/\"\"file\"\"\",repo0000.hgmanifest.sha1.a,10\n\
+             repo,Changeset,c,,repo0000.changeset.blake2.c,20\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_parquet() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("stats.parquet");
+        write_rows(path.to_str().unwrap(), StatsOutputFormat::Parquet).await?;
+
+        let reader = SerializedFileReader::new(File::open(&path)?)?;
+        let read: Vec<_> = reader.get_row_iter(None)?.collect();
+        assert_eq!(read.len(), 2);
+        for (row, expected) in read.iter().zip(rows()) {
+            assert_eq!(row.get_string(0)?, &expected.repo);
+            assert_eq!(row.get_string(1)?, expected.node_type);
+            assert_eq!(row.get_string(2)?, &expected.node_key);
+            assert_eq!(row.get_string(3).ok(), expected.path.as_ref());
+            assert_eq!(row.get_string(4)?, &expected.blobstore_key);
+            assert_eq!(row.get_long(5)?, expected.size as i64);
+        }
+        Ok(())
+    }
+}
@@ -799,13 +799,12 @@ impl ProgressReporterUnprotected for ValidateProgressState {
     }
 
     fn report_throttled(&mut self) {
-        if self.checked_nodes % self.throttle_options.sample_rate == 0 {
-            let new_update = Instant::now();
-            let delta_time = new_update.duration_since(self.last_update);
-            if delta_time >= self.throttle_options.interval {
-                self.report_progress_log();
-                self.last_update = new_update;
-            }
+        if let Some(new_update) = self
+            .throttle_options
+            .should_report(self.checked_nodes, self.last_update)
+        {
+            self.report_progress_log();
+            self.last_update = new_update;
         }
     }
 }
@@ -204,6 +204,7 @@ coreconfigitem("devel", "bundle2.debug", default=False)
 coreconfigitem("devel", "cache-vfs", default=None)
 coreconfigitem("devel", "check-locks", default=False)
 coreconfigitem("devel", "check-relroot", default=False)
+coreconfigitem("devel", "config-access-log", default=None)
 coreconfigitem("devel", "debugger", default=False)
 coreconfigitem("devel", "default-date", default=None)
 coreconfigitem("devel", "deprec-warn", default=False)
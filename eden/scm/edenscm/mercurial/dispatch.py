@@ -449,6 +449,10 @@ def dispatch(req):
                 ui.write_err(msg, label="hgmetrics")
             # Write to blackbox, and sampling
             ui.log("metrics", pformat({"metrics": metrics}, width=1024), **hgmetrics)
+        # developer config: devel.config-access-log
+        accesslog = ui.config("devel", "config-access-log")
+        if accesslog:
+            ui._uiconfig._rcfg.dumpaccessed(accesslog)
         blackbox.sync()
 
     if util.isoldversion():
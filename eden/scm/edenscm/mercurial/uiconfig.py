@@ -127,9 +127,14 @@ class uiconfig(object):
             raise error.ParseError(str(ex))
 
         u._rcfg = localrcfg(rcfg)
+        # developer config: devel.config-access-log
+        if u._rcfg.get("devel", "config-access-log"):
+            u._rcfg.recordaccessed()
         ui._uiconfig = u
         if repopath is not None:
             reportissues(ui, issues)
+        for path, reason in rcfg.insecurefiles():
+            ui.warn(_("warning: config file %s is insecure: %s\n") % (path, reason))
 
         root = os.path.expanduser("~")
         u.fixconfig(root=repopath or root)
@@ -7,12 +7,12 @@
 
 #![allow(non_camel_case_types)]
 
-use std::{cell::RefCell, collections::HashSet, convert::TryInto, iter::FromIterator};
+use std::{cell::RefCell, collections::HashSet, convert::TryInto, iter::FromIterator, sync::Arc};
 
 use cpython::*;
 
 use configparser::{
-    config::{ConfigSet, Options, SupersetVerification},
+    config::{AccessRecorder, ConfigSet, Options, SupersetVerification},
     convert::parse_list,
     dynamicconfig::Generator,
     hg::{generate_dynamicconfig, ConfigSetHgExt, OptionsHgExt},
@@ -142,6 +142,37 @@ py_class!(pub class config |py| {
         Ok(cfg.keys(section).iter().map(|s| PyUnicode::new(py, &s)).collect())
     }
 
+    // Record the config items that are read from now on, by this config and by its clones,
+    // for `dumpaccessed` to write them out.
+    def recordaccessed(&self) -> PyResult<PyNone> {
+        let mut cfg = self.cfg(py).borrow_mut();
+        if cfg.access_recorder().is_none() {
+            cfg.set_access_recorder(Arc::new(AccessRecorder::new()));
+        }
+        Ok(PyNone)
+    }
+
+    // Write the config items read so far to `path`, one `section.name` per line. Does nothing
+    // unless `recordaccessed` was called.
+    def dumpaccessed(&self, path: &PyPath) -> PyResult<PyNone> {
+        let cfg = self.cfg(py).borrow();
+        if let Some(recorder) = cfg.access_recorder() {
+            recorder.dump(path.as_path()).map_pyerr(py)?;
+        }
+        Ok(PyNone)
+    }
+
+    def insecurefiles(&self) -> PyResult<Vec<(PyPathBuf, Str)>> {
+        // Return [(path, reason)] of the files that were loaded despite failing the check of
+        // their ownership and permissions.
+        let cfg = self.cfg(py).borrow();
+        let mut result = Vec::new();
+        for (path, reason) in cfg.insecure_files() {
+            result.push((path.as_path().try_into().map_pyerr(py)?, reason.clone().into()));
+        }
+        Ok(result)
+    }
+
     def tostring(&self) -> PyResult<Str> {
         let cfg = self.cfg(py).borrow();
         Ok(cfg.to_string().into())
@@ -6,31 +6,96 @@
  */
 
 use std::collections::hash_map::RandomState;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::convert::AsRef;
+use std::fmt;
 use std::fs;
+use std::io::{Read, Write};
 use std::iter::FromIterator;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::str;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 use anyhow::Result;
 use indexmap::IndexMap;
 use minibytes::Text;
+use pest::error::{ErrorVariant, InputLocation};
 use pest::{self, Parser, Span};
 use util::path::expand_path;
 
-use crate::convert::FromConfigValue;
+use crate::convert::{parse_list, FromConfigValue};
 use crate::error::Error;
 use crate::parser::{ConfigParser, Rule};
 
 type Pair<'a> = pest::iterators::Pair<'a, Rule>;
 
+/// Describe a pest error the way pest does, without its own rendering of the location.
+fn parse_error_message(error: &pest::error::Error<Rule>) -> String {
+    fn enumerate(rules: &[Rule]) -> String {
+        match rules {
+            [rule] => format!("{:?}", rule),
+            [first, second] => format!("{:?} or {:?}", first, second),
+            [init @ .., last] => {
+                let init: Vec<_> = init.iter().map(|rule| format!("{:?}", rule)).collect();
+                format!("{}, or {:?}", init.join(", "), last)
+            }
+            [] => String::new(),
+        }
+    }
+
+    match &error.variant {
+        ErrorVariant::ParsingError {
+            positives,
+            negatives,
+        } => match (negatives.is_empty(), positives.is_empty()) {
+            (false, false) => format!(
+                "unexpected {}; expected {}",
+                enumerate(negatives),
+                enumerate(positives)
+            ),
+            (false, true) => format!("unexpected {}", enumerate(negatives)),
+            (true, false) => format!("expected {}", enumerate(positives)),
+            (true, true) => "unknown parsing error".to_string(),
+        },
+        ErrorVariant::CustomError { message } => message.clone(),
+    }
+}
+
 /// Collection of config sections loaded from various sources.
 #[derive(Clone, Default, Debug)]
 pub struct ConfigSet {
     sections: IndexMap<Text, Section>,
+    validators: Vec<Validator>,
+    // Files that failed the permission check but were loaded anyway, with the reason.
+    insecure_files: Vec<(PathBuf, String)>,
+    access_recorder: Option<Arc<AccessRecorder>>,
+}
+
+/// Records which config items are read, whether they are set or not, so that config that is
+/// never read can be found and cleaned up. It is shared by the clones of the `ConfigSet` it is
+/// attached to, so it covers every read in the process.
+#[derive(Debug, Default)]
+pub struct AccessRecorder {
+    // By section, so that the items read before can be looked up without allocating.
+    accessed: RwLock<HashMap<String, HashSet<String>>>,
+}
+
+/// Check the items of a section, returning the names that are invalid along with the reason.
+pub type ValidatorFn = dyn Fn(&ConfigSet) -> Vec<(Text, String)> + Send + Sync;
+
+/// A validation callback registered for a section.
+#[derive(Clone)]
+struct Validator {
+    section: Text,
+    name: Text,
+    validate: Arc<ValidatorFn>,
+}
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Validator({}, {})", self.name, self.section)
+    }
 }
 
 /// Internal representation of a config section.
@@ -45,6 +110,16 @@ pub struct ValueSource {
     value: Option<Text>,
     source: Text, // global, user, repo, "--config", or an extension name, etc.
     location: Option<ValueLocation>,
+    // For `+=` and `-=`, the items that were added or removed. `value` is then the list that
+    // resulted from that, so that the last value is always the effective one.
+    list_edit: Option<(ListEdit, Text)>,
+}
+
+/// How `+=` and `-=` change a list value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ListEdit {
+    Append,
+    Remove,
 }
 
 /// The on-disk file name and byte offsets that provide the config value.
@@ -62,6 +137,40 @@ struct ValueLocation {
 pub struct Options {
     source: Text,
     filters: Vec<Arc<Box<dyn Fn(Text, Text, Option<Text>) -> Option<(Text, Text, Option<Text>)>>>>,
+    file_check: FileCheck,
+    max_include_depth: Option<usize>,
+    report_include_cycles: bool,
+}
+
+/// How deep `%include`s can be nested, unless set by `Options::max_include_depth`.
+pub const DEFAULT_MAX_INCLUDE_DEPTH: usize = 32;
+
+/// The files seen by a `load_path` call.
+#[derive(Default)]
+struct Includes {
+    /// Files that were loaded, or are being loaded.
+    visited: HashSet<PathBuf>,
+    /// Files that are being loaded, each one included by the previous one.
+    chain: Vec<PathBuf>,
+}
+
+/// What to do with config files that someone other than root and the current user owns, or
+/// that anyone can write to. Whoever can change such a file can change the config of everyone
+/// that loads it, e.g. through a system hgrc on a shared host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileCheck {
+    /// Load config files without checking them.
+    Off,
+    /// Load them, and keep track of those that fail the check, see `ConfigSet::insecure_files`.
+    Record,
+    /// Don't load the files that fail the check, and report an error for them.
+    Reject,
+}
+
+impl Default for FileCheck {
+    fn default() -> Self {
+        FileCheck::Off
+    }
 }
 
 impl ConfigSet {
@@ -96,14 +205,16 @@ impl ConfigSet {
     ///
     /// Loading a file that is already parsed or being parsed by this `load_path` call is ignored,
     /// to avoid infinite loop. A separate `load_path` call would not ignore files loaded by
-    /// other `load_path` calls.
+    /// other `load_path` calls. A file that includes itself, directly or through other files, is
+    /// only an `Error::IncludeCycle` with `Options::report_include_cycles`. Includes nested
+    /// deeper than `Options::max_include_depth` are skipped, and are an `Error::IncludeDepth`.
     ///
     /// Return a list of errors. An error pasing a file will stop that file from loading, without
     /// affecting other files.
     pub fn load_path<P: AsRef<Path>>(&mut self, path: P, opts: &Options) -> Vec<Error> {
-        let mut visited = HashSet::new();
+        let mut includes = Includes::default();
         let mut errors = Vec::new();
-        self.load_file(path.as_ref(), opts, &mut visited, &mut errors);
+        self.load_file(path.as_ref(), opts, &mut includes, &mut errors);
         errors
     }
 
@@ -112,13 +223,36 @@ impl ConfigSet {
     ///
     /// Return a list of errors.
     pub fn parse<B: Into<Text>>(&mut self, content: B, opts: &Options) -> Vec<Error> {
-        let mut visited = HashSet::new();
+        let mut includes = Includes::default();
         let mut errors = Vec::new();
         let buf = content.into();
-        self.load_file_content(Path::new(""), buf, opts, &mut visited, &mut errors);
+        self.load_file_content(Path::new(""), buf, opts, &mut includes, &mut errors);
         errors
     }
 
+    /// Config files that were loaded even though they failed the permission check, along with
+    /// why, if they were loaded with `FileCheck::Record`.
+    pub fn insecure_files(&self) -> &[(PathBuf, String)] {
+        &self.insecure_files
+    }
+
+    /// Record the config items that are read from now on in `recorder`, including the ones read
+    /// from clones of this config set.
+    pub fn set_access_recorder(&mut self, recorder: Arc<AccessRecorder>) {
+        self.access_recorder = Some(recorder);
+    }
+
+    /// The recorder of the config items that are read, if there is one.
+    pub fn access_recorder(&self) -> Option<&Arc<AccessRecorder>> {
+        self.access_recorder.as_ref()
+    }
+
+    fn record_access(&self, section: &str, name: &str) {
+        if let Some(recorder) = &self.access_recorder {
+            recorder.record(section, name);
+        }
+    }
+
     /// Get config sections.
     pub fn sections(&self) -> Vec<Text> {
         self.sections.keys().cloned().collect()
@@ -135,6 +269,7 @@ impl ConfigSet {
     /// Get config value for a given config.
     /// Return `None` if the config item does not exist or is unset.
     pub fn get(&self, section: impl AsRef<str>, name: impl AsRef<str>) -> Option<Text> {
+        self.record_access(section.as_ref(), name.as_ref());
         self.sections.get(section.as_ref()).and_then(|section| {
             section
                 .items
@@ -148,6 +283,7 @@ impl ConfigSet {
     ///
     /// Return an emtpy vector if the config does not exist.
     pub fn get_sources(&self, section: impl AsRef<str>, name: impl AsRef<str>) -> Vec<ValueSource> {
+        self.record_access(section.as_ref(), name.as_ref());
         self.sections
             .get(section.as_ref())
             .and_then(|section| section.items.get(name.as_ref()).cloned())
@@ -184,6 +320,59 @@ impl ConfigSet {
         self.get_or(section, name, Default::default)
     }
 
+    /// Register a callback validating the items of `section`, so that an extension can check
+    /// its own config when it is loaded rather than at first use. The callback is given the whole
+    /// config, so that it can check items against each other, and returns the names of the
+    /// invalid items with the reason they are invalid.
+    ///
+    /// `name` identifies the callback, e.g. by the extension that registers it. Registering a
+    /// callback under a name that is already registered replaces the earlier one.
+    ///
+    /// Callbacks are run by `validate`, which `ConfigSetHgExt::load` calls once everything is
+    /// loaded.
+    pub fn register_validator(
+        &mut self,
+        section: impl AsRef<str>,
+        name: impl AsRef<str>,
+        validate: impl Fn(&ConfigSet) -> Vec<(Text, String)> + Send + Sync + 'static,
+    ) {
+        self.add_validator(Validator {
+            section: Text::copy_from_slice(section.as_ref()),
+            name: Text::copy_from_slice(name.as_ref()),
+            validate: Arc::new(validate),
+        });
+    }
+
+    fn add_validator(&mut self, validator: Validator) {
+        self.validators.retain(|v| v.name != validator.name);
+        self.validators.push(validator);
+    }
+
+    /// Run the registered validation callbacks, and return the errors from all of them.
+    pub fn validate(&self) -> Vec<Error> {
+        let mut errors = Vec::new();
+        for validator in self.validators.iter() {
+            for (name, message) in (validator.validate)(self) {
+                let origin = self
+                    .get_sources(&validator.section, &name)
+                    .last()
+                    .map(|source| match source.location() {
+                        Some((path, _)) if !path.as_os_str().is_empty() => {
+                            path.display().to_string()
+                        }
+                        _ => source.source().to_string(),
+                    });
+                errors.push(Error::Validation {
+                    section: validator.section.to_string(),
+                    name: name.to_string(),
+                    message,
+                    origin,
+                });
+            }
+        }
+        errors
+    }
+
     /// Set a config item directly. `section`, `name` locates the config. `value` is the new value.
     /// `source` is some annotation about who set it, ex. "reporc", "userrc", "--config", etc.
     pub fn set(
@@ -207,13 +396,7 @@ impl ConfigSet {
         location: Option<ValueLocation>,
         opts: &Options,
     ) {
-        let filtered = opts
-            .filters
-            .iter()
-            .fold(Some((section, name, value)), move |acc, func| {
-                acc.and_then(|(section, name, value)| func(section, name, value))
-            });
-        if let Some((section, name, value)) = filtered {
+        if let Some((section, name, value)) = opts.filter(section, name, value) {
             self.sections
                 .entry(section)
                 .or_insert_with(Default::default)
@@ -224,31 +407,103 @@ impl ConfigSet {
                     value,
                     location,
                     source: opts.source.clone(),
+                    list_edit: None,
                 })
         }
     }
 
+    /// Add `items` to, or remove them from, the list value of a config item, as `+=` and `-=`
+    /// do.
+    fn edit_list_internal(
+        &mut self,
+        section: Text,
+        name: Text,
+        edit: ListEdit,
+        items: Text,
+        location: Option<ValueLocation>,
+        opts: &Options,
+    ) {
+        if let Some((section, name, Some(items))) = opts.filter(section, name, Some(items)) {
+            let values = self
+                .sections
+                .entry(section)
+                .or_insert_with(Default::default)
+                .items
+                .entry(name)
+                .or_insert_with(|| Vec::with_capacity(1));
+            let value = apply_list_edit(values.last(), edit, &items);
+            values.push(ValueSource {
+                value: Some(value),
+                location,
+                source: opts.source.clone(),
+                list_edit: Some((edit, items)),
+            })
+        }
+    }
+
     fn load_file(
         &mut self,
         path: &Path,
         opts: &Options,
-        visited: &mut HashSet<PathBuf>,
+        includes: &mut Includes,
         errors: &mut Vec<Error>,
     ) {
         if let Ok(path) = path.canonicalize() {
             let path = &path;
             debug_assert!(path.is_absolute());
 
-            if !visited.insert(path.to_path_buf()) {
+            if includes.chain.contains(path) {
+                let mut chain = includes.chain.clone();
+                chain.push(path.to_path_buf());
+                let error = Error::IncludeCycle(chain);
+                if opts.report_include_cycles {
+                    errors.push(error);
+                } else {
+                    tracing::debug!("skipping {}", error);
+                }
+                return;
+            }
+
+            if !includes.visited.insert(path.to_path_buf()) {
                 // skip - visited before
                 return;
             }
 
-            match fs::read_to_string(path) {
-                Ok(mut text) => {
+            let max_include_depth = opts.max_include_depth.unwrap_or(DEFAULT_MAX_INCLUDE_DEPTH);
+            if includes.chain.len() > max_include_depth {
+                let mut chain = includes.chain.clone();
+                chain.push(path.to_path_buf());
+                errors.push(Error::IncludeDepth(chain, max_include_depth));
+                return;
+            }
+
+            // The file is checked once it is open, so that it can't be swapped for another one
+            // between the check and the read.
+            let mut file = match fs::File::open(path) {
+                Ok(file) => file,
+                Err(error) => {
+                    errors.push(Error::Io(path.to_path_buf(), error));
+                    return;
+                }
+            };
+            if opts.file_check != FileCheck::Off {
+                if let Some(reason) = insecure_reason(path, &file) {
+                    if opts.file_check == FileCheck::Reject {
+                        errors.push(Error::Insecure(path.to_path_buf(), reason));
+                        return;
+                    }
+                    self.insecure_files.push((path.to_path_buf(), reason));
+                }
+            }
+
+            let mut text = String::new();
+            match file.read_to_string(&mut text) {
+                Ok(_) => {
                     text.push('\n');
                     let text = Text::from(text);
-                    self.load_file_content(path, text, opts, visited, errors);
+                    includes.chain.push(path.to_path_buf());
+                    self.load_file_content(path, text, opts, includes, errors);
+                    includes.chain.pop();
                 }
                 Err(error) => errors.push(Error::Io(path.to_path_buf(), error)),
             }
@@ -264,7 +519,7 @@ impl ConfigSet {
                 if let Some(path_str) = path.to_str() {
                     if path_str.starts_with(r"\\?\") {
                         let path = Path::new(&path_str[4..]);
-                        self.load_file(&path, opts, visited, errors);
+                        self.load_file(&path, opts, includes, errors);
                     }
                 }
             }
@@ -280,7 +535,7 @@ impl ConfigSet {
         path: &Path,
         buf: Text,
         opts: &Options,
-        visited: &mut HashSet<PathBuf>,
+        includes: &mut Includes,
         errors: &mut Vec<Error>,
     ) {
         let mut section = Text::new();
@@ -288,13 +543,12 @@ impl ConfigSet {
         let skip_include = path.parent().is_none(); // skip handling %include if path is empty
 
         // Utilities to avoid too much indentation.
-        let handle_value = |
-            this: &mut ConfigSet,
-            pair: Pair,
-            section: Text,
-            name: Text,
-            location: ValueLocation,
-        | {
+        let handle_value = |this: &mut ConfigSet,
+                            pair: Pair,
+                            section: Text,
+                            name: Text,
+                            edit: Option<ListEdit>,
+                            location: ValueLocation| {
             let pairs = pair.into_inner();
             let mut lines = Vec::with_capacity(1);
             for pair in pairs {
@@ -309,15 +563,29 @@ impl ConfigSet {
             };
 
             let value = strip_whitespace(&value, 0, value.len());
-            this.set_internal(section, name, value.into(), location.into(), opts)
+            match edit {
+                Some(edit) => {
+                    this.edit_list_internal(section, name, edit, value, location.into(), opts)
+                }
+                None => this.set_internal(section, name, value.into(), location.into(), opts),
+            }
         };
 
         let handle_config_item = |this: &mut ConfigSet, pair: Pair, section: Text| {
             let pairs = pair.into_inner();
             let mut name = Text::new();
+            let mut edit = None;
             for pair in pairs {
                 match pair.as_rule() {
                     Rule::config_name => name = extract(&buf, pair.as_span()),
+                    Rule::equal_sign => {
+                        // The spaces before "+=" and "-=" are part of the equal sign.
+                        edit = match pair.as_str().trim_start().as_bytes()[0] {
+                            b'+' => Some(ListEdit::Append),
+                            b'-' => Some(ListEdit::Remove),
+                            _ => None,
+                        }
+                    }
                     Rule::value => {
                         let span = pair.as_span();
                         let location = ValueLocation {
@@ -325,7 +593,7 @@ impl ConfigSet {
                             content: buf.clone(),
                             location: span.start()..span.end(),
                         };
-                        return handle_value(this, pair, section, name, location);
+                        return handle_value(this, pair, section, name, edit, location);
                     }
                     _ => {}
                 }
@@ -352,7 +620,7 @@ impl ConfigSet {
                         let include_path = pair.as_str();
                         let full_include_path =
                             path.parent().unwrap().join(expand_path(include_path));
-                        this.load_file(&full_include_path, opts, visited, errors);
+                        this.load_file(&full_include_path, opts, includes, errors);
                     }
                 }
             }
@@ -391,7 +659,16 @@ impl ConfigSet {
         let pairs = match ConfigParser::parse(Rule::file, &text) {
             Ok(pairs) => pairs,
             Err(error) => {
-                return errors.push(Error::Parse(path.to_path_buf(), format!("{}", error)));
+                let span = match error.location {
+                    InputLocation::Pos(pos) => pos..pos,
+                    InputLocation::Span((start, end)) => start..end,
+                };
+                return errors.push(Error::Parse {
+                    path: path.to_path_buf(),
+                    content: buf.clone(),
+                    span,
+                    message: parse_error_message(&error),
+                });
             }
         };
 
@@ -550,6 +827,108 @@ impl ConfigSet {
 
         result
     }
+
+    /// Merge `overlay` on top of this config set, as if it was loaded after it. Keys set by both
+    /// to different values (including `overlay` unsetting a value) are reported, with where each
+    /// came from, so that tooling can show the user what `overlay` overrides, e.g. when system
+    /// defaults change during an upgrade.
+    ///
+    /// The history of values of each key is kept, so `get_sources` still shows the values that
+    /// were overridden. `+=` and `-=` in `overlay` apply to the lists of this config set, and
+    /// only extending a list is not reported as a conflict.
+    pub fn merge(&self, overlay: &ConfigSet) -> (ConfigSet, MergeReport) {
+        let mut merged = self.clone();
+        let mut report = MergeReport::default();
+
+        for (sname, overlay_section) in overlay.sections.iter() {
+            let section = merged
+                .sections
+                .entry(sname.clone())
+                .or_insert_with(Default::default);
+            for (kname, overlay_values) in overlay_section.items.iter() {
+                let values = section
+                    .items
+                    .entry(kname.clone())
+                    .or_insert_with(|| Vec::with_capacity(overlay_values.len()));
+                let base = values.last().cloned();
+                for overlay_value in overlay_values.iter() {
+                    let mut value = overlay_value.clone();
+                    if let Some((edit, items)) = &value.list_edit {
+                        value.value = Some(apply_list_edit(values.last(), *edit, items));
+                    }
+                    values.push(value);
+                }
+                let only_list_edits = overlay_values.iter().all(|v| v.list_edit.is_some());
+                if let (Some(base), Some(overlay)) = (base, values.last()) {
+                    if base.value != overlay.value && !only_list_edits {
+                        report.conflicts.push(MergeConflict {
+                            section: sname.clone(),
+                            name: kname.clone(),
+                            base,
+                            overlay: overlay.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        // Both sides usually register the same callbacks, which only need to run once.
+        for validator in overlay.validators.iter() {
+            merged.add_validator(validator.clone());
+        }
+        merged
+            .insecure_files
+            .extend(overlay.insecure_files.iter().cloned());
+
+        (merged, report)
+    }
+}
+
+impl AccessRecorder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn record(&self, section: &str, name: &str) {
+        let recorded = |accessed: &HashMap<String, HashSet<String>>| {
+            accessed
+                .get(section)
+                .map_or(false, |names| names.contains(name))
+        };
+        // Most reads are of items that were read before, which only need the read lock.
+        if recorded(&self.accessed.read().unwrap()) {
+            return;
+        }
+        self.accessed
+            .write()
+            .unwrap()
+            .entry(section.to_string())
+            .or_default()
+            .insert(name.to_string());
+    }
+
+    /// The `(section, name)` of the items read so far, sorted.
+    pub fn accessed(&self) -> Vec<(String, String)> {
+        let accessed = self.accessed.read().unwrap();
+        let mut items: Vec<_> = accessed
+            .iter()
+            .flat_map(|(section, names)| {
+                names
+                    .iter()
+                    .map(move |name| (section.clone(), name.clone()))
+            })
+            .collect();
+        items.sort();
+        items
+    }
+
+    /// Write the items read so far to `path`, one `section.name` per line.
+    pub fn dump(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = fs::File::create(path)?;
+        for (section, name) in self.accessed() {
+            writeln!(file, "{}.{}", section, name)?;
+        }
+        Ok(())
+    }
 }
 
 impl ValueSource {
@@ -612,6 +991,44 @@ impl Options {
         self.source = source.into();
         self
     }
+
+    /// Set how the ownership and permissions of config files are checked before loading them.
+    /// Files that are included by others are checked too.
+    pub fn file_check(mut self, file_check: FileCheck) -> Self {
+        self.file_check = file_check;
+        self
+    }
+
+    /// Set how deep `%include`s can be nested. The file that is loaded is at depth 0, the files
+    /// it includes at depth 1, and so on. Defaults to `DEFAULT_MAX_INCLUDE_DEPTH`.
+    pub fn max_include_depth(mut self, depth: usize) -> Self {
+        self.max_include_depth = Some(depth);
+        self
+    }
+
+    /// Set whether files that include themselves, directly or through other files, are reported
+    /// as `Error::IncludeCycle`. Either way, the include is skipped. Defaults to false, as config
+    /// files include each other on purpose.
+    pub fn report_include_cycles(mut self, report: bool) -> Self {
+        self.report_include_cycles = report;
+        self
+    }
+}
+
+impl Options {
+    /// Run the filters on a config item, in order.
+    fn filter(
+        &self,
+        section: Text,
+        name: Text,
+        value: Option<Text>,
+    ) -> Option<(Text, Text, Option<Text>)> {
+        self.filters
+            .iter()
+            .fold(Some((section, name, value)), move |acc, func| {
+                acc.and_then(|(section, name, value)| func(section, name, value))
+            })
+    }
 }
 
 /// Convert a "source" string to an `Options`.
@@ -621,6 +1038,69 @@ impl<S: Into<Text>> From<S> for Options {
     }
 }
 
+/// Why the config file at `path`, opened as `file`, is unsafe to load, if it is. It is unsafe
+/// if someone else than root and the current user can change it, or can replace it with another
+/// file through its directory.
+#[cfg(unix)]
+fn insecure_reason(path: &Path, file: &fs::File) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    // Errors reading the file itself are reported when it is read.
+    let metadata = file.metadata().ok()?;
+    if let Some(reason) = unsafe_owner_or_mode(&metadata) {
+        return Some(reason);
+    }
+
+    let dir = path.parent()?;
+    let dir_metadata = fs::metadata(dir).ok()?;
+    // In a sticky directory, such as /tmp, only the owner of a file can rename or delete it.
+    let reason = if dir_metadata.mode() & 0o1000 != 0 {
+        unsafe_owner(&dir_metadata)
+    } else {
+        unsafe_owner_or_mode(&dir_metadata)
+    };
+    reason.map(|reason| format!("in {}, which is {}", dir.display(), reason))
+}
+
+#[cfg(unix)]
+fn unsafe_owner_or_mode(metadata: &fs::Metadata) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mode = metadata.mode() & 0o777;
+    if mode & 0o002 != 0 {
+        return Some(format!("writable by anyone (mode {:o})", mode));
+    }
+    // The group of the current user is often the group of the current user alone.
+    let gid = unsafe { libc::getegid() };
+    if mode & 0o020 != 0 && metadata.gid() != 0 && metadata.gid() != gid {
+        return Some(format!(
+            "writable by group {} (mode {:o})",
+            metadata.gid(),
+            mode
+        ));
+    }
+    unsafe_owner(metadata)
+}
+
+#[cfg(unix)]
+fn unsafe_owner(metadata: &fs::Metadata) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let uid = unsafe { libc::geteuid() };
+    if metadata.uid() != 0 && metadata.uid() != uid {
+        return Some(format!(
+            "owned by uid {}, not root or the current user",
+            metadata.uid()
+        ));
+    }
+    None
+}
+
+#[cfg(not(unix))]
+fn insecure_reason(_path: &Path, _file: &fs::File) -> Option<String> {
+    None
+}
+
 /// Remove space characters from both ends. Remove newline characters from the end.
 /// `start` position is inclusive, `end` is exclusive.
 /// Return the stripped `Text`.
@@ -638,6 +1118,36 @@ fn extract<'a>(buf: &Text, span: Span<'a>) -> Text {
     strip_whitespace(buf, span.start(), span.end())
 }
 
+/// The list that results from adding `items` to, or removing them from, the list of `current`.
+/// An unset value is an empty list.
+fn apply_list_edit(current: Option<&ValueSource>, edit: ListEdit, items: &Text) -> Text {
+    let mut list = current
+        .and_then(|current| current.value.as_ref())
+        .map(parse_list)
+        .unwrap_or_default();
+    let items = parse_list(items);
+    match edit {
+        ListEdit::Append => list.extend(items),
+        ListEdit::Remove => list.retain(|item| !items.contains(item)),
+    }
+
+    // Quote what `parse_list` would split or unquote, so that the list reads back the same.
+    let list: Vec<String> = list
+        .iter()
+        .map(|item| {
+            if item.is_empty()
+                || item.starts_with('"')
+                || item.contains(|c: char| c.is_whitespace() || c == ',')
+            {
+                format!("\"{}\"", item.replace('"', "\\\""))
+            } else {
+                item.to_string()
+            }
+        })
+        .collect();
+    Text::from(list.join(", "))
+}
+
 pub struct SupersetVerification {
     // Configs (and their values) not set by the superset config, but should be.
     pub missing: Vec<((Text, Text), Text)>,
@@ -661,6 +1171,29 @@ impl SupersetVerification {
     }
 }
 
+/// A key that `ConfigSet::merge` found set to different values on both sides.
+#[derive(Clone, Debug)]
+pub struct MergeConflict {
+    pub section: Text,
+    pub name: Text,
+    // The final value in the base config set, which was overridden.
+    pub base: ValueSource,
+    // The final value in the overlay, which is the merged value.
+    pub overlay: ValueSource,
+}
+
+/// What `ConfigSet::merge` overrode, in the order the keys appear in the overlay.
+#[derive(Clone, Debug, Default)]
+pub struct MergeReport {
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl MergeReport {
+    pub fn is_empty(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
@@ -711,6 +1244,175 @@ pub(crate) mod tests {
         assert_eq!(cfg.clone().get("x", "a"), Some("1".into()));
     }
 
+    #[test]
+    fn test_merge() {
+        let mut system = ConfigSet::new();
+        system.parse(
+            "[ui]\n\
+             merge = internal:merge\n\
+             paginate = true\n\
+             [extensions]\n\
+             rebase =\n",
+            &"system".into(),
+        );
+        let mut user = ConfigSet::new();
+        user.parse(
+            "[ui]\n\
+             merge = vimdiff\n\
+             paginate = true\n\
+             username = Test\n\
+             [extensions]\n\
+             %unset rebase\n",
+            &"user".into(),
+        );
+
+        let (merged, report) = system.merge(&user);
+        assert_eq!(merged.get("ui", "merge"), Some(Text::from("vimdiff")));
+        assert_eq!(merged.get("ui", "paginate"), Some(Text::from("true")));
+        assert_eq!(merged.get("ui", "username"), Some(Text::from("Test")));
+        assert_eq!(merged.get("extensions", "rebase"), None);
+        assert_eq!(merged.get_sources("ui", "merge").len(), 2);
+
+        // Same value is not a conflict, and neither is a key only set by one side.
+        let conflicts: Vec<_> = report
+            .conflicts
+            .iter()
+            .map(|c| {
+                (
+                    c.section.to_string(),
+                    c.name.to_string(),
+                    c.base.value().clone(),
+                    c.base.source().to_string(),
+                    c.overlay.value().clone(),
+                    c.overlay.source().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            conflicts,
+            vec![
+                (
+                    "ui".to_string(),
+                    "merge".to_string(),
+                    Some(Text::from("internal:merge")),
+                    "system".to_string(),
+                    Some(Text::from("vimdiff")),
+                    "user".to_string(),
+                ),
+                (
+                    "extensions".to_string(),
+                    "rebase".to_string(),
+                    Some(Text::new()),
+                    "system".to_string(),
+                    None,
+                    "user".to_string(),
+                ),
+            ]
+        );
+
+        // The inputs are not modified.
+        assert_eq!(
+            system.get("ui", "merge"),
+            Some(Text::from("internal:merge"))
+        );
+        assert!(system.merge(&ConfigSet::new()).1.is_empty());
+    }
+
+    #[test]
+    fn test_merge_validators() {
+        let reject_all = |cfg: &ConfigSet| -> Vec<(Text, String)> {
+            cfg.keys("ext")
+                .into_iter()
+                .map(|name| (name, "rejected".to_string()))
+                .collect()
+        };
+        let mut system = ConfigSet::new();
+        system.register_validator("ext", "ext", reject_all);
+        system.parse("[ext]\na = 1\n", &"system".into());
+        let mut user = ConfigSet::new();
+        user.register_validator("ext", "ext", reject_all);
+        user.register_validator("other", "other", |_| {
+            vec![("b".into(), "rejected".to_string())]
+        });
+
+        // The validator that both register only runs once.
+        let (merged, _) = system.merge(&user);
+        let errors: Vec<_> = merged.validate().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec!["ext.a (set by system): rejected", "other.b: rejected"]
+        );
+    }
+
+    #[test]
+    fn test_list_edit() {
+        let mut cfg = ConfigSet::new();
+        cfg.parse(
+            "[ui]\n\
+             ignore = a, b\n\
+             ignore += c \"d e\"\n\
+             ignore -= a\n\
+             new += x\n\
+             %unset gone\n\
+             gone += y\n",
+            &"system".into(),
+        );
+        assert_eq!(cfg.get("ui", "ignore"), Some("b, c, \"d e\"".into()));
+        assert_eq!(
+            cfg.get_opt::<Vec<String>>("ui", "ignore").unwrap(),
+            Some(vec!["b".to_string(), "c".to_string(), "d e".to_string()])
+        );
+        assert_eq!(cfg.get("ui", "new"), Some("x".into()));
+        assert_eq!(cfg.get("ui", "gone"), Some("y".into()));
+        assert_eq!(
+            cfg.keys("ui"),
+            vec![Text::from("ignore"), Text::from("new"), Text::from("gone")]
+        );
+        assert_eq!(cfg.get_sources("ui", "ignore").len(), 3);
+
+        // Layers merged later extend the lists of earlier ones.
+        let mut user = ConfigSet::new();
+        user.parse(
+            "[ui]\n\
+             ignore += f\n\
+             new = z\n",
+            &"user".into(),
+        );
+        let (merged, report) = cfg.merge(&user);
+        assert_eq!(merged.get("ui", "ignore"), Some("b, c, \"d e\", f".into()));
+        assert_eq!(merged.get("ui", "new"), Some("z".into()));
+        let conflicts: Vec<_> = report.conflicts.iter().map(|c| c.name.clone()).collect();
+        assert_eq!(conflicts, vec![Text::from("new")]);
+    }
+
+    #[test]
+    fn test_list_edit_needs_space() {
+        let mut cfg = ConfigSet::new();
+        cfg.parse(
+            "[merge-patterns]\n\
+             **.c++=internal:merge\n\
+             **.c-=internal:fail\n\
+             x\t+= a\n",
+            &"test".into(),
+        );
+        assert_eq!(
+            cfg.keys("merge-patterns"),
+            vec![Text::from("**.c++"), Text::from("**.c-"), Text::from("x")]
+        );
+        assert_eq!(
+            cfg.get("merge-patterns", "**.c++"),
+            Some("internal:merge".into())
+        );
+        assert_eq!(
+            cfg.get("merge-patterns", "**.c-"),
+            Some("internal:fail".into())
+        );
+        assert_eq!(cfg.get("merge-patterns", "x"), Some("a".into()));
+        assert!(cfg.get_sources("merge-patterns", "x")[0]
+            .list_edit
+            .is_some());
+    }
+
     #[test]
     fn test_parse_basic() {
         let mut cfg = ConfigSet::new();
@@ -823,7 +1525,7 @@ pub(crate) mod tests {
  --> 1:1
   |
 1 | =foo
-  | ^---
+  | ^
   |
   = expected EOI, new_line, config_name, left_bracket, comment_line, or directive"
         );
@@ -835,7 +1537,7 @@ pub(crate) mod tests {
  --> 1:2
   |
 1 |  a=b
-  |  ^---
+  |  ^
   |
   = expected EOI or new_line"
         );
@@ -847,7 +1549,7 @@ pub(crate) mod tests {
  --> 1:8
   |
 1 | %unset =foo
-  |        ^---
+  |        ^
   |
   = expected space or config_name"
         );
@@ -859,7 +1561,7 @@ pub(crate) mod tests {
  --> 1:2
   |
 1 | [
-  |  ^---
+  |  ^
   |
   = expected section_name"
         );
@@ -871,7 +1573,7 @@ pub(crate) mod tests {
  --> 1:2
   |
 1 | []
-  |  ^---
+  |  ^
   |
   = expected section_name"
         );
@@ -883,7 +1585,7 @@ pub(crate) mod tests {
  --> 1:4
   |
 1 | [a]]
-  |    ^---
+  |    ^
   |
   = expected EOI, new_line, or space"
         );
@@ -894,8 +1596,9 @@ pub(crate) mod tests {
             "\"\":
  --> 2:3
   |
+1 | # foo
 2 | [y
-  |   ^---
+  |   ^
   |
   = expected right_bracket"
         );
@@ -907,8 +1610,10 @@ pub(crate) mod tests {
             "\"\":
  --> 3:2
   |
+1 |
+2 |
 3 | %unknown
-  |  ^---
+  |  ^
   |
   = expected include or unset"
         );
@@ -920,11 +1625,50 @@ pub(crate) mod tests {
             "\"\":
  --> 2:4
   |
+1 | [section]
 2 | abc
-  |    ^---
+  |    ^
   |
   = expected equal_sign"
         );
+
+        // Only a couple of lines of context are shown, and the gutter fits the line number.
+        let mut cfg = ConfigSet::new();
+        let content = format!("[section]\n{}a\tbc\n", "a = b\n".repeat(9));
+        let errors = cfg.parse(content, &"test_parse_errors".into());
+        assert_eq!(
+            format!("{}", errors[0]),
+            "\"\":
+  --> 11:5
+   |
+ 9 | a = b
+10 | a = b
+11 | a\tbc
+   |  \t  ^
+   |
+   = expected equal_sign"
+        );
+    }
+
+    #[test]
+    fn test_parse_error_span() {
+        let error = Error::Parse {
+            path: "a.rc".into(),
+            content: "[x]\nfoo = bar\n".into(),
+            span: 4..7,
+            message: "bad name".to_string(),
+        };
+        assert_eq!(
+            format!("{}", error),
+            "\"a.rc\":
+ --> 2:1
+  |
+1 | [x]
+2 | foo = bar
+  | ^^^
+  |
+  = bad name"
+        );
     }
 
     #[test]
@@ -1009,6 +1753,107 @@ pub(crate) mod tests {
         f.write_all(content.as_bytes()).unwrap();
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_file_check() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("test_file_check").unwrap();
+        let path = dir.path().join("shared.rc");
+        write_file(path.clone(), "[x]\na=1");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o666)).unwrap();
+
+        let mut cfg = ConfigSet::new();
+        assert!(cfg.load_path(&path, &"test".into()).is_empty());
+        assert!(cfg.insecure_files().is_empty());
+
+        let mut cfg = ConfigSet::new();
+        let opts = Options::from("test").file_check(FileCheck::Record);
+        assert!(cfg.load_path(&path, &opts).is_empty());
+        assert_eq!(cfg.get("x", "a"), Some("1".into()));
+        assert_eq!(cfg.insecure_files().len(), 1);
+        assert_eq!(cfg.insecure_files()[0].1, "writable by anyone (mode 666)");
+
+        let mut cfg = ConfigSet::new();
+        let opts = Options::from("test").file_check(FileCheck::Reject);
+        let errors = cfg.load_path(&path, &opts);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], Error::Insecure(..)));
+        assert_eq!(cfg.get("x", "a"), None);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let mut cfg = ConfigSet::new();
+        assert!(cfg.load_path(&path, &opts).is_empty());
+        assert_eq!(cfg.get("x", "a"), Some("1".into()));
+
+        // The group of the file is the group of the current user.
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o664)).unwrap();
+        let mut cfg = ConfigSet::new();
+        assert!(cfg.load_path(&path, &opts).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_check_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("test_file_check_dir").unwrap();
+        let shared = dir.path().join("shared");
+        let path = shared.join("shared.rc");
+        write_file(path.clone(), "[x]\na=1");
+        let opts = Options::from("test").file_check(FileCheck::Record);
+
+        // Anyone can replace the file through its directory.
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o777)).unwrap();
+        let mut cfg = ConfigSet::new();
+        assert!(cfg.load_path(&path, &opts).is_empty());
+        assert_eq!(cfg.insecure_files().len(), 1);
+        assert_eq!(
+            cfg.insecure_files()[0].1,
+            format!(
+                "in {}, which is writable by anyone (mode 777)",
+                shared.canonicalize().unwrap().display()
+            )
+        );
+
+        // Unless the directory is sticky.
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o1777)).unwrap();
+        let mut cfg = ConfigSet::new();
+        assert!(cfg.load_path(&path, &opts).is_empty());
+        assert!(cfg.insecure_files().is_empty());
+    }
+
+    #[test]
+    fn test_access_recorder() {
+        let dir = TempDir::new("test_access_recorder").unwrap();
+        let dump_path = dir.path().join("accessed");
+
+        let mut cfg = ConfigSet::new();
+        cfg.parse("[x]\na=1\nb=2\n[y]\nc=3", &"test".into());
+        let recorder = Arc::new(AccessRecorder::new());
+        cfg.set_access_recorder(recorder.clone());
+
+        assert_eq!(cfg.get("x", "a"), Some("1".into()));
+        let cloned = cfg.clone();
+        assert_eq!(cloned.get_sources("y", "c").len(), 1);
+        assert_eq!(cloned.get("y", "unset"), None);
+        assert_eq!(cfg.get_or_default::<u32>("x", "a").unwrap(), 1);
+        assert_eq!(
+            recorder.accessed(),
+            vec![
+                ("x".to_string(), "a".to_string()),
+                ("y".to_string(), "c".to_string()),
+                ("y".to_string(), "unset".to_string()),
+            ]
+        );
+
+        cfg.access_recorder().unwrap().dump(&dump_path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&dump_path).unwrap(),
+            "x.a\ny.c\ny.unset\n"
+        );
+    }
+
     #[test]
     fn test_parse_include() {
         let dir = TempDir::new("test_parse_include").unwrap();
@@ -1074,6 +1919,63 @@ pub(crate) mod tests {
         assert_eq!(cfg.get("y", "b"), Some(Text::from("1")));
     }
 
+    #[test]
+    fn test_parse_include_cycles() {
+        let dir = TempDir::new("test_parse_include_cycles").unwrap();
+        write_file(dir.path().join("a.rc"), "[x]\na=1\n%include b.rc");
+        write_file(dir.path().join("b.rc"), "[x]\nb=1\n%include a.rc");
+
+        // Skipped without being reported, by default.
+        let mut cfg = ConfigSet::new();
+        let errors = cfg.load_path(dir.path().join("a.rc"), &"test".into());
+        assert!(errors.is_empty());
+        assert_eq!(cfg.keys("x"), vec![Text::from("a"), Text::from("b")]);
+
+        let mut cfg = ConfigSet::new();
+        let opts = Options::new().source("test").report_include_cycles(true);
+        let errors = cfg.load_path(dir.path().join("a.rc"), &opts);
+        let root = dir.path().canonicalize().unwrap();
+        let cycles: Vec<Vec<PathBuf>> = errors
+            .iter()
+            .map(|error| match error {
+                Error::IncludeCycle(chain) => chain
+                    .iter()
+                    .map(|path| path.strip_prefix(&root).unwrap().to_path_buf())
+                    .collect(),
+                _ => panic!("unexpected error: {}", error),
+            })
+            .collect();
+        let chain = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(cycles, vec![chain(&["a.rc", "b.rc", "a.rc"])]);
+        assert_eq!(cfg.keys("x"), vec![Text::from("a"), Text::from("b")]);
+    }
+
+    #[test]
+    fn test_parse_include_depth() {
+        let dir = TempDir::new("test_parse_include_depth").unwrap();
+        for i in 0..4 {
+            write_file(
+                dir.path().join(format!("{}.rc", i)),
+                &format!("[x]\na{}=1\n%include {}.rc\n", i, i + 1),
+            );
+        }
+
+        let mut cfg = ConfigSet::new();
+        let opts = Options::new().source("test").max_include_depth(2);
+        let errors = cfg.load_path(dir.path().join("0.rc"), &opts);
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            Error::IncludeDepth(chain, 2) => assert_eq!(chain.len(), 4),
+            error => panic!("unexpected error: {}", error),
+        }
+        assert!(errors[0].to_string().contains("2.rc\" -> "));
+
+        assert_eq!(
+            cfg.keys("x"),
+            vec![Text::from("a0"), Text::from("a1"), Text::from("a2")]
+        );
+    }
+
     #[test]
     fn test_parse_include_expand() {
         use std::env;
@@ -1367,6 +2269,40 @@ space_list=value1.a value1.b
         );
     }
 
+    #[test]
+    fn test_validators() {
+        let mut cfg = ConfigSet::new();
+        cfg.register_validator("ext", "ext", |cfg| {
+            let mut invalid = Vec::new();
+            for name in cfg.keys("ext") {
+                if cfg.get_opt::<u64>("ext", &name).is_err() {
+                    invalid.push((name, "not a number".to_string()));
+                }
+            }
+            if cfg.get("ext", "required").is_none() {
+                invalid.push(("required".into(), "must be set".to_string()));
+            }
+            invalid
+        });
+        cfg.parse("[ext]\na = 1\nb = x\n[other]\nc = y", &"test".into());
+        cfg.set("ext", "d", Some("z"), &"--config".into());
+
+        let errors: Vec<_> = cfg.validate().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "ext.b (set by test): not a number",
+                "ext.d (set by --config): not a number",
+                "ext.required: must be set",
+            ]
+        );
+
+        cfg.set("ext", "required", Some("1"), &"--config".into());
+        cfg.set("ext", "b", Some("2"), &"--config".into());
+        cfg.set("ext", "d", None::<Text>, &"--config".into());
+        assert!(cfg.validate().is_empty());
+    }
+
     #[test]
     fn test_get_or() {
         let mut cfg = ConfigSet::new();
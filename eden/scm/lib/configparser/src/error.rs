@@ -8,11 +8,16 @@
 use std::ffi::CString;
 use std::fmt;
 use std::io;
-use std::path::PathBuf;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::str;
 
+use minibytes::Text;
 use thiserror::Error;
 
+/// Number of lines shown before the offending line in parse errors.
+const CONTEXT_LINES: usize = 2;
+
 /// The error type for parsing config files.
 #[derive(Error, Debug)]
 pub enum Error {
@@ -21,8 +26,15 @@ pub enum Error {
     Convert(String),
 
     /// Unable to parse a file due to syntax.
-    #[error("{0:?}:\n{1}")]
-    Parse(PathBuf, String),
+    #[error("{}", render_excerpt(.path, .content, .span, .message))]
+    Parse {
+        path: PathBuf,
+        /// The content of the file being parsed.
+        content: Text,
+        /// Byte range of the offending text in `content`.
+        span: Range<usize>,
+        message: String,
+    },
 
     /// Unable to read a file due to IO errors.
     #[error("{0:?}: {1}")]
@@ -34,6 +46,45 @@ pub enum Error {
 
     #[error("{0:?}: {1}")]
     Utf8Path(CString, #[source] str::Utf8Error),
+
+    /// A config file was not loaded, as someone other than root or the current user could have
+    /// changed it.
+    #[error("{0:?}: refusing to load insecure config file: {1}")]
+    Insecure(PathBuf, String),
+
+    /// A config file includes itself, directly or through other files. The chain of includes
+    /// starts at the file that was loaded, and ends with the file that is included again.
+    #[error("%include cycle: {}", render_chain(.0))]
+    IncludeCycle(Vec<PathBuf>),
+
+    /// Config files include each other deeper than the maximum include depth.
+    #[error("%include depth exceeds {1}: {}", render_chain(.0))]
+    IncludeDepth(Vec<PathBuf>, usize),
+
+    /// A config item was rejected by the validator registered for its section.
+    #[error("{}", render_validation(.section, .name, .message, .origin))]
+    Validation {
+        section: String,
+        name: String,
+        message: String,
+        /// Where the offending value was set, if it was set at all.
+        origin: Option<String>,
+    },
+}
+
+fn render_chain(chain: &[PathBuf]) -> String {
+    chain
+        .iter()
+        .map(|path| format!("{:?}", path))
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+fn render_validation(section: &str, name: &str, message: &str, origin: &Option<String>) -> String {
+    match origin {
+        Some(origin) => format!("{}.{} (set by {}): {}", section, name, origin, message),
+        None => format!("{}.{}: {}", section, name, message),
+    }
 }
 
 #[derive(Error, Debug)]
@@ -47,3 +98,63 @@ impl fmt::Display for Errors {
         Ok(())
     }
 }
+
+/// Render a parse error as an excerpt of the file, with carets pointing at `span`, in the style
+/// of rustc:
+///
+/// ```text
+/// "path":
+///  --> 2:4
+///   |
+/// 1 | [section]
+/// 2 | abc
+///   |    ^
+///   |
+///   = expected equal_sign
+/// ```
+fn render_excerpt(path: &Path, content: &str, span: &Range<usize>, message: &str) -> String {
+    let start = span.start.min(content.len());
+    let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[start..]
+        .find('\n')
+        .map_or(content.len(), |i| start + i);
+    let line_number = content[..line_start].matches('\n').count() + 1;
+    let column = content[line_start..start].chars().count() + 1;
+
+    // Spans covering several lines are only underlined up to the end of the first one.
+    let end = span.end.max(start).min(line_end);
+    let width = content[start..end].chars().count().max(1);
+
+    let gutter = " ".repeat(line_number.to_string().len());
+    let mut out = format!(
+        "{:?}:\n{}--> {}:{}\n{} |\n",
+        path, gutter, line_number, column, gutter
+    );
+
+    let first_line = line_number.saturating_sub(CONTEXT_LINES).max(1);
+    let lines = content[..line_end].split('\n').skip(first_line - 1);
+    for (number, line) in (first_line..).zip(lines) {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            out += &format!("{:>width$} |\n", number, width = gutter.len());
+        } else {
+            out += &format!("{:>width$} | {}\n", number, line, width = gutter.len());
+        }
+    }
+
+    // Keep tabs so that the carets line up with the text above them.
+    let indent: String = content[line_start..start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    out += &format!(
+        "{} | {}{}\n{} |\n{} = {}",
+        gutter,
+        indent,
+        "^".repeat(width),
+        gutter,
+        gutter,
+        message
+    );
+    out
+}
@@ -24,7 +24,7 @@ use minibytes::Text;
 use tempfile::tempfile_in;
 use util::{path::expand_path, run_background};
 
-use crate::config::{ConfigSet, Options, SupersetVerification};
+use crate::config::{ConfigSet, FileCheck, Options, SupersetVerification};
 use crate::dynamicconfig::Generator;
 use crate::error::{Error, Errors};
 
@@ -91,6 +91,19 @@ pub fn load<S: Into<Text>, N: Into<Text>>(
     Ok(cfg)
 }
 
+// The dynamic config relies on these, so bad values are reported along with the other load
+// errors rather than when the dynamic config is regenerated.
+fn validate_configs(cfg: &ConfigSet) -> Vec<(Text, String)> {
+    let mut invalid = Vec::new();
+    if cfg.get_opt::<u64>("configs", "generationtime").is_err() {
+        invalid.push((
+            "generationtime".into(),
+            "expected a number of seconds".to_string(),
+        ));
+    }
+    invalid
+}
+
 impl OptionsHgExt for Options {
     fn process_hgplain(self) -> Self {
         let plain_set = env::var(HGPLAIN).is_ok();
@@ -242,6 +255,9 @@ impl ConfigSetHgExt for ConfigSet {
             errors.append(&mut self.load_repo(&repo_path, opts.clone()));
         }
 
+        self.register_validator("configs", "dynamicconfig", validate_configs);
+        errors.append(&mut self.validate());
+
         if !errors.is_empty() {
             return Err(Errors(errors).into());
         }
@@ -263,6 +279,9 @@ impl ConfigSetHgExt for ConfigSet {
                 errors.append(&mut self.load_path(expand_path(path), &opts));
             }
         } else {
+            // The system config applies to everyone on the host, so keep track of whether
+            // someone could have changed it for everyone.
+            let opts = opts.file_check(FileCheck::Record);
             #[cfg(unix)]
             {
                 errors.append(&mut self.load_path("/etc/mercurial/system.rc", &opts));
@@ -670,6 +689,30 @@ mod tests {
         assert_eq!(cfg.get("y", "b"), Some("2".into()));
     }
 
+    #[test]
+    fn test_load_validates() {
+        let _guard = crate::ENV_LOCK.lock();
+
+        let dir = TempDir::new("test_load_validates").unwrap();
+        write_file(dir.path().join("1.rc"), "[configs]\ngenerationtime=soon");
+        env::set_var(HGRCPATH, dir.path().join("1.rc"));
+
+        let err = ConfigSet::new()
+            .load::<String, String>(None, None)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("configs.generationtime") && err.contains("expected a number of seconds"),
+            "unexpected error: {}",
+            err
+        );
+
+        write_file(dir.path().join("1.rc"), "[configs]\ngenerationtime=10");
+        let mut cfg = ConfigSet::new();
+        cfg.load::<String, String>(None, None).unwrap();
+        assert_eq!(cfg.get("configs", "generationtime"), Some("10".into()));
+    }
+
     #[test]
     fn test_load_user() {
         let _guard = ENV_LOCK.lock();
@@ -55,6 +55,21 @@
 //! %unset name1
 //! ```
 //!
+//! ### Extend a list
+//!
+//! Use `+=` and `-=` to add items to, or remove them from, a list value set
+//! by an earlier file, instead of overriding the whole list:
+//!
+//! ```plain,ignore
+//! [section]
+//! name1 += item1 item2
+//! name2 -= item3
+//! ```
+//!
+//! Lists are split the same way as `convert::parse_list` does.
+//! `+=` and `-=` need a space before them, so that `c++=value` still sets
+//! `c++`.
+//!
 //! ### Multi-line values
 //!
 //! Indent non-first lines with a space:
@@ -16,7 +16,7 @@
 // However, `#[grammar = "spec.pest"]` does not play well with Buck build,
 // because pest_derive cannot find "spec.pest" in buck build environment.
 // Therefore this file is @generated. @no-lint.
-// pest-checksum: af79d1287503f19c331643cd2e28d30f6312592f.
+// pest-checksum: 07bf64d3993fc266748b52aebe2c076f37bd7e4d.
 
 
 #[allow(dead_code, non_camel_case_types)]
@@ -182,7 +182,28 @@ impl ::pest::Parser<Rule> for ConfigParser {
                                                         {
                                                             state.sequence(|state|
                                                                                {
-                                                                                   state.match_string("=").and_then(|state|
+                                                                                   state.sequence(|state|
+                                                                                   {
+                                                                                       state.sequence(|state|
+                                                                                       {
+                                                                                           self::space(state).and_then(|state|
+                                                                                           {
+                                                                                               state.repeat(|state|
+                                                                                               {
+                                                                                                   self::space(state)
+                                                                                               })
+                                                                                           })
+                                                                                       }).and_then(|state|
+                                                                                       {
+                                                                                           state.match_string("+=").or_else(|state|
+                                                                                           {
+                                                                                               state.match_string("-=")
+                                                                                           })
+                                                                                       })
+                                                                                   }).or_else(|state|
+                                                                                   {
+                                                                                       state.match_string("=")
+                                                                                   }).and_then(|state|
                                                                                                                         {
                                                                                                                             state.repeat(|state|
                                                                                                                                              {
@@ -238,6 +259,27 @@ impl ::pest::Parser<Rule> for ConfigParser {
                                                                                                                                                                                                            {
                                                                                                                                                                                                                state.match_string("=").or_else(|state|
                                                                                                                                                                                                                                                    {
+                                                                                                                                                                                                                                                       state.sequence(|state|
+                                                                                                                                                                                                                                                       {
+                                                                                                                                                                                                                                                           state.sequence(|state|
+                                                                                                                                                                                                                                                           {
+                                                                                                                                                                                                                                                               self::space(state).and_then(|state|
+                                                                                                                                                                                                                                                               {
+                                                                                                                                                                                                                                                                   state.repeat(|state|
+                                                                                                                                                                                                                                                                   {
+                                                                                                                                                                                                                                                                       self::space(state)
+                                                                                                                                                                                                                                                                   })
+                                                                                                                                                                                                                                                               })
+                                                                                                                                                                                                                                                           }).and_then(|state|
+                                                                                                                                                                                                                                                           {
+                                                                                                                                                                                                                                                               state.match_string("+=").or_else(|state|
+                                                                                                                                                                                                                                                               {
+                                                                                                                                                                                                                                                                   state.match_string("-=")
+                                                                                                                                                                                                                                                               })
+                                                                                                                                                                                                                                                           })
+                                                                                                                                                                                                                                                       })
+                                                                                                                                                                                                                                                   }).or_else(|state|
+                                                                                                                                                                                                                                                   {
                                                                                                                                                                                                                                                        self::new_line(state)
                                                                                                                                                                                                                                                    })
                                                                                                                                                                                                            }).and_then(|state|
@@ -150,6 +150,9 @@ impl IntoIterator for HistoryResponse {
 pub struct HistoryResponseChunk {
     pub path: RepoPathBuf,
     pub entries: Vec<WireHistoryEntry>,
+    /// Where the history carries on from, when it was cut short by the length of the request.
+    /// Requesting these keys with the same options returns the rest of it.
+    pub next: Vec<Key>,
 }
 
 impl HistoryResponseChunk {
@@ -157,6 +160,7 @@ impl HistoryResponseChunk {
         Self {
             path,
             entries: entries.into_iter().collect(),
+            next: Vec::new(),
         }
     }
 }
@@ -248,6 +252,7 @@ impl Arbitrary for HistoryResponseChunk {
         Self {
             path: Arbitrary::arbitrary(g),
             entries: Arbitrary::arbitrary(g),
+            next: Arbitrary::arbitrary(g),
         }
     }
 }
@@ -11,7 +11,8 @@ use serde_derive::{Deserialize, Serialize};
 
 use crate::{
     wire::{
-        ToApi, ToWire, WireHgId, WireKey, WireParents, WireRepoPathBuf, WireToApiConversionError,
+        is_default, ToApi, ToWire, WireHgId, WireKey, WireParents, WireRepoPathBuf,
+        WireToApiConversionError,
     },
     HistoryRequest, HistoryResponseChunk, WireHistoryEntry,
 };
@@ -91,6 +92,8 @@ impl ToApi for WireWireHistoryEntry {
 pub struct WireHistoryResponseChunk {
     path: Option<WireRepoPathBuf>,
     entries: Vec<WireWireHistoryEntry>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    next: Vec<WireKey>,
 }
 
 impl ToWire for HistoryResponseChunk {
@@ -100,6 +103,7 @@ impl ToWire for HistoryResponseChunk {
         WireHistoryResponseChunk {
             path: Some(self.path.to_wire()),
             entries: self.entries.to_wire(),
+            next: self.next.to_wire(),
         }
     }
 }
@@ -114,6 +118,7 @@ impl ToApi for WireHistoryResponseChunk {
                 WireToApiConversionError::CannotPopulateRequiredField("path"),
             )?,
             entries: self.entries.to_api()?,
+            next: self.next.to_api()?,
         })
     }
 }
@@ -146,6 +151,7 @@ impl Arbitrary for WireHistoryResponseChunk {
         Self {
             path: Arbitrary::arbitrary(g),
             entries: Arbitrary::arbitrary(g),
+            next: Arbitrary::arbitrary(g),
         }
     }
 }
@@ -12,8 +12,8 @@ Invalid syntax: no value
   hg: parse errors: "$TESTTMP*hgrc": (glob)
    --> 1:11
     |
-  1 | novaluekey\xe2\x90\x8a (esc)
-    |           ^---
+  1 | novaluekey
+    |           ^
     |
     = expected equal_sign
   
@@ -28,8 +28,8 @@ Invalid syntax: no key
   hg: parse errors: "$TESTTMP*hgrc": (glob)
    --> 1:1
     |
-  1 | =nokeyvalue\xe2\x90\x8a (esc)
-    | ^---
+  1 | =nokeyvalue
+    | ^
     |
     = expected EOI, new_line, config_name, left_bracket, comment_line, or directive
   
@@ -44,8 +44,8 @@ Test hint about invalid syntax from leading white space
   hg: parse errors: "$TESTTMP*hgrc": (glob)
    --> 1:2
     |
-  1 |  key=value\xe2\x90\x8a (esc)
-    |  ^---
+  1 |  key=value
+    |  ^
     |
     = expected EOI or new_line
   
@@ -59,8 +59,8 @@ Test hint about invalid syntax from leading white space
   hg: parse errors: "$TESTTMP*hgrc": (glob)
    --> 1:2
     |
-  1 |  [section]\xe2\x90\x8a (esc)
-    |  ^---
+  1 |  [section]
+    |  ^
     |
     = expected EOI or new_line
   
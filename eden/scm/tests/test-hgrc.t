@@ -21,8 +21,8 @@ Basic syntax error
   hg: parse errors: "$TESTTMP*hgrc": (glob)
    --> 1:8
     |
-  1 | invalid\xe2\x90\x8a (esc)
-    |        ^---
+  1 | invalid
+    |        ^
     |
     = expected equal_sign
   
@@ -66,8 +66,9 @@ issue1829: wrong indentation
   hg: parse errors: "$TESTTMP*hgrc": (glob)
    --> 2:3
     |
-  2 |   x = y\xe2\x90\x8a (esc)
-    |   ^---
+  1 | [foo]
+  2 |   x = y
+    |   ^
     |
     = expected EOI or new_line
   